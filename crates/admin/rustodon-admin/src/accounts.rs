@@ -0,0 +1,481 @@
+//! Account administration
+//!
+//! Lets staff search accounts by origin, moderation state, username, email
+//! or sign-in IP, and lift the restrictions on them: approve or reject
+//! pending sign-ups, and enable, unsilence, unsuspend or unsensitive
+//! accounts. Every action is written to the admin action log in the same
+//! transaction.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::{diff, AdminAction, AdminActionType, AdminError, NewAdminAction};
+use chrono::NaiveDateTime;
+use rustodon_db::User;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::{PgExecutor, PgPool};
+use std::net::IpAddr;
+use tracing::{info, trace};
+
+/// Default number of accounts listed
+pub const DEFAULT_LIMIT: i64 = 100;
+/// Maximum number of accounts listed
+pub const MAX_LIMIT: i64 = 200;
+
+/// Where accounts live
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountOrigin {
+    Local,
+    Remote,
+}
+
+impl AccountOrigin {
+    /// Name of the origin
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountOrigin::Local => "local",
+            AccountOrigin::Remote => "remote",
+        }
+    }
+}
+
+/// Moderation state accounts are filtered by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountState {
+    /// Not restricted in any way
+    Active,
+    /// Waiting for approval
+    Pending,
+    Disabled,
+    Silenced,
+    Suspended,
+}
+
+impl AccountState {
+    /// Name of the state
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountState::Active => "active",
+            AccountState::Pending => "pending",
+            AccountState::Disabled => "disabled",
+            AccountState::Silenced => "silenced",
+            AccountState::Suspended => "suspended",
+        }
+    }
+
+    /// Parses a state by name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "active" => Some(AccountState::Active),
+            "pending" => Some(AccountState::Pending),
+            "disabled" => Some(AccountState::Disabled),
+            "silenced" => Some(AccountState::Silenced),
+            "suspended" => Some(AccountState::Suspended),
+            _ => None,
+        }
+    }
+}
+
+/// Filters of the account search
+#[derive(Debug, Clone, Default)]
+pub struct AccountFilter {
+    pub origin: Option<AccountOrigin>,
+    pub state: Option<AccountState>,
+    /// Start of the username
+    pub username: Option<String>,
+    /// Part of the email address
+    pub email: Option<String>,
+    /// IP address or CIDR range accounts last signed in from
+    pub ip: Option<String>,
+    pub max_id: Option<i64>,
+    pub since_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// Lifting a restriction, or deciding on a sign-up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountAction {
+    Approve,
+    Reject,
+    Enable,
+    Unsilence,
+    Unsuspend,
+    Unsensitive,
+}
+
+impl AccountAction {
+    /// Name of the action in the API and the action log
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountAction::Approve => "approve",
+            AccountAction::Reject => "reject",
+            AccountAction::Enable => "enable",
+            AccountAction::Unsilence => "unsilence",
+            AccountAction::Unsuspend => "unsuspend",
+            AccountAction::Unsensitive => "unsensitive",
+        }
+    }
+
+    /// The statement performing the action, taking the account ID as `$1`
+    fn statement(&self) -> &'static str {
+        match self {
+            AccountAction::Approve => {
+                "UPDATE users SET approved = TRUE, updated_at = NOW()
+                 WHERE id = $1 AND NOT approved"
+            }
+            AccountAction::Reject => {
+                "DELETE FROM users WHERE id = $1 AND NOT approved AND domain IS NULL"
+            }
+            AccountAction::Enable => {
+                "UPDATE users SET disabled = FALSE, updated_at = NOW() WHERE id = $1"
+            }
+            AccountAction::Unsilence => {
+                "UPDATE users SET silenced_at = NULL, updated_at = NOW() WHERE id = $1"
+            }
+            AccountAction::Unsuspend => {
+                "UPDATE users SET status = 'active', suspended_at = NULL, updated_at = NOW()
+                 WHERE id = $1 AND status = 'suspended'"
+            }
+            AccountAction::Unsensitive => {
+                "UPDATE users SET sensitized_at = NULL, updated_at = NOW() WHERE id = $1"
+            }
+        }
+    }
+}
+
+/// An account as staff see it
+#[derive(Debug, Clone)]
+pub struct AdminAccount {
+    pub account: User,
+    /// `admin`, `moderator` or `user`
+    pub role: &'static str,
+    pub language: Option<String>,
+    /// IP the account last signed in from
+    pub ip: Option<String>,
+    pub confirmed: bool,
+    pub approved: bool,
+    pub disabled: bool,
+    pub silenced: bool,
+    pub sensitized: bool,
+    pub suspended: bool,
+}
+
+/// Internal struct for database rows
+struct AdminAccountRow {
+    id: i64,
+    username: String,
+    email: String,
+    password_hash: String,
+    display_name: Option<String>,
+    note: Option<String>,
+    locked: bool,
+    bot: bool,
+    discoverable: bool,
+    group_account: bool,
+    domain: Option<String>,
+    uri: Option<String>,
+    inbox_url: Option<String>,
+    followers_count: i32,
+    following_count: i32,
+    statuses_count: i32,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    admin: bool,
+    moderator: bool,
+    language: Option<String>,
+    ip: Option<String>,
+    confirmed: bool,
+    approved: bool,
+    disabled: bool,
+    silenced: bool,
+    sensitized: bool,
+    suspended: bool,
+}
+
+impl From<AdminAccountRow> for AdminAccount {
+    fn from(row: AdminAccountRow) -> Self {
+        let role = if row.admin {
+            "admin"
+        } else if row.moderator {
+            "moderator"
+        } else {
+            "user"
+        };
+
+        Self {
+            account: User {
+                id: row.id,
+                username: row.username,
+                email: row.email,
+                password_hash: row.password_hash,
+                display_name: row.display_name,
+                note: row.note,
+                locked: row.locked,
+                bot: row.bot,
+                discoverable: row.discoverable,
+                group_account: row.group_account,
+                domain: row.domain,
+                uri: row.uri,
+                inbox_url: row.inbox_url,
+                followers_count: row.followers_count,
+                following_count: row.following_count,
+                statuses_count: row.statuses_count,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+            },
+            role,
+            language: row.language,
+            ip: row.ip,
+            confirmed: row.confirmed,
+            approved: row.approved,
+            disabled: row.disabled,
+            silenced: row.silenced,
+            sensitized: row.sensitized,
+            suspended: row.suspended,
+        }
+    }
+}
+
+/// Escapes the wildcards of a `LIKE` pattern
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Checks an IP address or CIDR range filter
+fn validate_ip(value: &str) -> Result<(), AdminError> {
+    let invalid = || AdminError::Validation(format!("Invalid IP address: {}", value));
+    let (address, prefix) = match value.split_once('/') {
+        Some((address, prefix)) => (address, Some(prefix)),
+        None => (value, None),
+    };
+    let address: IpAddr = address.parse().map_err(|_| invalid())?;
+
+    if let Some(prefix) = prefix {
+        let max = if address.is_ipv4() { 32 } else { 128 };
+        match prefix.parse::<u8>() {
+            Ok(prefix) if prefix <= max => {}
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(())
+}
+
+impl AdminAccount {
+    /// Moderation state of the account, as recorded in the action log
+    pub fn moderation_state(&self) -> Value {
+        json!({
+            "approved": self.approved,
+            "disabled": self.disabled,
+            "silenced": self.silenced,
+            "sensitized": self.sensitized,
+            "suspended": self.suspended,
+        })
+    }
+
+    /// Searches accounts, newest first
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `filter` - Filters of the search
+    pub async fn search(pool: &PgPool, filter: &AccountFilter) -> Result<Vec<Self>, AdminError> {
+        trace!("Searching accounts: {:?}", filter);
+
+        if let Some(ip) = &filter.ip {
+            validate_ip(ip)?;
+        }
+        let limit = filter.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        let username = filter
+            .username
+            .as_deref()
+            .map(|username| format!("{}%", escape_like(username.trim_start_matches('@'))));
+        let email = filter
+            .email
+            .as_deref()
+            .map(|email| format!("%{}%", escape_like(email)));
+
+        let rows = sqlx::query_as!(
+            AdminAccountRow,
+            r#"
+            SELECT id, username, email, password_hash, display_name, note, locked, bot,
+                   discoverable, group_account, domain, uri, inbox_url, followers_count,
+                   following_count, statuses_count, created_at, updated_at, admin, moderator,
+                   language, host(COALESCE(current_sign_in_ip, last_sign_in_ip)) AS ip,
+                   (confirmed_at IS NOT NULL) AS "confirmed!", approved, disabled,
+                   (silenced_at IS NOT NULL) AS "silenced!",
+                   (sensitized_at IS NOT NULL) AS "sensitized!",
+                   (status = 'suspended') AS "suspended!"
+            FROM users
+            WHERE status <> 'deleted'
+              AND ($1::TEXT IS NULL OR ($1 = 'local') = (domain IS NULL))
+              AND ($2::TEXT IS NULL OR CASE $2
+                    WHEN 'active' THEN approved AND NOT disabled AND silenced_at IS NULL
+                                       AND status <> 'suspended'
+                    WHEN 'pending' THEN NOT approved
+                    WHEN 'disabled' THEN disabled
+                    WHEN 'silenced' THEN silenced_at IS NOT NULL
+                    WHEN 'suspended' THEN status = 'suspended'
+                    ELSE FALSE
+                  END)
+              AND ($3::TEXT IS NULL OR username ILIKE $3)
+              AND ($4::TEXT IS NULL OR email ILIKE $4)
+              AND ($5::TEXT IS NULL OR current_sign_in_ip <<= $5::INET
+                   OR last_sign_in_ip <<= $5::INET)
+              AND ($6::BIGINT IS NULL OR id < $6)
+              AND ($7::BIGINT IS NULL OR id > $7)
+            ORDER BY id DESC
+            LIMIT $8
+            "#,
+            filter.origin.map(|origin| origin.as_str()),
+            filter.state.map(|state| state.as_str()),
+            username,
+            email,
+            filter.ip,
+            filter.max_id,
+            filter.since_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(AdminAccount::from).collect())
+    }
+
+    /// Gets an account by ID
+    pub async fn get<'e>(
+        executor: impl PgExecutor<'e>,
+        id: i64,
+    ) -> Result<Option<Self>, AdminError> {
+        let row = sqlx::query_as!(
+            AdminAccountRow,
+            r#"
+            SELECT id, username, email, password_hash, display_name, note, locked, bot,
+                   discoverable, group_account, domain, uri, inbox_url, followers_count,
+                   following_count, statuses_count, created_at, updated_at, admin, moderator,
+                   language, host(COALESCE(current_sign_in_ip, last_sign_in_ip)) AS ip,
+                   (confirmed_at IS NOT NULL) AS "confirmed!", approved, disabled,
+                   (silenced_at IS NOT NULL) AS "silenced!",
+                   (sensitized_at IS NOT NULL) AS "sensitized!",
+                   (status = 'suspended') AS "suspended!"
+            FROM users
+            WHERE id = $1 AND status <> 'deleted'
+            "#,
+            id
+        )
+        .fetch_optional(executor)
+        .await?;
+
+        Ok(row.map(AdminAccount::from))
+    }
+
+    /// Performs an action on an account and records it
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `moderator_id` - ID of the staff member acting
+    /// * `id` - ID of the account
+    /// * `action` - The action to perform
+    ///
+    /// # Returns
+    ///
+    /// The account after the action, or `None` once a sign-up is rejected
+    pub async fn perform(
+        pool: &PgPool,
+        moderator_id: i64,
+        id: i64,
+        action: AccountAction,
+    ) -> Result<Option<Self>, AdminError> {
+        trace!("Performing {} on account {}", action.as_str(), id);
+
+        let account = Self::get(pool, id).await?.ok_or(AdminError::NotFound(id))?;
+        if matches!(action, AccountAction::Approve | AccountAction::Reject)
+            && (account.approved || !account.account.is_local())
+        {
+            return Err(AdminError::Validation(
+                "Account is not pending approval".to_string(),
+            ));
+        }
+
+        let mut tx = pool.begin().await?;
+        sqlx::query(action.statement())
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        let after = match action {
+            AccountAction::Reject => None,
+            _ => Self::get(&mut *tx, id).await?,
+        };
+        AdminAction::record(
+            &mut *tx,
+            NewAdminAction {
+                account_id: moderator_id,
+                action_type: match action {
+                    AccountAction::Reject => AdminActionType::Delete,
+                    _ => AdminActionType::Update,
+                },
+                action: action.as_str(),
+                target_type: "Account",
+                target_id: Some(id),
+                target: &account.account.acct(),
+                changes: diff(
+                    &account.moderation_state(),
+                    &after
+                        .as_ref()
+                        .map(AdminAccount::moderation_state)
+                        .unwrap_or(Value::Null),
+                ),
+            },
+        )
+        .await?;
+        tx.commit().await?;
+
+        info!(
+            "Account {} performed {} on account {}",
+            moderator_id,
+            action.as_str(),
+            id
+        );
+        Ok(after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_state_names() {
+        for state in [
+            AccountState::Active,
+            AccountState::Pending,
+            AccountState::Disabled,
+            AccountState::Silenced,
+            AccountState::Suspended,
+        ] {
+            assert_eq!(AccountState::parse(state.as_str()), Some(state));
+        }
+        assert_eq!(AccountState::parse("sleeping"), None);
+    }
+
+    #[test]
+    fn test_validate_ip() {
+        assert!(validate_ip("192.0.2.1").is_ok());
+        assert!(validate_ip("192.0.2.0/24").is_ok());
+        assert!(validate_ip("2001:db8::/32").is_ok());
+        assert!(validate_ip("192.0.2.0/33").is_err());
+        assert!(validate_ip("not-an-ip").is_err());
+    }
+
+    #[test]
+    fn test_escape_like() {
+        assert_eq!(escape_like("a_b%c"), "a\\_b\\%c");
+    }
+}
@@ -0,0 +1,234 @@
+//! Admin action log
+//!
+//! Records who on the staff did what to which record, and what it changed,
+//! so staff can look back at the moderation history of an account or of the
+//! instance. The log is append-only: entries are only ever inserted, and
+//! the database rejects updates and deletions.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::{AdminAction, AdminActionType, AdminError};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde_json::{json, Map, Value};
+use sqlx::{PgExecutor, PgPool};
+use tracing::{debug, trace};
+
+/// Default number of log entries listed
+pub const DEFAULT_LOG_LIMIT: i64 = 40;
+/// Maximum number of log entries listed
+pub const MAX_LOG_LIMIT: i64 = 200;
+
+/// An action to record
+#[derive(Debug, Clone)]
+pub struct NewAdminAction<'a> {
+    /// Staff member who acted
+    pub account_id: i64,
+    pub action_type: AdminActionType,
+    /// What was done, such as `approve` or `suspend`
+    pub action: &'a str,
+    /// Kind of record acted on, such as `Account`
+    pub target_type: &'a str,
+    pub target_id: Option<i64>,
+    /// Readable name of the record, such as an account address
+    pub target: &'a str,
+    /// What the action changed, see [`diff`]
+    pub changes: Value,
+}
+
+/// Filters of the action log
+#[derive(Debug, Clone, Default)]
+pub struct ActionLogFilter {
+    /// Staff member who acted
+    pub account_id: Option<i64>,
+    pub action_type: Option<AdminActionType>,
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<i64>,
+    /// Only entries created at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only entries created before this time
+    pub until: Option<DateTime<Utc>>,
+    pub max_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// Describes the fields that differ between two states of a record
+///
+/// # Arguments
+///
+/// * `before` - Fields before the action, or `null` if the record was created
+/// * `after` - Fields after the action, or `null` if the record was deleted
+///
+/// # Returns
+///
+/// An object mapping each changed field to its `before` and `after` values
+pub fn diff(before: &Value, after: &Value) -> Value {
+    let empty = Map::new();
+    let before = before.as_object().unwrap_or(&empty);
+    let after = after.as_object().unwrap_or(&empty);
+
+    let mut changes = Map::new();
+    for key in before.keys().chain(after.keys()) {
+        let (old, new) = (before.get(key), after.get(key));
+        if old != new && !changes.contains_key(key) {
+            changes.insert(
+                key.clone(),
+                json!({
+                    "before": old.cloned().unwrap_or(Value::Null),
+                    "after": new.cloned().unwrap_or(Value::Null),
+                }),
+            );
+        }
+    }
+    Value::Object(changes)
+}
+
+/// Internal struct for database rows
+struct AdminActionRow {
+    id: i64,
+    account_id: Option<i64>,
+    action_type: String,
+    action: String,
+    target_type: String,
+    target_id: Option<i64>,
+    target: String,
+    changes: Value,
+    created_at: NaiveDateTime,
+}
+
+impl TryFrom<AdminActionRow> for AdminAction {
+    type Error = AdminError;
+
+    fn try_from(row: AdminActionRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            account_id: row.account_id,
+            action_type: AdminActionType::parse(&row.action_type).ok_or_else(|| {
+                AdminError::Internal(format!("Unknown action type: {}", row.action_type))
+            })?,
+            action: row.action,
+            target_type: row.target_type,
+            target_id: row.target_id,
+            target: row.target,
+            changes: row.changes,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+        })
+    }
+}
+
+impl AdminAction {
+    /// Records an action in the log
+    ///
+    /// # Arguments
+    ///
+    /// * `executor` - Pool or transaction the action was taken in
+    /// * `action` - The action to record
+    pub async fn record<'e>(
+        executor: impl PgExecutor<'e>,
+        action: NewAdminAction<'_>,
+    ) -> Result<Self, AdminError> {
+        debug!(
+            "Account {} {} {} {:?}",
+            action.account_id, action.action, action.target_type, action.target_id
+        );
+
+        let row = sqlx::query_as!(
+            AdminActionRow,
+            r#"
+            INSERT INTO admin_action_logs
+                (account_id, action_type, action, target_type, target_id, target, changes)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id, account_id, action_type, action, target_type, target_id, target,
+                      changes, created_at
+            "#,
+            action.account_id,
+            action.action_type.as_str(),
+            action.action,
+            action.target_type,
+            action.target_id,
+            action.target,
+            action.changes
+        )
+        .fetch_one(executor)
+        .await?;
+
+        row.try_into()
+    }
+
+    /// Browses the log, newest first
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `filter` - Filters of the log
+    pub async fn search(pool: &PgPool, filter: &ActionLogFilter) -> Result<Vec<Self>, AdminError> {
+        trace!("Browsing action log: {:?}", filter);
+
+        let limit = filter
+            .limit
+            .unwrap_or(DEFAULT_LOG_LIMIT)
+            .clamp(1, MAX_LOG_LIMIT);
+        let rows = sqlx::query_as!(
+            AdminActionRow,
+            r#"
+            SELECT id, account_id, action_type, action, target_type, target_id, target,
+                   changes, created_at
+            FROM admin_action_logs
+            WHERE ($1::BIGINT IS NULL OR account_id = $1)
+              AND ($2::TEXT IS NULL OR action_type = $2)
+              AND ($3::TEXT IS NULL OR action = $3)
+              AND ($4::TEXT IS NULL OR target_type = $4)
+              AND ($5::BIGINT IS NULL OR target_id = $5)
+              AND ($6::TIMESTAMP IS NULL OR created_at >= $6)
+              AND ($7::TIMESTAMP IS NULL OR created_at < $7)
+              AND ($8::BIGINT IS NULL OR id < $8)
+            ORDER BY id DESC
+            LIMIT $9
+            "#,
+            filter.account_id,
+            filter.action_type.map(|action_type| action_type.as_str()),
+            filter.action,
+            filter.target_type,
+            filter.target_id,
+            filter.since.map(|since| since.naive_utc()),
+            filter.until.map(|until| until.naive_utc()),
+            filter.max_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        rows.into_iter().map(AdminAction::try_from).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let changes = diff(
+            &json!({ "silenced": true, "disabled": false }),
+            &json!({ "silenced": false, "disabled": false }),
+        );
+        assert_eq!(
+            changes,
+            json!({ "silenced": { "before": true, "after": false } })
+        );
+    }
+
+    #[test]
+    fn test_diff_of_created_and_deleted_records() {
+        assert_eq!(
+            diff(&Value::Null, &json!({ "content": "Spam wave" })),
+            json!({ "content": { "before": null, "after": "Spam wave" } })
+        );
+        assert_eq!(
+            diff(&json!({ "content": "Spam wave" }), &Value::Null),
+            json!({ "content": { "before": "Spam wave", "after": null } })
+        );
+    }
+}
@@ -1,13 +1,23 @@
 //! Admin functionality for Rustodon
 //!
-//! This module provides admin management functionality.
+//! This module provides admin management functionality: searching and
+//! moderating accounts, and the append-only log of every action staff take.
 //!
 //! # Author
 //!
 //! arkSong (arksong2018@gmail.com)
 
+mod accounts;
+mod action_log;
+
+pub use accounts::{
+    AccountAction, AccountFilter, AccountOrigin, AccountState, AdminAccount, DEFAULT_LIMIT,
+    MAX_LIMIT,
+};
+pub use action_log::{diff, ActionLogFilter, NewAdminAction, DEFAULT_LOG_LIMIT, MAX_LOG_LIMIT};
+
 use serde::{Deserialize, Serialize};
-use tracing::{error, info, trace};
+use tracing::{info, trace};
 
 /// Domain block severity
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,19 +29,50 @@ pub enum DomainBlockSeverity {
 }
 
 /// Admin action type
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum AdminActionType {
     Create,
     Update,
     Delete,
 }
 
+impl AdminActionType {
+    /// Database name of the action type
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AdminActionType::Create => "create",
+            AdminActionType::Update => "update",
+            AdminActionType::Delete => "delete",
+        }
+    }
+
+    /// Parses an action type by name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "create" => Some(AdminActionType::Create),
+            "update" => Some(AdminActionType::Update),
+            "delete" => Some(AdminActionType::Delete),
+            _ => None,
+        }
+    }
+}
+
 /// Admin action
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AdminAction {
     pub id: i64,
+    /// Staff member who acted
+    pub account_id: Option<i64>,
     pub action_type: AdminActionType,
+    /// What was done, such as `approve` or `suspend`
+    pub action: String,
+    /// Kind of record acted on, such as `Account`
+    pub target_type: String,
+    pub target_id: Option<i64>,
+    /// Readable name of the record, such as an account address
     pub target: String,
+    /// What the action changed, as `{"field": {"before": ..., "after": ...}}`
+    pub changes: serde_json::Value,
     pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
@@ -44,6 +85,14 @@ pub enum AdminError {
     Validation(String),
     #[error("Internal error: {0}")]
     Internal(String),
+    #[error("Account not found: {0}")]
+    NotFound(i64),
+}
+
+impl From<sqlx::Error> for AdminError {
+    fn from(e: sqlx::Error) -> Self {
+        AdminError::Database(e.to_string())
+    }
 }
 
 /// Admin service
@@ -72,8 +121,13 @@ impl AdminService {
         // TODO: Implement domain block creation
         Ok(AdminAction {
             id: 1,
+            account_id: None,
             action_type: AdminActionType::Create,
+            action: "create".to_string(),
+            target_type: "DomainBlock".to_string(),
+            target_id: None,
             target: _domain.to_string(),
+            changes: serde_json::json!({}),
             created_at: chrono::Utc::now(),
         })
     }
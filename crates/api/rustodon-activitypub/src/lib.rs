@@ -6,9 +6,221 @@
 //!
 //! arkSong (arksong2018@gmail.com)
 
+use serde_json::{json, Value};
 use sqlx::PgPool;
 use tracing::{info, trace};
 
+/// ActivityStreams JSON-LD context
+pub const ACTIVITY_STREAMS_CONTEXT: &str = "https://www.w3.org/ns/activitystreams";
+
+/// JSON-LD context of actor public keys
+pub const SECURITY_CONTEXT: &str = "https://w3id.org/security/v1";
+
+/// Audience of public activities
+pub const PUBLIC_COLLECTION: &str = "https://www.w3.org/ns/activitystreams#Public";
+
+/// Build the actor URI of a local account
+///
+/// # Arguments
+///
+/// * `domain` - Local instance domain
+/// * `username` - Local username
+pub fn local_actor_uri(domain: &str, username: &str) -> String {
+    format!("https://{}/users/{}", domain, username)
+}
+
+/// Build the ID of the public key of an actor
+///
+/// # Arguments
+///
+/// * `actor_uri` - URI of the actor
+pub fn actor_key_id(actor_uri: &str) -> String {
+    format!("{}#main-key", actor_uri)
+}
+
+/// Profile of a local account, as published on its actor document
+#[derive(Debug, Clone, Copy)]
+pub struct ActorProfile<'a> {
+    pub username: &'a str,
+    pub display_name: Option<&'a str>,
+    pub note: Option<&'a str>,
+    pub locked: bool,
+    pub bot: bool,
+    pub group: bool,
+    pub discoverable: bool,
+}
+
+/// Build the actor document of a local account
+///
+/// Remote servers fetch it to learn about the account and to get the
+/// public key checking the signatures of its deliveries.
+///
+/// # Arguments
+///
+/// * `actor_uri` - URI of the account
+/// * `profile` - Profile of the account
+/// * `public_key_pem` - SPKI PEM of the account's public key
+pub fn actor_document(actor_uri: &str, profile: &ActorProfile, public_key_pem: &str) -> Value {
+    trace!("Building actor document of {}", actor_uri);
+    let actor_type = if profile.group {
+        "Group"
+    } else if profile.bot {
+        "Service"
+    } else {
+        "Person"
+    };
+
+    json!({
+        "@context": [ACTIVITY_STREAMS_CONTEXT, SECURITY_CONTEXT],
+        "id": actor_uri,
+        "type": actor_type,
+        "preferredUsername": profile.username,
+        "name": profile.display_name.unwrap_or(profile.username),
+        "summary": profile.note.unwrap_or_default(),
+        "inbox": format!("{}/inbox", actor_uri),
+        "outbox": format!("{}/outbox", actor_uri),
+        "followers": format!("{}/followers", actor_uri),
+        "following": format!("{}/following", actor_uri),
+        "featured": format!("{}/collections/featured", actor_uri),
+        "manuallyApprovesFollowers": profile.locked,
+        "discoverable": profile.discoverable,
+        "publicKey": {
+            "id": actor_key_id(actor_uri),
+            "owner": actor_uri,
+            "publicKeyPem": public_key_pem
+        }
+    })
+}
+
+/// Build an `Accept` activity answering a remote `Follow`
+///
+/// # Arguments
+///
+/// * `actor_uri` - URI of the local account accepting the follow
+/// * `follower_uri` - URI of the remote account that sent the follow
+/// * `follow_uri` - URI of the original `Follow` activity, if known
+/// * `follow_request_id` - Local ID of the follow request, used to build a unique activity ID
+pub fn accept_follow_activity(
+    actor_uri: &str,
+    follower_uri: &str,
+    follow_uri: Option<&str>,
+    follow_request_id: i64,
+) -> Value {
+    trace!("Building Accept activity for follow from {}", follower_uri);
+    follow_response_activity(
+        "Accept",
+        "accepts",
+        actor_uri,
+        follower_uri,
+        follow_uri,
+        follow_request_id,
+    )
+}
+
+/// Build a `Reject` activity answering a remote `Follow`
+///
+/// # Arguments
+///
+/// * `actor_uri` - URI of the local account rejecting the follow
+/// * `follower_uri` - URI of the remote account that sent the follow
+/// * `follow_uri` - URI of the original `Follow` activity, if known
+/// * `follow_request_id` - Local ID of the follow request, used to build a unique activity ID
+pub fn reject_follow_activity(
+    actor_uri: &str,
+    follower_uri: &str,
+    follow_uri: Option<&str>,
+    follow_request_id: i64,
+) -> Value {
+    trace!("Building Reject activity for follow from {}", follower_uri);
+    follow_response_activity(
+        "Reject",
+        "rejects",
+        actor_uri,
+        follower_uri,
+        follow_uri,
+        follow_request_id,
+    )
+}
+
+fn follow_response_activity(
+    activity_type: &str,
+    fragment: &str,
+    actor_uri: &str,
+    follower_uri: &str,
+    follow_uri: Option<&str>,
+    follow_request_id: i64,
+) -> Value {
+    // Remote servers match the response against the Follow they sent, so echo its ID back
+    let follow_id = follow_uri
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{}#follows/{}", follower_uri, follow_request_id));
+
+    json!({
+        "@context": ACTIVITY_STREAMS_CONTEXT,
+        "id": format!("{}#{}/follows/{}", actor_uri, fragment, follow_request_id),
+        "type": activity_type,
+        "actor": actor_uri,
+        "object": {
+            "id": follow_id,
+            "type": "Follow",
+            "actor": follower_uri,
+            "object": actor_uri
+        }
+    })
+}
+
+/// Build the featured collection of a local account
+///
+/// The collection lists the actors the account endorses on its profile.
+///
+/// # Arguments
+///
+/// * `actor_uri` - URI of the local account
+/// * `endorsed_uris` - Actor URIs of the endorsed accounts
+pub fn featured_collection(actor_uri: &str, endorsed_uris: &[String]) -> Value {
+    trace!("Building featured collection of {}", actor_uri);
+    json!({
+        "@context": ACTIVITY_STREAMS_CONTEXT,
+        "id": format!("{}/collections/featured", actor_uri),
+        "type": "OrderedCollection",
+        "totalItems": endorsed_uris.len(),
+        "orderedItems": endorsed_uris
+    })
+}
+
+/// Build a `Delete` activity announcing that a local account is gone
+///
+/// Remote servers receiving it remove the account and everything it posted.
+///
+/// # Arguments
+///
+/// * `actor_uri` - URI of the deleted local account
+pub fn delete_actor_activity(actor_uri: &str) -> Value {
+    trace!("Building Delete activity for {}", actor_uri);
+    json!({
+        "@context": ACTIVITY_STREAMS_CONTEXT,
+        "id": format!("{}#delete", actor_uri),
+        "type": "Delete",
+        "actor": actor_uri,
+        "to": [PUBLIC_COLLECTION],
+        "object": actor_uri
+    })
+}
+
+/// Build the tombstone served in place of a deleted local account
+///
+/// # Arguments
+///
+/// * `actor_uri` - URI of the deleted local account
+pub fn actor_tombstone(actor_uri: &str) -> Value {
+    json!({
+        "@context": ACTIVITY_STREAMS_CONTEXT,
+        "id": actor_uri,
+        "type": "Tombstone",
+        "formerType": "Person"
+    })
+}
+
 /// ActivityPub service
 pub struct ActivityPubService {
     #[allow(dead_code)]
@@ -44,6 +256,117 @@ impl ActivityPubService {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_featured_collection() {
+        let actor = local_actor_uri("rustodon.example.com", "alice");
+        let collection =
+            featured_collection(&actor, &["https://remote.example/users/bob".to_string()]);
+
+        assert_eq!(
+            collection["id"],
+            "https://rustodon.example.com/users/alice/collections/featured"
+        );
+        assert_eq!(collection["type"], "OrderedCollection");
+        assert_eq!(collection["totalItems"], 1);
+        assert_eq!(
+            collection["orderedItems"][0],
+            "https://remote.example/users/bob"
+        );
+    }
+
+    #[test]
+    fn test_actor_document() {
+        let actor = local_actor_uri("rustodon.example.com", "alice");
+        let profile = ActorProfile {
+            username: "alice",
+            display_name: None,
+            note: Some("Hi"),
+            locked: true,
+            bot: false,
+            group: false,
+            discoverable: true,
+        };
+        let document = actor_document(&actor, &profile, "PEM");
+
+        assert_eq!(document["type"], "Person");
+        assert_eq!(document["name"], "alice");
+        assert_eq!(document["summary"], "Hi");
+        assert_eq!(document["manuallyApprovesFollowers"], true);
+        assert_eq!(
+            document["inbox"],
+            "https://rustodon.example.com/users/alice/inbox"
+        );
+        assert_eq!(
+            document["publicKey"]["id"],
+            "https://rustodon.example.com/users/alice#main-key"
+        );
+        assert_eq!(document["publicKey"]["owner"], actor);
+        assert_eq!(document["publicKey"]["publicKeyPem"], "PEM");
+
+        let bot = ActorProfile {
+            bot: true,
+            ..profile
+        };
+        assert_eq!(actor_document(&actor, &bot, "PEM")["type"], "Service");
+    }
+
+    #[test]
+    fn test_delete_actor_activity() {
+        let actor = local_actor_uri("rustodon.example.com", "alice");
+        let activity = delete_actor_activity(&actor);
+
+        assert_eq!(activity["type"], "Delete");
+        assert_eq!(
+            activity["id"],
+            "https://rustodon.example.com/users/alice#delete"
+        );
+        assert_eq!(activity["actor"], activity["object"]);
+        assert_eq!(activity["to"][0], PUBLIC_COLLECTION);
+        assert_eq!(actor_tombstone(&actor)["type"], "Tombstone");
+    }
+
+    #[test]
+    fn test_accept_follow_activity() {
+        let actor = local_actor_uri("rustodon.example.com", "alice");
+        let activity = accept_follow_activity(
+            &actor,
+            "https://remote.example/users/bob",
+            Some("https://remote.example/follows/1"),
+            42,
+        );
+
+        assert_eq!(activity["type"], "Accept");
+        assert_eq!(
+            activity["actor"],
+            "https://rustodon.example.com/users/alice"
+        );
+        assert_eq!(activity["object"]["id"], "https://remote.example/follows/1");
+        assert_eq!(
+            activity["object"]["actor"],
+            "https://remote.example/users/bob"
+        );
+    }
+
+    #[test]
+    fn test_reject_follow_activity_without_follow_uri() {
+        let activity = reject_follow_activity(
+            "https://a.example/users/alice",
+            "https://b.example/users/bob",
+            None,
+            7,
+        );
+
+        assert_eq!(activity["type"], "Reject");
+        assert_eq!(
+            activity["id"],
+            "https://a.example/users/alice#rejects/follows/7"
+        );
+        assert_eq!(
+            activity["object"]["id"],
+            "https://b.example/users/bob#follows/7"
+        );
+    }
+
     #[tokio::test]
     async fn test_activitypub_service_new() {
         // This would require a real database connection for full testing
@@ -0,0 +1,121 @@
+//! Account self-deletion endpoints
+//!
+//! Lets an account ask for its own deletion on `/api/v1/account_deletion`
+//! by confirming its password, see when it will be purged, and cancel the
+//! request while the grace period lasts.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::extractors::CurrentUser;
+use crate::serializers::{error_response, success};
+use crate::AppState;
+use axum::{extract::State, http::StatusCode, response::Response, routing::get, Json, Router};
+use rustodon_account_deletion_requests::{AccountDeletionError, AccountDeletionRequest};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{error, info};
+
+/// Confirmation of a deletion request
+#[derive(Debug, Deserialize)]
+pub struct DeletionParams {
+    /// Current password of the account
+    pub password: String,
+}
+
+/// Routes of the account deletion API
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new().route(
+        "/api/v1/account_deletion",
+        get(get_deletion_handler)
+            .post(request_deletion_handler)
+            .delete(cancel_deletion_handler),
+    )
+}
+
+/// Maps account deletion errors to API responses
+fn deletion_error_response(e: AccountDeletionError) -> Response {
+    match e {
+        AccountDeletionError::NotFound(_) | AccountDeletionError::AccountNotFound(_) => {
+            error_response(StatusCode::NOT_FOUND, "Record not found")
+        }
+        AccountDeletionError::InvalidPassword => {
+            error_response(StatusCode::UNPROCESSABLE_ENTITY, "Invalid password")
+        }
+        AccountDeletionError::AlreadyRequested => error_response(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Account deletion already requested",
+        ),
+        e => {
+            error!("Account deletion operation failed: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+/// Renders a deletion request
+fn deletion_json(request: &AccountDeletionRequest) -> Value {
+    json!({
+        "delete_after": request.delete_after.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+        "created_at": request.created_at.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+    })
+}
+
+/// Request deletion handler
+async fn request_deletion_handler(
+    State(state): State<AppState>,
+    CurrentUser(user): CurrentUser,
+    Json(params): Json<DeletionParams>,
+) -> Response {
+    match AccountDeletionRequest::create(
+        &state.pool,
+        user.id,
+        &params.password,
+        state.config.account_deletion_grace_days,
+    )
+    .await
+    {
+        Ok(request) => {
+            info!("{} requested the deletion of their account", user.username);
+            success(deletion_json(&request))
+        }
+        Err(e) => deletion_error_response(e),
+    }
+}
+
+/// Get deletion handler
+async fn get_deletion_handler(
+    State(state): State<AppState>,
+    CurrentUser(user): CurrentUser,
+) -> Response {
+    match AccountDeletionRequest::get(&state.pool, user.id).await {
+        Ok(Some(request)) => success(deletion_json(&request)),
+        Ok(None) => deletion_error_response(AccountDeletionError::NotFound(user.id)),
+        Err(e) => deletion_error_response(e),
+    }
+}
+
+/// Cancel deletion handler
+async fn cancel_deletion_handler(
+    State(state): State<AppState>,
+    CurrentUser(user): CurrentUser,
+) -> Response {
+    match AccountDeletionRequest::cancel(&state.pool, user.id).await {
+        Ok(()) => {
+            info!("{} cancelled the deletion of their account", user.username);
+            success(json!({}))
+        }
+        Err(e) => deletion_error_response(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+}
@@ -0,0 +1,260 @@
+//! Account warning endpoints
+//!
+//! Moderators strike accounts on `/api/v1/admin/accounts/:id/action`, which
+//! applies the action right away, emails the owner and is recorded in the
+//! admin action log. Owners list their
+//! strikes, along with the state of their appeals, on
+//! `/api/v1/account_warnings`.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::admin_accounts::{account_label, begin_action, commit_action};
+use crate::appeals::appeal_json;
+use crate::extractors::{CurrentUser, StaffUser};
+use crate::serializers::{error_response, success};
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Response,
+    routing::{get, post},
+    Json, Router,
+};
+use rustodon_account_warnings::{
+    AccountWarning, AccountWarningError, NewAccountWarning, StrikeAction,
+};
+use rustodon_admin::{diff, AdminActionType, NewAdminAction};
+use rustodon_appeals::Appeal;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{error, info, warn};
+
+/// Default number of strikes listed
+const DEFAULT_LIMIT: i64 = 20;
+/// Maximum number of strikes listed
+const MAX_LIMIT: i64 = 40;
+
+/// Parameters of a moderation action
+#[derive(Debug, Deserialize)]
+pub struct ActionParams {
+    /// The action, one of the strike actions
+    #[serde(rename = "type")]
+    pub action: Option<String>,
+    /// Explanation for the owner of the account
+    pub text: Option<String>,
+    /// Statuses the strike is about
+    pub status_ids: Option<Vec<String>>,
+    /// Whether to email the owner, defaults to true
+    pub send_email_notification: Option<bool>,
+}
+
+/// Query parameters of the strike list
+#[derive(Debug, Deserialize)]
+struct WarningsQuery {
+    limit: Option<i64>,
+}
+
+/// Routes of the account warnings API
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/account_warnings", get(list_warnings_handler))
+        .route("/api/v1/account_warnings/:id", get(get_warning_handler))
+        .route("/api/v1/admin/accounts/:id/action", post(action_handler))
+}
+
+/// Maps account warning errors to API responses
+fn warnings_error_response(e: AccountWarningError) -> Response {
+    match e {
+        AccountWarningError::NotFound(_) | AccountWarningError::AccountNotFound(_) => {
+            error_response(StatusCode::NOT_FOUND, "Record not found")
+        }
+        AccountWarningError::InvalidAction(action) => error_response(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("Invalid action: {}", action),
+        ),
+        AccountWarningError::Validation(message) => {
+            error_response(StatusCode::UNPROCESSABLE_ENTITY, message)
+        }
+        e => {
+            error!("Account warning operation failed: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+/// Renders a strike with its appeal
+fn warning_json(warning: &AccountWarning, appeal: Option<&Appeal>) -> Value {
+    json!({
+        "id": warning.id.to_string(),
+        "action": warning.action.as_str(),
+        "text": warning.text,
+        "status_ids": warning
+            .status_ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>(),
+        "target_account_id": warning.target_account_id.to_string(),
+        "overruled": warning.overruled_at.is_some(),
+        "appeal": appeal.map(appeal_json),
+        "appealable_until": warning
+            .appeal_deadline()
+            .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+            .to_string(),
+        "created_at": warning.created_at.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+    })
+}
+
+/// Parses the status IDs of a moderation action
+fn parse_status_ids(ids: &[String]) -> Result<Vec<i64>, String> {
+    ids.iter()
+        .map(|id| id.parse().map_err(|_| format!("Invalid status ID: {}", id)))
+        .collect()
+}
+
+/// List warnings handler
+async fn list_warnings_handler(
+    State(state): State<AppState>,
+    CurrentUser(user): CurrentUser,
+    Query(query): Query<WarningsQuery>,
+) -> Response {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let warnings = match AccountWarning::by_target_account(&state.pool, user.id, limit).await {
+        Ok(warnings) => warnings,
+        Err(e) => return warnings_error_response(e),
+    };
+
+    let ids: Vec<i64> = warnings.iter().map(|warning| warning.id).collect();
+    let appeals = match Appeal::by_strikes(&state.pool, &ids).await {
+        Ok(appeals) => appeals,
+        Err(e) => {
+            error!("Failed to load appeals: {}", e);
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error");
+        }
+    };
+
+    success(
+        warnings
+            .iter()
+            .map(|warning| {
+                let appeal = appeals.iter().find(|appeal| appeal.strike_id == warning.id);
+                warning_json(warning, appeal)
+            })
+            .collect(),
+    )
+}
+
+/// Get warning handler
+async fn get_warning_handler(
+    State(state): State<AppState>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+) -> Response {
+    let warning = match AccountWarning::get(&state.pool, id).await {
+        Ok(Some(warning)) if warning.target_account_id == user.id => warning,
+        Ok(_) => return warnings_error_response(AccountWarningError::NotFound(id)),
+        Err(e) => return warnings_error_response(e),
+    };
+
+    match Appeal::by_strikes(&state.pool, &[id]).await {
+        Ok(appeals) => success(warning_json(&warning, appeals.first())),
+        Err(e) => {
+            error!("Failed to load appeal of strike {}: {}", id, e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+/// Moderation action handler
+async fn action_handler(
+    State(state): State<AppState>,
+    StaffUser(moderator): StaffUser,
+    Path(account_id): Path<i64>,
+    Json(params): Json<ActionParams>,
+) -> Response {
+    let action = match StrikeAction::parse(params.action.as_deref().unwrap_or("none")) {
+        Ok(action) => action,
+        Err(e) => return warnings_error_response(e),
+    };
+    let status_ids = match parse_status_ids(params.status_ids.as_deref().unwrap_or_default()) {
+        Ok(status_ids) => status_ids,
+        Err(message) => return error_response(StatusCode::UNPROCESSABLE_ENTITY, message),
+    };
+
+    let mut tx = match begin_action(&state).await {
+        Ok(tx) => tx,
+        Err(response) => return response,
+    };
+    let warning = match AccountWarning::create(
+        &mut tx,
+        moderator.id,
+        NewAccountWarning {
+            target_account_id: account_id,
+            action,
+            text: params.text.unwrap_or_default(),
+            status_ids,
+        },
+    )
+    .await
+    {
+        Ok(warning) => warning,
+        Err(e) => return warnings_error_response(e),
+    };
+    info!(
+        "{} took {} action against account {}",
+        moderator.username,
+        action.as_str(),
+        account_id
+    );
+    if let Err(response) = commit_action(
+        tx,
+        NewAdminAction {
+            account_id: moderator.id,
+            action_type: AdminActionType::Create,
+            action: action.as_str(),
+            target_type: "AccountWarning",
+            target_id: Some(warning.id),
+            target: &account_label(&state, account_id).await,
+            changes: diff(
+                &Value::Null,
+                &json!({
+                    "action": action.as_str(),
+                    "text": warning.text,
+                    "status_ids": warning.status_ids,
+                }),
+            ),
+        },
+    )
+    .await
+    {
+        return response;
+    }
+
+    if params.send_email_notification.unwrap_or(true) {
+        if let Err(e) = warning.notify(&state.pool, state.mailer.as_ref()).await {
+            warn!("Failed to email account {} about strike: {}", account_id, e);
+        }
+    }
+
+    success(warning_json(&warning, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+
+    #[test]
+    fn test_parse_status_ids() {
+        assert_eq!(
+            parse_status_ids(&["1".to_string(), "20".to_string()]).unwrap(),
+            vec![1, 20]
+        );
+        assert!(parse_status_ids(&["abc".to_string()]).is_err());
+    }
+}
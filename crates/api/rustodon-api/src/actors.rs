@@ -0,0 +1,94 @@
+//! Actor endpoints
+//!
+//! Serves the ActivityPub actor of local accounts on `/users/:username`.
+//! Remote servers fetch it to learn about the account and to get the public
+//! key checking the signatures of the activities it delivers. Deleted
+//! accounts are served as a tombstone, so that servers which missed the
+//! `Delete` activity drop the account when they next fetch it.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::serializers::error_response;
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use rustodon_activitypub::{actor_document, actor_tombstone, local_actor_uri, ActorProfile};
+use rustodon_db::User;
+use rustodon_federation::{ActorKey, ACTIVITY_JSON_CONTENT_TYPE};
+use tracing::{debug, error};
+
+/// Routes of the actor endpoints
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new().route("/users/:username", get(actor_handler))
+}
+
+/// Actor handler
+async fn actor_handler(State(state): State<AppState>, Path(username): Path<String>) -> Response {
+    debug!("Serving actor {}", username);
+
+    let account = match User::get_by_username(&state.pool, &username).await {
+        Ok(Some(account)) if account.is_local() => account,
+        Ok(_) => return error_response(StatusCode::NOT_FOUND, "Record not found"),
+        Err(e) => {
+            error!("Failed to load account {}: {}", username, e);
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error");
+        }
+    };
+
+    let actor_uri = local_actor_uri(&state.config.local_domain, &account.username);
+    match account.is_deleted(&state.pool).await {
+        Ok(false) => {}
+        Ok(true) => {
+            return (
+                StatusCode::GONE,
+                [(header::CONTENT_TYPE, ACTIVITY_JSON_CONTENT_TYPE)],
+                Json(actor_tombstone(&actor_uri)),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            error!("Failed to load account {}: {}", username, e);
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error");
+        }
+    }
+
+    let key = match ActorKey::for_account(&state.pool, account.id).await {
+        Ok(key) => key,
+        Err(e) => {
+            error!("Failed to load key of account {}: {}", account.id, e);
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error");
+        }
+    };
+
+    let profile = ActorProfile {
+        username: &account.username,
+        display_name: account.display_name.as_deref(),
+        note: account.note.as_deref(),
+        locked: account.locked,
+        bot: account.bot,
+        group: account.group_account,
+        discoverable: account.discoverable,
+    };
+    (
+        [(header::CONTENT_TYPE, ACTIVITY_JSON_CONTENT_TYPE)],
+        Json(actor_document(&actor_uri, &profile, &key.public_key)),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+}
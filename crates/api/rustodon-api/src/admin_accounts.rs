@@ -0,0 +1,405 @@
+//! Admin account endpoints
+//!
+//! Lets staff search accounts on `/api/v1/admin/accounts`, decide on
+//! pending sign-ups, lift restrictions, and keep moderation notes on
+//! accounts. Every action lands in the admin action log, recorded in the
+//! transaction taking it.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::extractors::StaffUser;
+use crate::serializers::{account_json, error_response, format_timestamp, success};
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Response,
+    routing::{delete, get, post, MethodRouter},
+    Json, Router,
+};
+use rustodon_account_moderation_notes::{AccountModerationNote, ModerationNoteError};
+use rustodon_admin::{
+    diff, AccountAction, AccountFilter, AccountOrigin, AccountState, AdminAccount, AdminAction,
+    AdminActionType, AdminError, NewAdminAction,
+};
+use rustodon_db::User;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::{Postgres, Transaction};
+use tracing::{error, info};
+
+/// Query parameters of the account search
+#[derive(Debug, Default, Deserialize)]
+pub struct AdminAccountsQuery {
+    pub local: Option<bool>,
+    pub remote: Option<bool>,
+    pub active: Option<bool>,
+    pub pending: Option<bool>,
+    pub disabled: Option<bool>,
+    pub silenced: Option<bool>,
+    pub suspended: Option<bool>,
+    pub username: Option<String>,
+    pub email: Option<String>,
+    pub ip: Option<String>,
+    pub max_id: Option<i64>,
+    pub since_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+impl AdminAccountsQuery {
+    /// Turns the flags of the query into a search filter
+    fn filter(self) -> AccountFilter {
+        let origin = match (self.local, self.remote) {
+            (Some(true), _) => Some(AccountOrigin::Local),
+            (_, Some(true)) => Some(AccountOrigin::Remote),
+            _ => None,
+        };
+        let state = [
+            (self.active, AccountState::Active),
+            (self.pending, AccountState::Pending),
+            (self.disabled, AccountState::Disabled),
+            (self.silenced, AccountState::Silenced),
+            (self.suspended, AccountState::Suspended),
+        ]
+        .into_iter()
+        .find(|(flag, _)| *flag == Some(true))
+        .map(|(_, state)| state);
+        let present = |value: Option<String>| value.filter(|value| !value.trim().is_empty());
+
+        AccountFilter {
+            origin,
+            state,
+            username: present(self.username),
+            email: present(self.email),
+            ip: present(self.ip),
+            max_id: self.max_id,
+            since_id: self.since_id,
+            limit: self.limit,
+        }
+    }
+}
+
+/// Parameters of a new moderation note
+#[derive(Debug, Deserialize)]
+pub struct NoteParams {
+    pub content: String,
+}
+
+/// Routes of the admin accounts API
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/admin/accounts", get(list_accounts_handler))
+        .route("/api/v1/admin/accounts/:id", get(get_account_handler))
+        .route(
+            "/api/v1/admin/accounts/:id/approve",
+            action_route(AccountAction::Approve),
+        )
+        .route(
+            "/api/v1/admin/accounts/:id/reject",
+            action_route(AccountAction::Reject),
+        )
+        .route(
+            "/api/v1/admin/accounts/:id/enable",
+            action_route(AccountAction::Enable),
+        )
+        .route(
+            "/api/v1/admin/accounts/:id/unsilence",
+            action_route(AccountAction::Unsilence),
+        )
+        .route(
+            "/api/v1/admin/accounts/:id/unsuspend",
+            action_route(AccountAction::Unsuspend),
+        )
+        .route(
+            "/api/v1/admin/accounts/:id/unsensitive",
+            action_route(AccountAction::Unsensitive),
+        )
+        .route(
+            "/api/v1/admin/accounts/:id/notes",
+            get(list_notes_handler).post(create_note_handler),
+        )
+        .route(
+            "/api/v1/admin/accounts/:id/notes/:note_id",
+            delete(delete_note_handler),
+        )
+}
+
+/// Maps admin errors to API responses
+fn admin_error_response(e: AdminError) -> Response {
+    match e {
+        AdminError::NotFound(_) => error_response(StatusCode::NOT_FOUND, "Record not found"),
+        AdminError::Validation(message) => {
+            error_response(StatusCode::UNPROCESSABLE_ENTITY, message)
+        }
+        e => {
+            error!("Admin operation failed: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+/// Maps moderation note errors to API responses
+fn notes_error_response(e: ModerationNoteError) -> Response {
+    match e {
+        ModerationNoteError::NotFound(_) | ModerationNoteError::AccountNotFound(_) => {
+            error_response(StatusCode::NOT_FOUND, "Record not found")
+        }
+        ModerationNoteError::Validation(message) => {
+            error_response(StatusCode::UNPROCESSABLE_ENTITY, message)
+        }
+        e => {
+            error!("Moderation note operation failed: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+/// Begins the transaction a staff action is taken in
+pub(crate) async fn begin_action(
+    state: &AppState,
+) -> Result<Transaction<'static, Postgres>, Response> {
+    state.pool.begin().await.map_err(|e| {
+        error!("Failed to begin staff action: {}", e);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+    })
+}
+
+/// Records a staff action in the transaction that took it, and commits both
+///
+/// Nothing is committed when the action can't be recorded, so that no
+/// staff action goes missing from the log.
+pub(crate) async fn commit_action(
+    mut tx: Transaction<'_, Postgres>,
+    action: NewAdminAction<'_>,
+) -> Result<(), Response> {
+    let committed = match AdminAction::record(&mut *tx, action.clone()).await {
+        Ok(_) => tx.commit().await.map_err(AdminError::from),
+        Err(e) => Err(e),
+    };
+    committed.map_err(|e| {
+        error!(
+            "Failed to record {} of {} {:?}: {}",
+            action.action, action.target_type, action.target_id, e
+        );
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+    })
+}
+
+/// Readable name of an account for the action log
+pub(crate) async fn account_label(state: &AppState, id: i64) -> String {
+    match User::get_by_id(&state.pool, id).await {
+        Ok(Some(user)) => user.acct(),
+        _ => id.to_string(),
+    }
+}
+
+/// Renders an account as staff see it
+fn admin_account_json(account: &AdminAccount, local_domain: &str) -> Value {
+    let user = &account.account;
+
+    json!({
+        "id": user.id.to_string(),
+        "username": user.username,
+        "domain": user.domain,
+        "created_at": format_timestamp(user.created_at),
+        "email": user.email,
+        "ip": account.ip,
+        "ips": [],
+        "locale": account.language,
+        "invite_request": null,
+        "role": { "name": account.role },
+        "confirmed": account.confirmed,
+        "approved": account.approved,
+        "disabled": account.disabled,
+        "silenced": account.silenced,
+        "sensitized": account.sensitized,
+        "suspended": account.suspended,
+        "account": account_json(user, local_domain),
+    })
+}
+
+/// Renders a moderation note
+fn note_json(note: &AccountModerationNote) -> Value {
+    json!({
+        "id": note.id.to_string(),
+        "account_id": note.account_id.map(|id| id.to_string()),
+        "target_account_id": note.target_account_id.to_string(),
+        "content": note.content,
+        "created_at": note.created_at.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+    })
+}
+
+/// List accounts handler
+async fn list_accounts_handler(
+    State(state): State<AppState>,
+    StaffUser(_moderator): StaffUser,
+    Query(query): Query<AdminAccountsQuery>,
+) -> Response {
+    match AdminAccount::search(&state.pool, &query.filter()).await {
+        Ok(accounts) => success(
+            accounts
+                .iter()
+                .map(|account| admin_account_json(account, &state.config.local_domain))
+                .collect(),
+        ),
+        Err(e) => admin_error_response(e),
+    }
+}
+
+/// Get account handler
+async fn get_account_handler(
+    State(state): State<AppState>,
+    StaffUser(_moderator): StaffUser,
+    Path(id): Path<i64>,
+) -> Response {
+    match AdminAccount::get(&state.pool, id).await {
+        Ok(Some(account)) => success(admin_account_json(&account, &state.config.local_domain)),
+        Ok(None) => admin_error_response(AdminError::NotFound(id)),
+        Err(e) => admin_error_response(e),
+    }
+}
+
+/// Route performing an account action
+fn action_route(action: AccountAction) -> MethodRouter<AppState> {
+    post(
+        move |State(state): State<AppState>,
+              StaffUser(moderator): StaffUser,
+              Path(id): Path<i64>| async move {
+            match AdminAccount::perform(&state.pool, moderator.id, id, action).await {
+                Ok(account) => {
+                    info!(
+                        "{} performed {} on account {}",
+                        moderator.username,
+                        action.as_str(),
+                        id
+                    );
+                    success(
+                        account
+                            .map(|account| admin_account_json(&account, &state.config.local_domain))
+                            .unwrap_or_else(|| json!({})),
+                    )
+                }
+                Err(e) => admin_error_response(e),
+            }
+        },
+    )
+}
+
+/// List notes handler
+async fn list_notes_handler(
+    State(state): State<AppState>,
+    StaffUser(_moderator): StaffUser,
+    Path(id): Path<i64>,
+) -> Response {
+    match AccountModerationNote::by_target_account(&state.pool, id).await {
+        Ok(notes) => success(notes.iter().map(note_json).collect()),
+        Err(e) => notes_error_response(e),
+    }
+}
+
+/// Create note handler
+async fn create_note_handler(
+    State(state): State<AppState>,
+    StaffUser(moderator): StaffUser,
+    Path(id): Path<i64>,
+    Json(params): Json<NoteParams>,
+) -> Response {
+    let mut tx = match begin_action(&state).await {
+        Ok(tx) => tx,
+        Err(response) => return response,
+    };
+    let note = match AccountModerationNote::create(&mut tx, moderator.id, id, &params.content).await
+    {
+        Ok(note) => note,
+        Err(e) => return notes_error_response(e),
+    };
+
+    if let Err(response) = commit_action(
+        tx,
+        NewAdminAction {
+            account_id: moderator.id,
+            action_type: AdminActionType::Create,
+            action: "create",
+            target_type: "AccountModerationNote",
+            target_id: Some(note.id),
+            target: &account_label(&state, id).await,
+            changes: diff(&Value::Null, &json!({ "content": note.content })),
+        },
+    )
+    .await
+    {
+        return response;
+    }
+    success(note_json(&note))
+}
+
+/// Delete note handler
+async fn delete_note_handler(
+    State(state): State<AppState>,
+    StaffUser(moderator): StaffUser,
+    Path((id, note_id)): Path<(i64, i64)>,
+) -> Response {
+    match AccountModerationNote::get(&state.pool, note_id).await {
+        Ok(Some(note)) if note.target_account_id == id => {}
+        Ok(_) => return notes_error_response(ModerationNoteError::NotFound(note_id)),
+        Err(e) => return notes_error_response(e),
+    }
+    let mut tx = match begin_action(&state).await {
+        Ok(tx) => tx,
+        Err(response) => return response,
+    };
+    let note = match AccountModerationNote::delete(&mut tx, note_id).await {
+        Ok(note) => note,
+        Err(e) => return notes_error_response(e),
+    };
+
+    if let Err(response) = commit_action(
+        tx,
+        NewAdminAction {
+            account_id: moderator.id,
+            action_type: AdminActionType::Delete,
+            action: "destroy",
+            target_type: "AccountModerationNote",
+            target_id: Some(note_id),
+            target: &account_label(&state, id).await,
+            changes: diff(&json!({ "content": note.content }), &Value::Null),
+        },
+    )
+    .await
+    {
+        return response;
+    }
+    success(json!({}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+
+    #[test]
+    fn test_query_filter() {
+        let filter = AdminAccountsQuery {
+            remote: Some(true),
+            suspended: Some(true),
+            username: Some(" ".to_string()),
+            email: Some("example.com".to_string()),
+            ..Default::default()
+        }
+        .filter();
+        assert_eq!(filter.origin, Some(AccountOrigin::Remote));
+        assert_eq!(filter.state, Some(AccountState::Suspended));
+        assert_eq!(filter.username, None);
+        assert_eq!(filter.email.as_deref(), Some("example.com"));
+
+        let filter = AdminAccountsQuery::default().filter();
+        assert_eq!(filter.origin, None);
+        assert_eq!(filter.state, None);
+    }
+}
@@ -0,0 +1,134 @@
+//! Admin action log endpoints
+//!
+//! Lets staff browse the action log on `/api/v1/admin/action_logs`,
+//! filtered by who acted, what was done, the record acted on and when. The
+//! log is read-only here; entries are only written by the actions themselves.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::extractors::StaffUser;
+use crate::serializers::{error_response, success};
+use crate::AppState;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Response,
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Utc};
+use rustodon_admin::{ActionLogFilter, AdminAction, AdminActionType};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::error;
+
+/// Query parameters of the action log
+#[derive(Debug, Default, Deserialize)]
+pub struct ActionLogsQuery {
+    pub account_id: Option<i64>,
+    pub action_type: Option<String>,
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<i64>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub max_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+impl ActionLogsQuery {
+    /// Turns the query into a log filter
+    fn filter(self) -> Result<ActionLogFilter, String> {
+        let action_type = match self.action_type.as_deref() {
+            Some(value) => Some(
+                AdminActionType::parse(value)
+                    .ok_or_else(|| format!("Invalid action type: {}", value))?,
+            ),
+            None => None,
+        };
+
+        Ok(ActionLogFilter {
+            account_id: self.account_id,
+            action_type,
+            action: self.action,
+            target_type: self.target_type,
+            target_id: self.target_id,
+            since: self.since,
+            until: self.until,
+            max_id: self.max_id,
+            limit: self.limit,
+        })
+    }
+}
+
+/// Routes of the action log API
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new().route("/api/v1/admin/action_logs", get(list_action_logs_handler))
+}
+
+/// Renders a log entry
+fn action_log_json(action: &AdminAction) -> Value {
+    json!({
+        "id": action.id.to_string(),
+        "account_id": action.account_id.map(|id| id.to_string()),
+        "action_type": action.action_type.as_str(),
+        "action": action.action,
+        "target_type": action.target_type,
+        "target_id": action.target_id.map(|id| id.to_string()),
+        "target": action.target,
+        "changes": action.changes,
+        "created_at": action.created_at.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+    })
+}
+
+/// List action logs handler
+async fn list_action_logs_handler(
+    State(state): State<AppState>,
+    StaffUser(_moderator): StaffUser,
+    Query(query): Query<ActionLogsQuery>,
+) -> Response {
+    let filter = match query.filter() {
+        Ok(filter) => filter,
+        Err(message) => return error_response(StatusCode::UNPROCESSABLE_ENTITY, message),
+    };
+
+    match AdminAction::search(&state.pool, &filter).await {
+        Ok(actions) => success(actions.iter().map(action_log_json).collect()),
+        Err(e) => {
+            error!("Failed to browse action log: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+
+    #[test]
+    fn test_query_filter() {
+        let filter = ActionLogsQuery {
+            action_type: Some("delete".to_string()),
+            target_type: Some("Account".to_string()),
+            ..Default::default()
+        }
+        .filter()
+        .unwrap();
+        assert_eq!(filter.action_type, Some(AdminActionType::Delete));
+        assert_eq!(filter.target_type.as_deref(), Some("Account"));
+
+        assert!(ActionLogsQuery {
+            action_type: Some("rename".to_string()),
+            ..Default::default()
+        }
+        .filter()
+        .is_err());
+    }
+}
@@ -0,0 +1,250 @@
+//! Admin dashboard analytics endpoints
+//!
+//! Serves the measures, dimensions and retention cohorts of the admin
+//! dashboard on `/api/v1/admin/measures`, `/api/v1/admin/dimensions` and
+//! `/api/v1/admin/retention`, computed over the requested days.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::extractors::StaffUser;
+use crate::serializers::{error_response, success};
+use crate::AppState;
+use axum::{extract::State, http::StatusCode, response::Response, routing::post, Json, Router};
+use chrono::NaiveDate;
+use rustodon_analytics::{
+    AnalyticsError, Cohort, DateRange, Dimension, DimensionKey, Frequency, Measure, MeasureKey,
+    DEFAULT_DIMENSION_LIMIT,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::error;
+
+/// Maximum number of items of a dimension
+const MAX_DIMENSION_LIMIT: usize = 100;
+
+/// Request body of the measures
+#[derive(Debug, Deserialize)]
+pub struct MeasuresRequest {
+    pub keys: Vec<String>,
+    pub start_at: NaiveDate,
+    pub end_at: NaiveDate,
+}
+
+/// Request body of the dimensions
+#[derive(Debug, Deserialize)]
+pub struct DimensionsRequest {
+    pub keys: Vec<String>,
+    pub start_at: NaiveDate,
+    pub end_at: NaiveDate,
+    pub limit: Option<usize>,
+}
+
+/// Request body of the retention
+#[derive(Debug, Deserialize)]
+pub struct RetentionRequest {
+    pub start_at: NaiveDate,
+    pub end_at: NaiveDate,
+    pub frequency: Option<String>,
+}
+
+/// Routes of the analytics API
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/admin/dimensions", post(dimensions_handler))
+        .route("/api/v1/admin/measures", post(measures_handler))
+        .route("/api/v1/admin/retention", post(retention_handler))
+}
+
+/// Maps an analytics error to a response
+fn analytics_error_response(e: AnalyticsError) -> Response {
+    match e {
+        AnalyticsError::Validation(message) => {
+            error_response(StatusCode::UNPROCESSABLE_ENTITY, message)
+        }
+        e => {
+            error!("Failed to compute analytics: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+/// Parses every key of a request, failing on the first unknown one
+fn parse_keys<K>(keys: &[String], parse: fn(&str) -> Option<K>) -> Result<Vec<K>, String> {
+    keys.iter()
+        .map(|key| parse(key).ok_or_else(|| format!("Unknown key: {}", key)))
+        .collect()
+}
+
+/// Renders a day as a timestamp at its start
+fn format_day(date: NaiveDate) -> String {
+    format!("{}T00:00:00.000Z", date.format("%Y-%m-%d"))
+}
+
+/// Renders a measure
+fn measure_json(measure: &Measure) -> Value {
+    json!({
+        "key": measure.key.as_str(),
+        "unit": measure.key.unit(),
+        "total": measure.total.to_string(),
+        "previous_total": measure.previous_total.to_string(),
+        "data": measure.data.iter().map(|value| json!({
+            "date": format_day(value.date),
+            "value": value.value.to_string(),
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// Renders a dimension
+fn dimension_json(dimension: &Dimension) -> Value {
+    json!({
+        "key": dimension.key.as_str(),
+        "data": dimension.data.iter().map(|item| json!({
+            "key": item.key,
+            "human_key": item.human_key,
+            "value": item.value,
+            "unit": item.unit,
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// Renders a retention cohort
+fn cohort_json(cohort: &Cohort) -> Value {
+    json!({
+        "period": format_day(cohort.period),
+        "frequency": cohort.frequency.as_str(),
+        "data": cohort.data.iter().map(|value| json!({
+            "date": format_day(value.date),
+            "rate": value.rate,
+            "value": value.value.to_string(),
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// Measures handler
+async fn measures_handler(
+    State(state): State<AppState>,
+    StaffUser(_moderator): StaffUser,
+    Json(request): Json<MeasuresRequest>,
+) -> Response {
+    let keys = match parse_keys(&request.keys, MeasureKey::parse) {
+        Ok(keys) => keys,
+        Err(message) => return error_response(StatusCode::UNPROCESSABLE_ENTITY, message),
+    };
+    let range = match DateRange::new(request.start_at, request.end_at) {
+        Ok(range) => range,
+        Err(e) => return analytics_error_response(e),
+    };
+
+    let mut measures = Vec::with_capacity(keys.len());
+    for key in keys {
+        match Measure::compute(&state.pool, key, range).await {
+            Ok(measure) => measures.push(measure_json(&measure)),
+            Err(e) => return analytics_error_response(e),
+        }
+    }
+    success(json!(measures))
+}
+
+/// Dimensions handler
+async fn dimensions_handler(
+    State(state): State<AppState>,
+    StaffUser(_moderator): StaffUser,
+    Json(request): Json<DimensionsRequest>,
+) -> Response {
+    let keys = match parse_keys(&request.keys, DimensionKey::parse) {
+        Ok(keys) => keys,
+        Err(message) => return error_response(StatusCode::UNPROCESSABLE_ENTITY, message),
+    };
+    let range = match DateRange::new(request.start_at, request.end_at) {
+        Ok(range) => range,
+        Err(e) => return analytics_error_response(e),
+    };
+    let limit = request
+        .limit
+        .unwrap_or(DEFAULT_DIMENSION_LIMIT)
+        .clamp(1, MAX_DIMENSION_LIMIT);
+
+    let mut dimensions = Vec::with_capacity(keys.len());
+    for key in keys {
+        match Dimension::compute(&state.pool, key, range, limit).await {
+            Ok(dimension) => dimensions.push(dimension_json(&dimension)),
+            Err(e) => return analytics_error_response(e),
+        }
+    }
+    success(json!(dimensions))
+}
+
+/// Retention handler
+async fn retention_handler(
+    State(state): State<AppState>,
+    StaffUser(_moderator): StaffUser,
+    Json(request): Json<RetentionRequest>,
+) -> Response {
+    let frequency = match request.frequency.as_deref() {
+        None => Frequency::Day,
+        Some(value) => match Frequency::parse(value) {
+            Some(frequency) => frequency,
+            None => {
+                return error_response(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    format!("Invalid frequency: {}", value),
+                )
+            }
+        },
+    };
+    let range = match DateRange::new(request.start_at, request.end_at) {
+        Ok(range) => range,
+        Err(e) => return analytics_error_response(e),
+    };
+
+    match Cohort::compute(&state.pool, range, frequency).await {
+        Ok(cohorts) => success(json!(cohorts.iter().map(cohort_json).collect::<Vec<_>>())),
+        Err(e) => analytics_error_response(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustodon_analytics::MeasureValue;
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        let keys = vec!["new_users".to_string(), "emails_sent".to_string()];
+        assert_eq!(
+            parse_keys(&keys, MeasureKey::parse).unwrap(),
+            vec![MeasureKey::NewUsers, MeasureKey::EmailsSent]
+        );
+
+        let keys = vec!["languages".to_string(), "tag_uses".to_string()];
+        assert_eq!(
+            parse_keys(&keys, DimensionKey::parse).unwrap_err(),
+            "Unknown key: tag_uses"
+        );
+    }
+
+    #[test]
+    fn test_measure_json() {
+        let date = NaiveDate::from_ymd_opt(2025, 7, 31).unwrap();
+        let measure = Measure {
+            key: MeasureKey::MediaStorage,
+            total: 2048,
+            previous_total: 0,
+            data: vec![MeasureValue { date, value: 2048 }],
+        };
+
+        let value = measure_json(&measure);
+        assert_eq!(value["key"], "media_storage");
+        assert_eq!(value["unit"], "bytes");
+        assert_eq!(value["total"], "2048");
+        assert_eq!(value["data"][0]["date"], "2025-07-31T00:00:00.000Z");
+        assert_eq!(value["data"][0]["value"], "2048");
+    }
+}
@@ -0,0 +1,242 @@
+//! Admin canonical email block endpoints
+//!
+//! Lets staff block email addresses from signing up again on
+//! `/api/v1/admin/canonical_email_blocks`, and test an address against the
+//! blocks. Addresses are only ever stored as hashes of their canonical form.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::admin_accounts::{begin_action, commit_action};
+use crate::extractors::StaffUser;
+use crate::serializers::{error_response, success};
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Response,
+    routing::{get, post},
+    Json, Router,
+};
+use rustodon_admin::{diff, AdminActionType, NewAdminAction};
+use rustodon_canonical_email_blocks::{email_hash, CanonicalEmailBlock, CanonicalEmailBlockError};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::error;
+
+/// Query parameters of the block list
+#[derive(Debug, Default, Deserialize)]
+pub struct CanonicalEmailBlocksQuery {
+    pub max_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// Block creation parameters, either an address or its hash
+#[derive(Debug, Default, Deserialize)]
+pub struct CanonicalEmailBlockParams {
+    pub email: Option<String>,
+    pub canonical_email_hash: Option<String>,
+}
+
+impl CanonicalEmailBlockParams {
+    /// Hash of the address to block
+    fn hash(&self) -> Result<String, CanonicalEmailBlockError> {
+        match (&self.email, &self.canonical_email_hash) {
+            (Some(email), _) => email_hash(email),
+            (None, Some(hash)) => Ok(hash.clone()),
+            (None, None) => Err(CanonicalEmailBlockError::InvalidEmail(
+                "email or canonical_email_hash is required".to_string(),
+            )),
+        }
+    }
+}
+
+/// Block test parameters
+#[derive(Debug, Deserialize)]
+pub struct TestEmailParams {
+    pub email: String,
+}
+
+/// Routes of the canonical email block API
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/api/v1/admin/canonical_email_blocks",
+            get(list_blocks_handler).post(create_block_handler),
+        )
+        .route(
+            "/api/v1/admin/canonical_email_blocks/test",
+            post(test_email_handler),
+        )
+        .route(
+            "/api/v1/admin/canonical_email_blocks/:id",
+            get(get_block_handler).delete(delete_block_handler),
+        )
+}
+
+/// Maps canonical email block errors to API responses
+fn canonical_email_blocks_error_response(e: CanonicalEmailBlockError) -> Response {
+    match e {
+        CanonicalEmailBlockError::NotFound(_) => {
+            error_response(StatusCode::NOT_FOUND, "Record not found")
+        }
+        CanonicalEmailBlockError::InvalidEmail(_)
+        | CanonicalEmailBlockError::InvalidEmailHash(_)
+        | CanonicalEmailBlockError::AlreadyBlocked => {
+            error_response(StatusCode::UNPROCESSABLE_ENTITY, e.to_string())
+        }
+        e => {
+            error!("Canonical email block operation failed: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+/// Renders a block
+fn canonical_email_block_json(block: &CanonicalEmailBlock) -> Value {
+    json!({
+        "id": block.id.to_string(),
+        "canonical_email_hash": block.canonical_email_hash,
+    })
+}
+
+/// List blocks handler
+async fn list_blocks_handler(
+    State(state): State<AppState>,
+    StaffUser(_moderator): StaffUser,
+    Query(query): Query<CanonicalEmailBlocksQuery>,
+) -> Response {
+    match CanonicalEmailBlock::list(&state.pool, query.max_id, query.limit).await {
+        Ok(blocks) => success(blocks.iter().map(canonical_email_block_json).collect()),
+        Err(e) => canonical_email_blocks_error_response(e),
+    }
+}
+
+/// Get block handler
+async fn get_block_handler(
+    State(state): State<AppState>,
+    StaffUser(_moderator): StaffUser,
+    Path(id): Path<i64>,
+) -> Response {
+    match CanonicalEmailBlock::get(&state.pool, id).await {
+        Ok(Some(block)) => success(canonical_email_block_json(&block)),
+        Ok(None) => error_response(StatusCode::NOT_FOUND, "Record not found"),
+        Err(e) => canonical_email_blocks_error_response(e),
+    }
+}
+
+/// Create block handler
+async fn create_block_handler(
+    State(state): State<AppState>,
+    StaffUser(moderator): StaffUser,
+    Json(params): Json<CanonicalEmailBlockParams>,
+) -> Response {
+    let hash = match params.hash() {
+        Ok(hash) => hash,
+        Err(e) => return canonical_email_blocks_error_response(e),
+    };
+
+    let mut tx = match begin_action(&state).await {
+        Ok(tx) => tx,
+        Err(response) => return response,
+    };
+    let block = match CanonicalEmailBlock::create(&mut tx, &hash, None).await {
+        Ok(block) => block,
+        Err(e) => return canonical_email_blocks_error_response(e),
+    };
+
+    if let Err(response) = commit_action(
+        tx,
+        NewAdminAction {
+            account_id: moderator.id,
+            action_type: AdminActionType::Create,
+            action: "create",
+            target_type: "CanonicalEmailBlock",
+            target_id: Some(block.id),
+            target: &block.canonical_email_hash,
+            changes: diff(&Value::Null, &canonical_email_block_json(&block)),
+        },
+    )
+    .await
+    {
+        return response;
+    }
+    success(canonical_email_block_json(&block))
+}
+
+/// Test email handler
+async fn test_email_handler(
+    State(state): State<AppState>,
+    StaffUser(_moderator): StaffUser,
+    Json(params): Json<TestEmailParams>,
+) -> Response {
+    match CanonicalEmailBlock::matching(&state.pool, &params.email).await {
+        Ok(blocks) => success(blocks.iter().map(canonical_email_block_json).collect()),
+        Err(e) => canonical_email_blocks_error_response(e),
+    }
+}
+
+/// Delete block handler
+async fn delete_block_handler(
+    State(state): State<AppState>,
+    StaffUser(moderator): StaffUser,
+    Path(id): Path<i64>,
+) -> Response {
+    let mut tx = match begin_action(&state).await {
+        Ok(tx) => tx,
+        Err(response) => return response,
+    };
+    let block = match CanonicalEmailBlock::delete(&mut tx, id).await {
+        Ok(block) => block,
+        Err(e) => return canonical_email_blocks_error_response(e),
+    };
+
+    if let Err(response) = commit_action(
+        tx,
+        NewAdminAction {
+            account_id: moderator.id,
+            action_type: AdminActionType::Delete,
+            action: "destroy",
+            target_type: "CanonicalEmailBlock",
+            target_id: Some(block.id),
+            target: &block.canonical_email_hash,
+            changes: diff(&canonical_email_block_json(&block), &Value::Null),
+        },
+    )
+    .await
+    {
+        return response;
+    }
+    success(json!({}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+
+    #[test]
+    fn test_params_hash() {
+        let by_email = CanonicalEmailBlockParams {
+            email: Some("J.Doe+x@gmail.com".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            by_email.hash().unwrap(),
+            email_hash("jdoe@gmail.com").unwrap()
+        );
+
+        let by_hash = CanonicalEmailBlockParams {
+            canonical_email_hash: Some("abc".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(by_hash.hash().unwrap(), "abc");
+
+        assert!(CanonicalEmailBlockParams::default().hash().is_err());
+    }
+}
@@ -0,0 +1,192 @@
+//! Admin email domain block endpoints
+//!
+//! Lets staff block email domains from signing up on
+//! `/api/v1/admin/email_domain_blocks`, optionally along with the mail
+//! servers the domain resolves to.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::admin_accounts::{begin_action, commit_action};
+use crate::extractors::StaffUser;
+use crate::serializers::{error_response, success};
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Response,
+    routing::get,
+    Json, Router,
+};
+use rustodon_admin::{diff, AdminActionType, NewAdminAction};
+use rustodon_email_domain_blocks::{EmailDomainBlock, EmailDomainBlockError};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::error;
+
+/// Query parameters of the block list
+#[derive(Debug, Default, Deserialize)]
+pub struct EmailDomainBlocksQuery {
+    pub max_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// Block creation parameters
+#[derive(Debug, Deserialize)]
+pub struct EmailDomainBlockParams {
+    pub domain: String,
+    pub reason: Option<String>,
+    /// Also block the mail servers the domain resolves to
+    #[serde(default)]
+    pub with_dns_records: bool,
+}
+
+/// Routes of the email domain block API
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/api/v1/admin/email_domain_blocks",
+            get(list_blocks_handler).post(create_block_handler),
+        )
+        .route(
+            "/api/v1/admin/email_domain_blocks/:id",
+            get(get_block_handler).delete(delete_block_handler),
+        )
+}
+
+/// Maps email domain block errors to API responses
+fn email_domain_blocks_error_response(e: EmailDomainBlockError) -> Response {
+    match e {
+        EmailDomainBlockError::NotFound(_) => {
+            error_response(StatusCode::NOT_FOUND, "Record not found")
+        }
+        EmailDomainBlockError::InvalidDomain(_) | EmailDomainBlockError::AlreadyBlocked(_) => {
+            error_response(StatusCode::UNPROCESSABLE_ENTITY, e.to_string())
+        }
+        e => {
+            error!("Email domain block operation failed: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+/// Renders a block
+fn email_domain_block_json(block: &EmailDomainBlock) -> Value {
+    json!({
+        "id": block.id.to_string(),
+        "domain": block.domain,
+        "parent_id": block.parent_id.map(|id| id.to_string()),
+        "reason": block.reason,
+        "created_at": block.created_at.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+    })
+}
+
+/// List blocks handler
+async fn list_blocks_handler(
+    State(state): State<AppState>,
+    StaffUser(_moderator): StaffUser,
+    Query(query): Query<EmailDomainBlocksQuery>,
+) -> Response {
+    match EmailDomainBlock::list(&state.pool, query.max_id, query.limit).await {
+        Ok(blocks) => success(blocks.iter().map(email_domain_block_json).collect()),
+        Err(e) => email_domain_blocks_error_response(e),
+    }
+}
+
+/// Get block handler
+async fn get_block_handler(
+    State(state): State<AppState>,
+    StaffUser(_moderator): StaffUser,
+    Path(id): Path<i64>,
+) -> Response {
+    match EmailDomainBlock::get(&state.pool, id).await {
+        Ok(Some(block)) => success(email_domain_block_json(&block)),
+        Ok(None) => error_response(StatusCode::NOT_FOUND, "Record not found"),
+        Err(e) => email_domain_blocks_error_response(e),
+    }
+}
+
+/// Create block handler
+async fn create_block_handler(
+    State(state): State<AppState>,
+    StaffUser(moderator): StaffUser,
+    Json(params): Json<EmailDomainBlockParams>,
+) -> Response {
+    let resolver = params
+        .with_dns_records
+        .then_some(state.mx_resolver.as_ref());
+    let mut tx = match begin_action(&state).await {
+        Ok(tx) => tx,
+        Err(response) => return response,
+    };
+    let block =
+        match EmailDomainBlock::create(&mut tx, &params.domain, params.reason.as_deref(), resolver)
+            .await
+        {
+            Ok(block) => block,
+            Err(e) => return email_domain_blocks_error_response(e),
+        };
+
+    if let Err(response) = commit_action(
+        tx,
+        NewAdminAction {
+            account_id: moderator.id,
+            action_type: AdminActionType::Create,
+            action: "create",
+            target_type: "EmailDomainBlock",
+            target_id: Some(block.id),
+            target: &block.domain,
+            changes: diff(&Value::Null, &email_domain_block_json(&block)),
+        },
+    )
+    .await
+    {
+        return response;
+    }
+    success(email_domain_block_json(&block))
+}
+
+/// Delete block handler
+async fn delete_block_handler(
+    State(state): State<AppState>,
+    StaffUser(moderator): StaffUser,
+    Path(id): Path<i64>,
+) -> Response {
+    let mut tx = match begin_action(&state).await {
+        Ok(tx) => tx,
+        Err(response) => return response,
+    };
+    let block = match EmailDomainBlock::delete(&mut tx, id).await {
+        Ok(block) => block,
+        Err(e) => return email_domain_blocks_error_response(e),
+    };
+
+    if let Err(response) = commit_action(
+        tx,
+        NewAdminAction {
+            account_id: moderator.id,
+            action_type: AdminActionType::Delete,
+            action: "destroy",
+            target_type: "EmailDomainBlock",
+            target_id: Some(block.id),
+            target: &block.domain,
+            changes: diff(&email_domain_block_json(&block), &Value::Null),
+        },
+    )
+    .await
+    {
+        return response;
+    }
+    success(json!({}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+}
@@ -0,0 +1,230 @@
+//! Admin trends endpoints
+//!
+//! Lets staff go through the trending tags, statuses and links on
+//! `/api/v1/admin/trends/:kind`, including those not approved yet, and
+//! approve or reject them. `pending_review=true` narrows the list to the
+//! review queue.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::admin_accounts::{begin_action, commit_action};
+use crate::extractors::StaffUser;
+use crate::serializers::{error_response, success};
+use crate::trends::{render_trends, trends_error_response};
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Response,
+    routing::{get, post},
+    Router,
+};
+use rustodon_admin::{diff, AdminActionType, NewAdminAction};
+use rustodon_trends::{Trend, TrendQuery, TrendType};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Query parameters of the trends list
+#[derive(Debug, Default, Deserialize)]
+pub struct AdminTrendsParams {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    pub language: Option<String>,
+    /// Only items waiting for review
+    #[serde(default)]
+    pub pending_review: bool,
+}
+
+/// Routes of the admin trends API
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/admin/trends/:kind", get(list_trends_handler))
+        .route(
+            "/api/v1/admin/trends/:kind/:id/approve",
+            post(approve_trend_handler),
+        )
+        .route(
+            "/api/v1/admin/trends/:kind/:id/reject",
+            post(reject_trend_handler),
+        )
+}
+
+/// Kind of record of the items of a type, for the action log
+fn target_type(trend_type: TrendType) -> &'static str {
+    match trend_type {
+        TrendType::Tag => "Tag",
+        TrendType::Status => "Status",
+        TrendType::Link => "PreviewCard",
+    }
+}
+
+/// Review state of a trend
+fn review_json(trend: &Trend) -> Value {
+    json!({
+        "trendable": trend.allowed,
+        "requires_review": trend.requires_review(),
+    })
+}
+
+/// Renders an item with its review state
+fn admin_trend_json(trend: &Trend, mut item: Value) -> Value {
+    if trend.trend_type == TrendType::Tag {
+        item["id"] = json!(trend.target_id.to_string());
+    }
+    item["trendable"] = json!(trend.allowed);
+    item["requires_review"] = json!(trend.requires_review());
+    item["language"] = json!(trend.language);
+    item
+}
+
+/// List trends handler
+async fn list_trends_handler(
+    State(state): State<AppState>,
+    StaffUser(moderator): StaffUser,
+    Path(kind): Path<String>,
+    Query(params): Query<AdminTrendsParams>,
+) -> Response {
+    let Some(trend_type) = TrendType::parse_plural(&kind) else {
+        return error_response(StatusCode::NOT_FOUND, "Record not found");
+    };
+
+    let query = TrendQuery {
+        language: params.language,
+        allowed_only: false,
+        pending_review: params.pending_review,
+        offset: params.offset.unwrap_or(0),
+        limit: params.limit,
+    };
+    let trends = match Trend::list(&state.pool, trend_type, &query).await {
+        Ok(trends) => trends,
+        Err(e) => return trends_error_response(e),
+    };
+
+    match render_trends(&state, trend_type, &trends, Some(moderator.id)).await {
+        Ok(items) => success(
+            items
+                .into_iter()
+                .map(|(trend, item)| admin_trend_json(trend, item))
+                .collect(),
+        ),
+        Err(response) => response,
+    }
+}
+
+/// Approves or rejects a trending item and logs the review
+async fn review_trend(
+    state: &AppState,
+    moderator_id: i64,
+    kind: &str,
+    target_id: i64,
+    allowed: bool,
+) -> Response {
+    let Some(trend_type) = TrendType::parse_plural(kind) else {
+        return error_response(StatusCode::NOT_FOUND, "Record not found");
+    };
+
+    let before = match Trend::get(&state.pool, trend_type, target_id).await {
+        Ok(Some(trend)) => trend,
+        Ok(None) => return error_response(StatusCode::NOT_FOUND, "Record not found"),
+        Err(e) => return trends_error_response(e),
+    };
+    let mut tx = match begin_action(state).await {
+        Ok(tx) => tx,
+        Err(response) => return response,
+    };
+    let result = if allowed {
+        Trend::approve(&mut tx, trend_type, target_id).await
+    } else {
+        Trend::reject(&mut tx, trend_type, target_id).await
+    };
+    let after = match result {
+        Ok(trend) => trend,
+        Err(e) => return trends_error_response(e),
+    };
+
+    let trends = [after];
+    let item = match render_trends(state, trend_type, &trends, Some(moderator_id)).await {
+        Ok(mut items) if !items.is_empty() => {
+            let (trend, item) = items.remove(0);
+            admin_trend_json(trend, item)
+        }
+        // The item itself is gone, such as a deleted status
+        Ok(_) => admin_trend_json(&trends[0], json!({ "id": target_id.to_string() })),
+        Err(response) => return response,
+    };
+    let target = item["name"]
+        .as_str()
+        .or_else(|| item["url"].as_str())
+        .map(str::to_string)
+        .unwrap_or_else(|| target_id.to_string());
+
+    if let Err(response) = commit_action(
+        tx,
+        NewAdminAction {
+            account_id: moderator_id,
+            action_type: AdminActionType::Update,
+            action: if allowed { "approve" } else { "reject" },
+            target_type: target_type(trend_type),
+            target_id: Some(target_id),
+            target: &target,
+            changes: diff(&review_json(&before), &review_json(&trends[0])),
+        },
+    )
+    .await
+    {
+        return response;
+    }
+    success(item)
+}
+
+/// Approve trend handler
+async fn approve_trend_handler(
+    State(state): State<AppState>,
+    StaffUser(moderator): StaffUser,
+    Path((kind, id)): Path<(String, i64)>,
+) -> Response {
+    review_trend(&state, moderator.id, &kind, id, true).await
+}
+
+/// Reject trend handler
+async fn reject_trend_handler(
+    State(state): State<AppState>,
+    StaffUser(moderator): StaffUser,
+    Path((kind, id)): Path<(String, i64)>,
+) -> Response {
+    review_trend(&state, moderator.id, &kind, id, false).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+
+    #[test]
+    fn test_admin_trend_json() {
+        let trend = Trend {
+            id: 1,
+            trend_type: TrendType::Tag,
+            target_id: 42,
+            language: Some("en".to_string()),
+            score: 10.0,
+            allowed: false,
+            reviewed_at: None,
+            requested_review_at: Some(Utc::now()),
+            updated_at: Utc::now(),
+        };
+        let item = admin_trend_json(&trend, json!({ "name": "rust" }));
+        assert_eq!(item["id"], "42");
+        assert_eq!(item["name"], "rust");
+        assert_eq!(item["trendable"], false);
+        assert_eq!(item["requires_review"], true);
+        assert_eq!(item["language"], "en");
+    }
+}
@@ -0,0 +1,224 @@
+//! Appeal endpoints
+//!
+//! Owners appeal a strike once on `/api/v1/account_warnings/:id/appeal`.
+//! Moderators go through the pending appeals on `/api/v1/admin/appeals`
+//! and approve them, which reverses the strike, or reject them. Either
+//! decision is emailed to the owner and recorded in the admin action log.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::admin_accounts::{account_label, begin_action, commit_action};
+use crate::extractors::{CurrentUser, StaffUser};
+use crate::serializers::{error_response, success};
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Response,
+    routing::{get, post},
+    Json, Router,
+};
+use rustodon_account_warnings::AccountWarningError;
+use rustodon_admin::{diff, AdminActionType, NewAdminAction};
+use rustodon_appeals::{Appeal, AppealError};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::{Postgres, Transaction};
+use tracing::{error, info, warn};
+
+/// Default number of pending appeals listed
+const DEFAULT_LIMIT: i64 = 20;
+/// Maximum number of pending appeals listed
+const MAX_LIMIT: i64 = 100;
+
+/// Parameters of an appeal
+#[derive(Debug, Deserialize)]
+pub struct AppealParams {
+    /// Why the strike should be reversed
+    pub text: String,
+}
+
+/// Query parameters of the pending appeal list
+#[derive(Debug, Deserialize)]
+struct AppealsQuery {
+    limit: Option<i64>,
+}
+
+/// Routes of the appeals API
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/account_warnings/:id/appeal", post(appeal_handler))
+        .route("/api/v1/admin/appeals", get(pending_appeals_handler))
+        .route("/api/v1/admin/appeals/:id/approve", post(approve_handler))
+        .route("/api/v1/admin/appeals/:id/reject", post(reject_handler))
+}
+
+/// Maps appeal errors to API responses
+fn appeals_error_response(e: AppealError) -> Response {
+    match e {
+        AppealError::NotFound(_)
+        | AppealError::StrikeNotFound(_)
+        | AppealError::Warning(AccountWarningError::NotFound(_)) => {
+            error_response(StatusCode::NOT_FOUND, "Record not found")
+        }
+        AppealError::InvalidAppeal(message) => {
+            error_response(StatusCode::UNPROCESSABLE_ENTITY, message)
+        }
+        AppealError::AlreadyAppealed => {
+            error_response(StatusCode::UNPROCESSABLE_ENTITY, "Strike already appealed")
+        }
+        AppealError::WindowExpired => error_response(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Strike can no longer be appealed",
+        ),
+        AppealError::AlreadyProcessed => {
+            error_response(StatusCode::UNPROCESSABLE_ENTITY, "Appeal already processed")
+        }
+        e => {
+            error!("Appeal operation failed: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+/// State of an appeal as shown in the API
+fn appeal_state(appeal: &Appeal) -> &'static str {
+    match appeal.approved {
+        Some(true) => "approved",
+        Some(false) => "rejected",
+        None => "pending",
+    }
+}
+
+/// Renders an appeal
+pub(crate) fn appeal_json(appeal: &Appeal) -> Value {
+    json!({
+        "id": appeal.id.to_string(),
+        "account_id": appeal.account_id.to_string(),
+        "strike_id": appeal.strike_id.to_string(),
+        "text": appeal.text,
+        "state": appeal_state(appeal),
+        "created_at": appeal.created_at.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+    })
+}
+
+/// Records a decision and commits it, then emails the owner about it
+///
+/// Failing to email doesn't fail the request, as the decision stands.
+async fn decided(
+    state: &AppState,
+    tx: Transaction<'static, Postgres>,
+    moderator_id: i64,
+    appeal: &Appeal,
+    action: &str,
+) -> Result<(), Response> {
+    commit_action(
+        tx,
+        NewAdminAction {
+            account_id: moderator_id,
+            action_type: AdminActionType::Update,
+            action,
+            target_type: "Appeal",
+            target_id: Some(appeal.id),
+            target: &account_label(state, appeal.account_id).await,
+            changes: diff(
+                &json!({ "state": "pending" }),
+                &json!({ "state": appeal_state(appeal) }),
+            ),
+        },
+    )
+    .await?;
+
+    if let Err(e) = appeal.notify(&state.pool, state.mailer.as_ref()).await {
+        warn!(
+            "Failed to email account {} about appeal {}: {}",
+            appeal.account_id, appeal.id, e
+        );
+    }
+    Ok(())
+}
+
+/// Appeal handler
+async fn appeal_handler(
+    State(state): State<AppState>,
+    CurrentUser(user): CurrentUser,
+    Path(strike_id): Path<i64>,
+    Json(params): Json<AppealParams>,
+) -> Response {
+    match Appeal::create(&state.pool, user.id, strike_id, &params.text).await {
+        Ok(appeal) => {
+            info!("{} appealed strike {}", user.username, strike_id);
+            success(appeal_json(&appeal))
+        }
+        Err(e) => appeals_error_response(e),
+    }
+}
+
+/// Pending appeals handler
+async fn pending_appeals_handler(
+    State(state): State<AppState>,
+    StaffUser(_moderator): StaffUser,
+    Query(query): Query<AppealsQuery>,
+) -> Response {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    match Appeal::pending(&state.pool, limit).await {
+        Ok(appeals) => success(appeals.iter().map(appeal_json).collect()),
+        Err(e) => appeals_error_response(e),
+    }
+}
+
+/// Approve appeal handler
+async fn approve_handler(
+    State(state): State<AppState>,
+    StaffUser(moderator): StaffUser,
+    Path(id): Path<i64>,
+) -> Response {
+    let mut tx = match begin_action(&state).await {
+        Ok(tx) => tx,
+        Err(response) => return response,
+    };
+    match Appeal::approve(&mut tx, id, moderator.id).await {
+        Ok(appeal) => {
+            if let Err(response) = decided(&state, tx, moderator.id, &appeal, "approve").await {
+                return response;
+            }
+            info!("{} approved appeal {}", moderator.username, id);
+            success(appeal_json(&appeal))
+        }
+        Err(e) => appeals_error_response(e),
+    }
+}
+
+/// Reject appeal handler
+async fn reject_handler(
+    State(state): State<AppState>,
+    StaffUser(moderator): StaffUser,
+    Path(id): Path<i64>,
+) -> Response {
+    let mut tx = match begin_action(&state).await {
+        Ok(tx) => tx,
+        Err(response) => return response,
+    };
+    match Appeal::reject(&mut tx, id, moderator.id).await {
+        Ok(appeal) => {
+            if let Err(response) = decided(&state, tx, moderator.id, &appeal, "reject").await {
+                return response;
+            }
+            info!("{} rejected appeal {}", moderator.username, id);
+            success(appeal_json(&appeal))
+        }
+        Err(e) => appeals_error_response(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+}
@@ -0,0 +1,179 @@
+//! Account archive and export endpoints
+//!
+//! Lets accounts request an archive of their data through
+//! `/api/v1/backups`, follow its generation and download it once ready.
+//! The CSV exports are served from `/api/v1/exports/:file`, named like
+//! Mastodon's (`following_accounts.csv`, `blocked_domains.csv`, ...).
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::extractors::CurrentUser;
+use crate::serializers::{error_response, success};
+use crate::AppState;
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use rustodon_backups::{export_csv, Backup, BackupsError, ExportFile};
+use serde_json::{json, Value};
+use tokio_util::io::ReaderStream;
+use tracing::{error, info};
+
+/// Routes of the archive and export API
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/api/v1/backups",
+            get(list_backups_handler).post(create_backup_handler),
+        )
+        .route("/api/v1/backups/:id", get(get_backup_handler))
+        .route("/api/v1/backups/:id/download", get(download_handler))
+        .route("/api/v1/exports/:file", get(export_handler))
+}
+
+/// Maps backup errors to API responses
+fn backups_error_response(e: BackupsError) -> Response {
+    match e {
+        BackupsError::NotFound(_) => error_response(StatusCode::NOT_FOUND, "Record not found"),
+        BackupsError::RateLimited(_) => {
+            error_response(StatusCode::TOO_MANY_REQUESTS, e.to_string())
+        }
+        BackupsError::NotReady(_) => error_response(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "The archive is not ready yet",
+        ),
+        e => {
+            error!("Backup operation failed: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+/// Renders an archive
+fn backup_json(backup: &Backup) -> Value {
+    json!({
+        "id": backup.id.to_string(),
+        "state": backup.state.as_str(),
+        "file_size": backup.file_size,
+        "url": backup
+            .file_name
+            .as_ref()
+            .map(|_| format!("/api/v1/backups/{}/download", backup.id)),
+        "created_at": backup.created_at.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+        "finished_at": backup
+            .finished_at
+            .map(|finished_at| finished_at.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()),
+    })
+}
+
+/// Create backup handler
+async fn create_backup_handler(
+    State(state): State<AppState>,
+    CurrentUser(user): CurrentUser,
+) -> Response {
+    match Backup::request(&state.pool, user.id).await {
+        Ok(backup) => {
+            info!("{} requested archive {}", user.username, backup.id);
+            success(backup_json(&backup))
+        }
+        Err(e) => backups_error_response(e),
+    }
+}
+
+/// List backups handler
+async fn list_backups_handler(
+    State(state): State<AppState>,
+    CurrentUser(user): CurrentUser,
+) -> Response {
+    match Backup::get_by_account(&state.pool, user.id).await {
+        Ok(backups) => success(backups.iter().map(backup_json).collect()),
+        Err(e) => backups_error_response(e),
+    }
+}
+
+/// Get backup handler
+async fn get_backup_handler(
+    State(state): State<AppState>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+) -> Response {
+    match Backup::get(&state.pool, user.id, id).await {
+        Ok(backup) => success(backup_json(&backup)),
+        Err(e) => backups_error_response(e),
+    }
+}
+
+/// Download backup handler
+async fn download_handler(
+    State(state): State<AppState>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+) -> Response {
+    let backup = match Backup::get(&state.pool, user.id, id).await {
+        Ok(backup) => backup,
+        Err(e) => return backups_error_response(e),
+    };
+    let Some(path) = backup.path(&state.storage) else {
+        return backups_error_response(BackupsError::NotReady(id));
+    };
+
+    let file = match tokio::fs::File::open(&path).await {
+        Ok(file) => file,
+        Err(e) => return backups_error_response(e.into()),
+    };
+    let disposition = format!(
+        "attachment; filename=\"archive-{}-{}.zip\"",
+        user.username,
+        backup.created_at.format("%Y%m%d")
+    );
+    (
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        Body::from_stream(ReaderStream::new(file)),
+    )
+        .into_response()
+}
+
+/// CSV export handler
+async fn export_handler(
+    State(state): State<AppState>,
+    CurrentUser(user): CurrentUser,
+    Path(file): Path<String>,
+) -> Response {
+    let Some(file) = ExportFile::from_file_name(&file) else {
+        return error_response(StatusCode::NOT_FOUND, "Record not found");
+    };
+
+    match export_csv(&state.pool, user.id, file, &state.config.local_domain).await {
+        Ok(data) => (
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}\"", file.file_name()),
+                ),
+            ],
+            data,
+        )
+            .into_response(),
+        Err(e) => backups_error_response(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+}
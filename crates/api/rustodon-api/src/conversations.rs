@@ -0,0 +1,293 @@
+//! Direct message conversations API
+//!
+//! Lists the direct message threads of the current account under
+//! `/api/v1/conversations`, marks them read and removes them, and pushes
+//! `conversation` events to the participants when a new direct message
+//! arrives.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::extractors::CurrentUser;
+use crate::serializers::{account_json, error_response, success};
+use crate::timelines::render_statuses;
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Response,
+    routing::{delete, get, post},
+    Router,
+};
+use rustodon_account_conversations::{AccountConversation, AccountConversationsError};
+use rustodon_db::User;
+use rustodon_statuses::Status;
+use rustodon_streaming::{StreamType, StreamingMessage};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use tracing::{debug, error, warn};
+
+/// Routes of the conversations API
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/conversations", get(list_conversations_handler))
+        .route(
+            "/api/v1/conversations/:id",
+            delete(remove_conversation_handler),
+        )
+        .route(
+            "/api/v1/conversations/:id/read",
+            post(read_conversation_handler),
+        )
+}
+
+/// Maps conversation errors to API responses
+fn conversations_error_response(e: AccountConversationsError) -> Response {
+    match e {
+        AccountConversationsError::NotFound(_) => {
+            error_response(StatusCode::NOT_FOUND, "Record not found")
+        }
+        e => {
+            error!("Conversation operation failed: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+fn internal_error(e: impl std::fmt::Display) -> Response {
+    error!("Failed to load conversation data: {}", e);
+    error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+}
+
+/// Query parameters of the conversation list
+#[derive(Debug, Deserialize)]
+struct ConversationsQuery {
+    limit: Option<i64>,
+    max_id: Option<i64>,
+    since_id: Option<i64>,
+    min_id: Option<i64>,
+}
+
+/// Accounts shown for a thread: the other participants, or the account
+/// itself for notes to self
+fn shown_account_ids(conversation: &AccountConversation) -> Vec<i64> {
+    if conversation.participant_account_ids.is_empty() {
+        vec![conversation.account_id]
+    } else {
+        conversation.participant_account_ids.clone()
+    }
+}
+
+/// Renders a conversation entity
+///
+/// # Arguments
+///
+/// * `conversation` - The thread
+/// * `accounts` - Rendered accounts, keyed by ID
+/// * `statuses` - Rendered last statuses, keyed by ID
+fn conversation_json(
+    conversation: &AccountConversation,
+    accounts: &HashMap<i64, Value>,
+    statuses: &HashMap<i64, Value>,
+) -> Value {
+    json!({
+        "id": conversation.id.to_string(),
+        "unread": conversation.unread,
+        "accounts": shown_account_ids(conversation)
+            .iter()
+            .filter_map(|id| accounts.get(id).cloned())
+            .collect::<Vec<_>>(),
+        "last_status": conversation
+            .last_status_id
+            .and_then(|id| statuses.get(&id).cloned())
+    })
+}
+
+/// Renders threads with their participants and last statuses, as seen by
+/// the given account
+async fn render_conversations(
+    state: &AppState,
+    viewer_id: i64,
+    conversations: &[AccountConversation],
+) -> Result<Vec<Value>, Response> {
+    let status_ids: Vec<i64> = conversations
+        .iter()
+        .filter_map(|c| c.last_status_id)
+        .collect();
+    let statuses = Status::get_by_ids(&state.pool, &status_ids)
+        .await
+        .map_err(internal_error)?;
+    let statuses: HashMap<i64, Value> = render_statuses(state, &statuses, Some(viewer_id))
+        .await?
+        .into_iter()
+        .filter_map(|status| Some((status["id"].as_str()?.parse().ok()?, status)))
+        .collect();
+
+    let account_ids: HashSet<i64> = conversations.iter().flat_map(shown_account_ids).collect();
+    let mut accounts = HashMap::with_capacity(account_ids.len());
+    for id in account_ids {
+        match User::get_by_id(&state.pool, id).await {
+            Ok(Some(user)) => {
+                accounts.insert(id, account_json(&user, &state.config.local_domain));
+            }
+            Ok(None) => warn!("Conversation references missing account {}", id),
+            Err(e) => return Err(internal_error(e)),
+        }
+    }
+
+    Ok(conversations
+        .iter()
+        .map(|conversation| conversation_json(conversation, &accounts, &statuses))
+        .collect())
+}
+
+/// List conversations handler
+async fn list_conversations_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Query(query): Query<ConversationsQuery>,
+) -> Response {
+    debug!("Listing conversations of account {}", current.id);
+
+    let conversations = match AccountConversation::get_by_account(
+        &state.pool,
+        current.id,
+        query.limit,
+        query.max_id,
+        query.since_id,
+        query.min_id,
+    )
+    .await
+    {
+        Ok(conversations) => conversations,
+        Err(e) => return conversations_error_response(e),
+    };
+
+    match render_conversations(&state, current.id, &conversations).await {
+        Ok(rendered) => success(rendered.into()),
+        Err(response) => response,
+    }
+}
+
+/// Remove conversation handler
+async fn remove_conversation_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(id): Path<i64>,
+) -> Response {
+    debug!("Removing conversation {} of account {}", id, current.id);
+
+    match AccountConversation::remove(&state.pool, current.id, id).await {
+        Ok(()) => success(json!({})),
+        Err(e) => conversations_error_response(e),
+    }
+}
+
+/// Mark conversation as read handler
+async fn read_conversation_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(id): Path<i64>,
+) -> Response {
+    debug!("Marking conversation {} of account {} read", id, current.id);
+
+    let conversation = match AccountConversation::mark_read(&state.pool, current.id, id).await {
+        Ok(conversation) => conversation,
+        Err(e) => return conversations_error_response(e),
+    };
+
+    match render_conversations(&state, current.id, &[conversation]).await {
+        Ok(mut rendered) => success(rendered.remove(0)),
+        Err(response) => response,
+    }
+}
+
+/// Pushes the threads a new direct message updated to the direct stream of
+/// their participants
+///
+/// # Arguments
+///
+/// * `state` - Application state
+/// * `status_id` - ID of the direct message
+pub(crate) async fn stream_conversations(state: &AppState, status_id: i64) {
+    let conversations = match AccountConversation::get_by_last_status(&state.pool, status_id).await
+    {
+        Ok(conversations) => conversations,
+        Err(e) => {
+            warn!(
+                "Failed to load conversations of status {}: {}",
+                status_id, e
+            );
+            return;
+        }
+    };
+
+    for conversation in conversations {
+        let account_id = conversation.account_id;
+        let Ok(mut rendered) = render_conversations(state, account_id, &[conversation]).await
+        else {
+            warn!("Failed to render conversation for account {}", account_id);
+            continue;
+        };
+        if let Err(e) = state
+            .streaming
+            .broadcast_to_user(
+                account_id,
+                StreamType::Direct,
+                StreamingMessage::Conversation(rendered.remove(0)),
+            )
+            .await
+        {
+            warn!(
+                "Failed to stream conversation to account {}: {}",
+                account_id, e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn conversation(participant_account_ids: Vec<i64>) -> AccountConversation {
+        AccountConversation {
+            id: 9,
+            account_id: 1,
+            conversation_id: 4,
+            participant_account_ids,
+            status_ids: vec![20, 21],
+            last_status_id: Some(21),
+            unread: true,
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_conversation_json() {
+        let accounts = HashMap::from([
+            (1, json!({ "id": "1" })),
+            (2, json!({ "id": "2" })),
+            (3, json!({ "id": "3" })),
+        ]);
+        let statuses = HashMap::from([(21, json!({ "id": "21" }))]);
+
+        let json = conversation_json(&conversation(vec![2, 3]), &accounts, &statuses);
+        assert_eq!(json["id"], "9");
+        assert_eq!(json["unread"], true);
+        assert_eq!(json["accounts"], json!([{ "id": "2" }, { "id": "3" }]));
+        assert_eq!(json["last_status"]["id"], "21");
+
+        let json = conversation_json(&conversation(vec![]), &accounts, &HashMap::new());
+        assert_eq!(json["accounts"], json!([{ "id": "1" }]));
+        assert_eq!(json["last_status"], Value::Null);
+    }
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+}
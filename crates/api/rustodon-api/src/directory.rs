@@ -0,0 +1,211 @@
+//! Profile directory
+//!
+//! Serves `/api/v1/directory`, listing the accounts that opted into
+//! discovery, and lets staff hide accounts from it through
+//! `/api/v1/admin/accounts/:id/hide_from_directory` and
+//! `/unhide_from_directory`, which is recorded in the admin action log.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::admin_accounts::{account_label, begin_action, commit_action};
+use crate::extractors::StaffUser;
+use crate::serializers::{account_json, error_response, success};
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Response,
+    routing::{get, post},
+    Router,
+};
+use rustodon_accounts::{AccountsError, Directory, DirectoryOrder, DirectoryQuery};
+use rustodon_admin::{diff, AdminActionType, NewAdminAction};
+use rustodon_db::User;
+use serde_json::json;
+use tracing::{debug, error, info};
+
+/// Routes of the directory API
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/directory", get(directory_handler))
+        .route(
+            "/api/v1/admin/accounts/:id/hide_from_directory",
+            post(hide_handler),
+        )
+        .route(
+            "/api/v1/admin/accounts/:id/unhide_from_directory",
+            post(unhide_handler),
+        )
+}
+
+/// Maps accounts errors to API responses
+fn accounts_error_response(e: AccountsError) -> Response {
+    match e {
+        AccountsError::NotFound(_) => error_response(StatusCode::NOT_FOUND, "Record not found"),
+        e => {
+            error!("Directory operation failed: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+/// Parses the query parameters of the directory
+fn parse_directory_query(pairs: Vec<(String, String)>) -> Result<DirectoryQuery, String> {
+    let number = |key: &str, value: &str| {
+        value
+            .parse::<i64>()
+            .map_err(|_| format!("Invalid {}: {}", key, value))
+    };
+
+    let mut query = DirectoryQuery::default();
+    for (key, value) in pairs {
+        match key.as_str() {
+            "limit" => query.limit = Some(number(&key, &value)?),
+            "offset" => query.offset = Some(number(&key, &value)?),
+            "order" => {
+                query.order = DirectoryOrder::parse(&value)
+                    .ok_or_else(|| format!("Invalid order: {}", value))?
+            }
+            "local" => {
+                query.local = match value.as_str() {
+                    "true" | "1" => true,
+                    "false" | "0" => false,
+                    _ => return Err(format!("Invalid local: {}", value)),
+                }
+            }
+            "language" if !value.is_empty() => query.language = Some(value),
+            _ => {}
+        }
+    }
+    Ok(query)
+}
+
+/// Profile directory handler
+async fn directory_handler(
+    State(state): State<AppState>,
+    Query(pairs): Query<Vec<(String, String)>>,
+) -> Response {
+    let query = match parse_directory_query(pairs) {
+        Ok(query) => query,
+        Err(message) => return error_response(StatusCode::UNPROCESSABLE_ENTITY, message),
+    };
+    debug!("Listing profile directory: {:?}", query);
+
+    match Directory::accounts(&state.pool, &query).await {
+        Ok(accounts) => success(
+            accounts
+                .iter()
+                .map(|account| account_json(account, &state.config.local_domain))
+                .collect(),
+        ),
+        Err(e) => accounts_error_response(e),
+    }
+}
+
+/// Hides an account from the directory, or shows it again, and logs it
+async fn set_hidden(state: &AppState, staff: &User, account_id: i64, hidden: bool) -> Response {
+    let mut tx = match begin_action(state).await {
+        Ok(tx) => tx,
+        Err(response) => return response,
+    };
+    if let Err(e) = Directory::set_hidden(&mut tx, account_id, hidden).await {
+        return accounts_error_response(e);
+    }
+
+    if let Err(response) = commit_action(
+        tx,
+        NewAdminAction {
+            account_id: staff.id,
+            action_type: AdminActionType::Update,
+            action: if hidden {
+                "hide_from_directory"
+            } else {
+                "unhide_from_directory"
+            },
+            target_type: "Account",
+            target_id: Some(account_id),
+            target: &account_label(state, account_id).await,
+            changes: diff(
+                &json!({ "hidden_from_directory": !hidden }),
+                &json!({ "hidden_from_directory": hidden }),
+            ),
+        },
+    )
+    .await
+    {
+        return response;
+    }
+    if hidden {
+        info!(
+            "{} hid account {} from the directory",
+            staff.username, account_id
+        );
+    } else {
+        info!(
+            "{} showed account {} in the directory again",
+            staff.username, account_id
+        );
+    }
+    success(json!({}))
+}
+
+/// Hide from directory handler
+async fn hide_handler(
+    State(state): State<AppState>,
+    StaffUser(staff): StaffUser,
+    Path(account_id): Path<i64>,
+) -> Response {
+    set_hidden(&state, &staff, account_id, true).await
+}
+
+/// Unhide from directory handler
+async fn unhide_handler(
+    State(state): State<AppState>,
+    StaffUser(staff): StaffUser,
+    Path(account_id): Path<i64>,
+) -> Response {
+    set_hidden(&state, &staff, account_id, false).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(query: &[(&str, &str)]) -> Vec<(String, String)> {
+        query
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_directory_query() {
+        let query = parse_directory_query(pairs(&[
+            ("order", "new"),
+            ("local", "true"),
+            ("offset", "40"),
+            ("limit", "20"),
+            ("language", "de"),
+        ]))
+        .unwrap();
+        assert_eq!(query.order, DirectoryOrder::New);
+        assert!(query.local);
+        assert_eq!(query.offset, Some(40));
+        assert_eq!(query.limit, Some(20));
+        assert_eq!(query.language.as_deref(), Some("de"));
+
+        assert_eq!(
+            parse_directory_query(Vec::new()).unwrap(),
+            DirectoryQuery::default()
+        );
+        assert!(parse_directory_query(pairs(&[("order", "popular")])).is_err());
+        assert!(parse_directory_query(pairs(&[("local", "yes")])).is_err());
+    }
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+}
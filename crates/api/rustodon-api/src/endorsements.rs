@@ -0,0 +1,256 @@
+//! Endorsements (featured accounts)
+//!
+//! Lets accounts endorse the accounts they follow through
+//! `/api/v1/accounts/:id/pin` and `/unpin`, lists endorsements under
+//! `/api/v1/endorsements` and serves the featured collection of local
+//! accounts for federation.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::extractors::CurrentUser;
+use crate::relationships::{find_account, relationship_response};
+use crate::serializers::{account_json, error_response, success};
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use rustodon_account_pins::{AccountPin, AccountPinsError};
+use rustodon_activitypub::{actor_tombstone, featured_collection, local_actor_uri};
+use rustodon_db::User;
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{debug, error, warn};
+
+/// Routes of the endorsements API
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/accounts/:id/pin", post(pin_handler))
+        .route("/api/v1/accounts/:id/unpin", post(unpin_handler))
+        .route("/api/v1/endorsements", get(endorsements_handler))
+        .route(
+            "/api/v1/accounts/:id/endorsements",
+            get(account_endorsements_handler),
+        )
+        .route(
+            "/users/:username/collections/featured",
+            get(featured_collection_handler),
+        )
+}
+
+/// Maps endorsement errors to API responses
+fn endorsements_error_response(e: AccountPinsError) -> Response {
+    match e {
+        AccountPinsError::Validation(message) => {
+            error_response(StatusCode::UNPROCESSABLE_ENTITY, message)
+        }
+        e => {
+            error!("Endorsement operation failed: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+/// Query parameters of the endorsement lists
+#[derive(Debug, Deserialize)]
+struct EndorsementsQuery {
+    limit: Option<i64>,
+    max_id: Option<i64>,
+    since_id: Option<i64>,
+}
+
+/// Actor URI of an account, local or remote
+fn actor_uri(account: &User, local_domain: &str) -> Option<String> {
+    if account.is_local() {
+        Some(local_actor_uri(local_domain, &account.username))
+    } else {
+        account.uri.clone()
+    }
+}
+
+/// Gets the accounts an account endorses
+async fn endorsed_accounts(
+    state: &AppState,
+    account_id: i64,
+    query: &EndorsementsQuery,
+) -> Result<Vec<User>, Response> {
+    let pins = AccountPin::get_by_account(
+        &state.pool,
+        account_id,
+        query.limit,
+        query.max_id,
+        query.since_id,
+    )
+    .await
+    .map_err(endorsements_error_response)?;
+
+    let mut accounts = Vec::with_capacity(pins.len());
+    for pin in pins {
+        match User::get_by_id(&state.pool, pin.target_account_id).await {
+            Ok(Some(account)) => accounts.push(account),
+            Ok(None) => warn!(
+                "Endorsement {} references missing account {}",
+                pin.id, pin.target_account_id
+            ),
+            Err(e) => {
+                error!("Failed to load endorsed account: {}", e);
+                return Err(error_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Internal server error",
+                ));
+            }
+        }
+    }
+    Ok(accounts)
+}
+
+/// Renders accounts
+fn accounts_json(accounts: &[User], local_domain: &str) -> Value {
+    accounts
+        .iter()
+        .map(|account| account_json(account, local_domain))
+        .collect()
+}
+
+/// Endorse account handler
+async fn pin_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(account_id): Path<i64>,
+) -> Response {
+    debug!("Account {} endorsing account {}", current.id, account_id);
+
+    if let Err(response) = find_account(&state, account_id).await {
+        return response;
+    }
+    if let Err(e) = AccountPin::pin(&state.pool, current.id, account_id).await {
+        return endorsements_error_response(e);
+    }
+
+    relationship_response(&state, current.id, account_id).await
+}
+
+/// Remove endorsement handler
+async fn unpin_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(account_id): Path<i64>,
+) -> Response {
+    debug!(
+        "Account {} removing endorsement of account {}",
+        current.id, account_id
+    );
+
+    if let Err(response) = find_account(&state, account_id).await {
+        return response;
+    }
+    if let Err(e) = AccountPin::unpin(&state.pool, current.id, account_id).await {
+        return endorsements_error_response(e);
+    }
+
+    relationship_response(&state, current.id, account_id).await
+}
+
+/// Own endorsements handler
+async fn endorsements_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Query(query): Query<EndorsementsQuery>,
+) -> Response {
+    debug!("Listing endorsements of account {}", current.id);
+
+    match endorsed_accounts(&state, current.id, &query).await {
+        Ok(accounts) => success(accounts_json(&accounts, &state.config.local_domain)),
+        Err(response) => response,
+    }
+}
+
+/// An account's endorsements handler
+async fn account_endorsements_handler(
+    State(state): State<AppState>,
+    Path(account_id): Path<i64>,
+    Query(query): Query<EndorsementsQuery>,
+) -> Response {
+    debug!("Listing endorsements of account {}", account_id);
+
+    if let Err(response) = find_account(&state, account_id).await {
+        return response;
+    }
+    match endorsed_accounts(&state, account_id, &query).await {
+        Ok(accounts) => success(accounts_json(&accounts, &state.config.local_domain)),
+        Err(response) => response,
+    }
+}
+
+/// Featured collection handler
+async fn featured_collection_handler(
+    State(state): State<AppState>,
+    Path(username): Path<String>,
+) -> Response {
+    debug!("Serving featured collection of {}", username);
+
+    let account = match User::get_by_username(&state.pool, &username).await {
+        Ok(Some(account)) if account.is_local() => account,
+        Ok(_) => return error_response(StatusCode::NOT_FOUND, "Record not found"),
+        Err(e) => {
+            error!("Failed to load account {}: {}", username, e);
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error");
+        }
+    };
+
+    match account.is_deleted(&state.pool).await {
+        Ok(false) => {}
+        Ok(true) => {
+            let actor = local_actor_uri(&state.config.local_domain, &account.username);
+            return (
+                StatusCode::GONE,
+                [(header::CONTENT_TYPE, "application/activity+json")],
+                Json(actor_tombstone(&actor)),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            error!("Failed to load account {}: {}", username, e);
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error");
+        }
+    }
+
+    let query = EndorsementsQuery {
+        limit: None,
+        max_id: None,
+        since_id: None,
+    };
+    let endorsed = match endorsed_accounts(&state, account.id, &query).await {
+        Ok(accounts) => accounts,
+        Err(response) => return response,
+    };
+    let local_domain = &state.config.local_domain;
+    let endorsed_uris: Vec<String> = endorsed
+        .iter()
+        .filter_map(|endorsed| actor_uri(endorsed, local_domain))
+        .collect();
+
+    (
+        [(header::CONTENT_TYPE, "application/activity+json")],
+        Json(featured_collection(
+            &local_actor_uri(local_domain, &account.username),
+            &endorsed_uris,
+        )),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+}
@@ -0,0 +1,107 @@
+//! Request extractors for the API
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::serializers::error_response;
+use crate::AppState;
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{request::Parts, StatusCode},
+    response::Response,
+};
+use rustodon_auth::{extract_token_from_headers, get_user_by_session};
+use rustodon_db::User;
+use rustodon_streaming::{StreamingError, TokenAuthenticator};
+use sqlx::PgPool;
+use tracing::{error, trace};
+
+/// The account authenticated by the request's bearer token
+///
+/// Rejects the request with `401 Unauthorized` when the token is missing,
+/// malformed or expired.
+#[derive(Debug, Clone)]
+pub struct CurrentUser(pub User);
+
+#[async_trait]
+impl FromRequestParts<AppState> for CurrentUser {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let token = extract_token_from_headers(&parts.headers).ok_or_else(|| {
+            error_response(StatusCode::UNAUTHORIZED, "The access token is invalid")
+        })?;
+
+        match get_user_by_session(&state.pool, &token).await {
+            Ok(Some(user)) => {
+                trace!("Authenticated request as {}", user.username);
+                Ok(CurrentUser(user))
+            }
+            Ok(None) => Err(error_response(
+                StatusCode::UNAUTHORIZED,
+                "The access token is invalid",
+            )),
+            Err(e) => {
+                error!("Failed to look up session: {}", e);
+                Err(error_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to authenticate request",
+                ))
+            }
+        }
+    }
+}
+
+/// An authenticated admin or moderator
+///
+/// Rejects the request like [`CurrentUser`] when unauthenticated, and with
+/// `403 Forbidden` when the account is not staff.
+#[derive(Debug, Clone)]
+pub struct StaffUser(pub User);
+
+#[async_trait]
+impl FromRequestParts<AppState> for StaffUser {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let CurrentUser(user) = CurrentUser::from_request_parts(parts, state).await?;
+
+        match user.is_staff(&state.pool).await {
+            Ok(true) => Ok(StaffUser(user)),
+            Ok(false) => Err(error_response(
+                StatusCode::FORBIDDEN,
+                "This action is not allowed",
+            )),
+            Err(e) => {
+                error!("Failed to look up staff role: {}", e);
+                Err(error_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to authenticate request",
+                ))
+            }
+        }
+    }
+}
+
+/// Authenticates streaming connections with the same sessions as the API
+pub(crate) struct SessionAuthenticator {
+    pub pool: PgPool,
+}
+
+#[async_trait]
+impl TokenAuthenticator for SessionAuthenticator {
+    async fn authenticate(&self, token: &str) -> Result<Option<i64>, StreamingError> {
+        get_user_by_session(&self.pool, token)
+            .await
+            .map(|user| user.map(|user| user.id))
+            .map_err(|e| StreamingError::Authentication(e.to_string()))
+    }
+}
@@ -0,0 +1,230 @@
+//! Featured tags API
+//!
+//! Lets accounts pin hashtags to their profile under
+//! `/api/v1/featured_tags`, and lists the tags an account features
+//! together with how often and how recently it used them.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::extractors::CurrentUser;
+use crate::serializers::{error_response, success};
+use crate::status_entities::tag_json;
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Response,
+    routing::{delete, get},
+    Json, Router,
+};
+use rustodon_db::User;
+use rustodon_tags::{FeaturedTag, TagError};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, error, info};
+
+/// Maximum number of suggested tags
+const SUGGESTIONS_LIMIT: i64 = 10;
+
+/// Routes of the featured tags API
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/api/v1/featured_tags",
+            get(list_featured_tags_handler).post(create_featured_tag_handler),
+        )
+        .route(
+            "/api/v1/featured_tags/suggestions",
+            get(suggestions_handler),
+        )
+        .route(
+            "/api/v1/featured_tags/:id",
+            delete(delete_featured_tag_handler),
+        )
+        .route(
+            "/api/v1/accounts/:id/featured_tags",
+            get(account_featured_tags_handler),
+        )
+}
+
+/// Maps featured tag errors to API responses
+fn featured_tags_error_response(e: TagError) -> Response {
+    match e {
+        TagError::TagNotFound(_) | TagError::FeaturedTagNotFound(_) => {
+            error_response(StatusCode::NOT_FOUND, "Record not found")
+        }
+        TagError::Validation(message) => error_response(StatusCode::UNPROCESSABLE_ENTITY, message),
+        e => {
+            error!("Featured tag operation failed: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+/// Request body for featuring a tag
+#[derive(Debug, Deserialize)]
+struct CreateFeaturedTagRequest {
+    name: String,
+}
+
+/// Renders a featured tag entity
+///
+/// # Arguments
+///
+/// * `featured` - The featured tag
+/// * `username` - Username of the featuring account
+/// * `local_domain` - Domain of this instance
+pub(crate) fn featured_tag_json(
+    featured: &FeaturedTag,
+    username: &str,
+    local_domain: &str,
+) -> Value {
+    json!({
+        "id": featured.id.to_string(),
+        "name": featured.name,
+        "url": format!("https://{}/@{}/tagged/{}", local_domain, username, featured.name),
+        "statuses_count": featured.statuses_count,
+        "last_status_at": featured
+            .last_status_at
+            .map(|time| time.format("%Y-%m-%d").to_string())
+    })
+}
+
+/// Renders the featured tags of an account
+fn featured_tags_json(featured: &[FeaturedTag], username: &str, local_domain: &str) -> Value {
+    featured
+        .iter()
+        .map(|featured| featured_tag_json(featured, username, local_domain))
+        .collect()
+}
+
+/// List own featured tags handler
+async fn list_featured_tags_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+) -> Response {
+    debug!("Listing featured tags of account {}", current.id);
+
+    match FeaturedTag::get_by_account(&state.pool, current.id).await {
+        Ok(featured) => success(featured_tags_json(
+            &featured,
+            &current.username,
+            &state.config.local_domain,
+        )),
+        Err(e) => featured_tags_error_response(e),
+    }
+}
+
+/// Feature tag handler
+async fn create_featured_tag_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Json(request): Json<CreateFeaturedTagRequest>,
+) -> Response {
+    debug!("Featuring tag {} for account {}", request.name, current.id);
+
+    match FeaturedTag::create(&state.pool, current.id, &request.name).await {
+        Ok(featured) => {
+            info!("Account {} featured tag {}", current.id, featured.name);
+            success(featured_tag_json(
+                &featured,
+                &current.username,
+                &state.config.local_domain,
+            ))
+        }
+        Err(e) => featured_tags_error_response(e),
+    }
+}
+
+/// Unfeature tag handler
+async fn delete_featured_tag_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(id): Path<i64>,
+) -> Response {
+    debug!("Unfeaturing tag {} for account {}", id, current.id);
+
+    match FeaturedTag::delete(&state.pool, current.id, id).await {
+        Ok(()) => success(json!({})),
+        Err(e) => featured_tags_error_response(e),
+    }
+}
+
+/// Suggested tags to feature handler
+async fn suggestions_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+) -> Response {
+    debug!("Suggesting tags to feature for account {}", current.id);
+
+    match FeaturedTag::suggestions(&state.pool, current.id, SUGGESTIONS_LIMIT).await {
+        Ok(tags) => success(
+            tags.iter()
+                .map(|tag| {
+                    let mut json = tag_json(tag, &state.config.local_domain);
+                    json["history"] = json!([]);
+                    json
+                })
+                .collect(),
+        ),
+        Err(e) => featured_tags_error_response(e),
+    }
+}
+
+/// List an account's featured tags handler
+async fn account_featured_tags_handler(
+    State(state): State<AppState>,
+    Path(account_id): Path<i64>,
+) -> Response {
+    debug!("Listing featured tags of account {}", account_id);
+
+    let account = match User::get_by_id(&state.pool, account_id).await {
+        Ok(Some(account)) => account,
+        Ok(None) => return error_response(StatusCode::NOT_FOUND, "Record not found"),
+        Err(e) => {
+            error!("Failed to load account {}: {}", account_id, e);
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error");
+        }
+    };
+
+    match FeaturedTag::get_by_account(&state.pool, account.id).await {
+        Ok(featured) => success(featured_tags_json(
+            &featured,
+            &account.username,
+            &state.config.local_domain,
+        )),
+        Err(e) => featured_tags_error_response(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_featured_tag_json() {
+        let featured = FeaturedTag {
+            id: 4,
+            account_id: 1,
+            tag_id: 2,
+            name: "rust".to_string(),
+            statuses_count: 12,
+            last_status_at: Some(Utc.with_ymd_and_hms(2025, 7, 19, 8, 30, 0).unwrap()),
+            created_at: Utc::now(),
+        };
+
+        let json = featured_tag_json(&featured, "alice", "rustodon.example");
+        assert_eq!(json["id"], "4");
+        assert_eq!(json["url"], "https://rustodon.example/@alice/tagged/rust");
+        assert_eq!(json["statuses_count"], 12);
+        assert_eq!(json["last_status_at"], "2025-07-19");
+    }
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+}
@@ -0,0 +1,831 @@
+//! Filter endpoints (v2)
+//!
+//! CRUD for filters, their keywords and their statuses under
+//! `/api/v2/filters`, plus helpers that run a user's filters over
+//! statuses and notifications before they are returned, and over the
+//! events streamed to the user's connections. Filters used for streaming
+//! are cached for a minute, or until the user changes them.
+//! Statuses matched by `warn` filters carry a `filtered` array; statuses
+//! matched by `hide` filters are dropped.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::extractors::CurrentUser;
+use crate::serializers::{error_response, success};
+use crate::AppState;
+use async_trait::async_trait;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Response,
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Duration, Utc};
+use rustodon_filters::{
+    CreateFilterRequest, Filter, FilterKeyword, FilterKeywordParams, FilterMatcher, FilterOutcome,
+    FilterResult, FilterStatus, FiltersError, UpdateFilterRequest, FILTER_ACTION_WARN,
+};
+use rustodon_streaming::{EventFilter, StreamType, StreamingMessage};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::{debug, error, info, warn};
+
+/// How long filters are cached for streaming, so that expired filters stop
+/// applying
+const STREAM_FILTERS_TTL: std::time::Duration = std::time::Duration::from_secs(60);
+/// Number of cached matchers above which stale ones are dropped
+const MAX_CACHED_MATCHERS: usize = 10_000;
+
+/// Keyword attributes nested in a filter create or update
+#[derive(Debug, Deserialize)]
+pub struct KeywordAttributes {
+    /// Existing keyword to update or destroy
+    pub id: Option<String>,
+    pub keyword: Option<String>,
+    pub whole_word: Option<bool>,
+    /// Remove the keyword identified by `id`
+    #[serde(rename = "_destroy", default)]
+    pub destroy: bool,
+}
+
+/// Filter creation parameters
+#[derive(Debug, Deserialize)]
+pub struct CreateFilterParams {
+    pub title: String,
+    pub context: Vec<String>,
+    pub filter_action: Option<String>,
+    /// Seconds until the filter expires
+    pub expires_in: Option<i64>,
+    #[serde(default)]
+    pub keywords_attributes: Vec<KeywordAttributes>,
+}
+
+/// Filter update parameters
+#[derive(Debug, Deserialize)]
+pub struct UpdateFilterParams {
+    pub title: Option<String>,
+    pub context: Option<Vec<String>>,
+    pub filter_action: Option<String>,
+    /// Seconds until the filter expires; the current expiry is kept when absent
+    pub expires_in: Option<i64>,
+    #[serde(default)]
+    pub keywords_attributes: Vec<KeywordAttributes>,
+}
+
+/// Keyword creation and update parameters
+#[derive(Debug, Deserialize)]
+pub struct KeywordParams {
+    pub keyword: Option<String>,
+    pub whole_word: Option<bool>,
+}
+
+/// Filter status creation parameters
+#[derive(Debug, Deserialize)]
+pub struct FilterStatusParams {
+    pub status_id: String,
+}
+
+/// Routes served by this module
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/api/v2/filters",
+            get(list_filters_handler).post(create_filter_handler),
+        )
+        .route(
+            "/api/v2/filters/:id",
+            get(get_filter_handler)
+                .put(update_filter_handler)
+                .delete(delete_filter_handler),
+        )
+        .route(
+            "/api/v2/filters/:id/keywords",
+            get(list_keywords_handler).post(create_keyword_handler),
+        )
+        .route(
+            "/api/v2/filters/keywords/:id",
+            get(get_keyword_handler)
+                .put(update_keyword_handler)
+                .delete(delete_keyword_handler),
+        )
+        .route(
+            "/api/v2/filters/:id/statuses",
+            get(list_filter_statuses_handler).post(create_filter_status_handler),
+        )
+        .route(
+            "/api/v2/filters/statuses/:id",
+            get(get_filter_status_handler).delete(delete_filter_status_handler),
+        )
+}
+
+/// Maps a filters error to an API error response
+fn filters_error_response(e: FiltersError) -> Response {
+    match e {
+        FiltersError::FilterNotFound(_)
+        | FiltersError::FilterKeywordNotFound(_)
+        | FiltersError::FilterStatusNotFound(_)
+        | FiltersError::UserNotFound(_) => {
+            error_response(StatusCode::NOT_FOUND, "Record not found")
+        }
+        FiltersError::Validation(_) => {
+            error_response(StatusCode::UNPROCESSABLE_ENTITY, e.to_string())
+        }
+        FiltersError::Database(_) | FiltersError::Internal(_) => {
+            error!("Filter operation failed: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+fn expires_at(expires_in: i64) -> DateTime<Utc> {
+    Utc::now() + Duration::seconds(expires_in)
+}
+
+fn keyword_json(keyword: &FilterKeyword) -> Value {
+    json!({
+        "id": keyword.id.to_string(),
+        "keyword": keyword.keyword,
+        "whole_word": keyword.whole_word
+    })
+}
+
+fn filter_status_json(filter_status: &FilterStatus) -> Value {
+    json!({
+        "id": filter_status.id.to_string(),
+        "status_id": filter_status.status_id.to_string()
+    })
+}
+
+/// Renders a filter in the v2 API format
+pub(crate) fn filter_json(filter: &Filter) -> Value {
+    json!({
+        "id": filter.id.to_string(),
+        "title": filter.title,
+        "context": filter.contexts(),
+        "expires_at": filter.expires_at.map(format_time),
+        "filter_action": filter.filter_action,
+        "keywords": filter.keywords.iter().map(keyword_json).collect::<Vec<_>>(),
+        "statuses": filter.statuses.iter().map(filter_status_json).collect::<Vec<_>>()
+    })
+}
+
+fn filter_result_json(result: &FilterResult) -> Value {
+    json!({
+        "filter": filter_json(&result.filter),
+        "keyword_matches": (!result.keyword_matches.is_empty()).then_some(&result.keyword_matches),
+        "status_matches": (!result.status_matches.is_empty()).then_some(&result.status_matches)
+    })
+}
+
+/// Loads an account's filters for one context
+///
+/// Returns `None` when no filter applies, so callers can skip matching.
+/// Lookup failures are logged and treated as "no filters" rather than
+/// failing the timeline.
+pub(crate) async fn load_matcher(
+    state: &AppState,
+    account_id: i64,
+    context: &str,
+) -> Option<FilterMatcher> {
+    match Filter::get_by_account(&state.pool, account_id).await {
+        Ok(filters) => Some(FilterMatcher::new(filters, context)).filter(|m| !m.is_empty()),
+        Err(e) => {
+            warn!("Failed to load filters for account {}: {}", account_id, e);
+            None
+        }
+    }
+}
+
+/// Applies filters to one rendered status
+///
+/// Sets the status' `filtered` array and returns `false` if a `hide`
+/// filter matched. Reblogs are matched on the reblogged status.
+pub(crate) fn filter_status(matcher: &FilterMatcher, status: &mut Value) -> bool {
+    let target = status
+        .get("reblog")
+        .filter(|r| r.is_object())
+        .unwrap_or(status);
+    let status_id = target["id"]
+        .as_str()
+        .and_then(|id| id.parse().ok())
+        .or_else(|| target["id"].as_i64())
+        .unwrap_or_default();
+    let text = format!(
+        "{} {}",
+        target["spoiler_text"].as_str().unwrap_or_default(),
+        target["content"].as_str().unwrap_or_default()
+    );
+
+    match matcher.apply(status_id, &text) {
+        FilterOutcome::Hide => false,
+        FilterOutcome::Show(results) => {
+            status["filtered"] = results.iter().map(filter_result_json).collect();
+            true
+        }
+    }
+}
+
+/// Applies filters to rendered statuses, dropping hidden ones
+pub(crate) fn filter_statuses(matcher: &FilterMatcher, statuses: Vec<Value>) -> Vec<Value> {
+    statuses
+        .into_iter()
+        .filter_map(|mut status| filter_status(matcher, &mut status).then_some(status))
+        .collect()
+}
+
+/// Applies filters to the statuses of rendered notifications
+///
+/// Notifications whose status is hidden are dropped.
+pub(crate) fn filter_notifications(
+    matcher: &FilterMatcher,
+    notifications: Vec<Value>,
+) -> Vec<Value> {
+    notifications
+        .into_iter()
+        .filter_map(|mut notification| match notification.get_mut("status") {
+            Some(status) if status.is_object() => {
+                filter_status(matcher, status).then_some(notification)
+            }
+            _ => Some(notification),
+        })
+        .collect()
+}
+
+/// Filter context of a streamed event, if it carries a status
+fn event_context(stream: &StreamType, message: &StreamingMessage) -> Option<&'static str> {
+    match (stream, message) {
+        (StreamType::User | StreamType::List(_), StreamingMessage::Update(_)) => Some("home"),
+        (
+            StreamType::Public | StreamType::Local | StreamType::Hashtag(_),
+            StreamingMessage::Update(_),
+        ) => Some("public"),
+        (_, StreamingMessage::Notification(_)) => Some("notifications"),
+        _ => None,
+    }
+}
+
+/// Applies filters to a streamed event, dropping it if its status is hidden
+fn filter_event(matcher: &FilterMatcher, message: StreamingMessage) -> Option<StreamingMessage> {
+    match message {
+        StreamingMessage::Update(mut status) => {
+            filter_status(matcher, &mut status).then_some(StreamingMessage::Update(status))
+        }
+        StreamingMessage::Notification(notification) => {
+            filter_notifications(matcher, vec![notification])
+                .pop()
+                .map(StreamingMessage::Notification)
+        }
+        message => Some(message),
+    }
+}
+
+/// Matcher of an account's filters in one context, `None` if none apply
+struct CachedMatcher {
+    loaded_at: Instant,
+    matcher: Option<Arc<FilterMatcher>>,
+}
+
+/// Runs the filters of streaming clients over the statuses they get, like
+/// the timelines do
+///
+/// Matchers are cached per account and context, so that an event going to
+/// many connections doesn't load filters for each of them.
+#[derive(Clone)]
+pub(crate) struct StreamFilters {
+    pool: PgPool,
+    matchers: Arc<Mutex<HashMap<(i64, &'static str), CachedMatcher>>>,
+}
+
+impl StreamFilters {
+    /// Creates stream filters loading filters from the database
+    pub(crate) fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            matchers: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Drops the cached filters of an account, after it changed them
+    pub(crate) fn invalidate(&self, account_id: i64) {
+        self.matchers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|(id, _), _| *id != account_id);
+    }
+
+    /// Gets the matcher of an account in a context, loading it if needed
+    async fn matcher(&self, account_id: i64, context: &'static str) -> Option<Arc<FilterMatcher>> {
+        if let Some(cached) = self
+            .matchers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&(account_id, context))
+            .filter(|cached| cached.loaded_at.elapsed() < STREAM_FILTERS_TTL)
+        {
+            return cached.matcher.clone();
+        }
+
+        // Failures aren't cached, so the next event tries again
+        let filters = match Filter::get_by_account(&self.pool, account_id).await {
+            Ok(filters) => filters,
+            Err(e) => {
+                warn!("Failed to load filters for account {}: {}", account_id, e);
+                return None;
+            }
+        };
+        let matcher = Some(FilterMatcher::new(filters, context))
+            .filter(|m| !m.is_empty())
+            .map(Arc::new);
+
+        let mut matchers = self.matchers.lock().unwrap_or_else(|e| e.into_inner());
+        if matchers.len() >= MAX_CACHED_MATCHERS {
+            matchers.retain(|_, cached| cached.loaded_at.elapsed() < STREAM_FILTERS_TTL);
+        }
+        matchers.insert(
+            (account_id, context),
+            CachedMatcher {
+                loaded_at: Instant::now(),
+                matcher: matcher.clone(),
+            },
+        );
+        matcher
+    }
+}
+
+#[async_trait]
+impl EventFilter for StreamFilters {
+    async fn filter(
+        &self,
+        user_id: i64,
+        stream: &StreamType,
+        message: StreamingMessage,
+    ) -> Option<StreamingMessage> {
+        let Some(context) = event_context(stream, &message) else {
+            return Some(message);
+        };
+        match self.matcher(user_id, context).await {
+            Some(matcher) => filter_event(&matcher, message),
+            None => Some(message),
+        }
+    }
+}
+
+/// Applies changes from `keywords_attributes` to an existing filter
+async fn apply_keyword_attributes(
+    state: &AppState,
+    filter_id: i64,
+    account_id: i64,
+    attributes: Vec<KeywordAttributes>,
+) -> Result<(), FiltersError> {
+    for attrs in attributes {
+        let id = match attrs.id.as_deref() {
+            Some(id) => Some(
+                id.parse::<i64>()
+                    .map_err(|_| FiltersError::Validation(format!("Invalid keyword id: {}", id)))?,
+            ),
+            None => None,
+        };
+
+        let result = match id {
+            Some(keyword_id) => {
+                // Make sure the keyword belongs to this filter, not just this account
+                let keyword = FilterKeyword::get_owned(&state.pool, keyword_id, account_id).await?;
+                if keyword.filter_id != filter_id {
+                    return Err(FiltersError::FilterKeywordNotFound(keyword_id));
+                }
+
+                if attrs.destroy {
+                    FilterKeyword::delete(&state.pool, keyword_id, account_id).await
+                } else {
+                    FilterKeyword::update(
+                        &state.pool,
+                        keyword_id,
+                        account_id,
+                        attrs.keyword,
+                        attrs.whole_word,
+                    )
+                    .await
+                    .map(|_| ())
+                }
+            }
+            None if attrs.destroy => continue,
+            None => FilterKeyword::create(
+                &state.pool,
+                filter_id,
+                account_id,
+                FilterKeywordParams {
+                    keyword: attrs.keyword.unwrap_or_default(),
+                    whole_word: attrs.whole_word.unwrap_or(false),
+                },
+            )
+            .await
+            .map(|_| ()),
+        };
+
+        result?;
+    }
+    Ok(())
+}
+
+/// List filters handler
+async fn list_filters_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+) -> Response {
+    debug!("Listing filters for account {}", current.id);
+
+    match Filter::get_by_account(&state.pool, current.id).await {
+        Ok(filters) => success(filters.iter().map(filter_json).collect()),
+        Err(e) => filters_error_response(e),
+    }
+}
+
+/// Get filter handler
+async fn get_filter_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(filter_id): Path<i64>,
+) -> Response {
+    match Filter::get_owned(&state.pool, filter_id, current.id).await {
+        Ok(filter) => success(filter_json(&filter)),
+        Err(e) => filters_error_response(e),
+    }
+}
+
+/// Create filter handler
+async fn create_filter_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Json(params): Json<CreateFilterParams>,
+) -> Response {
+    debug!("Creating filter for account {}", current.id);
+
+    let keywords = params
+        .keywords_attributes
+        .into_iter()
+        .filter(|attrs| !attrs.destroy)
+        .map(|attrs| FilterKeywordParams {
+            keyword: attrs.keyword.unwrap_or_default(),
+            whole_word: attrs.whole_word.unwrap_or(false),
+        })
+        .collect();
+
+    let request = CreateFilterRequest {
+        title: params.title,
+        context: params.context,
+        expires_at: params.expires_in.map(expires_at),
+        filter_action: params
+            .filter_action
+            .unwrap_or_else(|| FILTER_ACTION_WARN.to_string()),
+        keywords,
+    };
+
+    match Filter::create(&state.pool, current.id, request).await {
+        Ok(filter) => {
+            info!("Created filter {} for account {}", filter.id, current.id);
+            state.stream_filters.invalidate(current.id);
+            success(filter_json(&filter))
+        }
+        Err(e) => filters_error_response(e),
+    }
+}
+
+/// Update filter handler
+async fn update_filter_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(filter_id): Path<i64>,
+    Json(params): Json<UpdateFilterParams>,
+) -> Response {
+    debug!("Updating filter {} for account {}", filter_id, current.id);
+
+    let existing = match Filter::get_owned(&state.pool, filter_id, current.id).await {
+        Ok(filter) => filter,
+        Err(e) => return filters_error_response(e),
+    };
+
+    let request = UpdateFilterRequest {
+        title: params.title,
+        context: params.context,
+        expires_at: params.expires_in.map(expires_at).or(existing.expires_at),
+        filter_action: params.filter_action,
+        keywords: None,
+    };
+
+    if let Err(e) = Filter::update(&state.pool, filter_id, current.id, request).await {
+        return filters_error_response(e);
+    }
+
+    let applied =
+        apply_keyword_attributes(&state, filter_id, current.id, params.keywords_attributes).await;
+    state.stream_filters.invalidate(current.id);
+    if let Err(e) = applied {
+        return filters_error_response(e);
+    }
+
+    match Filter::get_by_id(&state.pool, filter_id).await {
+        Ok(filter) => success(filter_json(&filter)),
+        Err(e) => filters_error_response(e),
+    }
+}
+
+/// Delete filter handler
+async fn delete_filter_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(filter_id): Path<i64>,
+) -> Response {
+    match Filter::delete(&state.pool, filter_id, current.id).await {
+        Ok(()) => {
+            state.stream_filters.invalidate(current.id);
+            success(json!({}))
+        }
+        Err(e) => filters_error_response(e),
+    }
+}
+
+/// List filter keywords handler
+async fn list_keywords_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(filter_id): Path<i64>,
+) -> Response {
+    match Filter::get_owned(&state.pool, filter_id, current.id).await {
+        Ok(filter) => success(filter.keywords.iter().map(keyword_json).collect()),
+        Err(e) => filters_error_response(e),
+    }
+}
+
+/// Add filter keyword handler
+async fn create_keyword_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(filter_id): Path<i64>,
+    Json(params): Json<KeywordParams>,
+) -> Response {
+    let keyword_params = FilterKeywordParams {
+        keyword: params.keyword.unwrap_or_default(),
+        whole_word: params.whole_word.unwrap_or(false),
+    };
+
+    match FilterKeyword::create(&state.pool, filter_id, current.id, keyword_params).await {
+        Ok(keyword) => {
+            state.stream_filters.invalidate(current.id);
+            success(keyword_json(&keyword))
+        }
+        Err(e) => filters_error_response(e),
+    }
+}
+
+/// Get filter keyword handler
+async fn get_keyword_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(keyword_id): Path<i64>,
+) -> Response {
+    match FilterKeyword::get_owned(&state.pool, keyword_id, current.id).await {
+        Ok(keyword) => success(keyword_json(&keyword)),
+        Err(e) => filters_error_response(e),
+    }
+}
+
+/// Update filter keyword handler
+async fn update_keyword_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(keyword_id): Path<i64>,
+    Json(params): Json<KeywordParams>,
+) -> Response {
+    match FilterKeyword::update(
+        &state.pool,
+        keyword_id,
+        current.id,
+        params.keyword,
+        params.whole_word,
+    )
+    .await
+    {
+        Ok(keyword) => {
+            state.stream_filters.invalidate(current.id);
+            success(keyword_json(&keyword))
+        }
+        Err(e) => filters_error_response(e),
+    }
+}
+
+/// Delete filter keyword handler
+async fn delete_keyword_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(keyword_id): Path<i64>,
+) -> Response {
+    match FilterKeyword::delete(&state.pool, keyword_id, current.id).await {
+        Ok(()) => {
+            state.stream_filters.invalidate(current.id);
+            success(json!({}))
+        }
+        Err(e) => filters_error_response(e),
+    }
+}
+
+/// List filter statuses handler
+async fn list_filter_statuses_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(filter_id): Path<i64>,
+) -> Response {
+    match Filter::get_owned(&state.pool, filter_id, current.id).await {
+        Ok(filter) => success(filter.statuses.iter().map(filter_status_json).collect()),
+        Err(e) => filters_error_response(e),
+    }
+}
+
+/// Add filter status handler
+async fn create_filter_status_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(filter_id): Path<i64>,
+    Json(params): Json<FilterStatusParams>,
+) -> Response {
+    let Ok(status_id) = params.status_id.parse::<i64>() else {
+        return error_response(StatusCode::UNPROCESSABLE_ENTITY, "Invalid status_id");
+    };
+
+    match FilterStatus::create(&state.pool, filter_id, current.id, status_id).await {
+        Ok(filter_status) => {
+            state.stream_filters.invalidate(current.id);
+            success(filter_status_json(&filter_status))
+        }
+        Err(e) => filters_error_response(e),
+    }
+}
+
+/// Get filter status handler
+async fn get_filter_status_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(filter_status_id): Path<i64>,
+) -> Response {
+    match FilterStatus::get_owned(&state.pool, filter_status_id, current.id).await {
+        Ok(filter_status) => success(filter_status_json(&filter_status)),
+        Err(e) => filters_error_response(e),
+    }
+}
+
+/// Delete filter status handler
+async fn delete_filter_status_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(filter_status_id): Path<i64>,
+) -> Response {
+    match FilterStatus::delete(&state.pool, filter_status_id, current.id).await {
+        Ok(()) => {
+            state.stream_filters.invalidate(current.id);
+            success(json!({}))
+        }
+        Err(e) => filters_error_response(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustodon_filters::FilterKeyword;
+
+    fn filter(id: i64, action: &str, keyword: &str) -> Filter {
+        Filter {
+            id,
+            account_id: 1,
+            title: "Test".to_string(),
+            context: "public,notifications".to_string(),
+            expires_at: None,
+            filter_action: action.to_string(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            keywords: vec![FilterKeyword {
+                id: 1,
+                filter_id: id,
+                keyword: keyword.to_string(),
+                whole_word: true,
+                created_at: Utc::now(),
+            }],
+            statuses: vec![],
+        }
+    }
+
+    #[test]
+    fn test_filter_statuses() {
+        let matcher = FilterMatcher::new(
+            vec![filter(1, "warn", "rust"), filter(2, "hide", "spam")],
+            "public",
+        );
+        let statuses = vec![
+            json!({"id": "1", "content": "<p>I love Rust</p>", "spoiler_text": ""}),
+            json!({"id": "2", "content": "<p>buy spam now</p>", "spoiler_text": ""}),
+            json!({"id": "3", "content": "<p>hello</p>", "spoiler_text": ""}),
+        ];
+
+        let filtered = filter_statuses(&matcher, statuses);
+        assert_eq!(filtered.len(), 2);
+        assert_eq!(filtered[0]["filtered"][0]["filter"]["id"], "1");
+        assert_eq!(filtered[0]["filtered"][0]["keyword_matches"][0], "rust");
+        assert!(filtered[0]["filtered"][0]["status_matches"].is_null());
+        assert_eq!(filtered[1]["filtered"], json!([]));
+    }
+
+    #[test]
+    fn test_filter_notifications() {
+        let matcher = FilterMatcher::new(vec![filter(1, "hide", "spoiler")], "notifications");
+        let notifications = vec![
+            json!({"id": "1", "type": "follow"}),
+            json!({"id": "2", "type": "mention", "status": {"id": "9", "content": "a spoiler", "spoiler_text": ""}}),
+        ];
+
+        let filtered = filter_notifications(&matcher, notifications);
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0]["type"], "follow");
+    }
+
+    #[test]
+    fn test_filter_event() {
+        let matcher = FilterMatcher::new(vec![filter(1, "hide", "spam")], "public");
+        let update = |content: &str| {
+            StreamingMessage::Update(json!({"id": "1", "content": content, "spoiler_text": ""}))
+        };
+
+        assert!(filter_event(&matcher, update("buy spam now")).is_none());
+        assert!(matches!(
+            filter_event(&matcher, update("hello")),
+            Some(StreamingMessage::Update(status)) if status["filtered"] == json!([])
+        ));
+        assert!(filter_event(&matcher, StreamingMessage::Delete("1".to_string())).is_some());
+    }
+
+    #[test]
+    fn test_event_context() {
+        let update = StreamingMessage::Update(json!({}));
+        assert_eq!(event_context(&StreamType::User, &update), Some("home"));
+        assert_eq!(
+            event_context(&StreamType::Hashtag("rust".to_string()), &update),
+            Some("public")
+        );
+        assert_eq!(
+            event_context(
+                &StreamType::Notifications,
+                &StreamingMessage::Notification(json!({}))
+            ),
+            Some("notifications")
+        );
+        assert_eq!(
+            event_context(&StreamType::User, &StreamingMessage::Marker(json!({}))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+
+    #[test]
+    fn test_filter_json() {
+        let json = filter_json(&filter(5, "warn", "rust"));
+        assert_eq!(json["id"], "5");
+        assert_eq!(json["context"], json!(["public", "notifications"]));
+        assert_eq!(json["keywords"][0]["whole_word"], true);
+        assert!(json["expires_at"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_stream_filters_invalidate() {
+        let pool = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+        let stream_filters = StreamFilters::new(pool);
+        for key in [(1, "home"), (1, "public"), (2, "home")] {
+            stream_filters.matchers.lock().unwrap().insert(
+                key,
+                CachedMatcher {
+                    loaded_at: Instant::now(),
+                    matcher: None,
+                },
+            );
+        }
+
+        // Cached matchers are used without touching the database
+        assert!(stream_filters.matcher(1, "home").await.is_none());
+
+        stream_filters.invalidate(1);
+        let matchers = stream_filters.matchers.lock().unwrap();
+        assert_eq!(matchers.keys().collect::<Vec<_>>(), vec![&(2, "home")]);
+    }
+}
@@ -0,0 +1,253 @@
+//! Follow request endpoints
+//!
+//! Lets a locked account list pending follow requests and authorize or
+//! reject them. Remote followers are notified with an ActivityPub
+//! `Accept` or `Reject` delivered in the background, signed with the key of
+//! the account.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::extractors::CurrentUser;
+use crate::serializers::{account_json, error_response, relationship_json, success, Relationship};
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Response,
+    routing::{get, post},
+    Router,
+};
+use rustodon_activitypub::{
+    accept_follow_activity, actor_key_id, local_actor_uri, reject_follow_activity,
+};
+use rustodon_db::User;
+use rustodon_federation::{ActorKey, DeliveryClient};
+use rustodon_follow_requests::{FollowRequest, FollowRequestsError};
+use rustodon_notifications::{CreateNotificationRequest, Notification, NotificationType};
+use serde::Deserialize;
+use tracing::{debug, error, info, warn};
+
+/// Pagination parameters for listing follow requests
+#[derive(Debug, Deserialize)]
+pub struct FollowRequestsQuery {
+    pub limit: Option<i64>,
+    pub max_id: Option<i64>,
+    pub since_id: Option<i64>,
+}
+
+/// Routes served by this module
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/follow_requests", get(list_follow_requests_handler))
+        .route(
+            "/api/v1/follow_requests/:id/authorize",
+            post(authorize_follow_request_handler),
+        )
+        .route(
+            "/api/v1/follow_requests/:id/reject",
+            post(reject_follow_request_handler),
+        )
+}
+
+/// Maps a follow request error to an API error response
+pub(crate) fn follow_requests_error_response(e: FollowRequestsError) -> Response {
+    match e {
+        FollowRequestsError::FollowRequestNotFound => {
+            error_response(StatusCode::NOT_FOUND, "Record not found")
+        }
+        FollowRequestsError::AccountNotFound(_) => {
+            error_response(StatusCode::NOT_FOUND, "Record not found")
+        }
+        FollowRequestsError::CannotFollowSelf
+        | FollowRequestsError::AlreadyFollowing
+        | FollowRequestsError::AlreadyRequested => {
+            error_response(StatusCode::UNPROCESSABLE_ENTITY, e.to_string())
+        }
+        FollowRequestsError::Database(_) | FollowRequestsError::Follows(_) => {
+            error!("Follow request operation failed: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+/// List pending follow requests handler
+async fn list_follow_requests_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Query(query): Query<FollowRequestsQuery>,
+) -> Response {
+    debug!("Listing follow requests for account {}", current.id);
+
+    let requests = match FollowRequest::get_by_target(
+        &state.pool,
+        current.id,
+        query.limit,
+        query.since_id,
+        query.max_id,
+    )
+    .await
+    {
+        Ok(requests) => requests,
+        Err(e) => return follow_requests_error_response(e),
+    };
+
+    let mut accounts = Vec::with_capacity(requests.len());
+    for request in requests {
+        match User::get_by_id(&state.pool, request.account_id).await {
+            Ok(Some(user)) => accounts.push(account_json(&user, &state.config.local_domain)),
+            Ok(None) => warn!(
+                "Follow request {} references missing account {}",
+                request.id, request.account_id
+            ),
+            Err(e) => return follow_requests_error_response(e.into()),
+        }
+    }
+
+    success(accounts.into())
+}
+
+/// Authorize follow request handler
+async fn authorize_follow_request_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(account_id): Path<i64>,
+) -> Response {
+    debug!(
+        "Authorizing follow request from {} to {}",
+        account_id, current.id
+    );
+
+    let (request, _follow) =
+        match FollowRequest::authorize(&state.pool, account_id, current.id).await {
+            Ok(result) => result,
+            Err(e) => return follow_requests_error_response(e),
+        };
+
+    if let Err(e) = Notification::create(
+        &state.pool,
+        CreateNotificationRequest {
+            account_id: current.id,
+            from_account_id: Some(account_id),
+            notification_type: NotificationType::Follow,
+            status_id: None,
+            poll_id: None,
+            report_id: None,
+        },
+    )
+    .await
+    {
+        warn!("Failed to create follow notification: {}", e);
+    }
+
+    deliver_follow_response(&state, &current, &request, true).await;
+
+    info!(
+        "Authorized follow request from {} to {}",
+        account_id, current.id
+    );
+    success(relationship_json(&Relationship {
+        account_id,
+        followed_by: true,
+        ..Relationship::default()
+    }))
+}
+
+/// Reject follow request handler
+async fn reject_follow_request_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(account_id): Path<i64>,
+) -> Response {
+    debug!(
+        "Rejecting follow request from {} to {}",
+        account_id, current.id
+    );
+
+    let request = match FollowRequest::reject(&state.pool, account_id, current.id).await {
+        Ok(request) => request,
+        Err(e) => return follow_requests_error_response(e),
+    };
+
+    deliver_follow_response(&state, &current, &request, false).await;
+
+    info!(
+        "Rejected follow request from {} to {}",
+        account_id, current.id
+    );
+    success(relationship_json(&Relationship {
+        account_id,
+        ..Relationship::default()
+    }))
+}
+
+/// Sends an `Accept` or `Reject` to the follower's inbox if they are remote
+///
+/// Delivery happens on a background task so the HTTP response does not
+/// wait on the remote server.
+async fn deliver_follow_response(
+    state: &AppState,
+    target: &User,
+    request: &FollowRequest,
+    accepted: bool,
+) {
+    let follower = match User::get_by_id(&state.pool, request.account_id).await {
+        Ok(Some(follower)) => follower,
+        Ok(None) => return,
+        Err(e) => {
+            warn!("Failed to load follower {}: {}", request.account_id, e);
+            return;
+        }
+    };
+
+    let (Some(follower_uri), Some(inbox_url)) = (follower.uri.clone(), follower.inbox_url.clone())
+    else {
+        return;
+    };
+    if follower.is_local() {
+        return;
+    }
+
+    let actor_uri = local_actor_uri(&state.config.local_domain, &target.username);
+    let activity = if accepted {
+        accept_follow_activity(
+            &actor_uri,
+            &follower_uri,
+            request.uri.as_deref(),
+            request.id,
+        )
+    } else {
+        reject_follow_activity(
+            &actor_uri,
+            &follower_uri,
+            request.uri.as_deref(),
+            request.id,
+        )
+    };
+
+    let pool = state.pool.clone();
+    let account_id = target.id;
+    tokio::spawn(async move {
+        let key = match ActorKey::for_account(&pool, account_id)
+            .await
+            .and_then(|key| key.signing_key(&actor_key_id(&actor_uri)))
+        {
+            Ok(key) => key,
+            Err(e) => {
+                error!("Failed to load key of account {}: {}", account_id, e);
+                return;
+            }
+        };
+        let client = match DeliveryClient::new() {
+            Ok(client) => client,
+            Err(e) => {
+                error!("Failed to create delivery client: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = client.deliver(&key, &inbox_url, &activity).await {
+            warn!("Failed to deliver follow response to {}: {}", inbox_url, e);
+        }
+    });
+}
@@ -0,0 +1,220 @@
+//! Bulk import endpoints
+//!
+//! Accepts the CSV files Mastodon exports as a multipart upload on
+//! `/api/v1/imports` and reports the progress of the resulting imports,
+//! along with the rows that could not be imported.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::extractors::CurrentUser;
+use crate::serializers::{error_response, success};
+use crate::AppState;
+use axum::{
+    extract::{Multipart, Path, State},
+    http::StatusCode,
+    response::Response,
+    routing::get,
+    Router,
+};
+use rustodon_bulk_imports::{
+    parse_csv, BulkImport, BulkImportError, BulkImportMode, BulkImportType,
+};
+use serde_json::{json, Value};
+use tracing::{debug, error, info};
+
+/// Routes of the imports API
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/api/v1/imports",
+            get(list_imports_handler).post(create_import_handler),
+        )
+        .route("/api/v1/imports/:id", get(get_import_handler))
+        .route("/api/v1/imports/:id/failures", get(failures_handler))
+}
+
+/// Maps bulk import errors to API responses
+fn imports_error_response(e: BulkImportError) -> Response {
+    match e {
+        BulkImportError::NotFound(_) => error_response(StatusCode::NOT_FOUND, "Record not found"),
+        BulkImportError::File(message) => error_response(StatusCode::UNPROCESSABLE_ENTITY, message),
+        e => {
+            error!("Import operation failed: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+/// Renders an import with its progress
+fn import_json(import: &BulkImport) -> Value {
+    json!({
+        "id": import.id.to_string(),
+        "type": import.import_type.as_str(),
+        "mode": import.mode.as_str(),
+        "state": import.state.as_str(),
+        "original_filename": import.original_filename,
+        "total_items": import.total_items,
+        "processed_items": import.processed_items,
+        "imported_items": import.imported_items,
+        "failed_items": import.processed_items - import.imported_items,
+        "created_at": import.created_at.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+        "finished_at": import
+            .finished_at
+            .map(|finished_at| finished_at.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()),
+    })
+}
+
+/// Fields of an import upload
+struct ImportUpload {
+    import_type: BulkImportType,
+    mode: BulkImportMode,
+    filename: Option<String>,
+    data: Vec<u8>,
+}
+
+/// Reads the `type`, `mode` and `data` fields of an import upload
+async fn read_upload(mut multipart: Multipart) -> Result<ImportUpload, String> {
+    let mut import_type = None;
+    let mut mode = BulkImportMode::Merge;
+    let mut file = None;
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| format!("Invalid upload: {}", e))?
+    {
+        let name = field.name().unwrap_or("").to_string();
+        match name.as_str() {
+            "type" => {
+                let value = field.text().await.map_err(|e| e.to_string())?;
+                import_type = Some(
+                    BulkImportType::parse(&value)
+                        .ok_or_else(|| format!("Invalid type: {}", value))?,
+                );
+            }
+            "mode" => {
+                let value = field.text().await.map_err(|e| e.to_string())?;
+                mode = BulkImportMode::parse(&value)
+                    .ok_or_else(|| format!("Invalid mode: {}", value))?;
+            }
+            "data" => {
+                let filename = field.file_name().map(str::to_string);
+                let data = field.bytes().await.map_err(|e| e.to_string())?;
+                file = Some((filename, data.to_vec()));
+            }
+            _ => {}
+        }
+    }
+
+    let import_type = import_type.ok_or("Missing type")?;
+    let (filename, data) = file.ok_or("Missing data")?;
+    Ok(ImportUpload {
+        import_type,
+        mode,
+        filename,
+        data,
+    })
+}
+
+/// Create import handler
+async fn create_import_handler(
+    State(state): State<AppState>,
+    CurrentUser(user): CurrentUser,
+    multipart: Multipart,
+) -> Response {
+    let upload = match read_upload(multipart).await {
+        Ok(upload) => upload,
+        Err(message) => return error_response(StatusCode::UNPROCESSABLE_ENTITY, message),
+    };
+    let rows = match parse_csv(upload.import_type, &upload.data) {
+        Ok(rows) => rows,
+        Err(e) => return imports_error_response(e),
+    };
+
+    match BulkImport::create(
+        &state.pool,
+        user.id,
+        upload.import_type,
+        upload.mode,
+        upload.filename.as_deref(),
+        &rows,
+    )
+    .await
+    {
+        Ok(import) => {
+            info!(
+                "{} uploaded {} import {} with {} rows",
+                user.username,
+                import.import_type.as_str(),
+                import.id,
+                import.total_items
+            );
+            success(import_json(&import))
+        }
+        Err(e) => imports_error_response(e),
+    }
+}
+
+/// List imports handler
+async fn list_imports_handler(
+    State(state): State<AppState>,
+    CurrentUser(user): CurrentUser,
+) -> Response {
+    match BulkImport::get_by_account(&state.pool, user.id).await {
+        Ok(imports) => success(imports.iter().map(import_json).collect()),
+        Err(e) => imports_error_response(e),
+    }
+}
+
+/// Get import handler
+async fn get_import_handler(
+    State(state): State<AppState>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+) -> Response {
+    match BulkImport::get(&state.pool, user.id, id).await {
+        Ok(import) => success(import_json(&import)),
+        Err(e) => imports_error_response(e),
+    }
+}
+
+/// Import failures handler
+async fn failures_handler(
+    State(state): State<AppState>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+) -> Response {
+    if let Err(e) = BulkImport::get(&state.pool, user.id, id).await {
+        return imports_error_response(e);
+    }
+
+    match BulkImport::failed_rows(&state.pool, id).await {
+        Ok(rows) => {
+            debug!("Import {} has {} failed rows", id, rows.len());
+            success(
+                rows.iter()
+                    .map(|row| {
+                        json!({
+                            "id": row.id.to_string(),
+                            "row": row.data,
+                            "error": row.error,
+                        })
+                    })
+                    .collect(),
+            )
+        }
+        Err(e) => imports_error_response(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+}
@@ -0,0 +1,283 @@
+//! Instance information endpoints
+//!
+//! Serves `/api/v1/instance` and `/api/v2/instance` from the instance
+//! configuration, media storage limits, server rules and live statistics.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::serializers::account_json;
+use crate::AppState;
+use axum::{extract::State, routing::get, Json, Router};
+use rustodon_config::{Config, RegistrationsMode};
+use rustodon_db::User;
+use rustodon_instances::{InstanceStats, Rule};
+use rustodon_media::StorageConfig;
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+
+/// Version reported to clients; the Mastodon version is what they use for feature detection
+const INSTANCE_VERSION: &str = concat!(
+    "4.2.0 (compatible; Rustodon ",
+    env!("CARGO_PKG_VERSION"),
+    ")"
+);
+
+/// Source code repository
+const SOURCE_URL: &str = "https://github.com/arkCyber/Rustodon";
+
+/// Characters every URL counts as in a status, regardless of its length
+const CHARACTERS_RESERVED_PER_URL: usize = 23;
+
+/// Routes served by this module
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/instance", get(instance_v1_handler))
+        .route("/api/v2/instance", get(instance_v2_handler))
+}
+
+/// Live data that goes into both instance payloads
+struct InstanceData {
+    stats: InstanceStats,
+    rules: Vec<Rule>,
+    contact_account: Option<Value>,
+}
+
+/// Loads statistics, rules and the contact account
+///
+/// Lookups that fail are logged and left empty so the instance endpoints
+/// keep answering while the database is unavailable.
+async fn load_instance_data(state: &AppState) -> InstanceData {
+    let stats = InstanceStats::fetch(&state.pool).await.unwrap_or_else(|e| {
+        warn!("Failed to compute instance statistics: {}", e);
+        InstanceStats::default()
+    });
+
+    let rules = Rule::all(&state.pool).await.unwrap_or_else(|e| {
+        warn!("Failed to load server rules: {}", e);
+        Vec::new()
+    });
+
+    let contact_account = match &state.config.instance.contact_username {
+        Some(username) => match User::get_by_username(&state.pool, username).await {
+            Ok(Some(user)) if user.is_local() => {
+                Some(account_json(&user, &state.config.local_domain))
+            }
+            Ok(_) => {
+                warn!("Contact account {} not found", username);
+                None
+            }
+            Err(e) => {
+                warn!("Failed to load contact account {}: {}", username, e);
+                None
+            }
+        },
+        None => None,
+    };
+
+    InstanceData {
+        stats,
+        rules,
+        contact_account,
+    }
+}
+
+fn rules_json(rules: &[Rule]) -> Value {
+    rules
+        .iter()
+        .map(|rule| {
+            json!({
+                "id": rule.id.to_string(),
+                "text": rule.text,
+                "hint": rule.hint
+            })
+        })
+        .collect()
+}
+
+fn statuses_configuration(config: &Config) -> Value {
+    json!({
+        "max_characters": config.instance.max_status_characters,
+        "max_media_attachments": config.instance.max_media_attachments,
+        "characters_reserved_per_url": CHARACTERS_RESERVED_PER_URL
+    })
+}
+
+fn media_configuration(storage: &StorageConfig) -> Value {
+    json!({
+        "supported_mime_types": storage.supported_mime_types(),
+        "image_size_limit": storage.max_file_size,
+        "image_matrix_limit": 16777216,
+        "video_size_limit": storage.max_file_size,
+        "video_frame_rate_limit": 60,
+        "video_matrix_limit": 2304000
+    })
+}
+
+fn polls_configuration(config: &Config) -> Value {
+    json!({
+        "max_options": config.polls.max_options,
+        "max_characters_per_option": config.polls.max_characters_per_option,
+        "min_expiration": config.polls.min_expiration,
+        "max_expiration": config.polls.max_expiration
+    })
+}
+
+/// Builds the `/api/v1/instance` payload
+fn instance_v1_json(config: &Config, storage: &StorageConfig, data: &InstanceData) -> Value {
+    let instance = &config.instance;
+
+    json!({
+        "uri": config.local_domain,
+        "title": instance.title,
+        "short_description": instance.short_description,
+        "description": instance.description,
+        "email": instance.contact_email,
+        "version": INSTANCE_VERSION,
+        "urls": {
+            "streaming_api": config.streaming_url()
+        },
+        "stats": {
+            "user_count": data.stats.user_count,
+            "status_count": data.stats.status_count,
+            "domain_count": data.stats.domain_count
+        },
+        "thumbnail": instance.thumbnail_url,
+        "languages": instance.languages,
+        "registrations": instance.registrations_mode != RegistrationsMode::Closed,
+        "approval_required": instance.registrations_mode == RegistrationsMode::Approved,
+        "invites_enabled": false,
+        "configuration": {
+            "statuses": statuses_configuration(config),
+            "media_attachments": media_configuration(storage),
+            "polls": polls_configuration(config)
+        },
+        "contact_account": data.contact_account,
+        "rules": rules_json(&data.rules)
+    })
+}
+
+/// Builds the `/api/v2/instance` payload
+fn instance_v2_json(config: &Config, storage: &StorageConfig, data: &InstanceData) -> Value {
+    let instance = &config.instance;
+
+    json!({
+        "domain": config.local_domain,
+        "title": instance.title,
+        "version": INSTANCE_VERSION,
+        "source_url": SOURCE_URL,
+        "description": instance.short_description,
+        "usage": {
+            "users": {
+                "active_month": data.stats.active_month
+            }
+        },
+        "thumbnail": {
+            "url": instance.thumbnail_url
+        },
+        "languages": instance.languages,
+        "configuration": {
+            "urls": {
+                "streaming": config.streaming_url()
+            },
+            "statuses": statuses_configuration(config),
+            "media_attachments": media_configuration(storage),
+            "polls": polls_configuration(config),
+            "translation": {
+                "enabled": instance.translation_enabled
+            }
+        },
+        "registrations": {
+            "enabled": instance.registrations_mode != RegistrationsMode::Closed,
+            "approval_required": instance.registrations_mode == RegistrationsMode::Approved,
+            "message": null
+        },
+        "contact": {
+            "email": instance.contact_email,
+            "account": data.contact_account
+        },
+        "rules": rules_json(&data.rules)
+    })
+}
+
+/// Instance information handler (v1)
+async fn instance_v1_handler(State(state): State<AppState>) -> Json<Value> {
+    debug!("Handling v1 instance request");
+
+    let data = load_instance_data(&state).await;
+    Json(instance_v1_json(&state.config, &state.storage, &data))
+}
+
+/// Instance information handler (v2)
+async fn instance_v2_handler(State(state): State<AppState>) -> Json<Value> {
+    debug!("Handling v2 instance request");
+
+    let data = load_instance_data(&state).await;
+    Json(instance_v2_json(&state.config, &state.storage, &data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data() -> InstanceData {
+        InstanceData {
+            stats: InstanceStats {
+                user_count: 12,
+                status_count: 340,
+                domain_count: 5,
+                active_month: 7,
+            },
+            rules: vec![Rule {
+                id: 1,
+                text: "Be nice".to_string(),
+                hint: String::new(),
+                priority: 0,
+            }],
+            contact_account: None,
+        }
+    }
+
+    #[test]
+    fn test_instance_v1_json() {
+        let mut config = Config::default();
+        config.instance.registrations_mode = RegistrationsMode::Approved;
+        config.polls.max_options = 6;
+
+        let json = instance_v1_json(&config, &StorageConfig::default(), &data());
+        assert_eq!(json["uri"], "rustodon.example.com");
+        assert_eq!(
+            json["urls"]["streaming_api"],
+            "wss://rustodon.example.com:4000"
+        );
+        assert_eq!(json["stats"]["user_count"], 12);
+        assert_eq!(json["stats"]["status_count"], 340);
+        assert_eq!(json["registrations"], true);
+        assert_eq!(json["approval_required"], true);
+        assert_eq!(json["configuration"]["polls"]["max_options"], 6);
+        assert_eq!(
+            json["configuration"]["media_attachments"]["image_size_limit"],
+            40 * 1024 * 1024
+        );
+        assert_eq!(json["rules"][0]["id"], "1");
+    }
+
+    #[test]
+    fn test_instance_v2_json() {
+        let mut config = Config::default();
+        config.instance.registrations_mode = RegistrationsMode::Closed;
+        config.instance.translation_enabled = true;
+
+        let json = instance_v2_json(&config, &StorageConfig::default(), &data());
+        assert_eq!(json["domain"], "rustodon.example.com");
+        assert_eq!(
+            json["configuration"]["urls"]["streaming"],
+            "wss://rustodon.example.com:4000"
+        );
+        assert_eq!(json["usage"]["users"]["active_month"], 7);
+        assert_eq!(json["registrations"]["enabled"], false);
+        assert_eq!(json["configuration"]["translation"]["enabled"], true);
+        assert_eq!(json["contact"]["email"], "admin@rustodon.example.com");
+    }
+}
@@ -7,26 +7,110 @@
 //!
 //! arkSong (arksong2018@gmail.com)
 
+mod account_deletion;
+mod account_warnings;
+mod actors;
+mod admin_accounts;
+mod admin_action_logs;
+mod admin_analytics;
+mod admin_canonical_email_blocks;
+mod admin_email_domain_blocks;
+mod admin_trends;
+mod appeals;
+mod backups;
+mod conversations;
+mod directory;
+mod endorsements;
+mod extractors;
+mod featured_tags;
+mod filters;
+mod follow_requests;
+mod imports;
+mod instance;
+mod markers;
+mod notifications;
+mod polls;
+mod preview_cards;
+mod relationships;
+mod reports;
+mod scheduled_statuses;
+mod serializers;
+mod status_entities;
+mod suggestions;
+mod timelines;
+mod trends;
+
+pub use extractors::CurrentUser;
+
 use axum::{
     extract::{Path, State},
     http::StatusCode,
-    response::IntoResponse,
+    middleware::from_fn_with_state,
+    response::{IntoResponse, Response},
     routing::{get, post},
-    Json, Router,
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Utc};
+use extractors::SessionAuthenticator;
+use filters::StreamFilters;
+use follow_requests::follow_requests_error_response;
+use polls::StatusPolls;
+use preview_cards::fetch_preview_card_later;
+use rustodon_analytics::TrackedMailer;
+use rustodon_auth::{
+    login_user, register_user, AuthError, LoginRequest, RegisterRequest, Registration, SignUpOrigin,
+};
+use rustodon_config::Config;
+use rustodon_email_domain_blocks::{DnsMxResolver, MxResolver, StaticMxResolver};
+use rustodon_follow_requests::{follow_or_request, FollowOutcome};
+use rustodon_ip_blocks::{
+    ip_block_middleware, ClientIp, IpBlockConfig, IpBlockFilter, IpBlockSeverity,
+};
+use rustodon_mailer::{AsyncMailer, MockMailer};
+use rustodon_media::StorageConfig;
+use rustodon_notifications::{CreateNotificationRequest, Notification, NotificationType};
+use rustodon_preview_cards::{PreviewCardConfig, PreviewCardFetcher};
+use rustodon_statuses::{NewPoll, Status, StatusesError};
+use rustodon_streaming::StreamingServer;
+use rustodon_workers::JobQueue;
+use scheduled_statuses::{
+    scheduled_status_json, scheduled_status_service, scheduled_statuses_error_response,
+    status_params,
 };
-use rustodon_auth::{login_user, register_user, LoginRequest, RegisterRequest};
 use serde::Deserialize;
 use serde_json::json;
+use serializers::{error_response, relationship_json, status_json, success, Relationship};
 use sqlx::PgPool;
+use status_entities::StatusEntities;
 use std::net::SocketAddr;
-use tracing::{debug, error, info};
+use std::sync::Arc;
+use std::time::Duration;
+use timelines::stream_status_later;
+use tracing::{debug, error, info, warn};
 
 /// Application state
 #[derive(Clone)]
 pub struct AppState {
     pub pool: PgPool,
+    pub config: Config,
+    pub storage: StorageConfig,
+    pub streaming: StreamingServer,
+    pub mailer: Arc<dyn AsyncMailer>,
+    /// Looks up the mail servers of sign-up addresses
+    pub mx_resolver: Arc<dyn MxResolver>,
+    /// IP blocks matched against clients
+    pub ip_blocks: IpBlockFilter,
+    /// Filters run over streamed events, cached per account
+    pub(crate) stream_filters: StreamFilters,
+    /// Fetches the preview cards of new statuses
+    pub(crate) preview_card_fetcher: PreviewCardFetcher,
+    /// Queue of background jobs run by the worker
+    pub jobs: JobQueue,
 }
 
+/// How often the IP blocks held in memory are reloaded from the database
+const IP_BLOCKS_RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
 /// Status creation request
 #[derive(Debug, Deserialize)]
 pub struct StatusRequest {
@@ -37,6 +121,9 @@ pub struct StatusRequest {
     pub sensitive: Option<bool>,
     pub spoiler_text: Option<String>,
     pub language: Option<String>,
+    pub poll: Option<NewPoll>,
+    /// Publish the status at this time instead of right away
+    pub scheduled_at: Option<DateTime<Utc>>,
 }
 
 /// Follow request
@@ -59,29 +146,20 @@ pub struct ReblogRequest {
     pub visibility: Option<String>,
 }
 
-/// Start the API server
-///
-/// # Arguments
-///
-/// * `pool` - Database connection pool
-/// * `addr` - Socket address to bind to
-///
-/// # Returns
-///
-/// Result indicating success or failure
-pub async fn start_server(
-    pool: PgPool,
-    addr: SocketAddr,
-) -> Result<(), Box<dyn std::error::Error>> {
-    info!("Starting Rustodon API server on {}", addr);
-
-    let state = AppState { pool };
+/// Follow request options
+#[derive(Debug, Deserialize)]
+pub struct FollowParams {
+    /// Whether to show reblogs of the followed account, on by default
+    pub reblogs: Option<bool>,
+    /// Whether to notify when the followed account posts, off by default
+    pub notify: Option<bool>,
+}
 
-    // Create the router with POST support
-    let app = Router::new()
+/// Routes of the API, merging the routes of every endpoint module
+fn routes() -> Router<AppState> {
+    Router::new()
         .route("/", get(root_handler))
         .route("/health", get(health_handler))
-        .route("/api/v1/instance", get(instance_handler))
         .route(
             "/api/v1/accounts",
             get(accounts_handler).post(register_handler),
@@ -112,7 +190,6 @@ pub async fn start_server(
             "/api/v1/statuses/:id/unbookmark",
             post(unbookmark_status_handler),
         )
-        .route("/api/v1/timelines/public", get(public_timeline_handler))
         .route("/api/v1/apps", get(apps_handler))
         // Authentication endpoints
         .route("/api/v1/auth/register", post(register_handler))
@@ -135,8 +212,6 @@ pub async fn start_server(
         // Search endpoint
         .route("/api/v1/search", get(search_handler))
         .route("/api/v1/accounts/search", get(accounts_search_handler))
-        // Notifications endpoint
-        .route("/api/v1/notifications", get(notifications_handler))
         // Media upload endpoint
         .route("/api/v1/media", post(upload_media_handler))
         // Lists endpoints
@@ -144,22 +219,122 @@ pub async fn start_server(
             "/api/v1/lists",
             get(lists_handler).post(create_list_handler),
         )
-        // Conversations endpoints
-        .route("/api/v1/conversations", get(conversations_handler))
         // Bookmarks endpoints
         .route("/api/v1/bookmarks", get(bookmarks_handler))
-        // Polls endpoints
-        .route("/api/v1/polls/:id/votes", post(vote_poll_handler))
-        // Trends endpoints
-        .route("/api/v1/trends/tags", get(trending_tags_handler))
-        .route("/api/v1/trends/statuses", get(trending_statuses_handler))
+        .merge(account_deletion::routes())
+        .merge(account_warnings::routes())
+        .merge(actors::routes())
+        .merge(admin_accounts::routes())
+        .merge(admin_action_logs::routes())
+        .merge(admin_analytics::routes())
+        .merge(admin_canonical_email_blocks::routes())
+        .merge(admin_email_domain_blocks::routes())
+        .merge(admin_trends::routes())
+        .merge(appeals::routes())
+        .merge(backups::routes())
+        .merge(conversations::routes())
+        .merge(directory::routes())
+        .merge(endorsements::routes())
+        .merge(featured_tags::routes())
+        .merge(filters::routes())
+        .merge(follow_requests::routes())
+        .merge(imports::routes())
+        .merge(instance::routes())
+        .merge(markers::routes())
+        .merge(notifications::routes())
+        .merge(polls::routes())
+        .merge(relationships::routes())
+        .merge(reports::routes())
+        .merge(scheduled_statuses::routes())
+        .merge(suggestions::routes())
+        .merge(timelines::routes())
+        .merge(trends::routes())
+}
+
+/// Start the API server
+///
+/// # Arguments
+///
+/// * `pool` - Database connection pool
+/// * `addr` - Socket address to bind to
+/// * `jobs` - Queue of background jobs run by the worker
+///
+/// # Returns
+///
+/// Result indicating success or failure
+pub async fn start_server(
+    pool: PgPool,
+    addr: SocketAddr,
+    jobs: JobQueue,
+) -> Result<(), Box<dyn std::error::Error>> {
+    info!("Starting Rustodon API server on {}", addr);
+
+    let config = Config::from_env();
+    let stream_filters = StreamFilters::new(pool.clone());
+    let streaming = StreamingServer::new(&format!("{}:{}", addr.ip(), config.streaming_port))
+        .await?
+        .with_authenticator(Arc::new(SessionAuthenticator { pool: pool.clone() }))
+        .with_event_filter(Arc::new(stream_filters.clone()));
+
+    let streaming_server = streaming.clone();
+    tokio::spawn(async move {
+        if let Err(e) = streaming_server.start().await {
+            error!("Streaming server error: {}", e);
+        }
+    });
+
+    let mailer = TrackedMailer::new(Arc::new(MockMailer), pool.clone());
+    let mx_resolver: Arc<dyn MxResolver> = match DnsMxResolver::from_system_conf() {
+        Ok(resolver) => Arc::new(resolver),
+        Err(e) => {
+            warn!(
+                "DNS unavailable, email domain blocks won't match mail servers: {}",
+                e
+            );
+            Arc::new(StaticMxResolver::new())
+        }
+    };
+
+    // Blocks created by staff take effect on the next reload
+    let ip_blocks = IpBlockFilter::new(pool.clone(), IpBlockConfig::from_env());
+    let reloaded_ip_blocks = ip_blocks.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(IP_BLOCKS_RELOAD_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = reloaded_ip_blocks.reload().await {
+                error!("Failed to reload IP blocks: {}", e);
+            }
+        }
+    });
+
+    let state = AppState {
+        pool,
+        config,
+        storage: StorageConfig::from_env(),
+        streaming,
+        mailer: Arc::new(mailer),
+        mx_resolver,
+        ip_blocks: ip_blocks.clone(),
+        stream_filters,
+        preview_card_fetcher: PreviewCardFetcher::new(PreviewCardConfig::from_env())?,
+        jobs,
+    };
+
+    let app = routes()
+        .layer(from_fn_with_state(ip_blocks, ip_block_middleware))
         .with_state(state);
 
     // Start the server
     let listener = tokio::net::TcpListener::bind(addr).await?;
     info!("Server listening on {}", addr);
 
-    axum::serve(listener, app).await?;
+    // The peer address is needed to match clients against IP blocks
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
     Ok(())
 }
 
@@ -173,72 +348,45 @@ async fn health_handler() -> &'static str {
     "OK"
 }
 
-/// Instance information handler
-async fn instance_handler() -> impl IntoResponse {
-    Json(json!({
-        "uri": "rustodon.example.com",
-        "title": "Rustodon Instance",
-        "short_description": "A high-performance Rust implementation of Mastodon server",
-        "description": "Rustodon is a modern, type-safe, and high-performance implementation of the Mastodon server written in Rust.",
-        "email": "admin@rustodon.example.com",
-        "version": "0.1.0",
-        "urls": {
-            "streaming_api": "wss://rustodon.example.com"
-        },
-        "stats": {
-            "user_count": 0,
-            "status_count": 0,
-            "domain_count": 0
-        },
-        "thumbnail": null,
-        "languages": ["en"],
-        "registrations": true,
-        "approval_required": false,
-        "invites_enabled": false,
-        "configuration": {
-            "statuses": {
-                "max_characters": 500,
-                "max_media_attachments": 4,
-                "characters_reserved_per_url": 23
-            },
-            "media_attachments": {
-                "supported_mime_types": [
-                    "image/jpeg",
-                    "image/png",
-                    "image/gif",
-                    "image/webp",
-                    "video/mp4",
-                    "video/webm"
-                ],
-                "image_size_limit": 10485760,
-                "image_matrix_limit": 16777216,
-                "video_size_limit": 41943040,
-                "video_frame_rate_limit": 60,
-                "video_matrix_limit": 2304000
-            },
-            "polls": {
-                "max_options": 4,
-                "max_characters_per_option": 50,
-                "min_expiration": 300,
-                "max_expiration": 2629746
-            }
-        },
-        "contact_account": null
-    }))
-}
-
 /// User registration handler
+///
+/// Sign-ups from addresses blocked with `sign_up_block` are refused, and
+/// those from addresses blocked with `sign_up_requires_approval` wait for
+/// staff approval, without a session until then. The registrations mode of
+/// the instance can close sign-ups or make all of them wait for approval.
 async fn register_handler(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
+    client_ip: Option<Extension<ClientIp>>,
     Json(request): Json<RegisterRequest>,
-) -> impl IntoResponse {
+) -> Response {
     debug!(
         "Handling user registration request for: {}",
         request.username
     );
 
-    match register_user(&_state.pool, request).await {
-        Ok(session) => {
+    let client_ip = client_ip.map(|Extension(client_ip)| client_ip);
+    let block = client_ip.and_then(|client_ip| client_ip.block);
+    if block == Some(IpBlockSeverity::SignUpBlock) {
+        return error_response(
+            StatusCode::FORBIDDEN,
+            "Sign-ups are not allowed from your IP address",
+        );
+    }
+    let origin = SignUpOrigin {
+        ip: client_ip.map(|client_ip| client_ip.ip),
+        requires_approval: block == Some(IpBlockSeverity::SignUpRequiresApproval),
+    };
+
+    match register_user(
+        &state.pool,
+        request,
+        state.mx_resolver.as_ref(),
+        state.config.instance.registrations_mode,
+        origin,
+    )
+    .await
+    {
+        Ok(Registration::SignedIn(session)) => {
             info!("User registered successfully");
             (
                 StatusCode::CREATED,
@@ -247,39 +395,70 @@ async fn register_handler(
                     "data": {
                         "user_id": session.user_id,
                         "token": session.token,
-                        "expires_at": session.expires_at
+                        "expires_at": session.expires_at,
+                        "approval_required": false
                     },
                     "error": null
                 })),
             )
+                .into_response()
+        }
+        Ok(Registration::PendingApproval { user_id }) => {
+            info!("User {} registered, waiting for approval", user_id);
+            (
+                StatusCode::CREATED,
+                Json(json!({
+                    "success": true,
+                    "data": {
+                        "user_id": user_id,
+                        "token": null,
+                        "expires_at": null,
+                        "approval_required": true
+                    },
+                    "error": null
+                })),
+            )
+                .into_response()
         }
         Err(e) => {
             error!("Registration failed: {:?}", e);
+            let status = match e {
+                AuthError::RegistrationsClosed => StatusCode::FORBIDDEN,
+                _ => StatusCode::BAD_REQUEST,
+            };
             (
-                StatusCode::BAD_REQUEST,
+                status,
                 Json(json!({
                     "success": false,
                     "data": null,
                     "error": e.to_string()
                 })),
             )
+                .into_response()
         }
     }
 }
 
 /// User login handler
+///
+/// Addresses failing to log in too often are blocked for a while.
 async fn login_handler(
     State(_state): State<AppState>,
+    client_ip: Option<Extension<ClientIp>>,
     Json(request): Json<LoginRequest>,
-) -> impl IntoResponse {
+) -> Response {
     debug!(
         "Handling user login request for: {}",
         request.username_or_email
     );
 
-    match login_user(&_state.pool, request).await {
+    let ip = client_ip.map(|Extension(client_ip)| client_ip.ip);
+    match login_user(&_state.pool, request, ip).await {
         Ok(session) => {
             info!("User logged in successfully");
+            if let Some(ip) = ip {
+                _state.ip_blocks.record_successful_login(ip);
+            }
             (
                 StatusCode::OK,
                 Json(json!({
@@ -292,89 +471,91 @@ async fn login_handler(
                     "error": null
                 })),
             )
+                .into_response()
         }
         Err(e) => {
             error!("Login failed: {:?}", e);
+            if let (Some(ip), AuthError::InvalidCredentials | AuthError::UserNotFound(_)) = (ip, &e)
+            {
+                if let Err(e) = _state.ip_blocks.record_failed_login(ip).await {
+                    error!("Failed to record failed login from {}: {}", ip, e);
+                }
+            }
+            let status = match e {
+                AuthError::AccountDisabled | AuthError::PendingApproval => StatusCode::FORBIDDEN,
+                _ => StatusCode::UNAUTHORIZED,
+            };
             (
-                StatusCode::UNAUTHORIZED,
+                status,
                 Json(json!({
                     "success": false,
                     "data": null,
                     "error": e.to_string()
                 })),
             )
+                .into_response()
         }
     }
 }
 
 /// Create status handler
+///
+/// With `scheduled_at` the status is stored as a scheduled status and the
+/// scheduled status entity is returned instead.
 async fn create_status_handler(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
     Json(request): Json<StatusRequest>,
-) -> impl IntoResponse {
-    debug!("Handling status creation request");
+) -> Response {
+    debug!("Handling status creation request for user {}", current.id);
+
+    let params = match status_params(&request, state.config.instance.max_status_characters) {
+        Ok(params) => params,
+        Err(e) => return scheduled_statuses_error_response(e),
+    };
+    let service = scheduled_status_service(&state.config);
+
+    if let Some(scheduled_at) = request.scheduled_at {
+        return match service
+            .schedule(&state.pool, current.id, scheduled_at, params)
+            .await
+        {
+            Ok(scheduled) => success(scheduled_status_json(&scheduled)),
+            Err(e) => scheduled_statuses_error_response(e),
+        };
+    }
 
-    // For now, return a mock response
-    // TODO: Implement actual status creation
-    (
-        StatusCode::CREATED,
-        Json(json!({
-            "success": true,
-            "data": {
-                "id": "1",
-                "content": request.status,
-                "created_at": chrono::Utc::now().to_rfc3339(),
-                "visibility": request.visibility.unwrap_or_else(|| "public".to_string()),
-                "sensitive": request.sensitive.unwrap_or(false),
-                "spoiler_text": request.spoiler_text,
-                "language": request.language.clone().unwrap_or_else(|| "en".to_string()),
-                "account": {
-                    "id": "1",
-                    "username": "testuser",
-                    "acct": "testuser@rustodon.example.com",
-                    "display_name": "Test User",
-                    "locked": false,
-                    "bot": false,
-                    "discoverable": true,
-                    "group": false,
-                    "created_at": "2025-01-01T00:00:00.000Z",
-                    "note": "Test user account",
-                    "url": "https://rustodon.example.com/@testuser",
-                    "avatar": "https://rustodon.example.com/avatars/original/missing.png",
-                    "avatar_static": "https://rustodon.example.com/avatars/original/missing.png",
-                    "header": "https://rustodon.example.com/headers/original/missing.png",
-                    "header_static": "https://rustodon.example.com/headers/original/missing.png",
-                    "followers_count": 0,
-                    "following_count": 0,
-                    "statuses_count": 1,
-                    "last_status_at": chrono::Utc::now().to_rfc3339(),
-                    "emojis": [],
-                    "fields": []
-                },
-                "media_attachments": [],
-                "mentions": [],
-                "tags": [],
-                "emojis": [],
-                "reblogs_count": 0,
-                "favourites_count": 0,
-                "replies_count": 0,
-                "url": "https://rustodon.example.com/@testuser/1",
-                "in_reply_to_id": request.in_reply_to_id,
-                "in_reply_to_account_id": null,
-                "reblog": null,
-                "poll": null,
-                "card": null,
-                "application": null,
-                "language": request.language.clone().unwrap_or_else(|| "en".to_string()),
-                "muted": false,
-                "reblogged": false,
-                "favourited": false,
-                "bookmarked": false,
-                "pinned": false
-            },
-            "error": null
-        })),
-    )
+    if let Err(e) = service.validate_params(&params) {
+        return scheduled_statuses_error_response(e);
+    }
+
+    let new_status = params.to_new_status(current.id);
+    match Status::create(&state.pool, new_status, &state.config.local_domain).await {
+        Ok(status) => {
+            let mut body = status_json(&status, &current, &state.config.local_domain);
+            match StatusPolls::load(&state, &[status.id], Some(current.id)).await {
+                Ok(polls) => polls.attach(status.id, &mut body),
+                Err(e) => warn!("Failed to load poll of status {}: {}", status.id, e),
+            }
+            match StatusEntities::load(&state, &[status.id]).await {
+                Ok(entities) => entities.attach(status.id, &mut body),
+                Err(e) => warn!("Failed to load mentions of status {}: {}", status.id, e),
+            }
+            fetch_preview_card_later(&state, status.id).await;
+            stream_status_later(&state, status, body.clone());
+            success(body)
+        }
+        Err(StatusesError::StatusNotFound(_)) => {
+            error_response(StatusCode::NOT_FOUND, "Record not found")
+        }
+        Err(e @ (StatusesError::Validation(_) | StatusesError::InvalidVisibility(_))) => {
+            error_response(StatusCode::UNPROCESSABLE_ENTITY, e.to_string())
+        }
+        Err(e) => {
+            error!("Failed to create status: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
 }
 
 /// Favorite status handler
@@ -469,40 +650,63 @@ async fn unreblog_status_handler(
 }
 
 /// Follow account handler
+///
+/// Follows the account immediately unless it is locked, in which case a
+/// follow request is created and the relationship reports `requested`.
 async fn follow_account_handler(
-    State(_state): State<AppState>,
-    Path(account_id): Path<String>,
-) -> impl IntoResponse {
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(account_id): Path<i64>,
+    params: Option<Json<FollowParams>>,
+) -> Response {
     debug!(
         "Handling follow account request for account: {}",
         account_id
     );
 
-    // TODO: Implement actual follow functionality
-    (
-        StatusCode::OK,
-        Json(json!({
-            "success": true,
-            "data": {
-                "id": account_id,
-                "following": true,
-                "followed_by": false,
-                "requested": false,
-                "delivery_following": true,
-                "notifying": false,
-                "showing_reblogs": true,
-                "blocking": false,
-                "blocked_by": false,
-                "muting": false,
-                "muting_notifications": false,
-                "requested": false,
-                "domain_blocking": false,
-                "endorsed": false,
-                "note": ""
-            },
-            "error": null
-        })),
+    let (reblogs, notify) =
+        params.map_or((None, None), |Json(params)| (params.reblogs, params.notify));
+    let outcome = match follow_or_request(
+        &state.pool,
+        current.id,
+        account_id,
+        reblogs.unwrap_or(true),
+        notify.unwrap_or(false),
+        None,
+    )
+    .await
+    {
+        Ok(outcome) => outcome,
+        Err(e) => return follow_requests_error_response(e),
+    };
+
+    let (notification_type, following, requested) = match outcome {
+        FollowOutcome::Followed(_) => (NotificationType::Follow, true, false),
+        FollowOutcome::Requested(_) => (NotificationType::FollowRequest, false, true),
+    };
+
+    if let Err(e) = Notification::create(
+        &state.pool,
+        CreateNotificationRequest {
+            account_id,
+            from_account_id: Some(current.id),
+            notification_type,
+            status_id: None,
+            poll_id: None,
+            report_id: None,
+        },
     )
+    .await
+    {
+        warn!("Failed to create follow notification: {}", e);
+    }
+
+    success(relationship_json(&Relationship {
+        account_id,
+        following,
+        requested,
+        ..Relationship::default()
+    }))
 }
 
 /// Unfollow account handler
@@ -650,18 +854,6 @@ async fn search_handler() -> impl IntoResponse {
     }))
 }
 
-/// Notifications handler
-async fn notifications_handler() -> impl IntoResponse {
-    debug!("Handling notifications request");
-
-    // TODO: Implement actual notifications functionality
-    Json(json!({
-        "success": true,
-        "data": [],
-        "error": null
-    }))
-}
-
 /// Upload media handler
 async fn upload_media_handler() -> impl IntoResponse {
     debug!("Handling media upload request");
@@ -732,18 +924,6 @@ async fn create_list_handler() -> impl IntoResponse {
     )
 }
 
-/// Conversations handler
-async fn conversations_handler() -> impl IntoResponse {
-    debug!("Handling conversations request");
-
-    // TODO: Implement actual conversations functionality
-    Json(json!({
-        "success": true,
-        "data": [],
-        "error": null
-    }))
-}
-
 /// Bookmarks handler
 async fn bookmarks_handler() -> impl IntoResponse {
     debug!("Handling bookmarks request");
@@ -801,76 +981,6 @@ async fn unbookmark_status_handler(
     )
 }
 
-/// Vote poll handler
-async fn vote_poll_handler(
-    State(_state): State<AppState>,
-    Path(poll_id): Path<String>,
-) -> impl IntoResponse {
-    debug!("Handling vote poll request for poll: {}", poll_id);
-
-    // TODO: Implement actual poll voting functionality
-    (
-        StatusCode::OK,
-        Json(json!({
-            "success": true,
-            "data": {
-                "id": poll_id,
-                "expires_at": null,
-                "expired": false,
-                "multiple": false,
-                "votes_count": 1,
-                "voters_count": 1,
-                "voted": true,
-                "own_votes": [0],
-                "options": [
-                    {
-                        "title": "Option 1",
-                        "votes_count": 1
-                    }
-                ],
-                "emojis": []
-            },
-            "error": null
-        })),
-    )
-}
-
-/// Trending tags handler
-async fn trending_tags_handler() -> impl IntoResponse {
-    debug!("Handling trending tags request");
-
-    // TODO: Implement actual trending tags functionality
-    Json(json!({
-        "success": true,
-        "data": [
-            {
-                "name": "rustodon",
-                "url": "https://rustodon.example.com/tags/rustodon",
-                "history": [
-                    {
-                        "day": "1577664000",
-                        "uses": "1",
-                        "accounts": "1"
-                    }
-                ]
-            }
-        ],
-        "error": null
-    }))
-}
-
-/// Trending statuses handler
-async fn trending_statuses_handler() -> impl IntoResponse {
-    debug!("Handling trending statuses request");
-
-    // TODO: Implement actual trending statuses functionality
-    Json(json!({
-        "success": true,
-        "data": [],
-        "error": null
-    }))
-}
-
 /// Accounts handler
 async fn accounts_handler() -> impl IntoResponse {
     Json(json!([
@@ -934,40 +1044,6 @@ async fn statuses_handler() -> impl IntoResponse {
     ]))
 }
 
-/// Public timeline handler
-async fn public_timeline_handler() -> impl IntoResponse {
-    Json(json!([
-        {
-            "id": "1",
-            "content": "Welcome to the public timeline!",
-            "created_at": "2025-01-01T00:00:00.000Z",
-            "account": {
-                "id": "1",
-                "username": "admin",
-                "acct": "admin@rustodon.example.com",
-                "display_name": "Rustodon Admin",
-                "locked": false,
-                "bot": false,
-                "discoverable": true,
-                "group": false,
-                "created_at": "2025-01-01T00:00:00.000Z",
-                "note": "System administrator account",
-                "url": "https://rustodon.example.com/@admin",
-                "avatar": "https://rustodon.example.com/avatars/original/missing.png",
-                "avatar_static": "https://rustodon.example.com/avatars/original/missing.png",
-                "header": "https://rustodon.example.com/headers/original/missing.png",
-                "header_static": "https://rustodon.example.com/headers/original/missing.png",
-                "followers_count": 0,
-                "following_count": 0,
-                "statuses_count": 0,
-                "last_status_at": null,
-                "emojis": [],
-                "fields": []
-            }
-        }
-    ]))
-}
-
 /// Apps handler
 async fn apps_handler() -> impl IntoResponse {
     debug!("Handling apps request");
@@ -1068,6 +1144,11 @@ async fn get_status_handler(
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+
     #[tokio::test]
     async fn test_root_handler() {
         let response = root_handler().await;
@@ -1080,12 +1161,6 @@ mod tests {
         assert_eq!(response, "OK");
     }
 
-    #[tokio::test]
-    async fn test_instance_handler() {
-        let _response = instance_handler().await;
-        // Add assertions for the JSON response
-    }
-
     #[tokio::test]
     async fn test_accounts_handler() {
         let _response = accounts_handler().await;
@@ -1098,12 +1173,6 @@ mod tests {
         // Add assertions for the JSON response
     }
 
-    #[tokio::test]
-    async fn test_public_timeline_handler() {
-        let _response = public_timeline_handler().await;
-        // Add assertions for the JSON response
-    }
-
     #[tokio::test]
     async fn test_apps_handler() {
         let _response = apps_handler().await;
@@ -0,0 +1,177 @@
+//! Timeline read marker endpoints
+//!
+//! Saves the last-read positions of the home and notifications timelines
+//! and pushes every change to the user's other streaming connections.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::extractors::CurrentUser;
+use crate::serializers::{error_response, success};
+use crate::AppState;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Response,
+    routing::get,
+    Json, Router,
+};
+use rustodon_markers::{Marker, MarkerTimeline, MarkersError};
+use rustodon_streaming::{StreamType, StreamingMessage};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use tracing::{debug, error, warn};
+
+/// New position for one timeline
+#[derive(Debug, Deserialize)]
+pub struct MarkerParams {
+    pub last_read_id: String,
+    /// Version the client last saw; the update is rejected if it is stale
+    pub version: Option<i32>,
+}
+
+/// Routes served by this module
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new().route(
+        "/api/v1/markers",
+        get(get_markers_handler).post(save_markers_handler),
+    )
+}
+
+/// Renders markers as a map keyed by timeline name
+fn markers_json(markers: &[Marker]) -> Value {
+    let mut map = Map::new();
+    for marker in markers {
+        map.insert(
+            marker.timeline.to_string(),
+            serde_json::json!({
+                "last_read_id": marker.last_read_id.to_string(),
+                "version": marker.version,
+                "updated_at": marker.updated_at.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+            }),
+        );
+    }
+    Value::Object(map)
+}
+
+/// Maps a markers error to an API error response
+fn markers_error_response(e: MarkersError) -> Response {
+    match e {
+        MarkersError::InvalidTimeline(_) => {
+            error_response(StatusCode::UNPROCESSABLE_ENTITY, e.to_string())
+        }
+        MarkersError::StaleVersion(_) => error_response(StatusCode::CONFLICT, e.to_string()),
+        MarkersError::Database(_) => {
+            error!("Marker operation failed: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+/// Get markers handler
+///
+/// Accepts `timeline[]=home&timeline[]=notifications`; with no timeline
+/// parameters every marker is returned.
+async fn get_markers_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Query(params): Query<Vec<(String, String)>>,
+) -> Response {
+    debug!("Getting markers for user {}", current.id);
+
+    let mut timelines = Vec::new();
+    for (key, value) in params {
+        if key != "timeline[]" && key != "timeline" {
+            continue;
+        }
+        match value.parse::<MarkerTimeline>() {
+            Ok(timeline) => timelines.push(timeline),
+            Err(e) => return markers_error_response(e),
+        }
+    }
+    if timelines.is_empty() {
+        timelines.extend(MarkerTimeline::ALL);
+    }
+
+    match Marker::get_by_user(&state.pool, current.id, &timelines).await {
+        Ok(markers) => success(markers_json(&markers)),
+        Err(e) => markers_error_response(e),
+    }
+}
+
+/// Save markers handler
+async fn save_markers_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Json(request): Json<HashMap<String, MarkerParams>>,
+) -> Response {
+    debug!("Saving markers for user {}", current.id);
+
+    let mut updates = Vec::with_capacity(request.len());
+    for (name, params) in request {
+        let timeline = match name.parse::<MarkerTimeline>() {
+            Ok(timeline) => timeline,
+            Err(e) => return markers_error_response(e),
+        };
+        let Ok(last_read_id) = params.last_read_id.parse::<i64>() else {
+            return error_response(StatusCode::UNPROCESSABLE_ENTITY, "Invalid last_read_id");
+        };
+        updates.push((timeline, last_read_id, params.version));
+    }
+
+    // A stale version of one marker leaves all of them unsaved
+    let mut tx = match state.pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => return markers_error_response(e.into()),
+    };
+    let mut markers = Vec::with_capacity(updates.len());
+    for (timeline, last_read_id, version) in updates {
+        match Marker::save(&mut tx, current.id, timeline, last_read_id, version).await {
+            Ok(marker) => markers.push(marker),
+            Err(e) => return markers_error_response(e),
+        }
+    }
+    if let Err(e) = tx.commit().await {
+        return markers_error_response(e.into());
+    }
+
+    let body = markers_json(&markers);
+    if let Err(e) = state
+        .streaming
+        .broadcast_to_user(
+            current.id,
+            StreamType::User,
+            StreamingMessage::Marker(body.clone()),
+        )
+        .await
+    {
+        warn!("Failed to stream marker update: {}", e);
+    }
+
+    success(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_markers_json() {
+        let marker = Marker {
+            id: 1,
+            user_id: 2,
+            timeline: MarkerTimeline::Home,
+            last_read_id: 103,
+            version: 4,
+            updated_at: Utc::now(),
+        };
+
+        let json = markers_json(&[marker]);
+        assert_eq!(json["home"]["last_read_id"], "103");
+        assert_eq!(json["home"]["version"], 4);
+        assert!(json.get("notifications").is_none());
+    }
+}
@@ -0,0 +1,720 @@
+//! Notification endpoints
+//!
+//! Serves the flat `/api/v1/notifications` list, grouped notifications
+//! under `/api/v2/notifications`, the notification policy and the inbox
+//! of notification requests held back by it. Statuses attached to
+//! notifications go through the user's filters for the `notifications`
+//! context.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::extractors::CurrentUser;
+use crate::filters::{filter_notifications, filter_status, load_matcher};
+use crate::polls::StatusPolls;
+use crate::preview_cards::StatusCards;
+use crate::reports::report_json;
+use crate::serializers::{account_json, error_response, status_json, success};
+use crate::status_entities::StatusEntities;
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Response,
+    routing::{get, post},
+    Json, Router,
+};
+use rustodon_db::User;
+use rustodon_notifications::{
+    GroupQuery, Notification, NotificationGroup, NotificationPolicy, NotificationRequest,
+    NotificationType, NotificationsError, PolicySummary, UpdateNotificationPolicyRequest,
+};
+use rustodon_reports::Report;
+use rustodon_statuses::Status;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use tracing::{debug, error, info, warn};
+
+/// Pagination parameters for listing notification requests
+#[derive(Debug, Deserialize)]
+pub struct NotificationRequestsQuery {
+    pub limit: Option<i64>,
+    pub max_id: Option<i64>,
+    pub since_id: Option<i64>,
+}
+
+/// Routes served by this module
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/notifications", get(list_notifications_handler))
+        .route(
+            "/api/v1/notifications/policy",
+            get(get_policy_handler)
+                .put(update_policy_handler)
+                .patch(update_policy_handler),
+        )
+        .route("/api/v1/notifications/requests", get(list_requests_handler))
+        .route(
+            "/api/v1/notifications/requests/:id",
+            get(get_request_handler),
+        )
+        .route(
+            "/api/v1/notifications/requests/:id/accept",
+            post(accept_request_handler),
+        )
+        .route(
+            "/api/v1/notifications/requests/:id/dismiss",
+            post(dismiss_request_handler),
+        )
+        .route("/api/v2/notifications", get(list_groups_handler))
+        .route(
+            "/api/v2/notifications/unread_count",
+            get(unread_count_handler),
+        )
+        .route("/api/v2/notifications/:group_key", get(get_group_handler))
+        .route(
+            "/api/v2/notifications/:group_key/dismiss",
+            post(dismiss_group_handler),
+        )
+}
+
+/// Maps a notifications error to an API error response
+fn notifications_error_response(e: NotificationsError) -> Response {
+    match e {
+        NotificationsError::NotificationNotFound(_)
+        | NotificationsError::NotificationGroupNotFound(_)
+        | NotificationsError::NotificationRequestNotFound(_)
+        | NotificationsError::UserNotFound(_)
+        | NotificationsError::AccountNotFound(_)
+        | NotificationsError::StatusNotFound(_) => {
+            error_response(StatusCode::NOT_FOUND, "Record not found")
+        }
+        NotificationsError::Validation(_) => {
+            error_response(StatusCode::UNPROCESSABLE_ENTITY, e.to_string())
+        }
+        NotificationsError::Database(_) | NotificationsError::Internal(_) => {
+            error!("Notification operation failed: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+fn internal_error(e: impl std::fmt::Display) -> Response {
+    error!("Failed to load notification data: {}", e);
+    error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+}
+
+/// Query parameters shared by the v1 and v2 notification lists
+///
+/// Array parameters are accepted as `types[]=mention&types[]=poll`.
+#[derive(Debug, Default)]
+struct ListParams {
+    limit: Option<i64>,
+    max_id: Option<i64>,
+    since_id: Option<i64>,
+    types: Vec<NotificationType>,
+    exclude_types: Vec<NotificationType>,
+    grouped_types: Vec<NotificationType>,
+}
+
+impl ListParams {
+    fn parse(pairs: Vec<(String, String)>) -> Result<Self, NotificationsError> {
+        let number = |key: &str, value: &str| {
+            value
+                .parse::<i64>()
+                .map_err(|_| NotificationsError::Validation(format!("Invalid {}: {}", key, value)))
+        };
+
+        let mut params = ListParams::default();
+        for (key, value) in pairs {
+            match key.trim_end_matches("[]") {
+                "limit" => params.limit = Some(number(&key, &value)?),
+                "max_id" => params.max_id = Some(number(&key, &value)?),
+                // Both page forwards; results are always newest first
+                "since_id" | "min_id" => params.since_id = Some(number(&key, &value)?),
+                "types" => params.types.push(value.parse()?),
+                "exclude_types" => params.exclude_types.push(value.parse()?),
+                "grouped_types" => params.grouped_types.push(value.parse()?),
+                _ => {}
+            }
+        }
+        Ok(params)
+    }
+}
+
+/// Accounts, statuses and reports referenced by notifications
+#[derive(Default)]
+struct References {
+    accounts: HashMap<i64, User>,
+    statuses: HashMap<i64, Status>,
+    reports: HashMap<i64, Report>,
+    polls: StatusPolls,
+    cards: StatusCards,
+    entities: StatusEntities,
+}
+
+impl References {
+    /// Loads the given accounts and statuses, plus the statuses' authors
+    /// and polls as seen by the viewer, link preview cards, mentions and
+    /// hashtags
+    async fn load(
+        state: &AppState,
+        viewer_id: i64,
+        account_ids: impl IntoIterator<Item = i64>,
+        status_ids: impl IntoIterator<Item = i64>,
+    ) -> Result<Self, Response> {
+        let status_ids: Vec<i64> = status_ids
+            .into_iter()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let statuses = Status::get_by_ids(&state.pool, &status_ids)
+            .await
+            .map_err(internal_error)?;
+        let polls = StatusPolls::load(state, &status_ids, Some(viewer_id))
+            .await
+            .map_err(internal_error)?;
+        let cards = StatusCards::load(state, &status_ids)
+            .await
+            .map_err(internal_error)?;
+        let entities = StatusEntities::load(state, &status_ids)
+            .await
+            .map_err(internal_error)?;
+
+        let account_ids: HashSet<i64> = account_ids
+            .into_iter()
+            .chain(statuses.iter().map(|s| s.account_id))
+            .collect();
+
+        let mut accounts = HashMap::with_capacity(account_ids.len());
+        for id in account_ids {
+            match User::get_by_id(&state.pool, id).await {
+                Ok(Some(user)) => {
+                    accounts.insert(id, user);
+                }
+                Ok(None) => warn!("Notification references missing account {}", id),
+                Err(e) => return Err(internal_error(e)),
+            }
+        }
+
+        Ok(Self {
+            accounts,
+            statuses: statuses.into_iter().map(|s| (s.id, s)).collect(),
+            reports: HashMap::new(),
+            polls,
+            cards,
+            entities,
+        })
+    }
+
+    /// Loads the given reports and the accounts they are about
+    async fn load_reports(
+        &mut self,
+        state: &AppState,
+        report_ids: impl IntoIterator<Item = i64>,
+    ) -> Result<(), Response> {
+        let report_ids: Vec<i64> = report_ids.into_iter().collect();
+        let reports = Report::get_by_ids(&state.pool, &report_ids)
+            .await
+            .map_err(internal_error)?;
+
+        for report in reports {
+            if let Entry::Vacant(entry) = self.accounts.entry(report.target_account_id) {
+                match User::get_by_id(&state.pool, report.target_account_id).await {
+                    Ok(Some(user)) => {
+                        entry.insert(user);
+                    }
+                    Ok(None) => warn!(
+                        "Notification references missing account {}",
+                        report.target_account_id
+                    ),
+                    Err(e) => return Err(internal_error(e)),
+                }
+            }
+            self.reports.insert(report.id, report);
+        }
+        Ok(())
+    }
+
+    fn account(&self, id: i64, local_domain: &str) -> Option<Value> {
+        self.accounts
+            .get(&id)
+            .map(|user| account_json(user, local_domain))
+    }
+
+    fn status(&self, id: i64, local_domain: &str) -> Option<Value> {
+        let status = self.statuses.get(&id)?;
+        let author = self.accounts.get(&status.account_id)?;
+        let mut json = status_json(status, author, local_domain);
+        self.polls.attach(id, &mut json);
+        self.cards.attach(id, &mut json);
+        self.entities.attach(id, &mut json);
+        Some(json)
+    }
+
+    fn report(&self, id: i64, local_domain: &str) -> Option<Value> {
+        let report = self.reports.get(&id)?;
+        let target_account = self
+            .account(report.target_account_id, local_domain)
+            .unwrap_or(Value::Null);
+        Some(report_json(report, target_account))
+    }
+}
+
+fn format_time(time: chrono::DateTime<chrono::Utc>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+/// Renders a notification entity
+fn notification_json(
+    notification: &Notification,
+    references: &References,
+    local_domain: &str,
+) -> Option<Value> {
+    let account = references.account(notification.from_account_id?, local_domain)?;
+    let status = notification
+        .status_id
+        .and_then(|id| references.status(id, local_domain));
+    let report = notification
+        .report_id
+        .and_then(|id| references.report(id, local_domain));
+
+    Some(json!({
+        "id": notification.id.to_string(),
+        "type": notification.notification_type.to_string(),
+        "group_key": notification
+            .group_key
+            .clone()
+            .unwrap_or_else(|| format!("ungrouped-{}", notification.id)),
+        "created_at": format_time(notification.created_at),
+        "account": account,
+        "status": status,
+        "report": report
+    }))
+}
+
+/// Renders a notification group entity
+fn group_json(group: &NotificationGroup) -> Value {
+    json!({
+        "group_key": group.group_key,
+        "notifications_count": group.notifications_count,
+        "type": group.notification_type.to_string(),
+        "most_recent_notification_id": group.most_recent_notification_id.to_string(),
+        "page_min_id": group.page_min_id.to_string(),
+        "page_max_id": group.page_max_id.to_string(),
+        "latest_page_notification_at": format_time(group.latest_page_notification_at),
+        "sample_account_ids": group
+            .sample_account_ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>(),
+        "status_id": group.status_id.map(|id| id.to_string())
+    })
+}
+
+/// Renders the grouped notifications results for a set of groups
+///
+/// Groups about statuses hidden by the user's filters are dropped.
+async fn grouped_results(
+    state: &AppState,
+    account_id: i64,
+    mut groups: Vec<NotificationGroup>,
+) -> Result<Value, Response> {
+    let references = References::load(
+        state,
+        account_id,
+        groups.iter().flat_map(|g| g.sample_account_ids.clone()),
+        groups.iter().filter_map(|g| g.status_id),
+    )
+    .await?;
+    let local_domain = &state.config.local_domain;
+
+    let matcher = load_matcher(state, account_id, "notifications").await;
+    let mut statuses = HashMap::new();
+    for group in &groups {
+        let Some(status_id) = group.status_id else {
+            continue;
+        };
+        if let Some(mut status) = references.status(status_id, local_domain) {
+            let shown = matcher
+                .as_ref()
+                .is_none_or(|m| filter_status(m, &mut status));
+            statuses.insert(status_id, shown.then_some(status));
+        }
+    }
+    groups.retain(|g| {
+        g.status_id
+            .is_none_or(|id| !matches!(statuses.get(&id), Some(None)))
+    });
+
+    let mut account_ids: Vec<i64> = groups
+        .iter()
+        .flat_map(|g| g.sample_account_ids.iter().copied())
+        .chain(
+            statuses
+                .values()
+                .flatten()
+                .filter_map(|s| s["account"]["id"].as_str()?.parse().ok()),
+        )
+        .collect();
+    account_ids.sort_unstable();
+    account_ids.dedup();
+
+    Ok(json!({
+        "accounts": account_ids
+            .into_iter()
+            .filter_map(|id| references.account(id, local_domain))
+            .collect::<Vec<_>>(),
+        "statuses": statuses.into_values().flatten().collect::<Vec<_>>(),
+        "notification_groups": groups.iter().map(group_json).collect::<Vec<_>>()
+    }))
+}
+
+/// List notifications handler (v1)
+async fn list_notifications_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Query(pairs): Query<Vec<(String, String)>>,
+) -> Response {
+    debug!("Listing notifications for account {}", current.id);
+
+    let params = match ListParams::parse(pairs) {
+        Ok(params) => params,
+        Err(e) => return notifications_error_response(e),
+    };
+
+    let mut notifications = match Notification::get_by_account(
+        &state.pool,
+        current.id,
+        params.limit,
+        params.since_id,
+        params.max_id,
+        Some(params.exclude_types),
+    )
+    .await
+    {
+        Ok(notifications) => notifications,
+        Err(e) => return notifications_error_response(e),
+    };
+    if !params.types.is_empty() {
+        notifications.retain(|n| params.types.contains(&n.notification_type));
+    }
+
+    let mut references = match References::load(
+        &state,
+        current.id,
+        notifications.iter().filter_map(|n| n.from_account_id),
+        notifications.iter().filter_map(|n| n.status_id),
+    )
+    .await
+    {
+        Ok(references) => references,
+        Err(response) => return response,
+    };
+    if let Err(response) = references
+        .load_reports(&state, notifications.iter().filter_map(|n| n.report_id))
+        .await
+    {
+        return response;
+    }
+
+    let mut rendered: Vec<Value> = notifications
+        .iter()
+        .filter_map(|n| notification_json(n, &references, &state.config.local_domain))
+        .collect();
+    if let Some(matcher) = load_matcher(&state, current.id, "notifications").await {
+        rendered = filter_notifications(&matcher, rendered);
+    }
+
+    success(rendered.into())
+}
+
+/// List notification groups handler (v2)
+async fn list_groups_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Query(pairs): Query<Vec<(String, String)>>,
+) -> Response {
+    debug!("Listing notification groups for account {}", current.id);
+
+    let params = match ListParams::parse(pairs) {
+        Ok(params) => params,
+        Err(e) => return notifications_error_response(e),
+    };
+
+    let query = GroupQuery {
+        limit: params.limit,
+        max_id: params.max_id,
+        since_id: params.since_id,
+        types: (!params.types.is_empty()).then_some(params.types),
+        exclude_types: params.exclude_types,
+        grouped_types: (!params.grouped_types.is_empty()).then_some(params.grouped_types),
+    };
+
+    let groups = match NotificationGroup::get_by_account(&state.pool, current.id, query).await {
+        Ok(groups) => groups,
+        Err(e) => return notifications_error_response(e),
+    };
+
+    match grouped_results(&state, current.id, groups).await {
+        Ok(results) => success(results),
+        Err(response) => response,
+    }
+}
+
+/// Get notification group handler
+async fn get_group_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(group_key): Path<String>,
+) -> Response {
+    let group = match NotificationGroup::get(&state.pool, current.id, &group_key).await {
+        Ok(group) => group,
+        Err(e) => return notifications_error_response(e),
+    };
+
+    match grouped_results(&state, current.id, vec![group]).await {
+        Ok(results) => success(results),
+        Err(response) => response,
+    }
+}
+
+/// Dismiss notification group handler
+async fn dismiss_group_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(group_key): Path<String>,
+) -> Response {
+    match NotificationGroup::dismiss(&state.pool, current.id, &group_key).await {
+        Ok(()) => success(json!({})),
+        Err(e) => notifications_error_response(e),
+    }
+}
+
+/// Unread notification groups count handler
+async fn unread_count_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+) -> Response {
+    match NotificationGroup::unread_count(&state.pool, current.id).await {
+        Ok(count) => success(json!({ "count": count })),
+        Err(e) => notifications_error_response(e),
+    }
+}
+
+/// Renders a notification policy entity
+fn policy_json(policy: &NotificationPolicy, summary: &PolicySummary) -> Value {
+    json!({
+        "filter_not_following": policy.filter_not_following,
+        "filter_not_followers": policy.filter_not_followers,
+        "filter_new_accounts": policy.filter_new_accounts,
+        "filter_private_mentions": policy.filter_private_mentions,
+        "summary": {
+            "pending_requests_count": summary.pending_requests_count,
+            "pending_notifications_count": summary.pending_notifications_count
+        }
+    })
+}
+
+async fn policy_response(state: &AppState, policy: NotificationPolicy) -> Response {
+    match NotificationPolicy::summary(&state.pool, policy.account_id).await {
+        Ok(summary) => success(policy_json(&policy, &summary)),
+        Err(e) => notifications_error_response(e),
+    }
+}
+
+/// Get notification policy handler
+async fn get_policy_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+) -> Response {
+    match NotificationPolicy::get_or_create(&state.pool, current.id).await {
+        Ok(policy) => policy_response(&state, policy).await,
+        Err(e) => notifications_error_response(e),
+    }
+}
+
+/// Update notification policy handler
+async fn update_policy_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Json(request): Json<UpdateNotificationPolicyRequest>,
+) -> Response {
+    match NotificationPolicy::update(&state.pool, current.id, request).await {
+        Ok(policy) => {
+            info!("Updated notification policy for account {}", current.id);
+            policy_response(&state, policy).await
+        }
+        Err(e) => notifications_error_response(e),
+    }
+}
+
+/// Renders notification requests with their sender and latest status
+async fn requests_json(
+    state: &AppState,
+    account_id: i64,
+    requests: &[NotificationRequest],
+) -> Result<Vec<Value>, Response> {
+    let mut last_status_ids = HashMap::new();
+    for request in requests {
+        match Notification::get_by_id(&state.pool, request.last_notification_id).await {
+            Ok(notification) => {
+                if let Some(status_id) = notification.status_id {
+                    last_status_ids.insert(request.id, status_id);
+                }
+            }
+            Err(NotificationsError::NotificationNotFound(_)) => {}
+            Err(e) => return Err(notifications_error_response(e)),
+        }
+    }
+
+    let references = References::load(
+        state,
+        account_id,
+        requests.iter().map(|r| r.from_account_id),
+        last_status_ids.values().copied(),
+    )
+    .await?;
+    let local_domain = &state.config.local_domain;
+
+    Ok(requests
+        .iter()
+        .filter_map(|request| {
+            let account = references.account(request.from_account_id, local_domain)?;
+            let last_status = last_status_ids
+                .get(&request.id)
+                .and_then(|id| references.status(*id, local_domain));
+
+            Some(json!({
+                "id": request.id.to_string(),
+                "created_at": format_time(request.created_at),
+                "updated_at": format_time(request.updated_at),
+                "account": account,
+                "notifications_count": request.notifications_count.to_string(),
+                "last_status": last_status
+            }))
+        })
+        .collect())
+}
+
+/// List notification requests handler
+async fn list_requests_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Query(query): Query<NotificationRequestsQuery>,
+) -> Response {
+    debug!("Listing notification requests for account {}", current.id);
+
+    let requests = match NotificationRequest::get_by_account(
+        &state.pool,
+        current.id,
+        query.limit,
+        query.since_id,
+        query.max_id,
+    )
+    .await
+    {
+        Ok(requests) => requests,
+        Err(e) => return notifications_error_response(e),
+    };
+
+    match requests_json(&state, current.id, &requests).await {
+        Ok(rendered) => success(rendered.into()),
+        Err(response) => response,
+    }
+}
+
+/// Get notification request handler
+async fn get_request_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(request_id): Path<i64>,
+) -> Response {
+    let request = match NotificationRequest::get_owned(&state.pool, request_id, current.id).await {
+        Ok(request) => request,
+        Err(e) => return notifications_error_response(e),
+    };
+
+    match requests_json(&state, current.id, &[request]).await {
+        Ok(mut rendered) if !rendered.is_empty() => success(rendered.remove(0)),
+        Ok(_) => error_response(StatusCode::NOT_FOUND, "Record not found"),
+        Err(response) => response,
+    }
+}
+
+/// Accept notification request handler
+async fn accept_request_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(request_id): Path<i64>,
+) -> Response {
+    match NotificationRequest::accept(&state.pool, request_id, current.id).await {
+        Ok(_) => success(json!({})),
+        Err(e) => notifications_error_response(e),
+    }
+}
+
+/// Dismiss notification request handler
+async fn dismiss_request_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(request_id): Path<i64>,
+) -> Response {
+    match NotificationRequest::dismiss(&state.pool, request_id, current.id).await {
+        Ok(_) => success(json!({})),
+        Err(e) => notifications_error_response(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_list_params() {
+        let params = ListParams::parse(vec![
+            ("limit".to_string(), "10".to_string()),
+            ("min_id".to_string(), "5".to_string()),
+            ("types[]".to_string(), "mention".to_string()),
+            ("exclude_types[]".to_string(), "follow".to_string()),
+            ("exclude_types[]".to_string(), "favourite".to_string()),
+        ])
+        .unwrap();
+
+        assert_eq!(params.limit, Some(10));
+        assert_eq!(params.since_id, Some(5));
+        assert_eq!(params.types, vec![NotificationType::Mention]);
+        assert_eq!(params.exclude_types.len(), 2);
+
+        assert!(ListParams::parse(vec![("types[]".to_string(), "boost".to_string())]).is_err());
+        assert!(ListParams::parse(vec![("max_id".to_string(), "x".to_string())]).is_err());
+    }
+
+    #[test]
+    fn test_group_json() {
+        let json = group_json(&NotificationGroup {
+            group_key: "favourite-12".to_string(),
+            notification_type: NotificationType::Favourite,
+            notifications_count: 50,
+            most_recent_notification_id: 300,
+            page_min_id: 100,
+            page_max_id: 300,
+            latest_page_notification_at: Utc::now(),
+            sample_account_ids: vec![4, 5],
+            status_id: Some(12),
+        });
+
+        assert_eq!(json["type"], "favourite");
+        assert_eq!(json["notifications_count"], 50);
+        assert_eq!(json["sample_account_ids"], json!(["4", "5"]));
+        assert_eq!(json["status_id"], "12");
+    }
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+}
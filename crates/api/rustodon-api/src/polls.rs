@@ -0,0 +1,331 @@
+//! Poll endpoints
+//!
+//! Shows polls and records votes on them. Polls are only reachable by
+//! accounts that can see the status they are attached to, which includes
+//! the accounts it mentions. Statuses
+//! rendered elsewhere get their poll through [`StatusPolls`].
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::extractors::CurrentUser;
+use crate::serializers::{error_response, success};
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Response,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use rustodon_follows::Follow;
+use rustodon_mentions::Mention;
+use rustodon_polls::{Poll, PollsError};
+use rustodon_statuses::{Status, StatusesError, Visibility};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tracing::{debug, error};
+
+/// Choices of a vote, as option indexes
+///
+/// Clients send indexes either as numbers or as strings.
+#[derive(Debug, Deserialize)]
+pub struct VoteRequest {
+    pub choices: Vec<Value>,
+}
+
+/// Routes served by this module
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/polls/:id", get(get_poll_handler))
+        .route("/api/v1/polls/:id/votes", post(vote_handler))
+}
+
+/// Polls attached to a set of statuses, with the viewer's votes
+#[derive(Debug, Default)]
+pub(crate) struct StatusPolls {
+    polls: HashMap<i64, Poll>,
+    own_votes: HashMap<i64, Vec<usize>>,
+    viewer_id: Option<i64>,
+}
+
+impl StatusPolls {
+    /// Loads the polls of the given statuses
+    pub(crate) async fn load(
+        state: &AppState,
+        status_ids: &[i64],
+        viewer_id: Option<i64>,
+    ) -> Result<Self, PollsError> {
+        let polls = Poll::get_by_status_ids(&state.pool, status_ids).await?;
+        let own_votes = match viewer_id {
+            Some(viewer_id) if !polls.is_empty() => {
+                let poll_ids: Vec<i64> = polls.values().map(|poll| poll.id).collect();
+                Poll::own_votes(&state.pool, &poll_ids, viewer_id).await?
+            }
+            _ => HashMap::new(),
+        };
+
+        Ok(Self {
+            polls,
+            own_votes,
+            viewer_id,
+        })
+    }
+
+    /// Fills in the poll of a rendered status
+    pub(crate) fn attach(&self, status_id: i64, status: &mut Value) {
+        if let Some(poll) = self.polls.get(&status_id) {
+            let own_votes = self.viewer_id.map(|_| {
+                self.own_votes
+                    .get(&poll.id)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default()
+            });
+            status["poll"] = poll_json(poll, own_votes, self.viewer_id, Utc::now());
+        }
+    }
+}
+
+/// Renders a poll entity
+///
+/// Per-option counts of a poll with `hide_totals` are hidden from everyone
+/// but its author until the poll closes.
+///
+/// # Arguments
+///
+/// * `poll` - Poll to render
+/// * `own_votes` - The viewer's choices, or None without a viewer
+/// * `viewer_id` - ID of the viewing account
+/// * `now` - Current time
+pub(crate) fn poll_json(
+    poll: &Poll,
+    own_votes: Option<&[usize]>,
+    viewer_id: Option<i64>,
+    now: DateTime<Utc>,
+) -> Value {
+    let expired = poll.expired(now);
+    let hide_counts = poll.hide_totals && !expired && viewer_id != Some(poll.account_id);
+
+    let mut json = json!({
+        "id": poll.id.to_string(),
+        "expires_at": poll
+            .expires_at
+            .map(|at| at.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()),
+        "expired": expired,
+        "multiple": poll.multiple,
+        "votes_count": poll.votes_count,
+        "voters_count": poll.voters_count,
+        "options": poll
+            .options
+            .iter()
+            .map(|option| json!({
+                "title": option.title,
+                "votes_count": if hide_counts { Value::Null } else { json!(option.votes_count) }
+            }))
+            .collect::<Vec<_>>(),
+        "emojis": []
+    });
+    if let Some(own_votes) = own_votes {
+        json["voted"] = json!(!own_votes.is_empty());
+        json["own_votes"] = json!(own_votes);
+    }
+    json
+}
+
+/// Maps a polls error to an API error response
+fn polls_error_response(e: PollsError) -> Response {
+    match e {
+        PollsError::PollNotFound(_) => error_response(StatusCode::NOT_FOUND, "Record not found"),
+        PollsError::Validation(_) | PollsError::PollExpired | PollsError::AlreadyVoted => {
+            error_response(StatusCode::UNPROCESSABLE_ENTITY, e.to_string())
+        }
+        PollsError::Database(_) | PollsError::Internal(_) => {
+            error!("Poll operation failed: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+/// Parses vote choices sent as numbers or numeric strings
+fn parse_choices(choices: &[Value]) -> Result<Vec<usize>, PollsError> {
+    choices
+        .iter()
+        .map(|choice| {
+            choice
+                .as_u64()
+                .map(|index| index as usize)
+                .or_else(|| choice.as_str().and_then(|s| s.parse().ok()))
+                .ok_or_else(|| PollsError::Validation("Invalid choice".to_string()))
+        })
+        .collect()
+}
+
+/// Loads a poll, hiding it from accounts that can't see its status
+async fn visible_poll(state: &AppState, poll_id: i64, viewer_id: i64) -> Result<Poll, PollsError> {
+    let poll = Poll::get_by_id(&state.pool, poll_id).await?;
+    let status = match Status::get_by_id(&state.pool, poll.status_id).await {
+        Ok(status) => status,
+        Err(StatusesError::StatusNotFound(_)) => return Err(PollsError::PollNotFound(poll_id)),
+        Err(e) => return Err(PollsError::Internal(e.to_string())),
+    };
+
+    let visible = match status.visibility {
+        Visibility::Public | Visibility::Unlisted => true,
+        _ if status.account_id == viewer_id => true,
+        visibility => {
+            let mentioned = Mention::exists(&state.pool, status.id, viewer_id)
+                .await
+                .map_err(|e| PollsError::Internal(e.to_string()))?;
+            mentioned
+                || (visibility == Visibility::Private
+                    && Follow::exists(&state.pool, viewer_id, status.account_id)
+                        .await
+                        .map_err(|e| PollsError::Internal(e.to_string()))?)
+        }
+    };
+    if !visible {
+        return Err(PollsError::PollNotFound(poll_id));
+    }
+
+    Ok(poll)
+}
+
+/// Get poll handler
+async fn get_poll_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(poll_id): Path<i64>,
+) -> Response {
+    debug!("Getting poll {} for account {}", poll_id, current.id);
+
+    let poll = match visible_poll(&state, poll_id, current.id).await {
+        Ok(poll) => poll,
+        Err(e) => return polls_error_response(e),
+    };
+    match Poll::own_votes(&state.pool, &[poll.id], current.id).await {
+        Ok(own_votes) => success(poll_json(
+            &poll,
+            Some(
+                own_votes
+                    .get(&poll.id)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default(),
+            ),
+            Some(current.id),
+            Utc::now(),
+        )),
+        Err(e) => polls_error_response(e),
+    }
+}
+
+/// Vote handler
+async fn vote_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(poll_id): Path<i64>,
+    Json(request): Json<VoteRequest>,
+) -> Response {
+    debug!("Account {} voting on poll {}", current.id, poll_id);
+
+    let mut choices = match parse_choices(&request.choices) {
+        Ok(choices) => choices,
+        Err(e) => return polls_error_response(e),
+    };
+    if let Err(e) = visible_poll(&state, poll_id, current.id).await {
+        return polls_error_response(e);
+    }
+
+    match Poll::vote(&state.pool, poll_id, current.id, &choices).await {
+        Ok(poll) => {
+            choices.sort_unstable();
+            success(poll_json(
+                &poll,
+                Some(&choices),
+                Some(current.id),
+                Utc::now(),
+            ))
+        }
+        Err(e) => polls_error_response(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use rustodon_polls::PollOption;
+
+    fn poll(hide_totals: bool, expires_at: DateTime<Utc>) -> Poll {
+        Poll {
+            id: 7,
+            account_id: 1,
+            status_id: 3,
+            options: vec![
+                PollOption {
+                    title: "Tea".to_string(),
+                    votes_count: 2,
+                },
+                PollOption {
+                    title: "Coffee".to_string(),
+                    votes_count: 1,
+                },
+            ],
+            multiple: false,
+            hide_totals,
+            expires_at: Some(expires_at),
+            votes_count: 3,
+            voters_count: 3,
+            created_at: expires_at,
+            updated_at: expires_at,
+        }
+    }
+
+    #[test]
+    fn test_poll_json() {
+        let now = Utc::now();
+        let open = poll(false, now + Duration::hours(1));
+
+        let json = poll_json(&open, Some(&[1]), Some(2), now);
+        assert_eq!(json["id"], "7");
+        assert_eq!(json["expired"], false);
+        assert_eq!(json["options"][0]["votes_count"], 2);
+        assert_eq!(json["voted"], true);
+        assert_eq!(json["own_votes"], json!([1]));
+
+        let anonymous = poll_json(&open, None, None, now);
+        assert!(anonymous.get("voted").is_none());
+    }
+
+    #[test]
+    fn test_poll_json_hide_totals() {
+        let now = Utc::now();
+        let hidden = poll(true, now + Duration::hours(1));
+
+        assert!(poll_json(&hidden, Some(&[]), Some(2), now)["options"][0]["votes_count"].is_null());
+        assert_eq!(
+            poll_json(&hidden, Some(&[]), Some(1), now)["options"][0]["votes_count"],
+            2
+        );
+
+        let closed = poll(true, now - Duration::hours(1));
+        let json = poll_json(&closed, Some(&[]), Some(2), now);
+        assert_eq!(json["expired"], true);
+        assert_eq!(json["options"][0]["votes_count"], 2);
+        assert_eq!(json["voted"], false);
+    }
+
+    #[test]
+    fn test_parse_choices() {
+        assert_eq!(parse_choices(&[json!(0), json!("2")]).unwrap(), vec![0, 2]);
+        assert!(parse_choices(&[json!("first")]).is_err());
+        assert!(parse_choices(&[json!(-1)]).is_err());
+    }
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _ = routes();
+    }
+}
@@ -0,0 +1,121 @@
+//! Link preview cards of statuses
+//!
+//! New statuses get their preview card fetched in the background; statuses
+//! rendered by the API pick up their card through [`StatusCards`].
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::AppState;
+use rustodon_preview_cards::{FetchPreviewCardJob, PreviewCard, PreviewCardsError};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// Preview cards attached to a set of statuses
+#[derive(Debug, Default)]
+pub(crate) struct StatusCards {
+    cards: HashMap<i64, PreviewCard>,
+}
+
+impl StatusCards {
+    /// Loads the cards of the given statuses
+    pub(crate) async fn load(
+        state: &AppState,
+        status_ids: &[i64],
+    ) -> Result<Self, PreviewCardsError> {
+        Ok(Self {
+            cards: PreviewCard::get_by_status_ids(&state.pool, status_ids).await?,
+        })
+    }
+
+    /// Fills in the card of a rendered status
+    pub(crate) fn attach(&self, status_id: i64, status: &mut Value) {
+        if let Some(card) = self.cards.get(&status_id) {
+            status["card"] = preview_card_json(card);
+        }
+    }
+}
+
+/// Renders a preview card entity
+pub(crate) fn preview_card_json(card: &PreviewCard) -> Value {
+    json!({
+        "url": card.url,
+        "title": card.title,
+        "description": card.description,
+        "type": card.card_type,
+        "author_name": card.author_name,
+        "author_url": card.author_url,
+        "provider_name": card.provider_name,
+        "provider_url": card.provider_url,
+        "html": card.html,
+        "width": card.width,
+        "height": card.height,
+        "image": card.image_url,
+        "image_description": "",
+        "embed_url": card.embed_url,
+        "blurhash": card.blurhash,
+        "language": card.language,
+        "published_at": null
+    })
+}
+
+/// Queues fetching the preview card of a new status
+///
+/// # Arguments
+///
+/// * `state` - Application state
+/// * `status_id` - ID of the new status
+pub(crate) async fn fetch_preview_card_later(state: &AppState, status_id: i64) {
+    let job = FetchPreviewCardJob::new(
+        state.pool.clone(),
+        state.preview_card_fetcher.clone(),
+        state.storage.clone(),
+        status_id,
+    );
+    state.jobs.lock().await.push(Box::new(job));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_preview_card_json() {
+        let now = Utc::now();
+        let card = PreviewCard {
+            id: 1,
+            url: "https://example.com/post".to_string(),
+            title: "A post".to_string(),
+            description: "About things".to_string(),
+            card_type: "link".to_string(),
+            author_name: String::new(),
+            author_url: String::new(),
+            provider_name: "Example".to_string(),
+            provider_url: String::new(),
+            html: String::new(),
+            width: 640,
+            height: 336,
+            image_url: Some("https://rustodon.local/media/preview_cards/a.jpg".to_string()),
+            blurhash: Some("LEHV6nWB2yk8pyo0adR*.7kCMdnj".to_string()),
+            embed_url: String::new(),
+            language: Some("en".to_string()),
+            created_at: now,
+            updated_at: now,
+        };
+
+        let mut status = json!({ "id": "3", "card": null });
+        StatusCards {
+            cards: HashMap::from([(3, card)]),
+        }
+        .attach(3, &mut status);
+
+        assert_eq!(status["card"]["type"], "link");
+        assert_eq!(status["card"]["width"], 640);
+        assert_eq!(
+            status["card"]["image"],
+            "https://rustodon.local/media/preview_cards/a.jpg"
+        );
+    }
+}
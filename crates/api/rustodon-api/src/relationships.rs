@@ -0,0 +1,221 @@
+//! Relationships and private account notes
+//!
+//! Serves `/api/v1/accounts/relationships`, which reports how the current
+//! account relates to others including endorsements and private notes, and
+//! `/api/v1/accounts/:id/note` for writing those notes.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::extractors::CurrentUser;
+use crate::serializers::{error_response, relationship_json, success, Relationship};
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Response,
+    routing::{get, post},
+    Json, Router,
+};
+use rustodon_account_notes::{AccountNote, AccountNotesError};
+use rustodon_account_pins::AccountPin;
+use rustodon_db::User;
+use rustodon_follow_requests::FollowRequest;
+use rustodon_follows::Follow;
+use serde::Deserialize;
+use tracing::{debug, error};
+
+/// Maximum number of accounts per relationships request
+const MAX_RELATIONSHIPS: usize = 40;
+
+/// Routes of the relationships API
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/accounts/relationships", get(relationships_handler))
+        .route("/api/v1/accounts/:id/note", post(note_handler))
+}
+
+/// Maps account note errors to API responses
+fn account_notes_error_response(e: AccountNotesError) -> Response {
+    match e {
+        AccountNotesError::Validation(message) => {
+            error_response(StatusCode::UNPROCESSABLE_ENTITY, message)
+        }
+        e => {
+            error!("Account note operation failed: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+fn internal_error(e: impl std::fmt::Display) -> Response {
+    error!("Failed to load relationship data: {}", e);
+    error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+}
+
+/// Loads the relationships of the viewer with the given accounts
+///
+/// # Arguments
+///
+/// * `state` - Application state
+/// * `viewer_id` - ID of the current account
+/// * `account_ids` - IDs of the other accounts
+///
+/// # Returns
+///
+/// Relationships, in the order of `account_ids`
+pub(crate) async fn load_relationships(
+    state: &AppState,
+    viewer_id: i64,
+    account_ids: &[i64],
+) -> Result<Vec<Relationship>, Response> {
+    let endorsed = AccountPin::endorsed_ids(&state.pool, viewer_id, account_ids)
+        .await
+        .map_err(internal_error)?;
+    let mut notes = AccountNote::get_comments(&state.pool, viewer_id, account_ids)
+        .await
+        .map_err(internal_error)?;
+
+    let mut relationships = Vec::with_capacity(account_ids.len());
+    for &account_id in account_ids {
+        let following = Follow::exists(&state.pool, viewer_id, account_id)
+            .await
+            .map_err(internal_error)?;
+        let followed_by = Follow::exists(&state.pool, account_id, viewer_id)
+            .await
+            .map_err(internal_error)?;
+        let requested = !following
+            && FollowRequest::exists(&state.pool, viewer_id, account_id)
+                .await
+                .map_err(internal_error)?;
+
+        relationships.push(Relationship {
+            account_id,
+            following,
+            requested,
+            followed_by,
+            endorsed: endorsed.contains(&account_id),
+            note: notes.remove(&account_id).unwrap_or_default(),
+        });
+    }
+    Ok(relationships)
+}
+
+/// Loads the relationship of the viewer with one account, rendered
+pub(crate) async fn relationship_response(
+    state: &AppState,
+    viewer_id: i64,
+    account_id: i64,
+) -> Response {
+    match load_relationships(state, viewer_id, &[account_id]).await {
+        Ok(relationships) => success(relationship_json(&relationships[0])),
+        Err(response) => response,
+    }
+}
+
+/// Gets an account, answering 404 if it does not exist
+pub(crate) async fn find_account(state: &AppState, account_id: i64) -> Result<User, Response> {
+    match User::get_by_id(&state.pool, account_id).await {
+        Ok(Some(account)) => Ok(account),
+        Ok(None) => Err(error_response(StatusCode::NOT_FOUND, "Record not found")),
+        Err(e) => Err(internal_error(e)),
+    }
+}
+
+/// Parses the `id[]` parameters of a relationships request
+fn parse_account_ids(pairs: Vec<(String, String)>) -> Result<Vec<i64>, String> {
+    let mut ids = Vec::new();
+    for (key, value) in pairs {
+        if key.trim_end_matches("[]") != "id" {
+            continue;
+        }
+        let id = value
+            .parse::<i64>()
+            .map_err(|_| format!("Invalid id: {}", value))?;
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    ids.truncate(MAX_RELATIONSHIPS);
+    Ok(ids)
+}
+
+/// Relationships handler
+async fn relationships_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Query(pairs): Query<Vec<(String, String)>>,
+) -> Response {
+    debug!("Getting relationships of account {}", current.id);
+
+    let account_ids = match parse_account_ids(pairs) {
+        Ok(ids) => ids,
+        Err(message) => return error_response(StatusCode::UNPROCESSABLE_ENTITY, message),
+    };
+
+    match load_relationships(&state, current.id, &account_ids).await {
+        Ok(relationships) => success(relationships.iter().map(relationship_json).collect()),
+        Err(response) => response,
+    }
+}
+
+/// Request body for setting a note
+#[derive(Debug, Deserialize)]
+struct NoteRequest {
+    #[serde(default)]
+    comment: String,
+}
+
+/// Set account note handler
+async fn note_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(account_id): Path<i64>,
+    Json(request): Json<NoteRequest>,
+) -> Response {
+    debug!(
+        "Setting note of account {} on account {}",
+        current.id, account_id
+    );
+
+    if let Err(response) = find_account(&state, account_id).await {
+        return response;
+    }
+    if let Err(e) = AccountNote::set(&state.pool, current.id, account_id, &request.comment).await {
+        return account_notes_error_response(e);
+    }
+
+    relationship_response(&state, current.id, account_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(query: &[(&str, &str)]) -> Vec<(String, String)> {
+        query
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_account_ids() {
+        let ids = parse_account_ids(pairs(&[
+            ("id[]", "3"),
+            ("id[]", "1"),
+            ("id[]", "3"),
+            ("with_suspended", "true"),
+        ]))
+        .unwrap();
+        assert_eq!(ids, vec![3, 1]);
+        assert_eq!(parse_account_ids(pairs(&[("id", "5")])).unwrap(), vec![5]);
+        assert!(parse_account_ids(pairs(&[("id[]", "x")])).is_err());
+    }
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+}
@@ -0,0 +1,566 @@
+//! Report endpoints
+//!
+//! Users file reports against accounts on `/api/v1/reports`, and staff are
+//! notified of them. Staff triage the queue on `/api/v1/admin/reports`:
+//! they assign reports to themselves, recategorize them, resolve or reopen
+//! them, and discuss them in notes. Every triage action lands in the admin
+//! action log.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::admin_accounts::{account_label, begin_action, commit_action};
+use crate::extractors::{CurrentUser, StaffUser};
+use crate::serializers::{account_json, error_response, success};
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Response,
+    routing::{delete, get, post},
+    Json, Router,
+};
+use rustodon_admin::{diff, AdminActionType, NewAdminAction};
+use rustodon_db::User;
+use rustodon_reports::{
+    NewReport, Report, ReportCategory, ReportFilter, ReportNote, ReportsError, UpdateReportRequest,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::{Postgres, Transaction};
+use tracing::{error, info};
+
+/// Parameters of a new report
+#[derive(Debug, Deserialize)]
+pub struct ReportParams {
+    pub account_id: String,
+    pub status_ids: Option<Vec<String>>,
+    pub comment: Option<String>,
+    pub category: Option<String>,
+    pub rule_ids: Option<Vec<String>>,
+}
+
+/// Parameters of a report update
+#[derive(Debug, Deserialize)]
+pub struct UpdateReportParams {
+    pub category: Option<String>,
+    pub rule_ids: Option<Vec<String>>,
+}
+
+/// Parameters of a report note
+#[derive(Debug, Deserialize)]
+pub struct ReportNoteParams {
+    pub content: String,
+}
+
+/// Query parameters of the report queue
+#[derive(Debug, Default, Deserialize)]
+pub struct AdminReportsQuery {
+    pub resolved: Option<bool>,
+    pub account_id: Option<i64>,
+    pub target_account_id: Option<i64>,
+    pub assigned_account_id: Option<i64>,
+    pub category: Option<String>,
+    pub max_id: Option<i64>,
+    pub since_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+impl AdminReportsQuery {
+    /// Turns the query into a report filter
+    fn filter(self) -> Result<ReportFilter, String> {
+        Ok(ReportFilter {
+            resolved: self.resolved.unwrap_or(false),
+            account_id: self.account_id,
+            target_account_id: self.target_account_id,
+            assigned_account_id: self.assigned_account_id,
+            category: self.category.as_deref().map(parse_category).transpose()?,
+            max_id: self.max_id,
+            since_id: self.since_id,
+            limit: self.limit,
+        })
+    }
+}
+
+/// Routes of the reports API
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/reports", post(create_report_handler))
+        .route("/api/v1/admin/reports", get(list_reports_handler))
+        .route(
+            "/api/v1/admin/reports/:id",
+            get(get_report_handler).put(update_report_handler),
+        )
+        .route(
+            "/api/v1/admin/reports/:id/assign_to_self",
+            post(assign_to_self_handler),
+        )
+        .route("/api/v1/admin/reports/:id/unassign", post(unassign_handler))
+        .route("/api/v1/admin/reports/:id/resolve", post(resolve_handler))
+        .route("/api/v1/admin/reports/:id/reopen", post(reopen_handler))
+        .route(
+            "/api/v1/admin/reports/:id/notes",
+            get(list_notes_handler).post(create_note_handler),
+        )
+        .route(
+            "/api/v1/admin/reports/:id/notes/:note_id",
+            delete(delete_note_handler),
+        )
+}
+
+/// Maps report errors to API responses
+fn reports_error_response(e: ReportsError) -> Response {
+    match e {
+        ReportsError::NotFound(_)
+        | ReportsError::NoteNotFound(_)
+        | ReportsError::AccountNotFound(_)
+        | ReportsError::StatusNotFound(_)
+        | ReportsError::RuleNotFound(_) => {
+            error_response(StatusCode::NOT_FOUND, "Record not found")
+        }
+        ReportsError::Validation(message) => {
+            error_response(StatusCode::UNPROCESSABLE_ENTITY, message)
+        }
+        e => {
+            error!("Report operation failed: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+/// Parses a report category
+fn parse_category(value: &str) -> Result<ReportCategory, String> {
+    ReportCategory::parse(value).ok_or_else(|| format!("Invalid category: {}", value))
+}
+
+/// Parses IDs given as strings
+fn parse_ids(kind: &str, ids: &[String]) -> Result<Vec<i64>, String> {
+    ids.iter()
+        .map(|id| {
+            id.parse()
+                .map_err(|_| format!("Invalid {} ID: {}", kind, id))
+        })
+        .collect()
+}
+
+/// Renders IDs as strings
+fn id_strings(ids: &[i64]) -> Vec<String> {
+    ids.iter().map(|id| id.to_string()).collect()
+}
+
+fn format_time(time: chrono::DateTime<chrono::Utc>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+/// Renders a report as its reporter sees it
+pub(crate) fn report_json(report: &Report, target_account: Value) -> Value {
+    json!({
+        "id": report.id.to_string(),
+        "action_taken": report.action_taken(),
+        "action_taken_at": report.action_taken_at.map(format_time),
+        "category": report.category.as_str(),
+        "comment": report.comment,
+        "forwarded": false,
+        "created_at": format_time(report.created_at),
+        "status_ids": id_strings(&report.status_ids),
+        "rule_ids": id_strings(&report.rule_ids),
+        "target_account": target_account,
+    })
+}
+
+/// Renders a report as staff see it
+fn admin_report_json(report: &Report) -> Value {
+    json!({
+        "id": report.id.to_string(),
+        "action_taken": report.action_taken(),
+        "action_taken_at": report.action_taken_at.map(format_time),
+        "category": report.category.as_str(),
+        "comment": report.comment,
+        "forwarded": false,
+        "created_at": format_time(report.created_at),
+        "updated_at": format_time(report.updated_at),
+        "account_id": report.account_id.to_string(),
+        "target_account_id": report.target_account_id.to_string(),
+        "assigned_account_id": report.assigned_account_id.map(|id| id.to_string()),
+        "action_taken_by_account_id": report.action_taken_by_account_id.map(|id| id.to_string()),
+        "status_ids": id_strings(&report.status_ids),
+        "rule_ids": id_strings(&report.rule_ids),
+    })
+}
+
+/// Renders a report note
+fn note_json(note: &ReportNote) -> Value {
+    json!({
+        "id": note.id.to_string(),
+        "report_id": note.report_id.to_string(),
+        "account_id": note.account_id.map(|id| id.to_string()),
+        "content": note.content,
+        "created_at": format_time(note.created_at),
+    })
+}
+
+/// Triage state of a report for the action log
+fn triage_state(report: &Report) -> Value {
+    json!({
+        "category": report.category.as_str(),
+        "rule_ids": report.rule_ids,
+        "assigned_account_id": report.assigned_account_id,
+        "action_taken": report.action_taken(),
+    })
+}
+
+/// Records a triage action on a report and commits it
+async fn log_triage(
+    state: &AppState,
+    tx: Transaction<'static, Postgres>,
+    moderator_id: i64,
+    action: &str,
+    before: &Report,
+    after: &Report,
+) -> Result<(), Response> {
+    commit_action(
+        tx,
+        NewAdminAction {
+            account_id: moderator_id,
+            action_type: AdminActionType::Update,
+            action,
+            target_type: "Report",
+            target_id: Some(after.id),
+            target: &account_label(state, after.target_account_id).await,
+            changes: diff(&triage_state(before), &triage_state(after)),
+        },
+    )
+    .await
+}
+
+/// Create report handler
+async fn create_report_handler(
+    State(state): State<AppState>,
+    CurrentUser(user): CurrentUser,
+    Json(params): Json<ReportParams>,
+) -> Response {
+    let parsed = params
+        .account_id
+        .parse::<i64>()
+        .map_err(|_| format!("Invalid account ID: {}", params.account_id))
+        .and_then(|target_account_id| {
+            Ok(NewReport {
+                target_account_id,
+                status_ids: parse_ids("status", params.status_ids.as_deref().unwrap_or_default())?,
+                rule_ids: parse_ids("rule", params.rule_ids.as_deref().unwrap_or_default())?,
+                comment: params.comment.unwrap_or_default(),
+                category: params.category.as_deref().map(parse_category).transpose()?,
+            })
+        });
+    let new_report = match parsed {
+        Ok(new_report) => new_report,
+        Err(message) => return error_response(StatusCode::UNPROCESSABLE_ENTITY, message),
+    };
+
+    let report = match Report::create(&state.pool, user.id, new_report).await {
+        Ok(report) => report,
+        Err(e) => return reports_error_response(e),
+    };
+    info!(
+        "{} reported account {}",
+        user.username, report.target_account_id
+    );
+    report.notify_staff(&state.pool).await;
+
+    let target_account = match User::get_by_id(&state.pool, report.target_account_id).await {
+        Ok(Some(target)) => account_json(&target, &state.config.local_domain),
+        _ => Value::Null,
+    };
+    success(report_json(&report, target_account))
+}
+
+/// List reports handler
+async fn list_reports_handler(
+    State(state): State<AppState>,
+    StaffUser(_moderator): StaffUser,
+    Query(query): Query<AdminReportsQuery>,
+) -> Response {
+    let filter = match query.filter() {
+        Ok(filter) => filter,
+        Err(message) => return error_response(StatusCode::UNPROCESSABLE_ENTITY, message),
+    };
+
+    match Report::search(&state.pool, &filter).await {
+        Ok(reports) => success(reports.iter().map(admin_report_json).collect()),
+        Err(e) => reports_error_response(e),
+    }
+}
+
+/// Get report handler
+async fn get_report_handler(
+    State(state): State<AppState>,
+    StaffUser(_moderator): StaffUser,
+    Path(id): Path<i64>,
+) -> Response {
+    match Report::get(&state.pool, id).await {
+        Ok(report) => success(admin_report_json(&report)),
+        Err(e) => reports_error_response(e),
+    }
+}
+
+/// Update report handler
+async fn update_report_handler(
+    State(state): State<AppState>,
+    StaffUser(moderator): StaffUser,
+    Path(id): Path<i64>,
+    Json(params): Json<UpdateReportParams>,
+) -> Response {
+    let parsed = params
+        .category
+        .as_deref()
+        .map(parse_category)
+        .transpose()
+        .and_then(|category| {
+            Ok(UpdateReportRequest {
+                category,
+                rule_ids: params
+                    .rule_ids
+                    .as_deref()
+                    .map(|ids| parse_ids("rule", ids))
+                    .transpose()?,
+            })
+        });
+    let request = match parsed {
+        Ok(request) => request,
+        Err(message) => return error_response(StatusCode::UNPROCESSABLE_ENTITY, message),
+    };
+
+    let mut tx = match begin_action(&state).await {
+        Ok(tx) => tx,
+        Err(response) => return response,
+    };
+    let before = match Report::get(&mut *tx, id).await {
+        Ok(report) => report,
+        Err(e) => return reports_error_response(e),
+    };
+    match Report::update(&mut tx, id, request).await {
+        Ok(report) => {
+            if let Err(response) =
+                log_triage(&state, tx, moderator.id, "update", &before, &report).await
+            {
+                return response;
+            }
+            success(admin_report_json(&report))
+        }
+        Err(e) => reports_error_response(e),
+    }
+}
+
+/// Triage action on a report
+#[derive(Debug, Clone, Copy)]
+enum Triage {
+    AssignToSelf,
+    Unassign,
+    Resolve,
+    Reopen,
+}
+
+impl Triage {
+    /// Name of the action in the action log
+    fn as_str(self) -> &'static str {
+        match self {
+            Triage::AssignToSelf => "assign_to_self",
+            Triage::Unassign => "unassign",
+            Triage::Resolve => "resolve",
+            Triage::Reopen => "reopen",
+        }
+    }
+}
+
+/// Performs a triage action on a report and records it
+async fn triage(state: &AppState, moderator: &User, id: i64, triage: Triage) -> Response {
+    let mut tx = match begin_action(state).await {
+        Ok(tx) => tx,
+        Err(response) => return response,
+    };
+    let before = match Report::get(&mut *tx, id).await {
+        Ok(report) => report,
+        Err(e) => return reports_error_response(e),
+    };
+    let result = match triage {
+        Triage::AssignToSelf => Report::assign(&mut tx, id, Some(moderator.id)).await,
+        Triage::Unassign => Report::assign(&mut tx, id, None).await,
+        Triage::Resolve => Report::resolve(&mut tx, id, moderator.id).await,
+        Triage::Reopen => Report::reopen(&mut tx, id).await,
+    };
+    match result {
+        Ok(report) => {
+            let action = triage.as_str();
+            if let Err(response) =
+                log_triage(state, tx, moderator.id, action, &before, &report).await
+            {
+                return response;
+            }
+            info!(
+                "{} performed {} on report {}",
+                moderator.username, action, id
+            );
+            success(admin_report_json(&report))
+        }
+        Err(e) => reports_error_response(e),
+    }
+}
+
+/// Assign to self handler
+async fn assign_to_self_handler(
+    State(state): State<AppState>,
+    StaffUser(moderator): StaffUser,
+    Path(id): Path<i64>,
+) -> Response {
+    triage(&state, &moderator, id, Triage::AssignToSelf).await
+}
+
+/// Unassign handler
+async fn unassign_handler(
+    State(state): State<AppState>,
+    StaffUser(moderator): StaffUser,
+    Path(id): Path<i64>,
+) -> Response {
+    triage(&state, &moderator, id, Triage::Unassign).await
+}
+
+/// Resolve handler
+async fn resolve_handler(
+    State(state): State<AppState>,
+    StaffUser(moderator): StaffUser,
+    Path(id): Path<i64>,
+) -> Response {
+    triage(&state, &moderator, id, Triage::Resolve).await
+}
+
+/// Reopen handler
+async fn reopen_handler(
+    State(state): State<AppState>,
+    StaffUser(moderator): StaffUser,
+    Path(id): Path<i64>,
+) -> Response {
+    triage(&state, &moderator, id, Triage::Reopen).await
+}
+
+/// List notes handler
+async fn list_notes_handler(
+    State(state): State<AppState>,
+    StaffUser(_moderator): StaffUser,
+    Path(id): Path<i64>,
+) -> Response {
+    match ReportNote::by_report(&state.pool, id).await {
+        Ok(notes) => success(notes.iter().map(note_json).collect()),
+        Err(e) => reports_error_response(e),
+    }
+}
+
+/// Create note handler
+async fn create_note_handler(
+    State(state): State<AppState>,
+    StaffUser(moderator): StaffUser,
+    Path(id): Path<i64>,
+    Json(params): Json<ReportNoteParams>,
+) -> Response {
+    let mut tx = match begin_action(&state).await {
+        Ok(tx) => tx,
+        Err(response) => return response,
+    };
+    let note = match ReportNote::create(&mut tx, id, moderator.id, &params.content).await {
+        Ok(note) => note,
+        Err(e) => return reports_error_response(e),
+    };
+
+    if let Err(response) = commit_action(
+        tx,
+        NewAdminAction {
+            account_id: moderator.id,
+            action_type: AdminActionType::Create,
+            action: "create",
+            target_type: "ReportNote",
+            target_id: Some(note.id),
+            target: &format!("report {}", id),
+            changes: diff(&Value::Null, &json!({ "content": note.content })),
+        },
+    )
+    .await
+    {
+        return response;
+    }
+    success(note_json(&note))
+}
+
+/// Delete note handler
+async fn delete_note_handler(
+    State(state): State<AppState>,
+    StaffUser(moderator): StaffUser,
+    Path((id, note_id)): Path<(i64, i64)>,
+) -> Response {
+    let mut tx = match begin_action(&state).await {
+        Ok(tx) => tx,
+        Err(response) => return response,
+    };
+    let note = match ReportNote::delete(&mut tx, id, note_id).await {
+        Ok(note) => note,
+        Err(e) => return reports_error_response(e),
+    };
+
+    if let Err(response) = commit_action(
+        tx,
+        NewAdminAction {
+            account_id: moderator.id,
+            action_type: AdminActionType::Delete,
+            action: "destroy",
+            target_type: "ReportNote",
+            target_id: Some(note_id),
+            target: &format!("report {}", id),
+            changes: diff(&json!({ "content": note.content }), &Value::Null),
+        },
+    )
+    .await
+    {
+        return response;
+    }
+    success(json!({}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+
+    #[test]
+    fn test_query_filter() {
+        let filter = AdminReportsQuery {
+            category: Some("violation".to_string()),
+            ..Default::default()
+        }
+        .filter()
+        .unwrap();
+        assert!(!filter.resolved);
+        assert_eq!(filter.category, Some(ReportCategory::Violation));
+
+        assert!(AdminReportsQuery {
+            category: Some("harassment".to_string()),
+            ..Default::default()
+        }
+        .filter()
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_ids() {
+        assert_eq!(
+            parse_ids("rule", &["1".to_string(), "20".to_string()]).unwrap(),
+            vec![1, 20]
+        );
+        assert_eq!(
+            parse_ids("rule", &["abc".to_string()]).unwrap_err(),
+            "Invalid rule ID: abc"
+        );
+    }
+}
@@ -0,0 +1,322 @@
+//! Scheduled status endpoints
+//!
+//! Lists the statuses an account has scheduled and lets it move them to
+//! another time or cancel them. Statuses are scheduled by passing
+//! `scheduled_at` when creating a status.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::extractors::CurrentUser;
+use crate::serializers::{error_response, success};
+use crate::{AppState, StatusRequest};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Response,
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use rustodon_config::Config;
+use rustodon_scheduled_statuses::{
+    ScheduledStatus, ScheduledStatusError, ScheduledStatusLimits, ScheduledStatusParams,
+    ScheduledStatusService,
+};
+use rustodon_statuses::{NewPoll, Visibility};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, error};
+
+/// Pagination parameters for listing scheduled statuses
+#[derive(Debug, Deserialize)]
+pub struct ScheduledStatusesQuery {
+    pub limit: Option<i64>,
+    pub max_id: Option<i64>,
+    pub since_id: Option<i64>,
+}
+
+/// New publication time for a scheduled status
+#[derive(Debug, Deserialize)]
+pub struct RescheduleRequest {
+    pub scheduled_at: DateTime<Utc>,
+}
+
+/// Routes served by this module
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/api/v1/scheduled_statuses",
+            get(list_scheduled_statuses_handler),
+        )
+        .route(
+            "/api/v1/scheduled_statuses/:id",
+            get(get_scheduled_status_handler)
+                .put(reschedule_handler)
+                .delete(cancel_handler),
+        )
+}
+
+/// Builds the scheduling service with the instance's limits
+pub(crate) fn scheduled_status_service(config: &Config) -> ScheduledStatusService {
+    ScheduledStatusService::with_limits(ScheduledStatusLimits {
+        max_media_attachments: config.instance.max_media_attachments,
+        max_poll_options: config.polls.max_options,
+        max_poll_option_characters: config.polls.max_characters_per_option,
+        min_poll_expiration: config.polls.min_expiration,
+        max_poll_expiration: config.polls.max_expiration,
+        ..ScheduledStatusLimits::default()
+    })
+}
+
+/// Turns a status creation request into publication parameters
+///
+/// # Arguments
+///
+/// * `request` - Status creation request
+/// * `max_characters` - Maximum length of the status text
+pub(crate) fn status_params(
+    request: &StatusRequest,
+    max_characters: usize,
+) -> Result<ScheduledStatusParams, ScheduledStatusError> {
+    if request.status.chars().count() > max_characters {
+        return Err(ScheduledStatusError::Validation(format!(
+            "Text can't be longer than {} characters",
+            max_characters
+        )));
+    }
+
+    let visibility = match request.visibility.as_deref() {
+        Some(visibility) => visibility
+            .parse::<Visibility>()
+            .map_err(|e| ScheduledStatusError::Validation(e.to_string()))?,
+        None => Visibility::Public,
+    };
+    let in_reply_to_id = request
+        .in_reply_to_id
+        .as_deref()
+        .map(|id| {
+            id.parse::<i64>()
+                .map_err(|_| ScheduledStatusError::Validation("Invalid in_reply_to_id".to_string()))
+        })
+        .transpose()?;
+    let media_ids = request
+        .media_ids
+        .iter()
+        .flatten()
+        .map(|id| {
+            id.parse::<i64>()
+                .map_err(|_| ScheduledStatusError::Validation("Invalid media_ids".to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(ScheduledStatusParams {
+        text: request.status.clone(),
+        visibility,
+        sensitive: request.sensitive.unwrap_or(false),
+        spoiler_text: request.spoiler_text.clone().filter(|s| !s.is_empty()),
+        in_reply_to_id,
+        language: request.language.clone(),
+        media_ids,
+        poll: request.poll.clone(),
+    })
+}
+
+/// Renders a scheduled status entity
+pub(crate) fn scheduled_status_json(scheduled: &ScheduledStatus) -> Value {
+    let params = &scheduled.params;
+    let media_ids: Vec<String> = params.media_ids.iter().map(|id| id.to_string()).collect();
+
+    json!({
+        "id": scheduled.id.to_string(),
+        "scheduled_at": scheduled.scheduled_at.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+        "params": {
+            "text": params.text,
+            "poll": params.poll.as_ref().map(poll_params_json),
+            "media_ids": if media_ids.is_empty() { Value::Null } else { json!(media_ids) },
+            "sensitive": params.sensitive,
+            "spoiler_text": params.spoiler_text,
+            "visibility": params.visibility.as_str(),
+            "in_reply_to_id": params.in_reply_to_id.map(|id| id.to_string()),
+            "language": params.language,
+            "application_id": null,
+            "scheduled_at": null,
+            "idempotency": null,
+            "with_rate_limit": false
+        },
+        "media_attachments": media_ids
+            .iter()
+            .map(|id| json!({ "id": id }))
+            .collect::<Vec<_>>()
+    })
+}
+
+fn poll_params_json(poll: &NewPoll) -> Value {
+    json!({
+        "options": poll.options,
+        "expires_in": poll.expires_in.to_string(),
+        "multiple": poll.multiple,
+        "hide_totals": poll.hide_totals
+    })
+}
+
+/// Maps a scheduled status error to an API error response
+pub(crate) fn scheduled_statuses_error_response(e: ScheduledStatusError) -> Response {
+    match e {
+        ScheduledStatusError::NotFound(_) => {
+            error_response(StatusCode::NOT_FOUND, "Record not found")
+        }
+        ScheduledStatusError::Validation(_) => {
+            error_response(StatusCode::UNPROCESSABLE_ENTITY, e.to_string())
+        }
+        ScheduledStatusError::Database(_) | ScheduledStatusError::Internal(_) => {
+            error!("Scheduled status operation failed: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+/// List scheduled statuses handler
+async fn list_scheduled_statuses_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Query(query): Query<ScheduledStatusesQuery>,
+) -> Response {
+    debug!("Listing scheduled statuses for account {}", current.id);
+
+    match ScheduledStatus::get_by_account(
+        &state.pool,
+        current.id,
+        query.limit,
+        query.max_id,
+        query.since_id,
+    )
+    .await
+    {
+        Ok(scheduled) => success(Value::Array(
+            scheduled.iter().map(scheduled_status_json).collect(),
+        )),
+        Err(e) => scheduled_statuses_error_response(e),
+    }
+}
+
+/// Get scheduled status handler
+async fn get_scheduled_status_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(id): Path<i64>,
+) -> Response {
+    debug!("Getting scheduled status {} for account {}", id, current.id);
+
+    match ScheduledStatus::get_owned(&state.pool, current.id, id).await {
+        Ok(scheduled) => success(scheduled_status_json(&scheduled)),
+        Err(e) => scheduled_statuses_error_response(e),
+    }
+}
+
+/// Reschedule handler
+async fn reschedule_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(id): Path<i64>,
+    Json(request): Json<RescheduleRequest>,
+) -> Response {
+    debug!("Rescheduling status {} for account {}", id, current.id);
+
+    match scheduled_status_service(&state.config)
+        .reschedule(&state.pool, current.id, id, request.scheduled_at)
+        .await
+    {
+        Ok(scheduled) => success(scheduled_status_json(&scheduled)),
+        Err(e) => scheduled_statuses_error_response(e),
+    }
+}
+
+/// Cancel handler
+async fn cancel_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(id): Path<i64>,
+) -> Response {
+    debug!(
+        "Cancelling scheduled status {} for account {}",
+        id, current.id
+    );
+
+    match ScheduledStatus::cancel(&state.pool, current.id, id).await {
+        Ok(()) => success(json!({})),
+        Err(e) => scheduled_statuses_error_response(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(status: &str) -> StatusRequest {
+        StatusRequest {
+            status: status.to_string(),
+            visibility: None,
+            in_reply_to_id: None,
+            media_ids: None,
+            sensitive: None,
+            spoiler_text: None,
+            language: None,
+            poll: None,
+            scheduled_at: None,
+        }
+    }
+
+    #[test]
+    fn test_status_params() {
+        let mut req = request("Hello");
+        req.visibility = Some("unlisted".to_string());
+        req.in_reply_to_id = Some("12".to_string());
+        req.media_ids = Some(vec!["3".to_string(), "4".to_string()]);
+        req.spoiler_text = Some(String::new());
+
+        let params = status_params(&req, 500).unwrap();
+        assert_eq!(params.visibility, Visibility::Unlisted);
+        assert_eq!(params.in_reply_to_id, Some(12));
+        assert_eq!(params.media_ids, vec![3, 4]);
+        assert!(params.spoiler_text.is_none());
+
+        assert!(status_params(&request(&"a".repeat(501)), 500).is_err());
+        req.visibility = Some("followers".to_string());
+        assert!(status_params(&req, 500).is_err());
+    }
+
+    #[test]
+    fn test_scheduled_status_json() {
+        let now = Utc::now();
+        let scheduled = ScheduledStatus {
+            id: 5,
+            account_id: 1,
+            scheduled_at: now,
+            params: ScheduledStatusParams {
+                text: "Later".to_string(),
+                poll: Some(NewPoll {
+                    options: vec!["a".to_string(), "b".to_string()],
+                    expires_in: 600,
+                    ..NewPoll::default()
+                }),
+                ..ScheduledStatusParams::default()
+            },
+            created_at: now,
+            updated_at: now,
+        };
+
+        let json = scheduled_status_json(&scheduled);
+        assert_eq!(json["id"], "5");
+        assert_eq!(json["params"]["text"], "Later");
+        assert_eq!(json["params"]["visibility"], "public");
+        assert_eq!(json["params"]["poll"]["expires_in"], "600");
+        assert!(json["params"]["media_ids"].is_null());
+    }
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _ = routes();
+    }
+}
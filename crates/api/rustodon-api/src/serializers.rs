@@ -0,0 +1,270 @@
+//! JSON serializers for API responses
+//!
+//! Helpers shared by handlers to render entities and the
+//! `{ success, data, error }` response envelope.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rustodon_db::User;
+use rustodon_statuses::{sanitize_html, Status};
+use serde_json::{json, Value};
+
+/// Wraps data in a successful response envelope
+pub(crate) fn success(data: Value) -> Response {
+    (
+        StatusCode::OK,
+        Json(json!({
+            "success": true,
+            "data": data,
+            "error": null
+        })),
+    )
+        .into_response()
+}
+
+/// Builds an error response envelope with the given status
+pub(crate) fn error_response(status: StatusCode, message: impl Into<String>) -> Response {
+    (
+        status,
+        Json(json!({
+            "success": false,
+            "data": null,
+            "error": message.into()
+        })),
+    )
+        .into_response()
+}
+
+/// Formats a database timestamp the way Mastodon clients expect
+pub(crate) fn format_timestamp(timestamp: NaiveDateTime) -> String {
+    DateTime::<Utc>::from_naive_utc_and_offset(timestamp, Utc)
+        .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+        .to_string()
+}
+
+/// Renders an account entity
+///
+/// # Arguments
+///
+/// * `user` - Account to render
+/// * `local_domain` - Domain of this instance, used for profile URLs
+pub(crate) fn account_json(user: &User, local_domain: &str) -> Value {
+    let url = match (&user.uri, user.is_local()) {
+        (Some(uri), false) => uri.clone(),
+        _ => format!("https://{}/@{}", local_domain, user.username),
+    };
+
+    json!({
+        "id": user.id.to_string(),
+        "username": user.username,
+        "acct": user.acct(),
+        "display_name": user.display_name.clone().unwrap_or_default(),
+        "locked": user.locked,
+        "bot": user.bot,
+        "discoverable": user.discoverable,
+        "group": user.group_account,
+        "created_at": format_timestamp(user.created_at),
+        "note": user.note.clone().unwrap_or_default(),
+        "url": url,
+        "avatar": format!("https://{}/avatars/original/missing.png", local_domain),
+        "avatar_static": format!("https://{}/avatars/original/missing.png", local_domain),
+        "header": format!("https://{}/headers/original/missing.png", local_domain),
+        "header_static": format!("https://{}/headers/original/missing.png", local_domain),
+        "followers_count": user.followers_count,
+        "following_count": user.following_count,
+        "statuses_count": user.statuses_count,
+        "last_status_at": null,
+        "emojis": [],
+        "fields": []
+    })
+}
+
+/// Renders a status entity
+///
+/// Content of remote statuses is sanitized before it is served.
+///
+/// # Arguments
+///
+/// * `status` - Status to render
+/// * `author` - Account that posted the status
+/// * `local_domain` - Domain of this instance, used for status URLs
+pub(crate) fn status_json(status: &Status, author: &User, local_domain: &str) -> Value {
+    let uri = status.uri.clone().unwrap_or_else(|| {
+        format!(
+            "https://{}/users/{}/statuses/{}",
+            local_domain, author.username, status.id
+        )
+    });
+    let url = status.url.clone().unwrap_or_else(|| {
+        format!(
+            "https://{}/@{}/{}",
+            local_domain, author.username, status.id
+        )
+    });
+
+    json!({
+        "id": status.id.to_string(),
+        "created_at": status.created_at.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+        "in_reply_to_id": status.in_reply_to_id.map(|id| id.to_string()),
+        "in_reply_to_account_id": status.in_reply_to_account_id.map(|id| id.to_string()),
+        "sensitive": status.sensitive,
+        "spoiler_text": status.spoiler_text,
+        "visibility": status.visibility.as_str(),
+        "language": status.language,
+        "uri": uri,
+        "url": url,
+        "replies_count": status.replies_count,
+        "reblogs_count": status.reblogs_count,
+        "favourites_count": status.favourites_count,
+        "content": if status.local {
+            status.content.clone()
+        } else {
+            sanitize_html(&status.content)
+        },
+        "reblog": null,
+        "account": account_json(author, local_domain),
+        "media_attachments": [],
+        "mentions": [],
+        "tags": [],
+        "emojis": [],
+        "card": null,
+        "poll": null
+    })
+}
+
+/// Relationship of the viewer with another account
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Relationship {
+    /// ID of the other account
+    pub account_id: i64,
+    /// Whether the viewer follows the account
+    pub following: bool,
+    /// Whether the viewer has a pending follow request to the account
+    pub requested: bool,
+    /// Whether the account follows the viewer
+    pub followed_by: bool,
+    /// Whether the viewer endorses the account on its profile
+    pub endorsed: bool,
+    /// The viewer's private note on the account
+    pub note: String,
+}
+
+/// Renders a relationship entity from the viewer's point of view
+pub(crate) fn relationship_json(relationship: &Relationship) -> Value {
+    json!({
+        "id": relationship.account_id.to_string(),
+        "following": relationship.following,
+        "showing_reblogs": relationship.following,
+        "notifying": false,
+        "followed_by": relationship.followed_by,
+        "blocking": false,
+        "blocked_by": false,
+        "muting": false,
+        "muting_notifications": false,
+        "requested": relationship.requested,
+        "domain_blocking": false,
+        "endorsed": relationship.endorsed,
+        "note": relationship.note
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(domain: Option<&str>) -> User {
+        let now = Utc::now().naive_utc();
+        User {
+            id: 7,
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            password_hash: String::new(),
+            display_name: Some("Alice".to_string()),
+            note: None,
+            locked: true,
+            bot: false,
+            discoverable: true,
+            group_account: false,
+            domain: domain.map(str::to_string),
+            uri: domain.map(|d| format!("https://{}/users/alice", d)),
+            inbox_url: None,
+            followers_count: 3,
+            following_count: 1,
+            statuses_count: 0,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    #[test]
+    fn test_account_json_local() {
+        let json = account_json(&user(None), "rustodon.example.com");
+        assert_eq!(json["id"], "7");
+        assert_eq!(json["acct"], "alice");
+        assert_eq!(json["locked"], true);
+        assert_eq!(json["url"], "https://rustodon.example.com/@alice");
+    }
+
+    #[test]
+    fn test_account_json_remote() {
+        let json = account_json(&user(Some("remote.example")), "rustodon.example.com");
+        assert_eq!(json["acct"], "alice@remote.example");
+        assert_eq!(json["url"], "https://remote.example/users/alice");
+    }
+
+    #[test]
+    fn test_status_json() {
+        let status = Status {
+            id: 99,
+            account_id: 7,
+            content: "<p>Hi</p>".to_string(),
+            visibility: rustodon_statuses::Visibility::Private,
+            sensitive: false,
+            spoiler_text: String::new(),
+            in_reply_to_id: Some(98),
+            in_reply_to_account_id: None,
+            reblog_of_id: None,
+            language: None,
+            uri: None,
+            url: None,
+            local: true,
+            favourites_count: 0,
+            reblogs_count: 0,
+            replies_count: 0,
+            poll_id: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+
+        let json = status_json(&status, &user(None), "rustodon.example.com");
+        assert_eq!(json["id"], "99");
+        assert_eq!(json["in_reply_to_id"], "98");
+        assert_eq!(json["visibility"], "private");
+        assert_eq!(json["url"], "https://rustodon.example.com/@alice/99");
+        assert_eq!(json["account"]["id"], "7");
+    }
+
+    #[test]
+    fn test_relationship_json() {
+        let json = relationship_json(&Relationship {
+            account_id: 42,
+            requested: true,
+            endorsed: true,
+            note: "Met at RustConf".to_string(),
+            ..Relationship::default()
+        });
+        assert_eq!(json["id"], "42");
+        assert_eq!(json["requested"], true);
+        assert_eq!(json["following"], false);
+        assert_eq!(json["endorsed"], true);
+        assert_eq!(json["note"], "Met at RustConf");
+    }
+}
@@ -0,0 +1,114 @@
+//! Mentions and hashtags of statuses
+//!
+//! Statuses rendered by the API pick up the accounts they mention and the
+//! hashtags they use through [`StatusEntities`].
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::AppState;
+use rustodon_mentions::{Mention, MentionedAccount};
+use rustodon_statuses::StatusesError;
+use rustodon_tags::Tag;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// Mentions and hashtags of a set of statuses
+#[derive(Debug, Default)]
+pub(crate) struct StatusEntities {
+    mentions: HashMap<i64, Vec<MentionedAccount>>,
+    tags: HashMap<i64, Vec<Tag>>,
+    local_domain: String,
+}
+
+impl StatusEntities {
+    /// Loads the mentions and hashtags of the given statuses
+    pub(crate) async fn load(state: &AppState, status_ids: &[i64]) -> Result<Self, StatusesError> {
+        let local_domain = state.config.local_domain.clone();
+        let mentions = Mention::get_by_status_ids(&state.pool, status_ids, &local_domain).await?;
+        let tags = Tag::get_by_status_ids(&state.pool, status_ids).await?;
+
+        Ok(Self {
+            mentions,
+            tags,
+            local_domain,
+        })
+    }
+
+    /// Fills in the mentions and tags of a rendered status
+    pub(crate) fn attach(&self, status_id: i64, status: &mut Value) {
+        if let Some(mentions) = self.mentions.get(&status_id) {
+            status["mentions"] = mentions.iter().map(mention_json).collect();
+        }
+        if let Some(tags) = self.tags.get(&status_id) {
+            status["tags"] = tags
+                .iter()
+                .map(|tag| tag_json(tag, &self.local_domain))
+                .collect();
+        }
+    }
+}
+
+/// Renders a status mention entity
+pub(crate) fn mention_json(account: &MentionedAccount) -> Value {
+    json!({
+        "id": account.id.to_string(),
+        "username": account.username,
+        "url": account.url,
+        "acct": account.acct
+    })
+}
+
+/// Renders a status tag entity
+pub(crate) fn tag_json(tag: &Tag, local_domain: &str) -> Value {
+    json!({
+        "name": tag.name,
+        "url": format!("https://{}/tags/{}", local_domain, tag.name)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_attach() {
+        let entities = StatusEntities {
+            mentions: HashMap::from([(
+                5,
+                vec![MentionedAccount {
+                    id: 2,
+                    username: "bob".to_string(),
+                    domain: Some("remote.example".to_string()),
+                    acct: "bob@remote.example".to_string(),
+                    url: "https://remote.example/@bob".to_string(),
+                }],
+            )]),
+            tags: HashMap::from([(
+                5,
+                vec![Tag {
+                    id: 1,
+                    name: "rust".to_string(),
+                    created_at: Utc::now(),
+                    updated_at: Utc::now(),
+                }],
+            )]),
+            local_domain: "rustodon.example".to_string(),
+        };
+
+        let mut status = json!({ "id": "5", "mentions": [], "tags": [] });
+        entities.attach(5, &mut status);
+        assert_eq!(status["mentions"][0]["acct"], "bob@remote.example");
+        assert_eq!(status["mentions"][0]["id"], "2");
+        assert_eq!(
+            status["tags"][0]["url"],
+            "https://rustodon.example/tags/rust"
+        );
+
+        let mut other = json!({ "id": "6", "mentions": [], "tags": [] });
+        entities.attach(6, &mut other);
+        assert_eq!(other["mentions"], json!([]));
+    }
+}
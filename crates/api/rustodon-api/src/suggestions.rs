@@ -0,0 +1,163 @@
+//! Follow suggestions
+//!
+//! Serves `/api/v2/suggestions`, listing accounts to follow with the sources
+//! that suggested them, its account-only `/api/v1` counterpart, and
+//! dismissing a suggestion through `DELETE /api/v1/suggestions/:id`.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::extractors::CurrentUser;
+use crate::relationships::find_account;
+use crate::serializers::{account_json, error_response, success};
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Response,
+    routing::{delete, get},
+    Router,
+};
+use rustodon_account_suggestions::{AccountSuggestion, AccountSuggestionsError};
+use rustodon_db::User;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, error, warn};
+
+/// Routes of the suggestions API
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/suggestions", get(suggestions_v1_handler))
+        .route("/api/v1/suggestions/:id", delete(dismiss_handler))
+        .route("/api/v2/suggestions", get(suggestions_v2_handler))
+}
+
+/// Maps suggestion errors to API responses
+fn suggestions_error_response(e: AccountSuggestionsError) -> Response {
+    error!("Suggestion operation failed: {}", e);
+    error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+}
+
+/// Query parameters of the suggestion lists
+#[derive(Debug, Deserialize)]
+struct SuggestionsQuery {
+    limit: Option<i64>,
+}
+
+/// Gets the suggestions of an account along with the suggested accounts
+async fn load_suggestions(
+    state: &AppState,
+    account_id: i64,
+    limit: Option<i64>,
+) -> Result<Vec<(AccountSuggestion, User)>, Response> {
+    let suggestions = AccountSuggestion::get(&state.pool, account_id, limit)
+        .await
+        .map_err(suggestions_error_response)?;
+
+    let mut loaded = Vec::with_capacity(suggestions.len());
+    for suggestion in suggestions {
+        match User::get_by_id(&state.pool, suggestion.account_id).await {
+            Ok(Some(account)) => loaded.push((suggestion, account)),
+            Ok(None) => warn!("Suggested account {} not found", suggestion.account_id),
+            Err(e) => {
+                error!("Failed to load suggested account: {}", e);
+                return Err(error_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Internal server error",
+                ));
+            }
+        }
+    }
+    Ok(loaded)
+}
+
+/// Renders a suggestion
+fn suggestion_json(suggestion: &AccountSuggestion, account: &User, local_domain: &str) -> Value {
+    let source = suggestion
+        .sources
+        .first()
+        .map(|source| source.legacy_name())
+        .unwrap_or("global");
+    let sources: Vec<&str> = suggestion
+        .sources
+        .iter()
+        .map(|source| source.as_str())
+        .collect();
+
+    json!({
+        "source": source,
+        "sources": sources,
+        "account": account_json(account, local_domain),
+    })
+}
+
+/// Suggestions handler (v2)
+async fn suggestions_v2_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Query(query): Query<SuggestionsQuery>,
+) -> Response {
+    debug!("Getting follow suggestions for account {}", current.id);
+
+    match load_suggestions(&state, current.id, query.limit).await {
+        Ok(suggestions) => success(
+            suggestions
+                .iter()
+                .map(|(suggestion, account)| {
+                    suggestion_json(suggestion, account, &state.config.local_domain)
+                })
+                .collect(),
+        ),
+        Err(response) => response,
+    }
+}
+
+/// Suggestions handler (v1), listing only the accounts
+async fn suggestions_v1_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Query(query): Query<SuggestionsQuery>,
+) -> Response {
+    debug!("Getting follow suggestions for account {}", current.id);
+
+    match load_suggestions(&state, current.id, query.limit).await {
+        Ok(suggestions) => success(
+            suggestions
+                .iter()
+                .map(|(_, account)| account_json(account, &state.config.local_domain))
+                .collect(),
+        ),
+        Err(response) => response,
+    }
+}
+
+/// Dismiss suggestion handler
+async fn dismiss_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Path(account_id): Path<i64>,
+) -> Response {
+    debug!(
+        "Account {} dismissing suggestion of account {}",
+        current.id, account_id
+    );
+
+    if let Err(response) = find_account(&state, account_id).await {
+        return response;
+    }
+    match AccountSuggestion::dismiss(&state.pool, current.id, account_id).await {
+        Ok(()) => success(json!({})),
+        Err(e) => suggestions_error_response(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+}
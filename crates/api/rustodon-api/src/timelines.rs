@@ -0,0 +1,411 @@
+//! Home, public and hashtag timelines
+//!
+//! Serves `/api/v1/timelines/home`, `/api/v1/timelines/public` and
+//! `/api/v1/timelines/tag/:hashtag`, each run through the viewer's filters,
+//! and streams new statuses to the timelines that show them: the home
+//! timelines of the author's followers and of the accounts following one
+//! of its hashtags, plus the public and hashtag streams. Direct messages
+//! go to the conversations of their participants instead.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::conversations::stream_conversations;
+use crate::extractors::CurrentUser;
+use crate::filters::{filter_statuses, load_matcher};
+use crate::polls::StatusPolls;
+use crate::preview_cards::StatusCards;
+use crate::serializers::{error_response, status_json, success};
+use crate::status_entities::StatusEntities;
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Response,
+    routing::get,
+    Router,
+};
+use rustodon_db::User;
+use rustodon_statuses::{
+    PublicTimeline, Status, StatusesError, TagTimeline, TimelinePage, Visibility,
+};
+use rustodon_streaming::{StreamType, StreamingMessage};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use tracing::{debug, error, warn};
+
+/// Routes of the timelines API
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/timelines/home", get(home_timeline_handler))
+        .route("/api/v1/timelines/public", get(public_timeline_handler))
+        .route("/api/v1/timelines/tag/:hashtag", get(tag_timeline_handler))
+}
+
+/// Maps timeline errors to API responses
+fn timelines_error_response(e: StatusesError) -> Response {
+    match e {
+        StatusesError::Validation(message) => {
+            error_response(StatusCode::UNPROCESSABLE_ENTITY, message)
+        }
+        e => {
+            error!("Timeline operation failed: {}", e);
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        }
+    }
+}
+
+fn internal_error(e: impl std::fmt::Display) -> Response {
+    error!("Failed to load timeline data: {}", e);
+    error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+}
+
+/// Query parameters of the timelines
+///
+/// Array parameters are accepted as `any[]=rust&any[]=fediverse`.
+#[derive(Debug, Default)]
+struct TimelineParams {
+    page: TimelinePage,
+    any: Vec<String>,
+    all: Vec<String>,
+    none: Vec<String>,
+    local: bool,
+    remote: bool,
+    only_media: bool,
+}
+
+impl TimelineParams {
+    fn parse(pairs: Vec<(String, String)>) -> Result<Self, StatusesError> {
+        let number = |key: &str, value: &str| {
+            value
+                .parse::<i64>()
+                .map_err(|_| StatusesError::Validation(format!("Invalid {}: {}", key, value)))
+        };
+        let flag = |key: &str, value: &str| match value {
+            "true" | "1" => Ok(true),
+            "false" | "0" => Ok(false),
+            _ => Err(StatusesError::Validation(format!(
+                "Invalid {}: {}",
+                key, value
+            ))),
+        };
+
+        let mut params = TimelineParams::default();
+        for (key, value) in pairs {
+            match key.trim_end_matches("[]") {
+                "limit" => params.page.limit = Some(number(&key, &value)?),
+                "max_id" => params.page.max_id = Some(number(&key, &value)?),
+                "since_id" => params.page.since_id = Some(number(&key, &value)?),
+                "min_id" => params.page.min_id = Some(number(&key, &value)?),
+                "any" => params.any.push(value),
+                "all" => params.all.push(value),
+                "none" => params.none.push(value),
+                "local" => params.local = flag(&key, &value)?,
+                "remote" => params.remote = flag(&key, &value)?,
+                "only_media" => params.only_media = flag(&key, &value)?,
+                _ => {}
+            }
+        }
+        Ok(params)
+    }
+
+    /// The public timeline with the `local`, `remote` and `only_media`
+    /// modifiers applied
+    fn public_timeline(&self) -> PublicTimeline {
+        PublicTimeline {
+            local: self.local,
+            remote: self.remote,
+            only_media: self.only_media,
+        }
+    }
+
+    /// The hashtag timeline of `hashtag` with the `any`, `all` and `none`
+    /// modifiers applied
+    fn tag_timeline(self, hashtag: &str) -> TagTimeline {
+        let mut any = vec![hashtag.to_string()];
+        any.extend(self.any);
+        TagTimeline {
+            any,
+            all: self.all,
+            none: self.none,
+            local: self.local,
+            remote: self.remote,
+            only_media: self.only_media,
+        }
+    }
+}
+
+/// Renders statuses with their authors, polls as seen by the viewer, link
+/// preview cards, mentions and hashtags
+///
+/// Statuses whose author is missing are left out.
+pub(crate) async fn render_statuses(
+    state: &AppState,
+    statuses: &[Status],
+    viewer_id: Option<i64>,
+) -> Result<Vec<Value>, Response> {
+    let status_ids: Vec<i64> = statuses.iter().map(|s| s.id).collect();
+    let polls = StatusPolls::load(state, &status_ids, viewer_id)
+        .await
+        .map_err(internal_error)?;
+    let cards = StatusCards::load(state, &status_ids)
+        .await
+        .map_err(internal_error)?;
+    let entities = StatusEntities::load(state, &status_ids)
+        .await
+        .map_err(internal_error)?;
+
+    let account_ids: HashSet<i64> = statuses.iter().map(|s| s.account_id).collect();
+    let mut authors: HashMap<i64, User> = HashMap::with_capacity(account_ids.len());
+    for id in account_ids {
+        match User::get_by_id(&state.pool, id).await {
+            Ok(Some(user)) => {
+                authors.insert(id, user);
+            }
+            Ok(None) => warn!("Timeline references missing account {}", id),
+            Err(e) => return Err(internal_error(e)),
+        }
+    }
+
+    Ok(statuses
+        .iter()
+        .filter_map(|status| {
+            let author = authors.get(&status.account_id)?;
+            let mut json = status_json(status, author, &state.config.local_domain);
+            polls.attach(status.id, &mut json);
+            cards.attach(status.id, &mut json);
+            entities.attach(status.id, &mut json);
+            Some(json)
+        })
+        .collect())
+}
+
+/// Home timeline handler
+async fn home_timeline_handler(
+    State(state): State<AppState>,
+    CurrentUser(current): CurrentUser,
+    Query(pairs): Query<Vec<(String, String)>>,
+) -> Response {
+    debug!("Getting home timeline of account {}", current.id);
+
+    let params = match TimelineParams::parse(pairs) {
+        Ok(params) => params,
+        Err(e) => return timelines_error_response(e),
+    };
+    let statuses = match Status::home_timeline(&state.pool, current.id, &params.page).await {
+        Ok(statuses) => statuses,
+        Err(e) => return timelines_error_response(e),
+    };
+
+    let mut rendered = match render_statuses(&state, &statuses, Some(current.id)).await {
+        Ok(rendered) => rendered,
+        Err(response) => return response,
+    };
+    if let Some(matcher) = load_matcher(&state, current.id, "home").await {
+        rendered = filter_statuses(&matcher, rendered);
+    }
+
+    success(rendered.into())
+}
+
+/// Public timeline handler
+async fn public_timeline_handler(
+    State(state): State<AppState>,
+    current: Option<CurrentUser>,
+    Query(pairs): Query<Vec<(String, String)>>,
+) -> Response {
+    debug!("Getting public timeline");
+
+    let params = match TimelineParams::parse(pairs) {
+        Ok(params) => params,
+        Err(e) => return timelines_error_response(e),
+    };
+    let viewer_id = current.as_ref().map(|CurrentUser(user)| user.id);
+
+    let statuses = match Status::public_timeline(
+        &state.pool,
+        &params.public_timeline(),
+        &params.page,
+        viewer_id,
+    )
+    .await
+    {
+        Ok(statuses) => statuses,
+        Err(e) => return timelines_error_response(e),
+    };
+
+    let mut rendered = match render_statuses(&state, &statuses, viewer_id).await {
+        Ok(rendered) => rendered,
+        Err(response) => return response,
+    };
+    if let Some(viewer_id) = viewer_id {
+        if let Some(matcher) = load_matcher(&state, viewer_id, "public").await {
+            rendered = filter_statuses(&matcher, rendered);
+        }
+    }
+
+    success(rendered.into())
+}
+
+/// Hashtag timeline handler
+async fn tag_timeline_handler(
+    State(state): State<AppState>,
+    current: Option<CurrentUser>,
+    Path(hashtag): Path<String>,
+    Query(pairs): Query<Vec<(String, String)>>,
+) -> Response {
+    debug!("Getting timeline of hashtag {}", hashtag);
+
+    let params = match TimelineParams::parse(pairs) {
+        Ok(params) => params,
+        Err(e) => return timelines_error_response(e),
+    };
+    let page = params.page.clone();
+    let timeline = params.tag_timeline(&hashtag);
+    let viewer_id = current.as_ref().map(|CurrentUser(user)| user.id);
+
+    let statuses = match Status::tag_timeline(&state.pool, &timeline, &page, viewer_id).await {
+        Ok(statuses) => statuses,
+        Err(e) => return timelines_error_response(e),
+    };
+
+    let mut rendered = match render_statuses(&state, &statuses, viewer_id).await {
+        Ok(rendered) => rendered,
+        Err(response) => return response,
+    };
+    if let Some(viewer_id) = viewer_id {
+        if let Some(matcher) = load_matcher(&state, viewer_id, "public").await {
+            rendered = filter_statuses(&matcher, rendered);
+        }
+    }
+
+    success(rendered.into())
+}
+
+/// Streams a new status to the timelines that show it
+///
+/// # Arguments
+///
+/// * `state` - Application state
+/// * `status` - The new status
+/// * `body` - The rendered status
+pub(crate) fn stream_status_later(state: &AppState, status: Status, body: Value) {
+    let state = state.clone();
+    tokio::spawn(async move {
+        if status.visibility == Visibility::Direct {
+            stream_conversations(&state, status.id).await;
+            return;
+        }
+
+        let account_ids = match status.home_timeline_account_ids(&state.pool).await {
+            Ok(account_ids) => account_ids,
+            Err(e) => {
+                warn!(
+                    "Failed to get home timelines of status {}: {}",
+                    status.id, e
+                );
+                Vec::new()
+            }
+        };
+        for account_id in account_ids {
+            if let Err(e) = state
+                .streaming
+                .broadcast_to_user(
+                    account_id,
+                    StreamType::User,
+                    StreamingMessage::Update(body.clone()),
+                )
+                .await
+            {
+                warn!("Failed to stream status to account {}: {}", account_id, e);
+            }
+        }
+
+        if status.visibility != Visibility::Public {
+            return;
+        }
+        let mut streams = vec![StreamType::Public];
+        if status.local {
+            streams.push(StreamType::Local);
+        }
+        streams.extend(
+            body["tags"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|tag| tag["name"].as_str())
+                .map(|name| StreamType::Hashtag(name.to_string())),
+        );
+        for stream in streams {
+            if let Err(e) = state
+                .streaming
+                .broadcast(&stream, StreamingMessage::Update(body.clone()))
+                .await
+            {
+                warn!("Failed to stream status {}: {}", status.id, e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(query: &[(&str, &str)]) -> Vec<(String, String)> {
+        query
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_tag_timeline_params() {
+        let params = TimelineParams::parse(pairs(&[
+            ("any[]", "rustlang"),
+            ("all[]", "fediverse"),
+            ("none[]", "spam"),
+            ("local", "true"),
+            ("max_id", "100"),
+            ("min_id", "10"),
+            ("limit", "5"),
+        ]))
+        .unwrap();
+        assert_eq!(params.page.max_id, Some(100));
+        assert_eq!(params.page.min_id, Some(10));
+        assert_eq!(params.page.limit, Some(5));
+
+        let timeline = params.tag_timeline("rust");
+        assert_eq!(timeline.any, vec!["rust", "rustlang"]);
+        assert_eq!(timeline.all, vec!["fediverse"]);
+        assert_eq!(timeline.none, vec!["spam"]);
+        assert!(timeline.local);
+        assert!(!timeline.remote);
+    }
+
+    #[test]
+    fn test_parse_invalid_params() {
+        assert!(TimelineParams::parse(pairs(&[("max_id", "abc")])).is_err());
+        assert!(TimelineParams::parse(pairs(&[("local", "maybe")])).is_err());
+    }
+
+    #[test]
+    fn test_parse_public_timeline_params() {
+        let params =
+            TimelineParams::parse(pairs(&[("remote", "1"), ("only_media", "true")])).unwrap();
+        assert_eq!(
+            params.public_timeline(),
+            PublicTimeline {
+                local: false,
+                remote: true,
+                only_media: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+}
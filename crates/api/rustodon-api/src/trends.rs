@@ -0,0 +1,228 @@
+//! Trends endpoints
+//!
+//! Serves the trending tags, statuses and links approved by staff on
+//! `/api/v1/trends/tags`, `/api/v1/trends/statuses` and
+//! `/api/v1/trends/links`, best scored first. `/api/v1/trends` is kept as an
+//! alias of the tags. A `language` parameter narrows the trends to that
+//! language.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::extractors::CurrentUser;
+use crate::preview_cards::preview_card_json;
+use crate::serializers::{error_response, success};
+use crate::status_entities::tag_json;
+use crate::timelines::render_statuses;
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Response,
+    routing::get,
+    Router,
+};
+use chrono::{NaiveTime, Utc};
+use rustodon_preview_cards::PreviewCard;
+use rustodon_statuses::Status;
+use rustodon_tags::Tag;
+use rustodon_trends::{Trend, TrendHistory, TrendQuery, TrendType, TrendsError};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tracing::{debug, error};
+
+/// Days of history rendered with trending tags
+const TAG_HISTORY_DAYS: u64 = 7;
+
+/// Query parameters of the trends
+#[derive(Debug, Default, Deserialize)]
+pub struct TrendsParams {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    pub language: Option<String>,
+}
+
+/// Routes of the trends API
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/trends", get(trending_tags_handler))
+        .route("/api/v1/trends/:kind", get(trends_handler))
+}
+
+/// Maps trends errors to API responses
+pub(crate) fn trends_error_response(e: TrendsError) -> Response {
+    match e {
+        TrendsError::NotFound(_) => error_response(StatusCode::NOT_FOUND, "Record not found"),
+        e => internal_error(e),
+    }
+}
+
+fn internal_error(e: impl std::fmt::Display) -> Response {
+    error!("Failed to load trends: {}", e);
+    error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+}
+
+/// Renders the history of a tag
+fn history_json(history: &[TrendHistory]) -> Value {
+    history
+        .iter()
+        .map(|day| {
+            json!({
+                "day": day.day.and_time(NaiveTime::MIN).and_utc().timestamp().to_string(),
+                "uses": day.uses.to_string(),
+                "accounts": day.accounts.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Renders the items of trends, in the order of the trends
+///
+/// Items which no longer exist are left out.
+///
+/// # Arguments
+///
+/// * `state` - Application state
+/// * `trend_type` - Kind of the items
+/// * `trends` - The trends
+/// * `viewer_id` - Account viewing the statuses, if any
+///
+/// # Returns
+///
+/// Each remaining trend with its rendered item
+pub(crate) async fn render_trends<'a>(
+    state: &AppState,
+    trend_type: TrendType,
+    trends: &'a [Trend],
+    viewer_id: Option<i64>,
+) -> Result<Vec<(&'a Trend, Value)>, Response> {
+    let target_ids: Vec<i64> = trends.iter().map(|trend| trend.target_id).collect();
+
+    let mut items: HashMap<i64, Value> = match trend_type {
+        TrendType::Tag => {
+            let tag_ids: Vec<i32> = target_ids
+                .iter()
+                .filter_map(|&id| i32::try_from(id).ok())
+                .collect();
+            let tags = Tag::get_by_ids(&state.pool, &tag_ids)
+                .await
+                .map_err(internal_error)?;
+            let histories = TrendHistory::for_tags(
+                &state.pool,
+                &target_ids,
+                Utc::now().date_naive(),
+                TAG_HISTORY_DAYS,
+            )
+            .await
+            .map_err(internal_error)?;
+
+            tags.into_values()
+                .map(|tag| {
+                    let id = i64::from(tag.id);
+                    let mut item = tag_json(&tag, &state.config.local_domain);
+                    item["history"] = history_json(histories.get(&id).map_or(&[], Vec::as_slice));
+                    (id, item)
+                })
+                .collect()
+        }
+        TrendType::Status => {
+            let statuses = Status::get_by_ids(&state.pool, &target_ids)
+                .await
+                .map_err(internal_error)?;
+            let rendered = render_statuses(state, &statuses, viewer_id).await?;
+            rendered
+                .into_iter()
+                .filter_map(|item| {
+                    let id = item["id"].as_str()?.parse().ok()?;
+                    Some((id, item))
+                })
+                .collect()
+        }
+        TrendType::Link => PreviewCard::get_by_ids(&state.pool, &target_ids)
+            .await
+            .map_err(internal_error)?
+            .into_iter()
+            .map(|(id, card)| (id, preview_card_json(&card)))
+            .collect(),
+    };
+
+    Ok(trends
+        .iter()
+        .filter_map(|trend| Some((trend, items.remove(&trend.target_id)?)))
+        .collect())
+}
+
+/// Lists the approved trends of a type
+async fn list_trends(
+    state: &AppState,
+    trend_type: TrendType,
+    params: TrendsParams,
+    viewer_id: Option<i64>,
+) -> Response {
+    let query = TrendQuery {
+        language: params.language,
+        offset: params.offset.unwrap_or(0),
+        limit: params.limit,
+        ..TrendQuery::public()
+    };
+    let trends = match Trend::list(&state.pool, trend_type, &query).await {
+        Ok(trends) => trends,
+        Err(e) => return trends_error_response(e),
+    };
+
+    match render_trends(state, trend_type, &trends, viewer_id).await {
+        Ok(items) => success(items.into_iter().map(|(_, item)| item).collect()),
+        Err(response) => response,
+    }
+}
+
+/// Trending tags handler
+async fn trending_tags_handler(
+    State(state): State<AppState>,
+    Query(params): Query<TrendsParams>,
+) -> Response {
+    debug!("Handling trending tags request");
+    list_trends(&state, TrendType::Tag, params, None).await
+}
+
+/// Trends handler
+async fn trends_handler(
+    State(state): State<AppState>,
+    current: Option<CurrentUser>,
+    Path(kind): Path<String>,
+    Query(params): Query<TrendsParams>,
+) -> Response {
+    debug!("Handling trending {} request", kind);
+
+    let Some(trend_type) = TrendType::parse_plural(&kind) else {
+        return error_response(StatusCode::NOT_FOUND, "Record not found");
+    };
+    let viewer_id = current.as_ref().map(|CurrentUser(user)| user.id);
+    list_trends(&state, trend_type, params, viewer_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        let _router: Router<AppState> = routes();
+    }
+
+    #[test]
+    fn test_history_json() {
+        let history = vec![TrendHistory {
+            day: NaiveDate::from_ymd_opt(2019, 12, 30).unwrap(),
+            uses: 3,
+            accounts: 2,
+        }];
+        assert_eq!(
+            history_json(&history),
+            json!([{ "day": "1577664000", "uses": "3", "accounts": "2" }])
+        );
+    }
+}
@@ -4,9 +4,13 @@
 //! This crate provides real-time streaming functionality for Rustodon using WebSockets.
 //! It implements the streaming API similar to Mastodon's real-time timeline updates.
 //!
+//! Clients connect with their access token and subscribe to streams. Public
+//! streams are shared by every client, while the `user`, `direct` and
+//! `notification` streams are fed by a channel of the connected user only.
+//!
 //! # Examples
 //!
-//! ```rust
+//! ```rust,no_run
 //! use rustodon_streaming::StreamingServer;
 //!
 //! #[tokio::main]
@@ -26,24 +30,31 @@
 //!
 //! arkSong (arksong2018@gmail.com)
 
+use async_trait::async_trait;
 use axum::{
-    extract::{ws::WebSocket, WebSocketUpgrade},
-    response::IntoResponse,
+    extract::{
+        ws::{Message, WebSocket},
+        Query, WebSocketUpgrade,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     routing::get,
     Router,
 };
 use dashmap::DashMap;
 use futures::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
 use tower_http::{
     compression::CompressionLayer,
     cors::{Any, CorsLayer},
     limit::RequestBodyLimitLayer,
     trace::TraceLayer,
 };
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error, info, trace, warn};
 use uuid::Uuid;
 
 /// Streaming error type
@@ -59,6 +70,26 @@ pub enum StreamingError {
     Authentication(String),
 }
 
+/// Resolves the access tokens of connecting clients
+#[async_trait]
+pub trait TokenAuthenticator: Send + Sync {
+    /// Returns the ID of the user owning a token, or `None` if the token is
+    /// invalid
+    async fn authenticate(&self, token: &str) -> Result<Option<i64>, StreamingError>;
+}
+
+/// Runs the filters of a user over the events sent to their connections
+#[async_trait]
+pub trait EventFilter: Send + Sync {
+    /// Returns the event as the user should see it, or `None` to drop it
+    async fn filter(
+        &self,
+        user_id: i64,
+        stream: &StreamType,
+        message: StreamingMessage,
+    ) -> Option<StreamingMessage>;
+}
+
 /// Stream types supported by the streaming API
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum StreamType {
@@ -78,6 +109,32 @@ pub enum StreamType {
     Notifications,
 }
 
+impl StreamType {
+    /// Whether the stream only carries events of the connected user
+    ///
+    /// These streams need an authenticated connection and are fed through
+    /// the user's own channel instead of a shared one.
+    pub fn is_user_scoped(&self) -> bool {
+        matches!(
+            self,
+            StreamType::User | StreamType::Direct | StreamType::Notifications
+        )
+    }
+
+    /// Name of the stream in the events sent to clients
+    pub fn names(&self) -> Vec<String> {
+        match self {
+            StreamType::User => vec!["user".to_string()],
+            StreamType::Public => vec!["public".to_string()],
+            StreamType::Local => vec!["public:local".to_string()],
+            StreamType::Hashtag(name) => vec!["hashtag".to_string(), name.clone()],
+            StreamType::List(id) => vec!["list".to_string(), id.clone()],
+            StreamType::Direct => vec!["direct".to_string()],
+            StreamType::Notifications => vec!["notification".to_string()],
+        }
+    }
+}
+
 /// Streaming message types
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "event", content = "payload")]
@@ -94,10 +151,50 @@ pub enum StreamingMessage {
     Conversation(serde_json::Value),
     /// Announcement event
     Announcement(serde_json::Value),
+    /// Timeline read marker update event
+    Marker(serde_json::Value),
     /// Heartbeat event
     Heartbeat,
 }
 
+impl StreamingMessage {
+    /// Name of the event sent to clients
+    pub fn event(&self) -> &'static str {
+        match self {
+            StreamingMessage::Update(_) => "update",
+            StreamingMessage::Delete(_) => "delete",
+            StreamingMessage::Notification(_) => "notification",
+            StreamingMessage::StatusUpdate(_) => "status.update",
+            StreamingMessage::Conversation(_) => "conversation",
+            StreamingMessage::Announcement(_) => "announcement",
+            StreamingMessage::Marker(_) => "marker",
+            StreamingMessage::Heartbeat => "heartbeat",
+        }
+    }
+
+    /// Event sent to a client subscribed to a stream
+    ///
+    /// Like Mastodon, the payload of entities is itself JSON-encoded.
+    pub fn to_event(&self, stream: &StreamType) -> serde_json::Value {
+        let payload = match self {
+            StreamingMessage::Update(entity)
+            | StreamingMessage::Notification(entity)
+            | StreamingMessage::StatusUpdate(entity)
+            | StreamingMessage::Conversation(entity)
+            | StreamingMessage::Announcement(entity)
+            | StreamingMessage::Marker(entity) => Some(entity.to_string()),
+            StreamingMessage::Delete(id) => Some(id.clone()),
+            StreamingMessage::Heartbeat => None,
+        };
+
+        serde_json::json!({
+            "stream": stream.names(),
+            "event": self.event(),
+            "payload": payload
+        })
+    }
+}
+
 /// Client connection information
 #[derive(Debug, Clone)]
 pub struct ClientConnection {
@@ -111,13 +208,47 @@ pub struct ClientConnection {
     pub connected_at: chrono::DateTime<chrono::Utc>,
 }
 
+/// Message of a user's channel, with the stream it belongs to
+pub type UserMessage = (StreamType, StreamingMessage);
+
+/// State of a single WebSocket connection
+struct Connection {
+    client_id: String,
+    user_id: Option<i64>,
+    streams: Vec<StreamType>,
+    /// Tasks forwarding shared channels to the connection
+    forwarders: HashMap<StreamType, JoinHandle<()>>,
+    /// Sink of every event to send to the client
+    events: mpsc::Sender<UserMessage>,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        for forwarder in self.forwarders.values() {
+            forwarder.abort();
+        }
+    }
+}
+
+/// Query parameters of the streaming endpoint
+#[derive(Debug, Default, Deserialize)]
+struct StreamingParams {
+    access_token: Option<String>,
+}
+
 /// Streaming server implementation
 #[derive(Clone)]
 pub struct StreamingServer {
     /// Active client connections
     clients: Arc<DashMap<String, ClientConnection>>,
-    /// Broadcast channels for different stream types
+    /// Broadcast channels for the streams shared by every client
     channels: Arc<DashMap<StreamType, broadcast::Sender<StreamingMessage>>>,
+    /// Per-user channels for events only the account's own connections receive
+    user_channels: Arc<DashMap<i64, broadcast::Sender<UserMessage>>>,
+    /// Resolves the access tokens of connecting clients
+    authenticator: Option<Arc<dyn TokenAuthenticator>>,
+    /// Filters events for authenticated connections
+    event_filter: Option<Arc<dyn EventFilter>>,
     /// Server address
     address: String,
 }
@@ -136,14 +267,9 @@ impl StreamingServer {
         let clients = Arc::new(DashMap::new());
         let channels = Arc::new(DashMap::new());
 
-        // Initialize default channels
-        let default_streams = vec![
-            StreamType::Public,
-            StreamType::Local,
-            StreamType::User,
-            StreamType::Direct,
-            StreamType::Notifications,
-        ];
+        // Initialize default channels; hashtag and list channels are created
+        // when first subscribed to
+        let default_streams = vec![StreamType::Public, StreamType::Local];
 
         for stream_type in default_streams {
             let (tx, _) = broadcast::channel(1000);
@@ -153,18 +279,30 @@ impl StreamingServer {
         Ok(Self {
             clients,
             channels,
+            user_channels: Arc::new(DashMap::new()),
+            authenticator: None,
+            event_filter: None,
             address: address.to_string(),
         })
     }
 
-    /// Start the streaming server
+    /// Resolve access tokens with an authenticator
     ///
-    /// # Returns
-    /// Result indicating success or failure
-    pub async fn start(&self) -> Result<(), StreamingError> {
-        info!("Starting streaming server on {}", self.address);
+    /// Without one, only anonymous connections are accepted.
+    pub fn with_authenticator(mut self, authenticator: Arc<dyn TokenAuthenticator>) -> Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
 
-        let app = Router::new()
+    /// Run the filters of authenticated users over their events
+    pub fn with_event_filter(mut self, event_filter: Arc<dyn EventFilter>) -> Self {
+        self.event_filter = Some(event_filter);
+        self
+    }
+
+    /// Router serving the streaming API
+    pub fn router(&self) -> Router {
+        Router::new()
             .route("/api/v1/streaming", get(Self::streaming_handler))
             .route("/api/v1/streaming/health", get(Self::health_handler))
             .with_state(Arc::new(self.clone()))
@@ -174,11 +312,20 @@ impl StreamingServer {
                 CorsLayer::new()
                     .allow_origin(Any)
                     .allow_methods(Any)
-                    .allow_headers(Any)
-                    .allow_credentials(true),
+                    .allow_headers(Any),
             )
             .layer(CompressionLayer::new())
-            .layer(RequestBodyLimitLayer::new(1024 * 1024)); // 1MB limit for streaming
+            .layer(RequestBodyLimitLayer::new(1024 * 1024)) // 1MB limit for streaming
+    }
+
+    /// Start the streaming server
+    ///
+    /// # Returns
+    /// Result indicating success or failure
+    pub async fn start(&self) -> Result<(), StreamingError> {
+        info!("Starting streaming server on {}", self.address);
+
+        let app = self.router();
 
         let listener = tokio::net::TcpListener::bind(&self.address)
             .await
@@ -196,13 +343,48 @@ impl StreamingServer {
     }
 
     /// WebSocket streaming handler
+    ///
+    /// The access token is taken from the `access_token` parameter or the
+    /// `Authorization` header. Connections without one are anonymous and can
+    /// only follow the public streams.
     async fn streaming_handler(
         ws: WebSocketUpgrade,
         axum::extract::State(state): axum::extract::State<Arc<Self>>,
-    ) -> impl IntoResponse {
+        Query(params): Query<StreamingParams>,
+        headers: HeaderMap,
+    ) -> Response {
         info!("New WebSocket connection request");
 
-        ws.on_upgrade(|socket| state.handle_socket(socket))
+        let token = params.access_token.or_else(|| {
+            headers
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .map(str::to_string)
+        });
+        let user_id = match token {
+            Some(token) => match state.authenticate(&token).await {
+                Ok(Some(user_id)) => Some(user_id),
+                Ok(None) => {
+                    return (StatusCode::UNAUTHORIZED, "Invalid access token").into_response()
+                }
+                Err(e) => {
+                    error!("Failed to authenticate streaming client: {}", e);
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                }
+            },
+            None => None,
+        };
+
+        ws.on_upgrade(move |socket| state.handle_socket(socket, user_id))
+    }
+
+    /// Resolve an access token to its user
+    async fn authenticate(&self, token: &str) -> Result<Option<i64>, StreamingError> {
+        match &self.authenticator {
+            Some(authenticator) => authenticator.authenticate(token).await,
+            None => Ok(None),
+        }
     }
 
     /// Health check handler
@@ -214,13 +396,53 @@ impl StreamingServer {
     }
 
     /// Handle individual WebSocket connection
-    async fn handle_socket(self: Arc<Self>, socket: WebSocket) {
+    async fn handle_socket(self: Arc<Self>, socket: WebSocket, user_id: Option<i64>) {
         let client_id = Uuid::new_v4().to_string();
         info!("New WebSocket connection: {}", client_id);
 
         let (mut sender, mut receiver) = socket.split();
-        let mut client_streams = Vec::new();
-        let mut channel_receivers = Vec::new();
+        let (events, mut pending) = mpsc::channel(100);
+        let mut connection = Connection {
+            client_id: client_id.clone(),
+            user_id,
+            streams: Vec::new(),
+            forwarders: HashMap::new(),
+            events,
+        };
+        self.clients.insert(
+            client_id.clone(),
+            ClientConnection {
+                id: client_id.clone(),
+                user_id,
+                streams: Vec::new(),
+                connected_at: chrono::Utc::now(),
+            },
+        );
+
+        // The user's own events all go through one channel; the streams the
+        // client subscribed to pick which of them it gets
+        if let Some(user_id) = user_id {
+            let mut user_events = self.subscribe_user(user_id);
+            let events = connection.events.clone();
+            connection.forwarders.insert(
+                StreamType::User,
+                tokio::spawn(async move {
+                    loop {
+                        match user_events.recv().await {
+                            Ok(message) => {
+                                if events.send(message).await.is_err() {
+                                    break;
+                                }
+                            }
+                            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                                warn!("User {} lagged by {} events", user_id, skipped);
+                            }
+                            Err(broadcast::error::RecvError::Closed) => break,
+                        }
+                    }
+                }),
+            );
+        }
 
         // Send welcome message
         let welcome_msg = serde_json::json!({
@@ -231,44 +453,55 @@ impl StreamingServer {
             }
         });
 
-        if let Err(e) = sender
-            .send(axum::extract::ws::Message::Text(welcome_msg.to_string()))
-            .await
-        {
+        if let Err(e) = sender.send(Message::Text(welcome_msg.to_string())).await {
             error!("Failed to send welcome message: {}", e);
+            self.clients.remove(&client_id);
             return;
         }
 
-        // Handle incoming messages
-        while let Some(msg) = receiver.next().await {
-            match msg {
-                Ok(axum::extract::ws::Message::Text(text)) => {
-                    if let Err(e) = self
-                        .handle_client_message(
-                            &client_id,
-                            &text,
-                            &mut client_streams,
-                            &mut channel_receivers,
-                        )
-                        .await
-                    {
-                        error!("Error handling client message: {}", e);
+        loop {
+            tokio::select! {
+                msg = receiver.next() => match msg {
+                    Some(Ok(Message::Text(text))) => {
+                        if let Err(e) = self.handle_client_message(&mut connection, &text).await {
+                            error!("Error handling client message: {}", e);
+                            break;
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | None => {
+                        info!("Client {} disconnected", client_id);
+                        break;
+                    }
+                    Some(Err(e)) => {
+                        error!("WebSocket error for client {}: {}", client_id, e);
+                        break;
+                    }
+                    Some(Ok(_)) => {}
+                },
+                Some((stream, message)) = pending.recv() => {
+                    if !connection.streams.contains(&stream) {
+                        continue;
+                    }
+                    let message = match (&self.event_filter, user_id) {
+                        (Some(event_filter), Some(user_id)) => {
+                            match event_filter.filter(user_id, &stream, message).await {
+                                Some(message) => message,
+                                None => continue,
+                            }
+                        }
+                        _ => message,
+                    };
+                    let event = message.to_event(&stream).to_string();
+                    if let Err(e) = sender.send(Message::Text(event)).await {
+                        error!("Failed to send event to client {}: {}", client_id, e);
                         break;
                     }
                 }
-                Ok(axum::extract::ws::Message::Close(_)) => {
-                    info!("Client {} disconnected", client_id);
-                    break;
-                }
-                Err(e) => {
-                    error!("WebSocket error for client {}: {}", client_id, e);
-                    break;
-                }
-                _ => {}
             }
         }
 
-        // Cleanup
+        // Cleanup; dropping the connection stops its forwarders
+        drop(connection);
         self.clients.remove(&client_id);
         info!("Client {} disconnected and cleaned up", client_id);
     }
@@ -276,10 +509,8 @@ impl StreamingServer {
     /// Handle client message
     async fn handle_client_message(
         &self,
-        client_id: &str,
+        connection: &mut Connection,
         message: &str,
-        client_streams: &mut Vec<StreamType>,
-        channel_receivers: &mut Vec<broadcast::Receiver<StreamingMessage>>,
     ) -> Result<(), StreamingError> {
         let parsed: serde_json::Value =
             serde_json::from_str(message).map_err(StreamingError::Serialization)?;
@@ -287,15 +518,13 @@ impl StreamingServer {
         if let Some(event) = parsed.get("event").and_then(|e| e.as_str()) {
             match event {
                 "subscribe" => {
-                    self.handle_subscribe(client_id, &parsed, client_streams, channel_receivers)
-                        .await?;
+                    self.handle_subscribe(connection, &parsed).await?;
                 }
                 "unsubscribe" => {
-                    self.handle_unsubscribe(client_id, &parsed, client_streams)
-                        .await?;
+                    self.handle_unsubscribe(connection, &parsed).await?;
                 }
                 "ping" => {
-                    self.handle_ping(client_id).await?;
+                    self.handle_ping(&connection.client_id).await?;
                 }
                 _ => {
                     warn!("Unknown event type: {}", event);
@@ -303,44 +532,87 @@ impl StreamingServer {
             }
         }
 
+        if let Some(mut client) = self.clients.get_mut(&connection.client_id) {
+            client.streams = connection.streams.clone();
+        }
         Ok(())
     }
 
     /// Handle subscribe request
     async fn handle_subscribe(
         &self,
-        client_id: &str,
+        connection: &mut Connection,
         message: &serde_json::Value,
-        client_streams: &mut Vec<StreamType>,
-        channel_receivers: &mut Vec<broadcast::Receiver<StreamingMessage>>,
     ) -> Result<(), StreamingError> {
         let stream_type = self.parse_stream_type(message)?;
+        if connection.streams.contains(&stream_type) {
+            return Ok(());
+        }
 
-        // Subscribe to the stream
-        if let Some(channel) = self.channels.get(&stream_type) {
-            let receiver = channel.subscribe();
-            channel_receivers.push(receiver);
-            client_streams.push(stream_type.clone());
-
-            debug!("Client {} subscribed to {:?}", client_id, stream_type);
+        if stream_type.is_user_scoped() {
+            // Fed by the user channel the connection already follows
+            if connection.user_id.is_none() {
+                return Err(StreamingError::Authentication(format!(
+                    "Stream {:?} requires an access token",
+                    stream_type
+                )));
+            }
+        } else {
+            let mut receiver = self
+                .channels
+                .entry(stream_type.clone())
+                .or_insert_with(|| broadcast::channel(1000).0)
+                .subscribe();
+            let events = connection.events.clone();
+            let stream = stream_type.clone();
+            connection.forwarders.insert(
+                stream_type.clone(),
+                tokio::spawn(async move {
+                    loop {
+                        match receiver.recv().await {
+                            Ok(message) => {
+                                if events.send((stream.clone(), message)).await.is_err() {
+                                    break;
+                                }
+                            }
+                            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                                warn!("Stream {:?} lagged by {} events", stream, skipped);
+                            }
+                            Err(broadcast::error::RecvError::Closed) => break,
+                        }
+                    }
+                }),
+            );
         }
 
+        connection.streams.push(stream_type.clone());
+        debug!(
+            "Client {} subscribed to {:?}",
+            connection.client_id, stream_type
+        );
         Ok(())
     }
 
     /// Handle unsubscribe request
     async fn handle_unsubscribe(
         &self,
-        client_id: &str,
+        connection: &mut Connection,
         message: &serde_json::Value,
-        client_streams: &mut Vec<StreamType>,
     ) -> Result<(), StreamingError> {
         let stream_type = self.parse_stream_type(message)?;
 
         // Remove from client streams
-        client_streams.retain(|s| s != &stream_type);
+        connection.streams.retain(|s| s != &stream_type);
+        if !stream_type.is_user_scoped() {
+            if let Some(forwarder) = connection.forwarders.remove(&stream_type) {
+                forwarder.abort();
+            }
+        }
 
-        debug!("Client {} unsubscribed from {:?}", client_id, stream_type);
+        debug!(
+            "Client {} unsubscribed from {:?}",
+            connection.client_id, stream_type
+        );
         Ok(())
     }
 
@@ -378,7 +650,10 @@ impl StreamingServer {
         }
     }
 
-    /// Broadcast message to all subscribers of a stream
+    /// Broadcast message to all subscribers of a shared stream
+    ///
+    /// Events of user-scoped streams go through
+    /// [`StreamingServer::broadcast_to_user`] instead.
     pub async fn broadcast(
         &self,
         stream_type: &StreamType,
@@ -392,6 +667,52 @@ impl StreamingServer {
         Ok(())
     }
 
+    /// Subscribe to events addressed to a single user
+    ///
+    /// # Arguments
+    /// * `user_id` - ID of the authenticated user
+    ///
+    /// # Returns
+    /// Receiver for the user's private events, with their stream
+    pub fn subscribe_user(&self, user_id: i64) -> broadcast::Receiver<UserMessage> {
+        debug!("Subscribing to private events for user {}", user_id);
+
+        self.user_channels
+            .entry(user_id)
+            .or_insert_with(|| broadcast::channel(100).0)
+            .subscribe()
+    }
+
+    /// Send a message to the connections of a single user following a stream
+    ///
+    /// # Arguments
+    /// * `user_id` - ID of the user to notify
+    /// * `stream` - User-scoped stream the message belongs to
+    /// * `message` - Message to send
+    ///
+    /// # Returns
+    /// Result indicating success or failure
+    pub async fn broadcast_to_user(
+        &self,
+        user_id: i64,
+        stream: StreamType,
+        message: StreamingMessage,
+    ) -> Result<(), StreamingError> {
+        debug_assert!(stream.is_user_scoped(), "{:?} is not user-scoped", stream);
+        let Some(channel) = self.user_channels.get(&user_id) else {
+            trace!("No connections for user {}, dropping message", user_id);
+            return Ok(());
+        };
+
+        if channel.send((stream, message)).is_err() {
+            // Every receiver is gone, so forget the channel
+            drop(channel);
+            self.user_channels.remove(&user_id);
+            debug!("Removed idle channel for user {}", user_id);
+        }
+        Ok(())
+    }
+
     /// Get active client count
     pub fn active_clients(&self) -> usize {
         self.clients.len()
@@ -406,7 +727,11 @@ impl StreamingServer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
+    use std::time::Duration;
     use tokio::test;
+    use tokio::time::timeout;
+    use tokio_tungstenite::tungstenite::{self, Message::Text};
 
     #[test]
     async fn test_streaming_server_creation() {
@@ -425,4 +750,213 @@ mod tests {
         let stream_type = server.parse_stream_type(&message).unwrap();
         assert!(matches!(stream_type, StreamType::User));
     }
+
+    #[test]
+    async fn test_broadcast_to_user() {
+        let server = StreamingServer::new("127.0.0.1:0").await.unwrap();
+        let mut receiver = server.subscribe_user(1);
+
+        server
+            .broadcast_to_user(1, StreamType::User, StreamingMessage::Marker(json!({})))
+            .await
+            .unwrap();
+        server
+            .broadcast_to_user(2, StreamType::User, StreamingMessage::Heartbeat)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            receiver.recv().await.unwrap(),
+            (StreamType::User, StreamingMessage::Marker(_))
+        ));
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    async fn test_to_event() {
+        let event = StreamingMessage::Update(json!({ "id": "1" }))
+            .to_event(&StreamType::Hashtag("rust".to_string()));
+        assert_eq!(event["stream"], json!(["hashtag", "rust"]));
+        assert_eq!(event["event"], "update");
+        assert_eq!(event["payload"], r#"{"id":"1"}"#);
+
+        let event = StreamingMessage::Delete("7".to_string()).to_event(&StreamType::Public);
+        assert_eq!(event["payload"], "7");
+    }
+
+    struct FixedAuthenticator;
+
+    #[async_trait]
+    impl TokenAuthenticator for FixedAuthenticator {
+        async fn authenticate(&self, token: &str) -> Result<Option<i64>, StreamingError> {
+            Ok(match token {
+                "alice" => Some(1),
+                "bob" => Some(2),
+                _ => None,
+            })
+        }
+    }
+
+    /// Drops the statuses of user 2 from the streams of user 1
+    struct BlockingFilter;
+
+    #[async_trait]
+    impl EventFilter for BlockingFilter {
+        async fn filter(
+            &self,
+            user_id: i64,
+            _stream: &StreamType,
+            message: StreamingMessage,
+        ) -> Option<StreamingMessage> {
+            match &message {
+                StreamingMessage::Update(status) if user_id == 1 && status["account"] == 2 => None,
+                _ => Some(message),
+            }
+        }
+    }
+
+    type Socket = tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >;
+
+    /// Serves a streaming server on a free port
+    async fn serve() -> (StreamingServer, String) {
+        let server = StreamingServer::new("127.0.0.1:0")
+            .await
+            .unwrap()
+            .with_authenticator(Arc::new(FixedAuthenticator))
+            .with_event_filter(Arc::new(BlockingFilter));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/api/v1/streaming", listener.local_addr().unwrap());
+        let router = server.router();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        (server, url)
+    }
+
+    /// Connects and subscribes to a stream, skipping the welcome message
+    async fn connect(url: &str, stream: &str) -> Socket {
+        let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        socket.next().await.unwrap().unwrap();
+        socket
+            .send(Text(
+                json!({ "event": "subscribe", "stream": stream }).to_string(),
+            ))
+            .await
+            .unwrap();
+        socket
+    }
+
+    /// Reads the next event, if one comes shortly
+    async fn next_event(socket: &mut Socket) -> Option<serde_json::Value> {
+        let message = timeout(Duration::from_millis(200), socket.next())
+            .await
+            .ok()??
+            .unwrap();
+        Some(serde_json::from_str(message.to_text().unwrap()).unwrap())
+    }
+
+    /// Repeats a broadcast until the subscription is in place and the event arrives
+    async fn deliver<F, Fut>(socket: &mut Socket, broadcast: F) -> serde_json::Value
+    where
+        F: Fn() -> Fut,
+        Fut: std::future::Future<Output = ()>,
+    {
+        for _ in 0..25 {
+            broadcast().await;
+            if let Some(event) = next_event(socket).await {
+                return event;
+            }
+        }
+        panic!("event never delivered");
+    }
+
+    #[test]
+    async fn test_socket_receives_its_user_events() {
+        let (server, url) = serve().await;
+        let mut alice = connect(&format!("{}?access_token=alice", url), "user").await;
+        let mut bob = connect(&format!("{}?access_token=bob", url), "user").await;
+
+        let event = deliver(&mut alice, || async {
+            server
+                .broadcast_to_user(1, StreamType::User, StreamingMessage::Marker(json!({})))
+                .await
+                .unwrap();
+        })
+        .await;
+        assert_eq!(event["stream"], json!(["user"]));
+        assert_eq!(event["event"], "marker");
+        assert!(next_event(&mut bob).await.is_none());
+
+        // Events of streams the client did not subscribe to are held back
+        server
+            .broadcast_to_user(1, StreamType::Direct, StreamingMessage::Heartbeat)
+            .await
+            .unwrap();
+        assert!(next_event(&mut alice).await.is_none());
+        assert_eq!(server.active_clients(), 2);
+    }
+
+    #[test]
+    async fn test_socket_receives_public_events() {
+        let (server, url) = serve().await;
+        let mut socket = connect(&url, "hashtag:rust").await;
+
+        let event = deliver(&mut socket, || async {
+            server
+                .broadcast(
+                    &StreamType::Hashtag("rust".to_string()),
+                    StreamingMessage::Delete("7".to_string()),
+                )
+                .await
+                .unwrap();
+        })
+        .await;
+        assert_eq!(event["stream"], json!(["hashtag", "rust"]));
+        assert_eq!(event["payload"], "7");
+    }
+
+    #[test]
+    async fn test_socket_filters_events_of_its_user() {
+        let (server, url) = serve().await;
+        let mut alice = connect(&format!("{}?access_token=alice", url), "public").await;
+        let broadcast = |account: i64| {
+            let server = server.clone();
+            async move {
+                server
+                    .broadcast(
+                        &StreamType::Public,
+                        StreamingMessage::Update(json!({ "account": account })),
+                    )
+                    .await
+                    .unwrap();
+            }
+        };
+
+        // Once the filtered status is sent, the next one proves it was dropped
+        deliver(&mut alice, || broadcast(3)).await;
+        while next_event(&mut alice).await.is_some() {}
+        broadcast(2).await;
+        broadcast(3).await;
+        let event = next_event(&mut alice).await.unwrap();
+        assert_eq!(event["payload"], r#"{"account":3}"#);
+    }
+
+    #[test]
+    async fn test_socket_authentication() {
+        let (_server, url) = serve().await;
+        assert!(
+            tokio_tungstenite::connect_async(format!("{}?access_token=mallory", url))
+                .await
+                .is_err()
+        );
+
+        // Anonymous clients cannot follow user streams
+        let mut socket = connect(&url, "user").await;
+        assert!(matches!(
+            timeout(Duration::from_secs(1), socket.next())
+                .await
+                .unwrap(),
+            None | Some(Ok(tungstenite::Message::Close(_))) | Some(Err(_))
+        ));
+    }
 }
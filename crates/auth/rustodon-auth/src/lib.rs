@@ -8,7 +8,9 @@
 //!
 //! ```rust
 //! use rustodon_auth::{register_user, login_user, RegisterRequest, LoginRequest};
+//! use rustodon_config::RegistrationsMode;
 //! use rustodon_db::init_database;
+//! use rustodon_email_domain_blocks::StaticMxResolver;
 //! #[tokio::main]
 //! async fn main() {
 //!     let pool = init_database().await.unwrap();
@@ -17,8 +19,9 @@
 //!         email: "example123@example.com".to_string(),
 //!         password: "password123".to_string(),
 //!     };
-//!     match register_user(&pool, request).await {
-//!         Ok(session) => println!("User registered with ID: {}", session.user_id),
+//!     let mode = RegistrationsMode::Open;
+//!     match register_user(&pool, request, &StaticMxResolver::new(), mode, Default::default()).await {
+//!         Ok(registration) => println!("User registered with ID: {}", registration.user_id()),
 //!         Err(e) => println!("Registration failed: {}", e),
 //!     }
 //! }
@@ -27,6 +30,9 @@
 //! # Dependencies
 //!
 //! - `rustodon_db`: Database operations
+//! - `rustodon_config`: Registrations mode of the instance
+//! - `rustodon_canonical_email_blocks`, `rustodon_email_domain_blocks`:
+//!   Blocked sign-up addresses
 //! - `tracing`: Structured logging
 //! - `thiserror`: Error handling
 //! - `uuid`: Session token generation
@@ -46,9 +52,13 @@ use axum::{
 use base64::{engine::general_purpose, Engine as _};
 use bcrypt::{hash, verify, DEFAULT_COST};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use rustodon_canonical_email_blocks::{CanonicalEmailBlock, CanonicalEmailBlockError};
+use rustodon_config::RegistrationsMode;
 use rustodon_db::User;
+use rustodon_email_domain_blocks::{EmailDomainBlock, EmailDomainBlockError, MxResolver};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
+use std::net::IpAddr;
 use thiserror::Error;
 use tracing::{debug, error, info};
 
@@ -67,6 +77,14 @@ pub enum AuthError {
     Internal(String),
     #[error("Validation error: {0}")]
     Validation(String),
+    #[error("Email address is not allowed: {0}")]
+    EmailBlocked(String),
+    #[error("Your login is currently disabled")]
+    AccountDisabled,
+    #[error("Your login is currently pending approval")]
+    PendingApproval,
+    #[error("Sign-ups are closed on this instance")]
+    RegistrationsClosed,
 }
 
 /// User registration request
@@ -80,6 +98,16 @@ pub struct RegisterRequest {
     pub password: String,
 }
 
+/// Where a sign-up comes from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SignUpOrigin {
+    /// Address of the client, if known
+    pub ip: Option<IpAddr>,
+    /// Whether the account waits for staff approval, such as when the
+    /// address is in a range blocked with `sign_up_requires_approval`
+    pub requires_approval: bool,
+}
+
 /// User login request
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoginRequest {
@@ -100,6 +128,28 @@ pub struct AuthSession {
     pub expires_at: chrono::NaiveDateTime,
 }
 
+/// Outcome of a sign-up
+#[derive(Debug, Clone)]
+pub enum Registration {
+    /// The account can be used right away, and is signed in
+    SignedIn(AuthSession),
+    /// The account waits for staff approval, and gets no session until then
+    PendingApproval {
+        /// ID of the new account
+        user_id: i64,
+    },
+}
+
+impl Registration {
+    /// ID of the new account
+    pub fn user_id(&self) -> i64 {
+        match self {
+            Registration::SignedIn(session) => session.user_id,
+            Registration::PendingApproval { user_id } => *user_id,
+        }
+    }
+}
+
 impl AuthSession {
     /// Create a new session
     ///
@@ -342,20 +392,59 @@ pub fn hash_password(password: &str) -> Result<String, AuthError> {
         .map_err(|e| AuthError::Internal(format!("Password hashing error: {}", e)))
 }
 
+/// Rejects email addresses matching a canonical email or email domain block
+///
+/// # Arguments
+/// * `pool` - Database connection pool
+/// * `email` - Address signing up
+/// * `mx_resolver` - Looks up the mail servers of the address
+pub async fn check_email_blocks(
+    pool: &PgPool,
+    email: &str,
+    mx_resolver: &dyn MxResolver,
+) -> Result<(), AuthError> {
+    let blocked = CanonicalEmailBlock::is_blocked(pool, email)
+        .await
+        .map_err(|e| match e {
+            CanonicalEmailBlockError::Database(e) => AuthError::Database(e),
+            _ => AuthError::Validation("Email address is invalid".to_string()),
+        })?
+        || EmailDomainBlock::is_blocked(pool, email, mx_resolver)
+            .await
+            .map_err(|e| match e {
+                EmailDomainBlockError::Database(e) => AuthError::Database(e),
+                _ => AuthError::Validation("Email address is invalid".to_string()),
+            })?;
+
+    if blocked {
+        info!("Rejected sign-up from blocked email address {}", email);
+        return Err(AuthError::EmailBlocked(email.to_string()));
+    }
+    Ok(())
+}
+
 /// Register a new user
 ///
 /// # Arguments
 /// * `pool` - Database connection pool
 /// * `request` - Registration request
+/// * `mx_resolver` - Looks up the mail servers of the email address, for
+///   email domain blocks
+/// * `registrations_mode` - Whether the instance takes sign-ups, and
+///   whether they wait for staff approval
+/// * `origin` - Where the sign-up comes from
 ///
 /// # Returns
-/// Result with AuthSession or AuthError
+/// Result with the Registration or AuthError. Accounts waiting for staff
+/// approval get no session.
 ///
 /// # Examples
 ///
 /// ```rust
-/// use rustodon_auth::{register_user, RegisterRequest};
+/// use rustodon_auth::{register_user, RegisterRequest, SignUpOrigin};
+/// use rustodon_config::RegistrationsMode;
 /// use rustodon_db::init_database;
+/// use rustodon_email_domain_blocks::StaticMxResolver;
 /// #[tokio::main]
 /// async fn main() {
 ///     let pool = init_database().await.unwrap();
@@ -364,16 +453,31 @@ pub fn hash_password(password: &str) -> Result<String, AuthError> {
 ///         email: "new@example.com".to_string(),
 ///         password: "password123".to_string(),
 ///     };
-///     let session = register_user(&pool, request).await.unwrap();
-///     println!("User registered with ID: {}", session.user_id);
+///     let registration = register_user(
+///         &pool,
+///         request,
+///         &StaticMxResolver::new(),
+///         RegistrationsMode::Open,
+///         SignUpOrigin::default(),
+///     )
+///     .await
+///     .unwrap();
+///     println!("User registered with ID: {}", registration.user_id());
 /// }
 /// ```
 pub async fn register_user(
     pool: &PgPool,
     request: RegisterRequest,
-) -> Result<AuthSession, AuthError> {
+    mx_resolver: &dyn MxResolver,
+    registrations_mode: RegistrationsMode,
+    origin: SignUpOrigin,
+) -> Result<Registration, AuthError> {
     info!("Registering new user: {}", request.username);
 
+    if registrations_mode == RegistrationsMode::Closed {
+        return Err(AuthError::RegistrationsClosed);
+    }
+
     // Validate input
     if request.username.len() < 3 {
         return Err(AuthError::Validation(
@@ -387,6 +491,8 @@ pub async fn register_user(
         ));
     }
 
+    check_email_blocks(pool, &request.email, mx_resolver).await?;
+
     // Check if user already exists
     if User::get_by_username(pool, &request.username)
         .await?
@@ -413,11 +519,18 @@ pub async fn register_user(
     )
     .await?;
 
+    if origin.requires_approval || registrations_mode == RegistrationsMode::Approved {
+        info!("User {} waits for approval", request.username);
+        User::set_approved(pool, user.id, false).await?;
+        return Ok(Registration::PendingApproval { user_id: user.id });
+    }
+    User::record_sign_in(pool, user.id, origin.ip).await?;
+
     // Create session
     let session = AuthSession::new(user.id, 24);
 
     debug!("User registered successfully: {}", request.username);
-    Ok(session)
+    Ok(Registration::SignedIn(session))
 }
 
 /// Authenticate a user
@@ -425,6 +538,7 @@ pub async fn register_user(
 /// # Arguments
 /// * `pool` - Database connection pool
 /// * `request` - Login request
+/// * `ip` - Address of the client, if known
 ///
 /// # Returns
 /// Result with AuthSession or AuthError
@@ -441,14 +555,18 @@ pub async fn register_user(
 ///         username_or_email: "exampleuser".to_string(),
 ///         password: "password123".to_string(),
 ///     };
-///     let result = login_user(&pool, request).await;
+///     let result = login_user(&pool, request, None).await;
 ///     match result {
 ///         Ok(session) => println!("User logged in with ID: {}", session.user_id),
 ///         Err(_) => println!("Login failed"),
 ///     }
 /// }
 /// ```
-pub async fn login_user(pool: &PgPool, request: LoginRequest) -> Result<AuthSession, AuthError> {
+pub async fn login_user(
+    pool: &PgPool,
+    request: LoginRequest,
+    ip: Option<IpAddr>,
+) -> Result<AuthSession, AuthError> {
     info!("User login attempt: {}", request.username_or_email);
 
     // Try to find user by username or email
@@ -464,11 +582,27 @@ pub async fn login_user(pool: &PgPool, request: LoginRequest) -> Result<AuthSess
         AuthError::UserNotFound(format!("User {} not found", request.username_or_email))
     })?;
 
+    // Deleted accounts only remain as tombstones
+    if user.is_deleted(pool).await? {
+        return Err(AuthError::InvalidCredentials);
+    }
+
     // Verify password
     if !verify_password(&request.password, &user.password_hash)? {
         return Err(AuthError::InvalidCredentials);
     }
 
+    // Accounts waiting for approval can't log in yet, and disabled and
+    // suspended accounts can't log in at all
+    if !user.is_approved(pool).await? {
+        return Err(AuthError::PendingApproval);
+    }
+    if !user.is_functional(pool).await? {
+        return Err(AuthError::AccountDisabled);
+    }
+
+    User::record_sign_in(pool, user.id, ip).await?;
+
     // Create session
     let session = AuthSession::new(user.id, 24);
 
@@ -537,6 +671,11 @@ pub async fn get_user_by_session(pool: &PgPool, token: &str) -> Result<Option<Us
     // Get user from database
     match User::get_by_id(pool, user_id).await {
         Ok(Some(user)) => {
+            // Sessions of deleted, disabled and suspended accounts are revoked
+            if !user.is_functional(pool).await? {
+                debug!("Session belongs to restricted account {}", user.id);
+                return Ok(None);
+            }
             debug!("Found user {} for session token", user.username);
             Ok(Some(user))
         }
@@ -571,6 +710,7 @@ pub async fn logout_user(_pool: &PgPool, _token: &str) -> Result<(), AuthError>
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rustodon_email_domain_blocks::StaticMxResolver;
     use sqlx::PgPool;
 
     /// Helper function to establish database connection for tests
@@ -610,10 +750,19 @@ mod tests {
             password: "password123".to_string(),
         };
 
-        let result = register_user(&pool, request).await;
+        let result = register_user(
+            &pool,
+            request,
+            &StaticMxResolver::new(),
+            RegistrationsMode::Open,
+            SignUpOrigin::default(),
+        )
+        .await;
         assert!(result.is_ok());
 
-        let session = result.unwrap();
+        let Registration::SignedIn(session) = result.unwrap() else {
+            panic!("Sign-up should not need approval");
+        };
         assert!(session.user_id > 0);
         assert!(!session.token.is_empty());
     }
@@ -629,7 +778,14 @@ mod tests {
             password: "password123".to_string(),
         };
 
-        let result = register_user(&pool, request).await;
+        let result = register_user(
+            &pool,
+            request,
+            &StaticMxResolver::new(),
+            RegistrationsMode::Open,
+            SignUpOrigin::default(),
+        )
+        .await;
         assert!(result.is_err());
     }
 
@@ -647,7 +803,15 @@ mod tests {
             email: format!("login_{}@example.com", unique_id),
             password: "password123".to_string(),
         };
-        register_user(&pool, register_request).await.unwrap();
+        register_user(
+            &pool,
+            register_request,
+            &StaticMxResolver::new(),
+            RegistrationsMode::Open,
+            SignUpOrigin::default(),
+        )
+        .await
+        .unwrap();
 
         // Then try to login
         let login_request = LoginRequest {
@@ -655,7 +819,7 @@ mod tests {
             password: "password123".to_string(),
         };
 
-        let result = login_user(&pool, login_request).await;
+        let result = login_user(&pool, login_request, "127.0.0.1".parse().ok()).await;
         assert!(result.is_ok());
     }
 
@@ -669,7 +833,7 @@ mod tests {
             password: "password123".to_string(),
         };
 
-        let result = login_user(&pool, request).await;
+        let result = login_user(&pool, request, None).await;
         assert!(result.is_err());
     }
 }
@@ -1,14 +1,120 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
+//! Rustodon administration command-line tool
+//!
+//! Output helpers of the `rustodon-cli` binary, which lets administrators
+//! work with an instance from a shell, such as browsing the admin action log.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use colored::Colorize;
+use rustodon_admin::{AdminAction, AdminActionType};
+use serde_json::Value;
+
+/// Formats an action log entry as one line
+///
+/// # Arguments
+///
+/// * `action` - The log entry
+///
+/// # Returns
+///
+/// The time, actor, action and target of the entry, followed by its changes
+pub fn format_action(action: &AdminAction) -> String {
+    let actor = match action.account_id {
+        Some(id) => format!("account {}", id),
+        None => "deleted account".to_string(),
+    };
+    let target = match action.target_id {
+        Some(id) => format!("{} {} ({})", action.target_type, id, action.target),
+        None => format!("{} ({})", action.target_type, action.target),
+    };
+    let verb = match action.action_type {
+        AdminActionType::Create => action.action.green(),
+        AdminActionType::Update => action.action.yellow(),
+        AdminActionType::Delete => action.action.red(),
+    };
+
+    let mut line = format!(
+        "#{} {} {} {} {}",
+        action.id,
+        action.created_at.format("%Y-%m-%d %H:%M:%S"),
+        actor,
+        verb,
+        target
+    );
+    let changes = format_changes(&action.changes);
+    if !changes.is_empty() {
+        line.push_str(": ");
+        line.push_str(&changes);
+    }
+    line
+}
+
+/// Formats the changes of an action log entry
+///
+/// # Arguments
+///
+/// * `changes` - Changes as `{"field": {"before": ..., "after": ...}}`
+///
+/// # Returns
+///
+/// The changes as `field before -> after`, separated by commas
+pub fn format_changes(changes: &Value) -> String {
+    let Some(fields) = changes.as_object() else {
+        return String::new();
+    };
+
+    fields
+        .iter()
+        .map(|(field, change)| {
+            format!(
+                "{} {} -> {}",
+                field,
+                change.get("before").unwrap_or(&Value::Null),
+                change.get("after").unwrap_or(&Value::Null)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+
+    #[test]
+    fn test_format_changes() {
+        let changes = json!({
+            "silenced": { "before": true, "after": false },
+            "content": { "before": null, "after": "Spam wave" },
+        });
+        assert_eq!(
+            format_changes(&changes),
+            "content null -> \"Spam wave\", silenced true -> false"
+        );
+        assert_eq!(format_changes(&json!({})), "");
+    }
 
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn test_format_action() {
+        colored::control::set_override(false);
+        let action = AdminAction {
+            id: 7,
+            account_id: None,
+            action_type: AdminActionType::Update,
+            action: "unsilence".to_string(),
+            target_type: "Account".to_string(),
+            target_id: Some(42),
+            target: "alice".to_string(),
+            changes: json!({ "silenced": { "before": true, "after": false } }),
+            created_at: Utc.with_ymd_and_hms(2025, 7, 29, 12, 0, 0).unwrap(),
+        };
+        assert_eq!(
+            format_action(&action),
+            "#7 2025-07-29 12:00:00 deleted account unsilence Account 42 (alice): silenced true -> false"
+        );
     }
 }
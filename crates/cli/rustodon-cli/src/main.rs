@@ -0,0 +1,118 @@
+//!
+//! Rustodon CLI
+//!
+//! Command-line tool for administering a Rustodon instance.
+//!
+//! # Usage
+//!
+//! ```sh
+//! cargo run -p rustodon-cli -- action-logs --target-type Account --target-id 42
+//! cargo run -p rustodon-cli -- action-logs --account-id 1 --since 2025-07-01T00:00:00Z --json
+//! ```
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand};
+use rustodon_admin::{ActionLogFilter, AdminAction, AdminActionType};
+use rustodon_cli::format_action;
+
+#[derive(Parser, Debug)]
+#[command(name = "rustodon-cli")]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Browse the admin action log, newest first
+    ActionLogs {
+        /// Only actions taken by this account
+        #[arg(long)]
+        account_id: Option<i64>,
+        /// Only actions of this type (create, update or delete)
+        #[arg(long, value_parser = parse_action_type)]
+        action_type: Option<AdminActionType>,
+        /// Only this action, such as `suspend`
+        #[arg(long)]
+        action: Option<String>,
+        /// Only actions on this kind of record, such as `Account`
+        #[arg(long)]
+        target_type: Option<String>,
+        /// Only actions on this record
+        #[arg(long)]
+        target_id: Option<i64>,
+        /// Only actions taken at or after this time (RFC 3339)
+        #[arg(long)]
+        since: Option<DateTime<Utc>>,
+        /// Only actions taken before this time (RFC 3339)
+        #[arg(long)]
+        until: Option<DateTime<Utc>>,
+        /// Only actions older than this entry
+        #[arg(long)]
+        max_id: Option<i64>,
+        /// Number of entries to show
+        #[arg(long)]
+        limit: Option<i64>,
+        /// Print the entries as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Parses an action type argument
+fn parse_action_type(value: &str) -> Result<AdminActionType, String> {
+    AdminActionType::parse(value).ok_or_else(|| format!("invalid action type: {}", value))
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_target(false)
+        .init();
+    let cli = Cli::parse();
+    let database_url = std::env::var("DATABASE_URL").context("DATABASE_URL must be set")?;
+    let pool = sqlx::PgPool::connect(&database_url).await?;
+
+    match cli.command {
+        Commands::ActionLogs {
+            account_id,
+            action_type,
+            action,
+            target_type,
+            target_id,
+            since,
+            until,
+            max_id,
+            limit,
+            json,
+        } => {
+            let filter = ActionLogFilter {
+                account_id,
+                action_type,
+                action,
+                target_type,
+                target_id,
+                since,
+                until,
+                max_id,
+                limit,
+            };
+            let actions = AdminAction::search(&pool, &filter).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&actions)?);
+            } else {
+                for action in &actions {
+                    println!("{}", format_action(action));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
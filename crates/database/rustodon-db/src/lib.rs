@@ -10,6 +10,7 @@
 
 use chrono::NaiveDateTime;
 use sqlx::PgPool;
+use std::net::IpAddr;
 use tracing::{debug, info, trace};
 
 /// User model - simplified for testing
@@ -25,11 +26,79 @@ pub struct User {
     pub bot: bool,
     pub discoverable: bool,
     pub group_account: bool,
+    /// Domain of a remote account (None for local accounts)
+    pub domain: Option<String>,
+    /// ActivityPub actor URI of a remote account
+    pub uri: Option<String>,
+    /// ActivityPub inbox URL of a remote account
+    pub inbox_url: Option<String>,
+    pub followers_count: i32,
+    pub following_count: i32,
+    pub statuses_count: i32,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
 
 impl User {
+    /// Whether this account lives on this server
+    pub fn is_local(&self) -> bool {
+        self.domain.is_none()
+    }
+
+    /// Get the `acct` handle (`username` for local accounts, `username@domain` otherwise)
+    pub fn acct(&self) -> String {
+        match &self.domain {
+            Some(domain) => format!("{}@{}", self.username, domain),
+            None => self.username.clone(),
+        }
+    }
+
+    /// Whether this account is an admin or a moderator
+    pub async fn is_staff(&self, pool: &PgPool) -> Result<bool, sqlx::Error> {
+        let staff = sqlx::query_scalar!(
+            r#"SELECT (admin OR moderator) AS "staff!" FROM users WHERE id = $1"#,
+            self.id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(staff.unwrap_or(false))
+    }
+
+    /// Whether this account was deleted and only remains as a tombstone
+    pub async fn is_deleted(&self, pool: &PgPool) -> Result<bool, sqlx::Error> {
+        let deleted = sqlx::query_scalar!(
+            r#"SELECT (status = 'deleted') AS "deleted!" FROM users WHERE id = $1"#,
+            self.id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(deleted.unwrap_or(true))
+    }
+
+    /// Whether staff approved this account, or it never needed approval
+    pub async fn is_approved(&self, pool: &PgPool) -> Result<bool, sqlx::Error> {
+        let approved = sqlx::query_scalar!("SELECT approved FROM users WHERE id = $1", self.id)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(approved.unwrap_or(false))
+    }
+
+    /// Whether this account may log in and post, i.e. is approved and
+    /// neither disabled, suspended nor deleted
+    pub async fn is_functional(&self, pool: &PgPool) -> Result<bool, sqlx::Error> {
+        let functional = sqlx::query_scalar!(
+            r#"SELECT (approved AND NOT disabled AND status IN ('active', 'unconfirmed')) AS "functional!" FROM users WHERE id = $1"#,
+            self.id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(functional.unwrap_or(false))
+    }
+
     /// Get user by ID
     pub async fn get_by_id(pool: &PgPool, id: i64) -> Result<Option<Self>, sqlx::Error> {
         trace!("Getting user by ID: {}", id);
@@ -38,7 +107,8 @@ impl User {
             User,
             r#"
             SELECT id, username, email, password_hash, display_name, note, locked, bot,
-                   discoverable, group_account, created_at, updated_at
+                   discoverable, group_account, domain, uri, inbox_url, followers_count,
+                   following_count, statuses_count, created_at, updated_at
             FROM users
             WHERE id = $1
             "#,
@@ -67,7 +137,8 @@ impl User {
             User,
             r#"
             SELECT id, username, email, password_hash, display_name, note, locked, bot,
-                   discoverable, group_account, created_at, updated_at
+                   discoverable, group_account, domain, uri, inbox_url, followers_count,
+                   following_count, statuses_count, created_at, updated_at
             FROM users
             WHERE username = $1
             "#,
@@ -93,7 +164,8 @@ impl User {
             User,
             r#"
             SELECT id, username, email, password_hash, display_name, note, locked, bot,
-                   discoverable, group_account, created_at, updated_at
+                   discoverable, group_account, domain, uri, inbox_url, followers_count,
+                   following_count, statuses_count, created_at, updated_at
             FROM users
             WHERE email = $1
             "#,
@@ -128,7 +200,8 @@ impl User {
             INSERT INTO users (email, username, password_hash, display_name, note, created_at, updated_at)
             VALUES ($1, $2, $3, $4, $5, NOW(), NOW())
             RETURNING id, username, email, password_hash, display_name, note, locked, bot,
-                      discoverable, group_account, created_at, updated_at
+                      discoverable, group_account, domain, uri, inbox_url, followers_count,
+                      following_count, statuses_count, created_at, updated_at
             "#,
             email,
             username,
@@ -143,6 +216,55 @@ impl User {
         Ok(user)
     }
 
+    /// Records a sign-in, moving the current sign-in time and address to
+    /// the last ones
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `id` - User ID
+    /// * `ip` - Address the user signed in from, if known
+    pub async fn record_sign_in(
+        pool: &PgPool,
+        id: i64,
+        ip: Option<IpAddr>,
+    ) -> Result<(), sqlx::Error> {
+        trace!("Recording sign-in of user {}", id);
+
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET sign_in_count = sign_in_count + 1,
+                last_sign_in_at = current_sign_in_at,
+                last_sign_in_ip = current_sign_in_ip,
+                current_sign_in_at = NOW(),
+                current_sign_in_ip = $2::TEXT::INET
+            WHERE id = $1
+            "#,
+            id,
+            ip.map(|ip| ip.to_string())
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Sets whether the account of a user is approved by staff
+    pub async fn set_approved(pool: &PgPool, id: i64, approved: bool) -> Result<(), sqlx::Error> {
+        trace!("Setting approval of user {} to {}", id, approved);
+
+        sqlx::query!(
+            "UPDATE users SET approved = $2, updated_at = NOW() WHERE id = $1",
+            id,
+            approved
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
     /// Get all users
     pub async fn get_all(pool: &PgPool) -> Result<Vec<Self>, sqlx::Error> {
         trace!("Getting all users");
@@ -151,7 +273,8 @@ impl User {
             User,
             r#"
             SELECT id, username, email, password_hash, display_name, note, locked, bot,
-                   discoverable, group_account, created_at, updated_at
+                   discoverable, group_account, domain, uri, inbox_url, followers_count,
+                   following_count, statuses_count, created_at, updated_at
             FROM users
             ORDER BY created_at DESC
             "#
@@ -184,7 +307,8 @@ impl DatabaseService {
             User,
             r#"
             SELECT id, username, email, password_hash, display_name, note, locked, bot,
-                   discoverable, group_account, created_at, updated_at
+                   discoverable, group_account, domain, uri, inbox_url, followers_count,
+                   following_count, statuses_count, created_at, updated_at
             FROM users
             ORDER BY created_at DESC
             "#
@@ -0,0 +1,36 @@
+-- Migration: Create follow_requests table
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Adds pending follow requests for locked accounts and the remote actor
+-- columns needed to federate Accept/Reject activities back to remote followers
+
+-- Remote accounts are stored alongside local ones; local accounts have a NULL domain
+ALTER TABLE users
+ADD COLUMN IF NOT EXISTS domain VARCHAR(255),
+ADD COLUMN IF NOT EXISTS uri VARCHAR(2048),
+ADD COLUMN IF NOT EXISTS inbox_url VARCHAR(2048);
+
+CREATE INDEX IF NOT EXISTS idx_users_domain ON users(domain);
+
+COMMENT ON COLUMN users.domain IS 'Domain of a remote account (NULL for local accounts)';
+COMMENT ON COLUMN users.uri IS 'ActivityPub actor URI of a remote account';
+COMMENT ON COLUMN users.inbox_url IS 'ActivityPub inbox URL of a remote account';
+
+-- Create follow_requests table
+CREATE TABLE IF NOT EXISTS follow_requests (
+    id BIGSERIAL PRIMARY KEY,
+    account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    target_account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    show_reblogs BOOLEAN NOT NULL DEFAULT true,
+    notify BOOLEAN NOT NULL DEFAULT false,
+    uri VARCHAR(2048),
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE(account_id, target_account_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_follow_requests_target_account_id ON follow_requests(target_account_id);
+
+CREATE TRIGGER update_follow_requests_updated_at
+    BEFORE UPDATE ON follow_requests
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
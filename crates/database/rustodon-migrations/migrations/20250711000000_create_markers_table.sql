@@ -0,0 +1,20 @@
+-- Migration: Create markers table
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Stores each user's last-read position in the home and notifications
+-- timelines, versioned for optimistic locking across clients
+
+CREATE TABLE IF NOT EXISTS markers (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    timeline VARCHAR(32) NOT NULL CHECK (timeline IN ('home', 'notifications')),
+    last_read_id BIGINT NOT NULL,
+    lock_version INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE(user_id, timeline)
+);
+
+CREATE TRIGGER update_markers_updated_at
+    BEFORE UPDATE ON markers
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,20 @@
+-- Migration: Create rules table
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Server rules shown on the instance page and referenced by reports
+
+CREATE TABLE IF NOT EXISTS rules (
+    id BIGSERIAL PRIMARY KEY,
+    text TEXT NOT NULL,
+    hint TEXT NOT NULL DEFAULT '',
+    priority INTEGER NOT NULL DEFAULT 0,
+    deleted_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_rules_priority ON rules(priority) WHERE deleted_at IS NULL;
+
+CREATE TRIGGER update_rules_updated_at
+    BEFORE UPDATE ON rules
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,26 @@
+-- Migration: Create filter_statuses table
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Adds per-status filters for the v2 filters API and ties filter
+-- keywords to their filter so deleting a filter removes its keywords
+
+-- v2 filters keep their phrases in filter_keywords; the legacy column is unused
+ALTER TABLE filters ALTER COLUMN phrase SET DEFAULT '';
+
+CREATE INDEX IF NOT EXISTS idx_filters_account_id ON filters(account_id);
+
+DELETE FROM filter_keywords WHERE filter_id NOT IN (SELECT id FROM filters);
+
+ALTER TABLE filter_keywords
+ADD CONSTRAINT fk_filter_keywords_filter_id
+FOREIGN KEY (filter_id) REFERENCES filters(id) ON DELETE CASCADE;
+
+CREATE INDEX IF NOT EXISTS idx_filter_keywords_filter_id ON filter_keywords(filter_id);
+
+-- Create filter_statuses table
+CREATE TABLE IF NOT EXISTS filter_statuses (
+    id BIGSERIAL PRIMARY KEY,
+    filter_id BIGINT NOT NULL REFERENCES filters(id) ON DELETE CASCADE,
+    status_id BIGINT NOT NULL REFERENCES statuses(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE(filter_id, status_id)
+);
@@ -0,0 +1,60 @@
+-- Migration: Create notification policy tables
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Adds notification grouping, per-user notification policies and
+-- the filtered notification requests inbox
+
+-- Group key shared by notifications shown as one group (NULL = never grouped)
+ALTER TABLE notifications ADD COLUMN IF NOT EXISTS group_key VARCHAR(255);
+
+-- Notifications held back by the recipient's notification policy
+ALTER TABLE notifications ADD COLUMN IF NOT EXISTS filtered BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX IF NOT EXISTS idx_notifications_account_id_id ON notifications(account_id, id DESC);
+CREATE INDEX IF NOT EXISTS idx_notifications_account_id_group_key ON notifications(account_id, group_key);
+
+-- Create notification_policies table
+CREATE TABLE IF NOT EXISTS notification_policies (
+    id BIGSERIAL PRIMARY KEY,
+    account_id BIGINT NOT NULL UNIQUE REFERENCES users(id) ON DELETE CASCADE,
+    filter_not_following BOOLEAN NOT NULL DEFAULT FALSE,
+    filter_not_followers BOOLEAN NOT NULL DEFAULT FALSE,
+    filter_new_accounts BOOLEAN NOT NULL DEFAULT FALSE,
+    filter_private_mentions BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- One request per sender whose notifications were filtered
+CREATE TABLE IF NOT EXISTS notification_requests (
+    id BIGSERIAL PRIMARY KEY,
+    account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    from_account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    last_notification_id BIGINT NOT NULL,
+    notifications_count INTEGER NOT NULL DEFAULT 0,
+    dismissed BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE(account_id, from_account_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_notification_requests_account_id ON notification_requests(account_id, id DESC);
+
+-- Senders whose requests were accepted bypass the policy from then on
+CREATE TABLE IF NOT EXISTS notification_permissions (
+    id BIGSERIAL PRIMARY KEY,
+    account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    from_account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE(account_id, from_account_id)
+);
+
+-- Create trigger to update updated_at timestamp
+CREATE TRIGGER update_notification_policies_updated_at
+    BEFORE UPDATE ON notification_policies
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+CREATE TRIGGER update_notification_requests_updated_at
+    BEFORE UPDATE ON notification_requests
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
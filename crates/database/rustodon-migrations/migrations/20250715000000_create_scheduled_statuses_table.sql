@@ -0,0 +1,24 @@
+-- Migration: Create scheduled_statuses table
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Creates the scheduled_statuses table holding statuses to be
+-- published later, with the parameters they were submitted with
+
+-- Create scheduled_statuses table
+CREATE TABLE IF NOT EXISTS scheduled_statuses (
+    id BIGSERIAL PRIMARY KEY,
+    account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    scheduled_at TIMESTAMP NOT NULL,
+    params JSONB NOT NULL DEFAULT '{}'::jsonb,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Create indexes for performance
+CREATE INDEX IF NOT EXISTS idx_scheduled_statuses_account_id ON scheduled_statuses(account_id, scheduled_at);
+CREATE INDEX IF NOT EXISTS idx_scheduled_statuses_scheduled_at ON scheduled_statuses(scheduled_at);
+
+-- Create trigger to update updated_at timestamp
+CREATE TRIGGER update_scheduled_statuses_updated_at
+    BEFORE UPDATE ON scheduled_statuses
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
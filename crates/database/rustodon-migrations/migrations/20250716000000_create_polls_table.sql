@@ -0,0 +1,40 @@
+-- Migration: Create polls tables
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Creates the polls table attached to statuses and the
+-- poll_votes table holding one row per chosen option
+
+-- Create polls table
+CREATE TABLE IF NOT EXISTS polls (
+    id BIGSERIAL PRIMARY KEY,
+    account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    status_id BIGINT NOT NULL UNIQUE REFERENCES statuses(id) ON DELETE CASCADE,
+    options TEXT[] NOT NULL,
+    multiple BOOLEAN NOT NULL DEFAULT FALSE,
+    hide_totals BOOLEAN NOT NULL DEFAULT FALSE,
+    expires_at TIMESTAMP,
+    -- Set once the poll has ended and its voters were notified
+    closed_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Create poll_votes table
+CREATE TABLE IF NOT EXISTS poll_votes (
+    id BIGSERIAL PRIMARY KEY,
+    poll_id BIGINT NOT NULL REFERENCES polls(id) ON DELETE CASCADE,
+    account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    choice INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE(poll_id, account_id, choice)
+);
+
+-- Create indexes for performance
+CREATE INDEX IF NOT EXISTS idx_polls_open_expires_at ON polls(expires_at) WHERE closed_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_poll_votes_poll_id ON poll_votes(poll_id, choice);
+CREATE INDEX IF NOT EXISTS idx_poll_votes_account_id ON poll_votes(account_id);
+
+-- Create trigger to update updated_at timestamp
+CREATE TRIGGER update_polls_updated_at
+    BEFORE UPDATE ON polls
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
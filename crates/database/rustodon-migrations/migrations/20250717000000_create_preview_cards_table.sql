@@ -0,0 +1,41 @@
+-- Migration: Create preview_cards tables
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Creates link preview cards, shared by every status linking to
+-- the same URL, and the table attaching them to statuses
+
+-- Create preview_cards table
+CREATE TABLE IF NOT EXISTS preview_cards (
+    id BIGSERIAL PRIMARY KEY,
+    url TEXT NOT NULL UNIQUE,
+    title VARCHAR(255) NOT NULL DEFAULT '',
+    description TEXT NOT NULL DEFAULT '',
+    card_type VARCHAR(16) NOT NULL DEFAULT 'link',
+    author_name VARCHAR(255) NOT NULL DEFAULT '',
+    author_url TEXT NOT NULL DEFAULT '',
+    provider_name VARCHAR(255) NOT NULL DEFAULT '',
+    provider_url TEXT NOT NULL DEFAULT '',
+    html TEXT NOT NULL DEFAULT '',
+    width INTEGER NOT NULL DEFAULT 0,
+    height INTEGER NOT NULL DEFAULT 0,
+    image_url TEXT,
+    blurhash VARCHAR(255),
+    embed_url TEXT NOT NULL DEFAULT '',
+    language VARCHAR(10),
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- A status shows at most one card
+CREATE TABLE IF NOT EXISTS preview_cards_statuses (
+    status_id BIGINT PRIMARY KEY REFERENCES statuses(id) ON DELETE CASCADE,
+    preview_card_id BIGINT NOT NULL REFERENCES preview_cards(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_preview_cards_statuses_preview_card_id ON preview_cards_statuses(preview_card_id);
+
+-- Create trigger to update updated_at timestamp
+CREATE TRIGGER update_preview_cards_updated_at
+    BEFORE UPDATE ON preview_cards
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,24 @@
+-- Migration: Create mentions and statuses_tags tables
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Records the accounts mentioned by a status and the hashtags
+-- it uses, as extracted from the status text
+
+-- Create mentions table
+CREATE TABLE IF NOT EXISTS mentions (
+    id BIGSERIAL PRIMARY KEY,
+    status_id BIGINT NOT NULL REFERENCES statuses(id) ON DELETE CASCADE,
+    account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE(status_id, account_id)
+);
+
+-- Create statuses_tags table
+CREATE TABLE IF NOT EXISTS statuses_tags (
+    status_id BIGINT NOT NULL REFERENCES statuses(id) ON DELETE CASCADE,
+    tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+    PRIMARY KEY (status_id, tag_id)
+);
+
+-- Create indexes for performance
+CREATE INDEX IF NOT EXISTS idx_mentions_account_id ON mentions(account_id, status_id);
+CREATE INDEX IF NOT EXISTS idx_statuses_tags_tag_id ON statuses_tags(tag_id, status_id);
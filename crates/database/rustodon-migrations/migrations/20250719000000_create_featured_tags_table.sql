@@ -0,0 +1,16 @@
+-- Migration: Create featured_tags table
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Hashtags pinned to an account's profile
+
+-- Create featured_tags table
+CREATE TABLE IF NOT EXISTS featured_tags (
+    id BIGSERIAL PRIMARY KEY,
+    account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE(account_id, tag_id)
+);
+
+-- Create indexes for performance
+CREATE INDEX IF NOT EXISTS idx_featured_tags_account_id ON featured_tags(account_id, id);
+CREATE INDEX IF NOT EXISTS idx_tag_follows_tag_id ON tag_follows(tag_id, account_id);
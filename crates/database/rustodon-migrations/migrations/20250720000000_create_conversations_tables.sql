@@ -0,0 +1,37 @@
+-- Migration: Create conversations and account_conversations tables
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Threads statuses into conversations and tracks, for each
+-- participant of a direct message thread, its other participants, last
+-- status and unread state
+
+-- Create conversations table
+CREATE TABLE IF NOT EXISTS conversations (
+    id BIGSERIAL PRIMARY KEY,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Link statuses to their conversation
+ALTER TABLE statuses
+    ADD COLUMN IF NOT EXISTS conversation_id BIGINT REFERENCES conversations(id) ON DELETE SET NULL;
+
+-- Create account_conversations table
+CREATE TABLE IF NOT EXISTS account_conversations (
+    id BIGSERIAL PRIMARY KEY,
+    account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    conversation_id BIGINT NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    participant_account_ids BIGINT[] NOT NULL DEFAULT '{}',
+    status_ids BIGINT[] NOT NULL DEFAULT '{}',
+    last_status_id BIGINT REFERENCES statuses(id) ON DELETE SET NULL,
+    unread BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE(account_id, conversation_id, participant_account_ids)
+);
+
+-- Create indexes for performance
+CREATE INDEX IF NOT EXISTS idx_statuses_conversation_id ON statuses(conversation_id);
+CREATE INDEX IF NOT EXISTS idx_account_conversations_account_id
+    ON account_conversations(account_id, last_status_id DESC);
+CREATE INDEX IF NOT EXISTS idx_account_conversations_last_status_id
+    ON account_conversations(last_status_id);
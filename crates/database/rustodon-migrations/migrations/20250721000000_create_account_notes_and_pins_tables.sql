@@ -0,0 +1,28 @@
+-- Migration: Create account_notes and account_pins tables
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Private notes accounts keep on other accounts, and accounts
+-- endorsed (featured) on a profile
+
+-- Create account_notes table
+CREATE TABLE IF NOT EXISTS account_notes (
+    id BIGSERIAL PRIMARY KEY,
+    account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    target_account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    comment TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE(account_id, target_account_id)
+);
+
+-- Create account_pins table
+CREATE TABLE IF NOT EXISTS account_pins (
+    id BIGSERIAL PRIMARY KEY,
+    account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    target_account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE(account_id, target_account_id)
+);
+
+-- Create indexes for performance
+CREATE INDEX IF NOT EXISTS idx_account_pins_account_id ON account_pins(account_id, id);
+CREATE INDEX IF NOT EXISTS idx_account_pins_target_account_id ON account_pins(target_account_id);
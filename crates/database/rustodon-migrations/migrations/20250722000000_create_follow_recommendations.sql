@@ -0,0 +1,95 @@
+-- Migration: Create follow recommendation tables and views
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Staff picks, suppressions and dismissals of follow
+-- suggestions, plus the account_summaries and global_follow_recommendations
+-- materialized views adapted from db/views to the users table
+
+-- Accounts staff recommend to everyone
+CREATE TABLE IF NOT EXISTS follow_recommendation_staff_picks (
+    id BIGSERIAL PRIMARY KEY,
+    account_id BIGINT NOT NULL UNIQUE REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Accounts staff never recommend
+CREATE TABLE IF NOT EXISTS follow_recommendation_suppressions (
+    id BIGSERIAL PRIMARY KEY,
+    account_id BIGINT NOT NULL UNIQUE REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Suggestions an account dismissed
+CREATE TABLE IF NOT EXISTS follow_recommendation_mutes (
+    id BIGSERIAL PRIMARY KEY,
+    account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    target_account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE(account_id, target_account_id)
+);
+
+-- Language and sensitivity of the recent statuses of discoverable accounts
+CREATE MATERIALIZED VIEW IF NOT EXISTS account_summaries AS
+SELECT
+  users.id AS account_id,
+  mode() WITHIN GROUP (ORDER BY t0.language ASC) AS language,
+  mode() WITHIN GROUP (ORDER BY t0.sensitive ASC) AS sensitive
+FROM users
+CROSS JOIN LATERAL (
+  SELECT statuses.language, statuses.sensitive
+  FROM statuses
+  WHERE statuses.account_id = users.id
+    AND statuses.deleted_at IS NULL
+    AND statuses.reblog_of_id IS NULL
+  ORDER BY statuses.id DESC
+  LIMIT 20
+) t0
+WHERE users.status = 'active'
+  AND users.discoverable = TRUE
+  AND users.locked = FALSE
+GROUP BY users.id;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_account_summaries_account_id ON account_summaries(account_id);
+
+-- Local accounts popular with active local users or getting many interactions
+CREATE MATERIALIZED VIEW IF NOT EXISTS global_follow_recommendations AS
+SELECT
+  account_id,
+  sum(rank) AS rank,
+  array_agg(reason) AS reason
+FROM (
+  SELECT
+    account_summaries.account_id AS account_id,
+    count(follows.id) / (1.0 + count(follows.id)) AS rank,
+    'most_followed' AS reason
+  FROM follows
+  INNER JOIN account_summaries ON account_summaries.account_id = follows.followed_id
+  INNER JOIN users targets ON targets.id = follows.followed_id
+  INNER JOIN users followers ON followers.id = follows.follower_id
+  WHERE followers.domain IS NULL
+    AND followers.current_sign_in_at >= (NOW() - INTERVAL '30 days')
+    AND targets.domain IS NULL
+    AND account_summaries.sensitive = FALSE
+    AND NOT EXISTS (SELECT 1 FROM follow_recommendation_suppressions WHERE follow_recommendation_suppressions.account_id = follows.followed_id)
+  GROUP BY account_summaries.account_id
+  HAVING count(follows.id) >= 5
+  UNION ALL
+  SELECT
+    account_summaries.account_id AS account_id,
+    sum(statuses.reblogs_count + statuses.favourites_count) / (1.0 + sum(statuses.reblogs_count + statuses.favourites_count)) AS rank,
+    'most_interactions' AS reason
+  FROM statuses
+  INNER JOIN account_summaries ON account_summaries.account_id = statuses.account_id
+  INNER JOIN users ON users.id = statuses.account_id
+  WHERE statuses.created_at >= (NOW() - INTERVAL '30 days')
+    AND statuses.deleted_at IS NULL
+    AND users.domain IS NULL
+    AND account_summaries.sensitive = FALSE
+    AND NOT EXISTS (SELECT 1 FROM follow_recommendation_suppressions WHERE follow_recommendation_suppressions.account_id = statuses.account_id)
+  GROUP BY account_summaries.account_id
+  HAVING sum(statuses.reblogs_count + statuses.favourites_count) >= 5
+) t0
+GROUP BY account_id
+ORDER BY rank DESC;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_global_follow_recommendations_account_id ON global_follow_recommendations(account_id);
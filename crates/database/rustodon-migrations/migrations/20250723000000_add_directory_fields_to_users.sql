@@ -0,0 +1,10 @@
+-- Migration: Add profile directory fields to users
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Lets staff hide discoverable accounts from the profile
+-- directory, and indexes the directory orderings
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS hidden_from_directory BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Create indexes for performance
+CREATE INDEX IF NOT EXISTS idx_users_directory_last_status_at ON users(last_status_at DESC NULLS LAST, id DESC)
+    WHERE discoverable = TRUE AND hidden_from_directory = FALSE;
@@ -0,0 +1,39 @@
+-- Migration: Create bulk_imports and bulk_import_rows tables
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: CSV imports of follows, blocks, mutes, domain blocks,
+-- bookmarks and lists, processed in the background row by row
+
+-- Create bulk_imports table
+CREATE TABLE IF NOT EXISTS bulk_imports (
+    id BIGSERIAL PRIMARY KEY,
+    account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    import_type VARCHAR(32) NOT NULL,
+    mode VARCHAR(16) NOT NULL DEFAULT 'merge',
+    state VARCHAR(16) NOT NULL DEFAULT 'scheduled',
+    original_filename VARCHAR(255),
+    total_items INTEGER NOT NULL DEFAULT 0,
+    processed_items INTEGER NOT NULL DEFAULT 0,
+    imported_items INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    finished_at TIMESTAMP,
+    CHECK (import_type IN ('following', 'blocking', 'muting', 'domain_blocking', 'bookmarks', 'lists')),
+    CHECK (mode IN ('merge', 'overwrite')),
+    CHECK (state IN ('scheduled', 'in_progress', 'finished'))
+);
+
+-- Create bulk_import_rows table
+CREATE TABLE IF NOT EXISTS bulk_import_rows (
+    id BIGSERIAL PRIMARY KEY,
+    bulk_import_id BIGINT NOT NULL REFERENCES bulk_imports(id) ON DELETE CASCADE,
+    data JSONB NOT NULL,
+    target_id BIGINT,
+    error TEXT,
+    processed_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Create indexes for performance
+CREATE INDEX IF NOT EXISTS idx_bulk_imports_account_id ON bulk_imports(account_id, id);
+CREATE INDEX IF NOT EXISTS idx_bulk_imports_unfinished ON bulk_imports(id) WHERE state <> 'finished';
+CREATE INDEX IF NOT EXISTS idx_bulk_import_rows_bulk_import_id ON bulk_import_rows(bulk_import_id, id);
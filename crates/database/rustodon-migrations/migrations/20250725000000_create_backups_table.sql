@@ -0,0 +1,22 @@
+-- Migration: Create backups table
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Archives of account data requested by their owners and
+-- generated in the background
+
+-- Create backups table
+CREATE TABLE IF NOT EXISTS backups (
+    id BIGSERIAL PRIMARY KEY,
+    account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    state VARCHAR(16) NOT NULL DEFAULT 'pending',
+    file_name VARCHAR(255),
+    file_size BIGINT,
+    error TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    finished_at TIMESTAMP,
+    CHECK (state IN ('pending', 'processing', 'finished', 'failed'))
+);
+
+-- Create indexes for performance
+CREATE INDEX IF NOT EXISTS idx_backups_account_id ON backups(account_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_backups_pending ON backups(id) WHERE state = 'pending';
@@ -0,0 +1,17 @@
+-- Migration: Create account_deletion_requests table
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Accounts their owners asked to delete, purged once the
+-- grace period is over
+
+-- Create account_deletion_requests table
+CREATE TABLE IF NOT EXISTS account_deletion_requests (
+    id BIGSERIAL PRIMARY KEY,
+    account_id BIGINT NOT NULL UNIQUE REFERENCES users(id) ON DELETE CASCADE,
+    delete_after TIMESTAMP NOT NULL,
+    purged_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Create indexes for performance
+CREATE INDEX IF NOT EXISTS idx_account_deletion_requests_due
+    ON account_deletion_requests(delete_after) WHERE purged_at IS NULL;
@@ -0,0 +1,46 @@
+-- Migration: Create account_warnings and appeals tables
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Strikes moderators issue against accounts, the restrictions
+-- they apply, and the appeals their owners file against them
+
+-- Restrictions a strike can put on an account
+ALTER TABLE users ADD COLUMN IF NOT EXISTS disabled BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS sensitized_at TIMESTAMP;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS silenced_at TIMESTAMP;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS suspended_at TIMESTAMP;
+
+-- Create account_warnings table
+CREATE TABLE IF NOT EXISTS account_warnings (
+    id BIGSERIAL PRIMARY KEY,
+    account_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    target_account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    action VARCHAR(32) NOT NULL DEFAULT 'none',
+    text TEXT NOT NULL DEFAULT '',
+    status_ids BIGINT[] NOT NULL DEFAULT '{}',
+    overruled_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    CONSTRAINT account_warnings_action_check CHECK (
+        action IN ('none', 'disable', 'sensitive', 'silence', 'suspend', 'delete_statuses')
+    )
+);
+
+-- Create appeals table
+CREATE TABLE IF NOT EXISTS appeals (
+    id BIGSERIAL PRIMARY KEY,
+    account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    account_warning_id BIGINT NOT NULL UNIQUE REFERENCES account_warnings(id) ON DELETE CASCADE,
+    text TEXT NOT NULL,
+    approved_at TIMESTAMP,
+    approved_by_account_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    rejected_at TIMESTAMP,
+    rejected_by_account_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Create indexes for performance
+CREATE INDEX IF NOT EXISTS idx_account_warnings_target_account_id
+    ON account_warnings(target_account_id, id DESC);
+CREATE INDEX IF NOT EXISTS idx_appeals_pending
+    ON appeals(id) WHERE approved_at IS NULL AND rejected_at IS NULL;
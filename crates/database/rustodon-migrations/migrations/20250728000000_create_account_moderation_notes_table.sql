@@ -0,0 +1,17 @@
+-- Migration: Create account_moderation_notes table
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Notes staff leave on accounts, only visible to staff
+
+-- Create account_moderation_notes table
+CREATE TABLE IF NOT EXISTS account_moderation_notes (
+    id BIGSERIAL PRIMARY KEY,
+    account_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    target_account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    content TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Create indexes for performance
+CREATE INDEX IF NOT EXISTS idx_account_moderation_notes_target_account_id
+    ON account_moderation_notes(target_account_id, id);
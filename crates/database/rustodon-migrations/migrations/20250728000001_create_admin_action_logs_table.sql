@@ -0,0 +1,22 @@
+-- Migration: Create admin_action_logs table
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Audit log of the actions admins and moderators take
+
+-- Create admin_action_logs table
+CREATE TABLE IF NOT EXISTS admin_action_logs (
+    id BIGSERIAL PRIMARY KEY,
+    account_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    action_type VARCHAR(16) NOT NULL,
+    action VARCHAR(64) NOT NULL,
+    target_type VARCHAR(64) NOT NULL,
+    target_id BIGINT,
+    target TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    CONSTRAINT admin_action_logs_action_type_check CHECK (
+        action_type IN ('create', 'update', 'delete')
+    )
+);
+
+-- Create indexes for performance
+CREATE INDEX IF NOT EXISTS idx_admin_action_logs_account_id ON admin_action_logs(account_id, id DESC);
+CREATE INDEX IF NOT EXISTS idx_admin_action_logs_target ON admin_action_logs(target_type, target_id);
@@ -0,0 +1,31 @@
+-- Migration: Make admin_action_logs append-only
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Records what each staff action changed, and rejects any
+-- attempt to edit or remove the log
+
+-- What the action changed, as {"field": {"before": ..., "after": ...}}
+ALTER TABLE admin_action_logs ADD COLUMN IF NOT EXISTS changes JSONB NOT NULL DEFAULT '{}';
+
+-- Keep the ID of the acting account even once the account is gone, since
+-- clearing it would be an edit of the log
+ALTER TABLE admin_action_logs DROP CONSTRAINT IF EXISTS admin_action_logs_account_id_fkey;
+
+-- Reject updates, deletions and truncation of the log
+CREATE OR REPLACE FUNCTION admin_action_logs_append_only() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'admin_action_logs is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS admin_action_logs_no_update_or_delete ON admin_action_logs;
+CREATE TRIGGER admin_action_logs_no_update_or_delete
+    BEFORE UPDATE OR DELETE ON admin_action_logs
+    FOR EACH ROW EXECUTE FUNCTION admin_action_logs_append_only();
+
+DROP TRIGGER IF EXISTS admin_action_logs_no_truncate ON admin_action_logs;
+CREATE TRIGGER admin_action_logs_no_truncate
+    BEFORE TRUNCATE ON admin_action_logs
+    FOR EACH STATEMENT EXECUTE FUNCTION admin_action_logs_append_only();
+
+-- Create indexes for performance
+CREATE INDEX IF NOT EXISTS idx_admin_action_logs_created_at ON admin_action_logs(created_at);
@@ -0,0 +1,48 @@
+-- Migration: Create reports and report_notes tables
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Reports filed against accounts, triaged by staff with notes,
+-- and the report attached to admin.report notifications
+
+-- Create reports table
+CREATE TABLE IF NOT EXISTS reports (
+    id BIGSERIAL PRIMARY KEY,
+    account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    target_account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    status_ids BIGINT[] NOT NULL DEFAULT '{}',
+    rule_ids BIGINT[] NOT NULL DEFAULT '{}',
+    comment TEXT NOT NULL DEFAULT '',
+    category VARCHAR(20) NOT NULL DEFAULT 'other'
+        CHECK (category IN ('spam', 'legal', 'violation', 'other')),
+    assigned_account_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    action_taken_at TIMESTAMP,
+    action_taken_by_account_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Create report_notes table
+CREATE TABLE IF NOT EXISTS report_notes (
+    id BIGSERIAL PRIMARY KEY,
+    report_id BIGINT NOT NULL REFERENCES reports(id) ON DELETE CASCADE,
+    account_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    content TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Attach reports to notifications
+ALTER TABLE notifications
+    ADD COLUMN IF NOT EXISTS report_id BIGINT REFERENCES reports(id) ON DELETE CASCADE;
+
+-- Create indexes for performance
+CREATE INDEX IF NOT EXISTS idx_reports_unresolved
+    ON reports(id DESC) WHERE action_taken_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_reports_account_id ON reports(account_id);
+CREATE INDEX IF NOT EXISTS idx_reports_target_account_id ON reports(target_account_id);
+CREATE INDEX IF NOT EXISTS idx_reports_assigned_account_id ON reports(assigned_account_id);
+CREATE INDEX IF NOT EXISTS idx_report_notes_report_id ON report_notes(report_id, id);
+
+CREATE TRIGGER update_reports_updated_at
+    BEFORE UPDATE ON reports
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,30 @@
+-- Migration: Create media_attachments table
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Uploaded and cached media files, as written by rustodon-media
+
+-- Create media_attachments table
+CREATE TABLE IF NOT EXISTS media_attachments (
+    id BIGSERIAL PRIMARY KEY,
+    account_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    status_id BIGINT REFERENCES statuses(id) ON DELETE SET NULL,
+    type VARCHAR(20) NOT NULL,
+    url TEXT,
+    preview_url TEXT,
+    remote_url TEXT,
+    file_name VARCHAR(255),
+    file_size BIGINT,
+    file_content_type VARCHAR(255),
+    meta JSONB NOT NULL DEFAULT '{}',
+    description TEXT,
+    blurhash VARCHAR(255),
+    processing_status VARCHAR(20) NOT NULL DEFAULT 'pending',
+    focus_x REAL,
+    focus_y REAL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Create indexes for performance
+CREATE INDEX IF NOT EXISTS idx_media_attachments_account_id ON media_attachments(account_id);
+CREATE INDEX IF NOT EXISTS idx_media_attachments_status_id ON media_attachments(status_id);
+CREATE INDEX IF NOT EXISTS idx_media_attachments_created_at ON media_attachments(created_at);
@@ -0,0 +1,41 @@
+-- Migration: Create analytics tables
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Daily event counters, cached daily rollups of the admin
+-- dashboard measures and dimensions, and the activity they are based on
+
+-- Create analytics_events table
+CREATE TABLE IF NOT EXISTS analytics_events (
+    name VARCHAR(64) NOT NULL,
+    date DATE NOT NULL,
+    count BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (name, date)
+);
+
+-- Create analytics_rollups table; dimension is '' for measures
+CREATE TABLE IF NOT EXISTS analytics_rollups (
+    key VARCHAR(64) NOT NULL,
+    dimension VARCHAR(255) NOT NULL DEFAULT '',
+    date DATE NOT NULL,
+    value BIGINT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (key, date, dimension)
+);
+
+-- Accounts doing something: posting, favouriting, boosting or signing in
+CREATE OR REPLACE VIEW account_activity AS
+    SELECT account_id, created_at AS at FROM statuses
+    UNION ALL
+    SELECT account_id, created_at AS at FROM favourites
+    UNION ALL
+    SELECT account_id, created_at AS at FROM reblogs
+    UNION ALL
+    SELECT id AS account_id, current_sign_in_at AS at FROM users
+    WHERE current_sign_in_at IS NOT NULL
+    UNION ALL
+    SELECT id AS account_id, last_sign_in_at AS at FROM users
+    WHERE last_sign_in_at IS NOT NULL;
+
+-- Create indexes for performance
+CREATE INDEX IF NOT EXISTS idx_favourites_created_at ON favourites(created_at);
+CREATE INDEX IF NOT EXISTS idx_reblogs_created_at ON reblogs(created_at);
+CREATE INDEX IF NOT EXISTS idx_reports_action_taken_at ON reports(action_taken_at);
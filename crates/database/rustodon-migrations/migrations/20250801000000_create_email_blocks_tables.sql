@@ -0,0 +1,39 @@
+-- Migration: Create canonical_email_blocks and email_domain_blocks tables
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Email addresses and email domains sign-ups are rejected from.
+-- Addresses are stored as hashes of their canonical form; domains may have
+-- child blocks for the mail servers they were resolved to
+
+-- Create canonical_email_blocks table
+CREATE TABLE IF NOT EXISTS canonical_email_blocks (
+    id BIGSERIAL PRIMARY KEY,
+    canonical_email_hash VARCHAR(64) NOT NULL UNIQUE,
+    reference_account_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Create email_domain_blocks table
+CREATE TABLE IF NOT EXISTS email_domain_blocks (
+    id BIGSERIAL PRIMARY KEY,
+    domain VARCHAR(255) NOT NULL UNIQUE,
+    parent_id BIGINT REFERENCES email_domain_blocks(id) ON DELETE CASCADE,
+    reason TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Create indexes for performance
+CREATE INDEX IF NOT EXISTS idx_canonical_email_blocks_reference_account_id
+    ON canonical_email_blocks(reference_account_id);
+CREATE INDEX IF NOT EXISTS idx_email_domain_blocks_parent_id ON email_domain_blocks(parent_id);
+
+CREATE TRIGGER update_canonical_email_blocks_updated_at
+    BEFORE UPDATE ON canonical_email_blocks
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+CREATE TRIGGER update_email_domain_blocks_updated_at
+    BEFORE UPDATE ON email_domain_blocks
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
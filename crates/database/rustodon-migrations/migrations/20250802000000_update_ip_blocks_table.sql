@@ -0,0 +1,15 @@
+-- Migration: Update ip_blocks table
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Keeps blocked ranges in ip_address alone, since INET already
+-- holds the prefix length, and restricts severities to the known ones,
+-- including the sign-up ones enforced at request time
+
+ALTER TABLE ip_blocks DROP CONSTRAINT IF EXISTS ip_blocks_ip_address_cidr_range_key;
+ALTER TABLE ip_blocks DROP COLUMN IF EXISTS cidr_range;
+ALTER TABLE ip_blocks ADD CONSTRAINT ip_blocks_ip_address_key UNIQUE (ip_address);
+
+UPDATE ip_blocks SET severity = 'noop' WHERE severity = 'none';
+ALTER TABLE ip_blocks ALTER COLUMN severity SET DEFAULT 'block';
+ALTER TABLE ip_blocks ADD CONSTRAINT ip_blocks_severity_check CHECK (
+    severity IN ('noop', 'suspend', 'silence', 'block', 'sign_up_requires_approval', 'sign_up_block')
+);
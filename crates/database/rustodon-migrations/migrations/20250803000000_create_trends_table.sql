@@ -0,0 +1,31 @@
+-- Migration: Create trends table
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Trending tags, statuses and links with their decaying scores
+-- and language. Items crossing the review threshold wait in the review queue
+-- until staff approve or reject them; only approved items are public
+
+-- Create trends table
+CREATE TABLE IF NOT EXISTS trends (
+    id BIGSERIAL PRIMARY KEY,
+    trend_type VARCHAR(16) NOT NULL CHECK (trend_type IN ('tag', 'status', 'link')),
+    -- ID of the tag, status or preview card
+    target_id BIGINT NOT NULL,
+    language VARCHAR(10),
+    score DOUBLE PRECISION NOT NULL DEFAULT 0,
+    allowed BOOLEAN NOT NULL DEFAULT FALSE,
+    reviewed_at TIMESTAMP,
+    requested_review_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (trend_type, target_id)
+);
+
+-- Create indexes for performance
+CREATE INDEX IF NOT EXISTS idx_trends_type_score ON trends(trend_type, score DESC);
+CREATE INDEX IF NOT EXISTS idx_trends_review_queue
+    ON trends(trend_type, requested_review_at) WHERE reviewed_at IS NULL;
+
+CREATE TRIGGER update_trends_updated_at
+    BEFORE UPDATE ON trends
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
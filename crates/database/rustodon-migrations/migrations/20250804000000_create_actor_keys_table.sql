@@ -0,0 +1,12 @@
+-- Migration: Create actor keys table
+-- Author: arkSong (arksong2018@gmail.com)
+-- Description: Stores the RSA key pair of each local account, used to sign the
+-- activities it delivers and published on its actor document. Keys are kept
+-- when an account is deleted so that its Delete activity can still be signed
+
+CREATE TABLE IF NOT EXISTS actor_keys (
+    account_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    public_key TEXT NOT NULL,
+    private_key TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
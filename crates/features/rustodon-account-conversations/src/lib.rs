@@ -1,5 +1,332 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
+//! Account conversations module for Rustodon
+//!
+//! Tracks direct message threads as each local participant sees them: who
+//! else takes part, which statuses were exchanged, the last one of them and
+//! whether the participant has read it. A participant removing a thread
+//! only hides it until the next message arrives.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_account_conversations::AccountConversation;
+//!
+//! AccountConversation::add_status(&mut tx, conversation_id, status_id, author_id, &recipient_ids).await?;
+//! let threads = AccountConversation::get_by_account(&pool, account_id, Some(20), None, None, None).await?;
+//! AccountConversation::mark_read(&pool, account_id, threads[0].id).await?;
+//! ```
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use thiserror::Error;
+use tracing::{debug, info, trace};
+
+/// Default number of conversations per page
+const DEFAULT_LIMIT: i64 = 20;
+/// Maximum number of conversations per page
+const MAX_LIMIT: i64 = 40;
+
+/// Custom error type for account conversations module
+#[derive(Error, Debug)]
+pub enum AccountConversationsError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("Conversation not found: {0}")]
+    NotFound(i64),
+}
+
+/// A direct message thread as seen by one participant
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountConversation {
+    /// Unique identifier for the account conversation
+    pub id: i64,
+    /// ID of the participant
+    pub account_id: i64,
+    /// ID of the underlying conversation
+    pub conversation_id: i64,
+    /// IDs of the other participants, sorted
+    pub participant_account_ids: Vec<i64>,
+    /// IDs of the statuses of the thread, oldest first
+    pub status_ids: Vec<i64>,
+    /// ID of the most recent status
+    pub last_status_id: Option<i64>,
+    /// Whether the participant has unread statuses in the thread
+    pub unread: bool,
+    /// When the thread last changed
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Internal struct for database rows
+struct AccountConversationRow {
+    id: i64,
+    account_id: i64,
+    conversation_id: i64,
+    participant_account_ids: Vec<i64>,
+    status_ids: Vec<i64>,
+    last_status_id: Option<i64>,
+    unread: bool,
+    updated_at: NaiveDateTime,
+}
+
+impl From<AccountConversationRow> for AccountConversation {
+    fn from(row: AccountConversationRow) -> Self {
+        Self {
+            id: row.id,
+            account_id: row.account_id,
+            conversation_id: row.conversation_id,
+            participant_account_ids: row.participant_account_ids,
+            status_ids: row.status_ids,
+            last_status_id: row.last_status_id,
+            unread: row.unread,
+            updated_at: DateTime::from_naive_utc_and_offset(row.updated_at, Utc),
+        }
+    }
+}
+
+/// The participants of a direct message, sorted and without duplicates
+fn participants(author_id: i64, recipient_ids: &[i64]) -> Vec<i64> {
+    let mut ids: Vec<i64> = recipient_ids
+        .iter()
+        .copied()
+        .chain(std::iter::once(author_id))
+        .collect();
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+impl AccountConversation {
+    /// Records a direct message in the threads of its local participants
+    ///
+    /// Each local participant gets the status added to its thread with the
+    /// same other participants, which is created if needed. The thread
+    /// becomes unread for everyone but the author, for whom replying marks
+    /// it read.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx` - Transaction the status is being created in
+    /// * `conversation_id` - ID of the conversation of the status
+    /// * `status_id` - ID of the status
+    /// * `author_id` - ID of the author
+    /// * `recipient_ids` - IDs of the mentioned accounts
+    pub async fn add_status(
+        tx: &mut Transaction<'_, Postgres>,
+        conversation_id: i64,
+        status_id: i64,
+        author_id: i64,
+        recipient_ids: &[i64],
+    ) -> Result<(), AccountConversationsError> {
+        let participant_ids = participants(author_id, recipient_ids);
+        trace!(
+            "Adding status {} to conversation {} of {:?}",
+            status_id,
+            conversation_id,
+            participant_ids
+        );
+
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO account_conversations (
+                account_id, conversation_id, participant_account_ids, status_ids,
+                last_status_id, unread
+            )
+            SELECT u.id, $1,
+                   ARRAY(SELECT p FROM UNNEST($2::BIGINT[]) AS p WHERE p <> u.id ORDER BY p),
+                   ARRAY[$3::BIGINT], $3, u.id <> $4
+            FROM users u
+            WHERE u.id = ANY($2) AND u.domain IS NULL
+            ON CONFLICT (account_id, conversation_id, participant_account_ids) DO UPDATE
+            SET status_ids = array_append(account_conversations.status_ids, EXCLUDED.last_status_id),
+                last_status_id = EXCLUDED.last_status_id,
+                unread = EXCLUDED.unread,
+                updated_at = NOW()
+            "#,
+            conversation_id,
+            &participant_ids,
+            status_id,
+            author_id
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        debug!(
+            "Status {} added to {} conversation threads",
+            status_id,
+            result.rows_affected()
+        );
+        Ok(())
+    }
+
+    /// Gets a page of an account's threads, most recently active first
+    ///
+    /// Threads are paged by the ID of their last status.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the participant
+    /// * `limit` - Maximum number of threads
+    /// * `max_id` - Only threads whose last status is older than this ID
+    /// * `since_id` - Only threads whose last status is newer than this ID
+    /// * `min_id` - Like `since_id`, but the threads right after it
+    pub async fn get_by_account(
+        pool: &PgPool,
+        account_id: i64,
+        limit: Option<i64>,
+        max_id: Option<i64>,
+        since_id: Option<i64>,
+        min_id: Option<i64>,
+    ) -> Result<Vec<Self>, AccountConversationsError> {
+        trace!("Getting conversations of account {}", account_id);
+
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        let lower_bound = match (min_id, since_id) {
+            (Some(min_id), Some(since_id)) => Some(min_id.max(since_id)),
+            (min_id, since_id) => min_id.or(since_id),
+        };
+        let rows = if min_id.is_some() {
+            sqlx::query_as!(
+                AccountConversationRow,
+                r#"
+                SELECT id, account_id, conversation_id, participant_account_ids, status_ids,
+                       last_status_id, unread, updated_at
+                FROM account_conversations
+                WHERE account_id = $1
+                  AND last_status_id IS NOT NULL
+                  AND ($2::BIGINT IS NULL OR last_status_id < $2)
+                  AND ($3::BIGINT IS NULL OR last_status_id > $3)
+                ORDER BY last_status_id ASC
+                LIMIT $4
+                "#,
+                account_id,
+                max_id,
+                lower_bound,
+                limit
+            )
+            .fetch_all(pool)
+            .await?
+        } else {
+            sqlx::query_as!(
+                AccountConversationRow,
+                r#"
+                SELECT id, account_id, conversation_id, participant_account_ids, status_ids,
+                       last_status_id, unread, updated_at
+                FROM account_conversations
+                WHERE account_id = $1
+                  AND last_status_id IS NOT NULL
+                  AND ($2::BIGINT IS NULL OR last_status_id < $2)
+                  AND ($3::BIGINT IS NULL OR last_status_id > $3)
+                ORDER BY last_status_id DESC
+                LIMIT $4
+                "#,
+                account_id,
+                max_id,
+                lower_bound,
+                limit
+            )
+            .fetch_all(pool)
+            .await?
+        };
+
+        let mut conversations: Vec<Self> = rows.into_iter().map(Self::from).collect();
+        if min_id.is_some() {
+            conversations.reverse();
+        }
+        debug!(
+            "Retrieved {} conversations for account {}",
+            conversations.len(),
+            account_id
+        );
+        Ok(conversations)
+    }
+
+    /// Gets the threads whose last status is the given one
+    ///
+    /// Used to push the threads a new direct message just updated.
+    pub async fn get_by_last_status(
+        pool: &PgPool,
+        status_id: i64,
+    ) -> Result<Vec<Self>, AccountConversationsError> {
+        let rows = sqlx::query_as!(
+            AccountConversationRow,
+            r#"
+            SELECT id, account_id, conversation_id, participant_account_ids, status_ids,
+                   last_status_id, unread, updated_at
+            FROM account_conversations
+            WHERE last_status_id = $1
+            ORDER BY id
+            "#,
+            status_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    /// Marks a thread as read
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the participant
+    /// * `id` - ID of the thread
+    ///
+    /// # Returns
+    ///
+    /// The updated thread
+    pub async fn mark_read(
+        pool: &PgPool,
+        account_id: i64,
+        id: i64,
+    ) -> Result<Self, AccountConversationsError> {
+        let row = sqlx::query_as!(
+            AccountConversationRow,
+            r#"
+            UPDATE account_conversations
+            SET unread = FALSE, updated_at = NOW()
+            WHERE id = $1 AND account_id = $2
+            RETURNING id, account_id, conversation_id, participant_account_ids, status_ids,
+                      last_status_id, unread, updated_at
+            "#,
+            id,
+            account_id
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(AccountConversationsError::NotFound(id))?;
+
+        debug!("Account {} read conversation {}", account_id, id);
+        Ok(row.into())
+    }
+
+    /// Removes a thread from an account's conversations
+    ///
+    /// The statuses stay; a new message in the thread brings it back.
+    pub async fn remove(
+        pool: &PgPool,
+        account_id: i64,
+        id: i64,
+    ) -> Result<(), AccountConversationsError> {
+        let result = sqlx::query!(
+            "DELETE FROM account_conversations WHERE id = $1 AND account_id = $2",
+            id,
+            account_id
+        )
+        .execute(pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(AccountConversationsError::NotFound(id));
+        }
+
+        info!("Account {} removed conversation {}", account_id, id);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -7,8 +334,9 @@ mod tests {
     use super::*;
 
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn test_participants() {
+        assert_eq!(participants(3, &[7, 1, 7]), vec![1, 3, 7]);
+        assert_eq!(participants(3, &[3]), vec![3]);
+        assert_eq!(participants(3, &[]), vec![3]);
     }
 }
@@ -1,5 +1,441 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
+//! Account self-deletion for Rustodon
+//!
+//! Local accounts can ask for their own deletion by confirming their
+//! password. The request starts a grace period during which the account
+//! keeps working and the deletion can be cancelled. Once the grace period
+//! is over, a background job purges the account: its statuses and media,
+//! follows, blocks, mutes, lists, notifications and the other records it
+//! owns are removed, its sessions stop working, and a `Delete` activity is
+//! sent to every known inbox.
+//!
+//! The account row itself is kept as a tombstone, stripped of personal data
+//! and marked `deleted`, so its username cannot be registered again and its
+//! actor URI is answered with a `Tombstone`.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_account_deletion_requests::AccountDeletionRequest;
+//!
+//! let request = AccountDeletionRequest::create(&pool, account_id, "password", 30).await?;
+//! AccountDeletionRequest::cancel(&pool, account_id).await?;
+//! ```
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use rustodon_activitypub::{actor_key_id, delete_actor_activity, local_actor_uri};
+use rustodon_auth::verify_password;
+use rustodon_backups::backups_directory;
+use rustodon_federation::{ActorKey, DeliveryClient, FederationError};
+use rustodon_media::{MediaProcessor, StorageConfig};
+use rustodon_workers::{Job, WorkerError};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::future::Future;
+use std::pin::Pin;
+use thiserror::Error;
+use tracing::{debug, info, trace, warn};
+
+/// Error type for account deletion operations
+#[derive(Error, Debug)]
+pub enum AccountDeletionError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("Authentication error: {0}")]
+    Auth(String),
+    #[error("Invalid password")]
+    InvalidPassword,
+    #[error("Account deletion already requested")]
+    AlreadyRequested,
+    #[error("No pending deletion for account {0}")]
+    NotFound(i64),
+    #[error("Account not found: {0}")]
+    AccountNotFound(i64),
+    #[error("Federation error: {0}")]
+    Federation(#[from] FederationError),
+}
+
+/// A request to delete an account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountDeletionRequest {
+    pub id: i64,
+    pub account_id: i64,
+    /// When the grace period ends and the account gets purged
+    pub delete_after: DateTime<Utc>,
+    /// When the account was purged
+    pub purged_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Internal struct for database rows
+struct AccountDeletionRequestRow {
+    id: i64,
+    account_id: i64,
+    delete_after: NaiveDateTime,
+    purged_at: Option<NaiveDateTime>,
+    created_at: NaiveDateTime,
+}
+
+impl From<AccountDeletionRequestRow> for AccountDeletionRequest {
+    fn from(row: AccountDeletionRequestRow) -> Self {
+        Self {
+            id: row.id,
+            account_id: row.account_id,
+            delete_after: DateTime::from_naive_utc_and_offset(row.delete_after, Utc),
+            purged_at: row
+                .purged_at
+                .map(|purged_at| DateTime::from_naive_utc_and_offset(purged_at, Utc)),
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+        }
+    }
+}
+
+/// Statements removing what a purged account owns or left on others' records
+///
+/// Each takes the account ID as `$1`. Counters of statuses are adjusted
+/// before the rows behind them go away; follow counters of other accounts
+/// are kept by the trigger on `follows`. Rows pointing at the account's
+/// statuses are removed before the statuses themselves.
+const PURGE_STATEMENTS: &[&str] = &[
+    "UPDATE statuses SET favourites_count = GREATEST(favourites_count - 1, 0)
+     WHERE id IN (SELECT status_id FROM favourites WHERE account_id = $1)",
+    "UPDATE statuses SET reblogs_count = GREATEST(reblogs_count - 1, 0)
+     WHERE id IN (SELECT reblog_of_id FROM statuses WHERE account_id = $1 AND deleted_at IS NULL)",
+    "DELETE FROM notifications
+     WHERE account_id = $1 OR from_account_id = $1
+        OR status_id IN (SELECT id FROM statuses WHERE account_id = $1)",
+    "DELETE FROM favourites
+     WHERE account_id = $1 OR status_id IN (SELECT id FROM statuses WHERE account_id = $1)",
+    "DELETE FROM bookmarks
+     WHERE account_id = $1 OR status_id IN (SELECT id FROM statuses WHERE account_id = $1)",
+    "DELETE FROM reblogs
+     WHERE account_id = $1 OR status_id IN (SELECT id FROM statuses WHERE account_id = $1)",
+    "DELETE FROM status_pins
+     WHERE account_id = $1 OR status_id IN (SELECT id FROM statuses WHERE account_id = $1)",
+    "DELETE FROM statuses WHERE reblog_of_id IN (SELECT id FROM statuses WHERE account_id = $1)",
+    "DELETE FROM statuses WHERE account_id = $1",
+    "DELETE FROM follows
+     WHERE follower_id = $1 OR followed_id = $1 OR account_id = $1 OR target_account_id = $1",
+    "DELETE FROM follow_requests WHERE account_id = $1 OR target_account_id = $1",
+    "DELETE FROM blocks WHERE blocker_id = $1 OR blocked_id = $1",
+    "DELETE FROM mutes WHERE muter_id = $1 OR muted_id = $1",
+    "DELETE FROM list_accounts
+     WHERE account_id = $1 OR list_id IN (SELECT id FROM lists WHERE account_id = $1)",
+    "DELETE FROM lists WHERE account_id = $1",
+    "DELETE FROM domain_blocks WHERE account_id = $1",
+    "DELETE FROM filter_keywords WHERE filter_id IN (SELECT id FROM filters WHERE account_id = $1)",
+    "DELETE FROM filters WHERE account_id = $1",
+    "DELETE FROM tag_follows WHERE account_id = $1",
+    "DELETE FROM featured_tags WHERE account_id = $1",
+    "DELETE FROM account_conversations WHERE account_id = $1",
+    "DELETE FROM account_notes WHERE account_id = $1 OR target_account_id = $1",
+    "DELETE FROM account_pins WHERE account_id = $1 OR target_account_id = $1",
+    "DELETE FROM markers WHERE user_id = $1",
+    "DELETE FROM scheduled_statuses WHERE account_id = $1",
+    "DELETE FROM poll_votes WHERE account_id = $1",
+    "DELETE FROM notification_requests WHERE account_id = $1 OR from_account_id = $1",
+    "DELETE FROM notification_permissions WHERE account_id = $1 OR from_account_id = $1",
+    "DELETE FROM notification_policies WHERE account_id = $1",
+    "DELETE FROM follow_recommendation_staff_picks WHERE account_id = $1",
+    "DELETE FROM follow_recommendation_suppressions WHERE account_id = $1",
+    "DELETE FROM follow_recommendation_mutes WHERE account_id = $1 OR target_account_id = $1",
+    "DELETE FROM bulk_imports WHERE account_id = $1",
+    "DELETE FROM backups WHERE account_id = $1",
+];
+
+impl AccountDeletionRequest {
+    /// Requests the deletion of a local account
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the account
+    /// * `password` - Current password of the account, as confirmation
+    /// * `grace_period_days` - Days during which the deletion can be cancelled
+    pub async fn create(
+        pool: &PgPool,
+        account_id: i64,
+        password: &str,
+        grace_period_days: i64,
+    ) -> Result<Self, AccountDeletionError> {
+        trace!("Requesting deletion of account {}", account_id);
+
+        let password_hash = sqlx::query_scalar!(
+            "SELECT password_hash FROM users WHERE id = $1 AND domain IS NULL AND status <> 'deleted'",
+            account_id
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(AccountDeletionError::AccountNotFound(account_id))?;
+        if !verify_password(password, &password_hash)
+            .map_err(|e| AccountDeletionError::Auth(e.to_string()))?
+        {
+            return Err(AccountDeletionError::InvalidPassword);
+        }
+
+        let delete_after = (Utc::now() + Duration::days(grace_period_days.max(0))).naive_utc();
+        let row = sqlx::query_as!(
+            AccountDeletionRequestRow,
+            r#"
+            INSERT INTO account_deletion_requests (account_id, delete_after)
+            VALUES ($1, $2)
+            ON CONFLICT (account_id) DO NOTHING
+            RETURNING id, account_id, delete_after, purged_at, created_at
+            "#,
+            account_id,
+            delete_after
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(AccountDeletionError::AlreadyRequested)?;
+
+        info!(
+            "Account {} will be deleted after {}",
+            account_id, row.delete_after
+        );
+        Ok(row.into())
+    }
+
+    /// Cancels the pending deletion of an account
+    pub async fn cancel(pool: &PgPool, account_id: i64) -> Result<(), AccountDeletionError> {
+        let result = sqlx::query!(
+            "DELETE FROM account_deletion_requests WHERE account_id = $1 AND purged_at IS NULL",
+            account_id
+        )
+        .execute(pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(AccountDeletionError::NotFound(account_id));
+        }
+
+        info!("Cancelled deletion of account {}", account_id);
+        Ok(())
+    }
+
+    /// Gets the deletion request of an account, if any
+    pub async fn get(pool: &PgPool, account_id: i64) -> Result<Option<Self>, AccountDeletionError> {
+        let row = sqlx::query_as!(
+            AccountDeletionRequestRow,
+            r#"
+            SELECT id, account_id, delete_after, purged_at, created_at
+            FROM account_deletion_requests
+            WHERE account_id = $1
+            "#,
+            account_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row.map(Self::from))
+    }
+
+    /// Gets the requests whose grace period is over, oldest first
+    pub async fn due(pool: &PgPool) -> Result<Vec<Self>, AccountDeletionError> {
+        let rows = sqlx::query_as!(
+            AccountDeletionRequestRow,
+            r#"
+            SELECT id, account_id, delete_after, purged_at, created_at
+            FROM account_deletion_requests
+            WHERE purged_at IS NULL AND delete_after <= NOW()
+            ORDER BY delete_after
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    /// Purges the account of a request and tells other servers it is gone
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `local_domain` - Domain of this instance
+    /// * `config` - Media storage configuration
+    /// * `delivery` - Client delivering the `Delete` activity
+    pub async fn purge(
+        &self,
+        pool: &PgPool,
+        local_domain: &str,
+        config: &StorageConfig,
+        delivery: &DeliveryClient,
+    ) -> Result<(), AccountDeletionError> {
+        let account_id = self.account_id;
+        let username = sqlx::query_scalar!(
+            "SELECT username FROM users WHERE id = $1 AND domain IS NULL",
+            account_id
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(AccountDeletionError::AccountNotFound(account_id))?;
+        info!("Purging account {} ({})", account_id, username);
+
+        // The key outlives the purge so that the deletion can be signed
+        let actor_uri = local_actor_uri(local_domain, &username);
+        let key = ActorKey::for_account(pool, account_id)
+            .await?
+            .signing_key(&actor_key_id(&actor_uri))?;
+
+        let inboxes = sqlx::query_scalar!(
+            r#"
+            SELECT DISTINCT inbox_url AS "inbox_url!"
+            FROM users
+            WHERE domain IS NOT NULL AND inbox_url IS NOT NULL AND status <> 'deleted'
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        remove_files(pool, account_id, config).await?;
+
+        let mut tx = pool.begin().await?;
+        for statement in PURGE_STATEMENTS {
+            sqlx::query(statement)
+                .bind(account_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET status = 'deleted', email = 'deleted-' || id || '@invalid', password_hash = '',
+                display_name = NULL, note = NULL, avatar = NULL, header = NULL, website = NULL,
+                location = NULL, recovery_email = NULL, confirmation_token = NULL,
+                reset_password_token = NULL, current_sign_in_ip = NULL, last_sign_in_ip = NULL,
+                discoverable = FALSE, admin = FALSE, moderator = FALSE, statuses_count = 0,
+                followers_count = 0, following_count = 0, updated_at = NOW()
+            WHERE id = $1
+            "#,
+            account_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE account_deletion_requests SET purged_at = NOW() WHERE id = $1",
+            self.id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        let activity = delete_actor_activity(&actor_uri);
+        let mut delivered = 0;
+        for inbox in &inboxes {
+            match delivery.deliver(&key, inbox, &activity).await {
+                Ok(()) => delivered += 1,
+                Err(e) => warn!(
+                    "Failed to deliver deletion of {} to {}: {}",
+                    username, inbox, e
+                ),
+            }
+        }
+
+        info!(
+            "Purged account {}; deletion delivered to {} of {} inboxes",
+            account_id,
+            delivered,
+            inboxes.len()
+        );
+        Ok(())
+    }
+}
+
+/// Removes the media and archive files of an account
+async fn remove_files(
+    pool: &PgPool,
+    account_id: i64,
+    config: &StorageConfig,
+) -> Result<(), AccountDeletionError> {
+    let media_ids = sqlx::query_scalar!(
+        "SELECT id FROM media_attachments WHERE account_id = $1",
+        account_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let processor = MediaProcessor::new(pool.clone(), config.clone());
+    for media_id in media_ids {
+        if let Err(e) = processor
+            .delete_media_attachment(media_id, account_id)
+            .await
+        {
+            warn!("Failed to delete media {}: {}", media_id, e);
+        }
+    }
+
+    let archives = sqlx::query_scalar!(
+        r#"SELECT file_name AS "file_name!" FROM backups WHERE account_id = $1 AND file_name IS NOT NULL"#,
+        account_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let directory = backups_directory(config);
+    for file_name in archives {
+        if let Err(e) = tokio::fs::remove_file(directory.join(&file_name)).await {
+            warn!("Failed to delete archive {}: {}", file_name, e);
+        }
+    }
+
+    debug!("Removed files of account {}", account_id);
+    Ok(())
+}
+
+/// Background job purging the accounts whose grace period is over
+pub struct PurgeDeletedAccountsJob {
+    pool: PgPool,
+    local_domain: String,
+    config: StorageConfig,
+}
+
+impl PurgeDeletedAccountsJob {
+    /// Creates the job
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `local_domain` - Domain of this instance
+    /// * `config` - Media storage configuration
+    pub fn new(pool: PgPool, local_domain: String, config: StorageConfig) -> Self {
+        Self {
+            pool,
+            local_domain,
+            config,
+        }
+    }
+}
+
+impl Job for PurgeDeletedAccountsJob {
+    fn name(&self) -> &'static str {
+        "PurgeDeletedAccountsJob"
+    }
+
+    fn execute(&self) -> Pin<Box<dyn Future<Output = Result<(), WorkerError>> + Send>> {
+        let pool = self.pool.clone();
+        let local_domain = self.local_domain.clone();
+        let config = self.config.clone();
+        Box::pin(async move {
+            let requests = AccountDeletionRequest::due(&pool)
+                .await
+                .map_err(|e| WorkerError::Job(e.to_string()))?;
+            if requests.is_empty() {
+                return Ok(());
+            }
+
+            let delivery = DeliveryClient::new().map_err(|e| WorkerError::Job(e.to_string()))?;
+            for request in requests {
+                if let Err(e) = request
+                    .purge(&pool, &local_domain, &config, &delivery)
+                    .await
+                {
+                    warn!("Failed to purge account {}: {}", request.account_id, e);
+                }
+            }
+            Ok(())
+        })
+    }
 }
 
 #[cfg(test)]
@@ -7,8 +443,24 @@ mod tests {
     use super::*;
 
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn test_purge_statements_only_bind_the_account() {
+        for statement in PURGE_STATEMENTS {
+            assert!(statement.contains("$1"), "{}", statement);
+            assert!(!statement.contains("$2"), "{}", statement);
+        }
+    }
+
+    #[test]
+    fn test_statuses_are_purged_after_what_points_at_them() {
+        let position = |needle: &str| {
+            PURGE_STATEMENTS
+                .iter()
+                .position(|statement| statement.starts_with(needle))
+                .unwrap()
+        };
+        let statuses = position("DELETE FROM statuses WHERE account_id");
+        assert!(position("DELETE FROM favourites") < statuses);
+        assert!(position("DELETE FROM notifications") < statuses);
+        assert!(position("UPDATE statuses SET reblogs_count") < statuses);
     }
 }
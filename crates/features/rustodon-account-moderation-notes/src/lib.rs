@@ -1,5 +1,194 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
+//! Account moderation notes for Rustodon
+//!
+//! Staff keep notes on accounts to share context with each other, such as
+//! why an account was limited or what was discussed with its owner. Notes
+//! are only ever shown to staff.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_account_moderation_notes::AccountModerationNote;
+//!
+//! let note = AccountModerationNote::create(&pool, moderator_id, account_id, "Spam wave").await?;
+//! let notes = AccountModerationNote::by_target_account(&pool, account_id).await?;
+//! ```
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+use thiserror::Error;
+use tracing::{info, trace};
+
+/// Maximum length of a note
+pub const MAX_CONTENT_LENGTH: usize = 2_000;
+
+/// Error type for moderation note operations
+#[derive(Error, Debug)]
+pub enum ModerationNoteError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("Validation error: {0}")]
+    Validation(String),
+    #[error("Moderation note not found: {0}")]
+    NotFound(i64),
+    #[error("Account not found: {0}")]
+    AccountNotFound(i64),
+}
+
+/// A note staff left on an account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountModerationNote {
+    pub id: i64,
+    /// Staff member who wrote the note
+    pub account_id: Option<i64>,
+    /// Account the note is about
+    pub target_account_id: i64,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Internal struct for database rows
+struct AccountModerationNoteRow {
+    id: i64,
+    account_id: Option<i64>,
+    target_account_id: i64,
+    content: String,
+    created_at: NaiveDateTime,
+}
+
+impl From<AccountModerationNoteRow> for AccountModerationNote {
+    fn from(row: AccountModerationNoteRow) -> Self {
+        Self {
+            id: row.id,
+            account_id: row.account_id,
+            target_account_id: row.target_account_id,
+            content: row.content,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+        }
+    }
+}
+
+/// Checks the content of a note
+fn validate_content(content: &str) -> Result<&str, ModerationNoteError> {
+    let content = content.trim();
+    if content.is_empty() {
+        return Err(ModerationNoteError::Validation(
+            "Content can't be blank".to_string(),
+        ));
+    }
+    if content.chars().count() > MAX_CONTENT_LENGTH {
+        return Err(ModerationNoteError::Validation(format!(
+            "Content is too long (maximum is {} characters)",
+            MAX_CONTENT_LENGTH
+        )));
+    }
+    Ok(content)
+}
+
+impl AccountModerationNote {
+    /// Leaves a note on an account
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection of the transaction to run in
+    /// * `account_id` - ID of the staff member writing the note
+    /// * `target_account_id` - ID of the account the note is about
+    /// * `content` - Text of the note
+    pub async fn create(
+        conn: &mut PgConnection,
+        account_id: i64,
+        target_account_id: i64,
+        content: &str,
+    ) -> Result<Self, ModerationNoteError> {
+        trace!("Adding moderation note on account {}", target_account_id);
+        let content = validate_content(content)?;
+
+        let row = sqlx::query_as!(
+            AccountModerationNoteRow,
+            r#"
+            INSERT INTO account_moderation_notes (account_id, target_account_id, content)
+            SELECT $1, id, $3 FROM users WHERE id = $2
+            RETURNING id, account_id, target_account_id, content, created_at
+            "#,
+            account_id,
+            target_account_id,
+            content
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(ModerationNoteError::AccountNotFound(target_account_id))?;
+
+        info!(
+            "Account {} added moderation note {} on account {}",
+            account_id, row.id, target_account_id
+        );
+        Ok(row.into())
+    }
+
+    /// Gets a note by ID
+    pub async fn get(pool: &PgPool, id: i64) -> Result<Option<Self>, ModerationNoteError> {
+        let row = sqlx::query_as!(
+            AccountModerationNoteRow,
+            r#"
+            SELECT id, account_id, target_account_id, content, created_at
+            FROM account_moderation_notes
+            WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row.map(AccountModerationNote::from))
+    }
+
+    /// Gets the notes on an account, oldest first
+    pub async fn by_target_account(
+        pool: &PgPool,
+        target_account_id: i64,
+    ) -> Result<Vec<Self>, ModerationNoteError> {
+        let rows = sqlx::query_as!(
+            AccountModerationNoteRow,
+            r#"
+            SELECT id, account_id, target_account_id, content, created_at
+            FROM account_moderation_notes
+            WHERE target_account_id = $1
+            ORDER BY id
+            "#,
+            target_account_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(AccountModerationNote::from).collect())
+    }
+
+    /// Deletes a note
+    ///
+    /// # Returns
+    ///
+    /// The deleted note
+    pub async fn delete(conn: &mut PgConnection, id: i64) -> Result<Self, ModerationNoteError> {
+        let row = sqlx::query_as!(
+            AccountModerationNoteRow,
+            r#"
+            DELETE FROM account_moderation_notes
+            WHERE id = $1
+            RETURNING id, account_id, target_account_id, content, created_at
+            "#,
+            id
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(ModerationNoteError::NotFound(id))?;
+
+        info!("Deleted moderation note {}", id);
+        Ok(row.into())
+    }
 }
 
 #[cfg(test)]
@@ -7,8 +196,9 @@ mod tests {
     use super::*;
 
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn test_validate_content() {
+        assert_eq!(validate_content("  Spam wave  ").unwrap(), "Spam wave");
+        assert!(validate_content("   ").is_err());
+        assert!(validate_content(&"a".repeat(MAX_CONTENT_LENGTH + 1)).is_err());
     }
 }
@@ -1,5 +1,194 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
+//! Account notes module for Rustodon
+//!
+//! Accounts can keep a private note on any other account. Notes are only
+//! ever shown to their author, in the relationship with the noted account.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_account_notes::AccountNote;
+//!
+//! AccountNote::set(&pool, account_id, target_account_id, "Met at RustConf").await?;
+//! let comments = AccountNote::get_comments(&pool, account_id, &[target_account_id]).await?;
+//! ```
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use thiserror::Error;
+use tracing::{debug, trace};
+
+/// Maximum length of a note, in characters
+pub const MAX_NOTE_LENGTH: usize = 2000;
+
+/// Custom error type for account notes module
+#[derive(Error, Debug)]
+pub enum AccountNotesError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("Validation error: {0}")]
+    Validation(String),
+}
+
+/// A private note on another account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountNote {
+    /// Unique identifier for the note
+    pub id: i64,
+    /// ID of the account writing the note
+    pub account_id: i64,
+    /// ID of the noted account
+    pub target_account_id: i64,
+    /// Text of the note
+    pub comment: String,
+    /// When the note was first written
+    pub created_at: DateTime<Utc>,
+    /// When the note was last changed
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Internal struct for database rows
+struct AccountNoteRow {
+    id: i64,
+    account_id: i64,
+    target_account_id: i64,
+    comment: String,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+}
+
+impl From<AccountNoteRow> for AccountNote {
+    fn from(row: AccountNoteRow) -> Self {
+        Self {
+            id: row.id,
+            account_id: row.account_id,
+            target_account_id: row.target_account_id,
+            comment: row.comment,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+            updated_at: DateTime::from_naive_utc_and_offset(row.updated_at, Utc),
+        }
+    }
+}
+
+/// Checks the text of a note
+fn validate_comment(comment: &str) -> Result<(), AccountNotesError> {
+    if comment.chars().count() > MAX_NOTE_LENGTH {
+        return Err(AccountNotesError::Validation(format!(
+            "Note is longer than {} characters",
+            MAX_NOTE_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+impl AccountNote {
+    /// Sets the note an account keeps on another account
+    ///
+    /// A blank comment removes the note.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the account writing the note
+    /// * `target_account_id` - ID of the noted account
+    /// * `comment` - Text of the note
+    ///
+    /// # Returns
+    ///
+    /// The note, or None if it was removed
+    pub async fn set(
+        pool: &PgPool,
+        account_id: i64,
+        target_account_id: i64,
+        comment: &str,
+    ) -> Result<Option<Self>, AccountNotesError> {
+        trace!(
+            "Setting note of account {} on account {}",
+            account_id,
+            target_account_id
+        );
+        validate_comment(comment)?;
+
+        if comment.trim().is_empty() {
+            sqlx::query!(
+                "DELETE FROM account_notes WHERE account_id = $1 AND target_account_id = $2",
+                account_id,
+                target_account_id
+            )
+            .execute(pool)
+            .await?;
+            debug!(
+                "Removed note of account {} on account {}",
+                account_id, target_account_id
+            );
+            return Ok(None);
+        }
+
+        let row = sqlx::query_as!(
+            AccountNoteRow,
+            r#"
+            INSERT INTO account_notes (account_id, target_account_id, comment)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (account_id, target_account_id) DO UPDATE
+            SET comment = EXCLUDED.comment, updated_at = NOW()
+            RETURNING id, account_id, target_account_id, comment, created_at, updated_at
+            "#,
+            account_id,
+            target_account_id,
+            comment
+        )
+        .fetch_one(pool)
+        .await?;
+
+        debug!(
+            "Saved note of account {} on account {}",
+            account_id, target_account_id
+        );
+        Ok(Some(row.into()))
+    }
+
+    /// Gets the notes an account keeps on the given accounts
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the account that wrote the notes
+    /// * `target_account_ids` - IDs of the noted accounts
+    ///
+    /// # Returns
+    ///
+    /// Note texts keyed by noted account ID
+    pub async fn get_comments(
+        pool: &PgPool,
+        account_id: i64,
+        target_account_ids: &[i64],
+    ) -> Result<HashMap<i64, String>, AccountNotesError> {
+        if target_account_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT target_account_id, comment
+            FROM account_notes
+            WHERE account_id = $1 AND target_account_id = ANY($2)
+            "#,
+            account_id,
+            target_account_ids
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.target_account_id, row.comment))
+            .collect())
+    }
 }
 
 #[cfg(test)]
@@ -7,8 +196,12 @@ mod tests {
     use super::*;
 
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn test_validate_comment() {
+        assert!(validate_comment("").is_ok());
+        assert!(validate_comment(&"ü".repeat(MAX_NOTE_LENGTH)).is_ok());
+        assert!(matches!(
+            validate_comment(&"a".repeat(MAX_NOTE_LENGTH + 1)),
+            Err(AccountNotesError::Validation(_))
+        ));
     }
 }
@@ -0,0 +1,246 @@
+//! Account pins module for Rustodon
+//!
+//! Accounts can endorse accounts they follow, featuring them on their
+//! profile. Endorsements show up in relationships, in the endorsements
+//! API and in the account's featured collection.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_account_pins::AccountPin;
+//!
+//! AccountPin::pin(&pool, account_id, target_account_id).await?;
+//! let endorsed = AccountPin::get_by_account(&pool, account_id, Some(40), None, None).await?;
+//! AccountPin::unpin(&pool, account_id, target_account_id).await?;
+//! ```
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashSet;
+use thiserror::Error;
+use tracing::{debug, info, trace};
+
+/// Default number of endorsements per page
+const DEFAULT_LIMIT: i64 = 40;
+/// Maximum number of endorsements per page
+const MAX_LIMIT: i64 = 80;
+
+/// Custom error type for account pins module
+#[derive(Error, Debug)]
+pub enum AccountPinsError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("Validation error: {0}")]
+    Validation(String),
+}
+
+/// An account endorsed on a profile
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountPin {
+    /// Unique identifier for the endorsement
+    pub id: i64,
+    /// ID of the endorsing account
+    pub account_id: i64,
+    /// ID of the endorsed account
+    pub target_account_id: i64,
+    /// When the account was endorsed
+    pub created_at: DateTime<Utc>,
+}
+
+/// Internal struct for database rows
+struct AccountPinRow {
+    id: i64,
+    account_id: i64,
+    target_account_id: i64,
+    created_at: NaiveDateTime,
+}
+
+impl From<AccountPinRow> for AccountPin {
+    fn from(row: AccountPinRow) -> Self {
+        Self {
+            id: row.id,
+            account_id: row.account_id,
+            target_account_id: row.target_account_id,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+        }
+    }
+}
+
+impl AccountPin {
+    /// Endorses an account
+    ///
+    /// Only followed accounts can be endorsed. Endorsing an account twice
+    /// keeps the first endorsement.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the endorsing account
+    /// * `target_account_id` - ID of the account to endorse
+    pub async fn pin(
+        pool: &PgPool,
+        account_id: i64,
+        target_account_id: i64,
+    ) -> Result<Self, AccountPinsError> {
+        trace!(
+            "Account {} endorsing account {}",
+            account_id,
+            target_account_id
+        );
+
+        if account_id == target_account_id {
+            return Err(AccountPinsError::Validation(
+                "You cannot endorse yourself".to_string(),
+            ));
+        }
+        let following = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM follows WHERE follower_id = $1 AND followed_id = $2
+            ) AS "following!"
+            "#,
+            account_id,
+            target_account_id
+        )
+        .fetch_one(pool)
+        .await?;
+        if !following {
+            return Err(AccountPinsError::Validation(
+                "You must be following that account to endorse it".to_string(),
+            ));
+        }
+
+        sqlx::query!(
+            r#"
+            INSERT INTO account_pins (account_id, target_account_id)
+            VALUES ($1, $2)
+            ON CONFLICT (account_id, target_account_id) DO NOTHING
+            "#,
+            account_id,
+            target_account_id
+        )
+        .execute(pool)
+        .await?;
+        let row = sqlx::query_as!(
+            AccountPinRow,
+            r#"
+            SELECT id, account_id, target_account_id, created_at
+            FROM account_pins
+            WHERE account_id = $1 AND target_account_id = $2
+            "#,
+            account_id,
+            target_account_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        info!(
+            "Account {} endorsed account {}",
+            account_id, target_account_id
+        );
+        Ok(row.into())
+    }
+
+    /// Removes an endorsement, if any
+    pub async fn unpin(
+        pool: &PgPool,
+        account_id: i64,
+        target_account_id: i64,
+    ) -> Result<(), AccountPinsError> {
+        let result = sqlx::query!(
+            "DELETE FROM account_pins WHERE account_id = $1 AND target_account_id = $2",
+            account_id,
+            target_account_id
+        )
+        .execute(pool)
+        .await?;
+
+        if result.rows_affected() > 0 {
+            info!(
+                "Account {} no longer endorses account {}",
+                account_id, target_account_id
+            );
+        }
+        Ok(())
+    }
+
+    /// Gets which of the given accounts an account endorses
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the endorsing account
+    /// * `target_account_ids` - IDs of the accounts to check
+    pub async fn endorsed_ids(
+        pool: &PgPool,
+        account_id: i64,
+        target_account_ids: &[i64],
+    ) -> Result<HashSet<i64>, AccountPinsError> {
+        if target_account_ids.is_empty() {
+            return Ok(HashSet::new());
+        }
+
+        let ids = sqlx::query_scalar!(
+            r#"
+            SELECT target_account_id
+            FROM account_pins
+            WHERE account_id = $1 AND target_account_id = ANY($2)
+            "#,
+            account_id,
+            target_account_ids
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(ids.into_iter().collect())
+    }
+
+    /// Gets a page of the accounts an account endorses, newest first
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the endorsing account
+    /// * `limit` - Maximum number of endorsements
+    /// * `max_id` - Only endorsements older than this ID
+    /// * `since_id` - Only endorsements newer than this ID
+    pub async fn get_by_account(
+        pool: &PgPool,
+        account_id: i64,
+        limit: Option<i64>,
+        max_id: Option<i64>,
+        since_id: Option<i64>,
+    ) -> Result<Vec<Self>, AccountPinsError> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        let rows = sqlx::query_as!(
+            AccountPinRow,
+            r#"
+            SELECT id, account_id, target_account_id, created_at
+            FROM account_pins
+            WHERE account_id = $1
+              AND ($2::BIGINT IS NULL OR id < $2)
+              AND ($3::BIGINT IS NULL OR id > $3)
+            ORDER BY id DESC
+            LIMIT $4
+            "#,
+            account_id,
+            max_id,
+            since_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        debug!(
+            "Retrieved {} endorsements of account {}",
+            rows.len(),
+            account_id
+        );
+        Ok(rows.into_iter().map(AccountPin::from).collect())
+    }
+}
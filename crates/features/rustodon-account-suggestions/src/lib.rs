@@ -1,5 +1,501 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
+//! Account suggestions module for Rustodon
+//!
+//! Suggests accounts to follow from several sources: accounts picked by
+//! staff, accounts followed by the accounts one follows, and local accounts
+//! that are popular on the server according to the
+//! `global_follow_recommendations` materialized view. Suggestions leave out
+//! accounts already followed or requested, blocked or muted accounts,
+//! silenced accounts, accounts suppressed by staff and suggestions the
+//! account dismissed.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_account_suggestions::AccountSuggestion;
+//!
+//! let suggestions = AccountSuggestion::get(&pool, account_id, Some(40)).await?;
+//! AccountSuggestion::dismiss(&pool, account_id, suggestions[0].account_id).await?;
+//! ```
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rustodon_workers::{Job, WorkerError};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use thiserror::Error;
+use tracing::{debug, info, trace};
+
+/// Default number of suggestions
+const DEFAULT_LIMIT: i64 = 40;
+/// Maximum number of suggestions
+const MAX_LIMIT: i64 = 80;
+/// Number of candidates taken from each source before filtering
+const CANDIDATES_PER_SOURCE: i64 = 200;
+/// Followed accounts that must follow an account for it to be suggested
+const MIN_MUTUAL_FOLLOWS: i64 = 2;
+
+/// Custom error type for account suggestions module
+#[derive(Error, Debug)]
+pub enum AccountSuggestionsError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("Staff pick not found for account {0}")]
+    StaffPickNotFound(i64),
+}
+
+/// Why an account is suggested
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SuggestionSource {
+    /// Picked by staff
+    Featured,
+    /// Followed by many active local users
+    MostFollowed,
+    /// Its recent statuses got many favourites and reblogs
+    MostInteractions,
+    /// Followed by accounts the account follows
+    FriendsOfFriends,
+}
+
+impl SuggestionSource {
+    /// Name of the source in the API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuggestionSource::Featured => "featured",
+            SuggestionSource::MostFollowed => "most_followed",
+            SuggestionSource::MostInteractions => "most_interactions",
+            SuggestionSource::FriendsOfFriends => "friends_of_friends",
+        }
+    }
+
+    /// Name of the source in the deprecated `source` attribute
+    pub fn legacy_name(&self) -> &'static str {
+        match self {
+            SuggestionSource::Featured => "staff",
+            SuggestionSource::MostFollowed | SuggestionSource::MostInteractions => "global",
+            SuggestionSource::FriendsOfFriends => "past_interactions",
+        }
+    }
+
+    /// Parses a reason of the global recommendations view
+    fn from_reason(reason: &str) -> Option<Self> {
+        match reason {
+            "most_followed" => Some(SuggestionSource::MostFollowed),
+            "most_interactions" => Some(SuggestionSource::MostInteractions),
+            _ => None,
+        }
+    }
+}
+
+/// An account suggested to follow
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountSuggestion {
+    /// ID of the suggested account
+    pub account_id: i64,
+    /// Why the account is suggested, most relevant first
+    pub sources: Vec<SuggestionSource>,
+}
+
+/// Merges the candidates of several sources
+///
+/// Candidates keep the order of their first appearance, and a candidate
+/// found by several sources lists all of them.
+fn merge_candidates(candidates: Vec<(i64, SuggestionSource)>) -> Vec<AccountSuggestion> {
+    let mut suggestions: Vec<AccountSuggestion> = Vec::new();
+    let mut positions: HashMap<i64, usize> = HashMap::new();
+
+    for (account_id, source) in candidates {
+        match positions.get(&account_id) {
+            Some(&position) => {
+                let sources = &mut suggestions[position].sources;
+                if !sources.contains(&source) {
+                    sources.push(source);
+                }
+            }
+            None => {
+                positions.insert(account_id, suggestions.len());
+                suggestions.push(AccountSuggestion {
+                    account_id,
+                    sources: vec![source],
+                });
+            }
+        }
+    }
+    suggestions
+}
+
+impl AccountSuggestion {
+    /// Gets the accounts suggested to an account
+    ///
+    /// Staff picks come first, then friends of friends, then globally
+    /// popular accounts.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the account getting suggestions
+    /// * `limit` - Maximum number of suggestions
+    pub async fn get(
+        pool: &PgPool,
+        account_id: i64,
+        limit: Option<i64>,
+    ) -> Result<Vec<Self>, AccountSuggestionsError> {
+        trace!("Getting follow suggestions for account {}", account_id);
+
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT) as usize;
+        let staff_picks = StaffPick::account_ids(pool).await?;
+        let eligible = eligible_ids(pool, account_id, &staff_picks).await?;
+
+        let mut candidates = Vec::new();
+        candidates.extend(
+            staff_picks
+                .into_iter()
+                .filter(|id| eligible.contains(id))
+                .map(|id| (id, SuggestionSource::Featured)),
+        );
+        candidates.extend(
+            friends_of_friends(pool, account_id)
+                .await?
+                .into_iter()
+                .map(|id| (id, SuggestionSource::FriendsOfFriends)),
+        );
+        candidates.extend(global_recommendations(pool, account_id).await?);
+
+        let mut suggestions = merge_candidates(candidates);
+        suggestions.truncate(limit);
+
+        debug!(
+            "Found {} follow suggestions for account {}",
+            suggestions.len(),
+            account_id
+        );
+        Ok(suggestions)
+    }
+
+    /// Stops suggesting an account to an account
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the account getting suggestions
+    /// * `target_account_id` - ID of the dismissed account
+    pub async fn dismiss(
+        pool: &PgPool,
+        account_id: i64,
+        target_account_id: i64,
+    ) -> Result<(), AccountSuggestionsError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO follow_recommendation_mutes (account_id, target_account_id)
+            VALUES ($1, $2)
+            ON CONFLICT (account_id, target_account_id) DO NOTHING
+            "#,
+            account_id,
+            target_account_id
+        )
+        .execute(pool)
+        .await?;
+
+        info!(
+            "Account {} dismissed suggestion of account {}",
+            account_id, target_account_id
+        );
+        Ok(())
+    }
+}
+
+/// Gets the accounts followed by at least two of the accounts an account
+/// follows which may be suggested to it, most followed first
+async fn friends_of_friends(
+    pool: &PgPool,
+    account_id: i64,
+) -> Result<Vec<i64>, AccountSuggestionsError> {
+    let ids = sqlx::query_scalar!(
+        r#"
+        SELECT f2.followed_id
+        FROM follows f1
+        JOIN follows f2 ON f2.follower_id = f1.followed_id
+        JOIN users u ON u.id = f2.followed_id
+        WHERE f1.follower_id = $1
+          AND f2.followed_id <> $1
+          AND u.discoverable = TRUE
+          AND u.status = 'active'
+          AND u.silenced_at IS NULL
+          AND NOT EXISTS (
+              SELECT 1 FROM follows f3
+              WHERE f3.follower_id = $1 AND f3.followed_id = f2.followed_id
+          )
+          AND NOT EXISTS (SELECT 1 FROM follow_requests WHERE account_id = $1 AND target_account_id = u.id)
+          AND NOT EXISTS (
+              SELECT 1 FROM blocks
+              WHERE (blocker_id = $1 AND blocked_id = u.id) OR (blocker_id = u.id AND blocked_id = $1)
+          )
+          AND NOT EXISTS (SELECT 1 FROM mutes WHERE muter_id = $1 AND muted_id = u.id)
+          AND NOT EXISTS (
+              SELECT 1 FROM follow_recommendation_mutes
+              WHERE account_id = $1 AND target_account_id = u.id
+          )
+          AND NOT EXISTS (SELECT 1 FROM follow_recommendation_suppressions WHERE account_id = u.id)
+        GROUP BY f2.followed_id, u.followers_count
+        HAVING COUNT(*) >= $2
+        ORDER BY COUNT(*) DESC, u.followers_count DESC
+        LIMIT $3
+        "#,
+        account_id,
+        MIN_MUTUAL_FOLLOWS,
+        CANDIDATES_PER_SOURCE
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(ids)
+}
+
+/// Gets the globally recommended accounts which may be suggested to an
+/// account, best ranked first
+async fn global_recommendations(
+    pool: &PgPool,
+    account_id: i64,
+) -> Result<Vec<(i64, SuggestionSource)>, AccountSuggestionsError> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT r.account_id AS "account_id!", r.reason AS "reason!"
+        FROM global_follow_recommendations r
+        JOIN users u ON u.id = r.account_id
+        WHERE u.id <> $1
+          AND u.status = 'active'
+          AND u.silenced_at IS NULL
+          AND NOT EXISTS (SELECT 1 FROM follows WHERE follower_id = $1 AND followed_id = u.id)
+          AND NOT EXISTS (SELECT 1 FROM follow_requests WHERE account_id = $1 AND target_account_id = u.id)
+          AND NOT EXISTS (
+              SELECT 1 FROM blocks
+              WHERE (blocker_id = $1 AND blocked_id = u.id) OR (blocker_id = u.id AND blocked_id = $1)
+          )
+          AND NOT EXISTS (SELECT 1 FROM mutes WHERE muter_id = $1 AND muted_id = u.id)
+          AND NOT EXISTS (
+              SELECT 1 FROM follow_recommendation_mutes
+              WHERE account_id = $1 AND target_account_id = u.id
+          )
+          AND NOT EXISTS (SELECT 1 FROM follow_recommendation_suppressions WHERE account_id = u.id)
+        ORDER BY r.rank DESC, r.account_id
+        LIMIT $2
+        "#,
+        account_id,
+        CANDIDATES_PER_SOURCE
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .flat_map(|row| {
+            let account_id = row.account_id;
+            row.reason
+                .into_iter()
+                .filter_map(|reason| SuggestionSource::from_reason(&reason))
+                .map(move |source| (account_id, source))
+                .collect::<Vec<_>>()
+        })
+        .collect())
+}
+
+/// Gets which staff picks may be suggested to an account
+///
+/// The other sources apply the same conditions in their own queries, before
+/// their limit.
+async fn eligible_ids(
+    pool: &PgPool,
+    account_id: i64,
+    candidate_ids: &[i64],
+) -> Result<HashSet<i64>, AccountSuggestionsError> {
+    if candidate_ids.is_empty() {
+        return Ok(HashSet::new());
+    }
+
+    let ids = sqlx::query_scalar!(
+        r#"
+        SELECT u.id
+        FROM users u
+        WHERE u.id = ANY($2)
+          AND u.id <> $1
+          AND u.status = 'active'
+          AND u.silenced_at IS NULL
+          AND NOT EXISTS (SELECT 1 FROM follows WHERE follower_id = $1 AND followed_id = u.id)
+          AND NOT EXISTS (SELECT 1 FROM follow_requests WHERE account_id = $1 AND target_account_id = u.id)
+          AND NOT EXISTS (
+              SELECT 1 FROM blocks
+              WHERE (blocker_id = $1 AND blocked_id = u.id) OR (blocker_id = u.id AND blocked_id = $1)
+          )
+          AND NOT EXISTS (SELECT 1 FROM mutes WHERE muter_id = $1 AND muted_id = u.id)
+          AND NOT EXISTS (
+              SELECT 1 FROM follow_recommendation_mutes
+              WHERE account_id = $1 AND target_account_id = u.id
+          )
+          AND NOT EXISTS (SELECT 1 FROM follow_recommendation_suppressions WHERE account_id = u.id)
+        "#,
+        account_id,
+        candidate_ids
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(ids.into_iter().collect())
+}
+
+/// An account staff recommend to everyone
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StaffPick {
+    /// Unique identifier for the staff pick
+    pub id: i64,
+    /// ID of the picked account
+    pub account_id: i64,
+    /// When the account was picked
+    pub created_at: DateTime<Utc>,
+}
+
+/// Internal struct for database rows
+struct StaffPickRow {
+    id: i64,
+    account_id: i64,
+    created_at: NaiveDateTime,
+}
+
+impl From<StaffPickRow> for StaffPick {
+    fn from(row: StaffPickRow) -> Self {
+        Self {
+            id: row.id,
+            account_id: row.account_id,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+        }
+    }
+}
+
+impl StaffPick {
+    /// Recommends an account to everyone
+    ///
+    /// Picking an account twice keeps the first pick.
+    pub async fn add(pool: &PgPool, account_id: i64) -> Result<Self, AccountSuggestionsError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO follow_recommendation_staff_picks (account_id)
+            VALUES ($1)
+            ON CONFLICT (account_id) DO NOTHING
+            "#,
+            account_id
+        )
+        .execute(pool)
+        .await?;
+        let row = sqlx::query_as!(
+            StaffPickRow,
+            r#"
+            SELECT id, account_id, created_at
+            FROM follow_recommendation_staff_picks
+            WHERE account_id = $1
+            "#,
+            account_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        info!("Account {} picked by staff", account_id);
+        Ok(row.into())
+    }
+
+    /// Stops recommending an account to everyone
+    pub async fn remove(pool: &PgPool, account_id: i64) -> Result<(), AccountSuggestionsError> {
+        let result = sqlx::query!(
+            "DELETE FROM follow_recommendation_staff_picks WHERE account_id = $1",
+            account_id
+        )
+        .execute(pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(AccountSuggestionsError::StaffPickNotFound(account_id));
+        }
+
+        info!("Account {} no longer picked by staff", account_id);
+        Ok(())
+    }
+
+    /// Gets all staff picks, oldest first
+    pub async fn get_all(pool: &PgPool) -> Result<Vec<Self>, AccountSuggestionsError> {
+        let rows = sqlx::query_as!(
+            StaffPickRow,
+            r#"
+            SELECT id, account_id, created_at
+            FROM follow_recommendation_staff_picks
+            ORDER BY id
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    /// Gets the IDs of the picked accounts, oldest pick first
+    async fn account_ids(pool: &PgPool) -> Result<Vec<i64>, AccountSuggestionsError> {
+        let ids = sqlx::query_scalar!(
+            "SELECT account_id FROM follow_recommendation_staff_picks ORDER BY id"
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(ids)
+    }
+}
+
+/// Refreshes the account summaries and the global recommendations
+///
+/// The views are refreshed concurrently so suggestions keep being served
+/// during the refresh.
+pub async fn refresh_recommendations(pool: &PgPool) -> Result<(), AccountSuggestionsError> {
+    sqlx::query!("REFRESH MATERIALIZED VIEW CONCURRENTLY account_summaries")
+        .execute(pool)
+        .await?;
+    sqlx::query!("REFRESH MATERIALIZED VIEW CONCURRENTLY global_follow_recommendations")
+        .execute(pool)
+        .await?;
+
+    info!("Refreshed follow recommendations");
+    Ok(())
+}
+
+/// Background job refreshing the follow recommendation views
+pub struct RefreshFollowRecommendationsJob {
+    pool: PgPool,
+}
+
+impl RefreshFollowRecommendationsJob {
+    /// Creates the job
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+impl Job for RefreshFollowRecommendationsJob {
+    fn name(&self) -> &'static str {
+        "RefreshFollowRecommendationsJob"
+    }
+
+    fn execute(&self) -> Pin<Box<dyn Future<Output = Result<(), WorkerError>> + Send>> {
+        let pool = self.pool.clone();
+        Box::pin(async move {
+            refresh_recommendations(&pool)
+                .await
+                .map_err(|e| WorkerError::Job(e.to_string()))
+        })
+    }
 }
 
 #[cfg(test)]
@@ -7,8 +503,41 @@ mod tests {
     use super::*;
 
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn test_merge_candidates() {
+        let suggestions = merge_candidates(vec![
+            (3, SuggestionSource::Featured),
+            (5, SuggestionSource::FriendsOfFriends),
+            (3, SuggestionSource::MostFollowed),
+            (5, SuggestionSource::FriendsOfFriends),
+            (7, SuggestionSource::MostInteractions),
+        ]);
+        assert_eq!(
+            suggestions,
+            vec![
+                AccountSuggestion {
+                    account_id: 3,
+                    sources: vec![SuggestionSource::Featured, SuggestionSource::MostFollowed],
+                },
+                AccountSuggestion {
+                    account_id: 5,
+                    sources: vec![SuggestionSource::FriendsOfFriends],
+                },
+                AccountSuggestion {
+                    account_id: 7,
+                    sources: vec![SuggestionSource::MostInteractions],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_source_names() {
+        assert_eq!(SuggestionSource::Featured.legacy_name(), "staff");
+        assert_eq!(SuggestionSource::MostInteractions.legacy_name(), "global");
+        assert_eq!(
+            SuggestionSource::from_reason("most_followed"),
+            Some(SuggestionSource::MostFollowed)
+        );
+        assert_eq!(SuggestionSource::from_reason("unknown"), None);
     }
 }
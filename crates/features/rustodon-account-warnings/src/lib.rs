@@ -1,14 +1,614 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
+//! Account warnings for Rustodon
+//!
+//! Moderators issue strikes against accounts. A strike carries an
+//! explanation for its owner, the statuses it is about, and an action that
+//! is applied to the account right away:
+//!
+//! - `none`: a plain warning
+//! - `disable`: the account can no longer log in
+//! - `sensitive`: media of the account is marked as sensitive
+//! - `silence`: the account is limited and hidden from public timelines
+//! - `suspend`: the account is suspended
+//! - `delete_statuses`: the linked statuses are removed
+//!
+//! Owners can look at their strikes and appeal them for
+//! [`APPEAL_WINDOW_DAYS`] days. A strike whose appeal is approved is
+//! overruled, which reverses its action.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_account_warnings::{AccountWarning, NewAccountWarning, StrikeAction};
+//!
+//! let warning = AccountWarning::create(&pool, moderator_id, NewAccountWarning {
+//!     target_account_id,
+//!     action: StrikeAction::Silence,
+//!     text: "Repeated spam".to_string(),
+//!     status_ids: vec![status_id],
+//! }).await?;
+//! warning.notify(&pool, &mailer).await?;
+//! ```
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use rustodon_mailer::{AsyncMailer, Email};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use thiserror::Error;
+use tracing::{debug, info, trace};
+
+/// Days during which a strike can be appealed
+pub const APPEAL_WINDOW_DAYS: i64 = 20;
+
+/// Error type for account warning operations
+#[derive(Error, Debug)]
+pub enum AccountWarningError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("Mailer error: {0}")]
+    Mailer(String),
+    #[error("Account warning not found: {0}")]
+    NotFound(i64),
+    #[error("Account not found: {0}")]
+    AccountNotFound(i64),
+    #[error("Invalid action: {0}")]
+    InvalidAction(String),
+    #[error("Validation error: {0}")]
+    Validation(String),
+}
+
+/// What a strike does to the account
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StrikeAction {
+    None,
+    Disable,
+    Sensitive,
+    Silence,
+    Suspend,
+    DeleteStatuses,
+}
+
+impl StrikeAction {
+    /// Database and API name of the action
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StrikeAction::None => "none",
+            StrikeAction::Disable => "disable",
+            StrikeAction::Sensitive => "sensitive",
+            StrikeAction::Silence => "silence",
+            StrikeAction::Suspend => "suspend",
+            StrikeAction::DeleteStatuses => "delete_statuses",
+        }
+    }
+
+    /// Parses an action by name
+    pub fn parse(value: &str) -> Result<Self, AccountWarningError> {
+        match value {
+            "none" => Ok(StrikeAction::None),
+            "disable" => Ok(StrikeAction::Disable),
+            "sensitive" => Ok(StrikeAction::Sensitive),
+            "silence" => Ok(StrikeAction::Silence),
+            "suspend" => Ok(StrikeAction::Suspend),
+            "delete_statuses" => Ok(StrikeAction::DeleteStatuses),
+            other => Err(AccountWarningError::InvalidAction(other.to_string())),
+        }
+    }
+
+    /// Subject of the email telling the owner about the strike
+    fn email_subject(&self) -> &'static str {
+        match self {
+            StrikeAction::None => "You have received a warning",
+            StrikeAction::Disable => "Your account has been disabled",
+            StrikeAction::Sensitive => "Your media will be marked as sensitive",
+            StrikeAction::Silence => "Your account has been limited",
+            StrikeAction::Suspend => "Your account has been suspended",
+            StrikeAction::DeleteStatuses => "Your posts have been removed",
+        }
+    }
+
+    /// Applies the action to an account
+    async fn apply(
+        &self,
+        conn: &mut PgConnection,
+        account_id: i64,
+        status_ids: &[i64],
+    ) -> Result<(), sqlx::Error> {
+        match self {
+            StrikeAction::None => {}
+            StrikeAction::Disable => {
+                sqlx::query!("UPDATE users SET disabled = TRUE WHERE id = $1", account_id)
+                    .execute(&mut *conn)
+                    .await?;
+            }
+            StrikeAction::Sensitive => {
+                sqlx::query!(
+                    "UPDATE users SET sensitized_at = COALESCE(sensitized_at, NOW()) WHERE id = $1",
+                    account_id
+                )
+                .execute(&mut *conn)
+                .await?;
+            }
+            StrikeAction::Silence => {
+                sqlx::query!(
+                    "UPDATE users SET silenced_at = COALESCE(silenced_at, NOW()) WHERE id = $1",
+                    account_id
+                )
+                .execute(&mut *conn)
+                .await?;
+            }
+            StrikeAction::Suspend => {
+                sqlx::query!(
+                    r#"
+                    UPDATE users SET status = 'suspended', suspended_at = COALESCE(suspended_at, NOW())
+                    WHERE id = $1 AND status <> 'deleted'
+                    "#,
+                    account_id
+                )
+                .execute(&mut *conn)
+                .await?;
+            }
+            StrikeAction::DeleteStatuses => {
+                let deleted = sqlx::query!(
+                    r#"
+                    UPDATE statuses SET deleted_at = NOW()
+                    WHERE account_id = $1 AND id = ANY($2) AND deleted_at IS NULL
+                    "#,
+                    account_id,
+                    status_ids
+                )
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+                sqlx::query!(
+                    "UPDATE users SET statuses_count = GREATEST(statuses_count - $2, 0) WHERE id = $1",
+                    account_id,
+                    deleted as i32
+                )
+                .execute(&mut *conn)
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lifts the action from an account
+    async fn reverse(
+        &self,
+        conn: &mut PgConnection,
+        account_id: i64,
+        status_ids: &[i64],
+    ) -> Result<(), sqlx::Error> {
+        match self {
+            StrikeAction::None => {}
+            StrikeAction::Disable => {
+                sqlx::query!(
+                    "UPDATE users SET disabled = FALSE WHERE id = $1",
+                    account_id
+                )
+                .execute(&mut *conn)
+                .await?;
+            }
+            StrikeAction::Sensitive => {
+                sqlx::query!(
+                    "UPDATE users SET sensitized_at = NULL WHERE id = $1",
+                    account_id
+                )
+                .execute(&mut *conn)
+                .await?;
+            }
+            StrikeAction::Silence => {
+                sqlx::query!(
+                    "UPDATE users SET silenced_at = NULL WHERE id = $1",
+                    account_id
+                )
+                .execute(&mut *conn)
+                .await?;
+            }
+            StrikeAction::Suspend => {
+                sqlx::query!(
+                    r#"
+                    UPDATE users SET status = 'active', suspended_at = NULL
+                    WHERE id = $1 AND status = 'suspended'
+                    "#,
+                    account_id
+                )
+                .execute(&mut *conn)
+                .await?;
+            }
+            StrikeAction::DeleteStatuses => {
+                let restored = sqlx::query!(
+                    r#"
+                    UPDATE statuses SET deleted_at = NULL
+                    WHERE account_id = $1 AND id = ANY($2) AND deleted_at IS NOT NULL
+                    "#,
+                    account_id,
+                    status_ids
+                )
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+                sqlx::query!(
+                    "UPDATE users SET statuses_count = statuses_count + $2 WHERE id = $1",
+                    account_id,
+                    restored as i32
+                )
+                .execute(&mut *conn)
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A strike against an account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountWarning {
+    pub id: i64,
+    /// Moderator who issued the strike
+    pub account_id: Option<i64>,
+    /// Account the strike is against
+    pub target_account_id: i64,
+    pub action: StrikeAction,
+    /// Explanation for the owner of the account
+    pub text: String,
+    /// Statuses the strike is about
+    pub status_ids: Vec<i64>,
+    /// When an approved appeal reversed the strike
+    pub overruled_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Parameters of a new strike
+#[derive(Debug, Clone)]
+pub struct NewAccountWarning {
+    pub target_account_id: i64,
+    pub action: StrikeAction,
+    pub text: String,
+    pub status_ids: Vec<i64>,
+}
+
+/// Internal struct for database rows
+struct AccountWarningRow {
+    id: i64,
+    account_id: Option<i64>,
+    target_account_id: i64,
+    action: String,
+    text: String,
+    status_ids: Vec<i64>,
+    overruled_at: Option<NaiveDateTime>,
+    created_at: NaiveDateTime,
+}
+
+impl TryFrom<AccountWarningRow> for AccountWarning {
+    type Error = AccountWarningError;
+
+    fn try_from(row: AccountWarningRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            account_id: row.account_id,
+            target_account_id: row.target_account_id,
+            action: StrikeAction::parse(&row.action)?,
+            text: row.text,
+            status_ids: row.status_ids,
+            overruled_at: row
+                .overruled_at
+                .map(|overruled_at| DateTime::from_naive_utc_and_offset(overruled_at, Utc)),
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+        })
+    }
+}
+
+impl AccountWarning {
+    /// Issues a strike and applies its action
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection of the transaction to run in
+    /// * `moderator_id` - ID of the moderator issuing the strike
+    /// * `warning` - The strike to issue
+    ///
+    /// # Returns
+    ///
+    /// The strike, once its action is applied
+    pub async fn create(
+        conn: &mut PgConnection,
+        moderator_id: i64,
+        warning: NewAccountWarning,
+    ) -> Result<Self, AccountWarningError> {
+        trace!(
+            "Issuing {} strike against account {}",
+            warning.action.as_str(),
+            warning.target_account_id
+        );
+
+        let mut status_ids = warning.status_ids;
+        status_ids.sort_unstable();
+        status_ids.dedup();
+        if warning.action == StrikeAction::DeleteStatuses && status_ids.is_empty() {
+            return Err(AccountWarningError::Validation(
+                "Statuses to delete must be given".to_string(),
+            ));
+        }
+
+        sqlx::query_scalar!(
+            "SELECT id FROM users WHERE id = $1 AND status <> 'deleted' FOR UPDATE",
+            warning.target_account_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(AccountWarningError::AccountNotFound(
+            warning.target_account_id,
+        ))?;
+
+        let owned = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM statuses WHERE account_id = $1 AND id = ANY($2)"#,
+            warning.target_account_id,
+            &status_ids
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+        if owned != status_ids.len() as i64 {
+            return Err(AccountWarningError::Validation(
+                "Statuses must belong to the account".to_string(),
+            ));
+        }
+
+        let row = sqlx::query_as!(
+            AccountWarningRow,
+            r#"
+            INSERT INTO account_warnings (account_id, target_account_id, action, text, status_ids)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, account_id, target_account_id, action, text, status_ids,
+                      overruled_at, created_at
+            "#,
+            moderator_id,
+            warning.target_account_id,
+            warning.action.as_str(),
+            warning.text.trim(),
+            &status_ids
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        warning
+            .action
+            .apply(conn, warning.target_account_id, &status_ids)
+            .await?;
+
+        info!(
+            "Moderator {} issued a {} strike against account {}",
+            moderator_id,
+            warning.action.as_str(),
+            warning.target_account_id
+        );
+        row.try_into()
+    }
+
+    /// Gets a strike by ID
+    pub async fn get<'e>(
+        executor: impl PgExecutor<'e>,
+        id: i64,
+    ) -> Result<Option<Self>, AccountWarningError> {
+        let row = sqlx::query_as!(
+            AccountWarningRow,
+            r#"
+            SELECT id, account_id, target_account_id, action, text, status_ids,
+                   overruled_at, created_at
+            FROM account_warnings
+            WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(executor)
+        .await?;
+
+        row.map(AccountWarning::try_from).transpose()
+    }
+
+    /// Gets the strikes against an account, newest first
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `target_account_id` - ID of the account
+    /// * `limit` - Maximum number of strikes
+    pub async fn by_target_account(
+        pool: &PgPool,
+        target_account_id: i64,
+        limit: i64,
+    ) -> Result<Vec<Self>, AccountWarningError> {
+        let rows = sqlx::query_as!(
+            AccountWarningRow,
+            r#"
+            SELECT id, account_id, target_account_id, action, text, status_ids,
+                   overruled_at, created_at
+            FROM account_warnings
+            WHERE target_account_id = $1
+            ORDER BY id DESC
+            LIMIT $2
+            "#,
+            target_account_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        rows.into_iter().map(AccountWarning::try_from).collect()
+    }
+
+    /// Last moment the strike can be appealed
+    pub fn appeal_deadline(&self) -> DateTime<Utc> {
+        self.created_at + Duration::days(APPEAL_WINDOW_DAYS)
+    }
+
+    /// Whether the strike can still be appealed
+    pub fn is_appealable(&self, now: DateTime<Utc>) -> bool {
+        self.overruled_at.is_none() && now <= self.appeal_deadline()
+    }
+
+    /// Overrules the strike and reverses its action
+    ///
+    /// Meant to run in the transaction approving an appeal.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection of the transaction
+    pub async fn overrule(&self, conn: &mut PgConnection) -> Result<(), AccountWarningError> {
+        let overruled = sqlx::query!(
+            r#"
+            UPDATE account_warnings SET overruled_at = NOW(), updated_at = NOW()
+            WHERE id = $1 AND overruled_at IS NULL
+            "#,
+            self.id
+        )
+        .execute(&mut *conn)
+        .await?
+        .rows_affected();
+        if overruled == 0 {
+            debug!("Strike {} was already overruled", self.id);
+            return Ok(());
+        }
+
+        self.action
+            .reverse(conn, self.target_account_id, &self.status_ids)
+            .await?;
+        info!(
+            "Overruled strike {} against account {}",
+            self.id, self.target_account_id
+        );
+        Ok(())
+    }
+
+    /// Renders the email telling the owner about the strike
+    ///
+    /// # Arguments
+    ///
+    /// * `to` - Email address of the owner
+    pub fn email(&self, to: &str) -> Email {
+        let mut body = format!("{}.\n", self.action.email_subject());
+        if !self.text.is_empty() {
+            body.push_str(&format!("\n{}\n", self.text));
+        }
+        if !self.status_ids.is_empty() {
+            body.push_str(&format!(
+                "\nThis concerns {} of your posts.\n",
+                self.status_ids.len()
+            ));
+        }
+        body.push_str(&format!(
+            "\nIf you believe this is a mistake, you can appeal it until {}.\n",
+            self.appeal_deadline().format("%Y-%m-%d %H:%M UTC")
+        ));
+
+        Email {
+            to: to.to_string(),
+            subject: self.action.email_subject().to_string(),
+            body,
+        }
+    }
+
+    /// Emails the owner of the account about the strike
+    ///
+    /// Remote accounts are skipped, their instance is responsible for them.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `mailer` - Mailer sending the email
+    pub async fn notify(
+        &self,
+        pool: &PgPool,
+        mailer: &dyn AsyncMailer,
+    ) -> Result<(), AccountWarningError> {
+        let Some(address) = local_email(pool, self.target_account_id).await? else {
+            debug!("Not emailing remote account {}", self.target_account_id);
+            return Ok(());
+        };
+
+        mailer
+            .send(self.email(&address))
+            .await
+            .map_err(|e| AccountWarningError::Mailer(e.to_string()))
+    }
+}
+
+/// Gets the email address of a local account
+///
+/// # Arguments
+///
+/// * `pool` - Database connection pool
+/// * `account_id` - ID of the account
+///
+/// # Returns
+///
+/// The address, or `None` for remote and deleted accounts
+pub async fn local_email(pool: &PgPool, account_id: i64) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        "SELECT email FROM users WHERE id = $1 AND domain IS NULL AND status <> 'deleted'",
+        account_id
+    )
+    .fetch_optional(pool)
+    .await
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn warning(action: StrikeAction) -> AccountWarning {
+        AccountWarning {
+            id: 1,
+            account_id: Some(2),
+            target_account_id: 3,
+            action,
+            text: "Please stop posting spam".to_string(),
+            status_ids: vec![10, 11],
+            overruled_at: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_action_names_round_trip() {
+        for action in [
+            StrikeAction::None,
+            StrikeAction::Disable,
+            StrikeAction::Sensitive,
+            StrikeAction::Silence,
+            StrikeAction::Suspend,
+            StrikeAction::DeleteStatuses,
+        ] {
+            assert_eq!(StrikeAction::parse(action.as_str()).unwrap(), action);
+        }
+        assert!(matches!(
+            StrikeAction::parse("ban"),
+            Err(AccountWarningError::InvalidAction(_))
+        ));
+    }
+
+    #[test]
+    fn test_appeal_window() {
+        let mut strike = warning(StrikeAction::Silence);
+        assert!(strike.is_appealable(Utc::now()));
+        assert!(!strike.is_appealable(Utc::now() + Duration::days(APPEAL_WINDOW_DAYS + 1)));
+
+        strike.overruled_at = Some(Utc::now());
+        assert!(!strike.is_appealable(Utc::now()));
+    }
+
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn test_email() {
+        let email = warning(StrikeAction::Suspend).email("alice@example.com");
+        assert_eq!(email.to, "alice@example.com");
+        assert_eq!(email.subject, "Your account has been suspended");
+        assert!(email.body.contains("Please stop posting spam"));
+        assert!(email.body.contains("2 of your posts"));
+        assert!(email.body.contains("appeal"));
     }
 }
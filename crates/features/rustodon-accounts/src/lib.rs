@@ -1,5 +1,179 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
+//! Accounts module for Rustodon
+//!
+//! Lists accounts in the profile directory. The directory shows active
+//! accounts that opted in through `discoverable` and that staff neither hid
+//! nor silenced, either the most recently active first or the newest first,
+//! and can be narrowed to local accounts or to accounts mostly posting in a
+//! given language according to the `account_summaries` view.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_accounts::{Directory, DirectoryOrder, DirectoryQuery};
+//!
+//! let query = DirectoryQuery { order: DirectoryOrder::New, local: true, ..Default::default() };
+//! let accounts = Directory::accounts(&pool, &query).await?;
+//! Directory::set_hidden(&mut tx, accounts[0].id, true).await?;
+//! ```
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use rustodon_db::User;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+use thiserror::Error;
+use tracing::{debug, info, trace};
+
+/// Default number of accounts per directory page
+const DEFAULT_LIMIT: i64 = 40;
+/// Maximum number of accounts per directory page
+const MAX_LIMIT: i64 = 80;
+
+/// Custom error type for accounts module
+#[derive(Error, Debug)]
+pub enum AccountsError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("Account not found: {0}")]
+    NotFound(i64),
+}
+
+/// Order of the profile directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DirectoryOrder {
+    /// Most recently posting accounts first
+    #[default]
+    Active,
+    /// Most recently created accounts first
+    New,
+}
+
+impl DirectoryOrder {
+    /// Parses the `order` parameter of the directory
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "active" => Some(DirectoryOrder::Active),
+            "new" => Some(DirectoryOrder::New),
+            _ => None,
+        }
+    }
+}
+
+/// A page of the profile directory
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirectoryQuery {
+    /// Order of the accounts
+    pub order: DirectoryOrder,
+    /// Only list local accounts
+    pub local: bool,
+    /// Only list accounts mostly posting in this language
+    pub language: Option<String>,
+    /// Number of accounts to skip
+    pub offset: Option<i64>,
+    /// Maximum number of accounts
+    pub limit: Option<i64>,
+}
+
+impl DirectoryQuery {
+    /// Number of accounts to return, within bounds
+    pub fn limit(&self) -> i64 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+
+    /// Number of accounts to skip, never negative
+    pub fn offset(&self) -> i64 {
+        self.offset.unwrap_or(0).max(0)
+    }
+}
+
+/// The profile directory
+pub struct Directory;
+
+impl Directory {
+    /// Gets a page of the profile directory
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `query` - Order, filters and page of the directory
+    pub async fn accounts(
+        pool: &PgPool,
+        query: &DirectoryQuery,
+    ) -> Result<Vec<User>, AccountsError> {
+        trace!("Listing profile directory: {:?}", query);
+
+        let accounts = sqlx::query_as!(
+            User,
+            r#"
+            SELECT u.id, u.username, u.email, u.password_hash, u.display_name, u.note, u.locked,
+                   u.bot, u.discoverable, u.group_account, u.domain, u.uri, u.inbox_url,
+                   u.followers_count, u.following_count, u.statuses_count, u.created_at,
+                   u.updated_at
+            FROM users u
+            WHERE u.discoverable = TRUE
+              AND u.hidden_from_directory = FALSE
+              AND u.status = 'active'
+              AND u.silenced_at IS NULL
+              AND (NOT $1 OR u.domain IS NULL)
+              AND (
+                  $2::TEXT IS NULL
+                  OR EXISTS (
+                      SELECT 1 FROM account_summaries s
+                      WHERE s.account_id = u.id AND s.language = $2
+                  )
+              )
+            ORDER BY CASE WHEN $3 THEN u.last_status_at END DESC NULLS LAST, u.id DESC
+            LIMIT $4 OFFSET $5
+            "#,
+            query.local,
+            query.language.as_deref(),
+            query.order == DirectoryOrder::Active,
+            query.limit(),
+            query.offset()
+        )
+        .fetch_all(pool)
+        .await?;
+
+        debug!("Profile directory page has {} accounts", accounts.len());
+        Ok(accounts)
+    }
+
+    /// Hides an account from the profile directory, or shows it again
+    ///
+    /// Hidden accounts stay discoverable otherwise; only the directory
+    /// leaves them out.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection of the transaction to run in
+    /// * `account_id` - ID of the account
+    /// * `hidden` - Whether to hide the account
+    pub async fn set_hidden(
+        conn: &mut PgConnection,
+        account_id: i64,
+        hidden: bool,
+    ) -> Result<(), AccountsError> {
+        let result = sqlx::query!(
+            "UPDATE users SET hidden_from_directory = $2 WHERE id = $1",
+            account_id,
+            hidden
+        )
+        .execute(conn)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(AccountsError::NotFound(account_id));
+        }
+
+        info!(
+            "Account {} {} the profile directory",
+            account_id,
+            if hidden { "hidden from" } else { "shown in" }
+        );
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -7,8 +181,27 @@ mod tests {
     use super::*;
 
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn test_directory_query_bounds() {
+        let query = DirectoryQuery::default();
+        assert_eq!(query.limit(), DEFAULT_LIMIT);
+        assert_eq!(query.offset(), 0);
+
+        let query = DirectoryQuery {
+            limit: Some(500),
+            offset: Some(-3),
+            ..Default::default()
+        };
+        assert_eq!(query.limit(), MAX_LIMIT);
+        assert_eq!(query.offset(), 0);
+    }
+
+    #[test]
+    fn test_parse_order() {
+        assert_eq!(DirectoryOrder::parse("new"), Some(DirectoryOrder::New));
+        assert_eq!(
+            DirectoryOrder::parse("active"),
+            Some(DirectoryOrder::Active)
+        );
+        assert_eq!(DirectoryOrder::parse("popular"), None);
     }
 }
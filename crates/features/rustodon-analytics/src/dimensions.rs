@@ -0,0 +1,356 @@
+//! Dimensions
+//!
+//! Rankings shown on the admin dashboard. Languages, sources and servers
+//! are counted per day and cached like measures; software and space usage
+//! describe the server as it is now.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::rollups::{bounds, daily, DailyValue};
+use crate::{AnalyticsError, DateRange};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use tracing::trace;
+
+/// Default number of items of a ranking
+pub const DEFAULT_DIMENSION_LIMIT: usize = 10;
+
+/// Names of common languages by ISO 639-1 code
+const LANGUAGE_NAMES: &[(&str, &str)] = &[
+    ("ar", "Arabic"),
+    ("de", "German"),
+    ("en", "English"),
+    ("es", "Spanish"),
+    ("fa", "Persian"),
+    ("fr", "French"),
+    ("hi", "Hindi"),
+    ("id", "Indonesian"),
+    ("it", "Italian"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+    ("nl", "Dutch"),
+    ("pl", "Polish"),
+    ("pt", "Portuguese"),
+    ("ru", "Russian"),
+    ("sv", "Swedish"),
+    ("th", "Thai"),
+    ("tr", "Turkish"),
+    ("uk", "Ukrainian"),
+    ("zh", "Chinese"),
+    ("und", "Unknown"),
+];
+
+/// Dimension key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DimensionKey {
+    /// Languages of local statuses
+    Languages,
+    /// Applications local statuses were posted from
+    Sources,
+    /// Versions of the software the server runs
+    Software,
+    /// Remote servers by statuses received from them
+    Servers,
+    /// Bytes used by the database and stored media
+    SpaceUsage,
+}
+
+impl DimensionKey {
+    /// Every dimension
+    pub const ALL: [DimensionKey; 5] = [
+        DimensionKey::Languages,
+        DimensionKey::Sources,
+        DimensionKey::Software,
+        DimensionKey::Servers,
+        DimensionKey::SpaceUsage,
+    ];
+
+    /// Dimensions counted per day
+    pub const DAILY: [DimensionKey; 3] = [
+        DimensionKey::Languages,
+        DimensionKey::Sources,
+        DimensionKey::Servers,
+    ];
+
+    /// API name of the dimension
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DimensionKey::Languages => "languages",
+            DimensionKey::Sources => "sources",
+            DimensionKey::Software => "software",
+            DimensionKey::Servers => "servers",
+            DimensionKey::SpaceUsage => "space_usage",
+        }
+    }
+
+    /// Parses a dimension by name
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|key| key.as_str() == value)
+    }
+}
+
+/// An item of a ranking
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DimensionItem {
+    pub key: String,
+    /// Readable name of the item
+    pub human_key: String,
+    /// A count, a size or a version
+    pub value: String,
+    /// Unit of the value, if it isn't a plain count
+    pub unit: Option<&'static str>,
+}
+
+/// A dimension over a date range
+#[derive(Debug, Clone, Serialize)]
+pub struct Dimension {
+    pub key: DimensionKey,
+    /// Items, largest first
+    pub data: Vec<DimensionItem>,
+}
+
+/// Row of a daily count by dimension
+struct DimensionCount {
+    day: NaiveDate,
+    dimension: String,
+    value: i64,
+}
+
+impl From<DimensionCount> for DailyValue {
+    fn from(row: DimensionCount) -> Self {
+        DailyValue {
+            date: row.day,
+            dimension: row.dimension,
+            value: row.value,
+        }
+    }
+}
+
+/// Readable name of a language code
+fn language_name(code: &str) -> String {
+    LANGUAGE_NAMES
+        .iter()
+        .find(|(known, _)| *known == code)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| code.to_string())
+}
+
+/// Computes the values of a daily dimension from a first to a last day
+async fn compute_days(
+    pool: &PgPool,
+    key: DimensionKey,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<Vec<DailyValue>, AnalyticsError> {
+    let (from, to) = bounds(start, end);
+    let rows = match key {
+        DimensionKey::Languages => {
+            sqlx::query_as!(
+                DimensionCount,
+                r#"
+                SELECT created_at::DATE AS "day!", COALESCE(language, 'und') AS "dimension!",
+                       COUNT(*) AS "value!"
+                FROM statuses
+                WHERE local AND created_at >= $1 AND created_at < $2
+                GROUP BY 1, 2
+                "#,
+                from,
+                to
+            )
+            .fetch_all(pool)
+            .await?
+        }
+        DimensionKey::Sources => {
+            sqlx::query_as!(
+                DimensionCount,
+                r#"
+                SELECT created_at::DATE AS "day!",
+                       COALESCE(NULLIF(application->>'name', ''), 'Web') AS "dimension!",
+                       COUNT(*) AS "value!"
+                FROM statuses
+                WHERE local AND created_at >= $1 AND created_at < $2
+                GROUP BY 1, 2
+                "#,
+                from,
+                to
+            )
+            .fetch_all(pool)
+            .await?
+        }
+        DimensionKey::Servers => {
+            sqlx::query_as!(
+                DimensionCount,
+                r#"
+                SELECT s.created_at::DATE AS "day!", u.domain AS "dimension!",
+                       COUNT(*) AS "value!"
+                FROM statuses s
+                JOIN users u ON u.id = s.account_id
+                WHERE u.domain IS NOT NULL AND s.created_at >= $1 AND s.created_at < $2
+                GROUP BY 1, 2
+                "#,
+                from,
+                to
+            )
+            .fetch_all(pool)
+            .await?
+        }
+        DimensionKey::Software | DimensionKey::SpaceUsage => {
+            return Err(AnalyticsError::Internal(format!(
+                "{} is not counted per day",
+                key.as_str()
+            )))
+        }
+    };
+
+    Ok(rows.into_iter().map(DailyValue::from).collect())
+}
+
+/// Sums daily values by dimension, largest first
+fn rank(values: Vec<DailyValue>, limit: usize) -> Vec<(String, i64)> {
+    let mut totals: HashMap<String, i64> = HashMap::new();
+    for value in values {
+        if !value.dimension.is_empty() {
+            *totals.entry(value.dimension).or_default() += value.value;
+        }
+    }
+
+    let mut ranked: Vec<(String, i64)> = totals.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked.truncate(limit);
+    ranked
+}
+
+impl Dimension {
+    /// Computes a dimension over a date range
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `key` - The dimension
+    /// * `range` - Days to compute, ignored by software and space usage
+    /// * `limit` - Maximum number of items
+    pub async fn compute(
+        pool: &PgPool,
+        key: DimensionKey,
+        range: DateRange,
+        limit: usize,
+    ) -> Result<Self, AnalyticsError> {
+        trace!("Computing dimension {} over {:?}", key.as_str(), range);
+
+        let data = match key {
+            DimensionKey::Software => {
+                let postgresql = sqlx::query_scalar!(
+                    r#"SELECT current_setting('server_version') AS "version!""#
+                )
+                .fetch_one(pool)
+                .await?;
+                vec![
+                    DimensionItem {
+                        key: "rustodon".to_string(),
+                        human_key: "Rustodon".to_string(),
+                        value: env!("CARGO_PKG_VERSION").to_string(),
+                        unit: None,
+                    },
+                    DimensionItem {
+                        key: "postgresql".to_string(),
+                        human_key: "PostgreSQL".to_string(),
+                        value: postgresql,
+                        unit: None,
+                    },
+                ]
+            }
+            DimensionKey::SpaceUsage => {
+                let usage = sqlx::query!(
+                    r#"
+                    SELECT pg_database_size(current_database()) AS "database!",
+                           (SELECT COALESCE(SUM(file_size), 0)::BIGINT FROM media_attachments)
+                               AS "media!"
+                    "#
+                )
+                .fetch_one(pool)
+                .await?;
+                vec![
+                    DimensionItem {
+                        key: "postgresql".to_string(),
+                        human_key: "PostgreSQL".to_string(),
+                        value: usage.database.to_string(),
+                        unit: Some("bytes"),
+                    },
+                    DimensionItem {
+                        key: "media".to_string(),
+                        human_key: "Media".to_string(),
+                        value: usage.media.to_string(),
+                        unit: Some("bytes"),
+                    },
+                ]
+            }
+            _ => {
+                let values = daily(pool, key.as_str(), range, |start, end| {
+                    compute_days(pool, key, start, end)
+                })
+                .await?;
+                rank(values, limit)
+                    .into_iter()
+                    .map(|(dimension, value)| DimensionItem {
+                        human_key: match key {
+                            DimensionKey::Languages => language_name(&dimension),
+                            _ => dimension.clone(),
+                        },
+                        key: dimension,
+                        value: value.to_string(),
+                        unit: None,
+                    })
+                    .collect()
+            }
+        };
+
+        Ok(Self { key, data })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dimension_key_names() {
+        for key in DimensionKey::ALL {
+            assert_eq!(DimensionKey::parse(key.as_str()), Some(key));
+        }
+        assert_eq!(DimensionKey::parse("tag_servers"), None);
+    }
+
+    #[test]
+    fn test_language_name() {
+        assert_eq!(language_name("en"), "English");
+        assert_eq!(language_name("und"), "Unknown");
+        assert_eq!(language_name("eo"), "eo");
+    }
+
+    #[test]
+    fn test_rank() {
+        let day = |d| NaiveDate::from_ymd_opt(2025, 7, d).unwrap();
+        let value = |d, dimension: &str, value| DailyValue {
+            date: day(d),
+            dimension: dimension.to_string(),
+            value,
+        };
+        let values = vec![
+            value(1, "en", 3),
+            value(2, "en", 2),
+            value(1, "de", 4),
+            value(1, "fr", 1),
+            value(1, "", 0),
+        ];
+
+        assert_eq!(
+            rank(values, 2),
+            vec![("en".to_string(), 5), ("de".to_string(), 4)]
+        );
+    }
+}
@@ -1,28 +1,69 @@
 //! Analytics module for Rustodon
 //!
 //! This module provides analytics functionality for the Rustodon server.
-//! It handles data collection, analysis, and reporting for various metrics.
+//! It counts events such as sent emails per day, and computes the admin
+//! dashboard from the database over a date range:
+//!
+//! - [`Measure`]s: daily series such as new users, active users,
+//!   interactions, reports, emails sent and media storage
+//! - [`Dimension`]s: rankings such as languages, posting sources, software,
+//!   remote servers and space usage
+//! - [`Cohort`]s: how many of the users who signed up in a period are still
+//!   active in later ones
+//!
+//! Daily values of past days never change much, so they are computed once
+//! and cached in `analytics_rollups`; [`RollupAnalyticsJob`] fills the cache
+//! ahead of time. Only the current day is always computed live.
 //!
 //! # Examples
 //!
-//! ```rust
-//! use rustodon_analytics::Analytics;
+//! ```rust,ignore
+//! use rustodon_analytics::{Analytics, DateRange, Measure, MeasureKey};
 //!
 //! let analytics = Analytics::new();
-//! analytics.track_event("user_login");
+//! analytics.track_event(&pool, "user_login").await?;
+//!
+//! let range = DateRange::new(start, end)?;
+//! let measure = Measure::compute(&pool, MeasureKey::NewUsers, range).await?;
 //! ```
 //!
 //! # Dependencies
 //!
 //! - `rustodon_core`: Core types and traits
+//! - `rustodon_mailer`: Counting sent emails
+//! - `rustodon_workers`: Background rollup job
 //!
 //! # Author
 //!
 //! arkSong (arksong2018@gmail.com)
 
+mod dimensions;
+mod measures;
+mod retention;
+mod rollups;
+
+pub use dimensions::{Dimension, DimensionItem, DimensionKey, DEFAULT_DIMENSION_LIMIT};
+pub use measures::{Measure, MeasureKey, MeasureValue};
+pub use retention::{Cohort, CohortValue, Frequency};
+
+use async_trait::async_trait;
+use chrono::{Days, NaiveDate, Utc};
+use rustodon_mailer::{AsyncMailer, Email, MailerError};
+use rustodon_workers::{Job, WorkerError};
 use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
 use thiserror::Error;
-use tracing::{debug, error, info, trace};
+use tracing::{debug, info, trace, warn};
+
+/// Event counted for every email sent
+pub const EMAIL_SENT_EVENT: &str = "email_sent";
+/// Maximum length of an event name
+pub const MAX_EVENT_NAME_LENGTH: usize = 64;
+/// Maximum number of days in a date range
+pub const MAX_RANGE_DAYS: u64 = 366;
 
 /// Custom error type for analytics module
 #[derive(Error, Debug)]
@@ -70,22 +111,41 @@ impl Analytics {
 
     /// Tracks an analytics event
     ///
+    /// Events are counted per day in `analytics_events`.
+    ///
     /// # Arguments
     ///
+    /// * `pool` - Database connection pool
     /// * `event_name` - The name of the event to track
     ///
     /// # Returns
     ///
     /// Result indicating success or failure
-    pub async fn track_event(&self, event_name: &str) -> Result<(), AnalyticsError> {
-        info!("Tracking analytics event: {}", event_name);
+    pub async fn track_event(&self, pool: &PgPool, event_name: &str) -> Result<(), AnalyticsError> {
+        trace!("Tracking analytics event: {}", event_name);
 
         if !self.config.enabled {
             debug!("Analytics disabled, skipping event: {}", event_name);
             return Ok(());
         }
+        if event_name.is_empty() || event_name.len() > MAX_EVENT_NAME_LENGTH {
+            return Err(AnalyticsError::Validation(format!(
+                "Invalid event name: {}",
+                event_name
+            )));
+        }
 
-        // Implementation here
+        sqlx::query!(
+            r#"
+            INSERT INTO analytics_events (name, date, count)
+            VALUES ($1, $2, 1)
+            ON CONFLICT (name, date) DO UPDATE SET count = analytics_events.count + 1
+            "#,
+            event_name,
+            Utc::now().date_naive()
+        )
+        .execute(pool)
+        .await?;
 
         debug!("Analytics event tracked successfully: {}", event_name);
         Ok(())
@@ -98,10 +158,146 @@ impl Default for Analytics {
     }
 }
 
+/// An inclusive range of days
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateRange {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+impl DateRange {
+    /// Creates a range from its first to its last day
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - First day of the range
+    /// * `end` - Last day of the range
+    pub fn new(start: NaiveDate, end: NaiveDate) -> Result<Self, AnalyticsError> {
+        if end < start {
+            return Err(AnalyticsError::Validation(
+                "End date must not be before start date".to_string(),
+            ));
+        }
+        let range = Self { start, end };
+        if range.num_days() > MAX_RANGE_DAYS {
+            return Err(AnalyticsError::Validation(format!(
+                "Date range is too long (maximum is {} days)",
+                MAX_RANGE_DAYS
+            )));
+        }
+        Ok(range)
+    }
+
+    /// Number of days in the range
+    pub fn num_days(&self) -> u64 {
+        (self.end - self.start).num_days() as u64 + 1
+    }
+
+    /// Days of the range in order
+    pub fn days(&self) -> impl Iterator<Item = NaiveDate> {
+        self.start.iter_days().take(self.num_days() as usize)
+    }
+
+    /// The range of the same length right before this one
+    pub fn previous(&self) -> Self {
+        let len = Days::new(self.num_days());
+        Self {
+            start: self.start - len,
+            end: self.end - len,
+        }
+    }
+}
+
+/// Mailer counting every email sent by another mailer
+pub struct TrackedMailer {
+    inner: Arc<dyn AsyncMailer>,
+    pool: PgPool,
+    analytics: Analytics,
+}
+
+impl TrackedMailer {
+    /// Wraps a mailer
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - Mailer sending the emails
+    /// * `pool` - Database connection pool
+    pub fn new(inner: Arc<dyn AsyncMailer>, pool: PgPool) -> Self {
+        Self {
+            inner,
+            pool,
+            analytics: Analytics::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncMailer for TrackedMailer {
+    async fn send(&self, email: Email) -> Result<(), MailerError> {
+        self.inner.send(email).await?;
+        if let Err(e) = self
+            .analytics
+            .track_event(&self.pool, EMAIL_SENT_EVENT)
+            .await
+        {
+            warn!("Failed to count sent email: {}", e);
+        }
+        Ok(())
+    }
+}
+
+/// Background job caching the daily rollups of the days before today
+pub struct RollupAnalyticsJob {
+    pool: PgPool,
+}
+
+impl RollupAnalyticsJob {
+    /// Creates the job
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+impl Job for RollupAnalyticsJob {
+    fn name(&self) -> &'static str {
+        "RollupAnalyticsJob"
+    }
+
+    fn execute(&self) -> Pin<Box<dyn Future<Output = Result<(), WorkerError>> + Send>> {
+        let pool = self.pool.clone();
+        Box::pin(async move {
+            let yesterday = Utc::now().date_naive() - Days::new(1);
+            let range = DateRange::new(yesterday, yesterday)
+                .map_err(|e| WorkerError::Job(e.to_string()))?;
+
+            for key in MeasureKey::ALL {
+                Measure::compute(&pool, key, range)
+                    .await
+                    .map_err(|e| WorkerError::Job(e.to_string()))?;
+            }
+            for key in DimensionKey::DAILY {
+                Dimension::compute(&pool, key, range, DEFAULT_DIMENSION_LIMIT)
+                    .await
+                    .map_err(|e| WorkerError::Job(e.to_string()))?;
+            }
+            info!("Rolled up analytics of {}", yesterday);
+            Ok(())
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 7, day).unwrap()
+    }
+
     #[tokio::test]
     async fn test_analytics_new() {
         let analytics = Analytics::new();
@@ -110,9 +306,29 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_track_event() {
-        let analytics = Analytics::new();
-        let result = analytics.track_event("test_event").await;
+    async fn test_track_event_when_disabled() {
+        let mut analytics = Analytics::new();
+        analytics.config.enabled = false;
+        // Never connects, since disabled analytics don't touch the database
+        let pool = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+        let result = analytics.track_event(&pool, "test_event").await;
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_date_range() {
+        let range = DateRange::new(date(1), date(7)).unwrap();
+        assert_eq!(range.num_days(), 7);
+        assert_eq!(range.days().last(), Some(date(7)));
+        assert_eq!(
+            range.previous(),
+            DateRange {
+                start: NaiveDate::from_ymd_opt(2025, 6, 24).unwrap(),
+                end: NaiveDate::from_ymd_opt(2025, 6, 30).unwrap(),
+            }
+        );
+
+        assert!(DateRange::new(date(7), date(1)).is_err());
+        assert!(DateRange::new(date(1), date(1) + Days::new(MAX_RANGE_DAYS)).is_err());
+    }
 }
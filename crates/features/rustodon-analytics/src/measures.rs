@@ -0,0 +1,359 @@
+//! Measures
+//!
+//! Daily series shown on the admin dashboard, each with its total over the
+//! range and the total over the range of the same length before it.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::rollups::{bounds, daily, DailyValue};
+use crate::{AnalyticsError, DateRange, EMAIL_SENT_EVENT};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use tracing::trace;
+
+/// Measure key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MeasureKey {
+    /// Local accounts created
+    NewUsers,
+    /// Local accounts posting, favouriting, boosting or signing in
+    ActiveUsers,
+    /// Favourites, boosts and replies by others on local statuses
+    Interactions,
+    OpenedReports,
+    ResolvedReports,
+    EmailsSent,
+    /// Bytes of media stored
+    MediaStorage,
+}
+
+impl MeasureKey {
+    /// Every measure
+    pub const ALL: [MeasureKey; 7] = [
+        MeasureKey::NewUsers,
+        MeasureKey::ActiveUsers,
+        MeasureKey::Interactions,
+        MeasureKey::OpenedReports,
+        MeasureKey::ResolvedReports,
+        MeasureKey::EmailsSent,
+        MeasureKey::MediaStorage,
+    ];
+
+    /// API name of the measure
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MeasureKey::NewUsers => "new_users",
+            MeasureKey::ActiveUsers => "active_users",
+            MeasureKey::Interactions => "interactions",
+            MeasureKey::OpenedReports => "opened_reports",
+            MeasureKey::ResolvedReports => "resolved_reports",
+            MeasureKey::EmailsSent => "emails_sent",
+            MeasureKey::MediaStorage => "media_storage",
+        }
+    }
+
+    /// Parses a measure by name
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|key| key.as_str() == value)
+    }
+
+    /// Unit of the values, if they aren't plain counts
+    pub fn unit(&self) -> Option<&'static str> {
+        match self {
+            MeasureKey::MediaStorage => Some("bytes"),
+            _ => None,
+        }
+    }
+}
+
+/// Value of a measure on one day
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MeasureValue {
+    pub date: NaiveDate,
+    pub value: i64,
+}
+
+/// A measure over a date range
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Measure {
+    pub key: MeasureKey,
+    pub total: i64,
+    /// Total over the range of the same length before this one
+    pub previous_total: i64,
+    /// One value per day of the range
+    pub data: Vec<MeasureValue>,
+}
+
+/// Row of a daily count
+struct DayCount {
+    day: NaiveDate,
+    value: i64,
+}
+
+impl From<DayCount> for DailyValue {
+    fn from(row: DayCount) -> Self {
+        DailyValue {
+            date: row.day,
+            dimension: String::new(),
+            value: row.value,
+        }
+    }
+}
+
+/// Computes the values of a measure from a first to a last day
+async fn compute_days(
+    pool: &PgPool,
+    key: MeasureKey,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<Vec<DailyValue>, AnalyticsError> {
+    let (from, to) = bounds(start, end);
+    let rows = match key {
+        MeasureKey::NewUsers => {
+            sqlx::query_as!(
+                DayCount,
+                r#"
+                SELECT created_at::DATE AS "day!", COUNT(*) AS "value!"
+                FROM users
+                WHERE domain IS NULL AND created_at >= $1 AND created_at < $2
+                GROUP BY 1
+                "#,
+                from,
+                to
+            )
+            .fetch_all(pool)
+            .await?
+        }
+        MeasureKey::ActiveUsers => {
+            sqlx::query_as!(
+                DayCount,
+                r#"
+                SELECT a.at::DATE AS "day!", COUNT(DISTINCT a.account_id) AS "value!"
+                FROM account_activity a
+                JOIN users u ON u.id = a.account_id
+                WHERE u.domain IS NULL AND a.at >= $1 AND a.at < $2
+                GROUP BY 1
+                "#,
+                from,
+                to
+            )
+            .fetch_all(pool)
+            .await?
+        }
+        MeasureKey::Interactions => {
+            sqlx::query_as!(
+                DayCount,
+                r#"
+                SELECT at::DATE AS "day!", COUNT(*) AS "value!"
+                FROM (
+                    SELECT f.created_at AS at
+                    FROM favourites f JOIN statuses s ON s.id = f.status_id
+                    WHERE s.local AND f.created_at >= $1 AND f.created_at < $2
+                    UNION ALL
+                    SELECT r.created_at AS at
+                    FROM reblogs r JOIN statuses s ON s.id = r.status_id
+                    WHERE s.local AND r.created_at >= $1 AND r.created_at < $2
+                    UNION ALL
+                    SELECT r.created_at AS at
+                    FROM statuses r JOIN statuses s ON s.id = r.in_reply_to_id
+                    WHERE s.local AND r.account_id <> s.account_id
+                      AND r.created_at >= $1 AND r.created_at < $2
+                ) interactions
+                GROUP BY 1
+                "#,
+                from,
+                to
+            )
+            .fetch_all(pool)
+            .await?
+        }
+        MeasureKey::OpenedReports => {
+            sqlx::query_as!(
+                DayCount,
+                r#"
+                SELECT created_at::DATE AS "day!", COUNT(*) AS "value!"
+                FROM reports
+                WHERE created_at >= $1 AND created_at < $2
+                GROUP BY 1
+                "#,
+                from,
+                to
+            )
+            .fetch_all(pool)
+            .await?
+        }
+        MeasureKey::ResolvedReports => {
+            sqlx::query_as!(
+                DayCount,
+                r#"
+                SELECT action_taken_at::DATE AS "day!", COUNT(*) AS "value!"
+                FROM reports
+                WHERE action_taken_at >= $1 AND action_taken_at < $2
+                GROUP BY 1
+                "#,
+                from,
+                to
+            )
+            .fetch_all(pool)
+            .await?
+        }
+        MeasureKey::EmailsSent => {
+            sqlx::query_as!(
+                DayCount,
+                r#"
+                SELECT date AS "day!", count AS "value!"
+                FROM analytics_events
+                WHERE name = $1 AND date >= $2 AND date <= $3
+                "#,
+                EMAIL_SENT_EVENT,
+                start,
+                end
+            )
+            .fetch_all(pool)
+            .await?
+        }
+        MeasureKey::MediaStorage => {
+            let (from, to) = (from.and_utc(), to.and_utc());
+            sqlx::query_as!(
+                DayCount,
+                r#"
+                SELECT (created_at AT TIME ZONE 'UTC')::DATE AS "day!",
+                       COALESCE(SUM(file_size), 0)::BIGINT AS "value!"
+                FROM media_attachments
+                WHERE created_at >= $1 AND created_at < $2
+                GROUP BY 1
+                "#,
+                from,
+                to
+            )
+            .fetch_all(pool)
+            .await?
+        }
+    };
+
+    Ok(rows.into_iter().map(DailyValue::from).collect())
+}
+
+/// Number of distinct local accounts active over a whole range
+async fn distinct_active_users(pool: &PgPool, range: DateRange) -> Result<i64, AnalyticsError> {
+    let (from, to) = bounds(range.start, range.end);
+    let count = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(DISTINCT a.account_id) AS "count!"
+        FROM account_activity a
+        JOIN users u ON u.id = a.account_id
+        WHERE u.domain IS NULL AND a.at >= $1 AND a.at < $2
+        "#,
+        from,
+        to
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(count)
+}
+
+/// Puts daily values in the order of the days of a range, with zeros for
+/// days without one
+fn series(range: DateRange, values: Vec<DailyValue>) -> Vec<MeasureValue> {
+    let by_day: HashMap<NaiveDate, i64> = values
+        .into_iter()
+        .filter(|value| value.dimension.is_empty())
+        .map(|value| (value.date, value.value))
+        .collect();
+
+    range
+        .days()
+        .map(|date| MeasureValue {
+            date,
+            value: by_day.get(&date).copied().unwrap_or(0),
+        })
+        .collect()
+}
+
+impl Measure {
+    /// Computes a measure over a date range
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `key` - The measure
+    /// * `range` - Days to compute
+    pub async fn compute(
+        pool: &PgPool,
+        key: MeasureKey,
+        range: DateRange,
+    ) -> Result<Self, AnalyticsError> {
+        trace!("Computing measure {} over {:?}", key.as_str(), range);
+
+        let compute = |start, end| compute_days(pool, key, start, end);
+        let data = series(range, daily(pool, key.as_str(), range, compute).await?);
+        let previous = range.previous();
+        let previous_data = series(
+            previous,
+            daily(pool, key.as_str(), previous, compute).await?,
+        );
+
+        let (total, previous_total) = match key {
+            // The same account is active on many days
+            MeasureKey::ActiveUsers => (
+                distinct_active_users(pool, range).await?,
+                distinct_active_users(pool, previous).await?,
+            ),
+            _ => (
+                data.iter().map(|value| value.value).sum(),
+                previous_data.iter().map(|value| value.value).sum(),
+            ),
+        };
+
+        Ok(Self {
+            key,
+            total,
+            previous_total,
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_measure_key_names() {
+        for key in MeasureKey::ALL {
+            assert_eq!(MeasureKey::parse(key.as_str()), Some(key));
+        }
+        assert_eq!(MeasureKey::parse("tag_uses"), None);
+        assert_eq!(MeasureKey::MediaStorage.unit(), Some("bytes"));
+    }
+
+    #[test]
+    fn test_series() {
+        let day = |d| NaiveDate::from_ymd_opt(2025, 7, d).unwrap();
+        let range = DateRange::new(day(1), day(3)).unwrap();
+        let values = vec![
+            DailyValue {
+                date: day(3),
+                dimension: String::new(),
+                value: 4,
+            },
+            DailyValue {
+                date: day(1),
+                dimension: "en".to_string(),
+                value: 9,
+            },
+        ];
+
+        let data: Vec<_> = series(range, values)
+            .into_iter()
+            .map(|value| value.value)
+            .collect();
+        assert_eq!(data, vec![0, 0, 4]);
+    }
+}
@@ -0,0 +1,266 @@
+//! Retention
+//!
+//! Groups the local users who signed up over a date range into cohorts by
+//! day or month of sign-up, and counts how many of each cohort are still
+//! active in every later period of the range.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::rollups::bounds;
+use crate::{AnalyticsError, DateRange};
+use chrono::{Datelike, Days, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use tracing::trace;
+
+/// Length of the periods cohorts are grouped by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Frequency {
+    Day,
+    Month,
+}
+
+impl Frequency {
+    /// API name of the frequency, also a `date_trunc` field
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Frequency::Day => "day",
+            Frequency::Month => "month",
+        }
+    }
+
+    /// Parses a frequency by name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "day" => Some(Frequency::Day),
+            "month" => Some(Frequency::Month),
+            _ => None,
+        }
+    }
+
+    /// First day of the period a day is in
+    pub fn truncate(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Frequency::Day => date,
+            Frequency::Month => date.with_day(1).unwrap_or(date),
+        }
+    }
+
+    /// First day of the period after the one starting on a day
+    fn next(&self, period: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Frequency::Day => period.checked_add_days(Days::new(1)),
+            Frequency::Month => period.checked_add_months(Months::new(1)),
+        }
+    }
+
+    /// First days of the periods from the one of `start` to the one of `end`
+    fn periods(&self, start: NaiveDate, end: NaiveDate) -> Vec<NaiveDate> {
+        let mut periods = Vec::new();
+        let mut period = Some(self.truncate(start));
+        while let Some(current) = period.filter(|current| *current <= end) {
+            periods.push(current);
+            period = self.next(current);
+        }
+        periods
+    }
+}
+
+/// Users of a cohort active in one period
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CohortValue {
+    /// First day of the period
+    pub date: NaiveDate,
+    /// Share of the cohort active in the period
+    pub rate: f64,
+    pub value: i64,
+}
+
+/// Users who signed up in one period
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cohort {
+    /// First day of the sign-up period
+    pub period: NaiveDate,
+    pub frequency: Frequency,
+    /// The cohort size first, then one value per later period
+    pub data: Vec<CohortValue>,
+}
+
+/// Row of a cohort size
+struct CohortSize {
+    period: NaiveDate,
+    size: i64,
+}
+
+/// Row of the users of a cohort active in a period
+struct CohortActivity {
+    cohort: NaiveDate,
+    period: NaiveDate,
+    value: i64,
+}
+
+/// Builds cohorts from their sizes and activity, keeping periods of the range
+fn build_cohorts(
+    frequency: Frequency,
+    end: NaiveDate,
+    sizes: Vec<CohortSize>,
+    activity: Vec<CohortActivity>,
+) -> Vec<Cohort> {
+    let active: HashMap<(NaiveDate, NaiveDate), i64> = activity
+        .into_iter()
+        .map(|row| ((row.cohort, row.period), row.value))
+        .collect();
+
+    let mut cohorts: Vec<Cohort> = sizes
+        .into_iter()
+        .filter(|row| row.size > 0)
+        .map(|row| {
+            let data = frequency
+                .periods(row.period, end)
+                .into_iter()
+                .enumerate()
+                .map(|(i, date)| {
+                    let value = if i == 0 {
+                        row.size
+                    } else {
+                        active.get(&(row.period, date)).copied().unwrap_or(0)
+                    };
+                    CohortValue {
+                        date,
+                        rate: value as f64 / row.size as f64,
+                        value,
+                    }
+                })
+                .collect();
+            Cohort {
+                period: row.period,
+                frequency,
+                data,
+            }
+        })
+        .collect();
+
+    cohorts.sort_by_key(|cohort| cohort.period);
+    cohorts
+}
+
+impl Cohort {
+    /// Computes the cohorts of the users who signed up over a date range
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `range` - Days to compute; its first period starts on the start of
+    ///   the period `range.start` is in
+    /// * `frequency` - Length of the periods
+    pub async fn compute(
+        pool: &PgPool,
+        range: DateRange,
+        frequency: Frequency,
+    ) -> Result<Vec<Self>, AnalyticsError> {
+        trace!(
+            "Computing {} retention over {:?}",
+            frequency.as_str(),
+            range
+        );
+
+        let (from, to) = bounds(frequency.truncate(range.start), range.end);
+        let sizes = sqlx::query_as!(
+            CohortSize,
+            r#"
+            SELECT date_trunc($1, created_at)::DATE AS "period!", COUNT(*) AS "size!"
+            FROM users
+            WHERE domain IS NULL AND created_at >= $2 AND created_at < $3
+            GROUP BY 1
+            "#,
+            frequency.as_str(),
+            from,
+            to
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let activity = sqlx::query_as!(
+            CohortActivity,
+            r#"
+            SELECT date_trunc($1, u.created_at)::DATE AS "cohort!",
+                   date_trunc($1, a.at)::DATE AS "period!",
+                   COUNT(DISTINCT a.account_id) AS "value!"
+            FROM users u
+            JOIN account_activity a ON a.account_id = u.id
+            WHERE u.domain IS NULL AND u.created_at >= $2 AND u.created_at < $3
+              AND a.at >= date_trunc($1, u.created_at) AND a.at < $3
+            GROUP BY 1, 2
+            "#,
+            frequency.as_str(),
+            from,
+            to
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(build_cohorts(frequency, range.end, sizes, activity))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_frequency_periods() {
+        assert_eq!(Frequency::parse("month"), Some(Frequency::Month));
+        assert_eq!(Frequency::parse("week"), None);
+        assert_eq!(Frequency::Month.truncate(date(7, 31)), date(7, 1));
+        assert_eq!(
+            Frequency::Month.periods(date(5, 15), date(7, 1)),
+            vec![date(5, 1), date(6, 1), date(7, 1)]
+        );
+        assert_eq!(
+            Frequency::Day.periods(date(7, 30), date(8, 1)),
+            vec![date(7, 30), date(7, 31), date(8, 1)]
+        );
+    }
+
+    #[test]
+    fn test_build_cohorts() {
+        let sizes = vec![
+            CohortSize {
+                period: date(7, 2),
+                size: 2,
+            },
+            CohortSize {
+                period: date(7, 1),
+                size: 4,
+            },
+        ];
+        let activity = vec![
+            CohortActivity {
+                cohort: date(7, 1),
+                period: date(7, 1),
+                value: 4,
+            },
+            CohortActivity {
+                cohort: date(7, 1),
+                period: date(7, 3),
+                value: 1,
+            },
+        ];
+
+        let cohorts = build_cohorts(Frequency::Day, date(7, 3), sizes, activity);
+        assert_eq!(cohorts.len(), 2);
+        assert_eq!(cohorts[0].period, date(7, 1));
+        let values: Vec<_> = cohorts[0].data.iter().map(|v| (v.value, v.rate)).collect();
+        assert_eq!(values, vec![(4, 1.0), (0, 0.0), (1, 0.25)]);
+        assert_eq!(cohorts[1].data.len(), 2);
+        assert_eq!(cohorts[1].data[0].rate, 1.0);
+    }
+}
@@ -0,0 +1,170 @@
+//! Daily rollups
+//!
+//! Caches the daily values measures and dimensions are made of in
+//! `analytics_rollups`. Each cached day has a row with an empty dimension:
+//! the value itself for measures, and a marker for dimensions, so days
+//! without any value are told apart from days not computed yet. Today is
+//! never cached since it is still going on.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::{AnalyticsError, DateRange};
+use chrono::{Days, NaiveDate, NaiveDateTime, Utc};
+use sqlx::PgPool;
+use std::collections::HashSet;
+use std::future::Future;
+use tracing::debug;
+
+/// A value of one day
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DailyValue {
+    pub date: NaiveDate,
+    /// Empty for measures, such as a language code for dimensions
+    pub dimension: String,
+    pub value: i64,
+}
+
+/// Start of the first and end of the last day of a range, for queries
+pub(crate) fn bounds(start: NaiveDate, end: NaiveDate) -> (NaiveDateTime, NaiveDateTime) {
+    (
+        start.and_time(chrono::NaiveTime::MIN),
+        (end + Days::new(1)).and_time(chrono::NaiveTime::MIN),
+    )
+}
+
+/// Adds an empty-dimension row with no value to each day that has none
+fn mark_days(values: &mut Vec<DailyValue>, start: NaiveDate, end: NaiveDate) {
+    let marked: HashSet<NaiveDate> = values
+        .iter()
+        .filter(|value| value.dimension.is_empty())
+        .map(|value| value.date)
+        .collect();
+    for date in start.iter_days().take_while(|date| *date <= end) {
+        if !marked.contains(&date) {
+            values.push(DailyValue {
+                date,
+                dimension: String::new(),
+                value: 0,
+            });
+        }
+    }
+}
+
+/// Gets the daily values of a key over a range, computing and caching the
+/// past days missing from the cache
+///
+/// # Arguments
+///
+/// * `pool` - Database connection pool
+/// * `key` - Key of the measure or dimension
+/// * `range` - Days to get
+/// * `compute` - Computes the values of the days from a first to a last one
+pub(crate) async fn daily<F, Fut>(
+    pool: &PgPool,
+    key: &str,
+    range: DateRange,
+    compute: F,
+) -> Result<Vec<DailyValue>, AnalyticsError>
+where
+    F: Fn(NaiveDate, NaiveDate) -> Fut,
+    Fut: Future<Output = Result<Vec<DailyValue>, AnalyticsError>>,
+{
+    let today = Utc::now().date_naive();
+    let mut values = sqlx::query_as!(
+        DailyValue,
+        r#"
+        SELECT date, dimension, value
+        FROM analytics_rollups
+        WHERE key = $1 AND date >= $2 AND date <= $3 AND date < $4
+        "#,
+        key,
+        range.start,
+        range.end,
+        today
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let cached: HashSet<NaiveDate> = values
+        .iter()
+        .filter(|value| value.dimension.is_empty())
+        .map(|value| value.date)
+        .collect();
+    let missing: Vec<NaiveDate> = range
+        .days()
+        .filter(|date| *date < today && !cached.contains(date))
+        .collect();
+
+    if let (Some(&first), Some(&last)) = (missing.first(), missing.last()) {
+        debug!("Rolling up {} from {} to {}", key, first, last);
+        let mut computed = compute(first, last).await?;
+        mark_days(&mut computed, first, last);
+        computed.retain(|value| !cached.contains(&value.date));
+
+        sqlx::query!(
+            r#"
+            INSERT INTO analytics_rollups (key, date, dimension, value)
+            SELECT $1, * FROM UNNEST($2::DATE[], $3::TEXT[], $4::BIGINT[])
+            ON CONFLICT (key, date, dimension) DO UPDATE SET value = EXCLUDED.value
+            "#,
+            key,
+            &computed.iter().map(|value| value.date).collect::<Vec<_>>(),
+            &computed
+                .iter()
+                .map(|value| value.dimension.clone())
+                .collect::<Vec<_>>(),
+            &computed.iter().map(|value| value.value).collect::<Vec<_>>()
+        )
+        .execute(pool)
+        .await?;
+        values.extend(computed);
+    }
+
+    if range.start <= today && today <= range.end {
+        let mut live = compute(today, today).await?;
+        mark_days(&mut live, today, today);
+        values.extend(live);
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mark_days() {
+        let day = |d| NaiveDate::from_ymd_opt(2025, 7, d).unwrap();
+        let mut values = vec![
+            DailyValue {
+                date: day(1),
+                dimension: "en".to_string(),
+                value: 3,
+            },
+            DailyValue {
+                date: day(2),
+                dimension: String::new(),
+                value: 5,
+            },
+        ];
+        mark_days(&mut values, day(1), day(3));
+
+        let marked: Vec<_> = values
+            .iter()
+            .filter(|value| value.dimension.is_empty())
+            .map(|value| (value.date, value.value))
+            .collect();
+        assert_eq!(marked, vec![(day(2), 5), (day(1), 0), (day(3), 0)]);
+    }
+
+    #[test]
+    fn test_bounds() {
+        let day = NaiveDate::from_ymd_opt(2025, 7, 31).unwrap();
+        let (start, end) = bounds(day, day);
+        assert_eq!(start.to_string(), "2025-07-31 00:00:00");
+        assert_eq!(end.to_string(), "2025-08-01 00:00:00");
+    }
+}
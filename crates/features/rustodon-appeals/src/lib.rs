@@ -2,13 +2,35 @@
 //!
 //! This module provides appeal capabilities for the Rustodon server.
 //!
+//! The owner of an account can appeal each strike against it once, within
+//! the appeal window of the strike. Moderators either approve the appeal,
+//! which overrules the strike and reverses its action, or reject it. The
+//! owner is told about the decision by email.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_appeals::Appeal;
+//!
+//! let appeal = Appeal::create(&pool, account_id, strike_id, "This was satire").await?;
+//! let appeal = Appeal::approve(&pool, appeal.id, moderator_id).await?;
+//! appeal.notify(&pool, &mailer).await?;
+//! ```
+//!
 //! # Author
 //!
 //! arkSong (arksong2018@gmail.com)
 
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rustodon_account_warnings::{local_email, AccountWarning, AccountWarningError};
+use rustodon_mailer::{AsyncMailer, Email};
 use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgExecutor, PgPool};
 use thiserror::Error;
-use tracing::{error, info};
+use tracing::{debug, info, trace};
+
+/// Maximum length of the text of an appeal
+pub const MAX_TEXT_LENGTH: usize = 2_000;
 
 /// Appeal model
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,6 +55,321 @@ pub enum AppealError {
     Database(#[from] sqlx::Error),
     #[error("Invalid appeal: {0}")]
     InvalidAppeal(String),
+    #[error("Account warning error: {0}")]
+    Warning(#[from] AccountWarningError),
+    #[error("Mailer error: {0}")]
+    Mailer(String),
+    #[error("Appeal not found: {0}")]
+    NotFound(i64),
+    #[error("Strike not found: {0}")]
+    StrikeNotFound(i64),
+    #[error("Strike already appealed")]
+    AlreadyAppealed,
+    #[error("Appeal window has passed")]
+    WindowExpired,
+    #[error("Appeal already processed")]
+    AlreadyProcessed,
+}
+
+/// Internal struct for database rows
+struct AppealRow {
+    id: i64,
+    account_id: i64,
+    account_warning_id: i64,
+    text: String,
+    approved_at: Option<NaiveDateTime>,
+    approved_by_account_id: Option<i64>,
+    rejected_at: Option<NaiveDateTime>,
+    rejected_by_account_id: Option<i64>,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+}
+
+impl From<AppealRow> for Appeal {
+    fn from(row: AppealRow) -> Self {
+        let utc = |time: NaiveDateTime| DateTime::from_naive_utc_and_offset(time, Utc);
+        let approved = match (row.approved_at, row.rejected_at) {
+            (Some(_), _) => Some(true),
+            (None, Some(_)) => Some(false),
+            (None, None) => None,
+        };
+
+        Self {
+            id: row.id,
+            account_id: row.account_id,
+            strike_id: row.account_warning_id,
+            text: row.text,
+            approved,
+            approved_at: row.approved_at.map(utc),
+            approved_by_account_id: row.approved_by_account_id,
+            rejected_at: row.rejected_at.map(utc),
+            rejected_by_account_id: row.rejected_by_account_id,
+            created_at: utc(row.created_at),
+            updated_at: utc(row.updated_at),
+        }
+    }
+}
+
+impl Appeal {
+    /// Appeals a strike
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the account the strike is against
+    /// * `strike_id` - ID of the strike
+    /// * `text` - Why the strike should be reversed
+    pub async fn create(
+        pool: &PgPool,
+        account_id: i64,
+        strike_id: i64,
+        text: &str,
+    ) -> Result<Self, AppealError> {
+        trace!("Account {} appealing strike {}", account_id, strike_id);
+
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(AppealError::InvalidAppeal(
+                "Text can't be blank".to_string(),
+            ));
+        }
+        if text.chars().count() > MAX_TEXT_LENGTH {
+            return Err(AppealError::InvalidAppeal(format!(
+                "Text is too long (maximum is {} characters)",
+                MAX_TEXT_LENGTH
+            )));
+        }
+
+        let strike = AccountWarning::get(pool, strike_id)
+            .await?
+            .filter(|strike| strike.target_account_id == account_id)
+            .ok_or(AppealError::StrikeNotFound(strike_id))?;
+        if !strike.is_appealable(Utc::now()) {
+            return Err(AppealError::WindowExpired);
+        }
+
+        let row = sqlx::query_as!(
+            AppealRow,
+            r#"
+            INSERT INTO appeals (account_id, account_warning_id, text)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (account_warning_id) DO NOTHING
+            RETURNING id, account_id, account_warning_id, text, approved_at,
+                      approved_by_account_id, rejected_at, rejected_by_account_id,
+                      created_at, updated_at
+            "#,
+            account_id,
+            strike_id,
+            text
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(AppealError::AlreadyAppealed)?;
+
+        info!("Account {} appealed strike {}", account_id, strike_id);
+        Ok(row.into())
+    }
+
+    /// Gets an appeal by ID
+    pub async fn get<'e>(
+        executor: impl PgExecutor<'e>,
+        id: i64,
+    ) -> Result<Option<Self>, AppealError> {
+        let row = sqlx::query_as!(
+            AppealRow,
+            r#"
+            SELECT id, account_id, account_warning_id, text, approved_at,
+                   approved_by_account_id, rejected_at, rejected_by_account_id,
+                   created_at, updated_at
+            FROM appeals
+            WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(executor)
+        .await?;
+
+        Ok(row.map(Appeal::from))
+    }
+
+    /// Gets the appeals of strikes, such as the strikes of an account
+    pub async fn by_strikes(pool: &PgPool, strike_ids: &[i64]) -> Result<Vec<Self>, AppealError> {
+        let rows = sqlx::query_as!(
+            AppealRow,
+            r#"
+            SELECT id, account_id, account_warning_id, text, approved_at,
+                   approved_by_account_id, rejected_at, rejected_by_account_id,
+                   created_at, updated_at
+            FROM appeals
+            WHERE account_warning_id = ANY($1)
+            "#,
+            strike_ids
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(Appeal::from).collect())
+    }
+
+    /// Gets the appeals waiting for a decision, oldest first
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `limit` - Maximum number of appeals
+    pub async fn pending(pool: &PgPool, limit: i64) -> Result<Vec<Self>, AppealError> {
+        let rows = sqlx::query_as!(
+            AppealRow,
+            r#"
+            SELECT id, account_id, account_warning_id, text, approved_at,
+                   approved_by_account_id, rejected_at, rejected_by_account_id,
+                   created_at, updated_at
+            FROM appeals
+            WHERE approved_at IS NULL AND rejected_at IS NULL
+            ORDER BY id
+            LIMIT $1
+            "#,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(Appeal::from).collect())
+    }
+
+    /// Approves an appeal, overruling the strike and reversing its action
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection of the transaction to run in
+    /// * `id` - ID of the appeal
+    /// * `moderator_id` - ID of the approving moderator
+    pub async fn approve(
+        conn: &mut PgConnection,
+        id: i64,
+        moderator_id: i64,
+    ) -> Result<Self, AppealError> {
+        trace!("Approving appeal {}", id);
+
+        let row = sqlx::query_as!(
+            AppealRow,
+            r#"
+            UPDATE appeals
+            SET approved_at = NOW(), approved_by_account_id = $2, updated_at = NOW()
+            WHERE id = $1 AND approved_at IS NULL AND rejected_at IS NULL
+            RETURNING id, account_id, account_warning_id, text, approved_at,
+                      approved_by_account_id, rejected_at, rejected_by_account_id,
+                      created_at, updated_at
+            "#,
+            id,
+            moderator_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+        let Some(row) = row else {
+            return Err(Self::unprocessable(conn, id).await);
+        };
+
+        let strike = AccountWarning::get(&mut *conn, row.account_warning_id)
+            .await?
+            .ok_or(AppealError::StrikeNotFound(row.account_warning_id))?;
+        strike.overrule(conn).await?;
+
+        info!("Moderator {} approved appeal {}", moderator_id, id);
+        Ok(row.into())
+    }
+
+    /// Rejects an appeal, leaving the strike in place
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection of the transaction to run in
+    /// * `id` - ID of the appeal
+    /// * `moderator_id` - ID of the rejecting moderator
+    pub async fn reject(
+        conn: &mut PgConnection,
+        id: i64,
+        moderator_id: i64,
+    ) -> Result<Self, AppealError> {
+        trace!("Rejecting appeal {}", id);
+
+        let row = sqlx::query_as!(
+            AppealRow,
+            r#"
+            UPDATE appeals
+            SET rejected_at = NOW(), rejected_by_account_id = $2, updated_at = NOW()
+            WHERE id = $1 AND approved_at IS NULL AND rejected_at IS NULL
+            RETURNING id, account_id, account_warning_id, text, approved_at,
+                      approved_by_account_id, rejected_at, rejected_by_account_id,
+                      created_at, updated_at
+            "#,
+            id,
+            moderator_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+        let Some(row) = row else {
+            return Err(Self::unprocessable(conn, id).await);
+        };
+
+        info!("Moderator {} rejected appeal {}", moderator_id, id);
+        Ok(row.into())
+    }
+
+    /// Explains why an appeal could not be decided
+    async fn unprocessable(conn: &mut PgConnection, id: i64) -> AppealError {
+        match Self::get(conn, id).await {
+            Ok(Some(_)) => AppealError::AlreadyProcessed,
+            Ok(None) => AppealError::NotFound(id),
+            Err(e) => e,
+        }
+    }
+
+    /// Renders the email telling the owner about the decision
+    ///
+    /// # Arguments
+    ///
+    /// * `to` - Email address of the owner
+    pub fn email(&self, to: &str) -> Option<Email> {
+        let (subject, body) = match self.approved? {
+            true => (
+                "Your appeal has been approved",
+                "Your appeal has been approved and the strike against your account has been reversed.",
+            ),
+            false => (
+                "Your appeal has been rejected",
+                "Your appeal has been reviewed and rejected. The strike against your account stays in place.",
+            ),
+        };
+
+        Some(Email {
+            to: to.to_string(),
+            subject: subject.to_string(),
+            body: format!("{}\n\nYour appeal:\n\n{}\n", body, self.text),
+        })
+    }
+
+    /// Emails the owner about the decision on the appeal
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `mailer` - Mailer sending the email
+    pub async fn notify(&self, pool: &PgPool, mailer: &dyn AsyncMailer) -> Result<(), AppealError> {
+        let Some(address) = local_email(pool, self.account_id).await? else {
+            debug!("Not emailing remote account {}", self.account_id);
+            return Ok(());
+        };
+        let Some(email) = self.email(&address) else {
+            debug!("Appeal {} is still pending", self.id);
+            return Ok(());
+        };
+
+        mailer
+            .send(email)
+            .await
+            .map_err(|e| AppealError::Mailer(e.to_string()))
+    }
 }
 
 /// Initialize appeal functionality
@@ -45,21 +382,37 @@ pub async fn init_appeals() -> Result<(), AppealError> {
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_appeal() {
-        let appeal = Appeal {
+    fn appeal(approved: Option<bool>) -> Appeal {
+        Appeal {
             id: 1,
             account_id: 1,
             strike_id: 1,
             text: "I would like to appeal this strike".to_string(),
-            approved: None,
+            approved,
             approved_at: None,
             approved_by_account_id: None,
             rejected_at: None,
             rejected_by_account_id: None,
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
-        };
+        }
+    }
+
+    #[test]
+    fn test_appeal() {
+        let appeal = appeal(None);
         assert_eq!(appeal.account_id, 1);
     }
+
+    #[test]
+    fn test_email() {
+        assert!(appeal(None).email("alice@example.com").is_none());
+
+        let email = appeal(Some(true)).email("alice@example.com").unwrap();
+        assert_eq!(email.subject, "Your appeal has been approved");
+        assert!(email.body.contains("I would like to appeal this strike"));
+
+        let email = appeal(Some(false)).email("alice@example.com").unwrap();
+        assert_eq!(email.subject, "Your appeal has been rejected");
+    }
 }
@@ -0,0 +1,365 @@
+//! Archive documents
+//!
+//! Renders the ActivityPub documents of an archive: the account as a
+//! `Person` in `actor.json`, its posts and boosts as `Create` and
+//! `Announce` activities in `outbox.json`, and the statuses it liked or
+//! bookmarked as collections of URIs in `likes.json` and `bookmarks.json`.
+//! Media files are stored next to them under `media_attachments/`, and the
+//! documents refer to them by their path in the archive.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use chrono::{DateTime, Utc};
+use rustodon_activitypub::{local_actor_uri, ACTIVITY_STREAMS_CONTEXT, PUBLIC_COLLECTION};
+use serde_json::{json, Value};
+
+/// Directory of the media files in an archive
+pub const MEDIA_DIRECTORY: &str = "media_attachments";
+
+/// The account being archived
+#[derive(Debug, Clone)]
+pub struct ArchiveAccount {
+    pub username: String,
+    pub display_name: Option<String>,
+    pub note: Option<String>,
+    pub locked: bool,
+    pub bot: bool,
+    pub discoverable: bool,
+    pub group_account: bool,
+    /// Path of the avatar in the archive
+    pub avatar: Option<String>,
+    /// Path of the header image in the archive
+    pub header: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A media file attached to an archived status
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveMedia {
+    /// Path of the file in the archive
+    pub path: String,
+    pub content_type: Option<String>,
+    pub description: Option<String>,
+}
+
+/// A status or boost of the archived account
+#[derive(Debug, Clone)]
+pub struct ArchiveStatus {
+    pub id: i64,
+    /// URI of the status
+    pub uri: String,
+    pub url: Option<String>,
+    pub content: String,
+    pub visibility: String,
+    pub sensitive: bool,
+    pub spoiler_text: Option<String>,
+    pub language: Option<String>,
+    /// URI of the status this one replies to
+    pub in_reply_to_uri: Option<String>,
+    /// URI of the boosted status, if this is a boost
+    pub reblog_of_uri: Option<String>,
+    /// Actor URIs of the mentioned accounts
+    pub mentioned_uris: Vec<String>,
+    pub media: Vec<ArchiveMedia>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Gets the path in the archive of a media file served from this instance
+///
+/// # Arguments
+///
+/// * `url` - URL of the file
+/// * `base_url` - Base URL media is served from
+///
+/// # Returns
+///
+/// The path of the file relative to the media root, or `None` if the file
+/// is not stored on this instance
+pub fn media_path(url: &str, base_url: &str) -> Option<String> {
+    let relative = url
+        .strip_prefix(base_url.trim_end_matches('/'))?
+        .strip_prefix("/media/")?;
+    let safe = !relative.is_empty()
+        && relative
+            .split('/')
+            .all(|segment| !segment.is_empty() && segment != "." && segment != "..");
+    safe.then(|| relative.to_string())
+}
+
+/// Formats a timestamp the way ActivityPub documents do
+fn timestamp(time: &DateTime<Utc>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+/// Renders an image of the actor
+fn image(path: &Option<String>) -> Value {
+    match path {
+        Some(path) => json!({ "type": "Image", "url": path }),
+        None => Value::Null,
+    }
+}
+
+/// Renders the account as an ActivityPub actor
+///
+/// # Arguments
+///
+/// * `account` - The archived account
+/// * `domain` - Local instance domain
+pub fn actor_json(account: &ArchiveAccount, domain: &str) -> Value {
+    let actor_uri = local_actor_uri(domain, &account.username);
+    let actor_type = if account.group_account {
+        "Group"
+    } else if account.bot {
+        "Service"
+    } else {
+        "Person"
+    };
+
+    json!({
+        "@context": ACTIVITY_STREAMS_CONTEXT,
+        "id": actor_uri,
+        "type": actor_type,
+        "preferredUsername": account.username,
+        "name": account.display_name.clone().unwrap_or_default(),
+        "summary": account.note.clone().unwrap_or_default(),
+        "url": format!("https://{}/@{}", domain, account.username),
+        "inbox": format!("{}/inbox", actor_uri),
+        "outbox": "outbox.json",
+        "following": format!("{}/following", actor_uri),
+        "followers": format!("{}/followers", actor_uri),
+        "featured": format!("{}/collections/featured", actor_uri),
+        "likes": "likes.json",
+        "bookmarks": "bookmarks.json",
+        "manuallyApprovesFollowers": account.locked,
+        "discoverable": account.discoverable,
+        "published": timestamp(&account.created_at),
+        "icon": image(&account.avatar),
+        "image": image(&account.header),
+    })
+}
+
+/// Addressing of an activity by visibility
+fn audience(
+    visibility: &str,
+    actor_uri: &str,
+    mentioned_uris: &[String],
+) -> (Vec<String>, Vec<String>) {
+    let followers = format!("{}/followers", actor_uri);
+    let (to, mut cc) = match visibility {
+        "public" => (vec![PUBLIC_COLLECTION.to_string()], vec![followers]),
+        "unlisted" => (vec![followers], vec![PUBLIC_COLLECTION.to_string()]),
+        "private" => (vec![followers], Vec::new()),
+        _ => (mentioned_uris.to_vec(), Vec::new()),
+    };
+    if visibility != "direct" {
+        cc.extend(mentioned_uris.iter().cloned());
+    }
+    (to, cc)
+}
+
+/// Renders a status as the activity that published it
+fn activity_json(status: &ArchiveStatus, actor_uri: &str) -> Value {
+    let (to, cc) = audience(&status.visibility, actor_uri, &status.mentioned_uris);
+    let published = timestamp(&status.created_at);
+
+    if let Some(reblog_of_uri) = &status.reblog_of_uri {
+        return json!({
+            "id": format!("{}/activity", status.uri),
+            "type": "Announce",
+            "actor": actor_uri,
+            "published": published,
+            "to": to,
+            "cc": cc,
+            "object": reblog_of_uri,
+        });
+    }
+
+    let attachment: Vec<Value> = status
+        .media
+        .iter()
+        .map(|media| {
+            json!({
+                "type": "Document",
+                "mediaType": media.content_type,
+                "url": media.path,
+                "name": media.description,
+            })
+        })
+        .collect();
+    let tag: Vec<Value> = status
+        .mentioned_uris
+        .iter()
+        .map(|uri| json!({ "type": "Mention", "href": uri }))
+        .collect();
+
+    json!({
+        "id": format!("{}/activity", status.uri),
+        "type": "Create",
+        "actor": actor_uri,
+        "published": published,
+        "to": to,
+        "cc": cc,
+        "object": {
+            "id": status.uri,
+            "type": "Note",
+            "summary": status.spoiler_text.as_deref().filter(|text| !text.is_empty()),
+            "inReplyTo": status.in_reply_to_uri,
+            "published": published,
+            "url": status.url,
+            "attributedTo": actor_uri,
+            "to": to,
+            "cc": cc,
+            "sensitive": status.sensitive,
+            "content": status.content,
+            "contentMap": status
+                .language
+                .as_ref()
+                .map(|language| json!({ language: status.content })),
+            "attachment": attachment,
+            "tag": tag,
+        },
+    })
+}
+
+/// Renders the posts and boosts of the account as its outbox
+///
+/// # Arguments
+///
+/// * `account` - The archived account
+/// * `statuses` - Statuses of the account, oldest first
+/// * `domain` - Local instance domain
+pub fn outbox_json(account: &ArchiveAccount, statuses: &[ArchiveStatus], domain: &str) -> Value {
+    let actor_uri = local_actor_uri(domain, &account.username);
+    let items: Vec<Value> = statuses
+        .iter()
+        .map(|status| activity_json(status, &actor_uri))
+        .collect();
+
+    json!({
+        "@context": ACTIVITY_STREAMS_CONTEXT,
+        "id": "outbox.json",
+        "type": "OrderedCollection",
+        "totalItems": items.len(),
+        "orderedItems": items,
+    })
+}
+
+/// Renders a collection of status URIs, such as the liked statuses
+///
+/// # Arguments
+///
+/// * `id` - Name of the document in the archive
+/// * `uris` - URIs of the statuses
+pub fn collection_json(id: &str, uris: &[String]) -> Value {
+    json!({
+        "@context": ACTIVITY_STREAMS_CONTEXT,
+        "id": id,
+        "type": "OrderedCollection",
+        "orderedItems": uris,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account() -> ArchiveAccount {
+        ArchiveAccount {
+            username: "alice".to_string(),
+            display_name: Some("Alice".to_string()),
+            note: None,
+            locked: true,
+            bot: false,
+            discoverable: true,
+            group_account: false,
+            avatar: Some("media_attachments/avatars/1.png".to_string()),
+            header: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    fn status(visibility: &str) -> ArchiveStatus {
+        ArchiveStatus {
+            id: 1,
+            uri: "https://example.com/users/alice/statuses/1".to_string(),
+            url: Some("https://example.com/@alice/1".to_string()),
+            content: "<p>Hello</p>".to_string(),
+            visibility: visibility.to_string(),
+            sensitive: false,
+            spoiler_text: None,
+            language: Some("en".to_string()),
+            in_reply_to_uri: None,
+            reblog_of_uri: None,
+            mentioned_uris: vec!["https://other.example/users/bob".to_string()],
+            media: vec![ArchiveMedia {
+                path: "media_attachments/original/a.jpg".to_string(),
+                content_type: Some("image/jpeg".to_string()),
+                description: None,
+            }],
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_media_path() {
+        let base = "https://example.com";
+        assert_eq!(
+            media_path("https://example.com/media/original/a.jpg", base).as_deref(),
+            Some("original/a.jpg")
+        );
+        assert_eq!(
+            media_path(
+                "https://example.com/media/original/a.jpg",
+                "https://example.com/"
+            )
+            .as_deref(),
+            Some("original/a.jpg")
+        );
+        assert_eq!(media_path("https://cdn.example/media/a.jpg", base), None);
+        assert_eq!(
+            media_path("https://example.com/media/../secret", base),
+            None
+        );
+    }
+
+    #[test]
+    fn test_actor_json() {
+        let actor = actor_json(&account(), "example.com");
+        assert_eq!(actor["id"], "https://example.com/users/alice");
+        assert_eq!(actor["type"], "Person");
+        assert_eq!(actor["manuallyApprovesFollowers"], true);
+        assert_eq!(actor["icon"]["url"], "media_attachments/avatars/1.png");
+        assert!(actor["image"].is_null());
+    }
+
+    #[test]
+    fn test_outbox_json() {
+        let mut boost = status("public");
+        boost.reblog_of_uri = Some("https://other.example/notes/9".to_string());
+        let outbox = outbox_json(
+            &account(),
+            &[status("public"), status("direct"), boost],
+            "example.com",
+        );
+        assert_eq!(outbox["totalItems"], 3);
+
+        let create = &outbox["orderedItems"][0];
+        assert_eq!(create["type"], "Create");
+        assert_eq!(create["to"][0], PUBLIC_COLLECTION);
+        assert_eq!(
+            create["object"]["attachment"][0]["url"],
+            "media_attachments/original/a.jpg"
+        );
+        assert_eq!(create["object"]["contentMap"]["en"], "<p>Hello</p>");
+
+        let direct = &outbox["orderedItems"][1];
+        assert_eq!(direct["to"][0], "https://other.example/users/bob");
+        assert_eq!(direct["cc"].as_array().map(Vec::len), Some(0));
+
+        let announce = &outbox["orderedItems"][2];
+        assert_eq!(announce["type"], "Announce");
+        assert_eq!(announce["object"], "https://other.example/notes/9");
+    }
+}
@@ -0,0 +1,335 @@
+//! CSV exports
+//!
+//! Renders the CSV files Mastodon offers for download: followed accounts
+//! and followers, list members, blocked and muted accounts, blocked domains
+//! and bookmarks. The files use the same layout as the ones the bulk
+//! importer reads, so an export can be imported again here or elsewhere.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::BackupsError;
+use sqlx::PgPool;
+use tracing::debug;
+
+/// A CSV export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFile {
+    Following,
+    Followers,
+    Lists,
+    Blocks,
+    Mutes,
+    DomainBlocks,
+    Bookmarks,
+}
+
+impl ExportFile {
+    /// Every export, in the order they are offered
+    pub const ALL: [ExportFile; 7] = [
+        ExportFile::Following,
+        ExportFile::Followers,
+        ExportFile::Lists,
+        ExportFile::Blocks,
+        ExportFile::Mutes,
+        ExportFile::DomainBlocks,
+        ExportFile::Bookmarks,
+    ];
+
+    /// Name of the downloaded file
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            ExportFile::Following => "following_accounts.csv",
+            ExportFile::Followers => "followers.csv",
+            ExportFile::Lists => "lists.csv",
+            ExportFile::Blocks => "blocked_accounts.csv",
+            ExportFile::Mutes => "muted_accounts.csv",
+            ExportFile::DomainBlocks => "blocked_domains.csv",
+            ExportFile::Bookmarks => "bookmarks.csv",
+        }
+    }
+
+    /// Gets an export by the name of its file
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|file| file.file_name() == name)
+    }
+
+    /// Header line of the file, if it has one
+    fn header(&self) -> Option<&'static [&'static str]> {
+        match self {
+            ExportFile::Following => Some(&[
+                "Account address",
+                "Show boosts",
+                "Notify on new posts",
+                "Languages",
+            ]),
+            ExportFile::Followers => Some(&["Account address"]),
+            ExportFile::Mutes => Some(&["Account address", "Hide notifications"]),
+            ExportFile::Lists
+            | ExportFile::Blocks
+            | ExportFile::DomainBlocks
+            | ExportFile::Bookmarks => None,
+        }
+    }
+}
+
+/// Formats the address of an account, including the domain of local ones
+fn acct(username: &str, domain: Option<&str>, local_domain: &str) -> String {
+    format!("{}@{}", username, domain.unwrap_or(local_domain))
+}
+
+/// Writes the header and rows of an export
+fn render(file: ExportFile, rows: &[Vec<String>]) -> Result<String, BackupsError> {
+    let mut writer = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_writer(Vec::new());
+    let csv_error = |e: csv::Error| BackupsError::Archive(e.to_string());
+
+    if let Some(header) = file.header() {
+        writer.write_record(header).map_err(csv_error)?;
+    }
+    for row in rows {
+        writer.write_record(row).map_err(csv_error)?;
+    }
+
+    let data = writer
+        .into_inner()
+        .map_err(|e| BackupsError::Archive(e.to_string()))?;
+    String::from_utf8(data).map_err(|e| BackupsError::Archive(e.to_string()))
+}
+
+/// Renders a CSV export of an account
+///
+/// # Arguments
+///
+/// * `pool` - Database connection pool
+/// * `account_id` - ID of the exporting account
+/// * `file` - Which export to render
+/// * `local_domain` - Domain of this instance
+///
+/// # Returns
+///
+/// Contents of the CSV file
+pub async fn export_csv(
+    pool: &PgPool,
+    account_id: i64,
+    file: ExportFile,
+    local_domain: &str,
+) -> Result<String, BackupsError> {
+    let address = |username: &str, domain: Option<&str>| acct(username, domain, local_domain);
+    let flag = |value: bool| value.to_string();
+
+    let rows: Vec<Vec<String>> = match file {
+        ExportFile::Following => sqlx::query!(
+            r#"
+            SELECT u.username, u.domain, f.show_reblogs, f.notify
+            FROM follows f
+            JOIN users u ON u.id = f.followed_id
+            WHERE f.follower_id = $1
+            ORDER BY f.id
+            "#,
+            account_id
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| {
+            vec![
+                address(&row.username, row.domain.as_deref()),
+                flag(row.show_reblogs),
+                flag(row.notify),
+                String::new(),
+            ]
+        })
+        .collect(),
+        ExportFile::Followers => sqlx::query!(
+            r#"
+            SELECT u.username, u.domain
+            FROM follows f
+            JOIN users u ON u.id = f.follower_id
+            WHERE f.followed_id = $1
+            ORDER BY f.id
+            "#,
+            account_id
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| vec![address(&row.username, row.domain.as_deref())])
+        .collect(),
+        ExportFile::Lists => sqlx::query!(
+            r#"
+            SELECT l.title, u.username, u.domain
+            FROM list_accounts la
+            JOIN lists l ON l.id = la.list_id
+            JOIN users u ON u.id = la.account_id
+            WHERE l.account_id = $1
+            ORDER BY l.id, la.id
+            "#,
+            account_id
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| vec![row.title, address(&row.username, row.domain.as_deref())])
+        .collect(),
+        ExportFile::Blocks => sqlx::query!(
+            r#"
+            SELECT u.username, u.domain
+            FROM blocks b
+            JOIN users u ON u.id = b.blocked_id
+            WHERE b.blocker_id = $1
+            ORDER BY b.id
+            "#,
+            account_id
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| vec![address(&row.username, row.domain.as_deref())])
+        .collect(),
+        ExportFile::Mutes => sqlx::query!(
+            r#"
+            SELECT u.username, u.domain, m.hide_notifications
+            FROM mutes m
+            JOIN users u ON u.id = m.muted_id
+            WHERE m.muter_id = $1
+            ORDER BY m.id
+            "#,
+            account_id
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| {
+            vec![
+                address(&row.username, row.domain.as_deref()),
+                flag(row.hide_notifications),
+            ]
+        })
+        .collect(),
+        ExportFile::DomainBlocks => sqlx::query_scalar!(
+            "SELECT domain FROM domain_blocks WHERE account_id = $1 ORDER BY id",
+            account_id
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|domain| vec![domain])
+        .collect(),
+        ExportFile::Bookmarks => sqlx::query_scalar!(
+            r#"
+            SELECT COALESCE(s.uri, 'https://' || $2 || '/users/' || u.username || '/statuses/' || s.id)
+                AS "uri!"
+            FROM bookmarks b
+            JOIN statuses s ON s.id = b.status_id
+            JOIN users u ON u.id = s.account_id
+            WHERE b.account_id = $1 AND s.deleted_at IS NULL
+            ORDER BY b.id
+            "#,
+            account_id,
+            local_domain
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|uri| vec![uri])
+        .collect(),
+    };
+
+    debug!(
+        "Exporting {} rows of {} for account {}",
+        rows.len(),
+        file.file_name(),
+        account_id
+    );
+    render(file, &rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustodon_bulk_imports::{parse_csv, BulkImportType, ImportRow};
+
+    fn rows(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter()
+            .map(|row| row.iter().map(|value| value.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_file_names() {
+        for file in ExportFile::ALL {
+            assert_eq!(ExportFile::from_file_name(file.file_name()), Some(file));
+        }
+        assert_eq!(ExportFile::from_file_name("passwords.csv"), None);
+    }
+
+    #[test]
+    fn test_following_round_trips() {
+        let data = render(
+            ExportFile::Following,
+            &rows(&[
+                &["alice@example.com", "true", "false", ""],
+                &["bob@other.example", "false", "true", ""],
+            ]),
+        )
+        .unwrap();
+        assert!(data.starts_with("Account address,Show boosts,Notify on new posts,Languages\n"));
+
+        let imported = parse_csv(BulkImportType::Following, data.as_bytes()).unwrap();
+        assert_eq!(
+            imported[1],
+            ImportRow::Follow {
+                acct: "bob@other.example".to_string(),
+                show_reblogs: false,
+                notify: true,
+            }
+        );
+        assert_eq!(imported.len(), 2);
+    }
+
+    #[test]
+    fn test_other_exports_round_trip() {
+        let data = render(ExportFile::Mutes, &rows(&[&["alice@example.com", "false"]])).unwrap();
+        assert_eq!(
+            parse_csv(BulkImportType::Muting, data.as_bytes()).unwrap(),
+            vec![ImportRow::Mute {
+                acct: "alice@example.com".to_string(),
+                hide_notifications: false,
+            }]
+        );
+
+        let data = render(
+            ExportFile::Lists,
+            &rows(&[&["Friends, family", "alice@example.com"]]),
+        )
+        .unwrap();
+        assert_eq!(
+            parse_csv(BulkImportType::Lists, data.as_bytes()).unwrap(),
+            vec![ImportRow::ListAccount {
+                list_name: "Friends, family".to_string(),
+                acct: "alice@example.com".to_string(),
+            }]
+        );
+
+        let data = render(ExportFile::DomainBlocks, &rows(&[&["spam.example"]])).unwrap();
+        assert_eq!(data, "spam.example\n");
+        assert_eq!(
+            parse_csv(BulkImportType::DomainBlocking, data.as_bytes()).unwrap(),
+            vec![ImportRow::DomainBlock {
+                domain: "spam.example".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_acct_includes_local_domain() {
+        assert_eq!(acct("alice", None, "example.com"), "alice@example.com");
+        assert_eq!(
+            acct("bob", Some("other.example"), "example.com"),
+            "bob@other.example"
+        );
+    }
+}
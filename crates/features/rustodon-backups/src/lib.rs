@@ -0,0 +1,746 @@
+//! Account archives for Rustodon
+//!
+//! Accounts can download an archive of their data: a zip file with their
+//! profile in `actor.json`, their posts and boosts in `outbox.json`, the
+//! statuses they liked and bookmarked in `likes.json` and `bookmarks.json`,
+//! and the media files they uploaded. Archives are requested through the
+//! API and generated by a background job; an account may request one
+//! archive per week. Separate CSV exports, readable by the bulk importer,
+//! are rendered on demand by [`exports`].
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_backups::{Backup, export_csv, ExportFile};
+//!
+//! let backup = Backup::request(&pool, account_id).await?;
+//! let backup = Backup::generate(&pool, backup.id, "example.com", &storage).await?;
+//! let path = backup.path(&storage);
+//! let csv = export_csv(&pool, account_id, ExportFile::Following, "example.com").await?;
+//! ```
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+pub mod archive;
+pub mod exports;
+
+pub use archive::{ArchiveAccount, ArchiveMedia, ArchiveStatus};
+pub use exports::{export_csv, ExportFile};
+
+use archive::{actor_json, collection_json, media_path, outbox_json, MEDIA_DIRECTORY};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use rustodon_media::{MediaProcessor, StorageConfig};
+use rustodon_workers::{Job, WorkerError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use thiserror::Error;
+use tracing::{debug, error, info, trace, warn};
+
+/// Days an account has to wait between two archives
+pub const BACKUP_INTERVAL_DAYS: i64 = 7;
+
+/// Error type for backup operations
+#[derive(Error, Debug)]
+pub enum BackupsError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Archive error: {0}")]
+    Archive(String),
+    #[error("An archive can be requested again at {0}")]
+    RateLimited(DateTime<Utc>),
+    #[error("Backup not found: {0}")]
+    NotFound(i64),
+    #[error("Backup not ready: {0}")]
+    NotReady(i64),
+}
+
+/// Progress of an archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupState {
+    Pending,
+    Processing,
+    Finished,
+    Failed,
+}
+
+impl BackupState {
+    /// Name of the state in the database and the API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackupState::Pending => "pending",
+            BackupState::Processing => "processing",
+            BackupState::Finished => "finished",
+            BackupState::Failed => "failed",
+        }
+    }
+
+    /// Parses a state name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "pending" => Some(BackupState::Pending),
+            "processing" => Some(BackupState::Processing),
+            "finished" => Some(BackupState::Finished),
+            "failed" => Some(BackupState::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// Backup model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Backup {
+    pub id: i64,
+    pub account_id: i64,
+    pub state: BackupState,
+    /// Name of the archive in the backups directory, once generated
+    pub file_name: Option<String>,
+    /// Size of the archive in bytes, once generated
+    pub file_size: Option<i64>,
+    /// Why the archive could not be generated
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// Internal struct for database rows
+struct BackupRow {
+    id: i64,
+    account_id: i64,
+    state: String,
+    file_name: Option<String>,
+    file_size: Option<i64>,
+    error: Option<String>,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    finished_at: Option<NaiveDateTime>,
+}
+
+impl TryFrom<BackupRow> for Backup {
+    type Error = BackupsError;
+
+    fn try_from(row: BackupRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            account_id: row.account_id,
+            state: BackupState::parse(&row.state)
+                .ok_or_else(|| BackupsError::Archive(format!("Unknown state: {}", row.state)))?,
+            file_name: row.file_name,
+            file_size: row.file_size,
+            error: row.error,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+            updated_at: DateTime::from_naive_utc_and_offset(row.updated_at, Utc),
+            finished_at: row
+                .finished_at
+                .map(|finished_at| DateTime::from_naive_utc_and_offset(finished_at, Utc)),
+        })
+    }
+}
+
+/// Directory archives are written to
+pub fn backups_directory(config: &StorageConfig) -> PathBuf {
+    config.media_root.join("backups")
+}
+
+/// Everything an archive contains
+struct ArchiveContents {
+    actor: Value,
+    outbox: Value,
+    likes: Value,
+    bookmarks: Value,
+    /// Media files, relative to the media root
+    media: BTreeSet<String>,
+}
+
+impl Backup {
+    /// Requests an archive of an account
+    ///
+    /// An account may request one archive every [`BACKUP_INTERVAL_DAYS`]
+    /// days; failed archives do not count.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the account
+    pub async fn request(pool: &PgPool, account_id: i64) -> Result<Self, BackupsError> {
+        trace!("Requesting archive of account {}", account_id);
+
+        let row = sqlx::query_as!(
+            BackupRow,
+            r#"
+            INSERT INTO backups (account_id)
+            SELECT $1
+            WHERE NOT EXISTS (
+                SELECT 1 FROM backups
+                WHERE account_id = $1
+                  AND state <> 'failed'
+                  AND created_at > NOW() - make_interval(days => $2)
+            )
+            RETURNING id, account_id, state, file_name, file_size, error, created_at,
+                      updated_at, finished_at
+            "#,
+            account_id,
+            BACKUP_INTERVAL_DAYS as i32
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        match row {
+            Some(row) => {
+                info!("Scheduled archive {} of account {}", row.id, account_id);
+                row.try_into()
+            }
+            None => {
+                let last = sqlx::query_scalar!(
+                    r#"
+                    SELECT MAX(created_at) AS "created_at!"
+                    FROM backups
+                    WHERE account_id = $1 AND state <> 'failed'
+                    "#,
+                    account_id
+                )
+                .fetch_one(pool)
+                .await?;
+                Err(BackupsError::RateLimited(
+                    DateTime::from_naive_utc_and_offset(last, Utc)
+                        + Duration::days(BACKUP_INTERVAL_DAYS),
+                ))
+            }
+        }
+    }
+
+    /// Gets an archive of an account
+    pub async fn get(pool: &PgPool, account_id: i64, id: i64) -> Result<Self, BackupsError> {
+        let row = sqlx::query_as!(
+            BackupRow,
+            r#"
+            SELECT id, account_id, state, file_name, file_size, error, created_at, updated_at,
+                   finished_at
+            FROM backups
+            WHERE id = $1 AND account_id = $2
+            "#,
+            id,
+            account_id
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(BackupsError::NotFound(id))?;
+
+        row.try_into()
+    }
+
+    /// Gets the archives of an account, newest first
+    pub async fn get_by_account(pool: &PgPool, account_id: i64) -> Result<Vec<Self>, BackupsError> {
+        let rows = sqlx::query_as!(
+            BackupRow,
+            r#"
+            SELECT id, account_id, state, file_name, file_size, error, created_at, updated_at,
+                   finished_at
+            FROM backups
+            WHERE account_id = $1
+            ORDER BY id DESC
+            "#,
+            account_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        rows.into_iter().map(Self::try_from).collect()
+    }
+
+    /// Gets the IDs of the archives waiting to be generated, oldest first
+    pub async fn pending_ids(pool: &PgPool) -> Result<Vec<i64>, BackupsError> {
+        let ids = sqlx::query_scalar!("SELECT id FROM backups WHERE state = 'pending' ORDER BY id")
+            .fetch_all(pool)
+            .await?;
+
+        Ok(ids)
+    }
+
+    /// Path of the generated archive
+    ///
+    /// # Returns
+    ///
+    /// The path, or `None` if the archive is not generated yet
+    pub fn path(&self, config: &StorageConfig) -> Option<PathBuf> {
+        match (self.state, &self.file_name) {
+            (BackupState::Finished, Some(file_name)) => {
+                Some(backups_directory(config).join(file_name))
+            }
+            _ => None,
+        }
+    }
+
+    /// Generates a pending archive
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `id` - ID of the archive
+    /// * `local_domain` - Domain of this instance
+    /// * `config` - Media storage configuration
+    ///
+    /// # Returns
+    ///
+    /// The finished archive. If generating it fails, the archive is marked
+    /// failed and the error is returned.
+    pub async fn generate(
+        pool: &PgPool,
+        id: i64,
+        local_domain: &str,
+        config: &StorageConfig,
+    ) -> Result<Self, BackupsError> {
+        let account_id = sqlx::query_scalar!(
+            r#"
+            UPDATE backups SET state = 'processing', updated_at = NOW()
+            WHERE id = $1 AND state = 'pending'
+            RETURNING account_id
+            "#,
+            id
+        )
+        .fetch_optional(pool)
+        .await?;
+        let Some(account_id) = account_id else {
+            return Self::find(pool, id).await;
+        };
+
+        info!("Generating archive {} of account {}", id, account_id);
+        match Self::write_archive(pool, account_id, local_domain, config).await {
+            Ok((file_name, file_size)) => {
+                sqlx::query!(
+                    r#"
+                    UPDATE backups
+                    SET state = 'finished', file_name = $2, file_size = $3,
+                        finished_at = NOW(), updated_at = NOW()
+                    WHERE id = $1
+                    "#,
+                    id,
+                    file_name,
+                    file_size
+                )
+                .execute(pool)
+                .await?;
+                info!("Generated archive {} ({} bytes)", id, file_size);
+                Self::find(pool, id).await
+            }
+            Err(e) => {
+                error!("Failed to generate archive {}: {}", id, e);
+                sqlx::query!(
+                    "UPDATE backups SET state = 'failed', error = $2, updated_at = NOW() WHERE id = $1",
+                    id,
+                    e.to_string()
+                )
+                .execute(pool)
+                .await?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Gets an archive by ID, whoever it belongs to
+    async fn find(pool: &PgPool, id: i64) -> Result<Self, BackupsError> {
+        let row = sqlx::query_as!(
+            BackupRow,
+            r#"
+            SELECT id, account_id, state, file_name, file_size, error, created_at, updated_at,
+                   finished_at
+            FROM backups
+            WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(BackupsError::NotFound(id))?;
+
+        row.try_into()
+    }
+
+    /// Collects the data of an account and writes it to a new zip file
+    ///
+    /// # Returns
+    ///
+    /// Name and size of the zip file
+    async fn write_archive(
+        pool: &PgPool,
+        account_id: i64,
+        local_domain: &str,
+        config: &StorageConfig,
+    ) -> Result<(String, i64), BackupsError> {
+        let contents = collect_contents(pool, account_id, local_domain, config).await?;
+
+        let directory = backups_directory(config);
+        tokio::fs::create_dir_all(&directory).await?;
+        let file_name = format!("{}.zip", uuid::Uuid::new_v4());
+        let path = directory.join(&file_name);
+        let media_root = config.media_root.clone();
+
+        let written = path.clone();
+        let result =
+            tokio::task::spawn_blocking(move || write_zip(&written, &media_root, &contents))
+                .await
+                .map_err(|e| BackupsError::Archive(e.to_string()))
+                .and_then(|result| result);
+        if let Err(e) = result {
+            let _ = tokio::fs::remove_file(&path).await;
+            return Err(e);
+        }
+
+        let file_size = tokio::fs::metadata(&path).await?.len() as i64;
+        Ok((file_name, file_size))
+    }
+}
+
+/// Collects the documents and media files of an archive
+async fn collect_contents(
+    pool: &PgPool,
+    account_id: i64,
+    local_domain: &str,
+    config: &StorageConfig,
+) -> Result<ArchiveContents, BackupsError> {
+    let user = sqlx::query!(
+        r#"
+        SELECT username, display_name, note, locked, bot, discoverable, group_account, avatar,
+               header, created_at
+        FROM users
+        WHERE id = $1
+        "#,
+        account_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| BackupsError::Archive(format!("Account not found: {}", account_id)))?;
+
+    let mut media = BTreeSet::new();
+    let mut archived_image = |url: Option<String>| {
+        let relative = media_path(&url?, &config.base_url)?;
+        let path = format!("{}/{}", MEDIA_DIRECTORY, relative);
+        media.insert(relative);
+        Some(path)
+    };
+    let account = ArchiveAccount {
+        username: user.username,
+        display_name: user.display_name,
+        note: user.note,
+        locked: user.locked,
+        bot: user.bot,
+        discoverable: user.discoverable,
+        group_account: user.group_account,
+        avatar: archived_image(user.avatar),
+        header: archived_image(user.header),
+        created_at: DateTime::from_naive_utc_and_offset(user.created_at, Utc),
+    };
+
+    let rows = sqlx::query!(
+        r#"
+        SELECT s.id,
+               COALESCE(s.uri, 'https://' || $2 || '/users/' || u.username || '/statuses/' || s.id)
+                   AS "uri!",
+               s.url, s.content, s.visibility::TEXT AS "visibility!", s.sensitive, s.spoiler_text,
+               s.language, s.media_attachments, s.created_at,
+               COALESCE(p.uri, 'https://' || $2 || '/users/' || pu.username || '/statuses/' || p.id)
+                   AS in_reply_to_uri,
+               COALESCE(r.uri, 'https://' || $2 || '/users/' || ru.username || '/statuses/' || r.id)
+                   AS reblog_of_uri,
+               ARRAY(
+                   SELECT COALESCE(mu.uri, 'https://' || $2 || '/users/' || mu.username)
+                   FROM mentions m
+                   JOIN users mu ON mu.id = m.account_id
+                   WHERE m.status_id = s.id
+                   ORDER BY m.id
+               ) AS "mentioned_uris!"
+        FROM statuses s
+        JOIN users u ON u.id = s.account_id
+        LEFT JOIN statuses p ON p.id = s.in_reply_to_id
+        LEFT JOIN users pu ON pu.id = p.account_id
+        LEFT JOIN statuses r ON r.id = s.reblog_of_id
+        LEFT JOIN users ru ON ru.id = r.account_id
+        WHERE s.account_id = $1 AND s.deleted_at IS NULL
+        ORDER BY s.id
+        "#,
+        account_id,
+        local_domain
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let processor = MediaProcessor::new(pool.clone(), config.clone());
+    let mut statuses = Vec::with_capacity(rows.len());
+    for row in rows {
+        let mut attachments = Vec::new();
+        for media_id in media_ids(row.media_attachments.as_ref()) {
+            let attachment = match processor.get_media_attachment(media_id, account_id).await {
+                Ok(attachment) => attachment,
+                Err(e) => {
+                    warn!("Leaving media {} out of the archive: {}", media_id, e);
+                    continue;
+                }
+            };
+            let Some(relative) = attachment
+                .url
+                .as_deref()
+                .and_then(|url| media_path(url, &config.base_url))
+            else {
+                continue;
+            };
+            attachments.push(ArchiveMedia {
+                path: format!("{}/{}", MEDIA_DIRECTORY, relative),
+                content_type: attachment.file_content_type,
+                description: attachment.description,
+            });
+            media.insert(relative);
+        }
+
+        statuses.push(ArchiveStatus {
+            id: row.id,
+            uri: row.uri,
+            url: row.url,
+            content: row.content,
+            visibility: row.visibility,
+            sensitive: row.sensitive,
+            spoiler_text: row.spoiler_text,
+            language: row.language,
+            in_reply_to_uri: row.in_reply_to_uri,
+            reblog_of_uri: row.reblog_of_uri,
+            mentioned_uris: row.mentioned_uris,
+            media: attachments,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+        });
+    }
+
+    let likes = sqlx::query_scalar!(
+        r#"
+        SELECT COALESCE(s.uri, 'https://' || $2 || '/users/' || u.username || '/statuses/' || s.id)
+            AS "uri!"
+        FROM favourites f
+        JOIN statuses s ON s.id = f.status_id
+        JOIN users u ON u.id = s.account_id
+        WHERE f.account_id = $1 AND s.deleted_at IS NULL
+        ORDER BY f.id
+        "#,
+        account_id,
+        local_domain
+    )
+    .fetch_all(pool)
+    .await?;
+    let bookmarks = sqlx::query_scalar!(
+        r#"
+        SELECT COALESCE(s.uri, 'https://' || $2 || '/users/' || u.username || '/statuses/' || s.id)
+            AS "uri!"
+        FROM bookmarks b
+        JOIN statuses s ON s.id = b.status_id
+        JOIN users u ON u.id = s.account_id
+        WHERE b.account_id = $1 AND s.deleted_at IS NULL
+        ORDER BY b.id
+        "#,
+        account_id,
+        local_domain
+    )
+    .fetch_all(pool)
+    .await?;
+
+    debug!(
+        "Archive of account {} has {} statuses, {} likes, {} bookmarks and {} media files",
+        account_id,
+        statuses.len(),
+        likes.len(),
+        bookmarks.len(),
+        media.len()
+    );
+    Ok(ArchiveContents {
+        actor: actor_json(&account, local_domain),
+        outbox: outbox_json(&account, &statuses, local_domain),
+        likes: collection_json("likes.json", &likes),
+        bookmarks: collection_json("bookmarks.json", &bookmarks),
+        media,
+    })
+}
+
+/// Reads the media IDs stored with a status
+fn media_ids(media_attachments: Option<&Value>) -> Vec<i64> {
+    media_attachments
+        .and_then(Value::as_array)
+        .map(|attachments| {
+            attachments
+                .iter()
+                .filter_map(|attachment| match &attachment["id"] {
+                    Value::String(id) => id.parse().ok(),
+                    id => id.as_i64(),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Writes the documents and media files of an archive to a zip file
+fn write_zip(
+    path: &Path,
+    media_root: &Path,
+    contents: &ArchiveContents,
+) -> Result<(), BackupsError> {
+    let zip_error = |e: zip::result::ZipError| BackupsError::Archive(e.to_string());
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(path)?);
+
+    let documents = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    for (name, document) in [
+        ("actor.json", &contents.actor),
+        ("outbox.json", &contents.outbox),
+        ("likes.json", &contents.likes),
+        ("bookmarks.json", &contents.bookmarks),
+    ] {
+        zip.start_file(name, documents).map_err(zip_error)?;
+        serde_json::to_writer_pretty(&mut zip, document)
+            .map_err(|e| BackupsError::Archive(e.to_string()))?;
+    }
+
+    // Media files are compressed already
+    let files = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored)
+        .large_file(true);
+    for relative in &contents.media {
+        let mut file = match std::fs::File::open(media_root.join(relative)) {
+            Ok(file) => file,
+            Err(e) => {
+                warn!("Leaving {} out of the archive: {}", relative, e);
+                continue;
+            }
+        };
+        zip.start_file(format!("{}/{}", MEDIA_DIRECTORY, relative), files)
+            .map_err(zip_error)?;
+        std::io::copy(&mut file, &mut zip)?;
+    }
+
+    zip.finish().map_err(zip_error)?.flush()?;
+    Ok(())
+}
+
+/// Background job generating the pending archives
+pub struct GenerateBackupsJob {
+    pool: PgPool,
+    local_domain: String,
+    config: StorageConfig,
+}
+
+impl GenerateBackupsJob {
+    /// Creates the job
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `local_domain` - Domain of this instance
+    /// * `config` - Media storage configuration
+    pub fn new(pool: PgPool, local_domain: String, config: StorageConfig) -> Self {
+        Self {
+            pool,
+            local_domain,
+            config,
+        }
+    }
+}
+
+impl Job for GenerateBackupsJob {
+    fn name(&self) -> &'static str {
+        "GenerateBackupsJob"
+    }
+
+    fn execute(&self) -> Pin<Box<dyn Future<Output = Result<(), WorkerError>> + Send>> {
+        let pool = self.pool.clone();
+        let local_domain = self.local_domain.clone();
+        let config = self.config.clone();
+        Box::pin(async move {
+            let ids = Backup::pending_ids(&pool)
+                .await
+                .map_err(|e| WorkerError::Job(e.to_string()))?;
+            for id in ids {
+                if let Err(e) = Backup::generate(&pool, id, &local_domain, &config).await {
+                    warn!("Failed to generate archive {}: {}", id, e);
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_media_ids() {
+        let attachments = json!([{ "id": "3" }, { "id": 4 }, { "url": "x" }]);
+        assert_eq!(media_ids(Some(&attachments)), vec![3, 4]);
+        assert!(media_ids(None).is_empty());
+    }
+
+    #[test]
+    fn test_write_zip() {
+        let root = std::env::temp_dir().join(format!("rustodon-backup-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("original")).unwrap();
+        std::fs::write(root.join("original/a.jpg"), b"jpeg").unwrap();
+
+        let contents = ArchiveContents {
+            actor: json!({ "type": "Person" }),
+            outbox: json!({ "orderedItems": [] }),
+            likes: json!({}),
+            bookmarks: json!({}),
+            media: [
+                "original/a.jpg".to_string(),
+                "original/missing.jpg".to_string(),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let path = root.join("archive.zip");
+        write_zip(&path, &root, &contents).unwrap();
+
+        let archive = zip::ZipArchive::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "actor.json",
+                "bookmarks.json",
+                "likes.json",
+                "media_attachments/original/a.jpg",
+                "outbox.json"
+            ]
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_path_only_when_finished() {
+        let config = StorageConfig::default();
+        let mut backup = Backup {
+            id: 1,
+            account_id: 1,
+            state: BackupState::Processing,
+            file_name: Some("a.zip".to_string()),
+            file_size: None,
+            error: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            finished_at: None,
+        };
+        assert_eq!(backup.path(&config), None);
+        backup.state = BackupState::Finished;
+        assert_eq!(
+            backup.path(&config),
+            Some(config.media_root.join("backups/a.zip"))
+        );
+    }
+}
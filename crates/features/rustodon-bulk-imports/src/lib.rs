@@ -1,27 +1,56 @@
 //! Bulk import functionality for Rustodon
 //!
-//! This module provides bulk import capabilities for the Rustodon server.
+//! Accounts can import the CSV files Mastodon exports: followed, blocked
+//! and muted accounts, blocked domains, bookmarks and lists. An uploaded
+//! file is parsed right away and its rows are stored; a background job then
+//! processes them in batches, counting processed and imported rows so
+//! progress can be shown. Rows that cannot be imported, such as accounts
+//! this server does not know, are kept with the reason so they can be
+//! reported back.
+//!
+//! In `merge` mode the rows are added to what the account already has. In
+//! `overwrite` mode, once every row is processed, whatever the file did not
+//! list is removed.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_bulk_imports::{parse_csv, BulkImport, BulkImportMode, BulkImportType};
+//!
+//! let rows = parse_csv(BulkImportType::Following, &data)?;
+//! let import = BulkImport::create(&pool, account_id, BulkImportType::Following,
+//!     BulkImportMode::Merge, Some("following_accounts.csv"), &rows).await?;
+//! let import = BulkImport::process_batch(&pool, import.id, "example.com", 500).await?;
+//! let failures = BulkImport::failed_rows(&pool, import.id).await?;
+//! ```
 //!
 //! # Author
 //!
 //! arkSong (arksong2018@gmail.com)
 
+pub mod parser;
+
+pub use parser::{parse_csv, ImportRow, MAX_ROWS};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rustodon_blocks::{Block, BlocksError};
+use rustodon_bookmarks::{Bookmark, BookmarksError};
+use rustodon_domains::{DomainBlock, DomainBlockError};
+use rustodon_follow_requests::{follow_or_request, FollowOutcome, FollowRequestsError};
+use rustodon_follows::{Follow, FollowsError};
+use rustodon_lists::{CreateListRequest, List, ListsError};
+use rustodon_mutes::{Mute, MutesError};
+use rustodon_notifications::{CreateNotificationRequest, Notification, NotificationType};
+use rustodon_workers::{Job, WorkerError};
 use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::future::Future;
+use std::pin::Pin;
 use thiserror::Error;
-use tracing::{error, info};
+use tracing::{debug, info, trace, warn};
 
-/// Bulk import model
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct BulkImport {
-    pub id: i64,
-    pub account_id: i64,
-    pub type_: String,
-    pub state: String,
-    pub total_items: i64,
-    pub imported_items: i64,
-    pub created_at: chrono::DateTime<chrono::Utc>,
-    pub updated_at: chrono::DateTime<chrono::Utc>,
-}
+/// Number of rows processed per import each time the job runs
+pub const BATCH_SIZE: i64 = 500;
 
 /// Error type for bulk import operations
 #[derive(Error, Debug)]
@@ -32,30 +61,976 @@ pub enum BulkImportError {
     Import(String),
     #[error("File error: {0}")]
     File(String),
+    #[error("Import not found: {0}")]
+    NotFound(i64),
+}
+
+/// What an import contains
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkImportType {
+    Following,
+    Blocking,
+    Muting,
+    DomainBlocking,
+    Bookmarks,
+    Lists,
+}
+
+impl BulkImportType {
+    /// Name of the type in the database and the API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BulkImportType::Following => "following",
+            BulkImportType::Blocking => "blocking",
+            BulkImportType::Muting => "muting",
+            BulkImportType::DomainBlocking => "domain_blocking",
+            BulkImportType::Bookmarks => "bookmarks",
+            BulkImportType::Lists => "lists",
+        }
+    }
+
+    /// Parses a type name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "following" => Some(BulkImportType::Following),
+            "blocking" => Some(BulkImportType::Blocking),
+            "muting" => Some(BulkImportType::Muting),
+            "domain_blocking" => Some(BulkImportType::DomainBlocking),
+            "bookmarks" => Some(BulkImportType::Bookmarks),
+            "lists" => Some(BulkImportType::Lists),
+            _ => None,
+        }
+    }
+}
+
+/// How an import treats what the account already has
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkImportMode {
+    /// Keep existing entries and add the imported ones
+    Merge,
+    /// Remove existing entries the file does not list
+    Overwrite,
+}
+
+impl BulkImportMode {
+    /// Name of the mode in the database and the API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BulkImportMode::Merge => "merge",
+            BulkImportMode::Overwrite => "overwrite",
+        }
+    }
+
+    /// Parses a mode name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "merge" => Some(BulkImportMode::Merge),
+            "overwrite" => Some(BulkImportMode::Overwrite),
+            _ => None,
+        }
+    }
+}
+
+/// Progress of an import
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkImportState {
+    Scheduled,
+    InProgress,
+    Finished,
+}
+
+impl BulkImportState {
+    /// Name of the state in the database and the API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BulkImportState::Scheduled => "scheduled",
+            BulkImportState::InProgress => "in_progress",
+            BulkImportState::Finished => "finished",
+        }
+    }
+
+    /// Parses a state name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "scheduled" => Some(BulkImportState::Scheduled),
+            "in_progress" => Some(BulkImportState::InProgress),
+            "finished" => Some(BulkImportState::Finished),
+            _ => None,
+        }
+    }
+}
+
+/// Bulk import model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkImport {
+    pub id: i64,
+    pub account_id: i64,
+    pub import_type: BulkImportType,
+    pub mode: BulkImportMode,
+    pub state: BulkImportState,
+    /// Name of the uploaded file
+    pub original_filename: Option<String>,
+    /// Number of rows in the file
+    pub total_items: i32,
+    /// Number of rows processed so far
+    pub processed_items: i32,
+    /// Number of rows imported successfully so far
+    pub imported_items: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// A row that could not be imported
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedRow {
+    pub id: i64,
+    /// The row as read from the file
+    pub data: ImportRow,
+    /// Why the row could not be imported
+    pub error: String,
+}
+
+/// Internal struct for database rows
+struct BulkImportRow {
+    id: i64,
+    account_id: i64,
+    import_type: String,
+    mode: String,
+    state: String,
+    original_filename: Option<String>,
+    total_items: i32,
+    processed_items: i32,
+    imported_items: i32,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    finished_at: Option<NaiveDateTime>,
+}
+
+impl TryFrom<BulkImportRow> for BulkImport {
+    type Error = BulkImportError;
+
+    fn try_from(row: BulkImportRow) -> Result<Self, Self::Error> {
+        let unknown = |what: &str, value: &str| {
+            BulkImportError::Import(format!("Unknown import {}: {}", what, value))
+        };
+        Ok(Self {
+            id: row.id,
+            account_id: row.account_id,
+            import_type: BulkImportType::parse(&row.import_type)
+                .ok_or_else(|| unknown("type", &row.import_type))?,
+            mode: BulkImportMode::parse(&row.mode).ok_or_else(|| unknown("mode", &row.mode))?,
+            state: BulkImportState::parse(&row.state)
+                .ok_or_else(|| unknown("state", &row.state))?,
+            original_filename: row.original_filename,
+            total_items: row.total_items,
+            processed_items: row.processed_items,
+            imported_items: row.imported_items,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+            updated_at: DateTime::from_naive_utc_and_offset(row.updated_at, Utc),
+            finished_at: row
+                .finished_at
+                .map(|finished_at| DateTime::from_naive_utc_and_offset(finished_at, Utc)),
+        })
+    }
+}
+
+/// What became of a row
+enum RowOutcome {
+    /// The row was imported; the ID is that of the account or status it
+    /// refers to, if any
+    Imported(Option<i64>),
+    /// The row could not be imported, for this reason
+    Failed(String),
+}
+
+impl BulkImport {
+    /// Creates an import from the rows of an uploaded file
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the importing account
+    /// * `import_type` - What the file contains
+    /// * `mode` - Whether to merge with or overwrite existing entries
+    /// * `original_filename` - Name of the uploaded file
+    /// * `rows` - Rows parsed from the file
+    pub async fn create(
+        pool: &PgPool,
+        account_id: i64,
+        import_type: BulkImportType,
+        mode: BulkImportMode,
+        original_filename: Option<&str>,
+        rows: &[ImportRow],
+    ) -> Result<Self, BulkImportError> {
+        trace!(
+            "Creating {} import of {} rows for account {}",
+            import_type.as_str(),
+            rows.len(),
+            account_id
+        );
+
+        let data = rows
+            .iter()
+            .map(serde_json::to_value)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| BulkImportError::Import(e.to_string()))?;
+
+        let mut tx = pool.begin().await?;
+        let row = sqlx::query_as!(
+            BulkImportRow,
+            r#"
+            INSERT INTO bulk_imports (account_id, import_type, mode, original_filename, total_items)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, account_id, import_type, mode, state, original_filename, total_items,
+                      processed_items, imported_items, created_at, updated_at, finished_at
+            "#,
+            account_id,
+            import_type.as_str(),
+            mode.as_str(),
+            original_filename,
+            rows.len() as i32
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO bulk_import_rows (bulk_import_id, data)
+            SELECT $1, UNNEST($2::JSONB[])
+            "#,
+            row.id,
+            &data
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        info!(
+            "Scheduled {} import {} for account {}",
+            import_type.as_str(),
+            row.id,
+            account_id
+        );
+        row.try_into()
+    }
+
+    /// Gets an import of an account
+    pub async fn get(pool: &PgPool, account_id: i64, id: i64) -> Result<Self, BulkImportError> {
+        let row = sqlx::query_as!(
+            BulkImportRow,
+            r#"
+            SELECT id, account_id, import_type, mode, state, original_filename, total_items,
+                   processed_items, imported_items, created_at, updated_at, finished_at
+            FROM bulk_imports
+            WHERE id = $1 AND account_id = $2
+            "#,
+            id,
+            account_id
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(BulkImportError::NotFound(id))?;
+
+        row.try_into()
+    }
+
+    /// Gets the imports of an account, newest first
+    pub async fn get_by_account(
+        pool: &PgPool,
+        account_id: i64,
+    ) -> Result<Vec<Self>, BulkImportError> {
+        let rows = sqlx::query_as!(
+            BulkImportRow,
+            r#"
+            SELECT id, account_id, import_type, mode, state, original_filename, total_items,
+                   processed_items, imported_items, created_at, updated_at, finished_at
+            FROM bulk_imports
+            WHERE account_id = $1
+            ORDER BY id DESC
+            "#,
+            account_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        rows.into_iter().map(Self::try_from).collect()
+    }
+
+    /// Gets the rows of an import that could not be imported
+    pub async fn failed_rows(pool: &PgPool, id: i64) -> Result<Vec<FailedRow>, BulkImportError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, data, error AS "error!"
+            FROM bulk_import_rows
+            WHERE bulk_import_id = $1 AND error IS NOT NULL
+            ORDER BY id
+            "#,
+            id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(FailedRow {
+                    id: row.id,
+                    data: serde_json::from_value(row.data)
+                        .map_err(|e| BulkImportError::Import(e.to_string()))?,
+                    error: row.error,
+                })
+            })
+            .collect()
+    }
+
+    /// Gets the IDs of the imports still being processed, oldest first
+    pub async fn unfinished_ids(pool: &PgPool) -> Result<Vec<i64>, BulkImportError> {
+        let ids = sqlx::query_scalar!(
+            "SELECT id FROM bulk_imports WHERE state <> 'finished' ORDER BY id"
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(ids)
+    }
+
+    /// Processes the next batch of rows of an import
+    ///
+    /// Once the last row is processed, entries missing from the file are
+    /// removed in overwrite mode and the import is finished.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `id` - ID of the import
+    /// * `local_domain` - Domain of this instance
+    /// * `batch_size` - Maximum number of rows to process
+    ///
+    /// # Returns
+    ///
+    /// The import with its updated progress
+    pub async fn process_batch(
+        pool: &PgPool,
+        id: i64,
+        local_domain: &str,
+        batch_size: i64,
+    ) -> Result<Self, BulkImportError> {
+        let import = Self::find(pool, id).await?;
+        if import.state == BulkImportState::Finished {
+            return Ok(import);
+        }
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, data
+            FROM bulk_import_rows
+            WHERE bulk_import_id = $1 AND processed_at IS NULL
+            ORDER BY id
+            LIMIT $2
+            "#,
+            id,
+            batch_size
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut imported = 0;
+        for row in &rows {
+            let outcome = match serde_json::from_value::<ImportRow>(row.data.clone()) {
+                Ok(data) => process_row(pool, import.account_id, &data, local_domain).await?,
+                Err(e) => RowOutcome::Failed(format!("Invalid row: {}", e)),
+            };
+            match outcome {
+                RowOutcome::Imported(target_id) => {
+                    imported += 1;
+                    sqlx::query!(
+                        "UPDATE bulk_import_rows SET target_id = $2, processed_at = NOW() WHERE id = $1",
+                        row.id,
+                        target_id
+                    )
+                    .execute(pool)
+                    .await?;
+                }
+                RowOutcome::Failed(error) => {
+                    trace!("Row {} of import {} failed: {}", row.id, id, error);
+                    sqlx::query!(
+                        "UPDATE bulk_import_rows SET error = $2, processed_at = NOW() WHERE id = $1",
+                        row.id,
+                        error
+                    )
+                    .execute(pool)
+                    .await?;
+                }
+            }
+        }
+
+        sqlx::query!(
+            r#"
+            UPDATE bulk_imports
+            SET state = 'in_progress',
+                processed_items = processed_items + $2,
+                imported_items = imported_items + $3,
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+            id,
+            rows.len() as i32,
+            imported
+        )
+        .execute(pool)
+        .await?;
+
+        let remaining = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM bulk_import_rows WHERE bulk_import_id = $1 AND processed_at IS NULL
+            ) AS "remaining!"
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+        if !remaining {
+            if import.mode == BulkImportMode::Overwrite {
+                remove_unlisted(pool, &import).await?;
+            }
+            sqlx::query!(
+                r#"
+                UPDATE bulk_imports
+                SET state = 'finished', finished_at = NOW(), updated_at = NOW()
+                WHERE id = $1
+                "#,
+                id
+            )
+            .execute(pool)
+            .await?;
+            info!("Finished import {} of account {}", id, import.account_id);
+        }
+
+        debug!(
+            "Processed {} rows of import {}, {} imported",
+            rows.len(),
+            id,
+            imported
+        );
+        Self::find(pool, id).await
+    }
+
+    /// Gets an import by ID, whoever it belongs to
+    async fn find(pool: &PgPool, id: i64) -> Result<Self, BulkImportError> {
+        let row = sqlx::query_as!(
+            BulkImportRow,
+            r#"
+            SELECT id, account_id, import_type, mode, state, original_filename, total_items,
+                   processed_items, imported_items, created_at, updated_at, finished_at
+            FROM bulk_imports
+            WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(BulkImportError::NotFound(id))?;
+
+        row.try_into()
+    }
+}
+
+/// Splits an account address into username and domain
+///
+/// The local domain is dropped, since local accounts have no domain.
+fn split_acct<'a>(acct: &'a str, local_domain: &str) -> (&'a str, Option<&'a str>) {
+    match acct.split_once('@') {
+        Some((username, domain)) if !domain.eq_ignore_ascii_case(local_domain) => {
+            (username, Some(domain))
+        }
+        Some((username, _)) => (username, None),
+        None => (acct, None),
+    }
+}
+
+/// Gets the ID of a local status from its URL or URI on this server
+fn local_status_id(uri: &str, local_domain: &str) -> Option<i64> {
+    let path = uri
+        .strip_prefix("https://")
+        .and_then(|rest| rest.strip_prefix(local_domain))?;
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match segments.as_slice() {
+        [user, id] if user.starts_with('@') => id.parse().ok(),
+        ["users", _, "statuses", id] => id.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Looks up a known account by address
+///
+/// # Returns
+///
+/// The account's ID and whether it is local
+async fn resolve_account(
+    pool: &PgPool,
+    acct: &str,
+    local_domain: &str,
+) -> Result<Option<(i64, bool)>, BulkImportError> {
+    let (username, domain) = split_acct(acct, local_domain);
+    let row = sqlx::query!(
+        r#"
+        SELECT id, domain
+        FROM users
+        WHERE LOWER(username) = LOWER($1)
+          AND LOWER(domain) IS NOT DISTINCT FROM LOWER($2)
+          AND status <> 'deleted'
+        "#,
+        username,
+        domain
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| (row.id, row.domain.is_none())))
 }
 
-/// Initialize bulk import functionality
-pub async fn init_bulk_imports() -> Result<(), BulkImportError> {
-    info!("Initializing bulk import functionality");
+/// Looks up a known status by URL or URI that an account may see
+async fn resolve_status(
+    pool: &PgPool,
+    account_id: i64,
+    uri: &str,
+    local_domain: &str,
+) -> Result<Option<i64>, BulkImportError> {
+    let local_id = local_status_id(uri, local_domain);
+    let id = sqlx::query_scalar!(
+        r#"
+        SELECT s.id
+        FROM statuses s
+        WHERE (s.id = $2 OR s.uri = $3 OR s.url = $3)
+          AND s.deleted_at IS NULL
+          AND (
+              s.visibility IN ('public', 'unlisted')
+              OR s.account_id = $1
+              OR (
+                  s.visibility = 'private'
+                  AND EXISTS (
+                      SELECT 1 FROM follows WHERE follower_id = $1 AND followed_id = s.account_id
+                  )
+              )
+          )
+        ORDER BY s.id
+        LIMIT 1
+        "#,
+        account_id,
+        local_id,
+        uri
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(id)
+}
+
+/// Imports one row
+///
+/// Expected failures become failed rows; database errors abort the batch so
+/// the row is retried on the next run.
+async fn process_row(
+    pool: &PgPool,
+    account_id: i64,
+    row: &ImportRow,
+    local_domain: &str,
+) -> Result<RowOutcome, BulkImportError> {
+    let not_found = |acct: &str| RowOutcome::Failed(format!("Account not found: {}", acct));
+
+    match row {
+        ImportRow::Follow {
+            acct,
+            show_reblogs,
+            notify,
+        } => {
+            let Some((target_id, local)) = resolve_account(pool, acct, local_domain).await? else {
+                return Ok(not_found(acct));
+            };
+            let notification_type =
+                match follow_or_request(pool, account_id, target_id, *show_reblogs, *notify, None)
+                    .await
+                {
+                    Ok(FollowOutcome::Followed(_)) => Some(NotificationType::Follow),
+                    Ok(FollowOutcome::Requested(_)) => Some(NotificationType::FollowRequest),
+                    Err(FollowRequestsError::AlreadyFollowing)
+                    | Err(FollowRequestsError::Follows(FollowsError::AlreadyFollowing)) => {
+                        // Existing follows take the imported settings
+                        match Follow::update_settings(
+                            pool,
+                            account_id,
+                            target_id,
+                            *show_reblogs,
+                            *notify,
+                        )
+                        .await
+                        {
+                            Ok(_) | Err(FollowsError::FollowNotFound) => {}
+                            Err(FollowsError::Database(sqlx::Error::RowNotFound)) => {}
+                            Err(FollowsError::Database(e)) => return Err(e.into()),
+                            Err(e) => warn!("Failed to apply imported follow settings: {}", e),
+                        }
+                        None
+                    }
+                    Err(FollowRequestsError::AlreadyRequested) => None,
+                    Err(FollowRequestsError::Database(e))
+                    | Err(FollowRequestsError::Follows(FollowsError::Database(e))) => {
+                        return Err(e.into())
+                    }
+                    Err(e) => return Ok(RowOutcome::Failed(e.to_string())),
+                };
+
+            if let (Some(notification_type), true) = (notification_type, local) {
+                if let Err(e) = Notification::create(
+                    pool,
+                    CreateNotificationRequest {
+                        account_id: target_id,
+                        from_account_id: Some(account_id),
+                        notification_type,
+                        status_id: None,
+                        poll_id: None,
+                        report_id: None,
+                    },
+                )
+                .await
+                {
+                    warn!("Failed to create follow notification: {}", e);
+                }
+            }
+            Ok(RowOutcome::Imported(Some(target_id)))
+        }
+        ImportRow::Block { acct } => {
+            let Some((target_id, _)) = resolve_account(pool, acct, local_domain).await? else {
+                return Ok(not_found(acct));
+            };
+            match Block::create(pool, account_id, target_id).await {
+                Ok(_) | Err(BlocksError::AlreadyBlocked) => {
+                    Ok(RowOutcome::Imported(Some(target_id)))
+                }
+                Err(BlocksError::Database(e)) => Err(e.into()),
+                Err(e) => Ok(RowOutcome::Failed(e.to_string())),
+            }
+        }
+        ImportRow::Mute {
+            acct,
+            hide_notifications,
+        } => {
+            let Some((target_id, _)) = resolve_account(pool, acct, local_domain).await? else {
+                return Ok(not_found(acct));
+            };
+            match Mute::create(pool, account_id, target_id, Some(*hide_notifications)).await {
+                Ok(_) | Err(MutesError::AlreadyMuted) => Ok(RowOutcome::Imported(Some(target_id))),
+                Err(MutesError::Database(e)) => Err(e.into()),
+                Err(e) => Ok(RowOutcome::Failed(e.to_string())),
+            }
+        }
+        ImportRow::DomainBlock { domain } => {
+            if domain.eq_ignore_ascii_case(local_domain)
+                || !domain.contains('.')
+                || domain.contains(char::is_whitespace)
+            {
+                return Ok(RowOutcome::Failed(format!("Invalid domain: {}", domain)));
+            }
+            match DomainBlock::create(pool, account_id, domain).await {
+                Ok(_) | Err(DomainBlockError::AlreadyBlocked) => Ok(RowOutcome::Imported(None)),
+                Err(DomainBlockError::Database(e)) => Err(e.into()),
+                Err(e) => Ok(RowOutcome::Failed(e.to_string())),
+            }
+        }
+        ImportRow::Bookmark { uri } => {
+            let Some(status_id) = resolve_status(pool, account_id, uri, local_domain).await? else {
+                return Ok(RowOutcome::Failed(format!("Status not found: {}", uri)));
+            };
+            match Bookmark::create(pool, account_id, status_id).await {
+                Ok(_) | Err(BookmarksError::AlreadyBookmarked) => {
+                    Ok(RowOutcome::Imported(Some(status_id)))
+                }
+                Err(BookmarksError::Database(e)) => Err(e.into()),
+                Err(e) => Ok(RowOutcome::Failed(e.to_string())),
+            }
+        }
+        ImportRow::ListAccount { list_name, acct } => {
+            let Some((target_id, _)) = resolve_account(pool, acct, local_domain).await? else {
+                return Ok(not_found(acct));
+            };
+            let list_id = match find_or_create_list(pool, account_id, list_name).await {
+                Ok(list_id) => list_id,
+                Err(ListsError::Database(e)) => return Err(e.into()),
+                Err(e) => return Ok(RowOutcome::Failed(e.to_string())),
+            };
+            match List::add_account(pool, list_id, target_id).await {
+                Ok(_) | Err(ListsError::AccountAlreadyInList) => {
+                    Ok(RowOutcome::Imported(Some(target_id)))
+                }
+                Err(ListsError::Database(e)) => Err(e.into()),
+                Err(e) => Ok(RowOutcome::Failed(e.to_string())),
+            }
+        }
+    }
+}
+
+/// Gets the ID of an account's list with the given title, creating it if
+/// needed
+async fn find_or_create_list(
+    pool: &PgPool,
+    account_id: i64,
+    title: &str,
+) -> Result<i64, ListsError> {
+    let existing = sqlx::query_scalar!(
+        "SELECT id FROM lists WHERE account_id = $1 AND title = $2 ORDER BY id LIMIT 1",
+        account_id,
+        title
+    )
+    .fetch_optional(pool)
+    .await?;
+    if let Some(id) = existing {
+        return Ok(id);
+    }
+
+    let list = List::create(
+        pool,
+        account_id,
+        CreateListRequest {
+            title: title.to_string(),
+            is_private: false,
+        },
+    )
+    .await?;
+    Ok(list.id)
+}
+
+/// Removes the entries of the importing account the file did not list
+async fn remove_unlisted(pool: &PgPool, import: &BulkImport) -> Result<(), BulkImportError> {
+    let result = match import.import_type {
+        BulkImportType::Following => {
+            sqlx::query!(
+                r#"
+                DELETE FROM follow_requests
+                WHERE account_id = $1
+                  AND target_account_id NOT IN (
+                      SELECT target_id FROM bulk_import_rows
+                      WHERE bulk_import_id = $2 AND target_id IS NOT NULL
+                  )
+                "#,
+                import.account_id,
+                import.id
+            )
+            .execute(pool)
+            .await?;
+            sqlx::query!(
+                r#"
+                DELETE FROM follows
+                WHERE follower_id = $1
+                  AND followed_id NOT IN (
+                      SELECT target_id FROM bulk_import_rows
+                      WHERE bulk_import_id = $2 AND target_id IS NOT NULL
+                  )
+                "#,
+                import.account_id,
+                import.id
+            )
+            .execute(pool)
+            .await?
+        }
+        BulkImportType::Blocking => {
+            sqlx::query!(
+                r#"
+                DELETE FROM blocks
+                WHERE blocker_id = $1
+                  AND blocked_id NOT IN (
+                      SELECT target_id FROM bulk_import_rows
+                      WHERE bulk_import_id = $2 AND target_id IS NOT NULL
+                  )
+                "#,
+                import.account_id,
+                import.id
+            )
+            .execute(pool)
+            .await?
+        }
+        BulkImportType::Muting => {
+            sqlx::query!(
+                r#"
+                DELETE FROM mutes
+                WHERE muter_id = $1
+                  AND muted_id NOT IN (
+                      SELECT target_id FROM bulk_import_rows
+                      WHERE bulk_import_id = $2 AND target_id IS NOT NULL
+                  )
+                "#,
+                import.account_id,
+                import.id
+            )
+            .execute(pool)
+            .await?
+        }
+        BulkImportType::DomainBlocking => {
+            sqlx::query!(
+                r#"
+                DELETE FROM domain_blocks
+                WHERE account_id = $1
+                  AND domain NOT IN (
+                      SELECT data->>'domain' FROM bulk_import_rows
+                      WHERE bulk_import_id = $2 AND processed_at IS NOT NULL AND error IS NULL
+                  )
+                "#,
+                import.account_id,
+                import.id
+            )
+            .execute(pool)
+            .await?
+        }
+        BulkImportType::Bookmarks => {
+            sqlx::query!(
+                r#"
+                DELETE FROM bookmarks
+                WHERE account_id = $1
+                  AND status_id NOT IN (
+                      SELECT target_id FROM bulk_import_rows
+                      WHERE bulk_import_id = $2 AND target_id IS NOT NULL
+                  )
+                "#,
+                import.account_id,
+                import.id
+            )
+            .execute(pool)
+            .await?
+        }
+        BulkImportType::Lists => {
+            sqlx::query!(
+                r#"
+                DELETE FROM list_accounts la
+                USING lists l
+                WHERE la.list_id = l.id
+                  AND l.account_id = $1
+                  AND l.title IN (
+                      SELECT data->>'list_name' FROM bulk_import_rows WHERE bulk_import_id = $2
+                  )
+                  AND NOT EXISTS (
+                      SELECT 1 FROM bulk_import_rows r
+                      WHERE r.bulk_import_id = $2
+                        AND r.target_id = la.account_id
+                        AND r.data->>'list_name' = l.title
+                  )
+                "#,
+                import.account_id,
+                import.id
+            )
+            .execute(pool)
+            .await?
+        }
+    };
+
+    info!(
+        "Import {} overwrote {} {} entries of account {}",
+        import.id,
+        result.rows_affected(),
+        import.import_type.as_str(),
+        import.account_id
+    );
     Ok(())
 }
 
+/// Background job processing a batch of every unfinished import
+pub struct ProcessBulkImportsJob {
+    pool: PgPool,
+    local_domain: String,
+}
+
+impl ProcessBulkImportsJob {
+    /// Creates the job
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `local_domain` - Domain of this instance
+    pub fn new(pool: PgPool, local_domain: String) -> Self {
+        Self { pool, local_domain }
+    }
+}
+
+impl Job for ProcessBulkImportsJob {
+    fn name(&self) -> &'static str {
+        "ProcessBulkImportsJob"
+    }
+
+    fn execute(&self) -> Pin<Box<dyn Future<Output = Result<(), WorkerError>> + Send>> {
+        let pool = self.pool.clone();
+        let local_domain = self.local_domain.clone();
+        Box::pin(async move {
+            let ids = BulkImport::unfinished_ids(&pool)
+                .await
+                .map_err(|e| WorkerError::Job(e.to_string()))?;
+            for id in ids {
+                if let Err(e) =
+                    BulkImport::process_batch(&pool, id, &local_domain, BATCH_SIZE).await
+                {
+                    warn!("Failed to process import {}: {}", id, e);
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
-    fn test_bulk_import() {
-        let import = BulkImport {
-            id: 1,
-            account_id: 1,
-            type_: "following".to_string(),
-            state: "in_progress".to_string(),
-            total_items: 100,
-            imported_items: 50,
-            created_at: chrono::Utc::now(),
-            updated_at: chrono::Utc::now(),
-        };
-        assert_eq!(import.type_, "following");
+    fn test_split_acct() {
+        assert_eq!(split_acct("alice", "example.com"), ("alice", None));
+        assert_eq!(
+            split_acct("alice@example.com", "example.com"),
+            ("alice", None)
+        );
+        assert_eq!(
+            split_acct("bob@other.example", "example.com"),
+            ("bob", Some("other.example"))
+        );
+    }
+
+    #[test]
+    fn test_local_status_id() {
+        let domain = "example.com";
+        assert_eq!(
+            local_status_id("https://example.com/@alice/42", domain),
+            Some(42)
+        );
+        assert_eq!(
+            local_status_id("https://example.com/users/alice/statuses/7", domain),
+            Some(7)
+        );
+        assert_eq!(
+            local_status_id("https://other.example/@alice/42", domain),
+            None
+        );
+        assert_eq!(local_status_id("https://example.com/@alice", domain), None);
+    }
+
+    #[test]
+    fn test_names_round_trip() {
+        for import_type in [
+            BulkImportType::Following,
+            BulkImportType::DomainBlocking,
+            BulkImportType::Lists,
+        ] {
+            assert_eq!(
+                BulkImportType::parse(import_type.as_str()),
+                Some(import_type)
+            );
+        }
+        assert_eq!(
+            BulkImportMode::parse("overwrite"),
+            Some(BulkImportMode::Overwrite)
+        );
+        assert_eq!(
+            BulkImportState::parse("in_progress"),
+            Some(BulkImportState::InProgress)
+        );
+        assert_eq!(BulkImportType::parse("emoji"), None);
     }
 }
@@ -0,0 +1,241 @@
+//! CSV parsing
+//!
+//! Reads the CSV files Mastodon exports: followed accounts with their
+//! boost and notification settings, muted accounts with whether their
+//! notifications are hidden, and one-column files of blocked accounts,
+//! blocked domains and bookmarked status URLs. List files have a list name
+//! and an account address per line. Header lines are skipped, and account
+//! addresses may start with `@`.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::{BulkImportError, BulkImportType};
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of rows in an import
+pub const MAX_ROWS: usize = 20_000;
+
+/// A row of an import
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ImportRow {
+    /// An account to follow
+    Follow {
+        acct: String,
+        show_reblogs: bool,
+        notify: bool,
+    },
+    /// An account to block
+    Block { acct: String },
+    /// An account to mute
+    Mute {
+        acct: String,
+        hide_notifications: bool,
+    },
+    /// A domain to block
+    DomainBlock { domain: String },
+    /// A status to bookmark, by URL
+    Bookmark { uri: String },
+    /// An account to add to a list
+    ListAccount { list_name: String, acct: String },
+}
+
+/// Parses a boolean column, falling back to a default when empty
+fn flag(value: Option<&str>, default: bool, line: usize) -> Result<bool, BulkImportError> {
+    match value.map(str::trim).unwrap_or("") {
+        "" => Ok(default),
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        other => Err(BulkImportError::File(format!(
+            "Invalid boolean on line {}: {}",
+            line, other
+        ))),
+    }
+}
+
+/// Normalizes an account address
+fn acct(value: &str) -> String {
+    value.trim().trim_start_matches('@').to_string()
+}
+
+/// Whether a record is the header line of a file
+fn is_header(record: &csv::StringRecord) -> bool {
+    matches!(
+        record.get(0).map(str::trim),
+        Some("Account address") | Some("#domain") | Some("#uri")
+    )
+}
+
+/// Parses an uploaded CSV file
+///
+/// # Arguments
+///
+/// * `import_type` - What the file contains
+/// * `data` - Contents of the file
+///
+/// # Returns
+///
+/// Rows of the file, in order
+pub fn parse_csv(
+    import_type: BulkImportType,
+    data: &[u8],
+) -> Result<Vec<ImportRow>, BulkImportError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data);
+
+    let mut rows = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let line = index + 1;
+        let record = record.map_err(|e| BulkImportError::File(format!("Invalid CSV: {}", e)))?;
+        let first = record.get(0).unwrap_or("");
+        if first.is_empty() || (index == 0 && is_header(&record)) {
+            continue;
+        }
+
+        let row = match import_type {
+            BulkImportType::Following => ImportRow::Follow {
+                acct: acct(first),
+                show_reblogs: flag(record.get(1), true, line)?,
+                notify: flag(record.get(2), false, line)?,
+            },
+            BulkImportType::Blocking => ImportRow::Block { acct: acct(first) },
+            BulkImportType::Muting => ImportRow::Mute {
+                acct: acct(first),
+                hide_notifications: flag(record.get(1), true, line)?,
+            },
+            BulkImportType::DomainBlocking => ImportRow::DomainBlock {
+                domain: first.to_ascii_lowercase(),
+            },
+            BulkImportType::Bookmarks => ImportRow::Bookmark {
+                uri: first.to_string(),
+            },
+            BulkImportType::Lists => {
+                let member = record.get(1).unwrap_or("");
+                if member.is_empty() {
+                    return Err(BulkImportError::File(format!(
+                        "Missing account address on line {}",
+                        line
+                    )));
+                }
+                ImportRow::ListAccount {
+                    list_name: first.to_string(),
+                    acct: acct(member),
+                }
+            }
+        };
+        rows.push(row);
+
+        if rows.len() > MAX_ROWS {
+            return Err(BulkImportError::File(format!(
+                "Imports are limited to {} rows",
+                MAX_ROWS
+            )));
+        }
+    }
+
+    if rows.is_empty() {
+        return Err(BulkImportError::File("The file has no rows".to_string()));
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_following() {
+        let data = b"Account address,Show boosts,Notify on new posts,Languages\n\
+                     alice@example.com,true,false,\n\
+                     @bob,false,true,en\n\n\
+                     carol@example.org\n";
+        let rows = parse_csv(BulkImportType::Following, data).unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                ImportRow::Follow {
+                    acct: "alice@example.com".to_string(),
+                    show_reblogs: true,
+                    notify: false,
+                },
+                ImportRow::Follow {
+                    acct: "bob".to_string(),
+                    show_reblogs: false,
+                    notify: true,
+                },
+                ImportRow::Follow {
+                    acct: "carol@example.org".to_string(),
+                    show_reblogs: true,
+                    notify: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_single_column_files() {
+        let rows = parse_csv(
+            BulkImportType::DomainBlocking,
+            b"Spam.Example\nbad.example\n",
+        )
+        .unwrap();
+        assert_eq!(
+            rows[0],
+            ImportRow::DomainBlock {
+                domain: "spam.example".to_string()
+            }
+        );
+        assert_eq!(rows.len(), 2);
+
+        let rows = parse_csv(BulkImportType::Bookmarks, b"https://example.com/@alice/1\n").unwrap();
+        assert_eq!(
+            rows,
+            vec![ImportRow::Bookmark {
+                uri: "https://example.com/@alice/1".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_muting_and_lists() {
+        let rows = parse_csv(
+            BulkImportType::Muting,
+            b"Account address,Hide notifications\nalice@example.com,false\nbob\n",
+        )
+        .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                ImportRow::Mute {
+                    acct: "alice@example.com".to_string(),
+                    hide_notifications: false,
+                },
+                ImportRow::Mute {
+                    acct: "bob".to_string(),
+                    hide_notifications: true,
+                },
+            ]
+        );
+
+        let rows = parse_csv(BulkImportType::Lists, b"Friends,alice@example.com\n").unwrap();
+        assert_eq!(
+            rows,
+            vec![ImportRow::ListAccount {
+                list_name: "Friends".to_string(),
+                acct: "alice@example.com".to_string(),
+            }]
+        );
+        assert!(parse_csv(BulkImportType::Lists, b"Friends\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_files() {
+        assert!(parse_csv(BulkImportType::Blocking, b"").is_err());
+        assert!(parse_csv(BulkImportType::Following, b"alice,maybe\n").is_err());
+    }
+}
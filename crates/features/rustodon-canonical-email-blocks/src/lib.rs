@@ -1,22 +1,105 @@
 //! Canonical email blocking functionality for Rustodon
 //!
-//! This module provides canonical email blocking capabilities for the Rustodon server.
+//! Blocks email addresses from signing up again, whatever the variant used.
+//! Addresses are first brought to a canonical form: case is folded, and for
+//! known providers that ignore them, dots and `+tags` are stripped from the
+//! local part and domain aliases are merged, so `J.Doe+spam@googlemail.com`
+//! and `jdoe@gmail.com` are the same address. Only the SHA-256 hash of the
+//! canonical form is stored, so blocks don't keep the addresses themselves.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_canonical_email_blocks::{email_hash, CanonicalEmailBlock};
+//!
+//! let hash = email_hash("J.Doe+spam@gmail.com")?;
+//! CanonicalEmailBlock::create(&pool, &hash, Some(account_id)).await?;
+//! assert!(CanonicalEmailBlock::is_blocked(&pool, "jdoe@googlemail.com").await?);
+//! ```
 //!
 //! # Author
 //!
 //! arkSong (arksong2018@gmail.com)
 
+use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{PgConnection, PgPool};
 use thiserror::Error;
-use tracing::{error, info};
+use tracing::{info, trace};
+
+/// Default number of blocks per page
+pub const DEFAULT_LIMIT: i64 = 100;
+/// Maximum number of blocks per page
+pub const MAX_LIMIT: i64 = 200;
+
+/// A mail provider ignoring parts of its addresses
+struct Provider {
+    domain: &'static str,
+    /// Domain the addresses of this one are the same as
+    canonical_domain: &'static str,
+    /// Whether dots in the local part are ignored
+    ignores_dots: bool,
+}
+
+/// Known providers; all of them ignore `+tags`
+const PROVIDERS: &[Provider] = &[
+    Provider {
+        domain: "gmail.com",
+        canonical_domain: "gmail.com",
+        ignores_dots: true,
+    },
+    Provider {
+        domain: "googlemail.com",
+        canonical_domain: "gmail.com",
+        ignores_dots: true,
+    },
+    Provider {
+        domain: "outlook.com",
+        canonical_domain: "outlook.com",
+        ignores_dots: false,
+    },
+    Provider {
+        domain: "hotmail.com",
+        canonical_domain: "hotmail.com",
+        ignores_dots: false,
+    },
+    Provider {
+        domain: "live.com",
+        canonical_domain: "live.com",
+        ignores_dots: false,
+    },
+    Provider {
+        domain: "icloud.com",
+        canonical_domain: "icloud.com",
+        ignores_dots: false,
+    },
+    Provider {
+        domain: "protonmail.com",
+        canonical_domain: "protonmail.com",
+        ignores_dots: false,
+    },
+    Provider {
+        domain: "proton.me",
+        canonical_domain: "proton.me",
+        ignores_dots: false,
+    },
+    Provider {
+        domain: "fastmail.com",
+        canonical_domain: "fastmail.com",
+        ignores_dots: false,
+    },
+];
 
 /// Canonical email block model
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CanonicalEmailBlock {
     pub id: i64,
+    /// Hex SHA-256 hash of the canonical address
     pub canonical_email_hash: String,
-    pub reference_account_id: i64,
-    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Account the address was blocked from, if any
+    pub reference_account_id: Option<i64>,
+    pub created_at: DateTime<Utc>,
 }
 
 /// Error type for canonical email blocking operations
@@ -24,14 +107,234 @@ pub struct CanonicalEmailBlock {
 pub enum CanonicalEmailBlockError {
     #[error("Database error: {0}")]
     Database(#[from] sqlx::Error),
+    #[error("Invalid email: {0}")]
+    InvalidEmail(String),
     #[error("Invalid email hash: {0}")]
     InvalidEmailHash(String),
+    #[error("Email is already blocked")]
+    AlreadyBlocked,
+    #[error("Canonical email block not found: {0}")]
+    NotFound(i64),
+}
+
+/// Internal struct for database rows
+struct CanonicalEmailBlockRow {
+    id: i64,
+    canonical_email_hash: String,
+    reference_account_id: Option<i64>,
+    created_at: NaiveDateTime,
+}
+
+impl From<CanonicalEmailBlockRow> for CanonicalEmailBlock {
+    fn from(row: CanonicalEmailBlockRow) -> Self {
+        Self {
+            id: row.id,
+            canonical_email_hash: row.canonical_email_hash,
+            reference_account_id: row.reference_account_id,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+        }
+    }
 }
 
-/// Initialize canonical email blocking functionality
-pub async fn init_canonical_email_blocks() -> Result<(), CanonicalEmailBlockError> {
-    info!("Initializing canonical email blocking functionality");
-    Ok(())
+/// Brings an email address to its canonical form
+///
+/// # Arguments
+///
+/// * `email` - The address
+///
+/// # Returns
+///
+/// The lowercase address, without the parts its provider ignores
+pub fn canonicalize_email(email: &str) -> Result<String, CanonicalEmailBlockError> {
+    let email = email.trim().to_lowercase();
+    let (local, domain) = email
+        .rsplit_once('@')
+        .filter(|(local, domain)| !local.is_empty() && !domain.is_empty())
+        .ok_or_else(|| CanonicalEmailBlockError::InvalidEmail(email.clone()))?;
+    let domain = domain.trim_end_matches('.');
+
+    match PROVIDERS.iter().find(|provider| provider.domain == domain) {
+        Some(provider) => {
+            let local = local.split('+').next().unwrap_or(local);
+            let local = if provider.ignores_dots {
+                local.replace('.', "")
+            } else {
+                local.to_string()
+            };
+            if local.is_empty() {
+                return Err(CanonicalEmailBlockError::InvalidEmail(email.clone()));
+            }
+            Ok(format!("{}@{}", local, provider.canonical_domain))
+        }
+        None => Ok(format!("{}@{}", local, domain)),
+    }
+}
+
+/// Hashes the canonical form of an email address
+///
+/// # Returns
+///
+/// The hex SHA-256 hash stored by blocks
+pub fn email_hash(email: &str) -> Result<String, CanonicalEmailBlockError> {
+    let canonical = canonicalize_email(email)?;
+    Ok(hex::encode(Sha256::digest(canonical.as_bytes())))
+}
+
+/// Checks a stored hash is a hex SHA-256 hash
+fn validate_hash(hash: &str) -> Result<String, CanonicalEmailBlockError> {
+    let hash = hash.trim().to_lowercase();
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(CanonicalEmailBlockError::InvalidEmailHash(hash));
+    }
+    Ok(hash)
+}
+
+impl CanonicalEmailBlock {
+    /// Blocks the address with a canonical hash
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection of the transaction to run in
+    /// * `canonical_email_hash` - Hash of the address, see [`email_hash`]
+    /// * `reference_account_id` - Account the address was blocked from
+    pub async fn create(
+        conn: &mut PgConnection,
+        canonical_email_hash: &str,
+        reference_account_id: Option<i64>,
+    ) -> Result<Self, CanonicalEmailBlockError> {
+        let hash = validate_hash(canonical_email_hash)?;
+        trace!("Blocking canonical email {}", hash);
+
+        let row = sqlx::query_as!(
+            CanonicalEmailBlockRow,
+            r#"
+            INSERT INTO canonical_email_blocks (canonical_email_hash, reference_account_id)
+            VALUES ($1, $2)
+            ON CONFLICT (canonical_email_hash) DO NOTHING
+            RETURNING id, canonical_email_hash, reference_account_id, created_at
+            "#,
+            hash,
+            reference_account_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(CanonicalEmailBlockError::AlreadyBlocked)?;
+
+        info!("Created canonical email block {}", row.id);
+        Ok(row.into())
+    }
+
+    /// Gets a block by ID
+    pub async fn get(pool: &PgPool, id: i64) -> Result<Option<Self>, CanonicalEmailBlockError> {
+        let row = sqlx::query_as!(
+            CanonicalEmailBlockRow,
+            r#"
+            SELECT id, canonical_email_hash, reference_account_id, created_at
+            FROM canonical_email_blocks
+            WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row.map(CanonicalEmailBlock::from))
+    }
+
+    /// Lists blocks, newest first
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `max_id` - Only return blocks older than this ID
+    /// * `limit` - Maximum number of blocks
+    pub async fn list(
+        pool: &PgPool,
+        max_id: Option<i64>,
+        limit: Option<i64>,
+    ) -> Result<Vec<Self>, CanonicalEmailBlockError> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        let rows = sqlx::query_as!(
+            CanonicalEmailBlockRow,
+            r#"
+            SELECT id, canonical_email_hash, reference_account_id, created_at
+            FROM canonical_email_blocks
+            WHERE ($1::BIGINT IS NULL OR id < $1)
+            ORDER BY id DESC
+            LIMIT $2
+            "#,
+            max_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(CanonicalEmailBlock::from).collect())
+    }
+
+    /// Gets the blocks matching an email address
+    pub async fn matching(
+        pool: &PgPool,
+        email: &str,
+    ) -> Result<Vec<Self>, CanonicalEmailBlockError> {
+        let hash = email_hash(email)?;
+        let rows = sqlx::query_as!(
+            CanonicalEmailBlockRow,
+            r#"
+            SELECT id, canonical_email_hash, reference_account_id, created_at
+            FROM canonical_email_blocks
+            WHERE canonical_email_hash = $1
+            "#,
+            hash
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(CanonicalEmailBlock::from).collect())
+    }
+
+    /// Whether an email address is blocked
+    pub async fn is_blocked(pool: &PgPool, email: &str) -> Result<bool, CanonicalEmailBlockError> {
+        let hash = email_hash(email)?;
+        let blocked = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM canonical_email_blocks WHERE canonical_email_hash = $1
+            ) AS "blocked!"
+            "#,
+            hash
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(blocked)
+    }
+
+    /// Deletes a block
+    ///
+    /// # Returns
+    ///
+    /// The deleted block
+    pub async fn delete(
+        conn: &mut PgConnection,
+        id: i64,
+    ) -> Result<Self, CanonicalEmailBlockError> {
+        let row = sqlx::query_as!(
+            CanonicalEmailBlockRow,
+            r#"
+            DELETE FROM canonical_email_blocks
+            WHERE id = $1
+            RETURNING id, canonical_email_hash, reference_account_id, created_at
+            "#,
+            id
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(CanonicalEmailBlockError::NotFound(id))?;
+
+        info!("Deleted canonical email block {}", id);
+        Ok(row.into())
+    }
 }
 
 #[cfg(test)]
@@ -39,13 +342,39 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_canonical_email_block() {
-        let block = CanonicalEmailBlock {
-            id: 1,
-            canonical_email_hash: "hash123".to_string(),
-            reference_account_id: 1,
-            created_at: chrono::Utc::now(),
-        };
-        assert_eq!(block.canonical_email_hash, "hash123");
+    fn test_canonicalize_email() {
+        assert_eq!(
+            canonicalize_email(" J.Doe+Spam@GoogleMail.com ").unwrap(),
+            "jdoe@gmail.com"
+        );
+        assert_eq!(
+            canonicalize_email("j.doe+news@outlook.com").unwrap(),
+            "j.doe@outlook.com"
+        );
+        // Unknown providers may treat dots and tags as distinct mailboxes
+        assert_eq!(
+            canonicalize_email("J.Doe+x@Example.org.").unwrap(),
+            "j.doe+x@example.org"
+        );
+
+        assert!(canonicalize_email("no-at-sign").is_err());
+        assert!(canonicalize_email("@gmail.com").is_err());
+        assert!(canonicalize_email("+tag@gmail.com").is_err());
+    }
+
+    #[test]
+    fn test_email_hash() {
+        let hash = email_hash("jdoe@gmail.com").unwrap();
+        assert_eq!(hash.len(), 64);
+        assert_eq!(email_hash("J.D.O.E+1@googlemail.com").unwrap(), hash);
+        assert_ne!(email_hash("jdoe@example.com").unwrap(), hash);
+    }
+
+    #[test]
+    fn test_validate_hash() {
+        let hash = email_hash("jdoe@gmail.com").unwrap();
+        assert_eq!(validate_hash(&hash.to_uppercase()).unwrap(), hash);
+        assert!(validate_hash("hash123").is_err());
+        assert!(validate_hash(&"g".repeat(64)).is_err());
     }
 }
@@ -1,14 +1,86 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
+//! Conversations module for Rustodon
+//!
+//! Every status belongs to a conversation: replies join the conversation
+//! of the status they reply to, other statuses start a new one. Direct
+//! message threads are tracked per participant on top of conversations by
+//! `rustodon-account-conversations`.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_conversations::Conversation;
+//!
+//! let conversation_id = Conversation::for_status(&mut tx, new_status.in_reply_to_id).await?;
+//! ```
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, Transaction};
+use thiserror::Error;
+use tracing::{debug, trace};
+
+/// Custom error type for conversations module
+#[derive(Error, Debug)]
+pub enum ConversationsError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// A thread of statuses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conversation {
+    /// Unique identifier for the conversation
+    pub id: i64,
+    /// When the conversation was started
+    pub created_at: DateTime<Utc>,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl Conversation {
+    /// Gets the conversation a new status belongs to
+    ///
+    /// Replies join the conversation of the replied-to status. Other
+    /// statuses, and replies to statuses without a conversation, start a
+    /// new one.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx` - Transaction the status is being created in
+    /// * `in_reply_to_id` - ID of the status being replied to
+    ///
+    /// # Returns
+    ///
+    /// ID of the conversation
+    pub async fn for_status(
+        tx: &mut Transaction<'_, Postgres>,
+        in_reply_to_id: Option<i64>,
+    ) -> Result<i64, ConversationsError> {
+        if let Some(parent_id) = in_reply_to_id {
+            let existing = sqlx::query_scalar!(
+                "SELECT conversation_id FROM statuses WHERE id = $1",
+                parent_id
+            )
+            .fetch_optional(&mut **tx)
+            .await?
+            .flatten();
+            if let Some(conversation_id) = existing {
+                trace!(
+                    "Reply to status {} joins conversation {}",
+                    parent_id,
+                    conversation_id
+                );
+                return Ok(conversation_id);
+            }
+        }
 
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+        let conversation_id =
+            sqlx::query_scalar!("INSERT INTO conversations DEFAULT VALUES RETURNING id")
+                .fetch_one(&mut **tx)
+                .await?;
+        debug!("Started conversation {}", conversation_id);
+        Ok(conversation_id)
     }
 }
@@ -1,22 +1,52 @@
 //! Email domain blocking functionality for Rustodon
 //!
-//! This module provides email domain blocking capabilities for the Rustodon server.
+//! Rejects sign-ups from blocked email domains. A block on a domain also
+//! covers its subdomains, so blocking `example.com` blocks
+//! `mail.example.com`. Blocks can be created together with child blocks for
+//! the mail servers the domain resolves to, and sign-up addresses are also
+//! matched by their own mail servers, which catches the many domains a
+//! disposable email provider receives mail for.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_email_domain_blocks::{DnsMxResolver, EmailDomainBlock};
+//!
+//! let resolver = DnsMxResolver::from_system_conf()?;
+//! EmailDomainBlock::create(&pool, "spam.example", None, Some(&resolver)).await?;
+//! assert!(EmailDomainBlock::is_blocked(&pool, "a@mail.spam.example", &resolver).await?);
+//! ```
 //!
 //! # Author
 //!
 //! arkSong (arksong2018@gmail.com)
 
+mod resolver;
+
+pub use resolver::{DnsMxResolver, MxResolver, StaticMxResolver};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
 use thiserror::Error;
-use tracing::{error, info};
+use tracing::{info, trace, warn};
+
+/// Default number of blocks per page
+pub const DEFAULT_LIMIT: i64 = 100;
+/// Maximum number of blocks per page
+pub const MAX_LIMIT: i64 = 200;
+/// Maximum length of a domain
+pub const MAX_DOMAIN_LENGTH: usize = 253;
 
 /// Email domain block model
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmailDomainBlock {
     pub id: i64,
     pub domain: String,
+    /// Block this one was created for, when it blocks a mail server
+    pub parent_id: Option<i64>,
     pub reason: Option<String>,
-    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub created_at: DateTime<Utc>,
 }
 
 /// Error type for email domain blocking operations
@@ -26,12 +56,293 @@ pub enum EmailDomainBlockError {
     Database(#[from] sqlx::Error),
     #[error("Invalid domain: {0}")]
     InvalidDomain(String),
+    #[error("Domain is already blocked: {0}")]
+    AlreadyBlocked(String),
+    #[error("Email domain block not found: {0}")]
+    NotFound(i64),
+    #[error("Resolver error: {0}")]
+    Resolver(String),
+}
+
+/// Internal struct for database rows
+struct EmailDomainBlockRow {
+    id: i64,
+    domain: String,
+    parent_id: Option<i64>,
+    reason: Option<String>,
+    created_at: NaiveDateTime,
+}
+
+impl From<EmailDomainBlockRow> for EmailDomainBlock {
+    fn from(row: EmailDomainBlockRow) -> Self {
+        Self {
+            id: row.id,
+            domain: row.domain,
+            parent_id: row.parent_id,
+            reason: row.reason,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+        }
+    }
+}
+
+/// Brings a domain to the form blocks are stored in
+///
+/// # Arguments
+///
+/// * `domain` - The domain, in any case and with an optional trailing dot
+pub fn normalize_domain(domain: &str) -> Result<String, EmailDomainBlockError> {
+    let normalized = domain.trim().trim_end_matches('.').to_lowercase();
+    let valid = !normalized.is_empty()
+        && normalized.len() <= MAX_DOMAIN_LENGTH
+        && normalized.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+        });
+
+    if valid {
+        Ok(normalized)
+    } else {
+        Err(EmailDomainBlockError::InvalidDomain(domain.to_string()))
+    }
+}
+
+/// Gets the domain of an email address
+pub fn email_domain(email: &str) -> Result<String, EmailDomainBlockError> {
+    let (_, domain) = email
+        .trim()
+        .rsplit_once('@')
+        .ok_or_else(|| EmailDomainBlockError::InvalidDomain(email.to_string()))?;
+    normalize_domain(domain)
+}
+
+/// A domain and all of its parents, such as `a.b.example` then `b.example`
+/// and `example`
+pub fn domain_candidates(domain: &str) -> Vec<String> {
+    let mut candidates = vec![domain.to_string()];
+    let mut rest = domain;
+    while let Some((_, parent)) = rest.split_once('.') {
+        candidates.push(parent.to_string());
+        rest = parent;
+    }
+    candidates
 }
 
-/// Initialize email domain blocking functionality
-pub async fn init_email_domain_blocks() -> Result<(), EmailDomainBlockError> {
-    info!("Initializing email domain blocking functionality");
-    Ok(())
+impl EmailDomainBlock {
+    /// Blocks a domain and its subdomains
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection of the transaction to run in
+    /// * `domain` - The domain to block
+    /// * `reason` - Why the domain is blocked, for staff
+    /// * `resolver` - If given, the mail servers of the domain are blocked
+    ///   too, as children of the block
+    ///
+    /// # Returns
+    ///
+    /// The block of the domain
+    pub async fn create(
+        conn: &mut PgConnection,
+        domain: &str,
+        reason: Option<&str>,
+        resolver: Option<&dyn MxResolver>,
+    ) -> Result<Self, EmailDomainBlockError> {
+        let domain = normalize_domain(domain)?;
+        let reason = reason.map(str::trim).filter(|reason| !reason.is_empty());
+        trace!("Blocking email domain {}", domain);
+
+        let hosts = match resolver {
+            Some(resolver) => resolver.resolve_mx(&domain).await?,
+            None => Vec::new(),
+        };
+
+        let row = sqlx::query_as!(
+            EmailDomainBlockRow,
+            r#"
+            INSERT INTO email_domain_blocks (domain, reason)
+            VALUES ($1, $2)
+            ON CONFLICT (domain) DO NOTHING
+            RETURNING id, domain, parent_id, reason, created_at
+            "#,
+            domain,
+            reason
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| EmailDomainBlockError::AlreadyBlocked(domain.clone()))?;
+
+        let hosts: Vec<String> = hosts
+            .iter()
+            .filter_map(|host| normalize_domain(host).ok())
+            .filter(|host| *host != domain)
+            .collect();
+        if !hosts.is_empty() {
+            sqlx::query!(
+                r#"
+                INSERT INTO email_domain_blocks (domain, parent_id, reason)
+                SELECT host, $2, $3 FROM UNNEST($1::TEXT[]) AS host
+                ON CONFLICT (domain) DO NOTHING
+                "#,
+                &hosts,
+                row.id,
+                reason
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        info!(
+            "Created email domain block {} for {} with {} mail servers",
+            row.id,
+            domain,
+            hosts.len()
+        );
+        Ok(row.into())
+    }
+
+    /// Gets a block by ID
+    pub async fn get(pool: &PgPool, id: i64) -> Result<Option<Self>, EmailDomainBlockError> {
+        let row = sqlx::query_as!(
+            EmailDomainBlockRow,
+            r#"
+            SELECT id, domain, parent_id, reason, created_at
+            FROM email_domain_blocks
+            WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row.map(EmailDomainBlock::from))
+    }
+
+    /// Lists blocks, newest first
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `max_id` - Only return blocks older than this ID
+    /// * `limit` - Maximum number of blocks
+    pub async fn list(
+        pool: &PgPool,
+        max_id: Option<i64>,
+        limit: Option<i64>,
+    ) -> Result<Vec<Self>, EmailDomainBlockError> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        let rows = sqlx::query_as!(
+            EmailDomainBlockRow,
+            r#"
+            SELECT id, domain, parent_id, reason, created_at
+            FROM email_domain_blocks
+            WHERE ($1::BIGINT IS NULL OR id < $1)
+            ORDER BY id DESC
+            LIMIT $2
+            "#,
+            max_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(EmailDomainBlock::from).collect())
+    }
+
+    /// Gets the blocks matching an email address, by its domain or one of
+    /// its parents, or else by one of its mail servers
+    ///
+    /// Resolver failures are logged and only skip the mail server check, so
+    /// a DNS outage doesn't stop sign-ups.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `email` - The address signing up
+    /// * `resolver` - Looks up the mail servers of the address
+    pub async fn matching(
+        pool: &PgPool,
+        email: &str,
+        resolver: &dyn MxResolver,
+    ) -> Result<Vec<Self>, EmailDomainBlockError> {
+        let domain = email_domain(email)?;
+        let blocks = Self::matching_domains(pool, &domain_candidates(&domain)).await?;
+        if !blocks.is_empty() {
+            return Ok(blocks);
+        }
+
+        let hosts = match resolver.resolve_mx(&domain).await {
+            Ok(hosts) => hosts,
+            Err(e) => {
+                warn!("Failed to resolve mail servers of {}: {}", domain, e);
+                return Ok(Vec::new());
+            }
+        };
+        let candidates: Vec<String> = hosts
+            .iter()
+            .filter_map(|host| normalize_domain(host).ok())
+            .flat_map(|host| domain_candidates(&host))
+            .collect();
+        if candidates.is_empty() {
+            return Ok(Vec::new());
+        }
+        Self::matching_domains(pool, &candidates).await
+    }
+
+    /// Whether an email address is blocked, see [`EmailDomainBlock::matching`]
+    pub async fn is_blocked(
+        pool: &PgPool,
+        email: &str,
+        resolver: &dyn MxResolver,
+    ) -> Result<bool, EmailDomainBlockError> {
+        Ok(!Self::matching(pool, email, resolver).await?.is_empty())
+    }
+
+    /// Gets the blocks of any of some domains
+    async fn matching_domains(
+        pool: &PgPool,
+        domains: &[String],
+    ) -> Result<Vec<Self>, EmailDomainBlockError> {
+        let rows = sqlx::query_as!(
+            EmailDomainBlockRow,
+            r#"
+            SELECT id, domain, parent_id, reason, created_at
+            FROM email_domain_blocks
+            WHERE domain = ANY($1)
+            ORDER BY id
+            "#,
+            domains
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(EmailDomainBlock::from).collect())
+    }
+
+    /// Deletes a block along with its mail server blocks
+    ///
+    /// # Returns
+    ///
+    /// The deleted block
+    pub async fn delete(conn: &mut PgConnection, id: i64) -> Result<Self, EmailDomainBlockError> {
+        let row = sqlx::query_as!(
+            EmailDomainBlockRow,
+            r#"
+            DELETE FROM email_domain_blocks
+            WHERE id = $1
+            RETURNING id, domain, parent_id, reason, created_at
+            "#,
+            id
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(EmailDomainBlockError::NotFound(id))?;
+
+        info!("Deleted email domain block {}", id);
+        Ok(row.into())
+    }
 }
 
 #[cfg(test)]
@@ -39,13 +350,38 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_email_domain_block() {
-        let block = EmailDomainBlock {
-            id: 1,
-            domain: "example.com".to_string(),
-            reason: Some("Spam".to_string()),
-            created_at: chrono::Utc::now(),
-        };
-        assert_eq!(block.domain, "example.com");
+    fn test_normalize_domain() {
+        assert_eq!(
+            normalize_domain(" Mail.Example.COM. ").unwrap(),
+            "mail.example.com"
+        );
+        assert_eq!(
+            normalize_domain("bücher.example").unwrap(),
+            "bücher.example"
+        );
+
+        assert!(normalize_domain("").is_err());
+        assert!(normalize_domain("example..com").is_err());
+        assert!(normalize_domain("-example.com").is_err());
+        assert!(normalize_domain("exa mple.com").is_err());
+        assert!(normalize_domain(&"a".repeat(64)).is_err());
+    }
+
+    #[test]
+    fn test_email_domain() {
+        assert_eq!(
+            email_domain("jdoe@Mail.Example.com").unwrap(),
+            "mail.example.com"
+        );
+        assert!(email_domain("jdoe").is_err());
+    }
+
+    #[test]
+    fn test_domain_candidates() {
+        assert_eq!(
+            domain_candidates("a.b.example"),
+            vec!["a.b.example", "b.example", "example"]
+        );
+        assert_eq!(domain_candidates("localhost"), vec!["localhost"]);
     }
 }
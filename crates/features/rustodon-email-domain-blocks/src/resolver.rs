@@ -0,0 +1,115 @@
+//! MX resolvers
+//!
+//! Email domain blocks also match the mail servers of a domain, so a block
+//! on a disposable email provider catches the other domains it receives
+//! mail for. Looking up MX records goes through [`MxResolver`] so that tests
+//! and offline setups can use [`StaticMxResolver`] instead of DNS.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::EmailDomainBlockError;
+use async_trait::async_trait;
+use hickory_resolver::error::ResolveErrorKind;
+use hickory_resolver::TokioAsyncResolver;
+use std::collections::HashMap;
+use tracing::trace;
+
+/// Looks up the mail servers of a domain
+#[async_trait]
+pub trait MxResolver: Send + Sync {
+    /// Gets the hosts of the MX records of a domain
+    ///
+    /// # Arguments
+    ///
+    /// * `domain` - The email domain
+    ///
+    /// # Returns
+    ///
+    /// Lowercase host names without the trailing dot, empty if the domain
+    /// has no MX records
+    async fn resolve_mx(&self, domain: &str) -> Result<Vec<String>, EmailDomainBlockError>;
+}
+
+/// Resolves MX records through the DNS servers of the system
+pub struct DnsMxResolver {
+    resolver: TokioAsyncResolver,
+}
+
+impl DnsMxResolver {
+    /// Creates a resolver from the system configuration, such as
+    /// `/etc/resolv.conf`
+    pub fn from_system_conf() -> Result<Self, EmailDomainBlockError> {
+        let resolver = TokioAsyncResolver::tokio_from_system_conf()
+            .map_err(|e| EmailDomainBlockError::Resolver(e.to_string()))?;
+        Ok(Self { resolver })
+    }
+}
+
+#[async_trait]
+impl MxResolver for DnsMxResolver {
+    async fn resolve_mx(&self, domain: &str) -> Result<Vec<String>, EmailDomainBlockError> {
+        trace!("Resolving MX records of {}", domain);
+
+        // A trailing dot keeps search domains from being appended
+        match self.resolver.mx_lookup(format!("{}.", domain)).await {
+            Ok(lookup) => Ok(lookup
+                .iter()
+                .map(|mx| mx.exchange().to_utf8().trim_end_matches('.').to_lowercase())
+                .filter(|host| !host.is_empty())
+                .collect()),
+            Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => Ok(Vec::new()),
+            Err(e) => Err(EmailDomainBlockError::Resolver(e.to_string())),
+        }
+    }
+}
+
+/// Resolves MX records from a fixed table, for tests and offline setups
+#[derive(Debug, Clone, Default)]
+pub struct StaticMxResolver {
+    records: HashMap<String, Vec<String>>,
+}
+
+impl StaticMxResolver {
+    /// Creates a resolver without any records
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the MX hosts of a domain
+    ///
+    /// # Arguments
+    ///
+    /// * `domain` - The email domain
+    /// * `hosts` - Its mail servers
+    pub fn with_records(mut self, domain: &str, hosts: &[&str]) -> Self {
+        self.records.insert(
+            domain.to_lowercase(),
+            hosts.iter().map(|host| host.to_lowercase()).collect(),
+        );
+        self
+    }
+}
+
+#[async_trait]
+impl MxResolver for StaticMxResolver {
+    async fn resolve_mx(&self, domain: &str) -> Result<Vec<String>, EmailDomainBlockError> {
+        Ok(self.records.get(domain).cloned().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_static_mx_resolver() {
+        let resolver = StaticMxResolver::new().with_records("Example.com", &["MX1.example.net"]);
+        assert_eq!(
+            resolver.resolve_mx("example.com").await.unwrap(),
+            vec!["mx1.example.net".to_string()]
+        );
+        assert!(resolver.resolve_mx("example.org").await.unwrap().is_empty());
+    }
+}
@@ -4,21 +4,36 @@
 //! It handles creating, managing, and applying content filters
 //! with proper database operations and validation.
 //!
+//! A filter owns keywords (matched against status text) and statuses
+//! (matched by ID). [`FilterMatcher`] applies a user's active filters to
+//! statuses shown in a given context: matches are reported as
+//! [`FilterResult`]s for `warn` filters, while `hide` filters drop the
+//! status entirely.
+//!
 //! # Examples
 //!
-//! ```rust
-//! use rustodon_filters::{Filter, FilterKeyword};
+//! ```rust,ignore
+//! use rustodon_filters::{CreateFilterRequest, Filter, FilterKeywordParams, FilterMatcher};
+//!
+//! let filter = Filter::create(&pool, user_id, CreateFilterRequest {
+//!     title: "Spoilers".to_string(),
+//!     context: vec!["home".to_string()],
+//!     expires_at: None,
+//!     filter_action: "warn".to_string(),
+//!     keywords: vec![FilterKeywordParams { keyword: "finale".to_string(), whole_word: true }],
+//! }).await?;
 //!
-//! let filter = Filter::create(&pool, user_id, "My Filter", "home").await?;
+//! let matcher = FilterMatcher::new(Filter::get_by_account(&pool, user_id).await?, "home");
+//! let outcome = matcher.apply(status_id, "<p>That finale!</p>");
 //! ```
 //!
 //! # Dependencies
 //!
 //! - `rustodon_core`: Core types and traits
-//! - `rustodon_db`: Database operations
 //! - `sqlx`: Database queries
 //! - `serde`: Serialization
 //! - `chrono`: DateTime handling
+//! - `regex`: Keyword matching
 //! - `thiserror`: Error handling
 //! - `tracing`: Logging
 //!
@@ -26,11 +41,21 @@
 //!
 //! arkSong (arksong2018@gmail.com)
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use regex::{Regex, RegexBuilder};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 use thiserror::Error;
-use tracing::{debug, error, info, trace};
+use tracing::{debug, info, trace, warn};
+
+/// Contexts a filter can apply to
+pub const FILTER_CONTEXTS: [&str; 5] = ["home", "notifications", "public", "thread", "account"];
+
+/// Filter action that shows a warning over matching statuses
+pub const FILTER_ACTION_WARN: &str = "warn";
+
+/// Filter action that removes matching statuses
+pub const FILTER_ACTION_HIDE: &str = "hide";
 
 /// Custom error type for filters module
 #[derive(Error, Debug)]
@@ -41,6 +66,8 @@ pub enum FiltersError {
     FilterNotFound(i64),
     #[error("Filter keyword not found: {0}")]
     FilterKeywordNotFound(i64),
+    #[error("Filter status not found: {0}")]
+    FilterStatusNotFound(i64),
     #[error("User not found: {0}")]
     UserNotFound(i64),
     #[error("Validation error: {0}")]
@@ -58,11 +85,11 @@ pub struct Filter {
     pub account_id: i64,
     /// Title of the filter
     pub title: String,
-    /// Context where the filter applies (home, notifications, public, thread)
+    /// Comma-separated contexts where the filter applies (home, notifications, public, thread, account)
     pub context: String,
     /// When the filter expires (None if no expiration)
     pub expires_at: Option<DateTime<Utc>>,
-    /// Whether to filter from home timeline
+    /// What happens to matching statuses (warn, hide)
     pub filter_action: String,
     /// When the filter was created
     pub created_at: DateTime<Utc>,
@@ -70,6 +97,8 @@ pub struct Filter {
     pub updated_at: DateTime<Utc>,
     /// Keywords associated with this filter
     pub keywords: Vec<FilterKeyword>,
+    /// Individual statuses associated with this filter
+    pub statuses: Vec<FilterStatus>,
 }
 
 /// Filter keyword data structure
@@ -87,6 +116,29 @@ pub struct FilterKeyword {
     pub created_at: DateTime<Utc>,
 }
 
+/// Filter status data structure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilterStatus {
+    /// Unique identifier for the filter status
+    pub id: i64,
+    /// ID of the filter this status belongs to
+    pub filter_id: i64,
+    /// ID of the filtered status
+    pub status_id: i64,
+    /// When the status was added to the filter
+    pub created_at: DateTime<Utc>,
+}
+
+/// Keyword attributes used when creating or replacing keywords
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilterKeywordParams {
+    /// The keyword to filter
+    pub keyword: String,
+    /// Whether to match whole words only
+    #[serde(default)]
+    pub whole_word: bool,
+}
+
 /// Create filter request
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateFilterRequest {
@@ -99,7 +151,7 @@ pub struct CreateFilterRequest {
     /// Filter action (warn, hide)
     pub filter_action: String,
     /// Keywords to filter
-    pub keywords: Vec<String>,
+    pub keywords: Vec<FilterKeywordParams>,
 }
 
 /// Update filter request
@@ -113,8 +165,64 @@ pub struct UpdateFilterRequest {
     pub expires_at: Option<DateTime<Utc>>,
     /// Filter action (warn, hide)
     pub filter_action: Option<String>,
-    /// Keywords to filter
-    pub keywords: Option<Vec<String>>,
+    /// Keywords replacing the existing ones
+    pub keywords: Option<Vec<FilterKeywordParams>>,
+}
+
+fn validate_title(title: &str) -> Result<(), FiltersError> {
+    if title.trim().is_empty() {
+        return Err(FiltersError::Validation(
+            "Filter title cannot be empty".to_string(),
+        ));
+    }
+    if title.len() > 100 {
+        return Err(FiltersError::Validation(
+            "Filter title cannot exceed 100 characters".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn validate_context(context: &[String]) -> Result<(), FiltersError> {
+    if context.is_empty() {
+        return Err(FiltersError::Validation(
+            "Filter must have at least one context".to_string(),
+        ));
+    }
+    if let Some(invalid) = context
+        .iter()
+        .find(|c| !FILTER_CONTEXTS.contains(&c.as_str()))
+    {
+        return Err(FiltersError::Validation(format!(
+            "Invalid filter context: {}",
+            invalid
+        )));
+    }
+    Ok(())
+}
+
+fn validate_filter_action(filter_action: &str) -> Result<(), FiltersError> {
+    if filter_action != FILTER_ACTION_WARN && filter_action != FILTER_ACTION_HIDE {
+        return Err(FiltersError::Validation(format!(
+            "Invalid filter action: {}",
+            filter_action
+        )));
+    }
+    Ok(())
+}
+
+fn validate_keyword(keyword: &str) -> Result<(), FiltersError> {
+    if keyword.trim().is_empty() {
+        return Err(FiltersError::Validation(
+            "Filter keyword cannot be empty".to_string(),
+        ));
+    }
+    if keyword.len() > 255 {
+        return Err(FiltersError::Validation(
+            "Filter keyword cannot exceed 255 characters".to_string(),
+        ));
+    }
+    Ok(())
 }
 
 impl Filter {
@@ -141,25 +249,11 @@ impl Filter {
         );
 
         // Validate request
-        if request.title.trim().is_empty() {
-            return Err(FiltersError::Validation(
-                "Filter title cannot be empty".to_string(),
-            ));
-        }
-        if request.title.len() > 100 {
-            return Err(FiltersError::Validation(
-                "Filter title cannot exceed 100 characters".to_string(),
-            ));
-        }
-        if request.context.is_empty() {
-            return Err(FiltersError::Validation(
-                "Filter must have at least one context".to_string(),
-            ));
-        }
-        if request.keywords.is_empty() {
-            return Err(FiltersError::Validation(
-                "Filter must have at least one keyword".to_string(),
-            ));
+        validate_title(&request.title)?;
+        validate_context(&request.context)?;
+        validate_filter_action(&request.filter_action)?;
+        for params in &request.keywords {
+            validate_keyword(&params.keyword)?;
         }
 
         // Check if user exists
@@ -183,7 +277,8 @@ impl Filter {
         let mut tx = pool.begin().await?;
 
         // Insert filter
-        let filter_row = sqlx::query!(
+        let filter_row = sqlx::query_as!(
+            FilterRow,
             r#"
             INSERT INTO filters (account_id, title, context, expires_at, filter_action)
             VALUES ($1, $2, $3, $4, $5)
@@ -198,48 +293,30 @@ impl Filter {
         .fetch_one(&mut *tx)
         .await?;
 
-        let filter_id = filter_row.id;
-
         // Insert filter keywords
         let mut keywords = Vec::new();
-        for keyword in request.keywords {
-            let keyword_row = sqlx::query!(
+        for params in request.keywords {
+            let keyword_row = sqlx::query_as!(
+                FilterKeywordRow,
                 r#"
                 INSERT INTO filter_keywords (filter_id, keyword, whole_word)
-                VALUES ($1, $2, false)
+                VALUES ($1, $2, $3)
                 RETURNING id, filter_id, keyword, whole_word, created_at
                 "#,
-                filter_id,
-                keyword
+                filter_row.id,
+                params.keyword,
+                params.whole_word
             )
             .fetch_one(&mut *tx)
             .await?;
 
-            keywords.push(FilterKeyword {
-                id: keyword_row.id,
-                filter_id: keyword_row.filter_id,
-                keyword: keyword_row.keyword,
-                whole_word: keyword_row.whole_word.unwrap_or(false),
-                created_at: DateTime::from_naive_utc_and_offset(keyword_row.created_at, Utc),
-            });
+            keywords.push(FilterKeyword::from(keyword_row));
         }
 
         // Commit transaction
         tx.commit().await?;
 
-        let filter = Filter {
-            id: filter_row.id,
-            account_id: filter_row.account_id,
-            title: filter_row.title.unwrap_or_default(),
-            context: filter_row.context.join(","),
-            expires_at: filter_row
-                .expires_at
-                .map(|dt| DateTime::from_naive_utc_and_offset(dt, Utc)),
-            filter_action: filter_row.filter_action,
-            created_at: DateTime::from_naive_utc_and_offset(filter_row.created_at, Utc),
-            updated_at: DateTime::from_naive_utc_and_offset(filter_row.updated_at, Utc),
-            keywords,
-        };
+        let filter = filter_row.into_filter(keywords, Vec::new());
 
         info!(
             "Created filter with id: {} for account {} with title: {}",
@@ -262,7 +339,8 @@ impl Filter {
         trace!("Getting filter by id: {}", filter_id);
 
         // Get filter
-        let filter_row = sqlx::query!(
+        let filter_row = sqlx::query_as!(
+            FilterRow,
             r#"
             SELECT id, account_id, title, context, expires_at, filter_action, created_at, updated_at
             FROM filters
@@ -274,48 +352,37 @@ impl Filter {
         .await?
         .ok_or(FiltersError::FilterNotFound(filter_id))?;
 
-        // Get filter keywords
-        let keyword_rows = sqlx::query!(
-            r#"
-            SELECT id, filter_id, keyword, whole_word, created_at
-            FROM filter_keywords
-            WHERE filter_id = $1
-            ORDER BY id
-            "#,
-            filter_id
-        )
-        .fetch_all(pool)
-        .await?;
-
-        let keywords = keyword_rows
-            .into_iter()
-            .map(|row| FilterKeyword {
-                id: row.id,
-                filter_id: row.filter_id,
-                keyword: row.keyword,
-                whole_word: row.whole_word.unwrap_or(false),
-                created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
-            })
-            .collect();
-
-        let filter = Filter {
-            id: filter_row.id,
-            account_id: filter_row.account_id,
-            title: filter_row.title.unwrap_or_default(),
-            context: filter_row.context.join(","),
-            expires_at: filter_row
-                .expires_at
-                .map(|dt| DateTime::from_naive_utc_and_offset(dt, Utc)),
-            filter_action: filter_row.filter_action,
-            created_at: DateTime::from_naive_utc_and_offset(filter_row.created_at, Utc),
-            updated_at: DateTime::from_naive_utc_and_offset(filter_row.updated_at, Utc),
-            keywords,
-        };
+        let keywords = FilterKeyword::get_by_filter(pool, filter_id).await?;
+        let statuses = FilterStatus::get_by_filter(pool, filter_id).await?;
+        let filter = filter_row.into_filter(keywords, statuses);
 
         debug!("Retrieved filter with id: {}", filter.id);
         Ok(filter)
     }
 
+    /// Gets a filter by ID, making sure it belongs to the account
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `filter_id` - ID of the filter to retrieve
+    /// * `account_id` - ID of the account that must own the filter
+    ///
+    /// # Returns
+    ///
+    /// Result containing the filter, or `FilterNotFound` if another account owns it
+    pub async fn get_owned(
+        pool: &PgPool,
+        filter_id: i64,
+        account_id: i64,
+    ) -> Result<Self, FiltersError> {
+        let filter = Self::get_by_id(pool, filter_id).await?;
+        if filter.account_id != account_id {
+            return Err(FiltersError::FilterNotFound(filter_id));
+        }
+        Ok(filter)
+    }
+
     /// Gets all filters for an account
     ///
     /// # Arguments
@@ -330,7 +397,8 @@ impl Filter {
         trace!("Getting filters for account: {}", account_id);
 
         // Get filters
-        let filter_rows = sqlx::query!(
+        let filter_rows = sqlx::query_as!(
+            FilterRow,
             r#"
             SELECT id, account_id, title, context, expires_at, filter_action, created_at, updated_at
             FROM filters
@@ -344,45 +412,9 @@ impl Filter {
 
         let mut filters = Vec::new();
         for filter_row in filter_rows {
-            // Get filter keywords
-            let keyword_rows = sqlx::query!(
-                r#"
-                SELECT id, filter_id, keyword, whole_word, created_at
-                FROM filter_keywords
-                WHERE filter_id = $1
-                ORDER BY id
-                "#,
-                filter_row.id
-            )
-            .fetch_all(pool)
-            .await?;
-
-            let keywords = keyword_rows
-                .into_iter()
-                .map(|row| FilterKeyword {
-                    id: row.id,
-                    filter_id: row.filter_id,
-                    keyword: row.keyword,
-                    whole_word: row.whole_word.unwrap_or(false),
-                    created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
-                })
-                .collect();
-
-            let filter = Filter {
-                id: filter_row.id,
-                account_id: filter_row.account_id,
-                title: filter_row.title.unwrap_or_default(),
-                context: filter_row.context.join(","),
-                expires_at: filter_row
-                    .expires_at
-                    .map(|dt| DateTime::from_naive_utc_and_offset(dt, Utc)),
-                filter_action: filter_row.filter_action,
-                created_at: DateTime::from_naive_utc_and_offset(filter_row.created_at, Utc),
-                updated_at: DateTime::from_naive_utc_and_offset(filter_row.updated_at, Utc),
-                keywords,
-            };
-
-            filters.push(filter);
+            let keywords = FilterKeyword::get_by_filter(pool, filter_row.id).await?;
+            let statuses = FilterStatus::get_by_filter(pool, filter_row.id).await?;
+            filters.push(filter_row.into_filter(keywords, statuses));
         }
 
         debug!(
@@ -413,26 +445,18 @@ impl Filter {
     ) -> Result<Self, FiltersError> {
         trace!("Updating filter {} for account {}", filter_id, account_id);
 
-        // Validate title if provided
         if let Some(ref title) = request.title {
-            if title.trim().is_empty() {
-                return Err(FiltersError::Validation(
-                    "Filter title cannot be empty".to_string(),
-                ));
-            }
-            if title.len() > 100 {
-                return Err(FiltersError::Validation(
-                    "Filter title cannot exceed 100 characters".to_string(),
-                ));
-            }
+            validate_title(title)?;
         }
-
-        // Validate context if provided
         if let Some(ref context) = request.context {
-            if context.is_empty() {
-                return Err(FiltersError::Validation(
-                    "Filter must have at least one context".to_string(),
-                ));
+            validate_context(context)?;
+        }
+        if let Some(ref filter_action) = request.filter_action {
+            validate_filter_action(filter_action)?;
+        }
+        if let Some(ref keywords) = request.keywords {
+            for params in keywords {
+                validate_keyword(&params.keyword)?;
             }
         }
 
@@ -440,7 +464,8 @@ impl Filter {
         let mut tx = pool.begin().await?;
 
         // Update filter
-        let filter_row = sqlx::query!(
+        let filter_row = sqlx::query_as!(
+            FilterRow,
             r#"
             UPDATE filters
             SET title = COALESCE($3, title),
@@ -476,60 +501,27 @@ impl Filter {
             .await?;
 
             // Insert new keywords
-            for keyword in keywords {
+            for params in keywords {
                 sqlx::query!(
                     r#"
                     INSERT INTO filter_keywords (filter_id, keyword, whole_word)
-                    VALUES ($1, $2, false)
+                    VALUES ($1, $2, $3)
                     "#,
                     filter_id,
-                    keyword
+                    params.keyword,
+                    params.whole_word
                 )
                 .execute(&mut *tx)
                 .await?;
             }
         }
 
-        // Get updated keywords
-        let keyword_rows = sqlx::query!(
-            r#"
-            SELECT id, filter_id, keyword, whole_word, created_at
-            FROM filter_keywords
-            WHERE filter_id = $1
-            ORDER BY id
-            "#,
-            filter_id
-        )
-        .fetch_all(&mut *tx)
-        .await?;
-
-        let keywords = keyword_rows
-            .into_iter()
-            .map(|row| FilterKeyword {
-                id: row.id,
-                filter_id: row.filter_id,
-                keyword: row.keyword,
-                whole_word: row.whole_word.unwrap_or(false),
-                created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
-            })
-            .collect();
-
         // Commit transaction
         tx.commit().await?;
 
-        let filter = Filter {
-            id: filter_row.id,
-            account_id: filter_row.account_id,
-            title: filter_row.title.unwrap_or_default(),
-            context: filter_row.context.join(","),
-            expires_at: filter_row
-                .expires_at
-                .map(|dt| DateTime::from_naive_utc_and_offset(dt, Utc)),
-            filter_action: filter_row.filter_action,
-            created_at: DateTime::from_naive_utc_and_offset(filter_row.created_at, Utc),
-            updated_at: DateTime::from_naive_utc_and_offset(filter_row.updated_at, Utc),
-            keywords,
-        };
+        let keywords = FilterKeyword::get_by_filter(pool, filter_id).await?;
+        let statuses = FilterStatus::get_by_filter(pool, filter_id).await?;
+        let filter = filter_row.into_filter(keywords, statuses);
 
         info!(
             "Updated filter with id: {} for account {}",
@@ -590,11 +582,661 @@ impl Filter {
             false
         }
     }
+
+    /// Gets the contexts the filter applies to
+    pub fn contexts(&self) -> Vec<&str> {
+        self.context
+            .split(',')
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .collect()
+    }
+
+    /// Checks whether the filter applies to a context
+    pub fn applies_to(&self, context: &str) -> bool {
+        self.contexts().contains(&context)
+    }
+
+    /// Checks whether matching statuses are removed rather than flagged
+    pub fn is_hide(&self) -> bool {
+        self.filter_action == FILTER_ACTION_HIDE
+    }
+}
+
+impl FilterKeyword {
+    /// Adds a keyword to a filter
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `filter_id` - ID of the filter
+    /// * `account_id` - ID of the account that owns the filter
+    /// * `params` - Keyword attributes
+    ///
+    /// # Returns
+    ///
+    /// Result containing the created keyword or an error
+    pub async fn create(
+        pool: &PgPool,
+        filter_id: i64,
+        account_id: i64,
+        params: FilterKeywordParams,
+    ) -> Result<Self, FiltersError> {
+        trace!("Adding keyword to filter {}", filter_id);
+
+        validate_keyword(&params.keyword)?;
+
+        let row = sqlx::query_as!(
+            FilterKeywordRow,
+            r#"
+            INSERT INTO filter_keywords (filter_id, keyword, whole_word)
+            SELECT id, $3, $4
+            FROM filters
+            WHERE id = $1 AND account_id = $2
+            RETURNING id, filter_id, keyword, whole_word, created_at
+            "#,
+            filter_id,
+            account_id,
+            params.keyword,
+            params.whole_word
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(FiltersError::FilterNotFound(filter_id))?;
+
+        let keyword = FilterKeyword::from(row);
+        info!(
+            "Added keyword {} to filter {}",
+            keyword.id, keyword.filter_id
+        );
+        Ok(keyword)
+    }
+
+    /// Gets the keywords of a filter
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `filter_id` - ID of the filter
+    ///
+    /// # Returns
+    ///
+    /// Result containing the keywords in creation order
+    pub async fn get_by_filter(pool: &PgPool, filter_id: i64) -> Result<Vec<Self>, FiltersError> {
+        trace!("Getting keywords for filter {}", filter_id);
+
+        let rows = sqlx::query_as!(
+            FilterKeywordRow,
+            r#"
+            SELECT id, filter_id, keyword, whole_word, created_at
+            FROM filter_keywords
+            WHERE filter_id = $1
+            ORDER BY id
+            "#,
+            filter_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(FilterKeyword::from).collect())
+    }
+
+    /// Gets a keyword by ID, making sure its filter belongs to the account
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `keyword_id` - ID of the keyword
+    /// * `account_id` - ID of the account that must own the filter
+    ///
+    /// # Returns
+    ///
+    /// Result containing the keyword or an error
+    pub async fn get_owned(
+        pool: &PgPool,
+        keyword_id: i64,
+        account_id: i64,
+    ) -> Result<Self, FiltersError> {
+        trace!("Getting filter keyword {}", keyword_id);
+
+        let row = sqlx::query_as!(
+            FilterKeywordRow,
+            r#"
+            SELECT k.id, k.filter_id, k.keyword, k.whole_word, k.created_at
+            FROM filter_keywords k
+            JOIN filters f ON f.id = k.filter_id
+            WHERE k.id = $1 AND f.account_id = $2
+            "#,
+            keyword_id,
+            account_id
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(FiltersError::FilterKeywordNotFound(keyword_id))?;
+
+        Ok(FilterKeyword::from(row))
+    }
+
+    /// Updates a keyword
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `keyword_id` - ID of the keyword
+    /// * `account_id` - ID of the account that owns the filter
+    /// * `keyword` - New keyword text, if changing
+    /// * `whole_word` - New whole-word setting, if changing
+    ///
+    /// # Returns
+    ///
+    /// Result containing the updated keyword or an error
+    pub async fn update(
+        pool: &PgPool,
+        keyword_id: i64,
+        account_id: i64,
+        keyword: Option<String>,
+        whole_word: Option<bool>,
+    ) -> Result<Self, FiltersError> {
+        trace!("Updating filter keyword {}", keyword_id);
+
+        if let Some(ref keyword) = keyword {
+            validate_keyword(keyword)?;
+        }
+
+        let row = sqlx::query_as!(
+            FilterKeywordRow,
+            r#"
+            UPDATE filter_keywords k
+            SET keyword = COALESCE($3, k.keyword),
+                whole_word = COALESCE($4, k.whole_word)
+            FROM filters f
+            WHERE k.id = $1 AND f.id = k.filter_id AND f.account_id = $2
+            RETURNING k.id, k.filter_id, k.keyword, k.whole_word, k.created_at
+            "#,
+            keyword_id,
+            account_id,
+            keyword,
+            whole_word
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(FiltersError::FilterKeywordNotFound(keyword_id))?;
+
+        info!("Updated filter keyword {}", keyword_id);
+        Ok(FilterKeyword::from(row))
+    }
+
+    /// Removes a keyword from its filter
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `keyword_id` - ID of the keyword
+    /// * `account_id` - ID of the account that owns the filter
+    ///
+    /// # Returns
+    ///
+    /// Result indicating success or failure
+    pub async fn delete(
+        pool: &PgPool,
+        keyword_id: i64,
+        account_id: i64,
+    ) -> Result<(), FiltersError> {
+        trace!("Deleting filter keyword {}", keyword_id);
+
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM filter_keywords k
+            USING filters f
+            WHERE k.id = $1 AND f.id = k.filter_id AND f.account_id = $2
+            "#,
+            keyword_id,
+            account_id
+        )
+        .execute(pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(FiltersError::FilterKeywordNotFound(keyword_id));
+        }
+
+        info!("Deleted filter keyword {}", keyword_id);
+        Ok(())
+    }
+}
+
+impl FilterStatus {
+    /// Adds a status to a filter
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `filter_id` - ID of the filter
+    /// * `account_id` - ID of the account that owns the filter
+    /// * `status_id` - ID of the status to filter
+    ///
+    /// # Returns
+    ///
+    /// Result containing the created filter status or an error
+    pub async fn create(
+        pool: &PgPool,
+        filter_id: i64,
+        account_id: i64,
+        status_id: i64,
+    ) -> Result<Self, FiltersError> {
+        trace!("Adding status {} to filter {}", status_id, filter_id);
+
+        Filter::get_owned(pool, filter_id, account_id).await?;
+
+        let row = sqlx::query_as!(
+            FilterStatusRow,
+            r#"
+            INSERT INTO filter_statuses (filter_id, status_id)
+            SELECT $1, id
+            FROM statuses
+            WHERE id = $2
+            ON CONFLICT (filter_id, status_id) DO NOTHING
+            RETURNING id, filter_id, status_id, created_at
+            "#,
+            filter_id,
+            status_id
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| {
+            FiltersError::Validation(format!(
+                "Status {} does not exist or is already filtered",
+                status_id
+            ))
+        })?;
+
+        let filter_status = FilterStatus::from(row);
+        info!("Added status {} to filter {}", status_id, filter_id);
+        Ok(filter_status)
+    }
+
+    /// Gets the statuses of a filter
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `filter_id` - ID of the filter
+    ///
+    /// # Returns
+    ///
+    /// Result containing the filter statuses in creation order
+    pub async fn get_by_filter(pool: &PgPool, filter_id: i64) -> Result<Vec<Self>, FiltersError> {
+        trace!("Getting statuses for filter {}", filter_id);
+
+        let rows = sqlx::query_as!(
+            FilterStatusRow,
+            r#"
+            SELECT id, filter_id, status_id, created_at
+            FROM filter_statuses
+            WHERE filter_id = $1
+            ORDER BY id
+            "#,
+            filter_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(FilterStatus::from).collect())
+    }
+
+    /// Gets a filter status by ID, making sure its filter belongs to the account
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `filter_status_id` - ID of the filter status
+    /// * `account_id` - ID of the account that must own the filter
+    ///
+    /// # Returns
+    ///
+    /// Result containing the filter status or an error
+    pub async fn get_owned(
+        pool: &PgPool,
+        filter_status_id: i64,
+        account_id: i64,
+    ) -> Result<Self, FiltersError> {
+        trace!("Getting filter status {}", filter_status_id);
+
+        let row = sqlx::query_as!(
+            FilterStatusRow,
+            r#"
+            SELECT s.id, s.filter_id, s.status_id, s.created_at
+            FROM filter_statuses s
+            JOIN filters f ON f.id = s.filter_id
+            WHERE s.id = $1 AND f.account_id = $2
+            "#,
+            filter_status_id,
+            account_id
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(FiltersError::FilterStatusNotFound(filter_status_id))?;
+
+        Ok(FilterStatus::from(row))
+    }
+
+    /// Removes a status from its filter
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `filter_status_id` - ID of the filter status
+    /// * `account_id` - ID of the account that owns the filter
+    ///
+    /// # Returns
+    ///
+    /// Result indicating success or failure
+    pub async fn delete(
+        pool: &PgPool,
+        filter_status_id: i64,
+        account_id: i64,
+    ) -> Result<(), FiltersError> {
+        trace!("Deleting filter status {}", filter_status_id);
+
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM filter_statuses s
+            USING filters f
+            WHERE s.id = $1 AND f.id = s.filter_id AND f.account_id = $2
+            "#,
+            filter_status_id,
+            account_id
+        )
+        .execute(pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(FiltersError::FilterStatusNotFound(filter_status_id));
+        }
+
+        info!("Deleted filter status {}", filter_status_id);
+        Ok(())
+    }
+}
+
+/// A filter that matched a status
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilterResult {
+    /// The filter that matched
+    pub filter: Filter,
+    /// Keywords of the filter found in the status text
+    pub keyword_matches: Vec<String>,
+    /// IDs of the filter's statuses that matched
+    pub status_matches: Vec<String>,
+}
+
+/// What to do with a status after applying filters
+#[derive(Debug, Clone)]
+pub enum FilterOutcome {
+    /// Show the status, flagged with any `warn` filters that matched
+    Show(Vec<FilterResult>),
+    /// Remove the status because a `hide` filter matched
+    Hide,
+}
+
+/// A compiled filter ready for matching
+#[derive(Debug, Clone)]
+struct CompiledFilter {
+    filter: Filter,
+    keywords: Vec<(String, Regex)>,
+}
+
+/// Applies a user's filters to statuses shown in one context
+///
+/// Expired filters and filters for other contexts are skipped when the
+/// matcher is built, so the same matcher can be reused for a whole page
+/// of statuses.
+#[derive(Debug, Clone)]
+pub struct FilterMatcher {
+    filters: Vec<CompiledFilter>,
+}
+
+impl FilterMatcher {
+    /// Builds a matcher from an account's filters
+    ///
+    /// # Arguments
+    ///
+    /// * `filters` - The account's filters
+    /// * `context` - Context the statuses are shown in (home, notifications, public, thread, account)
+    pub fn new(filters: Vec<Filter>, context: &str) -> Self {
+        let filters = filters
+            .into_iter()
+            .filter(|f| !f.is_expired() && f.applies_to(context))
+            .map(|filter| {
+                let keywords = filter
+                    .keywords
+                    .iter()
+                    .filter_map(|k| match keyword_regex(&k.keyword, k.whole_word) {
+                        Ok(regex) => Some((k.keyword.clone(), regex)),
+                        Err(e) => {
+                            warn!("Skipping unusable filter keyword {}: {}", k.id, e);
+                            None
+                        }
+                    })
+                    .collect();
+                CompiledFilter { filter, keywords }
+            })
+            .collect();
+
+        Self { filters }
+    }
+
+    /// Checks whether no filter applies in this context
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    /// Finds the filters matching a status
+    ///
+    /// # Arguments
+    ///
+    /// * `status_id` - ID of the status
+    /// * `text` - Status content (HTML is stripped) and content warning
+    ///
+    /// # Returns
+    ///
+    /// The filters that matched, with the keywords and statuses that triggered them
+    pub fn matches(&self, status_id: i64, text: &str) -> Vec<FilterResult> {
+        let text = strip_html(text);
+
+        self.filters
+            .iter()
+            .filter_map(|compiled| {
+                let keyword_matches: Vec<String> = compiled
+                    .keywords
+                    .iter()
+                    .filter(|(_, regex)| regex.is_match(&text))
+                    .map(|(keyword, _)| keyword.clone())
+                    .collect();
+                let status_matches: Vec<String> = compiled
+                    .filter
+                    .statuses
+                    .iter()
+                    .filter(|s| s.status_id == status_id)
+                    .map(|s| s.status_id.to_string())
+                    .collect();
+
+                if keyword_matches.is_empty() && status_matches.is_empty() {
+                    return None;
+                }
+
+                Some(FilterResult {
+                    filter: compiled.filter.clone(),
+                    keyword_matches,
+                    status_matches,
+                })
+            })
+            .collect()
+    }
+
+    /// Decides whether a status is shown, and with which filter results
+    ///
+    /// # Arguments
+    ///
+    /// * `status_id` - ID of the status
+    /// * `text` - Status content (HTML is stripped) and content warning
+    pub fn apply(&self, status_id: i64, text: &str) -> FilterOutcome {
+        let results = self.matches(status_id, text);
+        if results.iter().any(|r| r.filter.is_hide()) {
+            trace!("Hiding status {} due to filters", status_id);
+            FilterOutcome::Hide
+        } else {
+            FilterOutcome::Show(results)
+        }
+    }
+}
+
+/// Builds a case-insensitive regex for a keyword
+///
+/// Whole-word keywords only get a word boundary on sides that start or end
+/// with a word character, so keywords like `#tag` still match.
+fn keyword_regex(keyword: &str, whole_word: bool) -> Result<Regex, regex::Error> {
+    let escaped = regex::escape(keyword.trim());
+    let pattern = if whole_word {
+        let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+        let start = if is_word(keyword.trim().chars().next()) {
+            r"\b"
+        } else {
+            ""
+        };
+        let end = if is_word(keyword.trim().chars().last()) {
+            r"\b"
+        } else {
+            ""
+        };
+        format!("{}{}{}", start, escaped, end)
+    } else {
+        escaped
+    };
+
+    RegexBuilder::new(&pattern).case_insensitive(true).build()
+}
+
+/// Removes HTML tags and decodes the entities Rustodon emits
+fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                text.push(' ');
+            }
+            '>' => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+struct FilterRow {
+    id: i64,
+    account_id: i64,
+    title: Option<String>,
+    context: Vec<String>,
+    expires_at: Option<NaiveDateTime>,
+    filter_action: String,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+}
+
+impl FilterRow {
+    fn into_filter(self, keywords: Vec<FilterKeyword>, statuses: Vec<FilterStatus>) -> Filter {
+        Filter {
+            id: self.id,
+            account_id: self.account_id,
+            title: self.title.unwrap_or_default(),
+            context: self.context.join(","),
+            expires_at: self
+                .expires_at
+                .map(|dt| DateTime::from_naive_utc_and_offset(dt, Utc)),
+            filter_action: self.filter_action,
+            created_at: DateTime::from_naive_utc_and_offset(self.created_at, Utc),
+            updated_at: DateTime::from_naive_utc_and_offset(self.updated_at, Utc),
+            keywords,
+            statuses,
+        }
+    }
+}
+
+struct FilterKeywordRow {
+    id: i64,
+    filter_id: i64,
+    keyword: String,
+    whole_word: Option<bool>,
+    created_at: NaiveDateTime,
+}
+
+impl From<FilterKeywordRow> for FilterKeyword {
+    fn from(row: FilterKeywordRow) -> Self {
+        Self {
+            id: row.id,
+            filter_id: row.filter_id,
+            keyword: row.keyword,
+            whole_word: row.whole_word.unwrap_or(false),
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+        }
+    }
+}
+
+struct FilterStatusRow {
+    id: i64,
+    filter_id: i64,
+    status_id: i64,
+    created_at: NaiveDateTime,
+}
+
+impl From<FilterStatusRow> for FilterStatus {
+    fn from(row: FilterStatusRow) -> Self {
+        Self {
+            id: row.id,
+            filter_id: row.filter_id,
+            status_id: row.status_id,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::Duration;
+
+    fn filter(id: i64, context: &str, action: &str, keywords: &[(&str, bool)]) -> Filter {
+        Filter {
+            id,
+            account_id: 1,
+            title: format!("Filter {}", id),
+            context: context.to_string(),
+            expires_at: None,
+            filter_action: action.to_string(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            keywords: keywords
+                .iter()
+                .enumerate()
+                .map(|(i, (keyword, whole_word))| FilterKeyword {
+                    id: i as i64,
+                    filter_id: id,
+                    keyword: keyword.to_string(),
+                    whole_word: *whole_word,
+                    created_at: Utc::now(),
+                })
+                .collect(),
+            statuses: vec![],
+        }
+    }
 
     #[tokio::test]
     async fn test_filter_create_and_delete() {
@@ -610,6 +1252,7 @@ mod tests {
             created_at: Utc::now(),
             updated_at: Utc::now(),
             keywords: vec![],
+            statuses: vec![],
         };
 
         assert_eq!(filter.account_id, 1);
@@ -632,4 +1275,74 @@ mod tests {
         assert_eq!(keyword.keyword, "spam");
         assert!(!keyword.whole_word);
     }
+
+    #[test]
+    fn test_validation() {
+        assert!(validate_context(&["home".to_string(), "thread".to_string()]).is_ok());
+        assert!(validate_context(&["timeline".to_string()]).is_err());
+        assert!(validate_context(&[]).is_err());
+        assert!(validate_filter_action("hide").is_ok());
+        assert!(validate_filter_action("blur").is_err());
+    }
+
+    #[test]
+    fn test_matcher_whole_word() {
+        let matcher = FilterMatcher::new(
+            vec![filter(1, "home", "warn", &[("cat", true), ("dog", false)])],
+            "home",
+        );
+
+        assert!(matcher.matches(1, "<p>My Cat sleeps</p>").len() == 1);
+        assert!(matcher.matches(2, "<p>concatenate</p>").is_empty());
+        assert_eq!(
+            matcher.matches(3, "hotdogs")[0].keyword_matches,
+            vec!["dog".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_matcher_hide_and_context() {
+        let filters = vec![
+            filter(1, "home,public", "hide", &[("spoiler", false)]),
+            filter(2, "notifications", "warn", &[("spoiler", false)]),
+        ];
+
+        let home = FilterMatcher::new(filters.clone(), "home");
+        assert!(matches!(home.apply(1, "big spoiler"), FilterOutcome::Hide));
+
+        let notifications = FilterMatcher::new(filters, "notifications");
+        match notifications.apply(1, "big spoiler") {
+            FilterOutcome::Show(results) => assert_eq!(results[0].filter.id, 2),
+            FilterOutcome::Hide => panic!("warn filter must not hide"),
+        }
+    }
+
+    #[test]
+    fn test_matcher_skips_expired_and_matches_statuses() {
+        let mut expired = filter(1, "home", "hide", &[("news", false)]);
+        expired.expires_at = Some(Utc::now() - Duration::hours(1));
+        assert!(expired.is_expired());
+
+        let mut by_status = filter(2, "home", "warn", &[]);
+        by_status.statuses.push(FilterStatus {
+            id: 1,
+            filter_id: 2,
+            status_id: 42,
+            created_at: Utc::now(),
+        });
+
+        let matcher = FilterMatcher::new(vec![expired, by_status], "home");
+        assert!(matcher.matches(7, "news").is_empty());
+        assert_eq!(
+            matcher.matches(42, "anything")[0].status_matches,
+            vec!["42"]
+        );
+    }
+
+    #[test]
+    fn test_keyword_regex_symbols() {
+        let regex = keyword_regex("#tag", true).unwrap();
+        assert!(regex.is_match("look #TAG here"));
+        assert!(!regex.is_match("look #tags here"));
+    }
 }
@@ -1,22 +1,38 @@
 //! Follow recommendation suppression functionality for Rustodon
 //!
-//! This module provides follow recommendation suppression capabilities for the Rustodon server.
+//! Staff can suppress accounts from follow suggestions. Suppressed accounts
+//! are left out of the global recommendations on their next refresh and
+//! filtered from every suggestion source right away.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_follow_recommendation_suppressions::FollowRecommendationSuppression;
+//!
+//! FollowRecommendationSuppression::create(&pool, account_id).await?;
+//! let suppressed = FollowRecommendationSuppression::get_all(&pool).await?;
+//! FollowRecommendationSuppression::remove(&pool, account_id).await?;
+//! ```
 //!
 //! # Author
 //!
 //! arkSong (arksong2018@gmail.com)
 
+use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
 use thiserror::Error;
-use tracing::{error, info};
+use tracing::{debug, info};
 
 /// Follow recommendation suppression model
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FollowRecommendationSuppression {
+    /// Unique identifier for the suppression
     pub id: i64,
+    /// ID of the suppressed account
     pub account_id: i64,
-    pub target_account_id: i64,
-    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// When the account was suppressed
+    pub created_at: DateTime<Utc>,
 }
 
 /// Error type for follow recommendation suppression operations
@@ -24,15 +40,114 @@ pub struct FollowRecommendationSuppression {
 pub enum FollowRecommendationSuppressionError {
     #[error("Database error: {0}")]
     Database(#[from] sqlx::Error),
-    #[error("Suppression error: {0}")]
-    Suppression(String),
+    #[error("Suppression not found for account {0}")]
+    NotFound(i64),
+}
+
+/// Internal struct for database rows
+struct SuppressionRow {
+    id: i64,
+    account_id: i64,
+    created_at: NaiveDateTime,
 }
 
-/// Initialize follow recommendation suppressions functionality
-pub async fn init_follow_recommendation_suppressions(
-) -> Result<(), FollowRecommendationSuppressionError> {
-    info!("Initializing follow recommendation suppressions functionality");
-    Ok(())
+impl From<SuppressionRow> for FollowRecommendationSuppression {
+    fn from(row: SuppressionRow) -> Self {
+        Self {
+            id: row.id,
+            account_id: row.account_id,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+        }
+    }
+}
+
+impl FollowRecommendationSuppression {
+    /// Suppresses an account from follow suggestions
+    ///
+    /// Suppressing an account twice keeps the first suppression.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the account to suppress
+    pub async fn create(
+        pool: &PgPool,
+        account_id: i64,
+    ) -> Result<Self, FollowRecommendationSuppressionError> {
+        let row = sqlx::query_as!(
+            SuppressionRow,
+            r#"
+            INSERT INTO follow_recommendation_suppressions (account_id)
+            VALUES ($1)
+            ON CONFLICT (account_id) DO UPDATE SET updated_at = NOW()
+            RETURNING id, account_id, created_at
+            "#,
+            account_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        info!("Suppressed account {} from follow suggestions", account_id);
+        Ok(row.into())
+    }
+
+    /// Lifts the suppression of an account
+    pub async fn remove(
+        pool: &PgPool,
+        account_id: i64,
+    ) -> Result<(), FollowRecommendationSuppressionError> {
+        let result = sqlx::query!(
+            "DELETE FROM follow_recommendation_suppressions WHERE account_id = $1",
+            account_id
+        )
+        .execute(pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(FollowRecommendationSuppressionError::NotFound(account_id));
+        }
+
+        info!(
+            "Account {} may be suggested again after suppression",
+            account_id
+        );
+        Ok(())
+    }
+
+    /// Gets all suppressions, newest first
+    pub async fn get_all(pool: &PgPool) -> Result<Vec<Self>, FollowRecommendationSuppressionError> {
+        let rows = sqlx::query_as!(
+            SuppressionRow,
+            r#"
+            SELECT id, account_id, created_at
+            FROM follow_recommendation_suppressions
+            ORDER BY id DESC
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        debug!("Retrieved {} suggestion suppressions", rows.len());
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    /// Checks whether an account is suppressed from follow suggestions
+    pub async fn is_suppressed(
+        pool: &PgPool,
+        account_id: i64,
+    ) -> Result<bool, FollowRecommendationSuppressionError> {
+        let suppressed = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM follow_recommendation_suppressions WHERE account_id = $1
+            ) AS "suppressed!"
+            "#,
+            account_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(suppressed)
+    }
 }
 
 #[cfg(test)]
@@ -40,13 +155,13 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_follow_recommendation_suppression() {
-        let suppression = FollowRecommendationSuppression {
+    fn test_from_row() {
+        let suppression = FollowRecommendationSuppression::from(SuppressionRow {
             id: 1,
-            account_id: 1,
-            target_account_id: 2,
-            created_at: chrono::Utc::now(),
-        };
-        assert_eq!(suppression.account_id, 1);
+            account_id: 2,
+            created_at: DateTime::from_timestamp(0, 0).unwrap().naive_utc(),
+        });
+        assert_eq!(suppression.account_id, 2);
+        assert_eq!(suppression.created_at.timestamp(), 0);
     }
 }
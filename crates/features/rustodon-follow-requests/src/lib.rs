@@ -1,5 +1,492 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
+//! Follow requests module for Rustodon
+//!
+//! This module provides pending follow requests for locked accounts.
+//! A follow aimed at a locked account is stored as a request until the
+//! target authorizes it (creating the real follow) or rejects it.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_follow_requests::{follow_or_request, FollowOutcome, FollowRequest};
+//!
+//! match follow_or_request(&pool, follower_id, locked_account_id, true, false, None).await? {
+//!     FollowOutcome::Requested(_) => {
+//!         let (_request, follow) =
+//!             FollowRequest::authorize(&pool, follower_id, locked_account_id).await?;
+//!     }
+//!     FollowOutcome::Followed(_) => {}
+//! }
+//! ```
+//!
+//! # Dependencies
+//!
+//! - `rustodon_core`: Core types and traits
+//! - `rustodon_follows`: Follow relationships created on authorization
+//! - `sqlx`: Database queries
+//! - `serde`: Serialization
+//! - `chrono`: DateTime handling
+//! - `thiserror`: Error handling
+//! - `tracing`: Logging
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rustodon_follows::Follow;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use thiserror::Error;
+use tracing::{debug, info, trace};
+
+/// Custom error type for follow requests module
+#[derive(Error, Debug)]
+pub enum FollowRequestsError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("Follows error: {0}")]
+    Follows(#[from] rustodon_follows::FollowsError),
+    #[error("Follow request not found")]
+    FollowRequestNotFound,
+    #[error("Account not found: {0}")]
+    AccountNotFound(i64),
+    #[error("Follow already requested")]
+    AlreadyRequested,
+    #[error("Already following")]
+    AlreadyFollowing,
+    #[error("Cannot follow yourself")]
+    CannotFollowSelf,
+}
+
+/// Follow request data structure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FollowRequest {
+    /// Unique identifier for the follow request
+    pub id: i64,
+    /// ID of the account requesting to follow
+    pub account_id: i64,
+    /// ID of the locked account being followed
+    pub target_account_id: i64,
+    /// Whether to show reblogs once the follow is accepted
+    pub show_reblogs: bool,
+    /// Whether to notify on posts once the follow is accepted
+    pub notify: bool,
+    /// URI of the remote `Follow` activity (None for local requests)
+    pub uri: Option<String>,
+    /// When the follow request was created
+    pub created_at: DateTime<Utc>,
+    /// When the follow request was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Result of following an account
+#[derive(Debug, Clone)]
+pub enum FollowOutcome {
+    /// The target is not locked and the follow was created immediately
+    Followed(Follow),
+    /// The target is locked and a follow request is now pending
+    Requested(FollowRequest),
+}
+
+/// Follows an account, or requests to follow it if the account is locked
+///
+/// # Arguments
+///
+/// * `pool` - Database connection pool
+/// * `account_id` - ID of the account following
+/// * `target_account_id` - ID of the account being followed
+/// * `show_reblogs` - Whether to show reblogs of the followed account
+/// * `notify` - Whether to notify when the followed account posts
+/// * `uri` - URI of the remote `Follow` activity, if the follow came from another server
+///
+/// # Returns
+///
+/// Result containing the created follow or the pending request
+pub async fn follow_or_request(
+    pool: &PgPool,
+    account_id: i64,
+    target_account_id: i64,
+    show_reblogs: bool,
+    notify: bool,
+    uri: Option<&str>,
+) -> Result<FollowOutcome, FollowRequestsError> {
+    trace!(
+        "Following or requesting to follow {} from {}",
+        target_account_id,
+        account_id
+    );
+
+    let target = sqlx::query!(
+        r#"
+        SELECT locked
+        FROM users
+        WHERE id = $1
+        "#,
+        target_account_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(FollowRequestsError::AccountNotFound(target_account_id))?;
+
+    if target.locked {
+        let request = FollowRequest::create(
+            pool,
+            account_id,
+            target_account_id,
+            show_reblogs,
+            notify,
+            uri,
+        )
+        .await?;
+        Ok(FollowOutcome::Requested(request))
+    } else {
+        let mut conn = pool.acquire().await?;
+        let follow = Follow::create(
+            &mut conn,
+            account_id,
+            target_account_id,
+            show_reblogs,
+            notify,
+        )
+        .await?;
+        Ok(FollowOutcome::Followed(follow))
+    }
+}
+
+impl FollowRequest {
+    /// Creates a new follow request
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the account requesting to follow
+    /// * `target_account_id` - ID of the locked account
+    /// * `show_reblogs` - Whether to show reblogs once the follow is accepted
+    /// * `notify` - Whether to notify on posts once the follow is accepted
+    /// * `uri` - URI of the remote `Follow` activity, if the request came from another server
+    ///
+    /// # Returns
+    ///
+    /// Result containing the created follow request or an error
+    pub async fn create(
+        pool: &PgPool,
+        account_id: i64,
+        target_account_id: i64,
+        show_reblogs: bool,
+        notify: bool,
+        uri: Option<&str>,
+    ) -> Result<Self, FollowRequestsError> {
+        trace!(
+            "Creating follow request from {} to {}",
+            account_id,
+            target_account_id
+        );
+
+        if account_id == target_account_id {
+            return Err(FollowRequestsError::CannotFollowSelf);
+        }
+
+        for id in [account_id, target_account_id] {
+            let exists = sqlx::query!(
+                r#"
+                SELECT COUNT(*) as count
+                FROM users
+                WHERE id = $1
+                "#,
+                id
+            )
+            .fetch_one(pool)
+            .await?
+            .count;
+
+            if exists.unwrap_or(0) == 0 {
+                return Err(FollowRequestsError::AccountNotFound(id));
+            }
+        }
+
+        if Follow::exists(pool, account_id, target_account_id).await? {
+            return Err(FollowRequestsError::AlreadyFollowing);
+        }
+
+        if Self::exists(pool, account_id, target_account_id).await? {
+            return Err(FollowRequestsError::AlreadyRequested);
+        }
+
+        let row = sqlx::query_as!(
+            FollowRequestRow,
+            r#"
+            INSERT INTO follow_requests (account_id, target_account_id, show_reblogs, notify, uri)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, account_id, target_account_id, show_reblogs, notify, uri, created_at, updated_at
+            "#,
+            account_id,
+            target_account_id,
+            show_reblogs,
+            notify,
+            uri
+        )
+        .fetch_one(pool)
+        .await?;
+
+        let request = FollowRequest::from(row);
+        info!(
+            "Created follow request with id: {} from {} to {}",
+            request.id, account_id, target_account_id
+        );
+        Ok(request)
+    }
+
+    /// Finds the pending follow request between two accounts
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the requesting account
+    /// * `target_account_id` - ID of the locked account
+    ///
+    /// # Returns
+    ///
+    /// Result containing the follow request if one is pending
+    pub async fn find(
+        pool: &PgPool,
+        account_id: i64,
+        target_account_id: i64,
+    ) -> Result<Option<Self>, FollowRequestsError> {
+        trace!(
+            "Finding follow request from {} to {}",
+            account_id,
+            target_account_id
+        );
+
+        let row = sqlx::query_as!(
+            FollowRequestRow,
+            r#"
+            SELECT id, account_id, target_account_id, show_reblogs, notify, uri, created_at, updated_at
+            FROM follow_requests
+            WHERE account_id = $1 AND target_account_id = $2
+            "#,
+            account_id,
+            target_account_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row.map(FollowRequest::from))
+    }
+
+    /// Checks if a follow request is pending between two accounts
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the requesting account
+    /// * `target_account_id` - ID of the locked account
+    ///
+    /// # Returns
+    ///
+    /// Result containing true if a request is pending
+    pub async fn exists(
+        pool: &PgPool,
+        account_id: i64,
+        target_account_id: i64,
+    ) -> Result<bool, FollowRequestsError> {
+        let count = sqlx::query!(
+            r#"
+            SELECT COUNT(*) as count
+            FROM follow_requests
+            WHERE account_id = $1 AND target_account_id = $2
+            "#,
+            account_id,
+            target_account_id
+        )
+        .fetch_one(pool)
+        .await?
+        .count;
+
+        Ok(count.unwrap_or(0) > 0)
+    }
+
+    /// Gets the pending follow requests addressed to an account
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `target_account_id` - ID of the locked account
+    /// * `limit` - Maximum number of requests to return
+    /// * `since_id` - Return requests after this ID
+    /// * `max_id` - Return requests before this ID
+    ///
+    /// # Returns
+    ///
+    /// Result containing the list of follow requests, newest first
+    pub async fn get_by_target(
+        pool: &PgPool,
+        target_account_id: i64,
+        limit: Option<i64>,
+        since_id: Option<i64>,
+        max_id: Option<i64>,
+    ) -> Result<Vec<Self>, FollowRequestsError> {
+        trace!(
+            "Getting follow requests for account {} with limit {:?}",
+            target_account_id,
+            limit
+        );
+        let limit = limit.unwrap_or(40).clamp(1, 80);
+
+        let rows = sqlx::query_as!(
+            FollowRequestRow,
+            r#"
+            SELECT id, account_id, target_account_id, show_reblogs, notify, uri, created_at, updated_at
+            FROM follow_requests
+            WHERE target_account_id = $1
+              AND ($2::BIGINT IS NULL OR id > $2)
+              AND ($3::BIGINT IS NULL OR id < $3)
+            ORDER BY id DESC
+            LIMIT $4
+            "#,
+            target_account_id,
+            since_id,
+            max_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let requests: Vec<FollowRequest> = rows.into_iter().map(FollowRequest::from).collect();
+        debug!(
+            "Retrieved {} follow requests for account {}",
+            requests.len(),
+            target_account_id
+        );
+        Ok(requests)
+    }
+
+    /// Authorizes a pending follow request
+    ///
+    /// Removes the request and creates the real follow in a single transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the requesting account
+    /// * `target_account_id` - ID of the locked account authorizing the request
+    ///
+    /// # Returns
+    ///
+    /// Result containing the authorized request and the created follow
+    pub async fn authorize(
+        pool: &PgPool,
+        account_id: i64,
+        target_account_id: i64,
+    ) -> Result<(Self, Follow), FollowRequestsError> {
+        trace!(
+            "Authorizing follow request from {} to {}",
+            account_id,
+            target_account_id
+        );
+
+        let mut tx = pool.begin().await?;
+
+        let request_row = sqlx::query_as!(
+            FollowRequestRow,
+            r#"
+            DELETE FROM follow_requests
+            WHERE account_id = $1 AND target_account_id = $2
+            RETURNING id, account_id, target_account_id, show_reblogs, notify, uri, created_at, updated_at
+            "#,
+            account_id,
+            target_account_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(FollowRequestsError::FollowRequestNotFound)?;
+
+        let follow = Follow::create(
+            &mut tx,
+            request_row.account_id,
+            request_row.target_account_id,
+            request_row.show_reblogs,
+            request_row.notify,
+        )
+        .await?;
+
+        tx.commit().await?;
+
+        info!(
+            "Authorized follow request from {} to {} (follow id: {})",
+            account_id, target_account_id, follow.id
+        );
+        Ok((FollowRequest::from(request_row), follow))
+    }
+
+    /// Rejects a pending follow request
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the requesting account
+    /// * `target_account_id` - ID of the locked account rejecting the request
+    ///
+    /// # Returns
+    ///
+    /// Result containing the rejected request
+    pub async fn reject(
+        pool: &PgPool,
+        account_id: i64,
+        target_account_id: i64,
+    ) -> Result<Self, FollowRequestsError> {
+        trace!(
+            "Rejecting follow request from {} to {}",
+            account_id,
+            target_account_id
+        );
+
+        let row = sqlx::query_as!(
+            FollowRequestRow,
+            r#"
+            DELETE FROM follow_requests
+            WHERE account_id = $1 AND target_account_id = $2
+            RETURNING id, account_id, target_account_id, show_reblogs, notify, uri, created_at, updated_at
+            "#,
+            account_id,
+            target_account_id
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(FollowRequestsError::FollowRequestNotFound)?;
+
+        info!(
+            "Rejected follow request from {} to {}",
+            account_id, target_account_id
+        );
+        Ok(FollowRequest::from(row))
+    }
+}
+
+struct FollowRequestRow {
+    id: i64,
+    account_id: i64,
+    target_account_id: i64,
+    show_reblogs: bool,
+    notify: bool,
+    uri: Option<String>,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+}
+
+impl From<FollowRequestRow> for FollowRequest {
+    fn from(row: FollowRequestRow) -> Self {
+        Self {
+            id: row.id,
+            account_id: row.account_id,
+            target_account_id: row.target_account_id,
+            show_reblogs: row.show_reblogs,
+            notify: row.notify,
+            uri: row.uri,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+            updated_at: DateTime::from_naive_utc_and_offset(row.updated_at, Utc),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -7,8 +494,34 @@ mod tests {
     use super::*;
 
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn test_follow_request_from_row() {
+        let now = Utc::now().naive_utc();
+        let request = FollowRequest::from(FollowRequestRow {
+            id: 1,
+            account_id: 2,
+            target_account_id: 3,
+            show_reblogs: true,
+            notify: false,
+            uri: Some("https://remote.example/follows/1".to_string()),
+            created_at: now,
+            updated_at: now,
+        });
+
+        assert_eq!(request.account_id, 2);
+        assert_eq!(request.target_account_id, 3);
+        assert!(request.show_reblogs);
+        assert_eq!(request.created_at.naive_utc(), now);
+    }
+
+    #[test]
+    fn test_error_display() {
+        assert_eq!(
+            FollowRequestsError::AlreadyRequested.to_string(),
+            "Follow already requested"
+        );
+        assert_eq!(
+            FollowRequestsError::AccountNotFound(5).to_string(),
+            "Account not found: 5"
+        );
     }
 }
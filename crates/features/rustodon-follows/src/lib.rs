@@ -9,7 +9,7 @@
 //! ```rust
 //! use rustodon_follows::Follow;
 //!
-//! let follow = Follow::create(&pool, follower_id, followed_id).await?;
+//! let follow = Follow::create(&mut conn, follower_id, followed_id, true, false).await?;
 //! ```
 //!
 //! # Dependencies
@@ -28,7 +28,7 @@
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
+use sqlx::{PgConnection, PgPool};
 use thiserror::Error;
 use tracing::{debug, error, info, trace};
 
@@ -77,17 +77,21 @@ impl Follow {
     ///
     /// # Arguments
     ///
-    /// * `pool` - Database connection pool
+    /// * `conn` - Connection of the transaction to run in
     /// * `follower_id` - ID of the account following
     /// * `followed_id` - ID of the account being followed
+    /// * `show_reblogs` - Whether to show reblogs of the followed account
+    /// * `notify` - Whether to notify when the followed account posts
     ///
     /// # Returns
     ///
     /// Result containing the created follow or an error
     pub async fn create(
-        pool: &PgPool,
+        conn: &mut PgConnection,
         follower_id: i64,
         followed_id: i64,
+        show_reblogs: bool,
+        notify: bool,
     ) -> Result<Self, FollowsError> {
         trace!("Creating follow from {} to {}", follower_id, followed_id);
 
@@ -105,7 +109,7 @@ impl Follow {
             "#,
             follower_id
         )
-        .fetch_one(pool)
+        .fetch_one(&mut *conn)
         .await?
         .count;
 
@@ -122,7 +126,7 @@ impl Follow {
             "#,
             followed_id
         )
-        .fetch_one(pool)
+        .fetch_one(&mut *conn)
         .await?
         .count;
 
@@ -140,7 +144,7 @@ impl Follow {
             follower_id,
             followed_id
         )
-        .fetch_one(pool)
+        .fetch_one(&mut *conn)
         .await?
         .count;
 
@@ -151,14 +155,16 @@ impl Follow {
         // Insert follow
         let follow_row = sqlx::query!(
             r#"
-            INSERT INTO follows (follower_id, followed_id, show_reblogs, notify)
-            VALUES ($1, $2, true, false)
+            INSERT INTO follows (account_id, target_account_id, follower_id, followed_id, show_reblogs, notify)
+            VALUES ($1, $2, $1, $2, $3, $4)
             RETURNING id, follower_id, followed_id, show_reblogs, notify, created_at, updated_at
             "#,
             follower_id,
-            followed_id
+            followed_id,
+            show_reblogs,
+            notify
         )
-        .fetch_one(pool)
+        .fetch_one(&mut *conn)
         .await?;
 
         let follow = Follow {
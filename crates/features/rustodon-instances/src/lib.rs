@@ -1,13 +1,32 @@
 //! Instances functionality for Rustodon
 //!
-//! This module provides instance management functionality.
+//! This module provides instance management functionality: server rules
+//! and the user, status and domain statistics shown on the instance page.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_instances::{InstanceStats, Rule};
+//!
+//! let stats = InstanceStats::fetch(&pool).await?;
+//! let rules = Rule::all(&pool).await?;
+//! ```
 //!
 //! # Author
 //!
 //! arkSong (arksong2018@gmail.com)
 
 use serde::{Deserialize, Serialize};
-use tracing::{info, trace};
+use sqlx::PgPool;
+use thiserror::Error;
+use tracing::{debug, info, trace};
+
+/// Custom error type for instances module
+#[derive(Error, Debug)]
+pub enum InstancesError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
 
 /// Instance model
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,6 +38,102 @@ pub struct Instance {
     pub version: String,
 }
 
+/// Server rule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rule {
+    /// Unique identifier for the rule
+    pub id: i64,
+    /// Short rule text
+    pub text: String,
+    /// Longer explanation of the rule
+    pub hint: String,
+    /// Display order, lowest first
+    pub priority: i32,
+}
+
+impl Rule {
+    /// Gets all active rules in display order
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    ///
+    /// # Returns
+    ///
+    /// Result containing the rules or an error
+    pub async fn all(pool: &PgPool) -> Result<Vec<Self>, InstancesError> {
+        trace!("Getting server rules");
+
+        let rules = sqlx::query_as!(
+            Rule,
+            r#"
+            SELECT id, text, hint, priority
+            FROM rules
+            WHERE deleted_at IS NULL
+            ORDER BY priority ASC, id ASC
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        debug!("Found {} server rules", rules.len());
+        Ok(rules)
+    }
+}
+
+/// Usage statistics of this instance
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InstanceStats {
+    /// Number of local accounts
+    pub user_count: i64,
+    /// Number of local statuses that have not been deleted
+    pub status_count: i64,
+    /// Number of other domains this instance knows accounts from
+    pub domain_count: i64,
+    /// Number of local accounts active in the last 30 days, that is posting,
+    /// favouriting, boosting or signing in
+    pub active_month: i64,
+}
+
+impl InstanceStats {
+    /// Computes the current statistics
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    ///
+    /// # Returns
+    ///
+    /// Result containing the statistics or an error
+    pub async fn fetch(pool: &PgPool) -> Result<Self, InstancesError> {
+        trace!("Computing instance statistics");
+
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                (SELECT COUNT(*) FROM users WHERE domain IS NULL) AS "user_count!",
+                (SELECT COUNT(*) FROM statuses WHERE local AND deleted_at IS NULL) AS "status_count!",
+                (SELECT COUNT(DISTINCT domain) FROM users WHERE domain IS NOT NULL) AS "domain_count!",
+                (SELECT COUNT(DISTINCT a.account_id)
+                 FROM account_activity a
+                 JOIN users u ON u.id = a.account_id
+                 WHERE u.domain IS NULL AND a.at > NOW() - INTERVAL '30 days') AS "active_month!"
+            "#
+        )
+        .fetch_one(pool)
+        .await?;
+
+        let stats = Self {
+            user_count: row.user_count,
+            status_count: row.status_count,
+            domain_count: row.domain_count,
+            active_month: row.active_month,
+        };
+        debug!("Instance statistics: {:?}", stats);
+        Ok(stats)
+    }
+}
+
 /// Instance service
 pub struct InstanceService;
 
@@ -65,4 +180,11 @@ mod tests {
         service.do_something(instance).await;
         // Operation completed
     }
+
+    #[test]
+    fn test_instance_stats_default() {
+        let stats = InstanceStats::default();
+        assert_eq!(stats.user_count, 0);
+        assert_eq!(stats.domain_count, 0);
+    }
 }
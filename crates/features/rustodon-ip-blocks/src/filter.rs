@@ -0,0 +1,383 @@
+//! In-memory IP block filter
+//!
+//! Requests are matched against the IP blocks without touching the database:
+//! [`IpBlockFilter`] keeps the unexpired blocks in an [`IpRadixTree`] which is
+//! rebuilt from the `ip_blocks` table on [`IpBlockFilter::reload`]. It also
+//! counts failed logins per address, and blocks an address for a while once
+//! it fails too often.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use chrono::{Duration as ChronoDuration, NaiveDateTime, Utc};
+use sqlx::types::ipnetwork::IpNetwork;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, trace, warn};
+
+use super::{IpBlock, IpBlockConfig, IpBlockError, IpBlockSeverity, IpRadixTree};
+
+/// Addresses tracked for failed logins before stale ones are pruned
+const MAX_TRACKED_ADDRESSES: usize = 10_000;
+
+/// A block as held in the tree
+#[derive(Debug, Clone, Copy)]
+struct BlockEntry {
+    severity: IpBlockSeverity,
+    expires_at: Option<NaiveDateTime>,
+}
+
+impl BlockEntry {
+    fn from_block(block: &IpBlock) -> Option<Self> {
+        match block.severity() {
+            Ok(severity) => Some(Self {
+                severity,
+                expires_at: block.expires_at,
+            }),
+            Err(e) => {
+                warn!("Skipping IP block {}: {}", block.id, e);
+                None
+            }
+        }
+    }
+
+    fn is_active(&self, now: NaiveDateTime) -> bool {
+        self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+}
+
+/// Failed logins of an address within the current window
+#[derive(Debug, Clone, Copy)]
+struct FailedLogins {
+    count: u32,
+    first_at: Instant,
+}
+
+/// Matches addresses against the IP blocks, shared between requests
+#[derive(Clone)]
+pub struct IpBlockFilter {
+    pool: PgPool,
+    config: Arc<IpBlockConfig>,
+    tree: Arc<RwLock<IpRadixTree<BlockEntry>>>,
+    failed_logins: Arc<Mutex<HashMap<IpAddr, FailedLogins>>>,
+}
+
+impl IpBlockFilter {
+    /// Creates a filter without any blocks until it is reloaded
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `config` - IP blocking configuration
+    pub fn new(pool: PgPool, config: IpBlockConfig) -> Self {
+        Self {
+            pool,
+            config: Arc::new(config),
+            tree: Arc::new(RwLock::new(IpRadixTree::new())),
+            failed_logins: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The configuration of the filter
+    pub fn config(&self) -> &IpBlockConfig {
+        &self.config
+    }
+
+    /// Replaces the blocks held in memory with the unexpired ones of the
+    /// database
+    ///
+    /// # Returns
+    ///
+    /// Result containing the number of blocks loaded or error
+    pub async fn reload(&self) -> Result<usize, IpBlockError> {
+        trace!("Reloading IP blocks");
+
+        let blocks = sqlx::query_as!(
+            IpBlock,
+            r#"
+            SELECT id, ip_address AS "ip_address!", severity AS "severity!",
+                   COALESCE(reason, '') AS "reason!", expires_at,
+                   created_at AS "created_at!", updated_at AS "updated_at!"
+            FROM ip_blocks
+            WHERE expires_at IS NULL OR expires_at > NOW()
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut tree = IpRadixTree::new();
+        for block in &blocks {
+            if let Some(entry) = BlockEntry::from_block(block) {
+                tree.insert(block.ip_address, entry);
+            }
+        }
+
+        let count = tree.count();
+        *self.tree.write().unwrap_or_else(|e| e.into_inner()) = tree;
+        debug!("Loaded {} IP blocks", count);
+        Ok(count)
+    }
+
+    /// Adds a block to the ones held in memory, until the next reload
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block
+    pub fn insert(&self, block: &IpBlock) {
+        if let Some(entry) = BlockEntry::from_block(block) {
+            self.tree
+                .write()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(block.ip_address, entry);
+        }
+    }
+
+    /// Gets the severity of the most specific unexpired block of an address
+    ///
+    /// # Arguments
+    ///
+    /// * `ip` - The client address
+    ///
+    /// # Returns
+    ///
+    /// The severity, or None if the address is not blocked or the filter is
+    /// disabled
+    pub fn check(&self, ip: IpAddr) -> Option<IpBlockSeverity> {
+        if !self.config.enabled {
+            return None;
+        }
+
+        let now = Utc::now().naive_utc();
+        self.tree
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .longest_match(ip, |entry| entry.is_active(now))
+            .map(|entry| entry.severity)
+    }
+
+    /// Records a failed login from an address, blocking the address once it
+    /// reaches the maximum number of failed attempts within the window
+    ///
+    /// # Arguments
+    ///
+    /// * `ip` - The client address
+    ///
+    /// # Returns
+    ///
+    /// Result containing the block created, if any, or error
+    pub async fn record_failed_login(&self, ip: IpAddr) -> Result<Option<IpBlock>, IpBlockError> {
+        if !self.config.enabled || self.config.max_failed_attempts == 0 {
+            return Ok(None);
+        }
+
+        let ip = ip.to_canonical();
+        let window = Duration::from_secs(self.config.failed_attempts_window);
+        let exceeded = {
+            let mut failed_logins = self.failed_logins.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            if failed_logins.len() >= MAX_TRACKED_ADDRESSES {
+                failed_logins.retain(|_, attempts| now.duration_since(attempts.first_at) < window);
+            }
+
+            let attempts = failed_logins.entry(ip).or_insert(FailedLogins {
+                count: 0,
+                first_at: now,
+            });
+            if now.duration_since(attempts.first_at) >= window {
+                *attempts = FailedLogins {
+                    count: 0,
+                    first_at: now,
+                };
+            }
+            attempts.count += 1;
+            trace!("{} failed logins from {}", attempts.count, ip);
+
+            let exceeded = attempts.count >= self.config.max_failed_attempts;
+            if exceeded {
+                failed_logins.remove(&ip);
+            }
+            exceeded
+        };
+
+        if !exceeded {
+            return Ok(None);
+        }
+
+        let expires_at = self
+            .config
+            .block_duration
+            .map(|duration| Utc::now().naive_utc() + ChronoDuration::seconds(duration as i64));
+        let reason = format!("{} failed login attempts", self.config.max_failed_attempts);
+
+        // An existing block of the address is only replaced once it has
+        // expired, so that blocks set by staff are left as they are
+        let block = sqlx::query_as!(
+            IpBlock,
+            r#"
+            INSERT INTO ip_blocks (ip_address, severity, reason, expires_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (ip_address) DO UPDATE
+            SET severity = EXCLUDED.severity, reason = EXCLUDED.reason,
+                expires_at = EXCLUDED.expires_at
+            WHERE ip_blocks.expires_at IS NOT NULL AND ip_blocks.expires_at <= NOW()
+            RETURNING id, ip_address AS "ip_address!", severity AS "severity!",
+                      COALESCE(reason, '') AS "reason!", expires_at,
+                      created_at AS "created_at!", updated_at AS "updated_at!"
+            "#,
+            IpNetwork::from(ip),
+            self.config.default_severity.to_string(),
+            reason,
+            expires_at,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if let Some(block) = &block {
+            info!("Blocked {} after repeated failed logins", ip);
+            self.insert(block);
+        }
+        Ok(block)
+    }
+
+    /// Forgets the failed logins of an address after it logs in
+    ///
+    /// # Arguments
+    ///
+    /// * `ip` - The client address
+    pub fn record_successful_login(&self, ip: IpAddr) {
+        self.failed_logins
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&ip.to_canonical());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(config: IpBlockConfig) -> IpBlockFilter {
+        let pool = PgPool::connect_lazy("postgres://localhost/rustodon").unwrap();
+        IpBlockFilter::new(pool, config)
+    }
+
+    fn block(
+        ip_address: &str,
+        severity: IpBlockSeverity,
+        expires_at: Option<NaiveDateTime>,
+    ) -> IpBlock {
+        IpBlock::new(
+            ip_address.parse().unwrap(),
+            severity,
+            "Test block".to_string(),
+            expires_at,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_check() {
+        let filter = filter(IpBlockConfig::default());
+        let ip = |value: &str| value.parse::<IpAddr>().unwrap();
+
+        filter.insert(&block("203.0.113.0/24", IpBlockSeverity::SignUpBlock, None));
+        filter.insert(&block("203.0.113.7/32", IpBlockSeverity::Block, None));
+        let expired = Utc::now().naive_utc() - ChronoDuration::minutes(1);
+        filter.insert(&block(
+            "198.51.100.0/24",
+            IpBlockSeverity::Block,
+            Some(expired),
+        ));
+
+        assert_eq!(
+            filter.check(ip("203.0.113.7")),
+            Some(IpBlockSeverity::Block)
+        );
+        assert_eq!(
+            filter.check(ip("203.0.113.8")),
+            Some(IpBlockSeverity::SignUpBlock)
+        );
+        assert_eq!(filter.check(ip("198.51.100.1")), None);
+        assert_eq!(filter.check(ip("192.0.2.1")), None);
+    }
+
+    #[tokio::test]
+    async fn test_check_disabled() {
+        let filter = filter(IpBlockConfig {
+            enabled: false,
+            ..Default::default()
+        });
+        filter.insert(&block("0.0.0.0/0", IpBlockSeverity::Block, None));
+
+        assert_eq!(filter.check("192.0.2.1".parse().unwrap()), None);
+    }
+
+    #[tokio::test]
+    async fn test_failed_logins_below_limit_do_not_block() {
+        let filter = filter(IpBlockConfig::default());
+        let ip = "192.0.2.1".parse().unwrap();
+
+        for _ in 1..filter.config().max_failed_attempts {
+            assert!(filter.record_failed_login(ip).await.unwrap().is_none());
+        }
+        filter.record_successful_login(ip);
+        assert!(filter.failed_logins.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_logins_block_again_after_block_expires() {
+        // This needs a migrated database, and is skipped without one
+        let database_url = std::env::var("DATABASE_URL")
+            .unwrap_or_else(|_| "postgres://localhost/rustodon".to_string());
+        let Ok(pool) = PgPool::connect(&database_url).await else {
+            return;
+        };
+        let filter = IpBlockFilter::new(
+            pool.clone(),
+            IpBlockConfig {
+                max_failed_attempts: 1,
+                block_duration: Some(3600),
+                ..Default::default()
+            },
+        );
+        let ip: IpAddr = "192.0.2.249".parse().unwrap();
+        let network = IpNetwork::from(ip);
+        sqlx::query!("DELETE FROM ip_blocks WHERE ip_address = $1", network)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let first = filter.record_failed_login(ip).await.unwrap().unwrap();
+        assert!(filter.record_failed_login(ip).await.unwrap().is_none());
+
+        sqlx::query!(
+            "UPDATE ip_blocks SET expires_at = NOW() - INTERVAL '1 minute' WHERE id = $1",
+            first.id
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let second = filter.record_failed_login(ip).await.unwrap().unwrap();
+        assert_eq!(second.id, first.id);
+        assert!(second.expires_at.unwrap() > Utc::now().naive_utc());
+        assert_eq!(filter.check(ip), Some(IpBlockSeverity::Block));
+
+        // A permanent block, as set by staff, is kept
+        sqlx::query!(
+            "UPDATE ip_blocks SET expires_at = NULL WHERE id = $1",
+            first.id
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        assert!(filter.record_failed_login(ip).await.unwrap().is_none());
+
+        sqlx::query!("DELETE FROM ip_blocks WHERE id = $1", first.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+}
@@ -9,42 +9,49 @@
 //! - CIDR range blocking
 //! - Temporary and permanent blocks
 //! - IP block management and querying
-//! - Integration with request middleware
+//! - Request middleware matching client addresses, behind trusted proxies,
+//!   against the blocks held in memory in a radix tree
+//! - Temporary blocks after repeated failed logins
 //!
 //! # Examples
 //!
-//! ```rust
-//! use rustodon_ip_blocks::IpBlockService;
+//! ```rust,ignore
+//! use rustodon_ip_blocks::{ip_block_middleware, IpBlockConfig, IpBlockFilter};
 //!
-//! let service = IpBlockService::new(pool);
-//! service.block_ip("192.168.1.1", "Spam", None).await?;
+//! let filter = IpBlockFilter::new(pool, IpBlockConfig::from_env());
+//! filter.reload().await?;
+//! let app = app.layer(axum::middleware::from_fn_with_state(filter, ip_block_middleware));
 //! ```
 //!
 //! # Dependencies
 //!
 //! - `rustodon_core`: Core types and traits
-//! - `rustodon_db`: Database operations
-//! - `rustodon_config`: Configuration management
-//! - `rustodon_logging`: Logging infrastructure
+//! - `axum`: Request middleware
 //!
 //! # Author
 //!
 //! arkSong (arksong2018@gmail.com)
 
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
-use tracing::{debug, error, info, trace};
+use sqlx::types::ipnetwork::IpNetwork;
+use tracing::warn;
 
 pub mod error;
+pub mod filter;
+pub mod middleware;
 pub mod models;
+pub mod radix;
 pub mod service;
 
 pub use error::*;
+pub use filter::*;
+pub use middleware::*;
 pub use models::*;
+pub use radix::*;
 pub use service::*;
 
 /// IP block severity levels
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum IpBlockSeverity {
     /// No action taken, just logging
     Noop,
@@ -53,7 +60,27 @@ pub enum IpBlockSeverity {
     /// Silence the account
     Silence,
     /// Block the IP address
+    #[default]
     Block,
+    /// Accounts signing up from the IP address wait for staff approval
+    SignUpRequiresApproval,
+    /// Refuse sign-ups from the IP address
+    SignUpBlock,
+}
+
+impl IpBlockSeverity {
+    /// Parses a severity by name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "noop" => Some(IpBlockSeverity::Noop),
+            "suspend" => Some(IpBlockSeverity::Suspend),
+            "silence" => Some(IpBlockSeverity::Silence),
+            "block" => Some(IpBlockSeverity::Block),
+            "sign_up_requires_approval" => Some(IpBlockSeverity::SignUpRequiresApproval),
+            "sign_up_block" => Some(IpBlockSeverity::SignUpBlock),
+            _ => None,
+        }
+    }
 }
 
 impl std::fmt::Display for IpBlockSeverity {
@@ -63,16 +90,12 @@ impl std::fmt::Display for IpBlockSeverity {
             IpBlockSeverity::Suspend => write!(f, "suspend"),
             IpBlockSeverity::Silence => write!(f, "silence"),
             IpBlockSeverity::Block => write!(f, "block"),
+            IpBlockSeverity::SignUpRequiresApproval => write!(f, "sign_up_requires_approval"),
+            IpBlockSeverity::SignUpBlock => write!(f, "sign_up_block"),
         }
     }
 }
 
-impl Default for IpBlockSeverity {
-    fn default() -> Self {
-        Self::Block
-    }
-}
-
 /// IP block configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IpBlockConfig {
@@ -84,8 +107,22 @@ pub struct IpBlockConfig {
     pub max_failed_attempts: u32,
     /// Block duration in seconds (None for permanent)
     pub block_duration: Option<u64>,
+    /// Seconds failed attempts are counted over
+    pub failed_attempts_window: u64,
+    /// Proxies whose `X-Forwarded-For` headers are trusted
+    pub trusted_proxies: Vec<IpNetwork>,
 }
 
+/// Loopback and private networks, where reverse proxies usually are
+const DEFAULT_TRUSTED_PROXIES: &[&str] = &[
+    "127.0.0.0/8",
+    "::1/128",
+    "10.0.0.0/8",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    "fc00::/7",
+];
+
 impl Default for IpBlockConfig {
     fn default() -> Self {
         Self {
@@ -93,65 +130,48 @@ impl Default for IpBlockConfig {
             default_severity: IpBlockSeverity::Block,
             max_failed_attempts: 5,
             block_duration: Some(3600), // 1 hour
+            failed_attempts_window: 600,
+            trusted_proxies: DEFAULT_TRUSTED_PROXIES
+                .iter()
+                .filter_map(|network| network.parse().ok())
+                .collect(),
         }
     }
 }
 
-/// Initialize IP blocking functionality
-///
-/// # Arguments
-///
-/// * `pool` - Database connection pool
-/// * `config` - IP blocking configuration
-///
-/// # Returns
-///
-/// Result indicating success or failure
-pub async fn init_ip_blocks(pool: &PgPool, config: &IpBlockConfig) -> Result<(), IpBlockError> {
-    info!("Initializing IP blocking functionality");
-    trace!("IP block config: {:?}", config);
-
-    // Create tables if they don't exist
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS ip_blocks (
-            id BIGSERIAL PRIMARY KEY,
-            ip_address INET NOT NULL,
-            cidr_range CIDR,
-            severity VARCHAR(20) NOT NULL DEFAULT 'block',
-            reason TEXT,
-            expires_at TIMESTAMP WITH TIME ZONE,
-            created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
-            updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
-        )
-        "#,
-    )
-    .execute(pool)
-    .await
-    .map_err(|e| {
-        error!("Failed to create ip_blocks table: {}", e);
-        IpBlockError::Database(e)
-    })?;
-
-    // Create indexes
-    sqlx::query("CREATE INDEX IF NOT EXISTS idx_ip_blocks_ip_address ON ip_blocks(ip_address)")
-        .execute(pool)
-        .await
-        .map_err(|e| {
-            error!("Failed to create ip_blocks index: {}", e);
-            IpBlockError::Database(e)
-        })?;
-
-    sqlx::query("CREATE INDEX IF NOT EXISTS idx_ip_blocks_expires_at ON ip_blocks(expires_at)")
-        .execute(pool)
-        .await
-        .map_err(|e| {
-            error!("Failed to create ip_blocks expires index: {}", e);
-            IpBlockError::Database(e)
-        })?;
-
-    debug!("IP blocking functionality initialized successfully");
-    Ok(())
+impl IpBlockConfig {
+    /// Loads the configuration from the environment
+    ///
+    /// Reads `TRUSTED_PROXY_IP`, a comma-separated list of proxy addresses
+    /// or networks, and `IP_BLOCK_MAX_FAILED_LOGINS`, falling back to the
+    /// defaults when unset
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+
+        if let Ok(proxies) = std::env::var("TRUSTED_PROXY_IP") {
+            config.trusted_proxies = proxies
+                .split(',')
+                .map(str::trim)
+                .filter(|proxy| !proxy.is_empty())
+                .filter_map(|proxy| match proxy.parse() {
+                    Ok(network) => Some(network),
+                    Err(e) => {
+                        warn!("Ignoring invalid trusted proxy {}: {}", proxy, e);
+                        None
+                    }
+                })
+                .collect();
+        }
+
+        if let Some(attempts) = std::env::var("IP_BLOCK_MAX_FAILED_LOGINS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+        {
+            config.max_failed_attempts = attempts;
+        }
+
+        config
+    }
 }
 
 #[cfg(test)]
@@ -179,4 +199,36 @@ mod tests {
         assert_eq!(IpBlockSeverity::Silence.to_string(), "silence");
         assert_eq!(IpBlockSeverity::Block.to_string(), "block");
     }
+
+    #[test]
+    fn test_ip_block_severity_parse() {
+        for severity in [
+            IpBlockSeverity::Noop,
+            IpBlockSeverity::Block,
+            IpBlockSeverity::SignUpRequiresApproval,
+            IpBlockSeverity::SignUpBlock,
+        ] {
+            assert_eq!(
+                IpBlockSeverity::parse(&severity.to_string()),
+                Some(severity)
+            );
+        }
+        assert_eq!(IpBlockSeverity::parse("none"), None);
+    }
+
+    #[test]
+    fn test_ip_block_config_trusted_proxies() {
+        let config = IpBlockConfig::default();
+        let trusted = |ip: &str| {
+            let ip = ip.parse().unwrap();
+            config
+                .trusted_proxies
+                .iter()
+                .any(|network| network.contains(ip))
+        };
+        assert!(trusted("127.0.0.1"));
+        assert!(trusted("10.1.2.3"));
+        assert!(trusted("::1"));
+        assert!(!trusted("203.0.113.7"));
+    }
 }
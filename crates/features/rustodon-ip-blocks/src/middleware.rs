@@ -0,0 +1,159 @@
+//! IP block middleware
+//!
+//! Finds the address of the client of each request, from the peer address or
+//! from the `X-Forwarded-For` header set by a trusted proxy, and matches it
+//! against the blocks of an [`IpBlockFilter`]. Blocked addresses are refused
+//! outright; the address and any sign-up block are left in the request
+//! extensions as a [`ClientIp`] for the handlers that act on them.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use sqlx::types::ipnetwork::IpNetwork;
+use std::net::{IpAddr, SocketAddr};
+use tracing::{debug, trace};
+
+use super::{IpBlockFilter, IpBlockSeverity};
+
+/// The client address of a request and its block, if any
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp {
+    /// The client address
+    pub ip: IpAddr,
+    /// Severity of the block matching the address
+    pub block: Option<IpBlockSeverity>,
+}
+
+/// Gets the address of the client of a request
+///
+/// `X-Forwarded-For` is only honoured when the peer is a trusted proxy, and
+/// is read from the right, skipping the trusted proxies it went through, so
+/// a client cannot pick its address by sending the header itself.
+///
+/// # Arguments
+///
+/// * `peer` - Address of the peer of the connection
+/// * `headers` - Headers of the request
+/// * `trusted_proxies` - Networks of the trusted proxies
+///
+/// # Returns
+///
+/// The client address
+pub fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpNetwork]) -> IpAddr {
+    let is_trusted = |ip: IpAddr| {
+        let ip = ip.to_canonical();
+        trusted_proxies.iter().any(|network| network.contains(ip))
+    };
+
+    let mut client = peer.to_canonical();
+    if !is_trusted(client) {
+        return client;
+    }
+
+    let forwarded = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect::<Vec<_>>();
+    for hop in forwarded.into_iter().rev() {
+        match hop.trim().parse::<IpAddr>() {
+            Ok(ip) => {
+                client = ip.to_canonical();
+                if !is_trusted(client) {
+                    break;
+                }
+            }
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+/// Refuses requests from blocked addresses
+///
+/// Requests from other addresses go on with a [`ClientIp`] extension. The
+/// server must be run with `into_make_service_with_connect_info` for the
+/// peer address to be known; requests without it are let through as they
+/// are.
+pub async fn ip_block_middleware(
+    State(filter): State<IpBlockFilter>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let peer = match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(addr)) => addr.ip(),
+        None => {
+            trace!("No peer address for request");
+            return Ok(next.run(request).await);
+        }
+    };
+
+    let ip = client_ip(peer, request.headers(), &filter.config().trusted_proxies);
+    let block = filter.check(ip);
+    if block == Some(IpBlockSeverity::Block) {
+        debug!("Refusing request from blocked address {}", ip);
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    request.extensions_mut().insert(ClientIp { ip, block });
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IpBlockConfig;
+
+    fn headers(forwarded_for: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", forwarded_for.parse().unwrap());
+        headers
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let trusted = IpBlockConfig::default().trusted_proxies;
+
+        // Untrusted peers cannot forward addresses
+        assert_eq!(
+            client_ip(ip("203.0.113.7"), &headers("198.51.100.1"), &trusted),
+            ip("203.0.113.7")
+        );
+        // Trusted peers can
+        assert_eq!(
+            client_ip(ip("127.0.0.1"), &headers("198.51.100.1"), &trusted),
+            ip("198.51.100.1")
+        );
+        // Addresses prepended by the client are ignored
+        assert_eq!(
+            client_ip(
+                ip("127.0.0.1"),
+                &headers("192.0.2.1, 198.51.100.1, 10.0.0.2"),
+                &trusted
+            ),
+            ip("198.51.100.1")
+        );
+        // Without the header, the peer is the client
+        assert_eq!(
+            client_ip(ip("::ffff:127.0.0.1"), &HeaderMap::new(), &trusted),
+            ip("127.0.0.1")
+        );
+        // Garbage stops the walk at the last trusted hop
+        assert_eq!(
+            client_ip(ip("127.0.0.1"), &headers("unknown, 10.0.0.2"), &trusted),
+            ip("10.0.0.2")
+        );
+    }
+}
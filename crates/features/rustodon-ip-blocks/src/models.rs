@@ -19,10 +19,8 @@ use super::{IpBlockError, IpBlockSeverity};
 pub struct IpBlock {
     /// Unique identifier
     pub id: i64,
-    /// IP address (IPv4 or IPv6)
+    /// IP address or CIDR range (IPv4 or IPv6)
     pub ip_address: IpNetwork,
-    /// CIDR range (optional)
-    pub cidr_range: Option<IpNetwork>,
     /// Block severity level
     pub severity: String,
     /// Reason for blocking
@@ -62,7 +60,6 @@ impl IpBlock {
         Self {
             id: 0, // Will be set by database
             ip_address,
-            cidr_range: None,
             severity,
             reason,
             expires_at,
@@ -90,26 +87,16 @@ impl IpBlock {
     ///
     /// The severity level
     pub fn severity(&self) -> Result<IpBlockSeverity, IpBlockError> {
-        match self.severity.as_str() {
-            "noop" => Ok(IpBlockSeverity::Noop),
-            "suspend" => Ok(IpBlockSeverity::Suspend),
-            "silence" => Ok(IpBlockSeverity::Silence),
-            "block" => Ok(IpBlockSeverity::Block),
-            _ => Err(IpBlockError::Validation(format!(
-                "Invalid severity: {}",
-                self.severity
-            ))),
-        }
+        IpBlockSeverity::parse(&self.severity)
+            .ok_or_else(|| IpBlockError::Validation(format!("Invalid severity: {}", self.severity)))
     }
 }
 
 /// Create IP block request
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateIpBlockRequest {
-    /// IP address to block
+    /// IP address or CIDR range to block
     pub ip_address: IpNetwork,
-    /// CIDR range (optional)
-    pub cidr_range: Option<IpNetwork>,
     /// Block severity
     pub severity: IpBlockSeverity,
     /// Reason for blocking
@@ -0,0 +1,180 @@
+//! Radix tree of IP ranges
+//!
+//! A binary trie keyed by the bits of IPv4 and IPv6 networks, used to match
+//! a client address against every blocked range in at most 32 or 128 steps,
+//! whatever the number of blocks.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use sqlx::types::ipnetwork::IpNetwork;
+use std::net::IpAddr;
+
+/// A node of the tree, holding the value of the network ending on it
+struct Node<T> {
+    children: [Option<Box<Node<T>>>; 2],
+    value: Option<T>,
+}
+
+impl<T> Default for Node<T> {
+    fn default() -> Self {
+        Self {
+            children: [None, None],
+            value: None,
+        }
+    }
+}
+
+/// Bits of an address from the most significant, and how many there are
+fn address_bits(ip: IpAddr) -> (u128, u8) {
+    match ip {
+        IpAddr::V4(ip) => ((u32::from(ip) as u128) << 96, 32),
+        IpAddr::V6(ip) => (u128::from(ip), 128),
+    }
+}
+
+/// Bit of an address at a depth of the tree
+fn bit(bits: u128, depth: u8) -> usize {
+    ((bits >> (127 - depth)) & 1) as usize
+}
+
+/// IP networks mapped to values
+pub struct IpRadixTree<T> {
+    v4: Node<T>,
+    v6: Node<T>,
+    count: usize,
+}
+
+impl<T> Default for IpRadixTree<T> {
+    fn default() -> Self {
+        Self {
+            v4: Node::default(),
+            v6: Node::default(),
+            count: 0,
+        }
+    }
+}
+
+impl<T> IpRadixTree<T> {
+    /// Creates an empty tree
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of networks in the tree
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Sets the value of a network
+    ///
+    /// # Returns
+    ///
+    /// The value the network had before, if any
+    pub fn insert(&mut self, network: IpNetwork, value: T) -> Option<T> {
+        let (bits, _) = address_bits(network.ip());
+        let mut node = match network {
+            IpNetwork::V4(_) => &mut self.v4,
+            IpNetwork::V6(_) => &mut self.v6,
+        };
+        for depth in 0..network.prefix() {
+            node = node.children[bit(bits, depth)].get_or_insert_with(Box::default);
+        }
+
+        let previous = node.value.replace(value);
+        if previous.is_none() {
+            self.count += 1;
+        }
+        previous
+    }
+
+    /// Gets the value of the most specific network containing an address,
+    /// among the values accepted by a predicate
+    ///
+    /// # Arguments
+    ///
+    /// * `ip` - The address
+    /// * `accept` - Whether a value may match, such as whether it expired
+    pub fn longest_match<F>(&self, ip: IpAddr, accept: F) -> Option<&T>
+    where
+        F: Fn(&T) -> bool,
+    {
+        let ip = ip.to_canonical();
+        let (bits, width) = address_bits(ip);
+        let mut node = match ip {
+            IpAddr::V4(_) => &self.v4,
+            IpAddr::V6(_) => &self.v6,
+        };
+
+        let mut found = node.value.as_ref().filter(|value| accept(value));
+        for depth in 0..width {
+            match node.children[bit(bits, depth)].as_deref() {
+                Some(child) => node = child,
+                None => break,
+            }
+            if let Some(value) = node.value.as_ref().filter(|value| accept(value)) {
+                found = Some(value);
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network(value: &str) -> IpNetwork {
+        value.parse().unwrap()
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_longest_match() {
+        let mut tree = IpRadixTree::new();
+        tree.insert(network("10.0.0.0/8"), "wide");
+        tree.insert(network("10.1.0.0/16"), "narrow");
+        tree.insert(network("10.1.2.3/32"), "host");
+        tree.insert(network("2001:db8::/32"), "v6");
+        assert_eq!(tree.count(), 4);
+
+        let any = |_: &&str| true;
+        assert_eq!(tree.longest_match(ip("10.200.0.1"), any), Some(&"wide"));
+        assert_eq!(tree.longest_match(ip("10.1.9.9"), any), Some(&"narrow"));
+        assert_eq!(tree.longest_match(ip("10.1.2.3"), any), Some(&"host"));
+        assert_eq!(tree.longest_match(ip("11.0.0.1"), any), None);
+        assert_eq!(tree.longest_match(ip("2001:db8::1"), any), Some(&"v6"));
+        assert_eq!(tree.longest_match(ip("2001:db9::1"), any), None);
+        // IPv4-mapped IPv6 addresses match IPv4 ranges
+        assert_eq!(
+            tree.longest_match(ip("::ffff:10.1.2.3"), any),
+            Some(&"host")
+        );
+    }
+
+    #[test]
+    fn test_longest_match_skips_rejected_values() {
+        let mut tree = IpRadixTree::new();
+        tree.insert(network("192.168.0.0/16"), 1);
+        tree.insert(network("192.168.1.0/24"), 2);
+
+        assert_eq!(
+            tree.longest_match(ip("192.168.1.1"), |value| *value != 2),
+            Some(&1)
+        );
+    }
+
+    #[test]
+    fn test_insert_replaces() {
+        let mut tree = IpRadixTree::new();
+        assert_eq!(tree.insert(network("0.0.0.0/0"), 1), None);
+        assert_eq!(tree.insert(network("0.0.0.0/0"), 2), Some(1));
+        assert_eq!(tree.count(), 1);
+        assert_eq!(tree.longest_match(ip("8.8.8.8"), |_| true), Some(&2));
+        assert_eq!(tree.longest_match(ip("::1"), |_| true), None);
+    }
+}
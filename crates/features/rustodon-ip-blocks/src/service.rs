@@ -40,7 +40,7 @@ impl IpBlockService {
         info!("Creating IP block for: {}", request.ip_address);
         trace!("Create request: {:?}", request);
 
-        // IP address and CIDR range are already validated as IpNetwork types
+        // The IP address or CIDR range is already validated as an IpNetwork
 
         // Calculate expiration time
         let expires_at = request
@@ -54,12 +54,11 @@ impl IpBlockService {
         let result = sqlx::query_as_unchecked!(
             IpBlock,
             r#"
-            INSERT INTO ip_blocks (ip_address, cidr_range, severity, reason, expires_at)
-            VALUES ($1, $2, $3, $4, $5)
-            RETURNING id, ip_address, cidr_range, severity, reason, expires_at, created_at, updated_at
+            INSERT INTO ip_blocks (ip_address, severity, reason, expires_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, ip_address, severity, COALESCE(reason, '') AS "reason!", expires_at, created_at, updated_at
             "#,
             request.ip_address,
-            request.cidr_range,
             request.severity.to_string(),
             reason,
             expires_at,
@@ -86,7 +85,7 @@ impl IpBlockService {
         let result = sqlx::query_as_unchecked!(
             IpBlock,
             r#"
-            SELECT id, ip_address, cidr_range, severity, reason, expires_at, created_at, updated_at
+            SELECT id, ip_address, severity, COALESCE(reason, '') AS "reason!", expires_at, created_at, updated_at
             FROM ip_blocks
             WHERE id = $1
             "#,
@@ -139,7 +138,7 @@ impl IpBlockService {
 
         let mut sql = String::from(
             r#"
-            SELECT id, ip_address, cidr_range, severity, reason, expires_at, created_at, updated_at
+            SELECT id, ip_address, severity, COALESCE(reason, '') AS reason, expires_at, created_at, updated_at
             FROM ip_blocks
             WHERE 1=1
             "#,
@@ -147,7 +146,7 @@ impl IpBlockService {
         let mut params: Vec<String> = Vec::new();
 
         if let Some(ref ip_address) = query.ip_address {
-            sql.push_str(&format!(" AND ip_address = ${}", params.len() + 1));
+            sql.push_str(&format!(" AND ip_address = ${}::INET", params.len() + 1));
             params.push(ip_address.clone());
         }
 
@@ -156,6 +155,10 @@ impl IpBlockService {
             params.push(severity.to_string());
         }
 
+        if !query.include_expired {
+            sql.push_str(" AND (expires_at IS NULL OR expires_at > NOW())");
+        }
+
         sql.push_str(" ORDER BY created_at DESC");
 
         if let Some(limit) = query.limit {
@@ -0,0 +1,333 @@
+//! Timeline read markers module for Rustodon
+//!
+//! This module stores each user's last-read position in the home and
+//! notifications timelines so that several clients can stay in sync.
+//! Every marker carries a version number that is bumped on each update;
+//! writers that pass a stale version are rejected (optimistic locking).
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_markers::{Marker, MarkerTimeline};
+//!
+//! let mut tx = pool.begin().await?;
+//! let marker = Marker::save(&mut tx, user_id, MarkerTimeline::Home, 103, None).await?;
+//! let updated =
+//!     Marker::save(&mut tx, user_id, MarkerTimeline::Home, 110, Some(marker.version)).await?;
+//! tx.commit().await?;
+//! ```
+//!
+//! # Dependencies
+//!
+//! - `rustodon_core`: Core types and traits
+//! - `sqlx`: Database queries
+//! - `serde`: Serialization
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+use tracing::{debug, info, trace};
+
+/// Custom error type for markers module
+#[derive(Error, Debug)]
+pub enum MarkersError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("Invalid timeline: {0}")]
+    InvalidTimeline(String),
+    #[error("Marker for {0} timeline was updated by another client")]
+    StaleVersion(MarkerTimeline),
+}
+
+/// Timelines that support read markers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkerTimeline {
+    /// Home timeline
+    Home,
+    /// Notifications timeline
+    Notifications,
+}
+
+impl MarkerTimeline {
+    /// All timelines that support markers
+    pub const ALL: [MarkerTimeline; 2] = [MarkerTimeline::Home, MarkerTimeline::Notifications];
+
+    /// Get the name used in the database and API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MarkerTimeline::Home => "home",
+            MarkerTimeline::Notifications => "notifications",
+        }
+    }
+}
+
+impl fmt::Display for MarkerTimeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for MarkerTimeline {
+    type Err = MarkersError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "home" => Ok(MarkerTimeline::Home),
+            "notifications" => Ok(MarkerTimeline::Notifications),
+            other => Err(MarkersError::InvalidTimeline(other.to_string())),
+        }
+    }
+}
+
+/// Read marker data structure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Marker {
+    /// Unique identifier for the marker
+    pub id: i64,
+    /// ID of the user owning the marker
+    pub user_id: i64,
+    /// Timeline the marker belongs to
+    pub timeline: MarkerTimeline,
+    /// ID of the last status or notification read
+    pub last_read_id: i64,
+    /// Version used for optimistic locking, bumped on every update
+    pub version: i32,
+    /// When the marker was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Marker {
+    /// Gets a user's markers for the given timelines
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `user_id` - ID of the user
+    /// * `timelines` - Timelines to fetch markers for
+    ///
+    /// # Returns
+    ///
+    /// Result containing the markers that exist, in no particular order
+    pub async fn get_by_user(
+        pool: &PgPool,
+        user_id: i64,
+        timelines: &[MarkerTimeline],
+    ) -> Result<Vec<Self>, MarkersError> {
+        trace!("Getting markers {:?} for user {}", timelines, user_id);
+
+        let names: Vec<String> = timelines.iter().map(|t| t.as_str().to_string()).collect();
+
+        let rows = sqlx::query_as!(
+            MarkerRow,
+            r#"
+            SELECT id, user_id, timeline, last_read_id, lock_version, updated_at
+            FROM markers
+            WHERE user_id = $1 AND timeline = ANY($2)
+            "#,
+            user_id,
+            &names
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let markers = rows
+            .into_iter()
+            .map(Marker::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        debug!("Found {} markers for user {}", markers.len(), user_id);
+        Ok(markers)
+    }
+
+    /// Finds a user's marker for one timeline
+    ///
+    /// # Arguments
+    ///
+    /// * `executor` - Database connection pool or transaction
+    /// * `user_id` - ID of the user
+    /// * `timeline` - Timeline of the marker
+    ///
+    /// # Returns
+    ///
+    /// Result containing the marker if one has been saved
+    pub async fn find<'e>(
+        executor: impl PgExecutor<'e>,
+        user_id: i64,
+        timeline: MarkerTimeline,
+    ) -> Result<Option<Self>, MarkersError> {
+        trace!("Finding {} marker for user {}", timeline, user_id);
+
+        let row = sqlx::query_as!(
+            MarkerRow,
+            r#"
+            SELECT id, user_id, timeline, last_read_id, lock_version, updated_at
+            FROM markers
+            WHERE user_id = $1 AND timeline = $2
+            "#,
+            user_id,
+            timeline.as_str()
+        )
+        .fetch_optional(executor)
+        .await?;
+
+        row.map(Marker::try_from).transpose()
+    }
+
+    /// Saves the last-read position of a timeline
+    ///
+    /// The update only succeeds if the stored version still matches the
+    /// version the caller last saw. When `expected_version` is `None` the
+    /// currently stored version is used, so concurrent writers still cannot
+    /// silently overwrite each other.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection of the transaction to run in
+    /// * `user_id` - ID of the user
+    /// * `timeline` - Timeline of the marker
+    /// * `last_read_id` - ID of the last status or notification read
+    /// * `expected_version` - Version the client last saw, if it sent one
+    ///
+    /// # Returns
+    ///
+    /// Result containing the saved marker, or `StaleVersion` on a conflict
+    pub async fn save(
+        conn: &mut PgConnection,
+        user_id: i64,
+        timeline: MarkerTimeline,
+        last_read_id: i64,
+        expected_version: Option<i32>,
+    ) -> Result<Self, MarkersError> {
+        trace!(
+            "Saving {} marker for user {} at {}",
+            timeline,
+            user_id,
+            last_read_id
+        );
+
+        let current = Self::find(&mut *conn, user_id, timeline).await?;
+
+        let row = match current {
+            None => {
+                if expected_version.is_some_and(|v| v != 0) {
+                    return Err(MarkersError::StaleVersion(timeline));
+                }
+
+                sqlx::query_as!(
+                    MarkerRow,
+                    r#"
+                    INSERT INTO markers (user_id, timeline, last_read_id)
+                    VALUES ($1, $2, $3)
+                    ON CONFLICT (user_id, timeline) DO NOTHING
+                    RETURNING id, user_id, timeline, last_read_id, lock_version, updated_at
+                    "#,
+                    user_id,
+                    timeline.as_str(),
+                    last_read_id
+                )
+                .fetch_optional(&mut *conn)
+                .await?
+            }
+            Some(current) => {
+                let version = expected_version.unwrap_or(current.version);
+
+                sqlx::query_as!(
+                    MarkerRow,
+                    r#"
+                    UPDATE markers
+                    SET last_read_id = $3, lock_version = lock_version + 1
+                    WHERE user_id = $1 AND timeline = $2 AND lock_version = $4
+                    RETURNING id, user_id, timeline, last_read_id, lock_version, updated_at
+                    "#,
+                    user_id,
+                    timeline.as_str(),
+                    last_read_id,
+                    version
+                )
+                .fetch_optional(&mut *conn)
+                .await?
+            }
+        };
+
+        let marker = Marker::try_from(row.ok_or(MarkersError::StaleVersion(timeline))?)?;
+        info!(
+            "Saved {} marker for user {} at {} (version {})",
+            timeline, user_id, marker.last_read_id, marker.version
+        );
+        Ok(marker)
+    }
+}
+
+struct MarkerRow {
+    id: i64,
+    user_id: i64,
+    timeline: String,
+    last_read_id: i64,
+    lock_version: i32,
+    updated_at: NaiveDateTime,
+}
+
+impl TryFrom<MarkerRow> for Marker {
+    type Error = MarkersError;
+
+    fn try_from(row: MarkerRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            user_id: row.user_id,
+            timeline: row.timeline.parse()?,
+            last_read_id: row.last_read_id,
+            version: row.lock_version,
+            updated_at: DateTime::from_naive_utc_and_offset(row.updated_at, Utc),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeline_round_trip() {
+        for timeline in MarkerTimeline::ALL {
+            assert_eq!(
+                timeline.as_str().parse::<MarkerTimeline>().unwrap(),
+                timeline
+            );
+        }
+        assert!("public".parse::<MarkerTimeline>().is_err());
+    }
+
+    #[test]
+    fn test_marker_from_row() {
+        let now = Utc::now().naive_utc();
+        let marker = Marker::try_from(MarkerRow {
+            id: 1,
+            user_id: 2,
+            timeline: "notifications".to_string(),
+            last_read_id: 42,
+            lock_version: 3,
+            updated_at: now,
+        })
+        .unwrap();
+
+        assert_eq!(marker.timeline, MarkerTimeline::Notifications);
+        assert_eq!(marker.last_read_id, 42);
+        assert_eq!(marker.version, 3);
+    }
+
+    #[test]
+    fn test_error_display() {
+        assert_eq!(
+            MarkersError::StaleVersion(MarkerTimeline::Home).to_string(),
+            "Marker for home timeline was updated by another client"
+        );
+    }
+}
@@ -1,5 +1,317 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
+//! Mentions module for Rustodon
+//!
+//! This module finds `@user` and `@user@domain` mentions in status text,
+//! resolves them to known accounts and records which accounts a status
+//! mentions. Mentions of unknown accounts are left as plain text.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_mentions::{extract_mentions, Mention};
+//!
+//! let tokens = extract_mentions("Hello @alice and @bob@remote.example!");
+//! let accounts = Mention::resolve(&mut tx, &tokens, "rustodon.example").await?;
+//! Mention::create_many(&mut tx, status_id, &accounts).await?;
+//! ```
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::OnceLock;
+use thiserror::Error;
+use tracing::{debug, trace};
+
+/// Custom error type for mentions module
+#[derive(Error, Debug)]
+pub enum MentionsError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// A mention found in status text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MentionToken {
+    /// Username, without the leading `@`
+    pub username: String,
+    /// Domain of a remote account, as written
+    pub domain: Option<String>,
+    /// Byte range of the whole mention, `@` included
+    pub range: Range<usize>,
+}
+
+impl MentionToken {
+    /// The mention as written, without the leading `@`
+    pub fn acct(&self) -> String {
+        match &self.domain {
+            Some(domain) => format!("{}@{}", self.username, domain),
+            None => self.username.clone(),
+        }
+    }
+}
+
+/// A mentioned account
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MentionedAccount {
+    /// ID of the account
+    pub id: i64,
+    /// Username of the account
+    pub username: String,
+    /// Domain of a remote account, None for local accounts
+    pub domain: Option<String>,
+    /// `username` for local accounts, `username@domain` for remote ones
+    pub acct: String,
+    /// Profile page of the account
+    pub url: String,
+}
+
+impl MentionedAccount {
+    fn new(
+        id: i64,
+        username: String,
+        domain: Option<String>,
+        uri: Option<String>,
+        local_domain: &str,
+    ) -> Self {
+        let (acct, url) = match &domain {
+            Some(domain) => (
+                format!("{}@{}", username, domain),
+                uri.unwrap_or_else(|| format!("https://{}/@{}", domain, username)),
+            ),
+            None => (
+                username.clone(),
+                format!("https://{}/@{}", local_domain, username),
+            ),
+        };
+        Self {
+            id,
+            username,
+            domain,
+            acct,
+            url,
+        }
+    }
+}
+
+fn mention_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"(?:^|[^/\w])(@([A-Za-z0-9_]+(?:[A-Za-z0-9_.-]*[A-Za-z0-9_])?)(?:@([\w.-]*\w))?)",
+        )
+        .expect("valid regex")
+    })
+}
+
+/// Finds the mentions in status text
+///
+/// Mentions preceded by a word character or a slash, as in e-mail
+/// addresses and URLs, are not mentions.
+///
+/// # Arguments
+///
+/// * `text` - Text as typed by the user
+///
+/// # Returns
+///
+/// Mentions in order of appearance
+pub fn extract_mentions(text: &str) -> Vec<MentionToken> {
+    mention_regex()
+        .captures_iter(text)
+        .map(|caps| {
+            let whole = caps.get(1).expect("mention group");
+            MentionToken {
+                username: caps[2].to_string(),
+                domain: caps.get(3).map(|m| m.as_str().to_ascii_lowercase()),
+                range: whole.range(),
+            }
+        })
+        .collect()
+}
+
+/// A row of the mentions table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mention {
+    pub id: i64,
+    pub status_id: i64,
+    pub account_id: i64,
+}
+
+impl Mention {
+    /// Resolves mentions to known accounts
+    ///
+    /// Mentions without a domain, or with the local domain, refer to local
+    /// accounts. Each account is returned once even if mentioned several
+    /// times, in order of first appearance.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx` - Transaction the status is being created in
+    /// * `tokens` - Mentions found in the status text
+    /// * `local_domain` - Domain of this instance
+    pub async fn resolve(
+        tx: &mut Transaction<'_, Postgres>,
+        tokens: &[MentionToken],
+        local_domain: &str,
+    ) -> Result<Vec<MentionedAccount>, MentionsError> {
+        let mut accounts: Vec<MentionedAccount> = Vec::new();
+        for token in tokens {
+            let domain = token
+                .domain
+                .as_deref()
+                .filter(|domain| !domain.eq_ignore_ascii_case(local_domain));
+            let row = sqlx::query!(
+                r#"
+                SELECT id, username, domain, uri
+                FROM users
+                WHERE LOWER(username) = LOWER($1)
+                  AND domain IS NOT DISTINCT FROM $2
+                "#,
+                token.username,
+                domain
+            )
+            .fetch_optional(&mut **tx)
+            .await?;
+
+            let Some(row) = row else {
+                trace!("Mention of unknown account @{}", token.acct());
+                continue;
+            };
+            if accounts.iter().any(|account| account.id == row.id) {
+                continue;
+            }
+            accounts.push(MentionedAccount::new(
+                row.id,
+                row.username,
+                row.domain,
+                row.uri,
+                local_domain,
+            ));
+        }
+
+        Ok(accounts)
+    }
+
+    /// Records the accounts a status mentions
+    ///
+    /// # Arguments
+    ///
+    /// * `tx` - Transaction the status is being created in
+    /// * `status_id` - ID of the status
+    /// * `accounts` - Mentioned accounts
+    pub async fn create_many(
+        tx: &mut Transaction<'_, Postgres>,
+        status_id: i64,
+        accounts: &[MentionedAccount],
+    ) -> Result<(), MentionsError> {
+        if accounts.is_empty() {
+            return Ok(());
+        }
+
+        let account_ids: Vec<i64> = accounts.iter().map(|account| account.id).collect();
+        sqlx::query!(
+            r#"
+            INSERT INTO mentions (status_id, account_id)
+            SELECT $1, UNNEST($2::BIGINT[])
+            ON CONFLICT (status_id, account_id) DO NOTHING
+            "#,
+            status_id,
+            &account_ids
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        debug!(
+            "Recorded {} mentions for status {}",
+            account_ids.len(),
+            status_id
+        );
+        Ok(())
+    }
+
+    /// Gets the accounts mentioned by the given statuses, keyed by status ID
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `status_ids` - IDs of the statuses
+    /// * `local_domain` - Domain of this instance
+    pub async fn get_by_status_ids(
+        pool: &PgPool,
+        status_ids: &[i64],
+        local_domain: &str,
+    ) -> Result<HashMap<i64, Vec<MentionedAccount>>, MentionsError> {
+        if status_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT m.status_id, u.id, u.username, u.domain, u.uri
+            FROM mentions m
+            JOIN users u ON u.id = m.account_id
+            WHERE m.status_id = ANY($1)
+            ORDER BY m.id
+            "#,
+            status_ids
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut mentions: HashMap<i64, Vec<MentionedAccount>> = HashMap::new();
+        for row in rows {
+            mentions
+                .entry(row.status_id)
+                .or_default()
+                .push(MentionedAccount::new(
+                    row.id,
+                    row.username,
+                    row.domain,
+                    row.uri,
+                    local_domain,
+                ));
+        }
+
+        Ok(mentions)
+    }
+
+    /// Gets the IDs of the accounts a status mentions
+    pub async fn account_ids(pool: &PgPool, status_id: i64) -> Result<Vec<i64>, MentionsError> {
+        let ids = sqlx::query_scalar!(
+            "SELECT account_id FROM mentions WHERE status_id = $1 ORDER BY id",
+            status_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(ids)
+    }
+
+    /// Whether a status mentions an account
+    pub async fn exists(
+        pool: &PgPool,
+        status_id: i64,
+        account_id: i64,
+    ) -> Result<bool, MentionsError> {
+        let exists = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM mentions WHERE status_id = $1 AND account_id = $2
+            ) AS "exists!"
+            "#,
+            status_id,
+            account_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(exists)
+    }
 }
 
 #[cfg(test)]
@@ -7,8 +319,22 @@ mod tests {
     use super::*;
 
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn test_extract_mentions() {
+        let text = "@alice hi, cc @Bob@Remote.Example. mail me@example.com or see https://x.example/@carol";
+        let tokens = extract_mentions(text);
+
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[0].username, "alice");
+        assert_eq!(tokens[0].domain, None);
+        assert_eq!(&text[tokens[0].range.clone()], "@alice");
+        assert_eq!(tokens[1].acct(), "Bob@remote.example");
+        assert_eq!(&text[tokens[1].range.clone()], "@Bob@Remote.Example");
+    }
+
+    #[test]
+    fn test_extract_mentions_trailing_punctuation() {
+        let tokens = extract_mentions("(@dave.) @erin_!");
+        let accts: Vec<String> = tokens.iter().map(MentionToken::acct).collect();
+        assert_eq!(accts, vec!["dave", "erin_"]);
     }
 }
@@ -0,0 +1,392 @@
+//! Grouped notifications
+//!
+//! Favourites and reblogs of the same status, and follows received on the
+//! same day, share a group key. Groups are built at read time, so the same
+//! notifications can also be listed individually, and notifications that
+//! can't be grouped get an `ungrouped-<id>` key of their own.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::{NotificationType, NotificationsError};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::{debug, info, trace};
+
+/// Number of accounts shown as a sample of each group
+pub const SAMPLE_ACCOUNTS_SIZE: usize = 8;
+
+/// Notification types that can be grouped
+pub const GROUPABLE_TYPES: [NotificationType; 4] = [
+    NotificationType::Favourite,
+    NotificationType::Reblog,
+    NotificationType::Follow,
+    NotificationType::AdminSignup,
+];
+
+/// Computes the group key of a new notification
+///
+/// # Arguments
+///
+/// * `notification_type` - Type of the notification
+/// * `status_id` - Status the notification is about, if any
+/// * `created_at` - When the notification is created
+///
+/// # Returns
+///
+/// The group key, or None if the notification is never grouped
+pub fn group_key_for(
+    notification_type: &NotificationType,
+    status_id: Option<i64>,
+    created_at: DateTime<Utc>,
+) -> Option<String> {
+    match (notification_type, status_id) {
+        (NotificationType::Favourite | NotificationType::Reblog, Some(status_id)) => {
+            Some(format!("{}-{}", notification_type, status_id))
+        }
+        (NotificationType::Follow | NotificationType::AdminSignup, _) => Some(format!(
+            "{}-{}",
+            notification_type,
+            created_at.timestamp() / 86_400
+        )),
+        _ => None,
+    }
+}
+
+/// Filters and pagination for listing notification groups
+#[derive(Debug, Clone, Default)]
+pub struct GroupQuery {
+    /// Maximum number of groups to return
+    pub limit: Option<i64>,
+    /// Only include notifications older than this ID
+    pub max_id: Option<i64>,
+    /// Only include notifications newer than this ID
+    pub since_id: Option<i64>,
+    /// Only include these notification types
+    pub types: Option<Vec<NotificationType>>,
+    /// Leave out these notification types
+    pub exclude_types: Vec<NotificationType>,
+    /// Types that may be grouped; defaults to every groupable type
+    pub grouped_types: Option<Vec<NotificationType>>,
+}
+
+/// Several notifications shown as one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationGroup {
+    /// Key shared by the notifications in the group
+    pub group_key: String,
+    /// Type of the notifications in the group
+    pub notification_type: NotificationType,
+    /// Number of notifications in the group
+    pub notifications_count: i64,
+    /// ID of the newest notification in the group
+    pub most_recent_notification_id: i64,
+    /// ID of the oldest notification of the group in this page
+    pub page_min_id: i64,
+    /// ID of the newest notification of the group in this page
+    pub page_max_id: i64,
+    /// When the newest notification of the group was created
+    pub latest_page_notification_at: DateTime<Utc>,
+    /// Most recent distinct accounts that triggered the notifications
+    pub sample_account_ids: Vec<i64>,
+    /// Status the notifications are about, if any
+    pub status_id: Option<i64>,
+}
+
+impl NotificationGroup {
+    /// Lists an account's notification groups, newest first
+    ///
+    /// Filtered notifications are left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the account
+    /// * `query` - Filters and pagination
+    ///
+    /// # Returns
+    ///
+    /// Result containing the groups or an error
+    pub async fn get_by_account(
+        pool: &PgPool,
+        account_id: i64,
+        query: GroupQuery,
+    ) -> Result<Vec<Self>, NotificationsError> {
+        trace!("Getting notification groups for account {}", account_id);
+
+        let limit = query.limit.unwrap_or(40).clamp(1, 80);
+        let types = query.types.map(|types| type_names(&types));
+        let exclude_types = type_names(&query.exclude_types);
+        let grouped_types = type_names(
+            query
+                .grouped_types
+                .as_deref()
+                .unwrap_or(GROUPABLE_TYPES.as_slice()),
+        );
+
+        let rows = sqlx::query_as!(
+            NotificationGroupRow,
+            r#"
+            SELECT
+                CASE
+                    WHEN group_key IS NOT NULL AND notification_type = ANY($6) THEN group_key
+                    ELSE 'ungrouped-' || id
+                END AS "group_key!",
+                (array_agg(notification_type ORDER BY id DESC))[1] AS "notification_type!",
+                COUNT(*) AS "notifications_count!",
+                MAX(id) AS "page_max_id!",
+                MIN(id) AS "page_min_id!",
+                MAX(created_at) AS "latest_page_notification_at!",
+                array_agg(from_account_id ORDER BY id DESC) AS "account_ids!",
+                (array_agg(status_id ORDER BY id DESC))[1] AS status_id
+            FROM notifications
+            WHERE account_id = $1
+              AND NOT filtered
+              AND ($2::TEXT[] IS NULL OR notification_type = ANY($2))
+              AND NOT (notification_type = ANY($3))
+              AND ($4::BIGINT IS NULL OR id < $4)
+              AND ($5::BIGINT IS NULL OR id > $5)
+            GROUP BY 1
+            ORDER BY MAX(id) DESC
+            LIMIT $7
+            "#,
+            account_id,
+            types.as_deref(),
+            &exclude_types,
+            query.max_id,
+            query.since_id,
+            &grouped_types,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let groups = rows
+            .into_iter()
+            .map(NotificationGroup::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        debug!(
+            "Retrieved {} notification groups for account {}",
+            groups.len(),
+            account_id
+        );
+        Ok(groups)
+    }
+
+    /// Gets one notification group
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the account
+    /// * `group_key` - Key of the group
+    ///
+    /// # Returns
+    ///
+    /// Result containing the group or an error
+    pub async fn get(
+        pool: &PgPool,
+        account_id: i64,
+        group_key: &str,
+    ) -> Result<Self, NotificationsError> {
+        trace!(
+            "Getting notification group {} for account {}",
+            group_key,
+            account_id
+        );
+
+        let row = sqlx::query_as!(
+            NotificationGroupRow,
+            r#"
+            SELECT
+                $2::TEXT AS "group_key!",
+                (array_agg(notification_type ORDER BY id DESC))[1] AS "notification_type!",
+                COUNT(*) AS "notifications_count!",
+                MAX(id) AS "page_max_id!",
+                MIN(id) AS "page_min_id!",
+                MAX(created_at) AS "latest_page_notification_at!",
+                array_agg(from_account_id ORDER BY id DESC) AS "account_ids!",
+                (array_agg(status_id ORDER BY id DESC))[1] AS status_id
+            FROM notifications
+            WHERE account_id = $1
+              AND NOT filtered
+              AND (group_key = $2 OR 'ungrouped-' || id = $2)
+            GROUP BY account_id
+            "#,
+            account_id,
+            group_key
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| NotificationsError::NotificationGroupNotFound(group_key.to_string()))?;
+
+        NotificationGroup::try_from(row)
+    }
+
+    /// Deletes every notification in a group
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the account
+    /// * `group_key` - Key of the group
+    ///
+    /// # Returns
+    ///
+    /// Result indicating success or failure
+    pub async fn dismiss(
+        pool: &PgPool,
+        account_id: i64,
+        group_key: &str,
+    ) -> Result<(), NotificationsError> {
+        trace!(
+            "Dismissing notification group {} for account {}",
+            group_key,
+            account_id
+        );
+
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM notifications
+            WHERE account_id = $1 AND (group_key = $2 OR 'ungrouped-' || id = $2)
+            "#,
+            account_id,
+            group_key
+        )
+        .execute(pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(NotificationsError::NotificationGroupNotFound(
+                group_key.to_string(),
+            ));
+        }
+
+        info!(
+            "Dismissed {} notifications in group {} for account {}",
+            result.rows_affected(),
+            group_key,
+            account_id
+        );
+        Ok(())
+    }
+
+    /// Counts the groups with unread notifications
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the account
+    ///
+    /// # Returns
+    ///
+    /// Result containing the count or an error
+    pub async fn unread_count(pool: &PgPool, account_id: i64) -> Result<i64, NotificationsError> {
+        let grouped_types = type_names(&GROUPABLE_TYPES);
+
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(DISTINCT CASE
+                WHEN group_key IS NOT NULL AND notification_type = ANY($2) THEN group_key
+                ELSE 'ungrouped-' || id
+            END) AS "count!"
+            FROM notifications
+            WHERE account_id = $1 AND NOT filtered AND NOT read
+            "#,
+            account_id,
+            &grouped_types
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(count)
+    }
+}
+
+fn type_names(types: &[NotificationType]) -> Vec<String> {
+    types.iter().map(|t| t.to_string()).collect()
+}
+
+/// Keeps the first occurrence of each account, up to the sample size
+fn sample_accounts(account_ids: Vec<i64>) -> Vec<i64> {
+    let mut sample = Vec::with_capacity(SAMPLE_ACCOUNTS_SIZE);
+    for id in account_ids {
+        if !sample.contains(&id) {
+            sample.push(id);
+            if sample.len() == SAMPLE_ACCOUNTS_SIZE {
+                break;
+            }
+        }
+    }
+    sample
+}
+
+struct NotificationGroupRow {
+    group_key: String,
+    notification_type: String,
+    notifications_count: i64,
+    page_max_id: i64,
+    page_min_id: i64,
+    latest_page_notification_at: NaiveDateTime,
+    account_ids: Vec<i64>,
+    status_id: Option<i64>,
+}
+
+impl TryFrom<NotificationGroupRow> for NotificationGroup {
+    type Error = NotificationsError;
+
+    fn try_from(row: NotificationGroupRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            group_key: row.group_key,
+            notification_type: row.notification_type.parse()?,
+            notifications_count: row.notifications_count,
+            most_recent_notification_id: row.page_max_id,
+            page_min_id: row.page_min_id,
+            page_max_id: row.page_max_id,
+            latest_page_notification_at: DateTime::from_naive_utc_and_offset(
+                row.latest_page_notification_at,
+                Utc,
+            ),
+            sample_account_ids: sample_accounts(row.account_ids),
+            status_id: row.status_id,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_group_key_for() {
+        let at = Utc.with_ymd_and_hms(2025, 7, 14, 12, 0, 0).unwrap();
+
+        assert_eq!(
+            group_key_for(&NotificationType::Favourite, Some(42), at).as_deref(),
+            Some("favourite-42")
+        );
+        assert_eq!(
+            group_key_for(&NotificationType::Follow, None, at),
+            group_key_for(
+                &NotificationType::Follow,
+                None,
+                at + chrono::Duration::hours(6)
+            )
+        );
+        assert!(group_key_for(&NotificationType::Mention, Some(42), at).is_none());
+        assert!(group_key_for(&NotificationType::Reblog, None, at).is_none());
+    }
+
+    #[test]
+    fn test_sample_accounts() {
+        assert_eq!(sample_accounts(vec![3, 2, 3, 1, 2]), vec![3, 2, 1]);
+        assert_eq!(
+            sample_accounts((0..20).collect()).len(),
+            SAMPLE_ACCOUNTS_SIZE
+        );
+    }
+}
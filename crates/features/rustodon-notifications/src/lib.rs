@@ -4,6 +4,12 @@
 //! It handles creating, managing, and delivering notifications to users
 //! with proper database operations and validation.
 //!
+//! Notifications about the same thing (favourites and reblogs of one
+//! status, follows on one day) share a group key so clients can show them
+//! as a single [`NotificationGroup`]. Each recipient's
+//! [`NotificationPolicy`] may hold notifications back; those are collected
+//! per sender as [`NotificationRequest`]s until accepted or dismissed.
+//!
 //! # Examples
 //!
 //! ```rust
@@ -26,13 +32,21 @@
 //!
 //! arkSong (arksong2018@gmail.com)
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::{PgPool, Row};
+use sqlx::PgPool;
 use std::str::FromStr;
 use thiserror::Error;
 use tracing::{debug, error, info, trace};
 
+pub mod groups;
+pub mod policy;
+
+pub use groups::{group_key_for, GroupQuery, NotificationGroup};
+pub use policy::{
+    NotificationPolicy, NotificationRequest, PolicySummary, UpdateNotificationPolicyRequest,
+};
+
 /// Custom error type for notifications module
 #[derive(Error, Debug)]
 pub enum NotificationsError {
@@ -46,6 +60,10 @@ pub enum NotificationsError {
     AccountNotFound(i64),
     #[error("Status not found: {0}")]
     StatusNotFound(i64),
+    #[error("Notification group not found: {0}")]
+    NotificationGroupNotFound(String),
+    #[error("Notification request not found: {0}")]
+    NotificationRequestNotFound(i64),
     #[error("Validation error: {0}")]
     Validation(String),
     #[error("Internal error: {0}")]
@@ -132,8 +150,14 @@ pub struct Notification {
     pub status_id: Option<i64>,
     /// ID of the poll related to this notification (if any)
     pub poll_id: Option<i64>,
+    /// ID of the report related to this notification (if any)
+    pub report_id: Option<i64>,
     /// Whether the notification has been read
     pub read: bool,
+    /// Key shared by notifications shown as one group (None if never grouped)
+    pub group_key: Option<String>,
+    /// Whether the recipient's notification policy held the notification back
+    pub filtered: bool,
     /// When the notification was created
     pub created_at: DateTime<Utc>,
     /// When the notification was last updated
@@ -153,6 +177,8 @@ pub struct CreateNotificationRequest {
     pub status_id: Option<i64>,
     /// ID of the poll related to this notification (if any)
     pub poll_id: Option<i64>,
+    /// ID of the report related to this notification (if any)
+    pub report_id: Option<i64>,
 }
 
 /// Update notification request
@@ -245,33 +271,46 @@ impl Notification {
             }
         }
 
+        // Apply the recipient's notification policy
+        let policy = NotificationPolicy::get_or_create(pool, request.account_id).await?;
+        let filtered = policy.should_filter(pool, &request).await?;
+        let group_key = group_key_for(&request.notification_type, request.status_id, Utc::now());
+
         // Insert notification
-        let notification_row = sqlx::query!(
+        let mut tx = pool.begin().await?;
+
+        let notification_row = sqlx::query_as!(
+            NotificationRow,
             r#"
-            INSERT INTO notifications (account_id, from_account_id, notification_type, status_id, poll_id, read)
-            VALUES ($1, $2, $3, $4, $5, false)
-            RETURNING id, account_id, from_account_id, notification_type, status_id, poll_id, read, created_at, updated_at
+            INSERT INTO notifications (account_id, from_account_id, notification_type, status_id, poll_id, report_id, read, group_key, filtered)
+            VALUES ($1, $2, $3, $4, $5, $6, false, $7, $8)
+            RETURNING id, account_id, from_account_id, notification_type, status_id, poll_id, report_id, read, group_key, filtered, created_at, updated_at
             "#,
             request.account_id,
             request.from_account_id,
             request.notification_type.to_string(),
             request.status_id,
-            request.poll_id
+            request.poll_id,
+            request.report_id,
+            group_key,
+            filtered
         )
-        .fetch_one(pool)
+        .fetch_one(&mut *tx)
         .await?;
 
-        let notification = Notification {
-            id: notification_row.id,
-            account_id: notification_row.account_id,
-            from_account_id: Some(notification_row.from_account_id),
-            notification_type: NotificationType::from_str(&notification_row.notification_type)?,
-            status_id: notification_row.status_id,
-            poll_id: notification_row.poll_id,
-            read: notification_row.read,
-            created_at: DateTime::from_naive_utc_and_offset(notification_row.created_at, Utc),
-            updated_at: DateTime::from_naive_utc_and_offset(notification_row.updated_at, Utc),
-        };
+        let notification = Notification::try_from(notification_row)?;
+
+        if let (true, Some(from_account_id)) = (filtered, notification.from_account_id) {
+            NotificationRequest::record(
+                &mut tx,
+                notification.account_id,
+                from_account_id,
+                notification.id,
+            )
+            .await?;
+        }
+
+        tx.commit().await?;
 
         info!(
             "Created notification with id: {} for account {} with type: {}",
@@ -296,9 +335,10 @@ impl Notification {
     ) -> Result<Self, NotificationsError> {
         trace!("Getting notification by id: {}", notification_id);
 
-        let notification_row = sqlx::query!(
+        let notification_row = sqlx::query_as!(
+            NotificationRow,
             r#"
-            SELECT id, account_id, from_account_id, notification_type, status_id, poll_id, read, created_at, updated_at
+            SELECT id, account_id, from_account_id, notification_type, status_id, poll_id, report_id, read, group_key, filtered, created_at, updated_at
             FROM notifications
             WHERE id = $1
             "#,
@@ -308,17 +348,7 @@ impl Notification {
         .await?
         .ok_or(NotificationsError::NotificationNotFound(notification_id))?;
 
-        let notification = Notification {
-            id: notification_row.id,
-            account_id: notification_row.account_id,
-            from_account_id: Some(notification_row.from_account_id),
-            notification_type: NotificationType::from_str(&notification_row.notification_type)?,
-            status_id: notification_row.status_id,
-            poll_id: notification_row.poll_id,
-            read: notification_row.read,
-            created_at: DateTime::from_naive_utc_and_offset(notification_row.created_at, Utc),
-            updated_at: DateTime::from_naive_utc_and_offset(notification_row.updated_at, Utc),
-        };
+        let notification = Notification::try_from(notification_row)?;
 
         debug!("Retrieved notification with id: {}", notification.id);
         Ok(notification)
@@ -349,61 +379,40 @@ impl Notification {
         trace!("Getting notifications for account: {}", account_id);
 
         let limit = limit.unwrap_or(20).min(40);
-        let mut query = String::from(
-            "SELECT id, account_id, from_account_id, notification_type, status_id, poll_id, read, created_at, updated_at FROM notifications WHERE account_id = $1"
-        );
-        let mut params: Vec<String> = vec![account_id.to_string()];
-        let mut param_count = 1;
-
-        // Add exclude types filter
-        if let Some(exclude_types) = exclude_types {
-            let exclude_strings: Vec<String> =
-                exclude_types.iter().map(|t| t.to_string()).collect();
-            param_count += 1;
-            query.push_str(&format!(" AND notification_type NOT IN (${})", param_count));
-            params.push(format!("{{{}}}", exclude_strings.join(",")));
-        }
-
-        // Add since_id filter
-        if let Some(since_id) = since_id {
-            param_count += 1;
-            query.push_str(&format!(" AND id > ${}", param_count));
-            params.push(since_id.to_string());
-        }
-
-        // Add max_id filter
-        if let Some(max_id) = max_id {
-            param_count += 1;
-            query.push_str(&format!(" AND id < ${}", param_count));
-            params.push(max_id.to_string());
-        }
-
-        query.push_str(" ORDER BY id DESC");
-        param_count += 1;
-        query.push_str(&format!(" LIMIT ${}", param_count));
-        params.push(limit.to_string());
+        let exclude_types: Vec<String> = exclude_types
+            .unwrap_or_default()
+            .iter()
+            .map(|t| t.to_string())
+            .collect();
 
-        // Execute query
-        let notification_rows = sqlx::query(&query).bind(&params).fetch_all(pool).await?;
+        // Notifications held back by the notification policy only show up
+        // through notification requests
+        let notification_rows = sqlx::query_as!(
+            NotificationRow,
+            r#"
+            SELECT id, account_id, from_account_id, notification_type, status_id, poll_id, report_id, read, group_key, filtered, created_at, updated_at
+            FROM notifications
+            WHERE account_id = $1
+              AND NOT filtered
+              AND NOT (notification_type = ANY($2))
+              AND ($3::BIGINT IS NULL OR id > $3)
+              AND ($4::BIGINT IS NULL OR id < $4)
+            ORDER BY id DESC
+            LIMIT $5
+            "#,
+            account_id,
+            &exclude_types,
+            since_id,
+            max_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
 
-        let notifications: Vec<Notification> = notification_rows
+        let notifications = notification_rows
             .into_iter()
-            .map(|row| {
-                let notification_type = NotificationType::from_str(row.get("notification_type"))
-                    .unwrap_or(NotificationType::Follow);
-                Notification {
-                    id: row.get("id"),
-                    account_id: row.get("account_id"),
-                    from_account_id: Some(row.get("from_account_id")),
-                    notification_type,
-                    status_id: row.get("status_id"),
-                    poll_id: row.get("poll_id"),
-                    read: row.get("read"),
-                    created_at: DateTime::from_naive_utc_and_offset(row.get("created_at"), Utc),
-                    updated_at: DateTime::from_naive_utc_and_offset(row.get("updated_at"), Utc),
-                }
-            })
-            .collect();
+            .map(Notification::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
 
         debug!(
             "Retrieved {} notifications for account {}",
@@ -437,13 +446,14 @@ impl Notification {
             account_id
         );
 
-        let notification_row = sqlx::query!(
+        let notification_row = sqlx::query_as!(
+            NotificationRow,
             r#"
             UPDATE notifications
             SET read = COALESCE($3, read),
                 updated_at = now()
             WHERE id = $1 AND account_id = $2
-            RETURNING id, account_id, from_account_id, notification_type, status_id, poll_id, read, created_at, updated_at
+            RETURNING id, account_id, from_account_id, notification_type, status_id, poll_id, report_id, read, group_key, filtered, created_at, updated_at
             "#,
             notification_id,
             account_id,
@@ -453,17 +463,7 @@ impl Notification {
         .await?
         .ok_or(NotificationsError::NotificationNotFound(notification_id))?;
 
-        let notification = Notification {
-            id: notification_row.id,
-            account_id: notification_row.account_id,
-            from_account_id: Some(notification_row.from_account_id),
-            notification_type: NotificationType::from_str(&notification_row.notification_type)?,
-            status_id: notification_row.status_id,
-            poll_id: notification_row.poll_id,
-            read: notification_row.read,
-            created_at: DateTime::from_naive_utc_and_offset(notification_row.created_at, Utc),
-            updated_at: DateTime::from_naive_utc_and_offset(notification_row.updated_at, Utc),
-        };
+        let notification = Notification::try_from(notification_row)?;
 
         info!(
             "Updated notification with id: {} for account {}",
@@ -593,6 +593,42 @@ impl Notification {
     }
 }
 
+struct NotificationRow {
+    id: i64,
+    account_id: i64,
+    from_account_id: i64,
+    notification_type: String,
+    status_id: Option<i64>,
+    poll_id: Option<i64>,
+    report_id: Option<i64>,
+    read: bool,
+    group_key: Option<String>,
+    filtered: bool,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+}
+
+impl TryFrom<NotificationRow> for Notification {
+    type Error = NotificationsError;
+
+    fn try_from(row: NotificationRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            account_id: row.account_id,
+            from_account_id: Some(row.from_account_id),
+            notification_type: NotificationType::from_str(&row.notification_type)?,
+            status_id: row.status_id,
+            poll_id: row.poll_id,
+            report_id: row.report_id,
+            read: row.read,
+            group_key: row.group_key,
+            filtered: row.filtered,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+            updated_at: DateTime::from_naive_utc_and_offset(row.updated_at, Utc),
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -608,7 +644,10 @@ mod tests {
             notification_type: NotificationType::Follow,
             status_id: None,
             poll_id: None,
+            report_id: None,
             read: false,
+            group_key: None,
+            filtered: false,
             created_at: Utc::now(),
             updated_at: Utc::now(),
         };
@@ -0,0 +1,685 @@
+//! Notification policies and filtered notification requests
+//!
+//! A policy decides which incoming notifications are held back: from
+//! accounts the recipient doesn't follow, accounts that don't follow the
+//! recipient, recently created accounts, or private mentions from
+//! strangers. Held-back notifications are grouped per sender into a
+//! [`NotificationRequest`]; accepting one releases its notifications and
+//! lets the sender through from then on, dismissing it hides it.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::{CreateNotificationRequest, NotificationType, NotificationsError};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::{debug, info, trace};
+
+/// Accounts younger than this count as new accounts
+const NEW_ACCOUNT_AGE_DAYS: i64 = 30;
+
+/// Notification types that are always delivered
+const NON_FILTERABLE_TYPES: [NotificationType; 4] = [
+    NotificationType::Poll,
+    NotificationType::Update,
+    NotificationType::AdminSignup,
+    NotificationType::AdminReport,
+];
+
+/// Per-user notification policy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationPolicy {
+    /// Unique identifier for the policy
+    pub id: i64,
+    /// ID of the account the policy belongs to
+    pub account_id: i64,
+    /// Hold back notifications from accounts the user doesn't follow
+    pub filter_not_following: bool,
+    /// Hold back notifications from accounts that don't follow the user
+    pub filter_not_followers: bool,
+    /// Hold back notifications from accounts created in the last 30 days
+    pub filter_new_accounts: bool,
+    /// Hold back private mentions from accounts the user doesn't follow
+    pub filter_private_mentions: bool,
+    /// When the policy was created
+    pub created_at: DateTime<Utc>,
+    /// When the policy was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Update notification policy request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateNotificationPolicyRequest {
+    pub filter_not_following: Option<bool>,
+    pub filter_not_followers: Option<bool>,
+    pub filter_new_accounts: Option<bool>,
+    pub filter_private_mentions: Option<bool>,
+}
+
+/// What the policy needs to know about the sender of a notification
+#[derive(Debug, Clone, Copy)]
+pub struct SenderFacts {
+    /// The recipient follows the sender
+    pub following: bool,
+    /// The sender follows the recipient
+    pub followed_by: bool,
+    /// When the sender's account was created
+    pub created_at: DateTime<Utc>,
+    /// The notification is a mention in a direct status
+    pub private_mention: bool,
+}
+
+/// Pending request counts shown alongside the policy
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct PolicySummary {
+    /// Senders with pending filtered notifications
+    pub pending_requests_count: i64,
+    /// Filtered notifications waiting in pending requests
+    pub pending_notifications_count: i64,
+}
+
+impl NotificationPolicy {
+    /// Gets an account's policy, creating the default policy if needed
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the account
+    ///
+    /// # Returns
+    ///
+    /// Result containing the policy or an error
+    pub async fn get_or_create(pool: &PgPool, account_id: i64) -> Result<Self, NotificationsError> {
+        trace!("Getting notification policy for account {}", account_id);
+
+        let row = sqlx::query_as!(
+            NotificationPolicyRow,
+            r#"
+            INSERT INTO notification_policies (account_id)
+            VALUES ($1)
+            ON CONFLICT (account_id) DO UPDATE SET account_id = EXCLUDED.account_id
+            RETURNING id, account_id, filter_not_following, filter_not_followers,
+                      filter_new_accounts, filter_private_mentions, created_at, updated_at
+            "#,
+            account_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(NotificationPolicy::from(row))
+    }
+
+    /// Updates an account's policy
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the account
+    /// * `request` - Settings to change
+    ///
+    /// # Returns
+    ///
+    /// Result containing the updated policy or an error
+    pub async fn update(
+        pool: &PgPool,
+        account_id: i64,
+        request: UpdateNotificationPolicyRequest,
+    ) -> Result<Self, NotificationsError> {
+        trace!("Updating notification policy for account {}", account_id);
+
+        Self::get_or_create(pool, account_id).await?;
+
+        let row = sqlx::query_as!(
+            NotificationPolicyRow,
+            r#"
+            UPDATE notification_policies
+            SET filter_not_following = COALESCE($2, filter_not_following),
+                filter_not_followers = COALESCE($3, filter_not_followers),
+                filter_new_accounts = COALESCE($4, filter_new_accounts),
+                filter_private_mentions = COALESCE($5, filter_private_mentions)
+            WHERE account_id = $1
+            RETURNING id, account_id, filter_not_following, filter_not_followers,
+                      filter_new_accounts, filter_private_mentions, created_at, updated_at
+            "#,
+            account_id,
+            request.filter_not_following,
+            request.filter_not_followers,
+            request.filter_new_accounts,
+            request.filter_private_mentions
+        )
+        .fetch_one(pool)
+        .await?;
+
+        info!("Updated notification policy for account {}", account_id);
+        Ok(NotificationPolicy::from(row))
+    }
+
+    /// Decides whether the policy holds back a notification from a sender
+    ///
+    /// # Arguments
+    ///
+    /// * `notification_type` - Type of the notification
+    /// * `sender` - Relationship between the sender and the recipient
+    ///
+    /// # Returns
+    ///
+    /// True if the notification should be filtered
+    pub fn filters(&self, notification_type: &NotificationType, sender: &SenderFacts) -> bool {
+        if NON_FILTERABLE_TYPES.contains(notification_type) {
+            return false;
+        }
+
+        (self.filter_not_following && !sender.following)
+            || (self.filter_not_followers && !sender.followed_by)
+            || (self.filter_new_accounts
+                && Utc::now() - sender.created_at < Duration::days(NEW_ACCOUNT_AGE_DAYS))
+            || (self.filter_private_mentions && sender.private_mention && !sender.following)
+    }
+
+    /// Decides whether a new notification is held back
+    ///
+    /// Notifications without a sender, from the recipient themselves or from
+    /// senders whose requests were accepted are never filtered.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `request` - The notification about to be created
+    ///
+    /// # Returns
+    ///
+    /// Result containing true if the notification should be filtered
+    pub async fn should_filter(
+        &self,
+        pool: &PgPool,
+        request: &CreateNotificationRequest,
+    ) -> Result<bool, NotificationsError> {
+        let Some(from_account_id) = request.from_account_id else {
+            return Ok(false);
+        };
+        if from_account_id == self.account_id
+            || NON_FILTERABLE_TYPES.contains(&request.notification_type)
+        {
+            return Ok(false);
+        }
+
+        let facts = sqlx::query!(
+            r#"
+            SELECT
+                u.created_at,
+                EXISTS(
+                    SELECT 1 FROM follows WHERE follower_id = $1 AND followed_id = u.id
+                ) AS "following!",
+                EXISTS(
+                    SELECT 1 FROM follows WHERE follower_id = u.id AND followed_id = $1
+                ) AS "followed_by!",
+                EXISTS(
+                    SELECT 1 FROM notification_permissions
+                    WHERE account_id = $1 AND from_account_id = u.id
+                ) AS "permitted!",
+                EXISTS(
+                    SELECT 1 FROM statuses WHERE id = $3 AND visibility = 'direct'
+                ) AS "direct!"
+            FROM users u
+            WHERE u.id = $2
+            "#,
+            self.account_id,
+            from_account_id,
+            request.status_id
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(NotificationsError::AccountNotFound(from_account_id))?;
+
+        if facts.permitted {
+            return Ok(false);
+        }
+
+        let sender = SenderFacts {
+            following: facts.following,
+            followed_by: facts.followed_by,
+            created_at: DateTime::from_naive_utc_and_offset(facts.created_at, Utc),
+            private_mention: request.notification_type == NotificationType::Mention && facts.direct,
+        };
+
+        let filtered = self.filters(&request.notification_type, &sender);
+        debug!(
+            "Notification from {} to {} filtered: {}",
+            from_account_id, self.account_id, filtered
+        );
+        Ok(filtered)
+    }
+
+    /// Counts pending notification requests for an account
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the account
+    ///
+    /// # Returns
+    ///
+    /// Result containing the summary or an error
+    pub async fn summary(
+        pool: &PgPool,
+        account_id: i64,
+    ) -> Result<PolicySummary, NotificationsError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT COUNT(*) AS "requests!", COALESCE(SUM(notifications_count), 0) AS "notifications!"
+            FROM notification_requests
+            WHERE account_id = $1 AND NOT dismissed
+            "#,
+            account_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(PolicySummary {
+            pending_requests_count: row.requests,
+            pending_notifications_count: row.notifications,
+        })
+    }
+}
+
+/// Filtered notifications from one sender
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationRequest {
+    /// Unique identifier for the request
+    pub id: i64,
+    /// ID of the account the notifications are for
+    pub account_id: i64,
+    /// ID of the account that sent them
+    pub from_account_id: i64,
+    /// ID of the latest filtered notification
+    pub last_notification_id: i64,
+    /// Number of filtered notifications
+    pub notifications_count: i32,
+    /// Whether the recipient dismissed the request
+    pub dismissed: bool,
+    /// When the request was created
+    pub created_at: DateTime<Utc>,
+    /// When the request was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+impl NotificationRequest {
+    /// Adds a filtered notification to the sender's request
+    ///
+    /// # Arguments
+    ///
+    /// * `tx` - Transaction the notification was inserted in
+    /// * `account_id` - ID of the recipient
+    /// * `from_account_id` - ID of the sender
+    /// * `notification_id` - ID of the filtered notification
+    ///
+    /// # Returns
+    ///
+    /// Result indicating success or failure
+    pub(crate) async fn record(
+        tx: &mut Transaction<'_, Postgres>,
+        account_id: i64,
+        from_account_id: i64,
+        notification_id: i64,
+    ) -> Result<(), NotificationsError> {
+        trace!(
+            "Recording filtered notification {} from {} to {}",
+            notification_id,
+            from_account_id,
+            account_id
+        );
+
+        sqlx::query!(
+            r#"
+            INSERT INTO notification_requests (account_id, from_account_id, last_notification_id, notifications_count)
+            VALUES ($1, $2, $3, 1)
+            ON CONFLICT (account_id, from_account_id) DO UPDATE
+            SET last_notification_id = EXCLUDED.last_notification_id,
+                notifications_count = notification_requests.notifications_count + 1
+            "#,
+            account_id,
+            from_account_id,
+            notification_id
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Gets the pending requests of an account, newest first
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the account
+    /// * `limit` - Maximum number of requests to return
+    /// * `since_id` - Return requests after this ID
+    /// * `max_id` - Return requests before this ID
+    ///
+    /// # Returns
+    ///
+    /// Result containing the requests or an error
+    pub async fn get_by_account(
+        pool: &PgPool,
+        account_id: i64,
+        limit: Option<i64>,
+        since_id: Option<i64>,
+        max_id: Option<i64>,
+    ) -> Result<Vec<Self>, NotificationsError> {
+        trace!("Getting notification requests for account {}", account_id);
+
+        let limit = limit.unwrap_or(40).clamp(1, 80);
+        let rows = sqlx::query_as!(
+            NotificationRequestRow,
+            r#"
+            SELECT id, account_id, from_account_id, last_notification_id, notifications_count,
+                   dismissed, created_at, updated_at
+            FROM notification_requests
+            WHERE account_id = $1
+              AND NOT dismissed
+              AND ($2::BIGINT IS NULL OR id > $2)
+              AND ($3::BIGINT IS NULL OR id < $3)
+            ORDER BY id DESC
+            LIMIT $4
+            "#,
+            account_id,
+            since_id,
+            max_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        debug!(
+            "Retrieved {} notification requests for account {}",
+            rows.len(),
+            account_id
+        );
+        Ok(rows.into_iter().map(NotificationRequest::from).collect())
+    }
+
+    /// Gets a request by ID, making sure it belongs to the account
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `request_id` - ID of the request
+    /// * `account_id` - ID of the account that must own the request
+    ///
+    /// # Returns
+    ///
+    /// Result containing the request or an error
+    pub async fn get_owned(
+        pool: &PgPool,
+        request_id: i64,
+        account_id: i64,
+    ) -> Result<Self, NotificationsError> {
+        let row = sqlx::query_as!(
+            NotificationRequestRow,
+            r#"
+            SELECT id, account_id, from_account_id, last_notification_id, notifications_count,
+                   dismissed, created_at, updated_at
+            FROM notification_requests
+            WHERE id = $1 AND account_id = $2
+            "#,
+            request_id,
+            account_id
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(NotificationsError::NotificationRequestNotFound(request_id))?;
+
+        Ok(NotificationRequest::from(row))
+    }
+
+    /// Accepts a request
+    ///
+    /// Releases the sender's filtered notifications into the regular
+    /// notifications and lets future notifications from the sender through.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `request_id` - ID of the request
+    /// * `account_id` - ID of the account that owns the request
+    ///
+    /// # Returns
+    ///
+    /// Result containing the accepted request or an error
+    pub async fn accept(
+        pool: &PgPool,
+        request_id: i64,
+        account_id: i64,
+    ) -> Result<Self, NotificationsError> {
+        trace!("Accepting notification request {}", request_id);
+
+        let mut tx = pool.begin().await?;
+
+        let row = sqlx::query_as!(
+            NotificationRequestRow,
+            r#"
+            DELETE FROM notification_requests
+            WHERE id = $1 AND account_id = $2
+            RETURNING id, account_id, from_account_id, last_notification_id, notifications_count,
+                      dismissed, created_at, updated_at
+            "#,
+            request_id,
+            account_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(NotificationsError::NotificationRequestNotFound(request_id))?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO notification_permissions (account_id, from_account_id)
+            VALUES ($1, $2)
+            ON CONFLICT (account_id, from_account_id) DO NOTHING
+            "#,
+            account_id,
+            row.from_account_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE notifications
+            SET filtered = false, updated_at = now()
+            WHERE account_id = $1 AND from_account_id = $2 AND filtered
+            "#,
+            account_id,
+            row.from_account_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        info!(
+            "Accepted notification request {} from {} for account {}",
+            request_id, row.from_account_id, account_id
+        );
+        Ok(NotificationRequest::from(row))
+    }
+
+    /// Dismisses a request
+    ///
+    /// The sender's notifications stay filtered and the request no longer
+    /// shows up in the inbox. New filtered notifications from the sender are
+    /// still counted on the dismissed request.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `request_id` - ID of the request
+    /// * `account_id` - ID of the account that owns the request
+    ///
+    /// # Returns
+    ///
+    /// Result containing the dismissed request or an error
+    pub async fn dismiss(
+        pool: &PgPool,
+        request_id: i64,
+        account_id: i64,
+    ) -> Result<Self, NotificationsError> {
+        trace!("Dismissing notification request {}", request_id);
+
+        let row = sqlx::query_as!(
+            NotificationRequestRow,
+            r#"
+            UPDATE notification_requests
+            SET dismissed = true
+            WHERE id = $1 AND account_id = $2
+            RETURNING id, account_id, from_account_id, last_notification_id, notifications_count,
+                      dismissed, created_at, updated_at
+            "#,
+            request_id,
+            account_id
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(NotificationsError::NotificationRequestNotFound(request_id))?;
+
+        info!(
+            "Dismissed notification request {} for account {}",
+            request_id, account_id
+        );
+        Ok(NotificationRequest::from(row))
+    }
+}
+
+struct NotificationPolicyRow {
+    id: i64,
+    account_id: i64,
+    filter_not_following: bool,
+    filter_not_followers: bool,
+    filter_new_accounts: bool,
+    filter_private_mentions: bool,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+}
+
+impl From<NotificationPolicyRow> for NotificationPolicy {
+    fn from(row: NotificationPolicyRow) -> Self {
+        Self {
+            id: row.id,
+            account_id: row.account_id,
+            filter_not_following: row.filter_not_following,
+            filter_not_followers: row.filter_not_followers,
+            filter_new_accounts: row.filter_new_accounts,
+            filter_private_mentions: row.filter_private_mentions,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+            updated_at: DateTime::from_naive_utc_and_offset(row.updated_at, Utc),
+        }
+    }
+}
+
+struct NotificationRequestRow {
+    id: i64,
+    account_id: i64,
+    from_account_id: i64,
+    last_notification_id: i64,
+    notifications_count: i32,
+    dismissed: bool,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+}
+
+impl From<NotificationRequestRow> for NotificationRequest {
+    fn from(row: NotificationRequestRow) -> Self {
+        Self {
+            id: row.id,
+            account_id: row.account_id,
+            from_account_id: row.from_account_id,
+            last_notification_id: row.last_notification_id,
+            notifications_count: row.notifications_count,
+            dismissed: row.dismissed,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+            updated_at: DateTime::from_naive_utc_and_offset(row.updated_at, Utc),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> NotificationPolicy {
+        NotificationPolicy {
+            id: 1,
+            account_id: 1,
+            filter_not_following: false,
+            filter_not_followers: false,
+            filter_new_accounts: false,
+            filter_private_mentions: true,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn stranger() -> SenderFacts {
+        SenderFacts {
+            following: false,
+            followed_by: false,
+            created_at: Utc::now() - Duration::days(365),
+            private_mention: false,
+        }
+    }
+
+    #[test]
+    fn test_default_policy_only_filters_private_mentions() {
+        let policy = policy();
+        assert!(!policy.filters(&NotificationType::Favourite, &stranger()));
+
+        let mention = SenderFacts {
+            private_mention: true,
+            ..stranger()
+        };
+        assert!(policy.filters(&NotificationType::Mention, &mention));
+
+        let followed = SenderFacts {
+            following: true,
+            ..mention
+        };
+        assert!(!policy.filters(&NotificationType::Mention, &followed));
+    }
+
+    #[test]
+    fn test_relationship_and_age_filters() {
+        let policy = NotificationPolicy {
+            filter_not_following: true,
+            filter_new_accounts: true,
+            ..policy()
+        };
+
+        assert!(policy.filters(&NotificationType::Follow, &stranger()));
+
+        let new_but_followed = SenderFacts {
+            following: true,
+            created_at: Utc::now() - Duration::days(2),
+            ..stranger()
+        };
+        assert!(policy.filters(&NotificationType::Reblog, &new_but_followed));
+
+        let old_and_followed = SenderFacts {
+            following: true,
+            ..stranger()
+        };
+        assert!(!policy.filters(&NotificationType::Reblog, &old_and_followed));
+    }
+
+    #[test]
+    fn test_non_filterable_types() {
+        let policy = NotificationPolicy {
+            filter_not_following: true,
+            filter_not_followers: true,
+            ..policy()
+        };
+        assert!(!policy.filters(&NotificationType::Poll, &stranger()));
+        assert!(!policy.filters(&NotificationType::AdminReport, &stranger()));
+    }
+}
@@ -1,82 +1,554 @@
 //! Polls functionality for Rustodon
 //!
-//! This module provides poll management functionality.
+//! This module provides polls attached to statuses: voting with single or
+//! multiple choices, vote and voter counts, and closing polls once they
+//! expire. When a poll closes, its author and everyone who voted receive a
+//! `poll` notification.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_polls::Poll;
+//!
+//! let poll = Poll::vote(&pool, poll_id, account_id, &[0, 2]).await?;
+//! let own_votes = Poll::own_votes(&pool, &[poll.id], account_id).await?;
+//!
+//! let closed = Poll::close_expired(&pool, Utc::now()).await?;
+//! ```
 //!
 //! # Author
 //!
 //! arkSong (arksong2018@gmail.com)
 
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use rustodon_notifications::{CreateNotificationRequest, Notification, NotificationType};
+use rustodon_workers::{Job, WorkerError};
 use serde::{Deserialize, Serialize};
-use tracing::{error, info, trace};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use tracing::{debug, error, info, trace};
+
+/// Polls error
+#[derive(Debug, thiserror::Error)]
+pub enum PollsError {
+    #[error("Database error: {0}")]
+    Database(String),
+    #[error("Validation error: {0}")]
+    Validation(String),
+    #[error("Poll not found: {0}")]
+    PollNotFound(i64),
+    #[error("The poll has already ended")]
+    PollExpired,
+    #[error("You have already voted on this poll")]
+    AlreadyVoted,
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+impl From<sqlx::Error> for PollsError {
+    fn from(e: sqlx::Error) -> Self {
+        PollsError::Database(e.to_string())
+    }
+}
+
+/// Poll attached to a new status
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewPoll {
+    /// Poll choices
+    pub options: Vec<String>,
+    /// Seconds until the poll closes, counted from publication
+    pub expires_in: i64,
+    /// Whether several choices may be voted for
+    #[serde(default)]
+    pub multiple: bool,
+    /// Whether vote counts are hidden until the poll closes
+    #[serde(default)]
+    pub hide_totals: bool,
+}
+
+/// One choice of a poll with its tally
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PollOption {
+    /// Text of the choice
+    pub title: String,
+    /// Number of votes for the choice
+    pub votes_count: i64,
+}
 
 /// Poll model
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Poll {
+    /// Unique identifier for the poll
     pub id: i64,
-    pub question: String,
-    pub options: Vec<String>,
-    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// ID of the account that posted the poll
+    pub account_id: i64,
+    /// ID of the status the poll is attached to
+    pub status_id: i64,
+    /// Choices with their tallies, in order
+    pub options: Vec<PollOption>,
+    /// Whether several choices may be voted for
+    pub multiple: bool,
+    /// Whether vote counts are hidden until the poll closes
+    pub hide_totals: bool,
+    /// When the poll closes
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Total number of votes across all choices
+    pub votes_count: i64,
+    /// Number of distinct accounts that voted
+    pub voters_count: i64,
+    /// When the poll was created
+    pub created_at: DateTime<Utc>,
+    /// When the poll was last updated
+    pub updated_at: DateTime<Utc>,
 }
 
-/// Create poll request
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CreatePollRequest {
-    pub question: String,
-    pub options: Vec<String>,
-    pub expires_in: Option<u64>,
+impl Poll {
+    /// Whether the poll has closed
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Current time
+    pub fn expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// Creates the poll of a new status
+    ///
+    /// Runs inside the transaction creating the status.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx` - Transaction creating the status
+    /// * `account_id` - ID of the posting account
+    /// * `status_id` - ID of the new status
+    /// * `new_poll` - Choices and settings of the poll
+    ///
+    /// # Returns
+    ///
+    /// Result containing the created poll or an error
+    pub async fn create(
+        tx: &mut Transaction<'_, Postgres>,
+        account_id: i64,
+        status_id: i64,
+        new_poll: &NewPoll,
+    ) -> Result<Self, PollsError> {
+        trace!("Creating poll for status {}", status_id);
+
+        let expires_at = Utc::now() + Duration::seconds(new_poll.expires_in);
+        let row = sqlx::query!(
+            r#"
+            INSERT INTO polls (account_id, status_id, options, multiple, hide_totals, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, created_at, updated_at
+            "#,
+            account_id,
+            status_id,
+            &new_poll.options,
+            new_poll.multiple,
+            new_poll.hide_totals,
+            expires_at.naive_utc()
+        )
+        .fetch_one(&mut **tx)
+        .await?;
+
+        info!("Created poll {} for status {}", row.id, status_id);
+        Ok(Self {
+            id: row.id,
+            account_id,
+            status_id,
+            options: new_poll
+                .options
+                .iter()
+                .map(|title| PollOption {
+                    title: title.clone(),
+                    votes_count: 0,
+                })
+                .collect(),
+            multiple: new_poll.multiple,
+            hide_totals: new_poll.hide_totals,
+            expires_at: Some(expires_at),
+            votes_count: 0,
+            voters_count: 0,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+            updated_at: DateTime::from_naive_utc_and_offset(row.updated_at, Utc),
+        })
+    }
+
+    /// Gets a poll by ID
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `poll_id` - ID of the poll
+    ///
+    /// # Returns
+    ///
+    /// Result containing the poll or an error
+    pub async fn get_by_id(pool: &PgPool, poll_id: i64) -> Result<Self, PollsError> {
+        trace!("Getting poll by id: {}", poll_id);
+
+        Self::get_by_ids(pool, &[poll_id])
+            .await?
+            .pop()
+            .ok_or(PollsError::PollNotFound(poll_id))
+    }
+
+    /// Gets several polls by ID
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `poll_ids` - IDs of the polls
+    ///
+    /// # Returns
+    ///
+    /// Result containing the polls that exist
+    pub async fn get_by_ids(pool: &PgPool, poll_ids: &[i64]) -> Result<Vec<Self>, PollsError> {
+        if poll_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query_as!(
+            PollRow,
+            r#"
+            SELECT p.id, p.account_id, p.status_id, p.options, p.multiple, p.hide_totals,
+                   p.expires_at, p.created_at, p.updated_at,
+                   ARRAY(
+                       SELECT COUNT(v.id)
+                       FROM generate_series(0, cardinality(p.options) - 1) AS c(choice)
+                       LEFT JOIN poll_votes v ON v.poll_id = p.id AND v.choice = c.choice
+                       GROUP BY c.choice
+                       ORDER BY c.choice
+                   ) AS "tallies!",
+                   (SELECT COUNT(DISTINCT account_id) FROM poll_votes WHERE poll_id = p.id)
+                       AS "voters_count!"
+            FROM polls p
+            WHERE p.id = ANY($1)
+            "#,
+            poll_ids
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(Poll::from).collect())
+    }
+
+    /// Gets the polls attached to statuses
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `status_ids` - IDs of the statuses
+    ///
+    /// # Returns
+    ///
+    /// Result containing the polls keyed by status ID
+    pub async fn get_by_status_ids(
+        pool: &PgPool,
+        status_ids: &[i64],
+    ) -> Result<HashMap<i64, Self>, PollsError> {
+        if status_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let poll_ids =
+            sqlx::query_scalar!("SELECT id FROM polls WHERE status_id = ANY($1)", status_ids)
+                .fetch_all(pool)
+                .await?;
+
+        Ok(Self::get_by_ids(pool, &poll_ids)
+            .await?
+            .into_iter()
+            .map(|poll| (poll.status_id, poll))
+            .collect())
+    }
+
+    /// Gets the choices an account voted for
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `poll_ids` - IDs of the polls
+    /// * `account_id` - ID of the voting account
+    ///
+    /// # Returns
+    ///
+    /// Result containing the chosen option indexes keyed by poll ID; polls
+    /// the account didn't vote on are left out
+    pub async fn own_votes(
+        pool: &PgPool,
+        poll_ids: &[i64],
+        account_id: i64,
+    ) -> Result<HashMap<i64, Vec<usize>>, PollsError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT poll_id, choice
+            FROM poll_votes
+            WHERE poll_id = ANY($1) AND account_id = $2
+            ORDER BY choice
+            "#,
+            poll_ids,
+            account_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut votes: HashMap<i64, Vec<usize>> = HashMap::new();
+        for row in rows {
+            votes
+                .entry(row.poll_id)
+                .or_default()
+                .push(row.choice as usize);
+        }
+        Ok(votes)
+    }
+
+    /// Votes on a poll
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `poll_id` - ID of the poll
+    /// * `account_id` - ID of the voting account
+    /// * `choices` - Indexes of the chosen options
+    ///
+    /// # Returns
+    ///
+    /// Result containing the poll with updated tallies or an error
+    pub async fn vote(
+        pool: &PgPool,
+        poll_id: i64,
+        account_id: i64,
+        choices: &[usize],
+    ) -> Result<Self, PollsError> {
+        trace!("Account {} voting on poll {}", account_id, poll_id);
+
+        let mut tx = pool.begin().await?;
+
+        // Lock the poll so concurrent votes by the same account are serialized
+        let poll = sqlx::query!(
+            r#"
+            SELECT cardinality(options) AS "option_count!", multiple, expires_at
+            FROM polls
+            WHERE id = $1
+            FOR UPDATE
+            "#,
+            poll_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(PollsError::PollNotFound(poll_id))?;
+
+        if poll
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now().naive_utc())
+        {
+            return Err(PollsError::PollExpired);
+        }
+        validate_choices(choices, poll.option_count as usize, poll.multiple)?;
+
+        let already_voted = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS(SELECT 1 FROM poll_votes WHERE poll_id = $1 AND account_id = $2)
+                AS "exists!"
+            "#,
+            poll_id,
+            account_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if already_voted {
+            return Err(PollsError::AlreadyVoted);
+        }
+
+        let choices: Vec<i32> = choices.iter().map(|&choice| choice as i32).collect();
+        sqlx::query!(
+            r#"
+            INSERT INTO poll_votes (poll_id, account_id, choice)
+            SELECT $1, $2, UNNEST($3::INT[])
+            "#,
+            poll_id,
+            account_id,
+            &choices
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        info!(
+            "Account {} voted {:?} on poll {}",
+            account_id, choices, poll_id
+        );
+        Self::get_by_id(pool, poll_id).await
+    }
+
+    /// Closes the polls that have expired and notifies their participants
+    ///
+    /// The author and every voter receive a `poll` notification. A poll is
+    /// closed only once, so participants are never notified twice.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `now` - Current time
+    ///
+    /// # Returns
+    ///
+    /// Result containing the closed polls or an error
+    pub async fn close_expired(pool: &PgPool, now: DateTime<Utc>) -> Result<Vec<Self>, PollsError> {
+        trace!("Closing polls expired by {}", now);
+
+        let poll_ids = sqlx::query_scalar!(
+            r#"
+            UPDATE polls
+            SET closed_at = $1
+            WHERE closed_at IS NULL AND expires_at <= $1
+            RETURNING id
+            "#,
+            now.naive_utc()
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let polls = Self::get_by_ids(pool, &poll_ids).await?;
+        for poll in &polls {
+            let voter_ids = sqlx::query_scalar!(
+                "SELECT DISTINCT account_id FROM poll_votes WHERE poll_id = $1",
+                poll.id
+            )
+            .fetch_all(pool)
+            .await?;
+
+            for recipient_id in notification_recipients(poll.account_id, voter_ids) {
+                let request = CreateNotificationRequest {
+                    account_id: recipient_id,
+                    from_account_id: Some(poll.account_id),
+                    notification_type: NotificationType::Poll,
+                    status_id: Some(poll.status_id),
+                    poll_id: Some(poll.id),
+                    report_id: None,
+                };
+                if let Err(e) = Notification::create(pool, request).await {
+                    error!(
+                        "Failed to notify account {} of closed poll {}: {}",
+                        recipient_id, poll.id, e
+                    );
+                }
+            }
+            info!("Closed poll {}", poll.id);
+        }
+
+        Ok(polls)
+    }
 }
 
-/// Vote poll request
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct VotePollRequest {
-    pub poll_id: i64,
-    pub choice: usize,
+/// Checks the choices of a vote
+fn validate_choices(
+    choices: &[usize],
+    option_count: usize,
+    multiple: bool,
+) -> Result<(), PollsError> {
+    if choices.is_empty() {
+        return Err(PollsError::Validation("No choices given".to_string()));
+    }
+    if !multiple && choices.len() > 1 {
+        return Err(PollsError::Validation(
+            "This poll only allows one choice".to_string(),
+        ));
+    }
+    if choices.iter().any(|&choice| choice >= option_count) {
+        return Err(PollsError::Validation("Invalid choice".to_string()));
+    }
+    if choices.iter().collect::<HashSet<_>>().len() != choices.len() {
+        return Err(PollsError::Validation("Duplicate choices".to_string()));
+    }
+    Ok(())
 }
 
-/// Polls error
-#[derive(Debug, thiserror::Error)]
-pub enum PollsError {
-    #[error("Database error: {0}")]
-    Database(String),
-    #[error("Validation error: {0}")]
-    Validation(String),
-    #[error("Internal error: {0}")]
-    Internal(String),
+/// The poll's author followed by every other voter
+fn notification_recipients(author_id: i64, voter_ids: Vec<i64>) -> Vec<i64> {
+    std::iter::once(author_id)
+        .chain(voter_ids.into_iter().filter(|&id| id != author_id))
+        .collect()
 }
 
-/// Poll service
-pub struct PollService;
+/// Background job closing expired polls
+pub struct ClosePollsJob {
+    pool: PgPool,
+}
 
-impl Default for PollService {
-    fn default() -> Self {
-        Self::new()
+impl ClosePollsJob {
+    /// Creates the job
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
     }
 }
 
-impl PollService {
-    /// Creates a new poll service
-    pub fn new() -> Self {
-        info!("Creating new poll service");
-        Self
+impl Job for ClosePollsJob {
+    fn name(&self) -> &'static str {
+        "ClosePollsJob"
     }
 
-    /// Create poll
-    pub async fn create_poll(&self, _request: CreatePollRequest) -> Result<Poll, PollsError> {
-        trace!("Creating poll");
-        // TODO: Implement poll creation
-        Ok(Poll {
-            id: 1,
-            question: _request.question,
-            options: _request.options,
-            expires_at: None,
+    fn execute(&self) -> Pin<Box<dyn Future<Output = Result<(), WorkerError>> + Send>> {
+        let pool = self.pool.clone();
+        Box::pin(async move {
+            let closed = Poll::close_expired(&pool, Utc::now())
+                .await
+                .map_err(|e| WorkerError::Job(e.to_string()))?;
+            debug!("Closed {} expired polls", closed.len());
+            Ok(())
         })
     }
+}
+
+struct PollRow {
+    id: i64,
+    account_id: i64,
+    status_id: i64,
+    options: Vec<String>,
+    multiple: bool,
+    hide_totals: bool,
+    expires_at: Option<NaiveDateTime>,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    tallies: Vec<i64>,
+    voters_count: i64,
+}
+
+impl From<PollRow> for Poll {
+    fn from(row: PollRow) -> Self {
+        let options: Vec<PollOption> = row
+            .options
+            .into_iter()
+            .enumerate()
+            .map(|(i, title)| PollOption {
+                title,
+                votes_count: row.tallies.get(i).copied().unwrap_or(0),
+            })
+            .collect();
 
-    /// Vote on poll
-    pub async fn vote_poll(&self, _request: VotePollRequest) -> Result<(), PollsError> {
-        trace!("Voting on poll");
-        // TODO: Implement poll voting
-        Ok(())
+        Self {
+            id: row.id,
+            account_id: row.account_id,
+            status_id: row.status_id,
+            votes_count: options.iter().map(|option| option.votes_count).sum(),
+            options,
+            multiple: row.multiple,
+            hide_totals: row.hide_totals,
+            expires_at: row
+                .expires_at
+                .map(|at| DateTime::from_naive_utc_and_offset(at, Utc)),
+            voters_count: row.voters_count,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+            updated_at: DateTime::from_naive_utc_and_offset(row.updated_at, Utc),
+        }
     }
 }
 
@@ -85,20 +557,43 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_poll_service_new() {
-        let _service = PollService::new();
-        // Service created successfully
-    }
-
-    #[tokio::test]
-    async fn test_create_poll() {
-        let service = PollService::new();
-        let request = CreatePollRequest {
-            question: "Test question?".to_string(),
-            options: vec!["Yes".to_string(), "No".to_string()],
-            expires_in: None,
-        };
-        let result = service.create_poll(request).await;
-        assert!(result.is_ok());
+    fn test_validate_choices() {
+        assert!(validate_choices(&[1], 3, false).is_ok());
+        assert!(validate_choices(&[0, 2], 3, true).is_ok());
+
+        assert!(validate_choices(&[], 3, true).is_err());
+        assert!(validate_choices(&[0, 1], 3, false).is_err());
+        assert!(validate_choices(&[3], 3, false).is_err());
+        assert!(validate_choices(&[1, 1], 3, true).is_err());
+    }
+
+    #[test]
+    fn test_poll_from_row() {
+        let now = Utc::now().naive_utc();
+        let poll = Poll::from(PollRow {
+            id: 1,
+            account_id: 2,
+            status_id: 3,
+            options: vec!["Tea".to_string(), "Coffee".to_string()],
+            multiple: true,
+            hide_totals: false,
+            expires_at: Some(now),
+            created_at: now,
+            updated_at: now,
+            tallies: vec![2, 3],
+            voters_count: 4,
+        });
+
+        assert_eq!(poll.options[1].title, "Coffee");
+        assert_eq!(poll.options[1].votes_count, 3);
+        assert_eq!(poll.votes_count, 5);
+        assert_eq!(poll.voters_count, 4);
+        assert!(poll.expired(Utc::now()));
+    }
+
+    #[test]
+    fn test_notification_recipients() {
+        assert_eq!(notification_recipients(1, vec![2, 1, 3]), vec![1, 2, 3]);
+        assert_eq!(notification_recipients(1, Vec::new()), vec![1]);
     }
 }
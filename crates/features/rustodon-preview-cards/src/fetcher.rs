@@ -0,0 +1,393 @@
+//! Remote page fetching
+//!
+//! Fetches linked pages, their oEmbed endpoint and their preview image
+//! with a bounded timeout, redirect count and body size. Hosts on the
+//! domain blocklist (and their subdomains) are never contacted, including
+//! when a redirect points at them. Neither are addresses of this server or
+//! its network: hosts are resolved before connecting, and refused if any of
+//! their addresses is loopback, private, link-local, unique local or
+//! unspecified, so that posted links can't reach internal services.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::parser::{parse_html, OEmbed, PageMetadata};
+use crate::PreviewCardsError;
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::{header, redirect, Client, Response, Url};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
+use url::Host;
+
+/// Limits applied when fetching link previews
+#[derive(Debug, Clone)]
+pub struct PreviewCardConfig {
+    /// Timeout of each request, body included
+    pub timeout: Duration,
+    /// Largest page or oEmbed body read, in bytes
+    pub max_html_bytes: usize,
+    /// Largest preview image downloaded, in bytes
+    pub max_image_bytes: usize,
+    /// Redirects followed before giving up
+    pub max_redirects: usize,
+    /// Domains never fetched, subdomains included
+    pub blocked_domains: Vec<String>,
+    /// User agent sent with every request
+    pub user_agent: String,
+    /// Whether non-public addresses may be fetched, for development only
+    pub allow_private_addresses: bool,
+}
+
+impl Default for PreviewCardConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            max_html_bytes: 1024 * 1024,
+            max_image_bytes: 8 * 1024 * 1024,
+            max_redirects: 3,
+            blocked_domains: Vec::new(),
+            user_agent: format!("Rustodon/{} (link preview)", env!("CARGO_PKG_VERSION")),
+            allow_private_addresses: false,
+        }
+    }
+}
+
+impl PreviewCardConfig {
+    /// Reads the configuration from the environment
+    ///
+    /// `PREVIEW_CARD_BLOCKED_DOMAINS` is a comma separated domain list and
+    /// `PREVIEW_CARD_TIMEOUT` a timeout in seconds.
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Ok(domains) = std::env::var("PREVIEW_CARD_BLOCKED_DOMAINS") {
+            config.blocked_domains = domains
+                .split(',')
+                .map(|domain| domain.trim().trim_start_matches('.').to_ascii_lowercase())
+                .filter(|domain| !domain.is_empty())
+                .collect();
+        }
+        if let Some(seconds) = std::env::var("PREVIEW_CARD_TIMEOUT")
+            .ok()
+            .and_then(|s| s.parse().ok())
+        {
+            config.timeout = Duration::from_secs(seconds);
+        }
+        config
+    }
+
+    /// Whether a URL must not be fetched
+    ///
+    /// Only http and https URLs are fetched, and never from a blocked
+    /// domain or one of its subdomains, nor from a non-public IP address.
+    /// Domains resolving to non-public addresses are refused when
+    /// connecting.
+    pub fn is_blocked(&self, url: &Url) -> bool {
+        if !matches!(url.scheme(), "http" | "https") {
+            return true;
+        }
+        let ip = match url.host() {
+            Some(Host::Domain(_)) => None,
+            Some(Host::Ipv4(ip)) => Some(IpAddr::V4(ip)),
+            Some(Host::Ipv6(ip)) => Some(IpAddr::V6(ip)),
+            None => return true,
+        };
+        if ip.is_some_and(|ip| !self.allow_private_addresses && !is_public_address(ip)) {
+            return true;
+        }
+
+        let host = url
+            .host_str()
+            .unwrap_or_default()
+            .trim_end_matches('.')
+            .to_ascii_lowercase();
+        self.blocked_domains.iter().any(|domain| {
+            host == *domain
+                || host
+                    .strip_suffix(domain.as_str())
+                    .is_some_and(|prefix| prefix.ends_with('.'))
+        })
+    }
+}
+
+/// Whether an address is reachable on the public internet
+///
+/// Loopback, private, shared, link-local, unique local, unspecified,
+/// broadcast and multicast addresses are not, including when they are
+/// IPv4 addresses mapped into IPv6.
+pub fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                || (a == 100 && b & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_address(IpAddr::V4(ip));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80)
+        }
+    }
+}
+
+/// DNS resolver refusing hosts with a non-public address
+///
+/// The check happens on the addresses actually connected to, so it covers
+/// every redirect and can't be dodged by a host that resolves differently
+/// on a second lookup.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            if let Some(addr) = addrs.iter().find(|addr| !is_public_address(addr.ip())) {
+                return Err(format!(
+                    "{} resolves to non-public address {}",
+                    name.as_str(),
+                    addr.ip()
+                )
+                .into());
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// A fetched page, ready to become a card
+#[derive(Debug, Clone)]
+pub struct FetchedPage {
+    /// Final URL of the page, after redirects
+    pub url: String,
+    /// Page metadata merged with its oEmbed response
+    pub metadata: PageMetadata,
+    /// Raw bytes of the preview image, if one could be downloaded
+    pub image: Option<Vec<u8>>,
+}
+
+/// HTTP client fetching link previews
+#[derive(Debug, Clone)]
+pub struct PreviewCardFetcher {
+    client: Client,
+    config: PreviewCardConfig,
+}
+
+impl PreviewCardFetcher {
+    /// Creates a fetcher
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Limits and blocklist applied to every request
+    pub fn new(config: PreviewCardConfig) -> Result<Self, PreviewCardsError> {
+        let policy_config = config.clone();
+        let policy = redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() > policy_config.max_redirects {
+                attempt.error("too many redirects")
+            } else if policy_config.is_blocked(attempt.url()) {
+                attempt.error("redirect to a blocked URL")
+            } else {
+                attempt.follow()
+            }
+        });
+
+        // A proxy would resolve hosts itself, bypassing the address check
+        let mut builder = Client::builder()
+            .timeout(config.timeout)
+            .connect_timeout(config.timeout)
+            .redirect(policy)
+            .no_proxy()
+            .user_agent(config.user_agent.clone());
+        if !config.allow_private_addresses {
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+        }
+        let client = builder.build()?;
+
+        Ok(Self { client, config })
+    }
+
+    /// Returns the fetcher's configuration
+    pub fn config(&self) -> &PreviewCardConfig {
+        &self.config
+    }
+
+    /// Fetches a page, its oEmbed data and its preview image
+    ///
+    /// Failing to fetch the oEmbed data or the image doesn't fail the page;
+    /// the card is built from whatever was found.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - URL of the page
+    pub async fn fetch(&self, url: &str) -> Result<FetchedPage, PreviewCardsError> {
+        let url = Url::parse(url).map_err(|e| PreviewCardsError::InvalidUrl(e.to_string()))?;
+        let response = self.get(url, "text/html").await?;
+
+        let is_html = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.to_ascii_lowercase().contains("html"));
+        if !is_html {
+            return Err(PreviewCardsError::Unsupported(
+                "linked resource is not an HTML page".to_string(),
+            ));
+        }
+
+        let page_url = response.url().clone();
+        let body = read_limited(response, self.config.max_html_bytes).await?;
+        let mut metadata = parse_html(&String::from_utf8_lossy(&body), &page_url);
+
+        if let Some(oembed_url) = metadata.oembed_url.clone() {
+            match self.fetch_oembed(&oembed_url).await {
+                Ok((oembed, base)) => metadata.merge_oembed(oembed, &base),
+                Err(e) => debug!("Ignoring oEmbed data of {}: {}", page_url, e),
+            }
+        }
+
+        let image = match metadata.image_url.clone() {
+            Some(image_url) => match self.fetch_image(&image_url).await {
+                Ok(image) => Some(image),
+                Err(e) => {
+                    warn!("Failed to fetch preview image {}: {}", image_url, e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        Ok(FetchedPage {
+            url: page_url.to_string(),
+            metadata,
+            image,
+        })
+    }
+
+    /// Sends a GET request, refusing blocked URLs and error statuses
+    async fn get(&self, url: Url, accept: &str) -> Result<Response, PreviewCardsError> {
+        if self.config.is_blocked(&url) {
+            return Err(PreviewCardsError::Blocked(url.to_string()));
+        }
+
+        let response = self
+            .client
+            .get(url)
+            .header(header::ACCEPT, accept)
+            .send()
+            .await?
+            .error_for_status()?;
+        if self.config.is_blocked(response.url()) {
+            return Err(PreviewCardsError::Blocked(response.url().to_string()));
+        }
+        Ok(response)
+    }
+
+    async fn fetch_oembed(&self, url: &str) -> Result<(OEmbed, Url), PreviewCardsError> {
+        let url = Url::parse(url).map_err(|e| PreviewCardsError::InvalidUrl(e.to_string()))?;
+        let response = self.get(url, "application/json").await?;
+        let base = response.url().clone();
+        let body = read_limited(response, self.config.max_html_bytes).await?;
+        let oembed = serde_json::from_slice(&body)
+            .map_err(|e| PreviewCardsError::Unsupported(format!("invalid oEmbed data: {}", e)))?;
+        Ok((oembed, base))
+    }
+
+    async fn fetch_image(&self, url: &str) -> Result<Vec<u8>, PreviewCardsError> {
+        let url = Url::parse(url).map_err(|e| PreviewCardsError::InvalidUrl(e.to_string()))?;
+        let response = self.get(url, "image/*").await?;
+        read_limited(response, self.config.max_image_bytes).await
+    }
+}
+
+/// Reads a response body, failing as soon as it exceeds `limit` bytes
+async fn read_limited(mut response: Response, limit: usize) -> Result<Vec<u8>, PreviewCardsError> {
+    if response
+        .content_length()
+        .is_some_and(|length| length > limit as u64)
+    {
+        return Err(PreviewCardsError::TooLarge(limit));
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > limit {
+            return Err(PreviewCardsError::TooLarge(limit));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_blocked() {
+        let config = PreviewCardConfig {
+            blocked_domains: vec!["blocked.example".to_string()],
+            ..PreviewCardConfig::default()
+        };
+        let blocked = |url: &str| config.is_blocked(&Url::parse(url).unwrap());
+
+        assert!(blocked("https://blocked.example/page"));
+        assert!(blocked("https://www.Blocked.example./page"));
+        assert!(blocked("ftp://example.com/file"));
+        assert!(!blocked("https://notblocked.example/page"));
+        assert!(!blocked("http://example.com/"));
+
+        assert!(blocked("http://127.0.0.1:3000/"));
+        assert!(blocked("http://10.1.2.3/"));
+        assert!(blocked("http://[::1]/"));
+        assert!(blocked("http://[::ffff:192.168.0.1]/"));
+        assert!(!blocked("http://93.184.215.14/"));
+
+        let development = PreviewCardConfig {
+            allow_private_addresses: true,
+            ..PreviewCardConfig::default()
+        };
+        assert!(!development.is_blocked(&Url::parse("http://127.0.0.1:3000/").unwrap()));
+    }
+
+    #[test]
+    fn test_is_public_address() {
+        let public = |ip: &str| is_public_address(ip.parse().unwrap());
+
+        for ip in [
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.5.4",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!public(ip), "{} is not public", ip);
+        }
+        assert!(public("93.184.215.14"));
+        assert!(public("2606:4700::1111"));
+    }
+}
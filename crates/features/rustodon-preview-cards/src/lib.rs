@@ -1,14 +1,723 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
+//! Link preview cards for Rustodon
+//!
+//! When a status links to a web page, a background job fetches the page,
+//! reads its OpenGraph, Twitter card and oEmbed metadata, stores the
+//! resized preview image through `rustodon-media` and attaches the
+//! resulting card to the status. Cards are shared by every status linking
+//! to the same URL and refetched once they get stale.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_preview_cards::{FetchPreviewCardJob, PreviewCardConfig, PreviewCardFetcher};
+//!
+//! let fetcher = PreviewCardFetcher::new(PreviewCardConfig::from_env())?;
+//! let job = FetchPreviewCardJob::new(pool, fetcher, storage, status.id);
+//! job.execute().await?;
+//! ```
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+pub mod fetcher;
+pub mod parser;
+
+pub use fetcher::{FetchedPage, PreviewCardConfig, PreviewCardFetcher};
+pub use parser::{OEmbed, PageMetadata};
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use regex::Regex;
+use rustodon_media::{store_preview_card_image, StorageConfig};
+use rustodon_workers::{Job, WorkerError};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::OnceLock;
+use tracing::{debug, info, warn};
+
+/// How long a card is reused before its page is fetched again
+pub const CARD_REFRESH_DAYS: i64 = 14;
+
+/// Preview cards error
+#[derive(Debug, thiserror::Error)]
+pub enum PreviewCardsError {
+    #[error("Database error: {0}")]
+    Database(String),
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+    #[error("Blocked URL: {0}")]
+    Blocked(String),
+    #[error("Response larger than {0} bytes")]
+    TooLarge(usize),
+    #[error("Unsupported content: {0}")]
+    Unsupported(String),
+    #[error("Status not found: {0}")]
+    StatusNotFound(i64),
+}
+
+impl From<sqlx::Error> for PreviewCardsError {
+    fn from(err: sqlx::Error) -> Self {
+        PreviewCardsError::Database(err.to_string())
+    }
+}
+
+/// A link preview card
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreviewCard {
+    pub id: i64,
+    pub url: String,
+    pub title: String,
+    pub description: String,
+    pub card_type: String,
+    pub author_name: String,
+    pub author_url: String,
+    pub provider_name: String,
+    pub provider_url: String,
+    pub html: String,
+    pub width: i32,
+    pub height: i32,
+    pub image_url: Option<String>,
+    pub blurhash: Option<String>,
+    pub embed_url: String,
+    pub language: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+struct PreviewCardRow {
+    id: i64,
+    url: String,
+    title: String,
+    description: String,
+    card_type: String,
+    author_name: String,
+    author_url: String,
+    provider_name: String,
+    provider_url: String,
+    html: String,
+    width: i32,
+    height: i32,
+    image_url: Option<String>,
+    blurhash: Option<String>,
+    embed_url: String,
+    language: Option<String>,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+}
+
+impl From<PreviewCardRow> for PreviewCard {
+    fn from(row: PreviewCardRow) -> Self {
+        Self {
+            id: row.id,
+            url: row.url,
+            title: row.title,
+            description: row.description,
+            card_type: row.card_type,
+            author_name: row.author_name,
+            author_url: row.author_url,
+            provider_name: row.provider_name,
+            provider_url: row.provider_url,
+            html: row.html,
+            width: row.width,
+            height: row.height,
+            image_url: row.image_url,
+            blurhash: row.blurhash,
+            embed_url: row.embed_url,
+            language: row.language,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+            updated_at: DateTime::from_naive_utc_and_offset(row.updated_at, Utc),
+        }
+    }
+}
+
+impl PreviewCard {
+    /// Gets the card of a URL, if one was stored
+    pub async fn get_by_url(pool: &PgPool, url: &str) -> Result<Option<Self>, PreviewCardsError> {
+        let row = sqlx::query_as!(
+            PreviewCardRow,
+            r#"
+            SELECT id, url, title, description, card_type, author_name, author_url,
+                   provider_name, provider_url, html, width, height, image_url, blurhash,
+                   embed_url, language, created_at, updated_at
+            FROM preview_cards
+            WHERE url = $1
+            "#,
+            url
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row.map(Self::from))
+    }
+
+    /// Gets the cards with the given IDs, keyed by ID
+    pub async fn get_by_ids(
+        pool: &PgPool,
+        ids: &[i64],
+    ) -> Result<HashMap<i64, Self>, PreviewCardsError> {
+        if ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let rows = sqlx::query_as!(
+            PreviewCardRow,
+            r#"
+            SELECT id, url, title, description, card_type, author_name, author_url,
+                   provider_name, provider_url, html, width, height, image_url, blurhash,
+                   embed_url, language, created_at, updated_at
+            FROM preview_cards
+            WHERE id = ANY($1)
+            "#,
+            ids
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.id, Self::from(row)))
+            .collect())
+    }
+
+    /// Gets the cards attached to the given statuses, keyed by status ID
+    pub async fn get_by_status_ids(
+        pool: &PgPool,
+        status_ids: &[i64],
+    ) -> Result<HashMap<i64, Self>, PreviewCardsError> {
+        if status_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT pcs.status_id, pc.id, pc.url, pc.title, pc.description, pc.card_type,
+                   pc.author_name, pc.author_url, pc.provider_name, pc.provider_url, pc.html,
+                   pc.width, pc.height, pc.image_url, pc.blurhash, pc.embed_url, pc.language,
+                   pc.created_at, pc.updated_at
+            FROM preview_cards_statuses pcs
+            JOIN preview_cards pc ON pc.id = pcs.preview_card_id
+            WHERE pcs.status_id = ANY($1)
+            "#,
+            status_ids
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let card = PreviewCardRow {
+                    id: row.id,
+                    url: row.url,
+                    title: row.title,
+                    description: row.description,
+                    card_type: row.card_type,
+                    author_name: row.author_name,
+                    author_url: row.author_url,
+                    provider_name: row.provider_name,
+                    provider_url: row.provider_url,
+                    html: row.html,
+                    width: row.width,
+                    height: row.height,
+                    image_url: row.image_url,
+                    blurhash: row.blurhash,
+                    embed_url: row.embed_url,
+                    language: row.language,
+                    created_at: row.created_at,
+                    updated_at: row.updated_at,
+                };
+                (row.status_id, Self::from(card))
+            })
+            .collect())
+    }
+
+    /// Stores the card of a URL, replacing an older card of the same URL
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `url` - URL the status links to
+    /// * `metadata` - Metadata of the page
+    /// * `image` - Stored preview image, if any
+    pub async fn upsert(
+        pool: &PgPool,
+        url: &str,
+        metadata: &PageMetadata,
+        image: Option<&rustodon_media::StoredImage>,
+    ) -> Result<Self, PreviewCardsError> {
+        // Link cards take the size of their image, embeds keep the provider's
+        let (width, height) = match image {
+            Some(image) if metadata.width == 0 || metadata.height == 0 => {
+                (image.width as i32, image.height as i32)
+            }
+            _ => (metadata.width, metadata.height),
+        };
+
+        let row = sqlx::query_as!(
+            PreviewCardRow,
+            r#"
+            INSERT INTO preview_cards (url, title, description, card_type, author_name,
+                                       author_url, provider_name, provider_url, html, width,
+                                       height, image_url, blurhash, embed_url, language)
+            VALUES ($1, LEFT($2, 255), $3, $4, LEFT($5, 255), $6, LEFT($7, 255), $8, $9, $10,
+                    $11, $12, $13, $14, $15)
+            ON CONFLICT (url) DO UPDATE SET
+                title = EXCLUDED.title,
+                description = EXCLUDED.description,
+                card_type = EXCLUDED.card_type,
+                author_name = EXCLUDED.author_name,
+                author_url = EXCLUDED.author_url,
+                provider_name = EXCLUDED.provider_name,
+                provider_url = EXCLUDED.provider_url,
+                html = EXCLUDED.html,
+                width = EXCLUDED.width,
+                height = EXCLUDED.height,
+                image_url = EXCLUDED.image_url,
+                blurhash = EXCLUDED.blurhash,
+                embed_url = EXCLUDED.embed_url,
+                language = EXCLUDED.language
+            RETURNING id, url, title, description, card_type, author_name, author_url,
+                      provider_name, provider_url, html, width, height, image_url, blurhash,
+                      embed_url, language, created_at, updated_at
+            "#,
+            url,
+            metadata.title,
+            metadata.description,
+            metadata.card_type,
+            metadata.author_name,
+            metadata.author_url,
+            metadata.provider_name,
+            metadata.provider_url,
+            metadata.html,
+            width,
+            height,
+            image.map(|image| image.url.clone()),
+            image.map(|image| image.blurhash.clone()),
+            metadata.embed_url,
+            metadata.language,
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(Self::from(row))
+    }
+
+    /// Attaches the card to a status, replacing the status' previous card
+    pub async fn attach(
+        pool: &PgPool,
+        status_id: i64,
+        card_id: i64,
+    ) -> Result<(), PreviewCardsError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO preview_cards_statuses (status_id, preview_card_id)
+            VALUES ($1, $2)
+            ON CONFLICT (status_id) DO UPDATE SET preview_card_id = EXCLUDED.preview_card_id
+            "#,
+            status_id,
+            card_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Whether the card is old enough to fetch its page again
+    pub fn stale(&self, now: DateTime<Utc>) -> bool {
+        self.updated_at + Duration::days(CARD_REFRESH_DAYS) < now
+    }
+}
+
+fn anchor_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?is)<a\s([^>]*)>").expect("valid regex"))
+}
+
+fn href_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r#"(?i)\bhref\s*=\s*(?:"([^"]*)"|'([^']*)')"#).expect("valid regex")
+    })
+}
+
+fn class_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r#"(?i)\b(?:class|rel)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).expect("valid regex")
+    })
+}
+
+fn bare_url_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r#"https?://[^\s<>"']+"#).expect("valid regex"))
+}
+
+fn tag_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?s)<[^>]*>").expect("valid regex"))
+}
+
+/// Finds the URL a status' card should preview
+///
+/// Links to mentioned accounts and hashtags are skipped. Content without
+/// any links is searched for bare URLs instead.
+///
+/// # Arguments
+///
+/// * `content` - Rendered HTML content of the status
+pub fn first_link_url(content: &str) -> Option<String> {
+    let mut anchors = anchor_regex().captures_iter(content).peekable();
+    if anchors.peek().is_some() {
+        return anchors.find_map(|caps| {
+            let attrs = &caps[1];
+            let skipped = class_regex().captures_iter(attrs).any(|class| {
+                let value = class
+                    .get(1)
+                    .or_else(|| class.get(2))
+                    .map_or("", |m| m.as_str());
+                value
+                    .split_whitespace()
+                    .any(|name| matches!(name, "mention" | "hashtag" | "u-url" | "tag"))
+            });
+            let href = href_regex().captures(attrs)?;
+            let href = href.get(1).or_else(|| href.get(2))?.as_str();
+            (!skipped && href.starts_with("http")).then(|| parser::decode_entities(href))
+        });
+    }
+
+    let text = parser::decode_entities(&tag_regex().replace_all(content, " "));
+    bare_url_regex().find(&text).map(|url| {
+        url.as_str()
+            .trim_end_matches(['.', ',', ';', ':', '!', '?', ')'])
+            .to_string()
+    })
+}
+
+/// Fetches and attaches the preview card of a status
+///
+/// Statuses with media attachments don't get a card. A card already
+/// stored for the URL is reused until it gets stale.
+///
+/// # Arguments
+///
+/// * `pool` - Database connection pool
+/// * `fetcher` - Fetcher used for the linked page
+/// * `storage` - Storage of the preview image
+/// * `status_id` - ID of the status
+///
+/// # Returns
+///
+/// The attached card, or None if the status has nothing to preview
+pub async fn fetch_for_status(
+    pool: &PgPool,
+    fetcher: &PreviewCardFetcher,
+    storage: &StorageConfig,
+    status_id: i64,
+) -> Result<Option<PreviewCard>, PreviewCardsError> {
+    let status = sqlx::query!(
+        r#"
+        SELECT content,
+               COALESCE(jsonb_array_length(media_attachments), 0) AS "media_count!"
+        FROM statuses
+        WHERE id = $1 AND deleted_at IS NULL
+        "#,
+        status_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(PreviewCardsError::StatusNotFound(status_id))?;
+
+    if status.media_count > 0 {
+        return Ok(None);
+    }
+    let Some(url) = first_link_url(&status.content) else {
+        return Ok(None);
+    };
+
+    let card = match PreviewCard::get_by_url(pool, &url).await? {
+        Some(card) if !card.stale(Utc::now()) => {
+            debug!("Reusing preview card {} for {}", card.id, url);
+            card
+        }
+        _ => {
+            let page = fetcher.fetch(&url).await?;
+            if page.metadata.title.is_empty() && page.metadata.html.is_empty() {
+                debug!("No card metadata found at {}", page.url);
+                return Ok(None);
+            }
+
+            let image = match &page.image {
+                Some(data) => match store_preview_card_image(storage, data).await {
+                    Ok(image) => Some(image),
+                    Err(e) => {
+                        warn!("Failed to store preview image of {}: {}", url, e);
+                        None
+                    }
+                },
+                None => None,
+            };
+            PreviewCard::upsert(pool, &url, &page.metadata, image.as_ref()).await?
+        }
+    };
+
+    PreviewCard::attach(pool, status_id, card.id).await?;
+    info!("Attached preview card {} to status {}", card.id, status_id);
+    Ok(Some(card))
+}
+
+/// Background job fetching the preview card of a new status
+pub struct FetchPreviewCardJob {
+    pool: PgPool,
+    fetcher: PreviewCardFetcher,
+    storage: StorageConfig,
+    status_id: i64,
+}
+
+impl FetchPreviewCardJob {
+    /// Creates the job
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `fetcher` - Fetcher used for the linked page
+    /// * `storage` - Storage of the preview image
+    /// * `status_id` - ID of the status
+    pub fn new(
+        pool: PgPool,
+        fetcher: PreviewCardFetcher,
+        storage: StorageConfig,
+        status_id: i64,
+    ) -> Self {
+        Self {
+            pool,
+            fetcher,
+            storage,
+            status_id,
+        }
+    }
+}
+
+impl Job for FetchPreviewCardJob {
+    fn name(&self) -> &'static str {
+        "FetchPreviewCardJob"
+    }
+
+    fn execute(&self) -> Pin<Box<dyn Future<Output = Result<(), WorkerError>> + Send>> {
+        let pool = self.pool.clone();
+        let fetcher = self.fetcher.clone();
+        let storage = self.storage.clone();
+        let status_id = self.status_id;
+        Box::pin(async move {
+            fetch_for_status(&pool, &fetcher, &storage, status_id)
+                .await
+                .map_err(|e| WorkerError::Job(e.to_string()))?;
+            Ok(())
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use axum::{
+        http::header,
+        response::{IntoResponse, Redirect},
+        routing::get,
+        Router,
+    };
+    use std::time::Duration as StdDuration;
 
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn test_first_link_url() {
+        let html = r#"<p><span class="h-card"><a href="https://social.example/@alice" class="u-url mention">@alice</a></span>
+            <a href="https://social.example/tags/rust" class="mention hashtag" rel="tag">#rust</a>
+            <a href="https://example.com/post?a=1&amp;b=2" rel="nofollow noopener">example.com/post</a></p>"#;
+        assert_eq!(
+            first_link_url(html).as_deref(),
+            Some("https://example.com/post?a=1&b=2")
+        );
+
+        assert_eq!(
+            first_link_url("<p>Read this: https://example.com/a.</p>").as_deref(),
+            Some("https://example.com/a")
+        );
+        assert!(first_link_url("<p>No links here</p>").is_none());
+    }
+
+    fn png() -> Vec<u8> {
+        let mut data = Vec::new();
+        image::DynamicImage::new_rgb8(1200, 630)
+            .write_to(
+                &mut std::io::Cursor::new(&mut data),
+                image::ImageFormat::Png,
+            )
+            .unwrap();
+        data
+    }
+
+    /// Starts a local HTTP server serving fixture pages
+    async fn fixture_server() -> String {
+        let page = r#"<html lang="en"><head>
+            <title>Fallback</title>
+            <meta property="og:title" content="Fixture page">
+            <meta property="og:description" content="A page served by the test">
+            <meta property="og:image" content="/image.png">
+            <link rel="alternate" type="application/json+oembed" href="/oembed.json">
+            </head><body>Hello</body></html>"#;
+        let oembed = r#"{"type": "rich", "provider_name": "Fixtures",
+            "html": "<iframe src=\"https://fixtures.example/embed\"></iframe>",
+            "width": 400, "height": 300}"#;
+
+        let app = Router::new()
+            .route(
+                "/page",
+                get(move || async move { ([(header::CONTENT_TYPE, "text/html")], page) }),
+            )
+            .route(
+                "/oembed.json",
+                get(move || async move { ([(header::CONTENT_TYPE, "application/json")], oembed) }),
+            )
+            .route(
+                "/image.png",
+                get(|| async { ([(header::CONTENT_TYPE, "image/png")], png()) }),
+            )
+            .route(
+                "/large",
+                get(|| async { ([(header::CONTENT_TYPE, "text/html")], "a".repeat(64 * 1024)) }),
+            )
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(StdDuration::from_secs(5)).await;
+                    ([(header::CONTENT_TYPE, "text/html")], "late").into_response()
+                }),
+            )
+            .route(
+                "/file.pdf",
+                get(|| async { ([(header::CONTENT_TYPE, "application/pdf")], "%PDF") }),
+            );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    /// Fetcher allowed to reach the fixture servers, which listen on loopback
+    fn fetcher(config: PreviewCardConfig) -> PreviewCardFetcher {
+        PreviewCardFetcher::new(PreviewCardConfig {
+            allow_private_addresses: true,
+            ..config
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_fetch_page_with_oembed_and_image() {
+        let base = fixture_server().await;
+        let page = fetcher(PreviewCardConfig::default())
+            .fetch(&format!("{}/page", base))
+            .await
+            .unwrap();
+
+        assert_eq!(page.metadata.title, "Fixture page");
+        assert_eq!(page.metadata.description, "A page served by the test");
+        assert_eq!(page.metadata.card_type, "rich");
+        assert_eq!(page.metadata.provider_name, "Fixtures");
+        assert_eq!(page.metadata.width, 400);
+        assert_eq!(page.image.as_deref(), Some(png().as_slice()));
+
+        let dir = tempfile::tempdir().unwrap();
+        let storage = StorageConfig {
+            media_root: dir.path().to_path_buf(),
+            ..StorageConfig::default()
+        };
+        let stored = store_preview_card_image(&storage, page.image.as_deref().unwrap())
+            .await
+            .unwrap();
+        assert_eq!((stored.width, stored.height), (640, 336));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_limits() {
+        let base = fixture_server().await;
+        let limited = fetcher(PreviewCardConfig {
+            timeout: StdDuration::from_millis(500),
+            max_html_bytes: 1024,
+            ..PreviewCardConfig::default()
+        });
+
+        assert!(matches!(
+            limited.fetch(&format!("{}/large", base)).await,
+            Err(PreviewCardsError::TooLarge(1024))
+        ));
+        assert!(matches!(
+            limited.fetch(&format!("{}/slow", base)).await,
+            Err(PreviewCardsError::Http(e)) if e.is_timeout()
+        ));
+        assert!(matches!(
+            limited.fetch(&format!("{}/file.pdf", base)).await,
+            Err(PreviewCardsError::Unsupported(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_blocked_domains() {
+        let base = fixture_server().await;
+        let port = base.rsplit(':').next().unwrap().to_string();
+        let blocking = fetcher(PreviewCardConfig {
+            blocked_domains: vec!["localhost".to_string()],
+            ..PreviewCardConfig::default()
+        });
+
+        assert!(matches!(
+            blocking
+                .fetch(&format!("http://localhost:{}/page", port))
+                .await,
+            Err(PreviewCardsError::Blocked(_))
+        ));
+
+        // Redirects into a blocked domain are refused too
+        let target = format!("http://localhost:{}/page", port);
+        let app = Router::new().route(
+            "/",
+            get(move || {
+                let target = target.clone();
+                async move { Redirect::temporary(&target) }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        assert!(blocking.fetch(&format!("http://{}/", addr)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_refuses_private_addresses() {
+        let base = fixture_server().await;
+        let port = base.rsplit(':').next().unwrap().to_string();
+        let public_only = PreviewCardFetcher::new(PreviewCardConfig::default()).unwrap();
+
+        assert!(matches!(
+            public_only.fetch(&format!("{}/page", base)).await,
+            Err(PreviewCardsError::Blocked(_))
+        ));
+        // Names resolving to loopback are refused when connecting
+        assert!(matches!(
+            public_only
+                .fetch(&format!("http://localhost:{}/page", port))
+                .await,
+            Err(PreviewCardsError::Http(_))
+        ));
     }
 }
@@ -0,0 +1,484 @@
+//! Page metadata parsing
+//!
+//! Extracts card metadata from an HTML page. OpenGraph properties take
+//! precedence over Twitter card properties, which take precedence over
+//! plain HTML (`<title>`, `<meta name="description">`). The page may
+//! also advertise an oEmbed endpoint, whose response is merged in by
+//! [`PageMetadata::merge_oembed`].
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use regex::Regex;
+use reqwest::Url;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Card metadata found on a page
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PageMetadata {
+    /// Canonical URL of the page
+    pub url: Option<String>,
+    pub title: String,
+    pub description: String,
+    /// Card type: `link`, `photo`, `video` or `rich`
+    pub card_type: String,
+    pub author_name: String,
+    pub author_url: String,
+    pub provider_name: String,
+    pub provider_url: String,
+    /// Embed HTML of video and rich cards
+    pub html: String,
+    pub width: i32,
+    pub height: i32,
+    /// Absolute URL of the preview image
+    pub image_url: Option<String>,
+    pub embed_url: String,
+    pub language: Option<String>,
+    /// Absolute URL of the page's JSON oEmbed endpoint
+    pub oembed_url: Option<String>,
+}
+
+/// An oEmbed response
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct OEmbed {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub title: Option<String>,
+    pub author_name: Option<String>,
+    pub author_url: Option<String>,
+    pub provider_name: Option<String>,
+    pub provider_url: Option<String>,
+    pub html: Option<String>,
+    pub url: Option<String>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub thumbnail_url: Option<String>,
+}
+
+fn regex(cell: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    cell.get_or_init(|| Regex::new(pattern).expect("valid regex"))
+}
+
+fn meta_tag_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    regex(&RE, r"(?is)<meta\s[^>]*>")
+}
+
+fn link_tag_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    regex(&RE, r"(?is)<link\s[^>]*>")
+}
+
+fn title_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    regex(&RE, r"(?is)<title[^>]*>(.*?)</title>")
+}
+
+fn html_tag_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    regex(&RE, r"(?is)<html\s[^>]*>")
+}
+
+fn attribute_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    regex(
+        &RE,
+        r#"(?s)([a-zA-Z_:.-]+)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#,
+    )
+}
+
+fn iframe_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    regex(
+        &RE,
+        r#"(?is)^\s*<iframe(\s(?:[^<>"']|"[^"]*"|'[^']*')*)?>\s*</iframe\s*>\s*$"#,
+    )
+}
+
+fn entity_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    regex(&RE, r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);")
+}
+
+/// Parses the attributes of a tag into a lowercase-keyed map
+fn attributes(tag: &str) -> HashMap<String, String> {
+    attribute_regex()
+        .captures_iter(tag)
+        .map(|caps| {
+            let value = caps
+                .get(2)
+                .or_else(|| caps.get(3))
+                .or_else(|| caps.get(4))
+                .map_or("", |m| m.as_str());
+            (caps[1].to_ascii_lowercase(), decode_entities(value))
+        })
+        .collect()
+}
+
+/// Decodes the HTML entities found in attribute values and titles
+pub fn decode_entities(text: &str) -> String {
+    entity_regex()
+        .replace_all(text, |caps: &regex::Captures| {
+            let entity = &caps[1];
+            let decoded = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+                    u32::from_str_radix(&entity[2..], 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                }
+                _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(char::from_u32),
+                _ => None,
+            };
+            decoded.map_or_else(|| caps[0].to_string(), String::from)
+        })
+        .into_owned()
+}
+
+/// Collapses whitespace and trims a text value
+fn clean(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Resolves a possibly relative URL against the page URL
+fn resolve(base: &Url, href: &str) -> Option<String> {
+    let url = base.join(href.trim()).ok()?;
+    matches!(url.scheme(), "http" | "https").then(|| url.to_string())
+}
+
+/// Parses card metadata out of an HTML page
+///
+/// # Arguments
+///
+/// * `html` - Page source
+/// * `base` - URL the page was fetched from, used to resolve relative URLs
+///
+/// # Returns
+///
+/// Metadata with the `link` card type; an empty title means the page
+/// doesn't describe itself well enough for a card.
+pub fn parse_html(html: &str, base: &Url) -> PageMetadata {
+    let mut properties: HashMap<String, String> = HashMap::new();
+    for tag in meta_tag_regex().find_iter(html) {
+        let attrs = attributes(tag.as_str());
+        let key = attrs.get("property").or_else(|| attrs.get("name"));
+        if let (Some(key), Some(content)) = (key, attrs.get("content")) {
+            properties
+                .entry(key.to_ascii_lowercase())
+                .or_insert_with(|| content.clone());
+        }
+    }
+    let property = |keys: &[&str]| -> Option<String> {
+        keys.iter()
+            .filter_map(|key| properties.get(*key))
+            .map(|value| clean(value))
+            .find(|value| !value.is_empty())
+    };
+
+    let mut canonical = None;
+    let mut oembed_url = None;
+    for tag in link_tag_regex().find_iter(html) {
+        let attrs = attributes(tag.as_str());
+        let (Some(rel), Some(href)) = (attrs.get("rel"), attrs.get("href")) else {
+            continue;
+        };
+        let rel = rel.to_ascii_lowercase();
+        let kind = attrs.get("type").map(|t| t.to_ascii_lowercase());
+        if rel == "canonical" && canonical.is_none() {
+            canonical = resolve(base, href);
+        } else if rel == "alternate"
+            && kind.as_deref() == Some("application/json+oembed")
+            && oembed_url.is_none()
+        {
+            oembed_url = resolve(base, href);
+        }
+    }
+
+    let html_title = title_regex()
+        .captures(html)
+        .map(|caps| clean(&decode_entities(&caps[1])));
+    let language = html_tag_regex()
+        .find(html)
+        .and_then(|tag| attributes(tag.as_str()).remove("lang"))
+        .or_else(|| property(&["og:locale"]))
+        .and_then(|lang| {
+            lang.split(['-', '_'])
+                .next()
+                .map(|code| code.to_ascii_lowercase())
+        })
+        .filter(|code| code.len() == 2 || code.len() == 3);
+
+    PageMetadata {
+        url: property(&["og:url"])
+            .and_then(|url| resolve(base, &url))
+            .or(canonical),
+        title: property(&["og:title", "twitter:title"])
+            .or(html_title)
+            .unwrap_or_default(),
+        description: property(&["og:description", "twitter:description", "description"])
+            .unwrap_or_default(),
+        card_type: "link".to_string(),
+        author_name: property(&["author"]).unwrap_or_default(),
+        provider_name: property(&["og:site_name", "twitter:site"]).unwrap_or_default(),
+        image_url: property(&[
+            "og:image",
+            "og:image:url",
+            "og:image:secure_url",
+            "twitter:image",
+            "twitter:image:src",
+        ])
+        .and_then(|image| resolve(base, &image)),
+        language,
+        oembed_url,
+        ..PageMetadata::default()
+    }
+}
+
+/// Escapes a value for a double-quoted attribute
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Rebuilds embed HTML consisting of a single empty iframe
+///
+/// Only an https `src`, numeric `width` and `height` and `allowfullscreen`
+/// are kept from the provider's iframe; every other attribute, such as
+/// `srcdoc` or event handlers, could run scripts in the client. Embeds that
+/// are anything else are dropped and the card falls back to a plain link.
+fn sanitize_iframe(html: &str) -> Option<String> {
+    let tag = iframe_regex().captures(html)?;
+    let tag = tag.get(1).map_or("", |m| m.as_str());
+    let attrs = attributes(tag);
+
+    let src = Url::parse(attrs.get("src")?.trim()).ok()?;
+    if src.scheme() != "https" {
+        return None;
+    }
+    let mut iframe = format!(r#"<iframe src="{}""#, escape_attribute(src.as_str()));
+    for name in ["width", "height"] {
+        if let Some(value) = attrs.get(name).and_then(|v| v.trim().parse::<u32>().ok()) {
+            iframe.push_str(&format!(r#" {}="{}""#, name, value));
+        }
+    }
+    // Valueless attributes are whatever is left once valued ones are removed
+    let allowfullscreen = attribute_regex()
+        .replace_all(tag, " ")
+        .split_whitespace()
+        .any(|word| word.eq_ignore_ascii_case("allowfullscreen"));
+    iframe.push_str(r#" frameborder="0""#);
+    if allowfullscreen {
+        iframe.push_str(" allowfullscreen");
+    }
+    iframe.push_str("></iframe>");
+    Some(iframe)
+}
+
+impl PageMetadata {
+    /// Merges an oEmbed response into the page metadata
+    ///
+    /// oEmbed values win over the page's own metadata, since the provider
+    /// publishes them specifically for embedding.
+    ///
+    /// # Arguments
+    ///
+    /// * `oembed` - Parsed oEmbed response
+    /// * `base` - URL of the oEmbed endpoint, used to resolve relative URLs
+    pub fn merge_oembed(&mut self, oembed: OEmbed, base: &Url) {
+        let set = |target: &mut String, value: Option<String>| {
+            if let Some(value) = value.map(|v| clean(&v)).filter(|v| !v.is_empty()) {
+                *target = value;
+            }
+        };
+        set(&mut self.title, oembed.title);
+        set(&mut self.author_name, oembed.author_name);
+        set(&mut self.provider_name, oembed.provider_name);
+        if let Some(url) = oembed.author_url.and_then(|url| resolve(base, &url)) {
+            self.author_url = url;
+        }
+        if let Some(url) = oembed.provider_url.and_then(|url| resolve(base, &url)) {
+            self.provider_url = url;
+        }
+        if let Some(url) = oembed.thumbnail_url.and_then(|url| resolve(base, &url)) {
+            self.image_url = Some(url);
+        }
+
+        match oembed.kind.as_str() {
+            "photo" => {
+                if let Some(url) = oembed.url.and_then(|url| resolve(base, &url)) {
+                    self.card_type = "photo".to_string();
+                    self.embed_url = url.clone();
+                    self.image_url.get_or_insert(url);
+                    self.width = oembed.width.unwrap_or(0);
+                    self.height = oembed.height.unwrap_or(0);
+                }
+            }
+            "video" | "rich" => {
+                if let Some(html) = oembed.html.as_deref().and_then(sanitize_iframe) {
+                    self.card_type = oembed.kind;
+                    self.html = html;
+                    self.width = oembed.width.unwrap_or(0);
+                    self.height = oembed.height.unwrap_or(0);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base() -> Url {
+        Url::parse("https://example.com/articles/1").unwrap()
+    }
+
+    #[test]
+    fn test_parse_html_prefers_opengraph() {
+        let html = r#"<!DOCTYPE html>
+            <html lang="en-GB"><head>
+            <title>HTML title</title>
+            <meta name="twitter:title" content="Twitter title">
+            <meta property="og:title" content="OG &amp; title">
+            <meta name="description" content="Plain description">
+            <meta name="twitter:description" content='Twitter description'>
+            <meta content="/images/cover.png" property="og:image" />
+            <meta property="og:site_name" content="Example">
+            <link rel="alternate" type="application/json+oembed" href="/oembed?url=1">
+            </head></html>"#;
+
+        let metadata = parse_html(html, &base());
+        assert_eq!(metadata.title, "OG & title");
+        assert_eq!(metadata.description, "Twitter description");
+        assert_eq!(metadata.provider_name, "Example");
+        assert_eq!(metadata.card_type, "link");
+        assert_eq!(
+            metadata.image_url.as_deref(),
+            Some("https://example.com/images/cover.png")
+        );
+        assert_eq!(
+            metadata.oembed_url.as_deref(),
+            Some("https://example.com/oembed?url=1")
+        );
+        assert_eq!(metadata.language.as_deref(), Some("en"));
+    }
+
+    #[test]
+    fn test_parse_html_falls_back_to_title() {
+        let html = "<html><head><title>\n  Just a\n title &#8212; site </title>\
+            <meta name=description content=Short></head></html>";
+
+        let metadata = parse_html(html, &base());
+        assert_eq!(metadata.title, "Just a title \u{2014} site");
+        assert_eq!(metadata.description, "Short");
+        assert!(metadata.image_url.is_none());
+        assert!(metadata.oembed_url.is_none());
+    }
+
+    #[test]
+    fn test_merge_oembed() {
+        let mut metadata = parse_html("<title>Page</title>", &base());
+        metadata.merge_oembed(
+            OEmbed {
+                kind: "video".to_string(),
+                title: Some("Video".to_string()),
+                author_name: Some("Someone".to_string()),
+                html: Some(r#"<iframe src="https://example.com/embed/1"></iframe>"#.to_string()),
+                width: Some(480),
+                height: Some(270),
+                thumbnail_url: Some("/thumb.jpg".to_string()),
+                ..OEmbed::default()
+            },
+            &base(),
+        );
+        assert_eq!(metadata.card_type, "video");
+        assert_eq!(metadata.title, "Video");
+        assert_eq!(metadata.author_name, "Someone");
+        assert_eq!(metadata.width, 480);
+        assert_eq!(
+            metadata.image_url.as_deref(),
+            Some("https://example.com/thumb.jpg")
+        );
+
+        let mut scripted = parse_html("<title>Page</title>", &base());
+        scripted.merge_oembed(
+            OEmbed {
+                kind: "rich".to_string(),
+                html: Some("<script>alert(1)</script>".to_string()),
+                ..OEmbed::default()
+            },
+            &base(),
+        );
+        assert_eq!(scripted.card_type, "link");
+        assert!(scripted.html.is_empty());
+    }
+
+    #[test]
+    fn test_sanitize_iframe() {
+        assert_eq!(
+            sanitize_iframe(
+                r#"<IFRAME width="560" height=315 src="https://video.example/embed/1?a=1&amp;b=2"
+                    title="Video" allowfullscreen></iframe>"#
+            )
+            .as_deref(),
+            Some(
+                r#"<iframe src="https://video.example/embed/1?a=1&amp;b=2" width="560" height="315" frameborder="0" allowfullscreen></iframe>"#
+            )
+        );
+        assert_eq!(
+            sanitize_iframe(r#"<iframe src="https://video.example/1" width="100%"></iframe>"#)
+                .as_deref(),
+            Some(r#"<iframe src="https://video.example/1" frameborder="0"></iframe>"#)
+        );
+    }
+
+    #[test]
+    fn test_sanitize_iframe_rebuilds_hostile_embeds() {
+        // Attributes that run scripts are dropped
+        let sanitized = sanitize_iframe(
+            r#"<iframe src="https://video.example/1" srcdoc="<script>alert(1)</script>"
+                onload="alert(1)" ONerror=alert(1) style="x"></iframe>"#,
+        )
+        .unwrap();
+        assert_eq!(
+            sanitized,
+            r#"<iframe src="https://video.example/1" frameborder="0"></iframe>"#
+        );
+
+        // A value can't smuggle allowfullscreen or break out of its quotes
+        assert_eq!(
+            sanitize_iframe(
+                r#"<iframe title="allowfullscreen" src='https://a.example/"x'></iframe>"#
+            )
+            .as_deref(),
+            Some(r#"<iframe src="https://a.example/%22x" frameborder="0"></iframe>"#)
+        );
+
+        for html in [
+            r#"<iframe src="javascript:alert(1)"></iframe>"#,
+            r#"<iframe src="http://video.example/1"></iframe>"#,
+            r#"<iframe src="data:text/html,<script>alert(1)</script>"></iframe>"#,
+            r#"<iframe srcdoc="<script>alert(1)</script>"></iframe>"#,
+            r#"<iframe src="https://video.example/1"></iframe><script>alert(1)</script>"#,
+            r#"<iframe src="https://video.example/1"></iframe><img src=x onerror=alert(1)>"#,
+            r#"<iframe src="https://video.example/1"><script>alert(1)</script></iframe>"#,
+            r#"<iframe src="https://a.example/"></iframe><iframe src="https://b.example/"></iframe>"#,
+            r#"<div><iframe src="https://video.example/1"></iframe></div>"#,
+        ] {
+            assert!(sanitize_iframe(html).is_none(), "{} is refused", html);
+        }
+    }
+}
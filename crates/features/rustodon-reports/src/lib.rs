@@ -1,28 +1,144 @@
 //! Reports functionality for Rustodon
 //!
-//! This module provides report management functionality.
+//! Users report accounts, optionally pointing at some of their statuses and
+//! at the server rules they break. Reports land in a queue where staff
+//! assign them to themselves, discuss them in [`ReportNote`]s, and resolve
+//! or reopen them. Staff are told about new reports with `admin.report`
+//! notifications.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_reports::{NewReport, Report, ReportCategory, ReportFilter};
+//!
+//! let report = Report::create(&pool, reporter_id, NewReport {
+//!     target_account_id,
+//!     status_ids: vec![status_id],
+//!     rule_ids: vec![],
+//!     comment: "Selling followers".to_string(),
+//!     category: Some(ReportCategory::Spam),
+//! }).await?;
+//! report.notify_staff(&pool).await;
+//!
+//! let open = Report::search(&pool, &ReportFilter::default()).await?;
+//! let report = Report::resolve(&pool, report.id, moderator_id).await?;
+//! ```
 //!
 //! # Author
 //!
 //! arkSong (arksong2018@gmail.com)
 
+mod notes;
+
+pub use notes::{ReportNote, MAX_NOTE_LENGTH};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rustodon_notifications::{CreateNotificationRequest, Notification, NotificationType};
 use serde::{Deserialize, Serialize};
-use tracing::{error, info, trace};
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use tracing::{debug, info, trace, warn};
+
+/// Maximum length of a report comment
+pub const MAX_COMMENT_LENGTH: usize = 1_000;
+/// Maximum number of statuses attached to a report
+pub const MAX_STATUSES: usize = 100;
+/// Default number of reports listed
+pub const DEFAULT_LIMIT: i64 = 100;
+/// Maximum number of reports listed
+pub const MAX_LIMIT: i64 = 200;
 
 /// Report category
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ReportCategory {
+    /// Unsolicited advertising or repetitive content
     Spam,
-    Harassment,
-    Misinformation,
+    /// Content that is illegal where the server operates
+    Legal,
+    /// Content breaking one or more server rules
+    Violation,
     Other,
 }
 
-/// Update report request
+impl ReportCategory {
+    /// Database name of the category
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportCategory::Spam => "spam",
+            ReportCategory::Legal => "legal",
+            ReportCategory::Violation => "violation",
+            ReportCategory::Other => "other",
+        }
+    }
+
+    /// Parses a category by name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "spam" => Some(ReportCategory::Spam),
+            "legal" => Some(ReportCategory::Legal),
+            "violation" => Some(ReportCategory::Violation),
+            "other" => Some(ReportCategory::Other),
+            _ => None,
+        }
+    }
+}
+
+/// A report filed against an account
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct UpdateReportRequest {
+pub struct Report {
+    pub id: i64,
+    /// Account that filed the report
+    pub account_id: i64,
+    /// Account being reported
+    pub target_account_id: i64,
+    pub status_ids: Vec<i64>,
+    /// Server rules broken, for `violation` reports
+    pub rule_ids: Vec<i64>,
+    pub comment: String,
     pub category: ReportCategory,
-    pub comment: Option<String>,
+    /// Staff member handling the report
+    pub assigned_account_id: Option<i64>,
+    /// When the report was resolved
+    pub action_taken_at: Option<DateTime<Utc>>,
+    /// Staff member who resolved the report
+    pub action_taken_by_account_id: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A report to file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NewReport {
+    pub target_account_id: i64,
+    pub status_ids: Vec<i64>,
+    pub rule_ids: Vec<i64>,
+    pub comment: String,
+    /// Defaults to `violation` when rules are given and `other` otherwise
+    pub category: Option<ReportCategory>,
+}
+
+/// Update report request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateReportRequest {
+    pub category: Option<ReportCategory>,
+    pub rule_ids: Option<Vec<i64>>,
+}
+
+/// Filters of the report queue
+#[derive(Debug, Clone, Default)]
+pub struct ReportFilter {
+    /// Only resolved reports when true, only open reports otherwise
+    pub resolved: bool,
+    /// Only reports filed by this account
+    pub account_id: Option<i64>,
+    /// Only reports against this account
+    pub target_account_id: Option<i64>,
+    /// Only reports assigned to this staff member
+    pub assigned_account_id: Option<i64>,
+    pub category: Option<ReportCategory>,
+    pub max_id: Option<i64>,
+    pub since_id: Option<i64>,
+    pub limit: Option<i64>,
 }
 
 /// Reports error
@@ -32,45 +148,486 @@ pub enum ReportsError {
     Database(String),
     #[error("Validation error: {0}")]
     Validation(String),
+    #[error("Report not found: {0}")]
+    NotFound(i64),
+    #[error("Report note not found: {0}")]
+    NoteNotFound(i64),
+    #[error("Account not found: {0}")]
+    AccountNotFound(i64),
+    #[error("Status not found: {0}")]
+    StatusNotFound(i64),
+    #[error("Rule not found: {0}")]
+    RuleNotFound(i64),
     #[error("Internal error: {0}")]
     Internal(String),
 }
 
-/// Report service
-pub struct ReportService;
+impl From<sqlx::Error> for ReportsError {
+    fn from(e: sqlx::Error) -> Self {
+        ReportsError::Database(e.to_string())
+    }
+}
+
+/// Checks that the rules given fit the category
+///
+/// `violation` reports need at least one rule; other categories take none.
+pub fn validate_category(category: ReportCategory, rule_ids: &[i64]) -> Result<(), ReportsError> {
+    trace!("Validating category {}", category.as_str());
 
-impl Default for ReportService {
-    fn default() -> Self {
-        Self::new()
+    match (category, rule_ids.is_empty()) {
+        (ReportCategory::Violation, true) => Err(ReportsError::Validation(
+            "Rules can't be blank for violation reports".to_string(),
+        )),
+        (ReportCategory::Violation, false) | (_, true) => Ok(()),
+        (_, false) => Err(ReportsError::Validation(format!(
+            "Rules can only be given for violation reports, not {}",
+            category.as_str()
+        ))),
     }
 }
 
-impl ReportService {
-    /// Creates a new report service
-    pub fn new() -> Self {
-        info!("Creating new report service");
-        Self
+/// Checks the comment of a report
+pub fn validate_comment(comment: &str) -> Result<(), ReportsError> {
+    trace!("Validating comment");
+
+    if comment.chars().count() > MAX_COMMENT_LENGTH {
+        return Err(ReportsError::Validation(format!(
+            "Comment is too long (maximum is {} characters)",
+            MAX_COMMENT_LENGTH
+        )));
     }
+    Ok(())
+}
+
+/// Sorts IDs and drops duplicates
+fn unique(mut ids: Vec<i64>) -> Vec<i64> {
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+/// Internal struct for database rows
+struct ReportRow {
+    id: i64,
+    account_id: i64,
+    target_account_id: i64,
+    status_ids: Vec<i64>,
+    rule_ids: Vec<i64>,
+    comment: String,
+    category: String,
+    assigned_account_id: Option<i64>,
+    action_taken_at: Option<NaiveDateTime>,
+    action_taken_by_account_id: Option<i64>,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+}
+
+impl TryFrom<ReportRow> for Report {
+    type Error = ReportsError;
 
-    /// Update report
-    pub async fn update_report(&self, _request: UpdateReportRequest) -> Result<(), ReportsError> {
-        trace!("Updating report");
-        // TODO: Implement report update
-        Ok(())
+    fn try_from(row: ReportRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            account_id: row.account_id,
+            target_account_id: row.target_account_id,
+            status_ids: row.status_ids,
+            rule_ids: row.rule_ids,
+            comment: row.comment,
+            category: ReportCategory::parse(&row.category).ok_or_else(|| {
+                ReportsError::Internal(format!("Unknown report category: {}", row.category))
+            })?,
+            assigned_account_id: row.assigned_account_id,
+            action_taken_at: row
+                .action_taken_at
+                .map(|at| DateTime::from_naive_utc_and_offset(at, Utc)),
+            action_taken_by_account_id: row.action_taken_by_account_id,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+            updated_at: DateTime::from_naive_utc_and_offset(row.updated_at, Utc),
+        })
     }
+}
 
-    /// Validate category
-    pub fn validate_category(&self, _category: &ReportCategory) -> Result<(), ReportsError> {
-        trace!("Validating category");
-        // TODO: Implement category validation
-        Ok(())
+impl Report {
+    /// Whether staff resolved the report
+    pub fn action_taken(&self) -> bool {
+        self.action_taken_at.is_some()
     }
 
-    /// Validate comment
-    pub fn validate_comment(&self, _comment: &str) -> Result<(), ReportsError> {
-        trace!("Validating comment");
-        // TODO: Implement comment validation
-        Ok(())
+    /// Files a report
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the reporting account
+    /// * `report` - The report to file
+    ///
+    /// # Returns
+    ///
+    /// The filed report. Statuses must be the reported account's own, and
+    /// rules must exist.
+    pub async fn create(
+        pool: &PgPool,
+        account_id: i64,
+        report: NewReport,
+    ) -> Result<Self, ReportsError> {
+        trace!(
+            "Account {} reporting account {}",
+            account_id,
+            report.target_account_id
+        );
+
+        let comment = report.comment.trim();
+        validate_comment(comment)?;
+        let rule_ids = unique(report.rule_ids);
+        let category = report.category.unwrap_or(if rule_ids.is_empty() {
+            ReportCategory::Other
+        } else {
+            ReportCategory::Violation
+        });
+        validate_category(category, &rule_ids)?;
+        let status_ids = unique(report.status_ids);
+        if status_ids.len() > MAX_STATUSES {
+            return Err(ReportsError::Validation(format!(
+                "Too many statuses (maximum is {})",
+                MAX_STATUSES
+            )));
+        }
+        if report.target_account_id == account_id {
+            return Err(ReportsError::Validation(
+                "You can't report yourself".to_string(),
+            ));
+        }
+
+        let target_exists = sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM users WHERE id = $1 AND status <> 'deleted') AS "exists!""#,
+            report.target_account_id
+        )
+        .fetch_one(pool)
+        .await?;
+        if !target_exists {
+            return Err(ReportsError::AccountNotFound(report.target_account_id));
+        }
+
+        let found = sqlx::query_scalar!(
+            r#"
+            SELECT id FROM statuses
+            WHERE id = ANY($1) AND account_id = $2 AND deleted_at IS NULL
+            "#,
+            &status_ids,
+            report.target_account_id
+        )
+        .fetch_all(pool)
+        .await?;
+        if let Some(missing) = status_ids.iter().find(|id| !found.contains(id)) {
+            return Err(ReportsError::StatusNotFound(*missing));
+        }
+
+        let found = sqlx::query_scalar!(
+            "SELECT id FROM rules WHERE id = ANY($1) AND deleted_at IS NULL",
+            &rule_ids
+        )
+        .fetch_all(pool)
+        .await?;
+        if let Some(missing) = rule_ids.iter().find(|id| !found.contains(id)) {
+            return Err(ReportsError::RuleNotFound(*missing));
+        }
+
+        let row = sqlx::query_as!(
+            ReportRow,
+            r#"
+            INSERT INTO reports (account_id, target_account_id, status_ids, rule_ids, comment, category)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, account_id, target_account_id, status_ids, rule_ids, comment, category,
+                      assigned_account_id, action_taken_at, action_taken_by_account_id,
+                      created_at, updated_at
+            "#,
+            account_id,
+            report.target_account_id,
+            &status_ids,
+            &rule_ids,
+            comment,
+            category.as_str()
+        )
+        .fetch_one(pool)
+        .await?;
+
+        info!(
+            "Account {} reported account {} in report {}",
+            account_id, report.target_account_id, row.id
+        );
+        row.try_into()
+    }
+
+    /// Gets a report by ID
+    pub async fn get<'e>(executor: impl PgExecutor<'e>, id: i64) -> Result<Self, ReportsError> {
+        Self::get_by_ids(executor, &[id])
+            .await?
+            .pop()
+            .ok_or(ReportsError::NotFound(id))
+    }
+
+    /// Gets reports by ID, in no particular order
+    pub async fn get_by_ids<'e>(
+        executor: impl PgExecutor<'e>,
+        ids: &[i64],
+    ) -> Result<Vec<Self>, ReportsError> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query_as!(
+            ReportRow,
+            r#"
+            SELECT id, account_id, target_account_id, status_ids, rule_ids, comment, category,
+                   assigned_account_id, action_taken_at, action_taken_by_account_id,
+                   created_at, updated_at
+            FROM reports
+            WHERE id = ANY($1)
+            "#,
+            ids
+        )
+        .fetch_all(executor)
+        .await?;
+
+        rows.into_iter().map(Report::try_from).collect()
+    }
+
+    /// Lists reports, newest first
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `filter` - Filters of the report queue
+    pub async fn search(pool: &PgPool, filter: &ReportFilter) -> Result<Vec<Self>, ReportsError> {
+        trace!("Searching reports: {:?}", filter);
+
+        let limit = filter.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        let rows = sqlx::query_as!(
+            ReportRow,
+            r#"
+            SELECT id, account_id, target_account_id, status_ids, rule_ids, comment, category,
+                   assigned_account_id, action_taken_at, action_taken_by_account_id,
+                   created_at, updated_at
+            FROM reports
+            WHERE (action_taken_at IS NOT NULL) = $1
+              AND ($2::BIGINT IS NULL OR account_id = $2)
+              AND ($3::BIGINT IS NULL OR target_account_id = $3)
+              AND ($4::BIGINT IS NULL OR assigned_account_id = $4)
+              AND ($5::TEXT IS NULL OR category = $5)
+              AND ($6::BIGINT IS NULL OR id < $6)
+              AND ($7::BIGINT IS NULL OR id > $7)
+            ORDER BY id DESC
+            LIMIT $8
+            "#,
+            filter.resolved,
+            filter.account_id,
+            filter.target_account_id,
+            filter.assigned_account_id,
+            filter.category.map(|category| category.as_str()),
+            filter.max_id,
+            filter.since_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        rows.into_iter().map(Report::try_from).collect()
+    }
+
+    /// Changes the category and rules of a report
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection of the transaction to run in
+    /// * `id` - ID of the report
+    /// * `request` - Fields to change; missing fields are kept
+    pub async fn update(
+        conn: &mut PgConnection,
+        id: i64,
+        request: UpdateReportRequest,
+    ) -> Result<Self, ReportsError> {
+        trace!("Updating report {}", id);
+
+        let report = Self::get(&mut *conn, id).await?;
+        let category = request.category.unwrap_or(report.category);
+        let rule_ids = match request.rule_ids {
+            Some(rule_ids) => unique(rule_ids),
+            None if category == ReportCategory::Violation => report.rule_ids,
+            None => Vec::new(),
+        };
+        validate_category(category, &rule_ids)?;
+
+        let found = sqlx::query_scalar!(
+            "SELECT id FROM rules WHERE id = ANY($1) AND deleted_at IS NULL",
+            &rule_ids
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        if let Some(missing) = rule_ids.iter().find(|id| !found.contains(id)) {
+            return Err(ReportsError::RuleNotFound(*missing));
+        }
+
+        let row = sqlx::query_as!(
+            ReportRow,
+            r#"
+            UPDATE reports SET category = $2, rule_ids = $3
+            WHERE id = $1
+            RETURNING id, account_id, target_account_id, status_ids, rule_ids, comment, category,
+                      assigned_account_id, action_taken_at, action_taken_by_account_id,
+                      created_at, updated_at
+            "#,
+            id,
+            category.as_str(),
+            &rule_ids
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(ReportsError::NotFound(id))?;
+
+        debug!("Updated report {}", id);
+        row.try_into()
+    }
+
+    /// Assigns a report to a staff member, or unassigns it
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection of the transaction to run in
+    /// * `id` - ID of the report
+    /// * `assigned_account_id` - Staff member to assign, or `None` to unassign
+    pub async fn assign(
+        conn: &mut PgConnection,
+        id: i64,
+        assigned_account_id: Option<i64>,
+    ) -> Result<Self, ReportsError> {
+        trace!("Assigning report {} to {:?}", id, assigned_account_id);
+
+        let row = sqlx::query_as!(
+            ReportRow,
+            r#"
+            UPDATE reports SET assigned_account_id = $2
+            WHERE id = $1
+            RETURNING id, account_id, target_account_id, status_ids, rule_ids, comment, category,
+                      assigned_account_id, action_taken_at, action_taken_by_account_id,
+                      created_at, updated_at
+            "#,
+            id,
+            assigned_account_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(ReportsError::NotFound(id))?;
+
+        row.try_into()
+    }
+
+    /// Marks a report as resolved
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection of the transaction to run in
+    /// * `id` - ID of the report
+    /// * `moderator_id` - ID of the staff member resolving the report
+    pub async fn resolve(
+        conn: &mut PgConnection,
+        id: i64,
+        moderator_id: i64,
+    ) -> Result<Self, ReportsError> {
+        trace!("Resolving report {}", id);
+
+        let row = sqlx::query_as!(
+            ReportRow,
+            r#"
+            UPDATE reports SET action_taken_at = NOW(), action_taken_by_account_id = $2
+            WHERE id = $1
+            RETURNING id, account_id, target_account_id, status_ids, rule_ids, comment, category,
+                      assigned_account_id, action_taken_at, action_taken_by_account_id,
+                      created_at, updated_at
+            "#,
+            id,
+            moderator_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(ReportsError::NotFound(id))?;
+
+        info!("Account {} resolved report {}", moderator_id, id);
+        row.try_into()
+    }
+
+    /// Puts a resolved report back in the queue
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection of the transaction to run in
+    /// * `id` - ID of the report
+    pub async fn reopen(conn: &mut PgConnection, id: i64) -> Result<Self, ReportsError> {
+        trace!("Reopening report {}", id);
+
+        let row = sqlx::query_as!(
+            ReportRow,
+            r#"
+            UPDATE reports SET action_taken_at = NULL, action_taken_by_account_id = NULL
+            WHERE id = $1
+            RETURNING id, account_id, target_account_id, status_ids, rule_ids, comment, category,
+                      assigned_account_id, action_taken_at, action_taken_by_account_id,
+                      created_at, updated_at
+            "#,
+            id
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(ReportsError::NotFound(id))?;
+
+        info!("Reopened report {}", id);
+        row.try_into()
+    }
+
+    /// Sends an `admin.report` notification to every local staff member
+    /// other than the reporter
+    ///
+    /// Failures are logged rather than returned, so they don't fail filing.
+    ///
+    /// # Returns
+    ///
+    /// The number of staff members notified
+    pub async fn notify_staff(&self, pool: &PgPool) -> usize {
+        let staff = match sqlx::query_scalar!(
+            r#"
+            SELECT id FROM users
+            WHERE (admin OR moderator) AND domain IS NULL AND id <> $1
+            "#,
+            self.account_id
+        )
+        .fetch_all(pool)
+        .await
+        {
+            Ok(staff) => staff,
+            Err(e) => {
+                warn!("Failed to look up staff for report {}: {}", self.id, e);
+                return 0;
+            }
+        };
+
+        let mut notified = 0;
+        for account_id in staff {
+            let request = CreateNotificationRequest {
+                account_id,
+                from_account_id: Some(self.account_id),
+                notification_type: NotificationType::AdminReport,
+                status_id: None,
+                poll_id: None,
+                report_id: Some(self.id),
+            };
+            match Notification::create(pool, request).await {
+                Ok(_) => notified += 1,
+                Err(e) => warn!(
+                    "Failed to notify account {} of report {}: {}",
+                    account_id, self.id, e
+                ),
+            }
+        }
+        notified
     }
 }
 
@@ -79,19 +636,35 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_report_service_new() {
-        let _service = ReportService::new();
-        // Service created successfully
+    fn test_category_names() {
+        for category in [
+            ReportCategory::Spam,
+            ReportCategory::Legal,
+            ReportCategory::Violation,
+            ReportCategory::Other,
+        ] {
+            assert_eq!(ReportCategory::parse(category.as_str()), Some(category));
+        }
+        assert_eq!(ReportCategory::parse("harassment"), None);
     }
 
-    #[tokio::test]
-    async fn test_update_report() {
-        let service = ReportService::new();
-        let request = UpdateReportRequest {
-            category: ReportCategory::Spam,
-            comment: Some("Test comment".to_string()),
-        };
-        let result = service.update_report(request).await;
-        assert!(result.is_ok());
+    #[test]
+    fn test_validate_category() {
+        assert!(validate_category(ReportCategory::Violation, &[1, 2]).is_ok());
+        assert!(validate_category(ReportCategory::Violation, &[]).is_err());
+        assert!(validate_category(ReportCategory::Spam, &[]).is_ok());
+        assert!(validate_category(ReportCategory::Spam, &[1]).is_err());
+    }
+
+    #[test]
+    fn test_validate_comment() {
+        assert!(validate_comment("Selling followers").is_ok());
+        assert!(validate_comment(&"a".repeat(MAX_COMMENT_LENGTH)).is_ok());
+        assert!(validate_comment(&"a".repeat(MAX_COMMENT_LENGTH + 1)).is_err());
+    }
+
+    #[test]
+    fn test_unique() {
+        assert_eq!(unique(vec![3, 1, 3, 2]), vec![1, 2, 3]);
     }
 }
@@ -0,0 +1,168 @@
+//! Report notes
+//!
+//! Staff discuss a report in notes attached to it while triaging it. Notes
+//! are only ever shown to staff.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::ReportsError;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+use tracing::{info, trace};
+
+/// Maximum length of a note
+pub const MAX_NOTE_LENGTH: usize = 500;
+
+/// A note staff left on a report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportNote {
+    pub id: i64,
+    pub report_id: i64,
+    /// Staff member who wrote the note
+    pub account_id: Option<i64>,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Internal struct for database rows
+struct ReportNoteRow {
+    id: i64,
+    report_id: i64,
+    account_id: Option<i64>,
+    content: String,
+    created_at: NaiveDateTime,
+}
+
+impl From<ReportNoteRow> for ReportNote {
+    fn from(row: ReportNoteRow) -> Self {
+        Self {
+            id: row.id,
+            report_id: row.report_id,
+            account_id: row.account_id,
+            content: row.content,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+        }
+    }
+}
+
+/// Checks the content of a note
+fn validate_content(content: &str) -> Result<&str, ReportsError> {
+    let content = content.trim();
+    if content.is_empty() {
+        return Err(ReportsError::Validation(
+            "Content can't be blank".to_string(),
+        ));
+    }
+    if content.chars().count() > MAX_NOTE_LENGTH {
+        return Err(ReportsError::Validation(format!(
+            "Content is too long (maximum is {} characters)",
+            MAX_NOTE_LENGTH
+        )));
+    }
+    Ok(content)
+}
+
+impl ReportNote {
+    /// Leaves a note on a report
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection of the transaction to run in
+    /// * `report_id` - ID of the report
+    /// * `account_id` - ID of the staff member writing the note
+    /// * `content` - Text of the note
+    pub async fn create(
+        conn: &mut PgConnection,
+        report_id: i64,
+        account_id: i64,
+        content: &str,
+    ) -> Result<Self, ReportsError> {
+        trace!("Adding note on report {}", report_id);
+        let content = validate_content(content)?;
+
+        let row = sqlx::query_as!(
+            ReportNoteRow,
+            r#"
+            INSERT INTO report_notes (report_id, account_id, content)
+            SELECT id, $2, $3 FROM reports WHERE id = $1
+            RETURNING id, report_id, account_id, content, created_at
+            "#,
+            report_id,
+            account_id,
+            content
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(ReportsError::NotFound(report_id))?;
+
+        info!(
+            "Account {} added note {} on report {}",
+            account_id, row.id, report_id
+        );
+        Ok(row.into())
+    }
+
+    /// Gets the notes on a report, oldest first
+    pub async fn by_report(pool: &PgPool, report_id: i64) -> Result<Vec<Self>, ReportsError> {
+        let rows = sqlx::query_as!(
+            ReportNoteRow,
+            r#"
+            SELECT id, report_id, account_id, content, created_at
+            FROM report_notes
+            WHERE report_id = $1
+            ORDER BY id
+            "#,
+            report_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(ReportNote::from).collect())
+    }
+
+    /// Deletes a note from a report
+    ///
+    /// # Returns
+    ///
+    /// The deleted note
+    pub async fn delete(
+        conn: &mut PgConnection,
+        report_id: i64,
+        id: i64,
+    ) -> Result<Self, ReportsError> {
+        let row = sqlx::query_as!(
+            ReportNoteRow,
+            r#"
+            DELETE FROM report_notes
+            WHERE id = $1 AND report_id = $2
+            RETURNING id, report_id, account_id, content, created_at
+            "#,
+            id,
+            report_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(ReportsError::NoteNotFound(id))?;
+
+        info!("Deleted note {} from report {}", id, report_id);
+        Ok(row.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_content() {
+        assert_eq!(
+            validate_content("  Same spammer  ").unwrap(),
+            "Same spammer"
+        );
+        assert!(validate_content("   ").is_err());
+        assert!(validate_content(&"a".repeat(MAX_NOTE_LENGTH + 1)).is_err());
+    }
+}
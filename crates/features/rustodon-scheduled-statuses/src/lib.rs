@@ -1,12 +1,44 @@
 //! Scheduled statuses functionality for Rustodon
 //!
-//! This module provides scheduled status functionality.
+//! This module stores statuses to be published later and publishes them
+//! once they are due. A scheduled status keeps the parameters it was
+//! submitted with, including attached media and polls, until it is
+//! published, rescheduled or cancelled.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_scheduled_statuses::{ScheduledStatus, ScheduledStatusParams, ScheduledStatusService};
+//!
+//! let service = ScheduledStatusService::new();
+//! let scheduled = service
+//!     .schedule(&pool, account_id, scheduled_at, ScheduledStatusParams {
+//!         text: "Good morning!".to_string(),
+//!         ..ScheduledStatusParams::default()
+//!     })
+//!     .await?;
+//!
+//! let published = ScheduledStatus::publish_due(&pool, Utc::now(), "rustodon.example").await?;
+//! ```
 //!
 //! # Author
 //!
 //! arkSong (arksong2018@gmail.com)
 
-use tracing::{error, info, trace};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use rustodon_media::StorageConfig;
+use rustodon_preview_cards::{fetch_for_status, PreviewCardConfig, PreviewCardFetcher};
+use rustodon_statuses::{NewPoll, NewStatus, Status, Visibility};
+use rustodon_workers::{Job, WorkerError};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::Pin;
+use tracing::{debug, error, info, trace, warn};
+
+/// Minimum time between now and the publication of a scheduled status
+pub const MIN_SCHEDULE_OFFSET_SECONDS: i64 = 300;
 
 /// Scheduled status error
 #[derive(Debug, thiserror::Error)]
@@ -15,12 +47,334 @@ pub enum ScheduledStatusError {
     Database(String),
     #[error("Validation error: {0}")]
     Validation(String),
+    #[error("Scheduled status not found: {0}")]
+    NotFound(i64),
     #[error("Internal error: {0}")]
     Internal(String),
 }
 
+impl From<sqlx::Error> for ScheduledStatusError {
+    fn from(e: sqlx::Error) -> Self {
+        ScheduledStatusError::Database(e.to_string())
+    }
+}
+
+/// Limits applied when scheduling statuses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledStatusLimits {
+    /// Maximum scheduled statuses per account on the same day
+    pub daily: i64,
+    /// Maximum scheduled statuses per account
+    pub total: i64,
+    /// Maximum media attachments per status
+    pub max_media_attachments: usize,
+    /// Maximum choices per poll
+    pub max_poll_options: usize,
+    /// Maximum characters per poll choice
+    pub max_poll_option_characters: usize,
+    /// Shortest poll duration in seconds
+    pub min_poll_expiration: i64,
+    /// Longest poll duration in seconds
+    pub max_poll_expiration: i64,
+}
+
+impl Default for ScheduledStatusLimits {
+    fn default() -> Self {
+        Self {
+            daily: 25,
+            total: 300,
+            max_media_attachments: 4,
+            max_poll_options: 4,
+            max_poll_option_characters: 50,
+            min_poll_expiration: 300,
+            max_poll_expiration: 2_629_746,
+        }
+    }
+}
+
+/// Parameters a scheduled status is published with
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledStatusParams {
+    /// Plain text of the status
+    pub text: String,
+    /// Who can see the status
+    #[serde(default)]
+    pub visibility: Visibility,
+    /// Whether the media is marked sensitive
+    #[serde(default)]
+    pub sensitive: bool,
+    /// Content warning shown before the content
+    pub spoiler_text: Option<String>,
+    /// ID of the status to reply to
+    pub in_reply_to_id: Option<i64>,
+    /// ISO 639 language code
+    pub language: Option<String>,
+    /// IDs of the attached media
+    #[serde(default)]
+    pub media_ids: Vec<i64>,
+    /// Attached poll
+    pub poll: Option<NewPoll>,
+}
+
+impl ScheduledStatusParams {
+    /// Builds the status to publish for an account
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - ID of the posting account
+    pub fn to_new_status(&self, account_id: i64) -> NewStatus {
+        NewStatus {
+            account_id,
+            text: self.text.clone(),
+            visibility: self.visibility,
+            sensitive: self.sensitive,
+            spoiler_text: self.spoiler_text.clone(),
+            in_reply_to_id: self.in_reply_to_id,
+            language: self.language.clone(),
+            media_ids: self.media_ids.clone(),
+            poll: self.poll.clone(),
+        }
+    }
+}
+
+/// A status waiting to be published
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledStatus {
+    /// Unique identifier for the scheduled status
+    pub id: i64,
+    /// ID of the account that scheduled the status
+    pub account_id: i64,
+    /// When the status will be published
+    pub scheduled_at: DateTime<Utc>,
+    /// Parameters the status will be published with
+    pub params: ScheduledStatusParams,
+    /// When the status was scheduled
+    pub created_at: DateTime<Utc>,
+    /// When the scheduled status was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ScheduledStatus {
+    /// Lists an account's scheduled statuses, newest first
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the account
+    /// * `limit` - Maximum number of scheduled statuses to return
+    /// * `max_id` - Only return scheduled statuses older than this ID
+    /// * `since_id` - Only return scheduled statuses newer than this ID
+    ///
+    /// # Returns
+    ///
+    /// Result containing the scheduled statuses or an error
+    pub async fn get_by_account(
+        pool: &PgPool,
+        account_id: i64,
+        limit: Option<i64>,
+        max_id: Option<i64>,
+        since_id: Option<i64>,
+    ) -> Result<Vec<Self>, ScheduledStatusError> {
+        trace!("Getting scheduled statuses for account {}", account_id);
+
+        let limit = limit.unwrap_or(20).clamp(1, 40);
+        let rows = sqlx::query_as!(
+            ScheduledStatusRow,
+            r#"
+            SELECT id, account_id, scheduled_at, params, created_at, updated_at
+            FROM scheduled_statuses
+            WHERE account_id = $1
+              AND ($2::BIGINT IS NULL OR id < $2)
+              AND ($3::BIGINT IS NULL OR id > $3)
+            ORDER BY id DESC
+            LIMIT $4
+            "#,
+            account_id,
+            max_id,
+            since_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let scheduled = rows
+            .into_iter()
+            .map(ScheduledStatus::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        debug!(
+            "Retrieved {} scheduled statuses for account {}",
+            scheduled.len(),
+            account_id
+        );
+        Ok(scheduled)
+    }
+
+    /// Gets a scheduled status owned by an account
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the owning account
+    /// * `id` - ID of the scheduled status
+    ///
+    /// # Returns
+    ///
+    /// Result containing the scheduled status or an error
+    pub async fn get_owned(
+        pool: &PgPool,
+        account_id: i64,
+        id: i64,
+    ) -> Result<Self, ScheduledStatusError> {
+        trace!("Getting scheduled status {} for account {}", id, account_id);
+
+        let row = sqlx::query_as!(
+            ScheduledStatusRow,
+            r#"
+            SELECT id, account_id, scheduled_at, params, created_at, updated_at
+            FROM scheduled_statuses
+            WHERE id = $1 AND account_id = $2
+            "#,
+            id,
+            account_id
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(ScheduledStatusError::NotFound(id))?;
+
+        ScheduledStatus::try_from(row)
+    }
+
+    /// Cancels a scheduled status
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the owning account
+    /// * `id` - ID of the scheduled status
+    ///
+    /// # Returns
+    ///
+    /// Result indicating success or failure
+    pub async fn cancel(
+        pool: &PgPool,
+        account_id: i64,
+        id: i64,
+    ) -> Result<(), ScheduledStatusError> {
+        trace!(
+            "Cancelling scheduled status {} for account {}",
+            id,
+            account_id
+        );
+
+        let result = sqlx::query!(
+            "DELETE FROM scheduled_statuses WHERE id = $1 AND account_id = $2",
+            id,
+            account_id
+        )
+        .execute(pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(ScheduledStatusError::NotFound(id));
+        }
+
+        info!(
+            "Cancelled scheduled status {} for account {}",
+            id, account_id
+        );
+        Ok(())
+    }
+
+    /// Publishes every scheduled status that is due
+    ///
+    /// Each scheduled status is removed in the transaction creating its
+    /// status, and is locked meanwhile so that concurrent publishers never
+    /// publish it twice. Scheduled statuses that fail to publish, for
+    /// example because the database is unavailable, are logged and kept to
+    /// be retried by the next run.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `now` - Current time
+    /// * `local_domain` - Domain of this instance
+    ///
+    /// # Returns
+    ///
+    /// Result containing the published statuses or an error
+    pub async fn publish_due(
+        pool: &PgPool,
+        now: DateTime<Utc>,
+        local_domain: &str,
+    ) -> Result<Vec<Status>, ScheduledStatusError> {
+        trace!("Publishing scheduled statuses due by {}", now);
+
+        let due_ids = sqlx::query_scalar!(
+            "SELECT id FROM scheduled_statuses WHERE scheduled_at <= $1 ORDER BY scheduled_at",
+            now.naive_utc()
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut published = Vec::with_capacity(due_ids.len());
+        for id in due_ids {
+            match Self::publish(pool, id, local_domain).await {
+                Ok(Some(status)) => {
+                    info!("Published scheduled status {} as status {}", id, status.id);
+                    published.push(status);
+                }
+                // Already published or cancelled in the meantime
+                Ok(None) => {}
+                Err(e) => error!("Failed to publish scheduled status {}: {}", id, e),
+            }
+        }
+
+        Ok(published)
+    }
+
+    /// Publishes a scheduled status, leaving it in place if that fails
+    async fn publish(
+        pool: &PgPool,
+        id: i64,
+        local_domain: &str,
+    ) -> Result<Option<Status>, ScheduledStatusError> {
+        let mut tx = pool.begin().await?;
+        let Some(row) = sqlx::query_as!(
+            ScheduledStatusRow,
+            r#"
+            SELECT id, account_id, scheduled_at, params, created_at, updated_at
+            FROM scheduled_statuses
+            WHERE id = $1
+            FOR UPDATE SKIP LOCKED
+            "#,
+            id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let scheduled = ScheduledStatus::try_from(row)?;
+        let new_status = scheduled.params.to_new_status(scheduled.account_id);
+        let created = Status::create_in(&mut tx, new_status, local_domain)
+            .await
+            .map_err(|e| ScheduledStatusError::Internal(e.to_string()))?;
+        sqlx::query!("DELETE FROM scheduled_statuses WHERE id = $1", id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        created.notify(pool).await;
+        Ok(Some(created.status))
+    }
+}
+
 /// Scheduled status service
-pub struct ScheduledStatusService;
+pub struct ScheduledStatusService {
+    limits: ScheduledStatusLimits,
+}
 
 impl Default for ScheduledStatusService {
     fn default() -> Self {
@@ -31,22 +385,379 @@ impl Default for ScheduledStatusService {
 impl ScheduledStatusService {
     /// Creates a new scheduled status service
     pub fn new() -> Self {
+        Self::with_limits(ScheduledStatusLimits::default())
+    }
+
+    /// Creates a scheduled status service with custom limits
+    ///
+    /// # Arguments
+    ///
+    /// * `limits` - Limits applied when scheduling statuses
+    pub fn with_limits(limits: ScheduledStatusLimits) -> Self {
         info!("Creating new scheduled status service");
-        Self
+        Self { limits }
     }
 
     /// Validate poll options
-    pub fn validate_poll_options(&self, _options: &[String]) -> Result<(), ScheduledStatusError> {
+    pub fn validate_poll_options(&self, options: &[String]) -> Result<(), ScheduledStatusError> {
         trace!("Validating poll options");
-        // TODO: Implement poll validation
+
+        if options.len() < 2 {
+            return Err(ScheduledStatusError::Validation(
+                "Poll must have at least 2 options".to_string(),
+            ));
+        }
+        if options.len() > self.limits.max_poll_options {
+            return Err(ScheduledStatusError::Validation(format!(
+                "Poll can have at most {} options",
+                self.limits.max_poll_options
+            )));
+        }
+
+        let mut seen = HashSet::with_capacity(options.len());
+        for option in options {
+            let option = option.trim();
+            if option.is_empty() {
+                return Err(ScheduledStatusError::Validation(
+                    "Poll options can't be blank".to_string(),
+                ));
+            }
+            if option.chars().count() > self.limits.max_poll_option_characters {
+                return Err(ScheduledStatusError::Validation(format!(
+                    "Poll options can have at most {} characters",
+                    self.limits.max_poll_option_characters
+                )));
+            }
+            if !seen.insert(option) {
+                return Err(ScheduledStatusError::Validation(
+                    "Poll options must be unique".to_string(),
+                ));
+            }
+        }
+
         Ok(())
     }
+
+    /// Validates the parameters of a status
+    ///
+    /// Statuses posted right away are checked the same way as scheduled ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Parameters of the status
+    ///
+    /// # Returns
+    ///
+    /// Result indicating whether the parameters are valid
+    pub fn validate_params(
+        &self,
+        params: &ScheduledStatusParams,
+    ) -> Result<(), ScheduledStatusError> {
+        if params.media_ids.len() > self.limits.max_media_attachments {
+            return Err(ScheduledStatusError::Validation(format!(
+                "Statuses can have at most {} media attachments",
+                self.limits.max_media_attachments
+            )));
+        }
+
+        if let Some(poll) = &params.poll {
+            if !params.media_ids.is_empty() {
+                return Err(ScheduledStatusError::Validation(
+                    "Statuses can't have both media and a poll".to_string(),
+                ));
+            }
+            self.validate_poll_options(&poll.options)?;
+            if poll.expires_in < self.limits.min_poll_expiration
+                || poll.expires_in > self.limits.max_poll_expiration
+            {
+                return Err(ScheduledStatusError::Validation(format!(
+                    "Poll duration must be between {} and {} seconds",
+                    self.limits.min_poll_expiration, self.limits.max_poll_expiration
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Validates the publication time of a scheduled status
+    ///
+    /// # Arguments
+    ///
+    /// * `scheduled_at` - Requested publication time
+    /// * `now` - Current time
+    ///
+    /// # Returns
+    ///
+    /// Result indicating whether the time is far enough in the future
+    pub fn validate_scheduled_at(
+        &self,
+        scheduled_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<(), ScheduledStatusError> {
+        if scheduled_at < now + Duration::seconds(MIN_SCHEDULE_OFFSET_SECONDS) {
+            return Err(ScheduledStatusError::Validation(format!(
+                "Scheduled time must be at least {} minutes in the future",
+                MIN_SCHEDULE_OFFSET_SECONDS / 60
+            )));
+        }
+        Ok(())
+    }
+
+    /// Schedules a status
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the posting account
+    /// * `scheduled_at` - When to publish the status
+    /// * `params` - Parameters to publish the status with
+    ///
+    /// # Returns
+    ///
+    /// Result containing the scheduled status or an error
+    pub async fn schedule(
+        &self,
+        pool: &PgPool,
+        account_id: i64,
+        scheduled_at: DateTime<Utc>,
+        params: ScheduledStatusParams,
+    ) -> Result<ScheduledStatus, ScheduledStatusError> {
+        trace!(
+            "Scheduling status for account {} at {}",
+            account_id,
+            scheduled_at
+        );
+
+        self.validate_scheduled_at(scheduled_at, Utc::now())?;
+        self.validate_params(&params)?;
+
+        let params_json = serde_json::to_value(&params)
+            .map_err(|e| ScheduledStatusError::Internal(e.to_string()))?;
+
+        let mut tx = pool.begin().await?;
+        self.check_limits(&mut tx, account_id, scheduled_at, None)
+            .await?;
+
+        let row = sqlx::query_as!(
+            ScheduledStatusRow,
+            r#"
+            INSERT INTO scheduled_statuses (account_id, scheduled_at, params)
+            VALUES ($1, $2, $3)
+            RETURNING id, account_id, scheduled_at, params, created_at, updated_at
+            "#,
+            account_id,
+            scheduled_at.naive_utc(),
+            params_json
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        let scheduled = ScheduledStatus::try_from(row)?;
+        info!(
+            "Scheduled status {} for account {} at {}",
+            scheduled.id, account_id, scheduled_at
+        );
+        Ok(scheduled)
+    }
+
+    /// Moves a scheduled status to another time
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the owning account
+    /// * `id` - ID of the scheduled status
+    /// * `scheduled_at` - New publication time
+    ///
+    /// # Returns
+    ///
+    /// Result containing the updated scheduled status or an error
+    pub async fn reschedule(
+        &self,
+        pool: &PgPool,
+        account_id: i64,
+        id: i64,
+        scheduled_at: DateTime<Utc>,
+    ) -> Result<ScheduledStatus, ScheduledStatusError> {
+        trace!("Rescheduling status {} to {}", id, scheduled_at);
+
+        self.validate_scheduled_at(scheduled_at, Utc::now())?;
+
+        let mut tx = pool.begin().await?;
+        self.check_limits(&mut tx, account_id, scheduled_at, Some(id))
+            .await?;
+
+        let row = sqlx::query_as!(
+            ScheduledStatusRow,
+            r#"
+            UPDATE scheduled_statuses
+            SET scheduled_at = $3
+            WHERE id = $1 AND account_id = $2
+            RETURNING id, account_id, scheduled_at, params, created_at, updated_at
+            "#,
+            id,
+            account_id,
+            scheduled_at.naive_utc()
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(ScheduledStatusError::NotFound(id))?;
+
+        tx.commit().await?;
+
+        info!("Rescheduled status {} to {}", id, scheduled_at);
+        ScheduledStatus::try_from(row)
+    }
+
+    /// Checks the daily and total limits, holding a lock on the account
+    /// so concurrent requests can't both slip under a limit
+    async fn check_limits(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        account_id: i64,
+        scheduled_at: DateTime<Utc>,
+        excluding_id: Option<i64>,
+    ) -> Result<(), ScheduledStatusError> {
+        sqlx::query!("SELECT id FROM users WHERE id = $1 FOR UPDATE", account_id)
+            .fetch_optional(&mut **tx)
+            .await?;
+
+        let counts = sqlx::query!(
+            r#"
+            SELECT
+                COUNT(*) AS "total!",
+                COUNT(*) FILTER (WHERE scheduled_at::DATE = $3::TIMESTAMP::DATE) AS "daily!"
+            FROM scheduled_statuses
+            WHERE account_id = $1 AND ($2::BIGINT IS NULL OR id <> $2)
+            "#,
+            account_id,
+            excluding_id,
+            scheduled_at.naive_utc()
+        )
+        .fetch_one(&mut **tx)
+        .await?;
+
+        check_counts(&self.limits, counts.total, counts.daily)
+    }
+}
+
+/// Rejects a new scheduled status when the account is at a limit
+fn check_counts(
+    limits: &ScheduledStatusLimits,
+    total: i64,
+    daily: i64,
+) -> Result<(), ScheduledStatusError> {
+    if total >= limits.total {
+        return Err(ScheduledStatusError::Validation(format!(
+            "You can't schedule more than {} statuses",
+            limits.total
+        )));
+    }
+    if daily >= limits.daily {
+        return Err(ScheduledStatusError::Validation(format!(
+            "You can't schedule more than {} statuses on the same day",
+            limits.daily
+        )));
+    }
+    Ok(())
+}
+
+/// Background job publishing the scheduled statuses that are due
+pub struct PublishScheduledStatusesJob {
+    pool: PgPool,
+    local_domain: String,
+}
+
+impl PublishScheduledStatusesJob {
+    /// Creates the job
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `local_domain` - Domain of this instance
+    pub fn new(pool: PgPool, local_domain: String) -> Self {
+        Self { pool, local_domain }
+    }
+}
+
+impl Job for PublishScheduledStatusesJob {
+    fn name(&self) -> &'static str {
+        "PublishScheduledStatusesJob"
+    }
+
+    fn execute(&self) -> Pin<Box<dyn Future<Output = Result<(), WorkerError>> + Send>> {
+        let pool = self.pool.clone();
+        let local_domain = self.local_domain.clone();
+        Box::pin(async move {
+            let published = ScheduledStatus::publish_due(&pool, Utc::now(), &local_domain)
+                .await
+                .map_err(|e| WorkerError::Job(e.to_string()))?;
+            debug!("Published {} scheduled statuses", published.len());
+            if published.is_empty() {
+                return Ok(());
+            }
+
+            // Published statuses get their link preview like any new status
+            let fetcher = PreviewCardFetcher::new(PreviewCardConfig::from_env())
+                .map_err(|e| WorkerError::Job(e.to_string()))?;
+            let storage = StorageConfig::from_env();
+            for status in &published {
+                if let Err(e) = fetch_for_status(&pool, &fetcher, &storage, status.id).await {
+                    warn!(
+                        "Failed to fetch preview card of status {}: {}",
+                        status.id, e
+                    );
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+struct ScheduledStatusRow {
+    id: i64,
+    account_id: i64,
+    scheduled_at: NaiveDateTime,
+    params: serde_json::Value,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+}
+
+impl TryFrom<ScheduledStatusRow> for ScheduledStatus {
+    type Error = ScheduledStatusError;
+
+    fn try_from(row: ScheduledStatusRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            account_id: row.account_id,
+            scheduled_at: DateTime::from_naive_utc_and_offset(row.scheduled_at, Utc),
+            params: serde_json::from_value(row.params)
+                .map_err(|e| ScheduledStatusError::Internal(e.to_string()))?,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+            updated_at: DateTime::from_naive_utc_and_offset(row.updated_at, Utc),
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn poll(options: &[&str], expires_in: i64) -> ScheduledStatusParams {
+        ScheduledStatusParams {
+            text: "Which one?".to_string(),
+            poll: Some(NewPoll {
+                options: options.iter().map(|o| o.to_string()).collect(),
+                expires_in,
+                ..NewPoll::default()
+            }),
+            ..ScheduledStatusParams::default()
+        }
+    }
+
     #[test]
     fn test_scheduled_status_service_new() {
         let _service = ScheduledStatusService::new();
@@ -59,5 +770,70 @@ mod tests {
         let options = vec!["option1".to_string(), "option2".to_string()];
         let result = service.validate_poll_options(&options);
         assert!(result.is_ok());
+
+        assert!(service.validate_poll_options(&options[..1]).is_err());
+        assert!(service
+            .validate_poll_options(&["a", "b", "c", "d", "e"].map(String::from))
+            .is_err());
+        assert!(service
+            .validate_poll_options(&["same".to_string(), "same ".to_string()])
+            .is_err());
+        assert!(service
+            .validate_poll_options(&["a".to_string(), "x".repeat(51)])
+            .is_err());
+    }
+
+    #[test]
+    fn test_validate_params() {
+        let service = ScheduledStatusService::new();
+
+        assert!(service.validate_params(&poll(&["a", "b"], 3600)).is_ok());
+        assert!(service.validate_params(&poll(&["a", "b"], 60)).is_err());
+
+        let mut with_media = poll(&["a", "b"], 3600);
+        with_media.media_ids = vec![1];
+        assert!(service.validate_params(&with_media).is_err());
+
+        let too_much_media = ScheduledStatusParams {
+            media_ids: vec![1, 2, 3, 4, 5],
+            ..ScheduledStatusParams::default()
+        };
+        assert!(service.validate_params(&too_much_media).is_err());
+    }
+
+    #[test]
+    fn test_validate_scheduled_at() {
+        let service = ScheduledStatusService::new();
+        let now = Utc::now();
+
+        assert!(service
+            .validate_scheduled_at(now + Duration::minutes(10), now)
+            .is_ok());
+        assert!(service
+            .validate_scheduled_at(now + Duration::minutes(2), now)
+            .is_err());
+    }
+
+    #[test]
+    fn test_check_counts() {
+        let limits = ScheduledStatusLimits::default();
+
+        assert!(check_counts(&limits, 0, 0).is_ok());
+        assert!(check_counts(&limits, 299, 24).is_ok());
+        assert!(check_counts(&limits, 300, 0).is_err());
+        assert!(check_counts(&limits, 30, 25).is_err());
+    }
+
+    #[test]
+    fn test_params_round_trip() {
+        let params = poll(&["a", "b"], 3600);
+        let json = serde_json::to_value(&params).unwrap();
+        let parsed: ScheduledStatusParams = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, params);
+
+        let new_status = parsed.to_new_status(9);
+        assert_eq!(new_status.account_id, 9);
+        assert_eq!(new_status.text, "Which one?");
+        assert!(new_status.poll.is_some());
     }
 }
@@ -1,5 +1,617 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
+//! Statuses module for Rustodon
+//!
+//! This module provides access to statuses (posts) stored in the
+//! database, for the API layer to render them in timelines,
+//! notifications and other entities. New statuses go through the
+//! [`text`] pipeline, which links URLs, mentions and hashtags and records
+//! the mentioned accounts and used hashtags.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_statuses::{NewStatus, Status};
+//!
+//! let status = Status::get_by_id(&pool, status_id).await?;
+//! let statuses = Status::get_by_ids(&pool, &[1, 2, 3]).await?;
+//!
+//! let status = Status::create(&pool, NewStatus {
+//!     account_id,
+//!     text: "Hello, @alice! #introductions".to_string(),
+//!     media_ids: vec![media_id],
+//!     ..NewStatus::default()
+//! }, "rustodon.example").await?;
+//! ```
+//!
+//! # Dependencies
+//!
+//! - `rustodon_core`: Core types and traits
+//! - `sqlx`: Database queries
+//! - `serde`: Serialization
+//! - `chrono`: DateTime handling
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+pub mod text;
+pub mod timeline;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rustodon_account_conversations::{AccountConversation, AccountConversationsError};
+use rustodon_conversations::{Conversation, ConversationsError};
+use rustodon_mentions::{extract_mentions, Mention, MentionedAccount, MentionsError};
+use rustodon_notifications::{CreateNotificationRequest, Notification, NotificationType};
+use rustodon_polls::{Poll, PollsError};
+use rustodon_tags::{extract_hashtags, Tag, TagError};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+pub use rustodon_polls::NewPoll;
+pub use text::{render_text, sanitize_html};
+pub use timeline::{PublicTimeline, TagTimeline, TimelinePage};
+use tracing::{debug, error, info, trace};
+
+/// Custom error type for statuses module
+#[derive(Error, Debug)]
+pub enum StatusesError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("Status not found: {0}")]
+    StatusNotFound(i64),
+    #[error("Invalid visibility: {0}")]
+    InvalidVisibility(String),
+    #[error("Validation error: {0}")]
+    Validation(String),
+    #[error("Poll error: {0}")]
+    Polls(#[from] PollsError),
+    #[error("Mention error: {0}")]
+    Mentions(#[from] MentionsError),
+    #[error("Tag error: {0}")]
+    Tags(#[from] TagError),
+    #[error("Conversation error: {0}")]
+    Conversations(#[from] ConversationsError),
+    #[error("Conversation error: {0}")]
+    AccountConversations(#[from] AccountConversationsError),
+}
+
+/// Who can see a status
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    /// Visible to everyone and shown in public timelines
+    #[default]
+    Public,
+    /// Visible to everyone but left out of public timelines
+    Unlisted,
+    /// Visible to followers only
+    Private,
+    /// Visible to mentioned accounts only
+    Direct,
+}
+
+impl Visibility {
+    /// Get the name used in the database and API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Visibility::Public => "public",
+            Visibility::Unlisted => "unlisted",
+            Visibility::Private => "private",
+            Visibility::Direct => "direct",
+        }
+    }
+}
+
+impl fmt::Display for Visibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Visibility {
+    type Err = StatusesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "public" => Ok(Visibility::Public),
+            "unlisted" => Ok(Visibility::Unlisted),
+            "private" => Ok(Visibility::Private),
+            "direct" => Ok(Visibility::Direct),
+            other => Err(StatusesError::InvalidVisibility(other.to_string())),
+        }
+    }
+}
+
+/// Status data structure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Status {
+    /// Unique identifier for the status
+    pub id: i64,
+    /// ID of the account that posted the status
+    pub account_id: i64,
+    /// HTML content of the status
+    pub content: String,
+    /// Who can see the status
+    pub visibility: Visibility,
+    /// Whether the media is marked sensitive
+    pub sensitive: bool,
+    /// Content warning shown before the content
+    pub spoiler_text: String,
+    /// ID of the status this one replies to
+    pub in_reply_to_id: Option<i64>,
+    /// ID of the account this status replies to
+    pub in_reply_to_account_id: Option<i64>,
+    /// ID of the status this one reblogs
+    pub reblog_of_id: Option<i64>,
+    /// ISO 639 language code
+    pub language: Option<String>,
+    /// ActivityPub ID of the status
+    pub uri: Option<String>,
+    /// HTML page of the status
+    pub url: Option<String>,
+    /// Whether the status was posted on this instance
+    pub local: bool,
+    /// Number of favourites
+    pub favourites_count: i32,
+    /// Number of reblogs
+    pub reblogs_count: i32,
+    /// Number of replies
+    pub replies_count: i32,
+    /// ID of the attached poll
+    pub poll_id: Option<i64>,
+    /// When the status was created
+    pub created_at: DateTime<Utc>,
+    /// When the status was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Data for a new local status
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NewStatus {
+    /// ID of the posting account
+    pub account_id: i64,
+    /// Text as typed by the user, rendered to HTML on creation
+    pub text: String,
+    /// Who can see the status
+    pub visibility: Visibility,
+    /// Whether the media is marked sensitive
+    pub sensitive: bool,
+    /// Content warning shown before the content
+    pub spoiler_text: Option<String>,
+    /// ID of the status this one replies to
+    pub in_reply_to_id: Option<i64>,
+    /// ISO 639 language code
+    pub language: Option<String>,
+    /// IDs of the attached media
+    #[serde(default)]
+    pub media_ids: Vec<i64>,
+    /// Attached poll
+    pub poll: Option<NewPoll>,
+}
+
+/// Locks the media to attach to a new status
+///
+/// Media can only be attached by the account that uploaded it, and only to
+/// one status.
+///
+/// # Arguments
+///
+/// * `tx` - Transaction creating the status
+/// * `account_id` - ID of the author of the status
+/// * `media_ids` - IDs of the media to attach
+///
+/// # Returns
+///
+/// Result containing the IDs of the media, in order and without duplicates,
+/// or a validation error if some of it cannot be attached
+async fn claim_media(
+    tx: &mut Transaction<'_, Postgres>,
+    account_id: i64,
+    media_ids: &[i64],
+) -> Result<Vec<i64>, StatusesError> {
+    let mut ids = Vec::with_capacity(media_ids.len());
+    for id in media_ids {
+        if !ids.contains(id) {
+            ids.push(*id);
+        }
+    }
+    if ids.is_empty() {
+        return Ok(ids);
+    }
+
+    let claimed = sqlx::query_scalar!(
+        r#"
+        SELECT id FROM media_attachments
+        WHERE id = ANY($1) AND account_id = $2 AND status_id IS NULL
+        FOR UPDATE
+        "#,
+        &ids,
+        account_id
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+    if claimed.len() != ids.len() {
+        return Err(StatusesError::Validation(
+            "Media not found or already attached to a status".to_string(),
+        ));
+    }
+
+    Ok(ids)
+}
+
+/// Renders the media attachments stored with a status
+fn media_ids_json(media_ids: &[i64]) -> Option<Value> {
+    if media_ids.is_empty() {
+        return None;
+    }
+    Some(Value::Array(
+        media_ids
+            .iter()
+            .map(|id| json!({ "id": id.to_string() }))
+            .collect(),
+    ))
+}
+
+/// Status created in a transaction that has not been committed yet
+///
+/// The mentioned accounts are only notified once the status is committed.
+pub struct CreatedStatus {
+    pub status: Status,
+    mentioned: Vec<MentionedAccount>,
+}
+
+impl CreatedStatus {
+    /// Notifies the local accounts mentioned by the committed status
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    pub async fn notify(&self, pool: &PgPool) {
+        notify_mentioned(pool, &self.status, &self.mentioned).await;
+    }
+}
+
+impl Status {
+    /// Creates a local status
+    ///
+    /// The text is rendered to HTML, and its mentions and hashtags are
+    /// recorded. Attached media must belong to the author and is linked
+    /// to the status. Replies are linked to the author of the replied-to status
+    /// and join its conversation, and the reply and status counters are
+    /// updated in the same transaction. Direct statuses are added to the
+    /// conversation threads of their local participants. Mentioned local
+    /// accounts are notified.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `new_status` - Data for the new status
+    /// * `local_domain` - Domain of this instance
+    ///
+    /// # Returns
+    ///
+    /// Result containing the created status or an error
+    pub async fn create(
+        pool: &PgPool,
+        new_status: NewStatus,
+        local_domain: &str,
+    ) -> Result<Self, StatusesError> {
+        let mut tx = pool.begin().await?;
+        let created = Self::create_in(&mut tx, new_status, local_domain).await?;
+        tx.commit().await?;
+
+        created.notify(pool).await;
+        info!(
+            "Created status {} for account {}",
+            created.status.id, created.status.account_id
+        );
+        Ok(created.status)
+    }
+
+    /// Creates a local status in a transaction
+    ///
+    /// Works like [`Status::create`], for callers that change other rows
+    /// along with the status. Mentioned accounts are notified by calling
+    /// [`CreatedStatus::notify`] once the transaction is committed.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx` - Transaction to create the status in
+    /// * `new_status` - Data for the new status
+    /// * `local_domain` - Domain of this instance
+    ///
+    /// # Returns
+    ///
+    /// Result containing the created status or an error
+    pub async fn create_in(
+        tx: &mut Transaction<'_, Postgres>,
+        new_status: NewStatus,
+        local_domain: &str,
+    ) -> Result<CreatedStatus, StatusesError> {
+        trace!("Creating status for account {}", new_status.account_id);
+
+        if new_status.text.trim().is_empty()
+            && new_status.media_ids.is_empty()
+            && new_status.poll.is_none()
+        {
+            return Err(StatusesError::Validation(
+                "Status must have text, media or a poll".to_string(),
+            ));
+        }
+
+        let in_reply_to_account_id = match new_status.in_reply_to_id {
+            Some(parent_id) => Some(
+                sqlx::query_scalar!(
+                    "SELECT account_id FROM statuses WHERE id = $1 AND deleted_at IS NULL",
+                    parent_id
+                )
+                .fetch_optional(&mut **tx)
+                .await?
+                .ok_or(StatusesError::StatusNotFound(parent_id))?,
+            ),
+            None => None,
+        };
+        let conversation_id = Conversation::for_status(tx, new_status.in_reply_to_id).await?;
+        let mentioned =
+            Mention::resolve(tx, &extract_mentions(&new_status.text), local_domain).await?;
+        let content = render_text(&new_status.text, &mentioned, local_domain);
+        let media_ids = claim_media(tx, new_status.account_id, &new_status.media_ids).await?;
+        let media_attachments = media_ids_json(&media_ids);
+        let status_type = if new_status.in_reply_to_id.is_some() {
+            "reply"
+        } else {
+            "status"
+        };
+
+        let row = sqlx::query_as!(
+            StatusRow,
+            r#"
+            INSERT INTO statuses (
+                account_id, content, visibility, sensitive, spoiler_text, in_reply_to_id,
+                in_reply_to_account_id, status_type, language, local, media_attachments,
+                conversation_id
+            )
+            VALUES ($1, $2, $3::TEXT::status_visibility, $4, $5, $6, $7, $8::TEXT::status_type,
+                    $9, TRUE, $10, $11)
+            RETURNING id, account_id, content, visibility::TEXT AS "visibility!",
+                      (sensitive OR EXISTS (
+                          SELECT 1 FROM users u
+                          WHERE u.id = statuses.account_id AND u.sensitized_at IS NOT NULL
+                      )) AS "sensitive!",
+                      spoiler_text, in_reply_to_id, in_reply_to_account_id, reblog_of_id,
+                      language, uri, url, local, favourites_count, reblogs_count, replies_count,
+                      NULL::BIGINT AS poll_id, created_at, updated_at
+            "#,
+            new_status.account_id,
+            content,
+            new_status.visibility.as_str(),
+            new_status.sensitive,
+            new_status.spoiler_text,
+            new_status.in_reply_to_id,
+            in_reply_to_account_id,
+            status_type,
+            new_status.language,
+            media_attachments,
+            conversation_id
+        )
+        .fetch_one(&mut **tx)
+        .await?;
+
+        let mut status = Status::try_from(row)?;
+        if !media_ids.is_empty() {
+            sqlx::query!(
+                "UPDATE media_attachments SET status_id = $1, updated_at = NOW() WHERE id = ANY($2)",
+                status.id,
+                &media_ids
+            )
+            .execute(&mut **tx)
+            .await?;
+        }
+        if let Some(new_poll) = &new_status.poll {
+            let poll = Poll::create(tx, status.account_id, status.id, new_poll).await?;
+            status.poll_id = Some(poll.id);
+        }
+
+        Mention::create_many(tx, status.id, &mentioned).await?;
+        let hashtags: Vec<String> = extract_hashtags(&new_status.text)
+            .into_iter()
+            .map(|token| token.name)
+            .collect();
+        let tags = Tag::find_or_create_many(tx, &hashtags).await?;
+        Tag::attach_to_status(tx, status.id, &tags).await?;
+
+        if status.visibility == Visibility::Direct {
+            let recipient_ids: Vec<i64> = mentioned.iter().map(|account| account.id).collect();
+            AccountConversation::add_status(
+                tx,
+                conversation_id,
+                status.id,
+                status.account_id,
+                &recipient_ids,
+            )
+            .await?;
+        }
+
+        if let Some(parent_id) = new_status.in_reply_to_id {
+            sqlx::query!(
+                "UPDATE statuses SET replies_count = replies_count + 1 WHERE id = $1",
+                parent_id
+            )
+            .execute(&mut **tx)
+            .await?;
+        }
+
+        sqlx::query!(
+            "UPDATE users SET statuses_count = statuses_count + 1 WHERE id = $1",
+            new_status.account_id
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(CreatedStatus { status, mentioned })
+    }
+
+    /// Gets a status by ID
+    ///
+    /// Deleted statuses are not returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `status_id` - ID of the status
+    ///
+    /// # Returns
+    ///
+    /// Result containing the status or an error
+    pub async fn get_by_id(pool: &PgPool, status_id: i64) -> Result<Self, StatusesError> {
+        trace!("Getting status by id: {}", status_id);
+
+        let row = sqlx::query_as!(
+            StatusRow,
+            r#"
+            SELECT id, account_id, content, visibility::TEXT AS "visibility!",
+                   (statuses.sensitive OR EXISTS (
+                       SELECT 1 FROM users u WHERE u.id = statuses.account_id AND u.sensitized_at IS NOT NULL
+                   )) AS "sensitive!",
+                   spoiler_text, in_reply_to_id, in_reply_to_account_id, reblog_of_id,
+                   language, uri, url, local, favourites_count, reblogs_count, replies_count,
+                   (SELECT p.id FROM polls p WHERE p.status_id = statuses.id) AS poll_id,
+                   created_at, updated_at
+            FROM statuses
+            WHERE id = $1 AND deleted_at IS NULL
+            "#,
+            status_id
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or(StatusesError::StatusNotFound(status_id))?;
+
+        Status::try_from(row)
+    }
+
+    /// Gets several statuses by ID
+    ///
+    /// Missing and deleted statuses are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `status_ids` - IDs of the statuses
+    ///
+    /// # Returns
+    ///
+    /// Result containing the statuses that exist, newest first
+    pub async fn get_by_ids(pool: &PgPool, status_ids: &[i64]) -> Result<Vec<Self>, StatusesError> {
+        trace!("Getting {} statuses by id", status_ids.len());
+
+        if status_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query_as!(
+            StatusRow,
+            r#"
+            SELECT id, account_id, content, visibility::TEXT AS "visibility!",
+                   (statuses.sensitive OR EXISTS (
+                       SELECT 1 FROM users u WHERE u.id = statuses.account_id AND u.sensitized_at IS NOT NULL
+                   )) AS "sensitive!",
+                   spoiler_text, in_reply_to_id, in_reply_to_account_id, reblog_of_id,
+                   language, uri, url, local, favourites_count, reblogs_count, replies_count,
+                   (SELECT p.id FROM polls p WHERE p.status_id = statuses.id) AS poll_id,
+                   created_at, updated_at
+            FROM statuses
+            WHERE id = ANY($1) AND deleted_at IS NULL
+            ORDER BY id DESC
+            "#,
+            status_ids
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let statuses = rows
+            .into_iter()
+            .map(Status::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        debug!("Retrieved {} statuses", statuses.len());
+        Ok(statuses)
+    }
+}
+
+/// Sends `mention` notifications to the local accounts a status mentions
+async fn notify_mentioned(pool: &PgPool, status: &Status, mentioned: &[MentionedAccount]) {
+    for account in mentioned
+        .iter()
+        .filter(|account| account.domain.is_none() && account.id != status.account_id)
+    {
+        let request = CreateNotificationRequest {
+            account_id: account.id,
+            from_account_id: Some(status.account_id),
+            notification_type: NotificationType::Mention,
+            status_id: Some(status.id),
+            poll_id: None,
+            report_id: None,
+        };
+        if let Err(e) = Notification::create(pool, request).await {
+            error!(
+                "Failed to notify account {} of mention in status {}: {}",
+                account.id, status.id, e
+            );
+        }
+    }
+}
+
+struct StatusRow {
+    id: i64,
+    account_id: i64,
+    content: String,
+    visibility: String,
+    sensitive: bool,
+    spoiler_text: Option<String>,
+    in_reply_to_id: Option<i64>,
+    in_reply_to_account_id: Option<i64>,
+    reblog_of_id: Option<i64>,
+    language: Option<String>,
+    uri: Option<String>,
+    url: Option<String>,
+    local: bool,
+    favourites_count: i32,
+    reblogs_count: i32,
+    replies_count: i32,
+    poll_id: Option<i64>,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+}
+
+impl TryFrom<StatusRow> for Status {
+    type Error = StatusesError;
+
+    fn try_from(row: StatusRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            account_id: row.account_id,
+            content: row.content,
+            visibility: row.visibility.parse()?,
+            sensitive: row.sensitive,
+            spoiler_text: row.spoiler_text.unwrap_or_default(),
+            in_reply_to_id: row.in_reply_to_id,
+            in_reply_to_account_id: row.in_reply_to_account_id,
+            reblog_of_id: row.reblog_of_id,
+            language: row.language,
+            uri: row.uri,
+            url: row.url,
+            local: row.local,
+            favourites_count: row.favourites_count,
+            reblogs_count: row.reblogs_count,
+            replies_count: row.replies_count,
+            poll_id: row.poll_id,
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+            updated_at: DateTime::from_naive_utc_and_offset(row.updated_at, Utc),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -7,8 +619,55 @@ mod tests {
     use super::*;
 
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn test_visibility_round_trip() {
+        for visibility in [
+            Visibility::Public,
+            Visibility::Unlisted,
+            Visibility::Private,
+            Visibility::Direct,
+        ] {
+            assert_eq!(
+                visibility.as_str().parse::<Visibility>().unwrap(),
+                visibility
+            );
+        }
+        assert!("followers".parse::<Visibility>().is_err());
+    }
+
+    #[test]
+    fn test_media_ids_json() {
+        assert!(media_ids_json(&[]).is_none());
+        assert_eq!(media_ids_json(&[7]).unwrap()[0]["id"], "7");
+    }
+
+    #[test]
+    fn test_status_from_row() {
+        let now = Utc::now().naive_utc();
+        let status = Status::try_from(StatusRow {
+            id: 1,
+            account_id: 2,
+            content: "<p>Hello</p>".to_string(),
+            visibility: "unlisted".to_string(),
+            sensitive: false,
+            spoiler_text: None,
+            in_reply_to_id: None,
+            in_reply_to_account_id: None,
+            reblog_of_id: None,
+            language: Some("en".to_string()),
+            uri: None,
+            url: None,
+            local: true,
+            favourites_count: 3,
+            reblogs_count: 0,
+            replies_count: 0,
+            poll_id: None,
+            created_at: now,
+            updated_at: now,
+        })
+        .unwrap();
+
+        assert_eq!(status.visibility, Visibility::Unlisted);
+        assert_eq!(status.spoiler_text, "");
+        assert_eq!(status.favourites_count, 3);
     }
 }
@@ -0,0 +1,475 @@
+//! Status text processing
+//!
+//! Turns the text typed by a user into the HTML stored with a status:
+//! URLs are linked, resolved mentions link to the mentioned profile and
+//! hashtags link to their timeline. Everything else is escaped.
+//!
+//! HTML received from remote servers goes through [`sanitize_html`],
+//! which keeps only the small set of elements, attributes and classes
+//! that local statuses are rendered with.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use regex::{Captures, Regex};
+use rustodon_mentions::{extract_mentions, MentionToken, MentionedAccount};
+use rustodon_tags::{extract_hashtags, normalize_name};
+use std::ops::Range;
+use std::sync::OnceLock;
+
+/// Elements kept by [`sanitize_html`]
+const ALLOWED_ELEMENTS: &[&str] = &[
+    "p",
+    "br",
+    "span",
+    "a",
+    "del",
+    "s",
+    "pre",
+    "blockquote",
+    "code",
+    "b",
+    "strong",
+    "u",
+    "i",
+    "em",
+    "ul",
+    "ol",
+    "li",
+];
+
+/// Elements dropped together with everything inside them
+const DROPPED_ELEMENTS: &[&str] = &[
+    "script", "style", "iframe", "object", "embed", "template", "noscript", "textarea", "title",
+    "head", "svg", "math", "select",
+];
+
+/// Link schemes kept by [`sanitize_html`]
+const ALLOWED_SCHEMES: &[&str] = &[
+    "http", "https", "dat", "dweb", "ipfs", "ipns", "ssb", "gopher", "xmpp", "magnet", "gemini",
+];
+
+/// Displayed length of a link before the rest is hidden
+const LINK_DISPLAY_LENGTH: usize = 30;
+
+fn url_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r#"https?://[^\s<>"]+"#).expect("valid regex"))
+}
+
+fn token_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r#"(?s)<!--.*?(?:-->|$)|<(?P<close>/?)(?P<name>[a-zA-Z][a-zA-Z0-9]*)(?P<attrs>(?:[^>"']|"[^"]*"|'[^']*')*)>|[^<]+|<"#,
+        )
+        .expect("valid regex")
+    })
+}
+
+fn attribute_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r#"(?s)([a-zA-Z_:.-]+)(?:\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+)))?"#)
+            .expect("valid regex")
+    })
+}
+
+fn entity_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").expect("valid regex"))
+}
+
+/// Escapes text for use in HTML content and attribute values
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn decode_entities(text: &str) -> String {
+    entity_regex()
+        .replace_all(text, |caps: &Captures| {
+            let entity = &caps[1];
+            let decoded = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+                    u32::from_str_radix(&entity[2..], 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                }
+                _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(char::from_u32),
+                _ => None,
+            };
+            decoded.map_or_else(|| caps[0].to_string(), String::from)
+        })
+        .into_owned()
+}
+
+/// Finds the URLs in status text, without trailing punctuation
+fn extract_urls(text: &str) -> Vec<Range<usize>> {
+    url_regex()
+        .find_iter(text)
+        .map(|m| {
+            let mut url = m.as_str();
+            loop {
+                let trimmed = url.trim_end_matches(['.', ',', ':', ';', '!', '?', '\'']);
+                // Keep a closing parenthesis only when it closes one in the URL
+                let trimmed = match trimmed.strip_suffix(')') {
+                    Some(rest) if rest.matches('(').count() <= rest.matches(')').count() => rest,
+                    _ => trimmed,
+                };
+                if trimmed.len() == url.len() {
+                    break;
+                }
+                url = trimmed;
+            }
+            m.start()..m.start() + url.len()
+        })
+        .filter(|range| {
+            let url = &text[range.clone()];
+            url.len() > url.find("://").map_or(0, |i| i + 3)
+        })
+        .collect()
+}
+
+/// A linked part of status text
+enum Entity<'a> {
+    Url,
+    Mention(&'a MentionedAccount),
+    Hashtag(&'a str),
+}
+
+fn link_html(url: &str) -> String {
+    // The scheme and a www. prefix are hidden
+    let mut prefix_len = url.find("://").map_or(0, |i| i + 3);
+    if url[prefix_len..].starts_with("www.") {
+        prefix_len += 4;
+    }
+    let (scheme, rest) = url.split_at(prefix_len);
+    let cut = rest
+        .char_indices()
+        .nth(LINK_DISPLAY_LENGTH)
+        .map_or(rest.len(), |(i, _)| i);
+    let (display, hidden) = rest.split_at(cut);
+
+    format!(
+        r#"<a href="{}" target="_blank" rel="nofollow noopener noreferrer" translate="no"><span class="invisible">{}</span><span class="{}">{}</span><span class="invisible">{}</span></a>"#,
+        escape_html(url),
+        escape_html(scheme),
+        if hidden.is_empty() { "" } else { "ellipsis" },
+        escape_html(display),
+        escape_html(hidden)
+    )
+}
+
+fn mention_html(account: &MentionedAccount) -> String {
+    format!(
+        r#"<span class="h-card" translate="no"><a href="{}" class="u-url mention">@<span>{}</span></a></span>"#,
+        escape_html(&account.url),
+        escape_html(&account.username)
+    )
+}
+
+fn hashtag_html(name: &str, local_domain: &str) -> String {
+    format!(
+        r#"<a href="https://{}/tags/{}" class="mention hashtag" rel="tag">#<span>{}</span></a>"#,
+        local_domain,
+        escape_html(&normalize_name(name)),
+        escape_html(name)
+    )
+}
+
+/// Finds the account a mention refers to among the resolved accounts
+fn mentioned_account<'a>(
+    token: &MentionToken,
+    accounts: &'a [MentionedAccount],
+    local_domain: &str,
+) -> Option<&'a MentionedAccount> {
+    let acct = match token.domain.as_deref() {
+        Some(domain) if !domain.eq_ignore_ascii_case(local_domain) => token.acct(),
+        _ => token.username.clone(),
+    };
+    accounts
+        .iter()
+        .find(|account| account.acct.eq_ignore_ascii_case(&acct))
+}
+
+/// Renders status text as HTML
+///
+/// URLs, mentions of the given accounts and hashtags become links; the
+/// rest of the text is escaped. Blank lines separate paragraphs and
+/// single line breaks become `<br>`.
+///
+/// # Arguments
+///
+/// * `text` - Text as typed by the user
+/// * `accounts` - Accounts the text's mentions resolved to
+/// * `local_domain` - Domain of this instance, used for hashtag links
+///
+/// # Returns
+///
+/// HTML content of the status
+pub fn render_text(text: &str, accounts: &[MentionedAccount], local_domain: &str) -> String {
+    let mut entities: Vec<(Range<usize>, Entity)> = extract_urls(text)
+        .into_iter()
+        .map(|range| (range, Entity::Url))
+        .collect();
+    entities.extend(extract_mentions(text).into_iter().filter_map(|token| {
+        mentioned_account(&token, accounts, local_domain)
+            .map(|account| (token.range, Entity::Mention(account)))
+    }));
+    entities.extend(extract_hashtags(text).into_iter().map(|token| {
+        let name = &text[token.range.start + 1..token.range.end];
+        (token.range, Entity::Hashtag(name))
+    }));
+    // URLs come first, so a mention or hashtag inside a URL is dropped
+    entities.sort_by_key(|(range, _)| range.start);
+
+    let mut html = String::with_capacity(text.len());
+    let mut position = 0;
+    for (range, entity) in entities {
+        if range.start < position {
+            continue;
+        }
+        html.push_str(&escape_html(&text[position..range.start]));
+        html.push_str(&match entity {
+            Entity::Url => link_html(&text[range.clone()]),
+            Entity::Mention(account) => mention_html(account),
+            Entity::Hashtag(name) => hashtag_html(name, local_domain),
+        });
+        position = range.end;
+    }
+    html.push_str(&escape_html(&text[position..]));
+
+    html.replace("\r\n", "\n")
+        .split("\n\n")
+        .map(str::trim)
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| format!("<p>{}</p>", paragraph.replace('\n', "<br>")))
+        .collect()
+}
+
+/// Keeps the classes used for mentions, hashtags, links and microformats
+fn allowed_classes(value: &str) -> String {
+    value
+        .split_whitespace()
+        .filter(|class| {
+            matches!(*class, "mention" | "hashtag" | "ellipsis" | "invisible")
+                || ["h-", "p-", "u-", "dt-", "e-"]
+                    .iter()
+                    .any(|prefix| class.starts_with(prefix))
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn allowed_href(value: &str) -> bool {
+    value
+        .split_once(':')
+        .is_some_and(|(scheme, _)| ALLOWED_SCHEMES.contains(&scheme.to_ascii_lowercase().as_str()))
+}
+
+/// Renders the allowed attributes of an element
+fn sanitized_attributes(element: &str, attrs: &str) -> String {
+    let mut out = String::new();
+    let mut rel_tag = false;
+    for caps in attribute_regex().captures_iter(attrs) {
+        let name = caps[1].to_ascii_lowercase();
+        let value = decode_entities(
+            caps.get(2)
+                .or_else(|| caps.get(3))
+                .or_else(|| caps.get(4))
+                .map_or("", |m| m.as_str()),
+        );
+        let value = value.trim();
+        let kept = match (element, name.as_str()) {
+            ("a", "href") if allowed_href(value) => Some(value.to_string()),
+            ("a", "rel") => {
+                rel_tag = value.split_whitespace().any(|rel| rel == "tag");
+                None
+            }
+            ("a" | "span", "class") => Some(allowed_classes(value)).filter(|c| !c.is_empty()),
+            ("a" | "span", "translate") if value == "no" => Some(value.to_string()),
+            ("ol", "start") | ("li", "value") if value.parse::<i64>().is_ok() => {
+                Some(value.to_string())
+            }
+            ("ol", "reversed") => Some(String::new()),
+            _ => None,
+        };
+        if let Some(value) = kept {
+            if out.contains(&format!(" {}=", name)) {
+                continue;
+            }
+            out.push_str(&format!(" {}=\"{}\"", name, escape_html(&value)));
+        }
+    }
+    if element == "a" {
+        out.push_str(if rel_tag {
+            r#" rel="tag nofollow noopener noreferrer" target="_blank""#
+        } else {
+            r#" rel="nofollow noopener noreferrer" target="_blank""#
+        });
+    }
+    out
+}
+
+/// Sanitizes HTML to the elements local statuses use
+///
+/// Allowed elements keep only their allowed attributes; links keep only
+/// http(s) and a few other safe schemes. Scripts, styles, embeds and
+/// similar elements are removed with their content, and any other element
+/// is replaced by its content. Unclosed elements are closed.
+///
+/// # Arguments
+///
+/// * `html` - HTML from a remote server
+///
+/// # Returns
+///
+/// HTML safe to serve to clients
+pub fn sanitize_html(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut open: Vec<&'static str> = Vec::new();
+    let mut dropping: Option<(String, usize)> = None;
+
+    for caps in token_regex().captures_iter(html) {
+        let token = caps.get(0).map_or("", |m| m.as_str());
+        let Some(name) = caps.name("name") else {
+            if dropping.is_none() && !token.starts_with("<!--") {
+                out.push_str(&escape_html(&decode_entities(token)));
+            }
+            continue;
+        };
+        let name = name.as_str().to_ascii_lowercase();
+        let closing = caps.name("close").is_some_and(|m| !m.as_str().is_empty());
+        let attrs = caps.name("attrs").map_or("", |m| m.as_str());
+        let self_closing = attrs.trim_end().ends_with('/');
+
+        if let Some((dropped, depth)) = &mut dropping {
+            if *dropped == name {
+                if closing {
+                    *depth -= 1;
+                } else if !self_closing {
+                    *depth += 1;
+                }
+                if *depth == 0 {
+                    dropping = None;
+                }
+            }
+            continue;
+        }
+        if DROPPED_ELEMENTS.contains(&name.as_str()) {
+            if !closing && !self_closing {
+                dropping = Some((name, 1));
+            }
+            continue;
+        }
+
+        let Some(element) = ALLOWED_ELEMENTS.iter().copied().find(|e| *e == name) else {
+            continue;
+        };
+        if element == "br" {
+            if !closing {
+                out.push_str("<br>");
+            }
+        } else if closing {
+            if let Some(index) = open.iter().rposition(|e| *e == element) {
+                for e in open.drain(index..).rev() {
+                    out.push_str(&format!("</{}>", e));
+                }
+            }
+        } else if !self_closing {
+            out.push_str(&format!(
+                "<{}{}>",
+                element,
+                sanitized_attributes(element, attrs)
+            ));
+            open.push(element);
+        }
+    }
+
+    for element in open.into_iter().rev() {
+        out.push_str(&format!("</{}>", element));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alice() -> MentionedAccount {
+        MentionedAccount {
+            id: 1,
+            username: "alice".to_string(),
+            domain: None,
+            acct: "alice".to_string(),
+            url: "https://rustodon.example/@alice".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_render_text() {
+        let html = render_text(
+            "Hi @alice & @nobody <3 #Rust\n\nhttps://example.com/a_very_long_path/that/goes/on?x=1.",
+            &[alice()],
+            "rustodon.example",
+        );
+
+        assert!(html.starts_with(r#"<p>Hi <span class="h-card" translate="no"><a href="https://rustodon.example/@alice" class="u-url mention">@<span>alice</span></a></span> &amp; @nobody &lt;3 "#));
+        assert!(html.contains(r#"<a href="https://rustodon.example/tags/rust" class="mention hashtag" rel="tag">#<span>Rust</span></a></p>"#));
+        assert!(html.contains(
+            r#"<a href="https://example.com/a_very_long_path/that/goes/on?x=1" target="_blank""#
+        ));
+        assert!(html.contains(r#"<span class="ellipsis">example.com/a_very_long_path/t</span>"#));
+        assert!(html.ends_with("</a>.</p>"));
+    }
+
+    #[test]
+    fn test_render_text_mentions_inside_urls() {
+        let html = render_text(
+            "see https://example.com/@alice#top and @alice@rustodon.example",
+            &[alice()],
+            "rustodon.example",
+        );
+        assert_eq!(html.matches("u-url mention").count(), 1);
+        assert!(!html.contains("hashtag"));
+    }
+
+    #[test]
+    fn test_sanitize_html() {
+        let html = r#"<p class="note">Hi <a href="https://remote.example/@bob" class="u-url mention evil" onclick="x()">@bob</a><script>alert(1)</script></p><div><img src="x.png">Tail &amp; <b>bold"#;
+        assert_eq!(
+            sanitize_html(html),
+            r#"<p>Hi <a href="https://remote.example/@bob" class="u-url mention" rel="nofollow noopener noreferrer" target="_blank">@bob</a></p>Tail &amp; <b>bold</b>"#
+        );
+    }
+
+    #[test]
+    fn test_sanitize_html_links() {
+        assert_eq!(
+            sanitize_html(r#"<a href="javascript:alert(1)">x</a><a href='/relative'>y</a>"#),
+            r#"<a rel="nofollow noopener noreferrer" target="_blank">x</a><a rel="nofollow noopener noreferrer" target="_blank">y</a>"#
+        );
+        assert_eq!(
+            sanitize_html("<ol start=\"3\" type=\"a\"><li>one<br/></li></ol><!-- c -->1 < 2"),
+            "<ol start=\"3\"><li>one<br></li></ol>1 &lt; 2"
+        );
+    }
+}
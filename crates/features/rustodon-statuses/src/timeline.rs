@@ -0,0 +1,671 @@
+//! Timeline queries
+//!
+//! Home, public and hashtag timelines are read straight from the statuses
+//! table: the home timeline of an account is made of its own statuses,
+//! those of the accounts it follows and public statuses using a hashtag it
+//! follows. Statuses from blocked and muted accounts are left out of all
+//! of them. Statuses of silenced accounts are only shown in the public
+//! and hashtag timelines to their followers, and statuses of accounts whose
+//! media is marked sensitive by staff are always sensitive.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::{Status, StatusRow, StatusesError, Visibility};
+use rustodon_tags::normalize_name;
+use sqlx::PgPool;
+use tracing::{debug, trace};
+
+/// Default number of statuses per page
+pub const DEFAULT_TIMELINE_LIMIT: i64 = 20;
+/// Maximum number of statuses per page
+pub const MAX_TIMELINE_LIMIT: i64 = 40;
+
+/// A page of a timeline
+///
+/// `max_id` pages backwards, `since_id` and `min_id` page forwards.
+/// `since_id` returns the newest statuses after the given one while
+/// `min_id` returns the statuses immediately after it. Results are always
+/// newest first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TimelinePage {
+    /// Maximum number of statuses, clamped to [`MAX_TIMELINE_LIMIT`]
+    pub limit: Option<i64>,
+    /// Only statuses older than this ID
+    pub max_id: Option<i64>,
+    /// Only statuses newer than this ID
+    pub since_id: Option<i64>,
+    /// Only statuses newer than this ID, oldest of them first
+    pub min_id: Option<i64>,
+}
+
+impl TimelinePage {
+    /// Number of statuses to return
+    pub fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_TIMELINE_LIMIT)
+            .clamp(1, MAX_TIMELINE_LIMIT)
+    }
+
+    /// Exclusive lower bound of the page
+    fn lower_bound(&self) -> Option<i64> {
+        match (self.min_id, self.since_id) {
+            (Some(min_id), Some(since_id)) => Some(min_id.max(since_id)),
+            (min_id, since_id) => min_id.or(since_id),
+        }
+    }
+}
+
+/// Which statuses a hashtag timeline shows
+///
+/// A status is shown if it uses any of the `any` tags, all of the `all`
+/// tags and none of the `none` tags.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagTimeline {
+    /// The status must use at least one of these tags
+    pub any: Vec<String>,
+    /// The status must use every one of these tags
+    pub all: Vec<String>,
+    /// The status must not use any of these tags
+    pub none: Vec<String>,
+    /// Only statuses posted on this instance
+    pub local: bool,
+    /// Only statuses posted on other instances
+    pub remote: bool,
+    /// Only statuses with media attachments
+    pub only_media: bool,
+}
+
+impl TagTimeline {
+    /// Creates a timeline of a single hashtag
+    pub fn new(name: &str) -> Self {
+        Self {
+            any: vec![name.to_string()],
+            ..Self::default()
+        }
+    }
+}
+
+/// Which statuses the public timeline shows
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PublicTimeline {
+    /// Only statuses posted on this instance
+    pub local: bool,
+    /// Only statuses posted on other instances
+    pub remote: bool,
+    /// Only statuses with media attachments
+    pub only_media: bool,
+}
+
+/// Normalizes tag names, dropping empty and duplicate ones
+fn normalize_names(names: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = names
+        .iter()
+        .map(|name| normalize_name(name))
+        .filter(|name| !name.is_empty())
+        .collect();
+    normalized.sort();
+    normalized.dedup();
+    normalized
+}
+
+/// Puts statuses fetched oldest first for `min_id` back to newest first
+fn into_statuses(rows: Vec<StatusRow>, page: &TimelinePage) -> Result<Vec<Status>, StatusesError> {
+    let mut statuses = rows
+        .into_iter()
+        .map(Status::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    if page.min_id.is_some() {
+        statuses.reverse();
+    }
+    Ok(statuses)
+}
+
+impl Status {
+    /// Gets a page of the public timeline
+    ///
+    /// The public timeline shows public statuses which are neither replies
+    /// nor reblogs.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `timeline` - Filters of the timeline
+    /// * `page` - Page to get
+    /// * `viewer_id` - Account looking at the timeline, if signed in
+    ///
+    /// # Returns
+    ///
+    /// Statuses, newest first
+    pub async fn public_timeline(
+        pool: &PgPool,
+        timeline: &PublicTimeline,
+        page: &TimelinePage,
+        viewer_id: Option<i64>,
+    ) -> Result<Vec<Self>, StatusesError> {
+        trace!("Getting public timeline {:?} ({:?})", timeline, page);
+
+        let rows = if page.min_id.is_some() {
+            sqlx::query_as!(
+                StatusRow,
+                r#"
+                SELECT s.id, s.account_id, s.content, s.visibility::TEXT AS "visibility!",
+                       (s.sensitive OR EXISTS (
+                           SELECT 1 FROM users u WHERE u.id = s.account_id AND u.sensitized_at IS NOT NULL
+                       )) AS "sensitive!",
+                       s.spoiler_text, s.in_reply_to_id, s.in_reply_to_account_id,
+                       s.reblog_of_id, s.language, s.uri, s.url, s.local, s.favourites_count,
+                       s.reblogs_count, s.replies_count,
+                       (SELECT p.id FROM polls p WHERE p.status_id = s.id) AS poll_id,
+                       s.created_at, s.updated_at
+                FROM statuses s
+                WHERE s.deleted_at IS NULL
+                  AND s.visibility = 'public'
+                  AND s.reblog_of_id IS NULL
+                  AND s.in_reply_to_id IS NULL
+                  AND (NOT $1 OR s.local)
+                  AND (NOT $2 OR NOT s.local)
+                  AND (NOT $3 OR jsonb_array_length(COALESCE(s.media_attachments, '[]')) > 0)
+                  AND ($4::BIGINT IS NULL OR (
+                      NOT EXISTS (
+                          SELECT 1 FROM blocks b
+                          WHERE (b.blocker_id = $4 AND b.blocked_id = s.account_id)
+                             OR (b.blocker_id = s.account_id AND b.blocked_id = $4)
+                      )
+                      AND NOT EXISTS (
+                          SELECT 1 FROM mutes m WHERE m.muter_id = $4 AND m.muted_id = s.account_id
+                      )
+                  ))
+                  AND NOT EXISTS (
+                      SELECT 1 FROM users u
+                      WHERE u.id = s.account_id AND u.silenced_at IS NOT NULL
+                        AND u.id IS DISTINCT FROM $4
+                        AND NOT EXISTS (
+                            SELECT 1 FROM follows f
+                            WHERE f.follower_id = $4 AND f.followed_id = u.id
+                              AND f.active AND NOT f.pending AND f.deleted_at IS NULL
+                        )
+                  )
+                  AND ($5::BIGINT IS NULL OR s.id < $5)
+                  AND ($6::BIGINT IS NULL OR s.id > $6)
+                ORDER BY s.id ASC
+                LIMIT $7
+                "#,
+                timeline.local,
+                timeline.remote,
+                timeline.only_media,
+                viewer_id,
+                page.max_id,
+                page.lower_bound(),
+                page.limit()
+            )
+            .fetch_all(pool)
+            .await?
+        } else {
+            sqlx::query_as!(
+                StatusRow,
+                r#"
+                SELECT s.id, s.account_id, s.content, s.visibility::TEXT AS "visibility!",
+                       (s.sensitive OR EXISTS (
+                           SELECT 1 FROM users u WHERE u.id = s.account_id AND u.sensitized_at IS NOT NULL
+                       )) AS "sensitive!",
+                       s.spoiler_text, s.in_reply_to_id, s.in_reply_to_account_id,
+                       s.reblog_of_id, s.language, s.uri, s.url, s.local, s.favourites_count,
+                       s.reblogs_count, s.replies_count,
+                       (SELECT p.id FROM polls p WHERE p.status_id = s.id) AS poll_id,
+                       s.created_at, s.updated_at
+                FROM statuses s
+                WHERE s.deleted_at IS NULL
+                  AND s.visibility = 'public'
+                  AND s.reblog_of_id IS NULL
+                  AND s.in_reply_to_id IS NULL
+                  AND (NOT $1 OR s.local)
+                  AND (NOT $2 OR NOT s.local)
+                  AND (NOT $3 OR jsonb_array_length(COALESCE(s.media_attachments, '[]')) > 0)
+                  AND ($4::BIGINT IS NULL OR (
+                      NOT EXISTS (
+                          SELECT 1 FROM blocks b
+                          WHERE (b.blocker_id = $4 AND b.blocked_id = s.account_id)
+                             OR (b.blocker_id = s.account_id AND b.blocked_id = $4)
+                      )
+                      AND NOT EXISTS (
+                          SELECT 1 FROM mutes m WHERE m.muter_id = $4 AND m.muted_id = s.account_id
+                      )
+                  ))
+                  AND NOT EXISTS (
+                      SELECT 1 FROM users u
+                      WHERE u.id = s.account_id AND u.silenced_at IS NOT NULL
+                        AND u.id IS DISTINCT FROM $4
+                        AND NOT EXISTS (
+                            SELECT 1 FROM follows f
+                            WHERE f.follower_id = $4 AND f.followed_id = u.id
+                              AND f.active AND NOT f.pending AND f.deleted_at IS NULL
+                        )
+                  )
+                  AND ($5::BIGINT IS NULL OR s.id < $5)
+                  AND ($6::BIGINT IS NULL OR s.id > $6)
+                ORDER BY s.id DESC
+                LIMIT $7
+                "#,
+                timeline.local,
+                timeline.remote,
+                timeline.only_media,
+                viewer_id,
+                page.max_id,
+                page.lower_bound(),
+                page.limit()
+            )
+            .fetch_all(pool)
+            .await?
+        };
+
+        let statuses = into_statuses(rows, page)?;
+        debug!("Retrieved {} statuses for public timeline", statuses.len());
+        Ok(statuses)
+    }
+
+    /// Gets a page of public statuses using the given hashtags
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `timeline` - Hashtags and filters of the timeline
+    /// * `page` - Page to get
+    /// * `viewer_id` - Account looking at the timeline, if signed in
+    ///
+    /// # Returns
+    ///
+    /// Statuses, newest first
+    pub async fn tag_timeline(
+        pool: &PgPool,
+        timeline: &TagTimeline,
+        page: &TimelinePage,
+        viewer_id: Option<i64>,
+    ) -> Result<Vec<Self>, StatusesError> {
+        trace!("Getting tag timeline {:?} ({:?})", timeline, page);
+
+        let any = normalize_names(&timeline.any);
+        if any.is_empty() {
+            return Ok(Vec::new());
+        }
+        let all = normalize_names(&timeline.all);
+        let none = normalize_names(&timeline.none);
+
+        let rows = if page.min_id.is_some() {
+            sqlx::query_as!(
+                StatusRow,
+                r#"
+                SELECT s.id, s.account_id, s.content, s.visibility::TEXT AS "visibility!",
+                       (s.sensitive OR EXISTS (
+                           SELECT 1 FROM users u WHERE u.id = s.account_id AND u.sensitized_at IS NOT NULL
+                       )) AS "sensitive!",
+                       s.spoiler_text, s.in_reply_to_id, s.in_reply_to_account_id,
+                       s.reblog_of_id, s.language, s.uri, s.url, s.local, s.favourites_count,
+                       s.reblogs_count, s.replies_count,
+                       (SELECT p.id FROM polls p WHERE p.status_id = s.id) AS poll_id,
+                       s.created_at, s.updated_at
+                FROM statuses s
+                WHERE s.deleted_at IS NULL
+                  AND s.visibility = 'public'
+                  AND s.reblog_of_id IS NULL
+                  AND EXISTS (
+                      SELECT 1 FROM statuses_tags st JOIN tags t ON t.id = st.tag_id
+                      WHERE st.status_id = s.id AND t.name = ANY($1)
+                  )
+                  AND NOT EXISTS (
+                      SELECT 1 FROM UNNEST($2::TEXT[]) AS wanted(name)
+                      WHERE NOT EXISTS (
+                          SELECT 1 FROM statuses_tags st JOIN tags t ON t.id = st.tag_id
+                          WHERE st.status_id = s.id AND t.name = wanted.name
+                      )
+                  )
+                  AND NOT EXISTS (
+                      SELECT 1 FROM statuses_tags st JOIN tags t ON t.id = st.tag_id
+                      WHERE st.status_id = s.id AND t.name = ANY($3)
+                  )
+                  AND (NOT $4 OR s.local)
+                  AND (NOT $5 OR NOT s.local)
+                  AND (NOT $6 OR jsonb_array_length(COALESCE(s.media_attachments, '[]')) > 0)
+                  AND ($7::BIGINT IS NULL OR (
+                      NOT EXISTS (
+                          SELECT 1 FROM blocks b
+                          WHERE (b.blocker_id = $7 AND b.blocked_id = s.account_id)
+                             OR (b.blocker_id = s.account_id AND b.blocked_id = $7)
+                      )
+                      AND NOT EXISTS (
+                          SELECT 1 FROM mutes m WHERE m.muter_id = $7 AND m.muted_id = s.account_id
+                      )
+                  ))
+                  AND NOT EXISTS (
+                      SELECT 1 FROM users u
+                      WHERE u.id = s.account_id AND u.silenced_at IS NOT NULL
+                        AND u.id IS DISTINCT FROM $7
+                        AND NOT EXISTS (
+                            SELECT 1 FROM follows f
+                            WHERE f.follower_id = $7 AND f.followed_id = u.id
+                              AND f.active AND NOT f.pending AND f.deleted_at IS NULL
+                        )
+                  )
+                  AND ($8::BIGINT IS NULL OR s.id < $8)
+                  AND ($9::BIGINT IS NULL OR s.id > $9)
+                ORDER BY s.id ASC
+                LIMIT $10
+                "#,
+                &any,
+                &all,
+                &none,
+                timeline.local,
+                timeline.remote,
+                timeline.only_media,
+                viewer_id,
+                page.max_id,
+                page.lower_bound(),
+                page.limit()
+            )
+            .fetch_all(pool)
+            .await?
+        } else {
+            sqlx::query_as!(
+                StatusRow,
+                r#"
+                SELECT s.id, s.account_id, s.content, s.visibility::TEXT AS "visibility!",
+                       (s.sensitive OR EXISTS (
+                           SELECT 1 FROM users u WHERE u.id = s.account_id AND u.sensitized_at IS NOT NULL
+                       )) AS "sensitive!",
+                       s.spoiler_text, s.in_reply_to_id, s.in_reply_to_account_id,
+                       s.reblog_of_id, s.language, s.uri, s.url, s.local, s.favourites_count,
+                       s.reblogs_count, s.replies_count,
+                       (SELECT p.id FROM polls p WHERE p.status_id = s.id) AS poll_id,
+                       s.created_at, s.updated_at
+                FROM statuses s
+                WHERE s.deleted_at IS NULL
+                  AND s.visibility = 'public'
+                  AND s.reblog_of_id IS NULL
+                  AND EXISTS (
+                      SELECT 1 FROM statuses_tags st JOIN tags t ON t.id = st.tag_id
+                      WHERE st.status_id = s.id AND t.name = ANY($1)
+                  )
+                  AND NOT EXISTS (
+                      SELECT 1 FROM UNNEST($2::TEXT[]) AS wanted(name)
+                      WHERE NOT EXISTS (
+                          SELECT 1 FROM statuses_tags st JOIN tags t ON t.id = st.tag_id
+                          WHERE st.status_id = s.id AND t.name = wanted.name
+                      )
+                  )
+                  AND NOT EXISTS (
+                      SELECT 1 FROM statuses_tags st JOIN tags t ON t.id = st.tag_id
+                      WHERE st.status_id = s.id AND t.name = ANY($3)
+                  )
+                  AND (NOT $4 OR s.local)
+                  AND (NOT $5 OR NOT s.local)
+                  AND (NOT $6 OR jsonb_array_length(COALESCE(s.media_attachments, '[]')) > 0)
+                  AND ($7::BIGINT IS NULL OR (
+                      NOT EXISTS (
+                          SELECT 1 FROM blocks b
+                          WHERE (b.blocker_id = $7 AND b.blocked_id = s.account_id)
+                             OR (b.blocker_id = s.account_id AND b.blocked_id = $7)
+                      )
+                      AND NOT EXISTS (
+                          SELECT 1 FROM mutes m WHERE m.muter_id = $7 AND m.muted_id = s.account_id
+                      )
+                  ))
+                  AND NOT EXISTS (
+                      SELECT 1 FROM users u
+                      WHERE u.id = s.account_id AND u.silenced_at IS NOT NULL
+                        AND u.id IS DISTINCT FROM $7
+                        AND NOT EXISTS (
+                            SELECT 1 FROM follows f
+                            WHERE f.follower_id = $7 AND f.followed_id = u.id
+                              AND f.active AND NOT f.pending AND f.deleted_at IS NULL
+                        )
+                  )
+                  AND ($8::BIGINT IS NULL OR s.id < $8)
+                  AND ($9::BIGINT IS NULL OR s.id > $9)
+                ORDER BY s.id DESC
+                LIMIT $10
+                "#,
+                &any,
+                &all,
+                &none,
+                timeline.local,
+                timeline.remote,
+                timeline.only_media,
+                viewer_id,
+                page.max_id,
+                page.lower_bound(),
+                page.limit()
+            )
+            .fetch_all(pool)
+            .await?
+        };
+
+        let statuses = into_statuses(rows, page)?;
+        debug!("Retrieved {} statuses for tag timeline", statuses.len());
+        Ok(statuses)
+    }
+
+    /// Gets a page of the home timeline of an account
+    ///
+    /// The home timeline shows the account's own statuses, non-direct
+    /// statuses of the accounts it follows, and public statuses using a
+    /// hashtag it follows.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - Account whose timeline to get
+    /// * `page` - Page to get
+    ///
+    /// # Returns
+    ///
+    /// Statuses, newest first
+    pub async fn home_timeline(
+        pool: &PgPool,
+        account_id: i64,
+        page: &TimelinePage,
+    ) -> Result<Vec<Self>, StatusesError> {
+        trace!(
+            "Getting home timeline of account {} ({:?})",
+            account_id,
+            page
+        );
+
+        let rows = if page.min_id.is_some() {
+            sqlx::query_as!(
+                StatusRow,
+                r#"
+                SELECT s.id, s.account_id, s.content, s.visibility::TEXT AS "visibility!",
+                       (s.sensitive OR EXISTS (
+                           SELECT 1 FROM users u WHERE u.id = s.account_id AND u.sensitized_at IS NOT NULL
+                       )) AS "sensitive!",
+                       s.spoiler_text, s.in_reply_to_id, s.in_reply_to_account_id,
+                       s.reblog_of_id, s.language, s.uri, s.url, s.local, s.favourites_count,
+                       s.reblogs_count, s.replies_count,
+                       (SELECT p.id FROM polls p WHERE p.status_id = s.id) AS poll_id,
+                       s.created_at, s.updated_at
+                FROM statuses s
+                WHERE s.deleted_at IS NULL
+                  AND s.visibility <> 'direct'
+                  AND (
+                      s.account_id = $1
+                      OR EXISTS (
+                          SELECT 1 FROM follows f
+                          WHERE f.follower_id = $1 AND f.followed_id = s.account_id
+                            AND f.active AND NOT f.pending AND f.deleted_at IS NULL
+                      )
+                      OR (s.visibility = 'public' AND EXISTS (
+                          SELECT 1 FROM statuses_tags st
+                          JOIN tag_follows tf ON tf.tag_id = st.tag_id
+                          WHERE st.status_id = s.id AND tf.account_id = $1
+                      ))
+                  )
+                  AND NOT EXISTS (
+                      SELECT 1 FROM blocks b
+                      WHERE (b.blocker_id = $1 AND b.blocked_id = s.account_id)
+                         OR (b.blocker_id = s.account_id AND b.blocked_id = $1)
+                  )
+                  AND NOT EXISTS (
+                      SELECT 1 FROM mutes m WHERE m.muter_id = $1 AND m.muted_id = s.account_id
+                  )
+                  AND ($2::BIGINT IS NULL OR s.id < $2)
+                  AND ($3::BIGINT IS NULL OR s.id > $3)
+                ORDER BY s.id ASC
+                LIMIT $4
+                "#,
+                account_id,
+                page.max_id,
+                page.lower_bound(),
+                page.limit()
+            )
+            .fetch_all(pool)
+            .await?
+        } else {
+            sqlx::query_as!(
+                StatusRow,
+                r#"
+                SELECT s.id, s.account_id, s.content, s.visibility::TEXT AS "visibility!",
+                       (s.sensitive OR EXISTS (
+                           SELECT 1 FROM users u WHERE u.id = s.account_id AND u.sensitized_at IS NOT NULL
+                       )) AS "sensitive!",
+                       s.spoiler_text, s.in_reply_to_id, s.in_reply_to_account_id,
+                       s.reblog_of_id, s.language, s.uri, s.url, s.local, s.favourites_count,
+                       s.reblogs_count, s.replies_count,
+                       (SELECT p.id FROM polls p WHERE p.status_id = s.id) AS poll_id,
+                       s.created_at, s.updated_at
+                FROM statuses s
+                WHERE s.deleted_at IS NULL
+                  AND s.visibility <> 'direct'
+                  AND (
+                      s.account_id = $1
+                      OR EXISTS (
+                          SELECT 1 FROM follows f
+                          WHERE f.follower_id = $1 AND f.followed_id = s.account_id
+                            AND f.active AND NOT f.pending AND f.deleted_at IS NULL
+                      )
+                      OR (s.visibility = 'public' AND EXISTS (
+                          SELECT 1 FROM statuses_tags st
+                          JOIN tag_follows tf ON tf.tag_id = st.tag_id
+                          WHERE st.status_id = s.id AND tf.account_id = $1
+                      ))
+                  )
+                  AND NOT EXISTS (
+                      SELECT 1 FROM blocks b
+                      WHERE (b.blocker_id = $1 AND b.blocked_id = s.account_id)
+                         OR (b.blocker_id = s.account_id AND b.blocked_id = $1)
+                  )
+                  AND NOT EXISTS (
+                      SELECT 1 FROM mutes m WHERE m.muter_id = $1 AND m.muted_id = s.account_id
+                  )
+                  AND ($2::BIGINT IS NULL OR s.id < $2)
+                  AND ($3::BIGINT IS NULL OR s.id > $3)
+                ORDER BY s.id DESC
+                LIMIT $4
+                "#,
+                account_id,
+                page.max_id,
+                page.lower_bound(),
+                page.limit()
+            )
+            .fetch_all(pool)
+            .await?
+        };
+
+        let statuses = into_statuses(rows, page)?;
+        debug!(
+            "Retrieved {} statuses for home timeline of account {}",
+            statuses.len(),
+            account_id
+        );
+        Ok(statuses)
+    }
+
+    /// Gets the local accounts whose home timeline shows a status
+    ///
+    /// These are the author, the local followers of the author unless the
+    /// status is direct, and for public statuses the local accounts
+    /// following one of its hashtags. Accounts blocking or muting the
+    /// author are left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    ///
+    /// # Returns
+    ///
+    /// IDs of the accounts, in no particular order
+    pub async fn home_timeline_account_ids(
+        &self,
+        pool: &PgPool,
+    ) -> Result<Vec<i64>, StatusesError> {
+        let ids = sqlx::query_scalar!(
+            r#"
+            SELECT u.id
+            FROM users u
+            WHERE u.domain IS NULL
+              AND (
+                  u.id = $1
+                  OR ($3 AND EXISTS (
+                      SELECT 1 FROM follows f
+                      WHERE f.follower_id = u.id AND f.followed_id = $1
+                        AND f.active AND NOT f.pending AND f.deleted_at IS NULL
+                  ))
+                  OR ($4 AND EXISTS (
+                      SELECT 1 FROM tag_follows tf
+                      JOIN statuses_tags st ON st.tag_id = tf.tag_id
+                      WHERE tf.account_id = u.id AND st.status_id = $2
+                  ))
+              )
+              AND NOT EXISTS (
+                  SELECT 1 FROM blocks b
+                  WHERE (b.blocker_id = u.id AND b.blocked_id = $1)
+                     OR (b.blocker_id = $1 AND b.blocked_id = u.id)
+              )
+              AND NOT EXISTS (
+                  SELECT 1 FROM mutes m WHERE m.muter_id = u.id AND m.muted_id = $1
+              )
+            "#,
+            self.account_id,
+            self.id,
+            self.visibility != Visibility::Direct,
+            self.visibility == Visibility::Public
+        )
+        .fetch_all(pool)
+        .await?;
+
+        trace!("Status {} goes to {} home timelines", self.id, ids.len());
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeline_page_limit() {
+        assert_eq!(TimelinePage::default().limit(), DEFAULT_TIMELINE_LIMIT);
+        let page = TimelinePage {
+            limit: Some(500),
+            ..TimelinePage::default()
+        };
+        assert_eq!(page.limit(), MAX_TIMELINE_LIMIT);
+        let page = TimelinePage {
+            since_id: Some(3),
+            min_id: Some(7),
+            ..TimelinePage::default()
+        };
+        assert_eq!(page.lower_bound(), Some(7));
+    }
+
+    #[test]
+    fn test_normalize_names() {
+        let names = vec![
+            "#Rust".to_string(),
+            "rust".to_string(),
+            String::new(),
+            "Fediverse".to_string(),
+        ];
+        assert_eq!(normalize_names(&names), vec!["fediverse", "rust"]);
+    }
+}
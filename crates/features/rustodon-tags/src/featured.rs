@@ -0,0 +1,232 @@
+//! Featured tags
+//!
+//! Accounts can pin up to [`MAX_FEATURED_TAGS`] hashtags to their profile.
+//! Usage stats of a featured tag are counted from the account's public and
+//! unlisted statuses using the tag.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::{is_valid_name, normalize_name, Tag, TagError};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::{debug, info, trace};
+
+/// Maximum number of tags an account can feature
+pub const MAX_FEATURED_TAGS: i64 = 10;
+
+/// A hashtag pinned to a profile
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeaturedTag {
+    /// Unique identifier for the featured tag
+    pub id: i64,
+    /// ID of the account featuring the tag
+    pub account_id: i64,
+    /// ID of the tag
+    pub tag_id: i32,
+    /// Name of the tag
+    pub name: String,
+    /// Number of the account's statuses using the tag
+    pub statuses_count: i64,
+    /// When the account last used the tag
+    pub last_status_at: Option<DateTime<Utc>>,
+    /// When the tag was featured
+    pub created_at: DateTime<Utc>,
+}
+
+/// Internal struct for database rows
+struct FeaturedTagRow {
+    id: i64,
+    account_id: i64,
+    tag_id: i32,
+    name: String,
+    statuses_count: i64,
+    last_status_at: Option<NaiveDateTime>,
+    created_at: NaiveDateTime,
+}
+
+impl From<FeaturedTagRow> for FeaturedTag {
+    fn from(row: FeaturedTagRow) -> Self {
+        Self {
+            id: row.id,
+            account_id: row.account_id,
+            tag_id: row.tag_id,
+            name: row.name,
+            statuses_count: row.statuses_count,
+            last_status_at: row
+                .last_status_at
+                .map(|time| DateTime::from_naive_utc_and_offset(time, Utc)),
+            created_at: DateTime::from_naive_utc_and_offset(row.created_at, Utc),
+        }
+    }
+}
+
+impl FeaturedTag {
+    /// Gets the tags featured by an account, oldest first
+    pub async fn get_by_account(pool: &PgPool, account_id: i64) -> Result<Vec<Self>, TagError> {
+        trace!("Getting featured tags of account {}", account_id);
+
+        let rows = sqlx::query_as!(
+            FeaturedTagRow,
+            r#"
+            SELECT ft.id, ft.account_id, ft.tag_id, t.name, ft.created_at,
+                   COUNT(s.id) AS "statuses_count!",
+                   MAX(s.created_at) AS last_status_at
+            FROM featured_tags ft
+            JOIN tags t ON t.id = ft.tag_id
+            LEFT JOIN statuses_tags st ON st.tag_id = ft.tag_id
+            LEFT JOIN statuses s ON s.id = st.status_id
+                 AND s.account_id = ft.account_id
+                 AND s.deleted_at IS NULL
+                 AND s.visibility IN ('public', 'unlisted')
+            WHERE ft.account_id = $1
+            GROUP BY ft.id, t.name
+            ORDER BY ft.id
+            "#,
+            account_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(FeaturedTag::from).collect())
+    }
+
+    /// Features a tag on an account's profile
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the account
+    /// * `name` - Name of the tag, with or without its leading `#`
+    ///
+    /// # Returns
+    ///
+    /// The featured tag, or a validation error if the name is not a valid
+    /// hashtag, the tag is already featured or the account reached
+    /// [`MAX_FEATURED_TAGS`]
+    pub async fn create(pool: &PgPool, account_id: i64, name: &str) -> Result<Self, TagError> {
+        trace!("Featuring tag {} for account {}", name, account_id);
+
+        if !is_valid_name(name) {
+            return Err(TagError::Validation(format!("Invalid tag name: {}", name)));
+        }
+
+        // Holding a lock on the account keeps concurrent requests from both
+        // slipping under the limit
+        let mut tx = pool.begin().await?;
+        sqlx::query!("SELECT id FROM users WHERE id = $1 FOR UPDATE", account_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM featured_tags WHERE account_id = $1"#,
+            account_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if count >= MAX_FEATURED_TAGS {
+            return Err(TagError::Validation(format!(
+                "You can feature at most {} tags",
+                MAX_FEATURED_TAGS
+            )));
+        }
+
+        let tags = Tag::find_or_create_many(&mut tx, &[normalize_name(name)]).await?;
+        let tag = tags
+            .first()
+            .ok_or_else(|| TagError::Internal(format!("Tag {} was not created", name)))?;
+
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO featured_tags (account_id, tag_id)
+            VALUES ($1, $2)
+            ON CONFLICT (account_id, tag_id) DO NOTHING
+            RETURNING id
+            "#,
+            account_id,
+            tag.id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| TagError::Validation("Tag is already featured".to_string()))?;
+        tx.commit().await?;
+
+        info!("Account {} featured tag {}", account_id, tag.name);
+        Self::get_by_account(pool, account_id)
+            .await?
+            .into_iter()
+            .find(|featured| featured.id == id)
+            .ok_or(TagError::FeaturedTagNotFound(id))
+    }
+
+    /// Removes a featured tag from an account's profile
+    pub async fn delete(pool: &PgPool, account_id: i64, id: i64) -> Result<(), TagError> {
+        trace!("Unfeaturing tag {} for account {}", id, account_id);
+
+        let result = sqlx::query!(
+            "DELETE FROM featured_tags WHERE id = $1 AND account_id = $2",
+            id,
+            account_id
+        )
+        .execute(pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(TagError::FeaturedTagNotFound(id));
+        }
+
+        info!("Account {} unfeatured tag {}", account_id, id);
+        Ok(())
+    }
+
+    /// Suggests tags to feature: those the account used most recently
+    /// that are not featured yet
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `account_id` - ID of the account
+    /// * `limit` - Maximum number of suggestions
+    pub async fn suggestions(
+        pool: &PgPool,
+        account_id: i64,
+        limit: i64,
+    ) -> Result<Vec<Tag>, TagError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT t.id, t.name, t.created_at, t.updated_at
+            FROM tags t
+            JOIN statuses_tags st ON st.tag_id = t.id
+            JOIN statuses s ON s.id = st.status_id
+            WHERE s.account_id = $1
+              AND s.deleted_at IS NULL
+              AND NOT EXISTS (
+                  SELECT 1 FROM featured_tags ft
+                  WHERE ft.account_id = $1 AND ft.tag_id = t.id
+              )
+            GROUP BY t.id
+            ORDER BY MAX(s.id) DESC
+            LIMIT $2
+            "#,
+            account_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        debug!(
+            "Suggested {} tags to feature for account {}",
+            rows.len(),
+            account_id
+        );
+        Ok(rows
+            .into_iter()
+            .map(|row| Tag {
+                id: row.id,
+                name: row.name,
+                created_at: row.created_at.unwrap_or_else(Utc::now),
+                updated_at: row.updated_at.unwrap_or_else(Utc::now),
+            })
+            .collect())
+    }
+}
@@ -23,12 +23,20 @@
 //!
 //! arkSong (arksong2018@gmail.com)
 
+pub mod featured;
+
 use chrono::{DateTime, Utc};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::OnceLock;
 use thiserror::Error;
 use tracing::{debug, error, info, trace};
 
+pub use featured::{FeaturedTag, MAX_FEATURED_TAGS};
+
 /// Custom error type for tags module
 #[derive(Error, Debug)]
 pub enum TagError {
@@ -36,6 +44,8 @@ pub enum TagError {
     Database(#[from] sqlx::Error),
     #[error("Tag not found: {0}")]
     TagNotFound(i32),
+    #[error("Featured tag not found: {0}")]
+    FeaturedTagNotFound(i64),
     #[error("Tag name already exists")]
     TagNameExists,
     #[error("Validation error: {0}")]
@@ -57,7 +67,199 @@ pub struct Tag {
     pub updated_at: DateTime<Utc>,
 }
 
+/// A hashtag found in status text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashtagToken {
+    /// Tag name as written, without the leading `#`
+    pub name: String,
+    /// Byte range of the whole hashtag, `#` included
+    pub range: Range<usize>,
+}
+
+fn hashtag_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"(?:^|[^/)\w#])(#([\w·]*[\p{Alphabetic}_·][\w·]*))").expect("valid regex")
+    })
+}
+
+/// Normalizes a tag name for storage and lookups
+pub fn normalize_name(name: &str) -> String {
+    name.trim_start_matches('#').to_lowercase()
+}
+
+/// Finds the hashtags in status text
+///
+/// A hashtag needs at least one non-digit character, so `#1` is not a
+/// hashtag. Hashtags inside URLs are skipped.
+///
+/// # Arguments
+///
+/// * `text` - Text as typed by the user
+///
+/// # Returns
+///
+/// Hashtags in order of appearance
+pub fn extract_hashtags(text: &str) -> Vec<HashtagToken> {
+    hashtag_regex()
+        .captures_iter(text)
+        .filter_map(|caps| {
+            let whole = caps.get(1)?;
+            let name = caps[2].trim_end_matches('·');
+            (!name.is_empty() && name.chars().count() <= 255).then(|| HashtagToken {
+                name: name.to_string(),
+                range: whole.start()..whole.start() + 1 + name.len(),
+            })
+        })
+        .collect()
+}
+
+/// Whether a name, with or without its leading `#`, is a valid hashtag
+pub fn is_valid_name(name: &str) -> bool {
+    let name = name.strip_prefix('#').unwrap_or(name);
+    let text = format!("#{}", name);
+    matches!(extract_hashtags(&text).as_slice(), [token] if token.name == name)
+}
+
 impl Tag {
+    /// Finds or creates the tags with the given names
+    ///
+    /// Names are normalized, so `#Rust` and `#rust` are the same tag.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx` - Transaction the status is being created in
+    /// * `names` - Tag names
+    pub async fn find_or_create_many(
+        tx: &mut Transaction<'_, Postgres>,
+        names: &[String],
+    ) -> Result<Vec<Self>, TagError> {
+        let mut normalized: Vec<String> = names.iter().map(|name| normalize_name(name)).collect();
+        normalized.sort();
+        normalized.dedup();
+        if normalized.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        sqlx::query!(
+            r#"
+            INSERT INTO tags (name)
+            SELECT UNNEST($1::TEXT[])
+            ON CONFLICT (name) DO NOTHING
+            "#,
+            &normalized
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        let rows = sqlx::query_as!(
+            TagRow,
+            r#"
+            SELECT id, name, created_at, updated_at
+            FROM tags
+            WHERE name = ANY($1)
+            ORDER BY name
+            "#,
+            &normalized
+        )
+        .fetch_all(&mut **tx)
+        .await?;
+
+        Ok(rows.into_iter().map(Tag::from).collect())
+    }
+
+    /// Links tags to a status
+    ///
+    /// # Arguments
+    ///
+    /// * `tx` - Transaction the status is being created in
+    /// * `status_id` - ID of the status
+    /// * `tags` - Tags used by the status
+    pub async fn attach_to_status(
+        tx: &mut Transaction<'_, Postgres>,
+        status_id: i64,
+        tags: &[Tag],
+    ) -> Result<(), TagError> {
+        if tags.is_empty() {
+            return Ok(());
+        }
+
+        let tag_ids: Vec<i32> = tags.iter().map(|tag| tag.id).collect();
+        sqlx::query!(
+            r#"
+            INSERT INTO statuses_tags (status_id, tag_id)
+            SELECT $1, UNNEST($2::INTEGER[])
+            ON CONFLICT DO NOTHING
+            "#,
+            status_id,
+            &tag_ids
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        debug!("Linked {} tags to status {}", tag_ids.len(), status_id);
+        Ok(())
+    }
+
+    /// Gets the tags used by the given statuses, keyed by status ID
+    pub async fn get_by_status_ids(
+        pool: &PgPool,
+        status_ids: &[i64],
+    ) -> Result<HashMap<i64, Vec<Self>>, TagError> {
+        if status_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT st.status_id, t.id, t.name, t.created_at, t.updated_at
+            FROM statuses_tags st
+            JOIN tags t ON t.id = st.tag_id
+            WHERE st.status_id = ANY($1)
+            ORDER BY t.name
+            "#,
+            status_ids
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut tags: HashMap<i64, Vec<Self>> = HashMap::new();
+        for row in rows {
+            tags.entry(row.status_id).or_default().push(Tag {
+                id: row.id,
+                name: row.name,
+                created_at: row.created_at.unwrap_or_else(Utc::now),
+                updated_at: row.updated_at.unwrap_or_else(Utc::now),
+            });
+        }
+
+        Ok(tags)
+    }
+
+    /// Gets the tags with the given IDs, keyed by ID
+    pub async fn get_by_ids(pool: &PgPool, ids: &[i32]) -> Result<HashMap<i32, Self>, TagError> {
+        if ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let rows = sqlx::query_as!(
+            TagRow,
+            r#"
+            SELECT id, name, created_at, updated_at
+            FROM tags
+            WHERE id = ANY($1)
+            "#,
+            ids
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.id, Tag::from(row)))
+            .collect())
+    }
+
     /// Creates a new tag
     pub async fn create(pool: &PgPool, name: &str) -> Result<Self, TagError> {
         trace!("Creating tag: {}", name);
@@ -209,6 +411,17 @@ struct TagRow {
     updated_at: Option<DateTime<Utc>>,
 }
 
+impl From<TagRow> for Tag {
+    fn from(row: TagRow) -> Self {
+        Tag {
+            id: row.id,
+            name: row.name,
+            created_at: row.created_at.unwrap_or_else(Utc::now),
+            updated_at: row.updated_at.unwrap_or_else(Utc::now),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -224,5 +437,27 @@ mod tests {
         };
         assert_eq!(tag.name, "rustodon");
     }
+
+    #[test]
+    fn test_extract_hashtags() {
+        let text = "#Rust is fun #2024 #rust_lang·, see https://example.com/#anchor and a#b";
+        let names: Vec<String> = extract_hashtags(text)
+            .into_iter()
+            .map(|token| token.name)
+            .collect();
+        assert_eq!(names, vec!["Rust", "rust_lang"]);
+
+        let tokens = extract_hashtags("(#日本語)");
+        assert_eq!(tokens[0].name, "日本語");
+        assert_eq!(&"(#日本語)"[tokens[0].range.clone()], "#日本語");
+        assert_eq!(normalize_name("#Rust"), "rust");
+
+        assert!(is_valid_name("#Rust"));
+        assert!(is_valid_name("日本語"));
+        assert!(!is_valid_name("2024"));
+        assert!(!is_valid_name("two words"));
+        assert!(!is_valid_name(""));
+    }
+
     // Note: Full async DB tests would require a test database setup
 }
@@ -1,63 +1,522 @@
 //! Trends functionality for Rustodon
 //!
-//! This module provides trending content functionality.
+//! This module provides trending content functionality. Tags, statuses and
+//! links are scored from the accounts using them lately, with older uses
+//! decaying (see [`scoring`]), and [`RefreshTrendsJob`] keeps the best ones
+//! in `trends` along with their language.
+//!
+//! Trending items are only public once approved by staff: items crossing
+//! the review threshold of their type wait in the review queue until a
+//! moderator approves or rejects them, and the review sticks when they trend
+//! again later.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use rustodon_trends::{Trend, TrendQuery, TrendType, TrendsService};
+//!
+//! TrendsService::new(pool.clone()).refresh().await?;
+//! let query = TrendQuery { language: Some("en".to_string()), ..TrendQuery::public() };
+//! let tags = Trend::list(&pool, TrendType::Tag, &query).await?;
+//! ```
+//!
+//! # Dependencies
+//!
+//! - `rustodon_core`: Core types and traits
+//! - `rustodon_workers`: Background refresh job
 //!
 //! # Author
 //!
 //! arkSong (arksong2018@gmail.com)
 
-use chrono::{DateTime, Utc};
+mod refresh;
+pub mod scoring;
+
+pub use refresh::MAX_TRENDS;
+
+use chrono::{DateTime, Days, NaiveDate, NaiveDateTime, Utc};
+use rustodon_workers::{Job, WorkerError};
 use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
 use std::collections::HashMap;
-use tracing::info;
+use std::future::Future;
+use std::pin::Pin;
+use thiserror::Error;
+use tracing::{debug, info, trace};
 
-/// Trend history
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Errors of trends operations
+#[derive(Error, Debug)]
+pub enum TrendsError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("Trend not found: {0}")]
+    NotFound(String),
+}
+
+/// Kinds of trending items
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TrendType {
+    /// Hashtags
+    Tag,
+    /// Statuses
+    Status,
+    /// Links, as their preview cards
+    Link,
+}
+
+impl TrendType {
+    /// Every kind of trending item
+    pub const ALL: [TrendType; 3] = [TrendType::Tag, TrendType::Status, TrendType::Link];
+
+    /// Database name of the type
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrendType::Tag => "tag",
+            TrendType::Status => "status",
+            TrendType::Link => "link",
+        }
+    }
+
+    /// Parses a type by its database name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "tag" => Some(TrendType::Tag),
+            "status" => Some(TrendType::Status),
+            "link" => Some(TrendType::Link),
+            _ => None,
+        }
+    }
+
+    /// Parses a type by its plural name, as used in API paths
+    pub fn parse_plural(value: &str) -> Option<Self> {
+        match value {
+            "tags" => Some(TrendType::Tag),
+            "statuses" => Some(TrendType::Status),
+            "links" => Some(TrendType::Link),
+            _ => None,
+        }
+    }
+
+    /// Score from which items wait for review
+    pub fn review_threshold(&self) -> f64 {
+        match self {
+            TrendType::Tag => 5.0,
+            TrendType::Status | TrendType::Link => 3.0,
+        }
+    }
+
+    /// Items listed by default
+    pub fn default_limit(&self) -> i64 {
+        match self {
+            TrendType::Tag => 10,
+            TrendType::Status | TrendType::Link => 20,
+        }
+    }
+
+    /// Most items listed at once
+    pub fn max_limit(&self) -> i64 {
+        match self {
+            TrendType::Tag => 20,
+            TrendType::Status | TrendType::Link => 40,
+        }
+    }
+}
+
+/// Uses of a tag on a day
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TrendHistory {
-    pub timestamp: DateTime<Utc>,
-    pub score: f64,
+    pub day: NaiveDate,
+    /// Statuses using the tag
+    pub uses: i64,
+    /// Accounts using the tag
+    pub accounts: i64,
 }
 
-/// Trending status
+impl TrendHistory {
+    /// Gets the daily uses of tags
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `tag_ids` - The tags
+    /// * `today` - Last day of the history
+    /// * `days` - Number of days of the history
+    ///
+    /// # Returns
+    ///
+    /// Result containing the history of each tag, newest day first and with
+    /// every day present, or error
+    pub async fn for_tags(
+        pool: &PgPool,
+        tag_ids: &[i64],
+        today: NaiveDate,
+        days: u64,
+    ) -> Result<HashMap<i64, Vec<TrendHistory>>, TrendsError> {
+        trace!("Getting history of {} tags", tag_ids.len());
+
+        let first_day = today - Days::new(days.saturating_sub(1));
+        let rows = sqlx::query!(
+            r#"
+            SELECT st.tag_id::BIGINT AS "tag_id!", s.created_at::DATE AS "day!",
+                   COUNT(*) AS "uses!", COUNT(DISTINCT s.account_id) AS "accounts!"
+            FROM statuses_tags st
+            JOIN statuses s ON s.id = st.status_id
+            WHERE st.tag_id = ANY($1::BIGINT[]) AND s.created_at >= $2
+              AND s.visibility = 'public' AND s.deleted_at IS NULL AND s.reblog_of_id IS NULL
+            GROUP BY st.tag_id, s.created_at::DATE
+            "#,
+            tag_ids,
+            first_day.and_hms_opt(0, 0, 0).unwrap_or_default()
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut counts: HashMap<(i64, NaiveDate), (i64, i64)> = HashMap::new();
+        for row in rows {
+            counts.insert((row.tag_id, row.day), (row.uses, row.accounts));
+        }
+
+        Ok(tag_ids
+            .iter()
+            .map(|&tag_id| {
+                let history = today
+                    .iter_days()
+                    .rev()
+                    .take_while(|day| *day >= first_day)
+                    .map(|day| {
+                        let (uses, accounts) =
+                            counts.get(&(tag_id, day)).copied().unwrap_or((0, 0));
+                        TrendHistory {
+                            day,
+                            uses,
+                            accounts,
+                        }
+                    })
+                    .collect();
+                (tag_id, history)
+            })
+            .collect())
+    }
+}
+
+/// A trending item
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TrendingStatus {
+pub struct Trend {
     pub id: i64,
+    pub trend_type: TrendType,
+    /// ID of the tag, status or preview card
+    pub target_id: i64,
+    pub language: Option<String>,
     pub score: f64,
+    /// Whether staff approved the item
+    pub allowed: bool,
+    pub reviewed_at: Option<DateTime<Utc>>,
+    pub requested_review_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
 }
 
-/// Trends service
-pub struct TrendsService {
-    #[allow(dead_code)]
-    cache: HashMap<String, (DateTime<Utc>, Vec<u8>)>, // Simple in-memory cache
+struct TrendRow {
+    id: i64,
+    trend_type: String,
+    target_id: i64,
+    language: Option<String>,
+    score: f64,
+    allowed: bool,
+    reviewed_at: Option<NaiveDateTime>,
+    requested_review_at: Option<NaiveDateTime>,
+    updated_at: NaiveDateTime,
 }
 
-impl Default for TrendsService {
-    fn default() -> Self {
-        Self::new()
+impl From<TrendRow> for Trend {
+    fn from(row: TrendRow) -> Self {
+        Self {
+            id: row.id,
+            trend_type: TrendType::parse(&row.trend_type).unwrap_or(TrendType::Tag),
+            target_id: row.target_id,
+            language: row.language,
+            score: row.score,
+            allowed: row.allowed,
+            reviewed_at: row
+                .reviewed_at
+                .map(|at| DateTime::from_naive_utc_and_offset(at, Utc)),
+            requested_review_at: row
+                .requested_review_at
+                .map(|at| DateTime::from_naive_utc_and_offset(at, Utc)),
+            updated_at: DateTime::from_naive_utc_and_offset(row.updated_at, Utc),
+        }
     }
 }
 
+/// Filters of a trends list
+#[derive(Debug, Clone, Default)]
+pub struct TrendQuery {
+    /// Only items in this language, or whose language is unknown
+    pub language: Option<String>,
+    /// Only items approved by staff
+    pub allowed_only: bool,
+    /// Only items waiting for review
+    pub pending_review: bool,
+    pub offset: i64,
+    /// Items listed, the default of the type if None
+    pub limit: Option<i64>,
+}
+
+impl TrendQuery {
+    /// The filters of the public trends
+    pub fn public() -> Self {
+        Self {
+            allowed_only: true,
+            ..Default::default()
+        }
+    }
+}
+
+impl Trend {
+    /// Whether the item waits for review
+    pub fn requires_review(&self) -> bool {
+        self.requested_review_at.is_some() && self.reviewed_at.is_none()
+    }
+
+    /// Gets the trend of an item
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `trend_type` - Kind of item
+    /// * `target_id` - ID of the tag, status or preview card
+    ///
+    /// # Returns
+    ///
+    /// Result containing the trend, if the item trended, or error
+    pub async fn get(
+        pool: &PgPool,
+        trend_type: TrendType,
+        target_id: i64,
+    ) -> Result<Option<Self>, TrendsError> {
+        let row = sqlx::query_as!(
+            TrendRow,
+            r#"
+            SELECT id, trend_type, target_id, language, score, allowed, reviewed_at,
+                   requested_review_at, updated_at
+            FROM trends
+            WHERE trend_type = $1 AND target_id = $2
+            "#,
+            trend_type.as_str(),
+            target_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row.map(Self::from))
+    }
+
+    /// Lists the trending items of a type, best scored first
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    /// * `trend_type` - Kind of items
+    /// * `query` - Filters
+    ///
+    /// # Returns
+    ///
+    /// Result containing the items or error
+    pub async fn list(
+        pool: &PgPool,
+        trend_type: TrendType,
+        query: &TrendQuery,
+    ) -> Result<Vec<Self>, TrendsError> {
+        trace!("Listing {} trends: {:?}", trend_type.as_str(), query);
+
+        let limit = query
+            .limit
+            .unwrap_or(trend_type.default_limit())
+            .clamp(1, trend_type.max_limit());
+        let rows = sqlx::query_as!(
+            TrendRow,
+            r#"
+            SELECT id, trend_type, target_id, language, score, allowed, reviewed_at,
+                   requested_review_at, updated_at
+            FROM trends
+            WHERE trend_type = $1 AND score > 0
+              AND (NOT $2 OR allowed)
+              AND (NOT $3 OR (requested_review_at IS NOT NULL AND reviewed_at IS NULL))
+              AND ($4::TEXT IS NULL OR language = $4 OR language IS NULL)
+            ORDER BY score DESC, id DESC
+            OFFSET $5
+            LIMIT $6
+            "#,
+            trend_type.as_str(),
+            query.allowed_only,
+            query.pending_review,
+            query.language,
+            query.offset.max(0),
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    /// Approves an item, making it public while it trends
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection of the transaction to run in
+    /// * `trend_type` - Kind of item
+    /// * `target_id` - ID of the tag, status or preview card
+    ///
+    /// # Returns
+    ///
+    /// Result containing the reviewed item or error
+    pub async fn approve(
+        conn: &mut PgConnection,
+        trend_type: TrendType,
+        target_id: i64,
+    ) -> Result<Self, TrendsError> {
+        Self::review(conn, trend_type, target_id, true).await
+    }
+
+    /// Rejects an item, keeping it out of the public trends
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - Connection of the transaction to run in
+    /// * `trend_type` - Kind of item
+    /// * `target_id` - ID of the tag, status or preview card
+    ///
+    /// # Returns
+    ///
+    /// Result containing the reviewed item or error
+    pub async fn reject(
+        conn: &mut PgConnection,
+        trend_type: TrendType,
+        target_id: i64,
+    ) -> Result<Self, TrendsError> {
+        Self::review(conn, trend_type, target_id, false).await
+    }
+
+    async fn review(
+        conn: &mut PgConnection,
+        trend_type: TrendType,
+        target_id: i64,
+        allowed: bool,
+    ) -> Result<Self, TrendsError> {
+        info!(
+            "Reviewing {} trend {}: allowed = {}",
+            trend_type.as_str(),
+            target_id,
+            allowed
+        );
+
+        let row = sqlx::query_as!(
+            TrendRow,
+            r#"
+            UPDATE trends
+            SET allowed = $3, reviewed_at = NOW()
+            WHERE trend_type = $1 AND target_id = $2
+            RETURNING id, trend_type, target_id, language, score, allowed, reviewed_at,
+                      requested_review_at, updated_at
+            "#,
+            trend_type.as_str(),
+            target_id,
+            allowed
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        row.map(Self::from)
+            .ok_or_else(|| TrendsError::NotFound(format!("{} {}", trend_type.as_str(), target_id)))
+    }
+}
+
+/// Trends service
+pub struct TrendsService {
+    pool: PgPool,
+}
+
 impl TrendsService {
     /// Creates a new trends service
-    pub fn new() -> Self {
+    pub fn new(pool: PgPool) -> Self {
         info!("Creating new trends service");
-        Self {
-            cache: HashMap::new(),
+        Self { pool }
+    }
+
+    /// Scores the items of every type and stores the best ones
+    ///
+    /// # Returns
+    ///
+    /// Result containing the number of items newly waiting for review or
+    /// error
+    pub async fn refresh(&self) -> Result<u64, TrendsError> {
+        let mut requested = 0;
+        for trend_type in TrendType::ALL {
+            requested += self.refresh_type(trend_type, Utc::now()).await?;
         }
+        Ok(requested)
+    }
+
+    /// Scores the items of a type and stores the best ones
+    ///
+    /// # Arguments
+    ///
+    /// * `trend_type` - Kind of items
+    /// * `now` - Time the scores decay to
+    ///
+    /// # Returns
+    ///
+    /// Result containing the number of items newly waiting for review or
+    /// error
+    pub async fn refresh_type(
+        &self,
+        trend_type: TrendType,
+        now: DateTime<Utc>,
+    ) -> Result<u64, TrendsError> {
+        debug!("Refreshing {} trends", trend_type.as_str());
+
+        let candidates = match trend_type {
+            TrendType::Tag => refresh::tag_candidates(&self.pool, now).await?,
+            TrendType::Status => refresh::status_candidates(&self.pool, now).await?,
+            TrendType::Link => refresh::link_candidates(&self.pool, now).await?,
+        };
+        refresh::store(&self.pool, trend_type, &refresh::best(candidates)).await
     }
+}
 
-    /// Calculate tag score
-    #[allow(dead_code)]
-    fn calculate_tag_score(&self, _tag: &str, _history: &[TrendHistory]) -> f64 {
-        // TODO: Implement tag score calculation
-        0.0
+/// Background job refreshing the trends
+pub struct RefreshTrendsJob {
+    pool: PgPool,
+}
+
+impl RefreshTrendsJob {
+    /// Creates the job
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Database connection pool
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
     }
+}
 
-    /// Calculate status score
-    #[allow(dead_code)]
-    fn calculate_status_score(&self, _status: &TrendingStatus) -> f64 {
-        // TODO: Implement status score calculation
-        0.0
+impl Job for RefreshTrendsJob {
+    fn name(&self) -> &'static str {
+        "RefreshTrendsJob"
+    }
+
+    fn execute(&self) -> Pin<Box<dyn Future<Output = Result<(), WorkerError>> + Send>> {
+        let pool = self.pool.clone();
+        Box::pin(async move {
+            let requested = TrendsService::new(pool)
+                .refresh()
+                .await
+                .map_err(|e| WorkerError::Job(e.to_string()))?;
+            if requested > 0 {
+                info!("{} trending items are waiting for review", requested);
+            }
+            Ok(())
+        })
     }
 }
 
@@ -66,24 +525,42 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_trends_service_new() {
-        let service = TrendsService::new();
-        assert!(service.cache.is_empty());
+    fn test_trend_type_names() {
+        for trend_type in TrendType::ALL {
+            assert_eq!(TrendType::parse(trend_type.as_str()), Some(trend_type));
+        }
+        assert_eq!(TrendType::parse_plural("statuses"), Some(TrendType::Status));
+        assert_eq!(TrendType::parse_plural("links"), Some(TrendType::Link));
+        assert_eq!(TrendType::parse_plural("tag"), None);
     }
 
     #[test]
-    fn test_calculate_tag_score() {
-        let service = TrendsService::new();
-        let history = vec![];
-        let score = service.calculate_tag_score("test", &history);
-        assert_eq!(score, 0.0);
+    fn test_requires_review() {
+        let now = Utc::now();
+        let mut trend = Trend {
+            id: 1,
+            trend_type: TrendType::Tag,
+            target_id: 1,
+            language: None,
+            score: 10.0,
+            allowed: false,
+            reviewed_at: None,
+            requested_review_at: None,
+            updated_at: now,
+        };
+        assert!(!trend.requires_review());
+
+        trend.requested_review_at = Some(now);
+        assert!(trend.requires_review());
+
+        trend.reviewed_at = Some(now);
+        assert!(!trend.requires_review());
     }
 
     #[test]
-    fn test_calculate_status_score() {
-        let service = TrendsService::new();
-        let status = TrendingStatus { id: 1, score: 0.0 };
-        let score = service.calculate_status_score(&status);
-        assert_eq!(score, 0.0);
+    fn test_public_query() {
+        let query = TrendQuery::public();
+        assert!(query.allowed_only);
+        assert!(!query.pending_review);
     }
 }
@@ -0,0 +1,333 @@
+//! Trend refresh
+//!
+//! Scores the tags, statuses and links used recently, and stores the best
+//! ones in `trends`. Scores of items which stopped trending drop to zero;
+//! those nobody reviewed are then removed, while reviewed ones are kept so
+//! that the review still applies if they trend again.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::scoring::{
+    decay, link_score, most_common_language, status_score, tag_score, TAG_HALF_LIFE,
+};
+use crate::{TrendType, TrendsError};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use tracing::{debug, trace};
+
+/// Items of each type kept in `trends`
+pub const MAX_TRENDS: usize = 200;
+
+/// Window over which tag uses are observed
+const TAG_WINDOW: Duration = Duration::days(1);
+
+/// Days before the observed one that make the usual use of a tag
+const TAG_BASELINE_DAYS: i64 = 6;
+
+/// Age after which statuses no longer trend
+const STATUS_MAX_AGE: Duration = Duration::days(2);
+
+/// Window over which link shares are counted
+const LINK_WINDOW: Duration = Duration::days(2);
+
+/// A scored item
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Candidate {
+    pub target_id: i64,
+    pub language: Option<String>,
+    pub score: f64,
+}
+
+/// Shares of a link: the language of its page, the age of each account's
+/// last share and the languages of the sharing statuses
+type LinkShares = (Option<String>, Vec<Duration>, Vec<String>);
+
+fn age(now: DateTime<Utc>, at: NaiveDateTime) -> Duration {
+    now.naive_utc() - at
+}
+
+/// Scores the tags used in the last day
+pub(crate) async fn tag_candidates(
+    pool: &PgPool,
+    now: DateTime<Utc>,
+) -> Result<Vec<Candidate>, TrendsError> {
+    let since = (now - TAG_WINDOW).naive_utc();
+    let uses = sqlx::query!(
+        r#"
+        SELECT st.tag_id::BIGINT AS "tag_id!", s.account_id,
+               MAX(s.created_at) AS "last_used_at!",
+               MODE() WITHIN GROUP (ORDER BY s.language) AS language
+        FROM statuses_tags st
+        JOIN statuses s ON s.id = st.status_id
+        WHERE s.created_at > $1 AND s.visibility = 'public'
+          AND s.deleted_at IS NULL AND s.reblog_of_id IS NULL
+        GROUP BY st.tag_id, s.account_id
+        "#,
+        since
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut observed: HashMap<i64, (f64, Vec<String>)> = HashMap::new();
+    for row in uses {
+        let (weight, languages) = observed.entry(row.tag_id).or_default();
+        *weight += decay(age(now, row.last_used_at), TAG_HALF_LIFE);
+        languages.extend(row.language);
+    }
+    if observed.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let tag_ids: Vec<i64> = observed.keys().copied().collect();
+    let baseline_since = (now - TAG_WINDOW - Duration::days(TAG_BASELINE_DAYS)).naive_utc();
+    let baseline: HashMap<i64, i64> = sqlx::query!(
+        r#"
+        SELECT st.tag_id::BIGINT AS "tag_id!",
+               COUNT(DISTINCT (s.account_id, s.created_at::DATE)) AS "account_days!"
+        FROM statuses_tags st
+        JOIN statuses s ON s.id = st.status_id
+        WHERE st.tag_id = ANY($1::BIGINT[]) AND s.created_at > $2 AND s.created_at <= $3
+          AND s.visibility = 'public' AND s.deleted_at IS NULL AND s.reblog_of_id IS NULL
+        GROUP BY st.tag_id
+        "#,
+        &tag_ids,
+        baseline_since,
+        since
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| (row.tag_id, row.account_days))
+    .collect();
+
+    Ok(observed
+        .into_iter()
+        .map(|(tag_id, (weight, languages))| {
+            let expected =
+                baseline.get(&tag_id).copied().unwrap_or(0) as f64 / TAG_BASELINE_DAYS as f64;
+            Candidate {
+                target_id: tag_id,
+                language: most_common_language(languages.iter().map(String::as_str)),
+                score: tag_score(weight, expected),
+            }
+        })
+        .collect())
+}
+
+/// Scores the recent statuses favourited or reblogged by other accounts
+///
+/// Replies, sensitive statuses and statuses of accounts which are not
+/// discoverable, or are limited, are left out.
+pub(crate) async fn status_candidates(
+    pool: &PgPool,
+    now: DateTime<Utc>,
+) -> Result<Vec<Candidate>, TrendsError> {
+    let since = (now - STATUS_MAX_AGE).naive_utc();
+    let rows = sqlx::query!(
+        r#"
+        SELECT s.id, s.language, s.created_at,
+               COUNT(DISTINCT i.account_id) AS "accounts!"
+        FROM statuses s
+        JOIN users u ON u.id = s.account_id
+        JOIN (
+            SELECT status_id, account_id FROM favourites
+            UNION
+            SELECT reblog_of_id, account_id FROM statuses
+            WHERE reblog_of_id IS NOT NULL AND deleted_at IS NULL
+        ) i ON i.status_id = s.id AND i.account_id <> s.account_id
+        WHERE s.created_at > $1 AND s.visibility = 'public' AND NOT s.sensitive
+          AND s.deleted_at IS NULL AND s.reblog_of_id IS NULL AND s.in_reply_to_id IS NULL
+          AND u.discoverable AND NOT u.disabled AND u.status IN ('active', 'unconfirmed')
+          AND u.silenced_at IS NULL AND u.suspended_at IS NULL
+        GROUP BY s.id
+        "#,
+        since
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Candidate {
+            target_id: row.id,
+            language: row.language,
+            score: status_score(row.accounts, age(now, row.created_at)),
+        })
+        .collect())
+}
+
+/// Scores the links shared in public statuses lately
+///
+/// A link counts once per account sharing it, and takes the language of its
+/// page or else the most common one of the statuses sharing it.
+pub(crate) async fn link_candidates(
+    pool: &PgPool,
+    now: DateTime<Utc>,
+) -> Result<Vec<Candidate>, TrendsError> {
+    let since = (now - LINK_WINDOW).naive_utc();
+    let shares = sqlx::query!(
+        r#"
+        SELECT pc.id, pc.language AS card_language, s.account_id,
+               MAX(s.created_at) AS "last_shared_at!",
+               MODE() WITHIN GROUP (ORDER BY s.language) AS language
+        FROM preview_cards_statuses pcs
+        JOIN preview_cards pc ON pc.id = pcs.preview_card_id
+        JOIN statuses s ON s.id = pcs.status_id
+        WHERE s.created_at > $1 AND pc.card_type = 'link' AND s.visibility = 'public'
+          AND s.deleted_at IS NULL AND s.reblog_of_id IS NULL
+        GROUP BY pc.id, pc.language, s.account_id
+        "#,
+        since
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut links: HashMap<i64, LinkShares> = HashMap::new();
+    for row in shares {
+        let (card_language, ages, languages) = links
+            .entry(row.id)
+            .or_insert_with(|| (row.card_language.clone(), Vec::new(), Vec::new()));
+        ages.push(age(now, row.last_shared_at));
+        languages.extend(row.language);
+        if card_language.is_none() {
+            *card_language = row.card_language;
+        }
+    }
+
+    Ok(links
+        .into_iter()
+        .map(|(card_id, (card_language, ages, languages))| Candidate {
+            target_id: card_id,
+            language: card_language
+                .or_else(|| most_common_language(languages.iter().map(String::as_str))),
+            score: link_score(&ages),
+        })
+        .collect())
+}
+
+/// Keeps the best scored candidates, at most [`MAX_TRENDS`]
+pub(crate) fn best(mut candidates: Vec<Candidate>) -> Vec<Candidate> {
+    candidates.retain(|candidate| candidate.score > 0.0);
+    candidates.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| b.target_id.cmp(&a.target_id))
+    });
+    candidates.truncate(MAX_TRENDS);
+    candidates
+}
+
+/// Replaces the scores of a type of trends
+///
+/// Items crossing the review threshold of their type for the first time
+/// are put in the review queue.
+///
+/// # Returns
+///
+/// Result containing the number of items newly waiting for review or error
+pub(crate) async fn store(
+    pool: &PgPool,
+    trend_type: TrendType,
+    candidates: &[Candidate],
+) -> Result<u64, TrendsError> {
+    trace!(
+        "Storing {} {} trends",
+        candidates.len(),
+        trend_type.as_str()
+    );
+
+    let target_ids: Vec<i64> = candidates.iter().map(|c| c.target_id).collect();
+    let languages: Vec<Option<String>> = candidates.iter().map(|c| c.language.clone()).collect();
+    let scores: Vec<f64> = candidates.iter().map(|c| c.score).collect();
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        "UPDATE trends SET score = 0 WHERE trend_type = $1 AND score > 0",
+        trend_type.as_str()
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let requested = sqlx::query_scalar!(
+        r#"
+        WITH upserted AS (
+            INSERT INTO trends (trend_type, target_id, language, score, requested_review_at)
+            SELECT $1, c.target_id, c.language, c.score,
+                   CASE WHEN c.score >= $5 THEN NOW() END
+            FROM UNNEST($2::BIGINT[], $3::TEXT[], $4::FLOAT8[]) AS c(target_id, language, score)
+            ON CONFLICT (trend_type, target_id) DO UPDATE
+            SET language = EXCLUDED.language,
+                score = EXCLUDED.score,
+                requested_review_at = CASE
+                    WHEN trends.reviewed_at IS NULL AND trends.requested_review_at IS NULL
+                    THEN EXCLUDED.requested_review_at
+                    ELSE trends.requested_review_at
+                END
+            RETURNING requested_review_at
+        )
+        SELECT COUNT(*) AS "count!" FROM upserted
+        WHERE requested_review_at >= NOW()
+        "#,
+        trend_type.as_str(),
+        &target_ids,
+        &languages as &[Option<String>],
+        &scores,
+        trend_type.review_threshold()
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "DELETE FROM trends WHERE trend_type = $1 AND score = 0 AND reviewed_at IS NULL",
+        trend_type.as_str()
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    debug!(
+        "Stored {} {} trends, {} newly waiting for review",
+        candidates.len(),
+        trend_type.as_str(),
+        requested
+    );
+    Ok(requested as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(target_id: i64, score: f64) -> Candidate {
+        Candidate {
+            target_id,
+            language: None,
+            score,
+        }
+    }
+
+    #[test]
+    fn test_best() {
+        let best = best(vec![
+            candidate(1, 2.0),
+            candidate(2, 0.0),
+            candidate(3, 5.0),
+            candidate(4, 2.0),
+        ]);
+        let ids: Vec<i64> = best.iter().map(|c| c.target_id).collect();
+        assert_eq!(ids, vec![3, 4, 1]);
+    }
+
+    #[test]
+    fn test_best_keeps_at_most_max_trends() {
+        let candidates = (0..MAX_TRENDS as i64 + 10)
+            .map(|id| candidate(id, 1.0))
+            .collect();
+        assert_eq!(best(candidates).len(), MAX_TRENDS);
+    }
+}
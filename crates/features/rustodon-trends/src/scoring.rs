@@ -0,0 +1,161 @@
+//! Trend scores
+//!
+//! Every account counts once per item, weighted by how recently it used the
+//! tag, interacted with the status or shared the link: the weight halves
+//! every half-life. Tags also have to be used more than usual to trend, so
+//! their score compares today's weighted accounts with the average of the
+//! days before.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use chrono::Duration;
+use std::collections::HashMap;
+
+/// Half-life of a use of a tag
+pub const TAG_HALF_LIFE: Duration = Duration::hours(12);
+
+/// Half-life of a status, from its creation
+pub const STATUS_HALF_LIFE: Duration = Duration::hours(6);
+
+/// Half-life of a share of a link
+pub const LINK_HALF_LIFE: Duration = Duration::hours(12);
+
+/// Accounts below which statuses and links do not trend
+pub const MIN_ACCOUNTS: i64 = 2;
+
+/// Weight of an event of a given age
+///
+/// # Arguments
+///
+/// * `age` - Time since the event, clamped to zero
+/// * `half_life` - Time after which the weight is halved
+///
+/// # Returns
+///
+/// A weight between 0 and 1
+pub fn decay(age: Duration, half_life: Duration) -> f64 {
+    let age = age.num_seconds().max(0) as f64;
+    let half_life = half_life.num_seconds().max(1) as f64;
+    0.5f64.powf(age / half_life)
+}
+
+/// Score of a tag
+///
+/// # Arguments
+///
+/// * `observed` - Accounts which used the tag in the last day, weighted by
+///   [`decay`]
+/// * `expected` - Average accounts per day which used the tag before
+///
+/// # Returns
+///
+/// The squared excess of accounts over the expected ones, relative to the
+/// expected ones, or 0 if the tag is not used more than usual
+pub fn tag_score(observed: f64, expected: f64) -> f64 {
+    let expected = expected.max(1.0);
+    if observed <= expected {
+        return 0.0;
+    }
+    (observed - expected).powi(2) / expected
+}
+
+/// Score of a status
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts which favourited or reblogged the status
+/// * `age` - Time since the status was created
+///
+/// # Returns
+///
+/// The accounts weighted by the age of the status, or 0 below
+/// [`MIN_ACCOUNTS`]
+pub fn status_score(accounts: i64, age: Duration) -> f64 {
+    if accounts < MIN_ACCOUNTS {
+        return 0.0;
+    }
+    accounts as f64 * decay(age, STATUS_HALF_LIFE)
+}
+
+/// Score of a link
+///
+/// # Arguments
+///
+/// * `share_ages` - Time since each account last shared the link
+///
+/// # Returns
+///
+/// The accounts weighted by [`decay`], or 0 below [`MIN_ACCOUNTS`]
+pub fn link_score(share_ages: &[Duration]) -> f64 {
+    if (share_ages.len() as i64) < MIN_ACCOUNTS {
+        return 0.0;
+    }
+    share_ages
+        .iter()
+        .map(|age| decay(*age, LINK_HALF_LIFE))
+        .sum()
+}
+
+/// Most common language among the uses of an item
+///
+/// Ties go to the first language in alphabetical order, and uses without a
+/// language are ignored.
+pub fn most_common_language<'a>(languages: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for language in languages {
+        *counts.entry(language).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then_with(|| b.cmp(a)))
+        .map(|(language, _)| language.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decay() {
+        assert_eq!(decay(Duration::zero(), TAG_HALF_LIFE), 1.0);
+        assert_eq!(decay(TAG_HALF_LIFE, TAG_HALF_LIFE), 0.5);
+        assert_eq!(decay(TAG_HALF_LIFE * 2, TAG_HALF_LIFE), 0.25);
+        assert_eq!(decay(Duration::hours(-1), TAG_HALF_LIFE), 1.0);
+    }
+
+    #[test]
+    fn test_tag_score() {
+        assert_eq!(tag_score(0.0, 0.0), 0.0);
+        // Tags used as much as usual do not trend
+        assert_eq!(tag_score(3.0, 3.0), 0.0);
+        assert_eq!(tag_score(5.0, 0.0), 16.0);
+        assert_eq!(tag_score(10.0, 2.0), 32.0);
+        // A spike counts more for a rarely used tag
+        assert!(tag_score(10.0, 1.0) > tag_score(20.0, 11.0));
+    }
+
+    #[test]
+    fn test_status_score() {
+        assert_eq!(status_score(1, Duration::zero()), 0.0);
+        assert_eq!(status_score(4, Duration::zero()), 4.0);
+        assert_eq!(status_score(4, STATUS_HALF_LIFE), 2.0);
+    }
+
+    #[test]
+    fn test_link_score() {
+        assert_eq!(link_score(&[Duration::zero()]), 0.0);
+        assert_eq!(link_score(&[Duration::zero(), LINK_HALF_LIFE]), 1.5);
+    }
+
+    #[test]
+    fn test_most_common_language() {
+        assert_eq!(
+            most_common_language(["en", "de", "en"]),
+            Some("en".to_string())
+        );
+        assert_eq!(most_common_language(["ja", "de"]), Some("de".to_string()));
+        assert_eq!(most_common_language([]), None);
+    }
+}
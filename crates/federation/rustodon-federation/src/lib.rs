@@ -1,5 +1,160 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
+//! Federation delivery for Rustodon
+//!
+//! This module delivers ActivityPub activities to remote inboxes, signed
+//! with the key of the local account sending them.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use rustodon_federation::{ActorKey, DeliveryClient};
+//!
+//! # async fn run(pool: sqlx::PgPool) -> Result<(), rustodon_federation::FederationError> {
+//! let key = ActorKey::for_account(&pool, 1)
+//!     .await?
+//!     .signing_key("https://rustodon.example.com/users/alice#main-key")?;
+//! let client = DeliveryClient::new()?;
+//! let activity = serde_json::json!({ "type": "Accept" });
+//! client.deliver(&key, "https://remote.example/inbox", &activity).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Dependencies
+//!
+//! - `reqwest`: HTTP client
+//! - `rsa`, `sha2`: HTTP Signatures
+//! - `url`: Inbox URL validation
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+pub mod keys;
+pub mod signature;
+
+pub use keys::ActorKey;
+pub use signature::SigningKey;
+
+use chrono::Utc;
+use signature::sign_request;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{debug, error, info, trace};
+use url::Url;
+
+/// Content type used for ActivityPub deliveries
+pub const ACTIVITY_JSON_CONTENT_TYPE: &str = "application/activity+json";
+
+/// Error type for federation operations
+#[derive(Error, Debug)]
+pub enum FederationError {
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("Invalid inbox URL: {0}")]
+    InvalidInbox(String),
+    #[error("Delivery to {0} failed with status {1}")]
+    DeliveryFailed(String, u16),
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("Key error: {0}")]
+    Key(String),
+}
+
+/// Client delivering activities to remote inboxes
+#[derive(Debug, Clone)]
+pub struct DeliveryClient {
+    client: reqwest::Client,
+}
+
+impl DeliveryClient {
+    /// Creates a new delivery client
+    ///
+    /// # Returns
+    ///
+    /// Result containing the client or an error
+    pub fn new() -> Result<Self, FederationError> {
+        info!("Creating new federation delivery client");
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .user_agent(concat!("Rustodon/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+
+        Ok(Self { client })
+    }
+
+    /// Delivers an activity to a remote inbox
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Key of the actor of the activity, signing the request
+    /// * `inbox_url` - Inbox to POST the activity to
+    /// * `activity` - Activity JSON
+    ///
+    /// # Returns
+    ///
+    /// Result indicating success or failure
+    pub async fn deliver(
+        &self,
+        key: &SigningKey,
+        inbox_url: &str,
+        activity: &serde_json::Value,
+    ) -> Result<(), FederationError> {
+        trace!("Delivering activity to {} as {}", inbox_url, key.key_id());
+
+        let inbox = parse_inbox_url(inbox_url)?;
+        let body = serde_json::to_vec(activity)?;
+        let signed = sign_request(
+            key,
+            "POST",
+            &inbox,
+            ACTIVITY_JSON_CONTENT_TYPE,
+            &body,
+            Utc::now(),
+        )?;
+
+        let response = self
+            .client
+            .post(inbox)
+            .header(reqwest::header::HOST, signed.host)
+            .header(reqwest::header::DATE, signed.date)
+            .header("Digest", signed.digest)
+            .header("Signature", signed.signature)
+            .header(reqwest::header::CONTENT_TYPE, ACTIVITY_JSON_CONTENT_TYPE)
+            .body(body)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            error!("Delivery to {} failed with status {}", inbox_url, status);
+            return Err(FederationError::DeliveryFailed(
+                inbox_url.to_string(),
+                status.as_u16(),
+            ));
+        }
+
+        debug!("Delivered activity to {}", inbox_url);
+        Ok(())
+    }
+}
+
+/// Validates an inbox URL
+///
+/// Only absolute `http` and `https` URLs are accepted.
+pub fn parse_inbox_url(inbox_url: &str) -> Result<Url, FederationError> {
+    let url = Url::parse(inbox_url)
+        .map_err(|e| FederationError::InvalidInbox(format!("{}: {}", inbox_url, e)))?;
+
+    match url.scheme() {
+        "http" | "https" => Ok(url),
+        scheme => Err(FederationError::InvalidInbox(format!(
+            "{}: unsupported scheme {}",
+            inbox_url, scheme
+        ))),
+    }
 }
 
 #[cfg(test)]
@@ -7,8 +162,55 @@ mod tests {
     use super::*;
 
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn test_parse_inbox_url() {
+        assert!(parse_inbox_url("https://remote.example/inbox").is_ok());
+        assert!(parse_inbox_url("ftp://remote.example/inbox").is_err());
+        assert!(parse_inbox_url("not a url").is_err());
+    }
+
+    #[test]
+    fn test_delivery_client_new() {
+        assert!(DeliveryClient::new().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_deliver_sends_a_signed_request() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let inbox = format!("http://{}/inbox", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 4096];
+            while !String::from_utf8_lossy(&request).ends_with("{}") {
+                let read = socket.read(&mut buffer).await.unwrap();
+                if read == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buffer[..read]);
+            }
+            socket
+                .write_all(b"HTTP/1.1 202 Accepted\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8(request).unwrap().to_lowercase()
+        });
+
+        let (private_key, _) = keys::generate_key_pair(1024).unwrap();
+        let key = SigningKey::new("https://a.example/users/alice#main-key", &private_key).unwrap();
+        DeliveryClient::new()
+            .unwrap()
+            .deliver(&key, &inbox, &serde_json::json!({}))
+            .await
+            .unwrap();
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("post /inbox http/1.1"));
+        assert!(request.contains("\r\ndigest: sha-256="));
+        assert!(request.contains("\r\ndate: "));
+        assert!(request.contains(
+            "\r\nsignature: keyid=\"https://a.example/users/alice#main-key\",algorithm=\"rsa-sha256\""
+        ));
     }
 }
@@ -0,0 +1,263 @@
+//! HTTP Signatures of deliveries
+//!
+//! Remote servers only accept activities POSTed with an HTTP Signature made
+//! by the key of the sending actor. Deliveries are signed the way Mastodon
+//! expects: `rsa-sha256` over the request target and the `host`, `date`,
+//! `digest` and `content-type` headers, with the body covered by a SHA-256
+//! `Digest` header. The `keyId` points at the actor's public key, which the
+//! remote server fetches to check the signature.
+//!
+//! # Author
+//!
+//! arkSong (arksong2018@gmail.com)
+
+use crate::FederationError;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chrono::{DateTime, Utc};
+use rsa::pkcs1v15::SigningKey as RsaSigningKey;
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::signature::{SignatureEncoding, Signer};
+use rsa::RsaPrivateKey;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+/// Headers covered by the signature, in signing order
+pub const SIGNED_HEADERS: &str = "(request-target) host date digest content-type";
+
+/// Private key of an actor, with the ID under which its public key is published
+#[derive(Clone)]
+pub struct SigningKey {
+    key_id: String,
+    signer: RsaSigningKey<Sha256>,
+}
+
+impl std::fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SigningKey")
+            .field("key_id", &self.key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SigningKey {
+    /// Loads a signing key
+    ///
+    /// # Arguments
+    ///
+    /// * `key_id` - URI of the public key, such as the actor's `#main-key`
+    /// * `private_key_pem` - PKCS#8 PEM of the private key
+    ///
+    /// # Returns
+    ///
+    /// Result containing the key or an error if the PEM is invalid
+    pub fn new(key_id: impl Into<String>, private_key_pem: &str) -> Result<Self, FederationError> {
+        let private_key = RsaPrivateKey::from_pkcs8_pem(private_key_pem)
+            .map_err(|e| FederationError::Key(e.to_string()))?;
+
+        Ok(Self {
+            key_id: key_id.into(),
+            signer: RsaSigningKey::new(private_key),
+        })
+    }
+
+    /// URI of the public key
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// Signs a string, returning the base64 signature
+    fn sign(&self, signing_string: &str) -> String {
+        STANDARD.encode(self.signer.sign(signing_string.as_bytes()).to_bytes())
+    }
+}
+
+/// Headers to send with a signed request
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignedHeaders {
+    pub host: String,
+    pub date: String,
+    pub digest: String,
+    pub signature: String,
+}
+
+/// `Digest` header of a body
+pub fn digest_header(body: &[u8]) -> String {
+    format!("SHA-256={}", STANDARD.encode(Sha256::digest(body)))
+}
+
+/// `Date` header of a moment, in the IMF-fixdate format
+pub fn http_date(at: DateTime<Utc>) -> String {
+    at.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// `Host` header of a URL, with the port only when it is not the default one
+fn host_header(url: &Url) -> Result<String, FederationError> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| FederationError::InvalidInbox(format!("{}: missing host", url)))?;
+
+    Ok(match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    })
+}
+
+/// String signed for a request, covering [`SIGNED_HEADERS`]
+///
+/// # Arguments
+///
+/// * `method` - HTTP method, such as `post`
+/// * `url` - URL of the request
+/// * `host` - `Host` header
+/// * `date` - `Date` header
+/// * `digest` - `Digest` header
+/// * `content_type` - `Content-Type` header
+pub fn signing_string(
+    method: &str,
+    url: &Url,
+    host: &str,
+    date: &str,
+    digest: &str,
+    content_type: &str,
+) -> String {
+    let target = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+
+    format!(
+        "(request-target): {} {}\nhost: {}\ndate: {}\ndigest: {}\ncontent-type: {}",
+        method.to_lowercase(),
+        target,
+        host,
+        date,
+        digest,
+        content_type
+    )
+}
+
+/// Signs a request
+///
+/// # Arguments
+///
+/// * `key` - Key of the actor sending the request
+/// * `method` - HTTP method
+/// * `url` - URL of the request
+/// * `content_type` - `Content-Type` header
+/// * `body` - Body of the request
+/// * `at` - Moment the request is sent
+///
+/// # Returns
+///
+/// Result containing the headers to send or an error if the URL has no host
+pub fn sign_request(
+    key: &SigningKey,
+    method: &str,
+    url: &Url,
+    content_type: &str,
+    body: &[u8],
+    at: DateTime<Utc>,
+) -> Result<SignedHeaders, FederationError> {
+    let host = host_header(url)?;
+    let date = http_date(at);
+    let digest = digest_header(body);
+    let signature = key.sign(&signing_string(
+        method,
+        url,
+        &host,
+        &date,
+        &digest,
+        content_type,
+    ));
+
+    Ok(SignedHeaders {
+        signature: format!(
+            r#"keyId="{}",algorithm="rsa-sha256",headers="{}",signature="{}""#,
+            key.key_id, SIGNED_HEADERS, signature
+        ),
+        host,
+        date,
+        digest,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::generate_key_pair;
+    use chrono::TimeZone;
+    use rsa::pkcs1v15::{Signature, VerifyingKey};
+    use rsa::pkcs8::DecodePublicKey;
+    use rsa::signature::Verifier;
+    use rsa::RsaPublicKey;
+
+    #[test]
+    fn test_digest_header() {
+        assert_eq!(
+            digest_header(b""),
+            "SHA-256=47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+        );
+    }
+
+    #[test]
+    fn test_http_date() {
+        let at = Utc.with_ymd_and_hms(2019, 12, 30, 8, 5, 9).unwrap();
+        assert_eq!(http_date(at), "Mon, 30 Dec 2019 08:05:09 GMT");
+    }
+
+    #[test]
+    fn test_signing_string() {
+        let url = Url::parse("https://remote.example:8443/users/bob/inbox?a=1").unwrap();
+        assert_eq!(host_header(&url).unwrap(), "remote.example:8443");
+        assert_eq!(
+            host_header(&Url::parse("https://remote.example/inbox").unwrap()).unwrap(),
+            "remote.example"
+        );
+        assert_eq!(
+            signing_string("POST", &url, "remote.example:8443", "d", "SHA-256=x", "t"),
+            "(request-target): post /users/bob/inbox?a=1\nhost: remote.example:8443\n\
+             date: d\ndigest: SHA-256=x\ncontent-type: t"
+        );
+    }
+
+    #[test]
+    fn test_sign_request_verifies_with_the_public_key() {
+        let (private_key, public_key) = generate_key_pair(1024).unwrap();
+        let key = SigningKey::new("https://a.example/users/alice#main-key", &private_key).unwrap();
+        let url = Url::parse("https://b.example/inbox").unwrap();
+        let at = Utc::now();
+
+        let headers =
+            sign_request(&key, "POST", &url, "application/activity+json", b"{}", at).unwrap();
+        assert_eq!(headers.host, "b.example");
+        assert_eq!(headers.digest, digest_header(b"{}"));
+        assert!(headers.signature.starts_with(
+            r#"keyId="https://a.example/users/alice#main-key",algorithm="rsa-sha256""#
+        ));
+
+        let encoded = headers
+            .signature
+            .rsplit("signature=\"")
+            .next()
+            .unwrap()
+            .trim_end_matches('"');
+        let signature = Signature::try_from(STANDARD.decode(encoded).unwrap().as_slice()).unwrap();
+        let verifier =
+            VerifyingKey::<Sha256>::new(RsaPublicKey::from_public_key_pem(&public_key).unwrap());
+        let signed = signing_string(
+            "post",
+            &url,
+            &headers.host,
+            &headers.date,
+            &headers.digest,
+            "application/activity+json",
+        );
+        assert!(verifier.verify(signed.as_bytes(), &signature).is_ok());
+        assert!(verifier.verify(b"tampered", &signature).is_err());
+    }
+
+    #[test]
+    fn test_signing_key_rejects_invalid_pem() {
+        assert!(SigningKey::new("key", "not a key").is_err());
+    }
+}
@@ -360,6 +360,134 @@ impl Default for StorageConfig {
     }
 }
 
+impl StorageConfig {
+    /// 从环境变量加载存储配置
+    ///
+    /// 读取 `MEDIA_STORAGE_PATH` 和 `MEDIA_MAX_SIZE`，未设置时使用默认值
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+
+        if let Ok(path) = std::env::var("MEDIA_STORAGE_PATH") {
+            config.media_root = PathBuf::from(path);
+        }
+
+        if let Some(size) = std::env::var("MEDIA_MAX_SIZE")
+            .ok()
+            .and_then(|s| s.parse().ok())
+        {
+            config.max_file_size = size;
+        }
+
+        config
+    }
+
+    /// 所有支持的MIME类型
+    pub fn supported_mime_types(&self) -> Vec<String> {
+        self.supported_image_formats
+            .iter()
+            .chain(&self.supported_video_formats)
+            .chain(&self.supported_audio_formats)
+            .cloned()
+            .collect()
+    }
+}
+
+/// 链接预览卡片图片的最大宽度
+pub const PREVIEW_CARD_IMAGE_WIDTH: u32 = 640;
+
+/// 链接预览卡片图片的最大高度
+pub const PREVIEW_CARD_IMAGE_HEIGHT: u32 = 360;
+
+/// 已保存的链接预览卡片图片
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredImage {
+    /// 图片URL
+    pub url: String,
+
+    /// 宽度
+    pub width: u32,
+
+    /// 高度
+    pub height: u32,
+
+    /// BlurHash
+    pub blurhash: String,
+}
+
+/// 保存链接预览卡片的图片
+///
+/// 图片缩放到 640x360 以内并转换为 JPEG，文件名取图片内容的 SHA-256，
+/// 因此相同的图片只保存一次。
+pub async fn store_preview_card_image(config: &StorageConfig, data: &[u8]) -> Result<StoredImage> {
+    if data.len() as u64 > config.max_file_size {
+        anyhow::bail!("图片过大: {} 字节", data.len());
+    }
+
+    let img = image::load_from_memory(data).context("无法加载图片数据")?;
+    let resized = fit_within(&img, PREVIEW_CARD_IMAGE_WIDTH, PREVIEW_CARD_IMAGE_HEIGHT);
+    let rgb = DynamicImage::ImageRgb8(resized.to_rgb8());
+
+    let mut encoded = Vec::new();
+    rgb.write_to(&mut std::io::Cursor::new(&mut encoded), ImageFormat::Jpeg)
+        .context("编码预览图片失败")?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    let file_id = format!("{:x}", hasher.finalize());
+
+    let dir = config.media_root.join("preview_cards");
+    fs::create_dir_all(&dir)
+        .await
+        .context("创建预览图片目录失败")?;
+    fs::write(dir.join(format!("{}.jpg", file_id)), &encoded)
+        .await
+        .context("保存预览图片失败")?;
+
+    debug!(
+        "保存预览图片: {} ({}x{})",
+        file_id,
+        rgb.width(),
+        rgb.height()
+    );
+    Ok(StoredImage {
+        url: format!("{}/media/preview_cards/{}.jpg", config.base_url, file_id),
+        width: rgb.width(),
+        height: rgb.height(),
+        blurhash: encode_blurhash(&img),
+    })
+}
+
+/// 等比缩放图片，使其不超过给定尺寸
+fn fit_within(img: &DynamicImage, max_width: u32, max_height: u32) -> DynamicImage {
+    let (width, height) = (img.width(), img.height());
+
+    // 计算缩放比例
+    let scale_x = max_width as f32 / width as f32;
+    let scale_y = max_height as f32 / height as f32;
+    let scale = scale_x.min(scale_y);
+
+    if scale >= 1.0 {
+        // 不需要缩放
+        return img.clone();
+    }
+
+    let new_width = (width as f32 * scale) as u32;
+    let new_height = (height as f32 * scale) as u32;
+
+    img.resize(new_width, new_height, image::imageops::FilterType::Lanczos3)
+}
+
+/// 生成 BlurHash
+fn encode_blurhash(img: &DynamicImage) -> String {
+    // 缩小图片以提高 BlurHash 生成速度
+    let small_img = img.resize(32, 32, image::imageops::FilterType::Nearest);
+    let rgba_img = small_img.to_rgba8();
+
+    // blurhash 需要 RGBA 像素数据
+    let (width, height) = rgba_img.dimensions();
+    blurhash::encode(4, 4, width, height, &rgba_img.into_raw())
+}
+
 /// 媒体处理器
 #[derive(Clone)]
 pub struct MediaProcessor {
@@ -775,34 +903,12 @@ impl MediaProcessor {
         max_width: u32,
         max_height: u32,
     ) -> Result<DynamicImage> {
-        let (width, height) = (img.width(), img.height());
-
-        // 计算缩放比例
-        let scale_x = max_width as f32 / width as f32;
-        let scale_y = max_height as f32 / height as f32;
-        let scale = scale_x.min(scale_y);
-
-        if scale >= 1.0 {
-            // 不需要缩放
-            return Ok(img.clone());
-        }
-
-        let new_width = (width as f32 * scale) as u32;
-        let new_height = (height as f32 * scale) as u32;
-
-        Ok(img.resize(new_width, new_height, image::imageops::FilterType::Lanczos3))
+        Ok(fit_within(img, max_width, max_height))
     }
 
     /// 生成 BlurHash
     fn generate_blurhash(&self, img: &DynamicImage) -> Result<String> {
-        // 缩小图片以提高 BlurHash 生成速度
-        let small_img = img.resize(32, 32, image::imageops::FilterType::Nearest);
-        let rgb_img = small_img.to_rgb8();
-
-        let (width, height) = rgb_img.dimensions();
-        let pixels: Vec<u8> = rgb_img.pixels().flat_map(|p| [p[0], p[1], p[2]]).collect();
-
-        Ok(blurhash::encode(4, 4, width, height, &pixels))
+        Ok(encode_blurhash(img))
     }
 
     /// 处理视频（占位符实现）
@@ -1170,4 +1276,49 @@ mod tests {
             .contains(&"audio/mpeg".to_string()));
         assert_eq!(config.max_file_size, 40 * 1024 * 1024);
     }
+
+    fn png_image(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        DynamicImage::new_rgb8(width, height)
+            .write_to(&mut std::io::Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_fit_within() {
+        let img = DynamicImage::new_rgb8(1280, 1280);
+        let resized = fit_within(&img, PREVIEW_CARD_IMAGE_WIDTH, PREVIEW_CARD_IMAGE_HEIGHT);
+        assert_eq!((resized.width(), resized.height()), (360, 360));
+
+        let small = DynamicImage::new_rgb8(10, 20);
+        assert_eq!(fit_within(&small, 640, 360).width(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_store_preview_card_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = StorageConfig {
+            media_root: dir.path().to_path_buf(),
+            ..StorageConfig::default()
+        };
+
+        let stored = store_preview_card_image(&config, &png_image(1280, 720))
+            .await
+            .unwrap();
+        assert_eq!((stored.width, stored.height), (640, 360));
+        assert!(stored
+            .url
+            .starts_with("http://localhost:3000/media/preview_cards/"));
+        assert_eq!(
+            std::fs::read_dir(dir.path().join("preview_cards"))
+                .unwrap()
+                .count(),
+            1
+        );
+
+        assert!(store_preview_card_image(&config, b"not an image")
+            .await
+            .is_err());
+    }
 }
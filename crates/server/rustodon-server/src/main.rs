@@ -29,10 +29,20 @@
 //!
 //! arkSong (arksong2018@gmail.com)
 
+use rustodon_account_deletion_requests::PurgeDeletedAccountsJob;
+use rustodon_account_suggestions::RefreshFollowRecommendationsJob;
+use rustodon_analytics::RollupAnalyticsJob;
 use rustodon_api::start_server;
+use rustodon_backups::GenerateBackupsJob;
+use rustodon_bulk_imports::ProcessBulkImportsJob;
+use rustodon_config::Config;
 use rustodon_mailer::AsyncMailer;
 use rustodon_mailer::{Email, MockMailer};
-use rustodon_workers::{ExampleJob, Worker};
+use rustodon_media::StorageConfig;
+use rustodon_polls::ClosePollsJob;
+use rustodon_scheduled_statuses::PublishScheduledStatusesJob;
+use rustodon_trends::RefreshTrendsJob;
+use rustodon_workers::{ExampleJob, JobQueue, Worker};
 use std::sync::Arc;
 use std::time::Instant;
 use tokio::sync::Mutex;
@@ -245,21 +255,76 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Start API server with performance optimizations (in background)
     let addr = format!("127.0.0.1:{}", port).parse().unwrap();
     let pool_clone = pool.clone();
+    let queue: JobQueue = Arc::new(Mutex::new(vec![
+        Box::new(ExampleJob) as Box<dyn rustodon_workers::Job>
+    ]));
+    let api_queue = queue.clone();
     let api_handle = tokio::spawn(async move {
-        if let Err(e) = start_server(pool_clone, addr).await {
+        if let Err(e) = start_server(pool_clone, addr, api_queue).await {
             error!("API server failed: {}", e);
         }
     });
 
     // Start worker with optimized queue (in background)
-    let queue = Arc::new(Mutex::new(vec![
-        Box::new(ExampleJob) as Box<dyn rustodon_workers::Job>
-    ]));
     let worker = Worker::new(queue.clone());
     let worker_handle = tokio::spawn(async move {
         let _ = worker.start().await;
     });
 
+    // Publish due scheduled statuses, close expired polls, process a batch
+    // of every pending import, generate requested archives and refresh the
+    // trends every minute
+    let scheduler_queue = queue.clone();
+    let scheduler_pool = pool.clone();
+    let local_domain = Config::from_env().local_domain;
+    let storage = StorageConfig::from_env();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            let mut queue = scheduler_queue.lock().await;
+            queue.push(Box::new(PublishScheduledStatusesJob::new(
+                scheduler_pool.clone(),
+                local_domain.clone(),
+            )));
+            queue.push(Box::new(ClosePollsJob::new(scheduler_pool.clone())));
+            queue.push(Box::new(ProcessBulkImportsJob::new(
+                scheduler_pool.clone(),
+                local_domain.clone(),
+            )));
+            queue.push(Box::new(GenerateBackupsJob::new(
+                scheduler_pool.clone(),
+                local_domain.clone(),
+                storage.clone(),
+            )));
+            queue.push(Box::new(RefreshTrendsJob::new(scheduler_pool.clone())));
+        }
+    });
+
+    // Refresh the follow recommendation views, purge the accounts whose
+    // deletion grace period is over and roll up the past days of analytics
+    // every hour
+    let hourly_queue = queue.clone();
+    let hourly_pool = pool.clone();
+    let hourly_domain = Config::from_env().local_domain;
+    let hourly_storage = StorageConfig::from_env();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            let mut queue = hourly_queue.lock().await;
+            queue.push(Box::new(RefreshFollowRecommendationsJob::new(
+                hourly_pool.clone(),
+            )));
+            queue.push(Box::new(PurgeDeletedAccountsJob::new(
+                hourly_pool.clone(),
+                hourly_domain.clone(),
+                hourly_storage.clone(),
+            )));
+            queue.push(Box::new(RollupAnalyticsJob::new(hourly_pool.clone())));
+        }
+    });
+
     // Start mailer (mock example)
     let mailer = MockMailer;
     let email = Email {
@@ -52,6 +52,9 @@ pub trait Job: Send + Sync + 'static {
     fn execute(&self) -> Pin<Box<dyn Future<Output = Result<(), WorkerError>> + Send>>;
 }
 
+/// Queue of jobs shared between the worker and the code enqueueing them
+pub type JobQueue = Arc<Mutex<Vec<Box<dyn Job>>>>;
+
 /// Example job implementation
 pub struct ExampleJob;
 
@@ -69,12 +72,12 @@ impl Job for ExampleJob {
 
 /// Worker struct that processes jobs from a queue
 pub struct Worker {
-    queue: Arc<Mutex<Vec<Box<dyn Job>>>>,
+    queue: JobQueue,
 }
 
 impl Worker {
     /// Create a new worker with a shared job queue
-    pub fn new(queue: Arc<Mutex<Vec<Box<dyn Job>>>>) -> Self {
+    pub fn new(queue: JobQueue) -> Self {
         info!("Creating new Worker");
         Self { queue }
     }